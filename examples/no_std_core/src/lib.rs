@@ -0,0 +1,62 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{sync::Arc, vec, vec::Vec};
+use bytes::Bytes;
+use ggpo::core::{
+    game_input::{Frame, GameInput, InputBuffer, NULL_FRAME},
+    sync::{Config, ConnectStatus, GGPOSync, RollbackCallbacks, SavedState, SyncError},
+    Mutex, GGPO_MAX_PREDICTION_FRAMES,
+};
+
+// A game whose whole state is a frame counter.
+#[derive(Debug, Default, Clone)]
+pub struct Counter {
+    pub frame: u32,
+}
+
+impl RollbackCallbacks for Counter {
+    type Error = &'static str;
+
+    fn save_state(&mut self, _frame: Frame) -> Result<SavedState, Self::Error> {
+        Ok(SavedState {
+            data: Bytes::copy_from_slice(&self.frame.to_le_bytes()),
+            checksum: None,
+        })
+    }
+
+    fn load_state(&mut self, buffer: &Bytes, _length: usize) -> bool {
+        let mut frame = [0; 4];
+        frame.copy_from_slice(&buffer[..4]);
+        self.frame = u32::from_le_bytes(frame);
+        true
+    }
+
+    fn resimulate_frame(&mut self) -> bool {
+        self.frame += 1;
+        true
+    }
+}
+
+// Plays `frames` frames for a single local player, returning the game's state.
+pub fn play(frames: u32) -> Result<Counter, SyncError> {
+    let game = Arc::new(Mutex::new(Counter::default()));
+    let status: Vec<Arc<Mutex<ConnectStatus>>> = vec![Arc::new(Mutex::new(ConnectStatus::new()))];
+    let mut sync = GGPOSync::new(&status);
+    let mut config = Config::new();
+    config.init(game.clone(), GGPO_MAX_PREDICTION_FRAMES, 1, 1);
+    sync.init(config)?;
+
+    for _ in 0..frames {
+        let mut input = GameInput::init(NULL_FRAME, None, 1);
+        sync.add_local_input(0, &mut input)?;
+        let mut values: InputBuffer = Default::default();
+        sync.synchronize_inputs(&mut values)?;
+        sync.set_last_confirmed_frame(input.frame)?;
+        game.lock().frame += 1;
+        sync.increment_frame()?;
+    }
+    let counter = game.lock().clone();
+    Ok(counter)
+}
@@ -36,7 +36,7 @@ pub struct ChecksumInfo {
 }
 impl ChecksumInfo {
     pub fn frame_number(&self) -> Option<FrameNum> {
-        self.frame_number
+        self.frame_number.number()
     }
 }
 
@@ -295,8 +295,7 @@ impl<'a> SDLRenderer {
     ) -> Result<(), String> {
         let checksum_str = format!(
             "Frame: {:04}  Checksum: {:#08x}",
-            info.frame_number.unwrap(),
-            info.checksum
+            info.frame_number, info.checksum
         );
 
         self.draw_text(
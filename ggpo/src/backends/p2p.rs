@@ -1,26 +1,50 @@
 use crate::{
-    game_input::{FrameNum, GameInput, InputBuffer},
+    checksum,
+    config::SessionConfig,
+    debug_info::{PlayerDebugInfo, SessionDebugInfo},
+    desync::{DesyncAction, DesyncDetector, DesyncReport, InputChecksums},
+    game_input::{
+        Frame, FrameNum, GameInput, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS,
+        NULL_FRAME,
+    },
     ggpo::{
-        self, GGPOError, GGPOSessionCallbacks, NetworkStats, Session, GGPO_MAX_PLAYERS,
-        GGPO_MAX_SPECTATORS,
+        self, ConnectionQuality, GGPOError, GGPOSessionCallbacks, NetworkStats, Session,
+        GGPO_MAX_PLAYERS, GGPO_MAX_SPECTATORS,
     },
     network::{
+        clock::{Clock, Sleep, SystemClock, TimerSource},
+        input_codec::MAX_LOCAL_PLAYERS_PER_HOST,
         udp::{Udp, UdpCallback, UdpError},
-        udp_msg::{ConnectStatus, UdpMsg, UDP_MSG_MAX_PLAYERS},
-        udp_proto::{self, UdpProtoError, UdpProtocol},
+        udp_msg::{ConnectStatus, Pause, UdpMsg, MAX_STATE_SNAPSHOT_SIZE, UDP_MSG_MAX_PLAYERS},
+        udp_proto::{self, TransportProfile, UdpProtoError, UdpProtocol},
     },
-    player::{Player, PlayerHandle},
-    sync::{self, GGPOSync, SyncError},
+    player::{Player, PlayerHandle, PlayerInfo},
+    prediction::PredictionStrategy,
+    sync::{self, GGPOSync, MemoryUsage, RollbackStats, SyncError},
+    time_sync::{self, AutoFrameDelay},
 };
+use bytes::Bytes;
 use log::{error, info};
-use mio::{Events, Poll, Token};
+use mio::{Events, Poll};
 use parking_lot::Mutex;
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    convert::TryFrom,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 use thiserror::Error;
 
+#[cfg(feature = "encryption")]
+use crate::network::encryption::EncryptedTransport;
+
 const RECOMMENDATION_INTERVAL: u32 = 240;
-const DEFAULT_DISCONNECT_TIMEOUT: u128 = 5000;
-const DEFAULT_DISCONNECT_NOTIFY_START: u128 = 750;
+/*
+ * The most frames of input a spectator can leave unacked, by default.  One
+ * that falls further behind is dropped, rather than have us hold, and keep
+ * resending, everything back to the frame it stalled on.
+ */
+pub const SPECTATOR_LAG_WINDOW: usize = 128;
 
 #[derive(Debug, Error)]
 pub enum Peer2PeerError {
@@ -34,12 +58,8 @@ pub enum Peer2PeerError {
         #[from]
         source: UdpError,
     },
-    #[error("Last frame is None.")]
-    LastFrameNone,
     #[error("Next Spectator Frame is None.")]
     SpectatorFrameNone,
-    #[error("current_remote_frame is None.")]
-    CurrentRemoteFrameNone,
     #[error("GGPO Session error {0}")]
     GGPO(String),
     #[error("Sync Error")]
@@ -54,6 +74,18 @@ pub enum Peer2PeerError {
     },
 }
 
+/*
+ * Where the session stands on pausing.  Each pause is a new epoch; within
+ * one, the pause frame only ever goes up, to the furthest frame any peer had
+ * reached when it heard.
+ */
+#[derive(Debug, Default, Clone, Copy)]
+struct PauseState {
+    epoch: u32,
+    paused: bool,
+    frame: Frame,
+}
+
 #[derive(Clone)]
 pub struct Peer2PeerBackend<T>
 where
@@ -64,6 +96,7 @@ where
     udp: Arc<Mutex<Udp<Self>>>,
     endpoints: Vec<Arc<Mutex<UdpProtocol<Self>>>>, //; GGPO_MAX_PLAYERS],
     spectators: Vec<Arc<Mutex<UdpProtocol<Self>>>>, //; GGPO_MAX_SPECTATORS],
+    players: Vec<Option<PlayerInfo>>,
     num_spectators: usize,
     input_size: usize,
 
@@ -72,8 +105,49 @@ where
     next_recommended_sleep: u32,
 
     next_spectator_frame: FrameNum,
+    // See `SessionConfig::spectator_lag_window`.
+    spectator_lag_window: usize,
+    // Per spectator: the first frame of input it was sent.
+    spectator_first_frame: Vec<Option<Frame>>,
+    // When we last sent a spectator a state snapshot.
+    last_state_sent: Arc<Mutex<u128>>,
+    // The endpoints' clock, for the timers kept here; see `set_clock`.
+    clock: Arc<dyn Clock>,
+    // The same clock, when it can be waited on; see `set_timers`.
+    timers: Option<Arc<dyn TimerSource>>,
+    next_checksum_frame: Frame,
+    next_confirmed_frame: Frame,
+    desync: Arc<Mutex<DesyncDetector>>,
+    on_desync: DesyncAction,
+    // Set when peers compare checksums of confirmed input; see `SessionConfig::input_checksums`.
+    input_checksums: Option<Arc<Mutex<InputChecksums>>>,
+    next_input_checksum_frame: Frame,
+    num_sync_packets: u32,
+    sync_first_retry_interval: u128,
+    sync_retry_interval: u128,
+    sync_timeout: u128,
     disconnect_timeout: u128,
     disconnect_notify_start: u128,
+    retransmit_interval: u128,
+    send_rate_limit: usize,
+    transport_profile: TransportProfile,
+    reconnect_window: u128,
+    // Whether `init` starts the transport's send task; see `SessionConfig::send_task`.
+    send_task: bool,
+    // Set for a playerless host relaying a match; see `SessionConfig::broadcast`.
+    broadcast: bool,
+    // Given to local players as they're added.
+    frame_delay: usize,
+    // Set while the frame delay follows the round trip to the slowest peer.
+    auto_frame_delay: Option<AutoFrameDelay>,
+    // Frames local input has been refused at the prediction barrier, while it holds.
+    prediction_barrier: Option<usize>,
+    // The prediction window as last reported, to spot the sync layer adapting it.
+    prediction_frames: FrameNum,
+    version: u32,
+    seed_nonce: u64,
+    shared_seed: Arc<Mutex<Option<u64>>>,
+    pause: Arc<Mutex<PauseState>>,
 
     local_connect_status: [Arc<Mutex<ConnectStatus>>; UDP_MSG_MAX_PLAYERS],
     poll: Arc<Mutex<Poll>>,
@@ -86,13 +160,55 @@ impl<T: GGPOSessionCallbacks + Send + Sync> Peer2PeerBackend<T> {
         local_port: u16,
         num_players: usize,
         input_size: usize,
+        saved_state_depth: Option<usize>,
+    ) -> Result<Arc<Mutex<Self>>, Peer2PeerError> {
+        let config = SessionConfig {
+            local_port,
+            num_players,
+            input_size,
+            saved_state_depth,
+            ..Default::default()
+        };
+        Self::with_config(callbacks, &config)
+    }
+
+    // Like `new`, but with every setting from `config`, which is checked first.
+    pub fn from_config(
+        config: SessionConfig,
+        callbacks: Arc<Mutex<T>>,
+    ) -> Result<Arc<Mutex<Self>>, GGPOError> {
+        config.validate()?;
+        Ok(Self::with_config(callbacks, &config)?)
+    }
+
+    fn with_config(
+        callbacks: Arc<Mutex<T>>,
+        session_config: &SessionConfig,
     ) -> Result<Arc<Mutex<Self>>, Peer2PeerError> {
+        let num_players = session_config.num_players;
+        let input_size = session_config.input_size;
+        if num_players > GGPO_MAX_PLAYERS {
+            return Err(Peer2PeerError::GGPO(format!(
+                "{} players requested, but at most {} are supported.",
+                num_players, GGPO_MAX_PLAYERS
+            )));
+        }
+        if input_size == 0 || input_size > GAMEINPUT_MAX_BYTES {
+            return Err(Peer2PeerError::GGPO(format!(
+                "input size {} requested, but it must be between 1 and {} bytes.",
+                input_size, GAMEINPUT_MAX_BYTES
+            )));
+        }
+
         let mut connect_status: [Arc<Mutex<ConnectStatus>>; UDP_MSG_MAX_PLAYERS] =
             Default::default();
-        for i in 0..UDP_MSG_MAX_PLAYERS {
-            connect_status[i] = Arc::new(Mutex::new(ConnectStatus::new()));
+        for status in connect_status.iter_mut() {
+            // Every player starts out connected, with no frames received yet.
+            *status = Arc::new(Mutex::new(ConnectStatus {
+                disconnected: false,
+                last_frame: NULL_FRAME,
+            }));
         }
-        // let mut connect_status: [ConnectStatus; UDP_MSG_MAX_PLAYERS] = Default::default();
 
         let udp = Udp::new();
 
@@ -103,23 +219,31 @@ impl<T: GGPOSessionCallbacks + Send + Sync> Peer2PeerBackend<T> {
         let mut config = sync::Config::new();
         config.init(
             callbacks.clone(),
-            ggpo::GGPO_MAX_PREDICTION_FRAMES,
+            session_config.prediction_frames,
             num_players,
             input_size,
         );
+        config.saved_state_depth = session_config.saved_state_depth;
+        config.checkpoint_interval = session_config.checkpoint_interval;
+        config.checksum_cadence = session_config.checksum_cadence;
+        config.input_queue_length = session_config.input_queue_length;
+        config.queue_overflow = session_config.queue_overflow;
+        config.adaptive_prediction = session_config.adaptive_prediction;
+        config.start_frame = session_config.start_frame;
         sync.lock().init(config)?;
+        let start_frame = Frame::new(session_config.start_frame);
 
         // Init the UDP layer
 
-        let mut spectators = Vec::with_capacity(GGPO_MAX_SPECTATORS);
-        for i in 0..GGPO_MAX_SPECTATORS {
-            spectators[i] = Arc::new(Mutex::new(UdpProtocol::new()));
-        }
+        let spectators = (0..GGPO_MAX_SPECTATORS)
+            .map(|_| Arc::new(Mutex::new(UdpProtocol::new())))
+            .collect();
 
-        let mut endpoints = Vec::with_capacity(GGPO_MAX_PLAYERS);
-        for i in 0..GGPO_MAX_PLAYERS {
-            endpoints[i] = Arc::new(Mutex::new(UdpProtocol::new()));
-        }
+        // One endpoint per player, each running its own sync handshake and
+        // disconnect timer. Local players simply leave theirs uninitialized.
+        let endpoints = (0..GGPO_MAX_PLAYERS)
+            .map(|_| Arc::new(Mutex::new(UdpProtocol::new())))
+            .collect();
 
         // Create the event poll.
         let poll = Arc::new(Mutex::new(Poll::new()?));
@@ -130,21 +254,73 @@ impl<T: GGPOSessionCallbacks + Send + Sync> Peer2PeerBackend<T> {
             num_players,
             input_size,
             num_spectators: 0,
-            next_spectator_frame: 0,
+            next_spectator_frame: session_config.start_frame,
+            spectator_lag_window: session_config.spectator_lag_window,
+            spectator_first_frame: vec![None; GGPO_MAX_SPECTATORS],
+            last_state_sent: Arc::new(Mutex::new(0)),
+            clock: Arc::new(SystemClock),
+            timers: None,
+            next_checksum_frame: start_frame,
+            next_confirmed_frame: start_frame,
+            desync: Arc::new(Mutex::new(DesyncDetector::new(num_players, input_size))),
+            on_desync: session_config.on_desync,
+            input_checksums: if session_config.input_checksums {
+                Some(Arc::new(Mutex::new(InputChecksums::new(num_players))))
+            } else {
+                None
+            },
+            next_input_checksum_frame: start_frame,
             next_recommended_sleep: 0,
             callbacks: callbacks.clone(),
             synchronizing: Arc::new(Mutex::new(true)),
             udp: Arc::new(Mutex::new(udp)),
-            disconnect_timeout: DEFAULT_DISCONNECT_TIMEOUT,
-            disconnect_notify_start: DEFAULT_DISCONNECT_NOTIFY_START,
+            num_sync_packets: session_config.num_sync_packets,
+            sync_first_retry_interval: session_config.sync_first_retry_interval,
+            sync_retry_interval: session_config.sync_retry_interval,
+            sync_timeout: session_config.sync_timeout,
+            disconnect_timeout: session_config.disconnect_timeout,
+            disconnect_notify_start: session_config.disconnect_notify_start,
+            retransmit_interval: session_config.retransmit_interval,
+            send_rate_limit: session_config.send_rate_limit,
+            transport_profile: session_config.transport_profile,
+            reconnect_window: session_config.reconnect_window,
+            send_task: session_config.send_task,
+            broadcast: session_config.broadcast,
+            frame_delay: session_config.frame_delay,
+            auto_frame_delay: if session_config.auto_frame_delay {
+                Some(AutoFrameDelay::new(session_config.frame_delay))
+            } else {
+                None
+            },
+            prediction_barrier: None,
+            prediction_frames: session_config.prediction_frames,
+            version: session_config.version,
+            seed_nonce: rand::random(),
+            shared_seed: Arc::new(Mutex::new(None)),
+            pause: Arc::new(Mutex::new(PauseState::default())),
             sync,
             local_connect_status: connect_status,
             spectators,
             endpoints,
+            players: vec![None; num_players],
             poll,
             events,
         }));
-        p2p.clone().lock().init(p2p.clone(), local_port)?;
+        {
+            let p2p = p2p.lock();
+            let mut udp = p2p.udp.lock();
+            udp.set_dual_stack(session_config.dual_stack);
+            udp.set_relay(session_config.relay);
+            #[cfg(feature = "encryption")]
+            udp.set_encryption(
+                session_config
+                    .preshared_key
+                    .map(|key| EncryptedTransport::new(&key)),
+            );
+        }
+        p2p.clone()
+            .lock()
+            .init(p2p.clone(), session_config.local_port)?;
 
         Ok(p2p.clone())
     }
@@ -157,18 +333,141 @@ impl<T: GGPOSessionCallbacks + Send + Sync> Peer2PeerBackend<T> {
         /*
          * Initialize the UDP port
          */
-        self.udp.lock().init(local_port, self.poll.clone(), p2p)?;
+        let mut udp = self.udp.lock();
+        udp.init(local_port, self.poll.clone(), p2p)?;
+        if self.send_task {
+            udp.start_send_task()?;
+        }
+        Ok(())
+    }
+
+    /*
+     * Runs every endpoint's timers, and the session's own, off `clock` in
+     * place of the wall clock, so tests can step through timeouts without
+     * waiting for them.
+     */
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        for endpoint in self.endpoints.iter().chain(self.spectators.iter()) {
+            endpoint.lock().set_clock(clock.clone());
+        }
+        self.udp.lock().set_clock(clock.clone());
+        self.clock = clock;
+        self.timers = None;
+    }
+
+    /*
+     * Keeps time by `timers`, as `set_clock` would, and has
+     * `sleep_until_next_timer` wait on them, under whichever async runtime
+     * `timers` belongs to.
+     */
+    pub fn set_timers(&mut self, timers: Arc<dyn TimerSource>) {
+        self.set_clock(timers.clone());
+        self.timers = Some(timers);
+    }
+
+    // When the soonest of the endpoints' and the transport's timers is due.
+    pub fn next_timer(&self) -> Option<SystemTime> {
+        self.endpoints
+            .iter()
+            .chain(self.spectators.iter())
+            .filter_map(|endpoint| endpoint.lock().next_timer())
+            .chain(self.udp.lock().next_timer())
+            .min()
+    }
+
+    /*
+     * Sleeps on the session's `TimerSource` until `next_timer`, when
+     * `poll_once` next has a timer to run.  The wait doesn't hold on to the
+     * session, so it only needs locking long enough to take it.  `None`
+     * without a `TimerSource`, or with no timer set; packets arriving still
+     * need polling for either way.
+     */
+    pub fn sleep_until_next_timer(&self) -> Option<Sleep> {
+        let timers = self.timers.as_ref()?;
+        Some(timers.sleep_until(self.next_timer()?))
+    }
+
+    /*
+     * Simulates a bad link by holding every packet we send for `latency` ms
+     * and dropping `loss_percent` of them.  For testing only.
+     */
+    pub fn set_network_conditions(&mut self, latency: i32, loss_percent: i32) {
+        for endpoint in self
+            .endpoints
+            .iter()
+            .take(self.num_players)
+            .chain(self.spectators.iter().take(self.num_spectators))
+        {
+            let mut endpoint = endpoint.lock();
+            endpoint.set_send_latency(latency);
+            endpoint.set_packet_loss(loss_percent);
+        }
+    }
+
+    /*
+     * How much that latency varies, in ms; see `UdpProtocol::set_send_jitter`.
+     * It's a standard deviation, so it can't be negative.
+     */
+    pub fn set_network_jitter(&mut self, jitter: i32) -> Result<(), GGPOError> {
+        if jitter < 0 {
+            error!("Refusing a network jitter of {} ms.\n", jitter);
+            return Err(GGPOError::InvalidRequest);
+        }
+        for endpoint in self
+            .endpoints
+            .iter()
+            .take(self.num_players)
+            .chain(self.spectators.iter().take(self.num_spectators))
+        {
+            endpoint.lock().set_send_jitter(jitter);
+        }
         Ok(())
     }
 
-    // Take a player handle and return that player's input queue....I think.
-    fn player_handle_to_queue(&self, player: PlayerHandle) -> Result<u32, GGPOError> {
-        let offset = player - 1;
-        // if offset < 0 || offset >= self.num_players as u32 {
-        if offset >= self.num_players as u32 {
-            return Err(GGPOError::InvalidPlayerHandle);
+    /*
+     * Swaps in another game's callbacks, for a scene change, without dropping
+     * the session.  They take over once every frame up to the current one is
+     * confirmed, never mid-rollback; see `GGPOSync::set_callbacks`.  Until
+     * then rollbacks still go to the old ones, and the new ones hear
+     * `Event::CallbacksSwapped` when it's done.
+     */
+    pub fn set_callbacks(&mut self, callbacks: Arc<Mutex<T>>) {
+        self.sync.lock().set_callbacks(callbacks);
+    }
+
+    /*
+     * Flips a bit of `player`'s input for `frame` as it arrives, so this
+     * session runs it with input its peer never sent.  For testing only.
+     */
+    pub fn set_input_corruption(&mut self, player: PlayerHandle, frame: Frame) {
+        if let Some(endpoint) = self.endpoints.get((player as usize).wrapping_sub(1)) {
+            endpoint.lock().set_input_corruption(frame);
+        }
+    }
+
+    /*
+     * Look up a player by handle.  Every method taking a handle from the game
+     * goes through here, so they all fail the same way: handles past the end
+     * of the player table are out of range, handles for slots nobody was added
+     * to are invalid, and players we've dropped are disconnected.
+     */
+    fn handle_to_player(&self, handle: PlayerHandle) -> Result<&PlayerInfo, GGPOError> {
+        let queue = handle
+            .checked_sub(1)
+            .ok_or(GGPOError::InvalidPlayerHandle)?;
+        let player = self
+            .players
+            .get(queue as usize)
+            .ok_or(GGPOError::PlayerOutOfRange)?
+            .as_ref()
+            .ok_or(GGPOError::InvalidPlayerHandle)?;
+        if self.local_connect_status[queue as usize]
+            .lock()
+            .disconnected
+        {
+            return Err(GGPOError::PlayerDisconnected);
         }
-        Ok(offset)
+        Ok(player)
     }
 
     fn queue_to_player_handle(queue: u32) -> PlayerHandle {
@@ -179,24 +478,27 @@ impl<T: GGPOSessionCallbacks + Send + Sync> Peer2PeerBackend<T> {
         queue + 1000 /* out of range of the player array, basically */
     }
 
-    fn disconnect_player_queue(&self, queue: u32, sync_to: FrameNum) -> Result<(), Peer2PeerError> {
+    fn disconnect_player_queue(&self, queue: u32, sync_to: Frame) -> Result<(), Peer2PeerError> {
         let frame_count = self.sync.lock().get_frame_count();
 
         self.endpoints[queue as usize].lock().disconnect()?;
         {
             let mut local_connect_status = self.local_connect_status[queue as usize].lock();
-            info!("Changing queue {:?} local connect status for last frame from {:?} to {:?} on disconnect request (current: {:?}).\n", queue, local_connect_status.last_frame, sync_to, frame_count);
+            info!("Changing queue {:?} local connect status for last frame from {} to {} on disconnect request (current: {:?}).\n", queue, local_connect_status.last_frame, sync_to, frame_count);
             local_connect_status.disconnected = true;
-            local_connect_status.last_frame = Some(sync_to);
+            local_connect_status.last_frame = sync_to;
         }
 
-        if sync_to < frame_count {
-            info!(
-                "adjusting simulation to account for the fact that {:?} disconnected @ {:?}.\n",
-                queue, sync_to
-            );
-            self.sync.lock().adjust_simulation(sync_to)?;
-            info!("Finished adjusting simulation.\n");
+        // A player that never sent us a frame has nothing to roll back to.
+        if let Some(sync_to) = sync_to.number() {
+            if sync_to < frame_count {
+                info!(
+                    "adjusting simulation to account for the fact that {:?} disconnected @ {:?}.\n",
+                    queue, sync_to
+                );
+                self.sync.lock().adjust_simulation(sync_to)?;
+                info!("Finished adjusting simulation.\n");
+            }
         }
 
         let info = ggpo::Event::DisconnectedFromPeer(ggpo::DisconnectedFromPeer {
@@ -220,25 +522,35 @@ impl<T: GGPOSessionCallbacks + Send + Sync> Peer2PeerBackend<T> {
     }
 
     fn poll_udp_protocol_events(&mut self) -> Result<(), Peer2PeerError> {
-        let mut event = udp_proto::Event::Unknown;
-        for i in 0..self.num_players {
-            let mut endpoint = self.endpoints[i].lock();
-            while endpoint.get_event(&mut event) {
-                self.on_udp_protocol_peer_event(&mut event, i as u32)?;
-            }
+        // Handling an event can lock its endpoint again (to disconnect a player,
+        // say), so collect them all before handling any.
+        let peer_events = Self::drain_events(&self.endpoints[..self.num_players]);
+        for (event, queue) in peer_events.iter() {
+            self.on_udp_protocol_peer_event(event, *queue)?;
         }
-        for i in 0..self.num_spectators {
-            let mut endpoint = self.endpoints[i].lock();
+        let spectator_events = Self::drain_events(&self.spectators[..self.num_spectators]);
+        for (event, queue) in spectator_events.iter() {
+            self.on_udp_protocol_spectator_event(event, *queue)?;
+        }
+        Ok(())
+    }
+
+    fn drain_events(endpoints: &[Arc<Mutex<UdpProtocol<Self>>>]) -> Vec<(udp_proto::Event, u32)> {
+        let mut events = Vec::new();
+        let mut event = udp_proto::Event::Unknown;
+        for (i, endpoint) in endpoints.iter().enumerate() {
+            let mut endpoint = endpoint.lock();
             while endpoint.get_event(&mut event) {
-                self.on_udp_protocol_spectator_event(&mut event, i as u32)?;
+                let event = std::mem::replace(&mut event, udp_proto::Event::Unknown);
+                events.push((event, i as u32));
             }
         }
-        Ok(())
+        events
     }
 
-    fn poll_2_players(&mut self, _current_frame: FrameNum) -> Result<u32, Peer2PeerError> {
+    fn poll_2_players(&mut self, _current_frame: FrameNum) -> Result<Frame, Peer2PeerError> {
         //discard confirmed frames as appropriate
-        let mut total_min_confirmed = std::u32::MAX;
+        let mut total_min_confirmed = Frame::MAX;
         for i in 0..self.num_players {
             let mut queue_connected = true;
             // need to drop the lock here
@@ -250,37 +562,34 @@ impl<T: GGPOSessionCallbacks + Send + Sync> Peer2PeerBackend<T> {
                 }
             }
 
-            let local_connect_status = self.local_connect_status[i].lock();
+            let local_connect_status = *self.local_connect_status[i].lock();
             if !local_connect_status.disconnected {
-                total_min_confirmed = std::cmp::min(
-                    local_connect_status
-                        .last_frame
-                        .ok_or(Peer2PeerError::LastFrameNone)?,
-                    total_min_confirmed,
-                )
+                total_min_confirmed =
+                    std::cmp::min(local_connect_status.last_frame, total_min_confirmed)
             }
             info!(
-                "local endp: connected = {:?}, last_received = {:?}, total_min_confirmed = {:?}.\n",
+                "local endp: connected = {:?}, last_received = {}, total_min_confirmed = {}.\n",
                 !local_connect_status.disconnected,
                 local_connect_status.last_frame,
                 total_min_confirmed
             );
+            // Copied out above, as disconnecting takes the lock again.
             if !queue_connected && !local_connect_status.disconnected {
                 info!("disconnecting i {:?} by remote request.\n", i);
                 self.disconnect_player_queue(i as u32, total_min_confirmed)?;
             }
 
-            info!("total_min_confirmed = {:?}.\n", total_min_confirmed);
+            info!("total_min_confirmed = {}.\n", total_min_confirmed);
         }
         Ok(total_min_confirmed)
     }
 
-    fn poll_n_players(&mut self, _current_frame: FrameNum) -> Result<u32, Peer2PeerError> {
+    fn poll_n_players(&mut self, _current_frame: FrameNum) -> Result<Frame, Peer2PeerError> {
         // discard confirmed frames as appropriate
-        let mut total_min_confirmed = std::u32::MAX;
+        let mut total_min_confirmed = Frame::MAX;
         for queue in 0..self.num_players {
             let mut queue_connected = true;
-            let mut queue_min_confirmed = std::u32::MAX;
+            let mut queue_min_confirmed = Frame::MAX;
             info!("considering queue {:?}.\n", queue);
             for i in 0..self.num_players {
                 // we're going to do a lot of logic here in consideration of endpoint i.
@@ -292,27 +601,20 @@ impl<T: GGPOSessionCallbacks + Send + Sync> Peer2PeerBackend<T> {
                     let (last_received, connected) = endpoint.get_peer_connect_status(queue);
 
                     queue_connected = queue_connected && connected;
-                    queue_min_confirmed = std::cmp::min(
-                        last_received.ok_or(Peer2PeerError::LastFrameNone)?,
-                        queue_min_confirmed,
-                    );
-                    info!("endpoint {:?}: connected = {:?}, last_received = {:?}, queue_min_confirmed = {:?}.\n", i, connected, last_received, queue_min_confirmed);
+                    queue_min_confirmed = std::cmp::min(last_received, queue_min_confirmed);
+                    info!("endpoint {:?}: connected = {:?}, last_received = {}, queue_min_confirmed = {}.\n", i, connected, last_received, queue_min_confirmed);
                 } else {
                     info!("endpoint {:?}: ignoring... not running.\n", i);
                 }
             }
-            let local_connect_status = self.local_connect_status[queue].lock();
+            let local_connect_status = *self.local_connect_status[queue].lock();
             // merge in our local status only if we're still connected!
             if !local_connect_status.disconnected {
-                queue_min_confirmed = std::cmp::min(
-                    local_connect_status
-                        .last_frame
-                        .ok_or(Peer2PeerError::LastFrameNone)?,
-                    queue_min_confirmed,
-                );
+                queue_min_confirmed =
+                    std::cmp::min(local_connect_status.last_frame, queue_min_confirmed);
             }
             info!(
-                "local endp: connected = {:?}, last_received = {:?}, queue_min_confirmed = {:?}.\n",
+                "local endp: connected = {:?}, last_received = {}, queue_min_confirmed = {}.\n",
                 !local_connect_status.disconnected,
                 local_connect_status.last_frame,
                 queue_min_confirmed
@@ -325,20 +627,30 @@ impl<T: GGPOSessionCallbacks + Send + Sync> Peer2PeerBackend<T> {
                 // so, we need to re-adjust.  This can happen when we detect our own disconnect at frame n
                 // and later receive a disconnect notification for frame n-1.
                 if !local_connect_status.disconnected
-                    || local_connect_status
-                        .last_frame
-                        .ok_or(Peer2PeerError::LastFrameNone)?
-                        > queue_min_confirmed
+                    || local_connect_status.last_frame > queue_min_confirmed
                 {
                     info!("disconnecting queue {:?} by remote request.\n", queue);
                     self.disconnect_player_queue(queue as u32, queue_min_confirmed)?;
                 }
             }
-            info!("total_min_confirmed = {:?}.\n", total_min_confirmed);
+            info!("total_min_confirmed = {}.\n", total_min_confirmed);
         }
         Ok(total_min_confirmed)
     }
 
+    /*
+     * What our endpoints send: the local player's input size, or the session's
+     * until a local player is added.
+     */
+    fn local_input_size(&self) -> usize {
+        let sync = self.sync.lock();
+        self.players
+            .iter()
+            .flatten()
+            .find(|info| matches!(info.player_type, crate::player::PlayerType::Local))
+            .map_or(self.input_size, |info| sync.input_size(info.queue as usize))
+    }
+
     fn add_remote_player(
         &mut self,
         remote_addr: SocketAddr,
@@ -351,6 +663,7 @@ impl<T: GGPOSessionCallbacks + Send + Sync> Peer2PeerBackend<T> {
 
         *self.synchronizing.lock() = true;
 
+        let local_input_size = self.local_input_size();
         let mut endpoint = self.endpoints[queue as usize].lock();
         endpoint.init(
             self.udp.clone(),
@@ -360,21 +673,38 @@ impl<T: GGPOSessionCallbacks + Send + Sync> Peer2PeerBackend<T> {
         );
         endpoint.set_disconnect_timeout(self.disconnect_timeout);
         endpoint.set_disconnect_notify_start(self.disconnect_notify_start);
+        endpoint.set_transport_profile(self.transport_profile);
+        endpoint.set_retransmit_interval(self.retransmit_interval);
+        endpoint.set_send_rate_limit(self.send_rate_limit);
+        endpoint.set_reconnect_window(self.reconnect_window);
+        endpoint.set_local_input_size(local_input_size);
+        endpoint.set_remote_input_size(self.sync.lock().input_size(queue as usize));
+        endpoint.set_seed_nonce(self.seed_nonce);
+        endpoint.set_version(self.version);
+        if self.broadcast {
+            // The player's session sends us what it sends its spectators.
+            endpoint.set_input_size(GAMEINPUT_MAX_BYTES * self.num_players);
+        }
+        self.configure_sync(&mut endpoint);
         Ok(endpoint.synchronize()?)
     }
 
-    fn add_spectator(&mut self, remote_addr: SocketAddr) -> Result<(), GGPOError> {
+    fn add_spectator(
+        &mut self,
+        remote_addr: SocketAddr,
+        handle: &mut PlayerHandle,
+    ) -> Result<(), GGPOError> {
         if self.num_spectators == GGPO_MAX_SPECTATORS {
             return Err(GGPOError::TooManySpectators);
         }
         /*
-         * Currently, we can only add spectators before the game starts.
+         * Spectators added once the game is running only get input from the
+         * frame they finish synchronizing on, and ask for a saved state to
+         * start from (see `send_state_snapshot`).
          */
-        if !*self.synchronizing.lock() {
-            return Err(GGPOError::InvalidRequest);
-        }
         let queue: u32 = self.num_spectators as u32;
         self.num_spectators += 1;
+        *handle = Self::queue_to_spectator_handle(queue);
 
         let mut spectator = self.spectators[queue as usize].lock();
         spectator.init(
@@ -385,10 +715,319 @@ impl<T: GGPOSessionCallbacks + Send + Sync> Peer2PeerBackend<T> {
         );
         spectator.set_disconnect_timeout(self.disconnect_timeout);
         spectator.set_disconnect_notify_start(self.disconnect_notify_start);
+        spectator.set_transport_profile(self.transport_profile);
+        spectator.set_retransmit_interval(self.retransmit_interval);
+        spectator.set_send_rate_limit(self.send_rate_limit);
+        // Spectators get every player's input in one message.
+        spectator.set_input_size(GAMEINPUT_MAX_BYTES * self.num_players);
+        spectator.set_version(self.version);
+        self.configure_sync(&mut spectator);
 
         Ok(spectator.synchronize()?)
     }
 
+    /*
+     * Hands a spectator its next frame of input.  A spectator that can't keep
+     * up is dropped before it holds up anyone else: once it's left more than
+     * `spectator_lag_window` frames unacked, or has so many that they no
+     * longer fit in a message, it's disconnected and its backlog let go.
+     */
+    fn send_spectator_input(&mut self, i: usize, input: &GameInput) -> Result<(), Peer2PeerError> {
+        let mut spectator = self.spectators[i].lock();
+        if spectator.is_running() && self.spectator_first_frame[i].is_none() {
+            self.spectator_first_frame[i] = Some(input.frame);
+        }
+        let sent = spectator.send_input(input);
+        // One already dropped is only being told so.
+        if spectator.is_disconnected() {
+            return Ok(());
+        }
+        let lagging =
+            spectator.is_running() && spectator.unacked_input_len() > self.spectator_lag_window;
+        match sent {
+            Ok(()) if !lagging => return Ok(()),
+            Ok(()) => error!(
+                "Spectator {} hasn't acked input since frame {:?}.  Dropping them.\n",
+                i,
+                spectator.oldest_unacked_frame()
+            ),
+            Err(e) => error!(
+                "Can't send spectator {} input ({}).  Dropping them.\n",
+                i, e
+            ),
+        }
+        spectator.disconnect()?;
+        spectator.clear_pending_output();
+        self.callbacks
+            .lock()
+            .on_event(&ggpo::Event::DisconnectedFromPeer(
+                ggpo::DisconnectedFromPeer {
+                    player: Self::queue_to_spectator_handle(i as u32),
+                },
+            ));
+        Ok(())
+    }
+
+    fn configure_sync(&self, endpoint: &mut UdpProtocol<Self>) {
+        endpoint.set_num_sync_packets(self.num_sync_packets);
+        endpoint.set_sync_retry_intervals(self.sync_first_retry_interval, self.sync_retry_interval);
+        endpoint.set_sync_timeout(self.sync_timeout);
+    }
+
+    /*
+     * Sends the peers our checksum for every frame confirmed since the last
+     * poll, and checks it against any they've already sent.  Has to run before
+     * the sync layer discards the confirmed inputs.  Frames whose saved state
+     * has already left the ring are skipped.
+     */
+    fn exchange_checksums(&mut self, confirmed: Frame) -> Result<(), Peer2PeerError> {
+        while self.next_checksum_frame <= confirmed {
+            let frame = self.next_checksum_frame;
+            self.next_checksum_frame = frame.next();
+            let checksum = match self.sync.lock().saved_checksum(frame) {
+                Some(checksum) => checksum,
+                None => continue,
+            };
+            let mut inputs: InputBuffer = Default::default();
+            self.sync.lock().get_confirmed_inputs(&mut inputs, frame)?;
+
+            for endpoint in self.endpoints[..self.num_players].iter() {
+                let mut endpoint = endpoint.lock();
+                if endpoint.is_running() {
+                    endpoint.send_checksum(frame, checksum)?;
+                }
+            }
+            let reports = self.desync.lock().record_local(frame, &inputs, checksum);
+            for report in reports {
+                self.on_desync(report)?;
+            }
+        }
+        Ok(())
+    }
+
+    /*
+     * Folds every frame confirmed since the last poll into the rolling input
+     * checksum, and hands the latest to the endpoints for their next quality
+     * report.  Like `exchange_checksums`, has to run before the sync layer
+     * discards the confirmed inputs.
+     */
+    fn update_input_checksums(&mut self, confirmed: Frame) -> Result<(), Peer2PeerError> {
+        let checksums = match &self.input_checksums {
+            Some(checksums) => checksums.clone(),
+            None => return Ok(()),
+        };
+        let sizes = self.sync.lock().input_sizes();
+        while self.next_input_checksum_frame <= confirmed {
+            let frame = self.next_input_checksum_frame;
+            self.next_input_checksum_frame = frame.next();
+            let mut inputs: InputBuffer = Default::default();
+            self.sync.lock().get_confirmed_inputs(&mut inputs, frame)?;
+            let mismatched = checksums.lock().record_local(frame, &inputs, &sizes);
+            for queue in mismatched {
+                self.on_input_desync(queue as u32, frame);
+            }
+        }
+        if let Some((frame, checksum)) = checksums.lock().latest() {
+            for endpoint in self.endpoints[..self.num_players].iter() {
+                endpoint.lock().set_input_checksum(frame, checksum);
+            }
+        }
+        Ok(())
+    }
+
+    fn on_input_desync(&self, queue: u32, frame: Frame) {
+        let player = Self::queue_to_player_handle(queue);
+        error!(
+            "Input checksums disagree with player {} as of frame {}.\n",
+            player, frame
+        );
+        self.callbacks
+            .lock()
+            .on_event(&ggpo::Event::InputDesyncDetected(
+                ggpo::InputDesyncDetected { player, frame },
+            ));
+    }
+
+    /*
+     * Tells the game about each frame that's become final since the last poll:
+     * confirmed for every player, and already run, which after
+     * `check_simulation` means run with the real inputs.
+     */
+    fn report_confirmed_frames(&mut self, confirmed: Frame, current_frame: FrameNum) {
+        while self.next_confirmed_frame <= confirmed
+            && self.next_confirmed_frame < Frame::new(current_frame)
+        {
+            let frame = self.next_confirmed_frame;
+            self.next_confirmed_frame = frame.next();
+            self.callbacks
+                .lock()
+                .on_event(&ggpo::Event::FrameConfirmed(ggpo::FrameConfirmed { frame }));
+        }
+    }
+
+    // `NotSynchronized`, or better, why a handshake with a player failed.
+    fn not_synchronized(&self) -> GGPOError {
+        self.endpoints[..self.num_players]
+            .iter()
+            .find_map(|endpoint| endpoint.lock().sync_failure())
+            .map_or(GGPOError::NotSynchronized, GGPOError::from)
+    }
+
+    fn swap_callbacks(&mut self) -> Result<(), GGPOError> {
+        let frame = match self.sync.lock().swap_callbacks()? {
+            Some(frame) => frame,
+            None => return Ok(()),
+        };
+        if let Some(callbacks) = self.sync.lock().callbacks() {
+            self.callbacks = callbacks;
+        }
+        self.callbacks
+            .lock()
+            .on_event(&ggpo::Event::CallbacksSwapped(ggpo::CallbacksSwapped {
+                frame,
+            }));
+        Ok(())
+    }
+
+    /*
+     * In auto mode, moves every local player's frame delay to match the
+     * slowest peer's smoothed round trip, and tells the game when it changes.
+     */
+    fn update_auto_frame_delay(&mut self) {
+        let auto = match self.auto_frame_delay.as_mut() {
+            Some(auto) => auto,
+            None => return,
+        };
+        let round_trip_time = match self
+            .endpoints
+            .iter()
+            .take(self.num_players)
+            .filter_map(|endpoint| endpoint.lock().smoothed_round_trip_time())
+            .max()
+        {
+            Some(round_trip_time) => round_trip_time,
+            None => return,
+        };
+        let delay = match auto.update(round_trip_time) {
+            Some(delay) => delay,
+            None => return,
+        };
+
+        self.frame_delay = delay;
+        let mut sync = self.sync.lock();
+        for player in self.players.iter().flatten() {
+            if let crate::player::PlayerType::Local = player.player_type {
+                sync.set_frame_delay(player.queue as usize, delay);
+            }
+        }
+        drop(sync);
+        self.callbacks
+            .lock()
+            .on_event(&ggpo::Event::FrameDelayChanged(ggpo::FrameDelayChanged {
+                delay,
+                round_trip_time: round_trip_time as usize,
+            }));
+    }
+
+    // Tells the game when adaptive prediction has moved the prediction window.
+    fn report_prediction_window(&mut self) {
+        let (frames, mispredict_rate) = {
+            let sync = self.sync.lock();
+            let adaptive = match sync.adaptive_prediction() {
+                Some(adaptive) => adaptive,
+                None => return,
+            };
+            (adaptive.frames(), adaptive.mispredict_rate())
+        };
+        if frames == self.prediction_frames {
+            return;
+        }
+        self.prediction_frames = frames;
+        self.callbacks
+            .lock()
+            .on_event(&ggpo::Event::PredictionWindowChanged(
+                ggpo::PredictionWindowChanged {
+                    frames,
+                    mispredict_rate,
+                },
+            ));
+    }
+
+    /*
+     * Tells the game it's too far ahead of a peer to take input this frame.
+     * Every local player hits the barrier on the same frame, so only the
+     * first one's refusal counts.
+     */
+    fn on_prediction_barrier(&mut self, queue: u32) {
+        let first_local = self
+            .players
+            .iter()
+            .flatten()
+            .filter(|info| matches!(info.player_type, crate::player::PlayerType::Local))
+            .map(|info| info.queue)
+            .min();
+        if first_local != Some(queue) {
+            return;
+        }
+        let frames_stalled = self.prediction_barrier.map_or(1, |frames| frames + 1);
+        self.prediction_barrier = Some(frames_stalled);
+        let estimated_frames = self
+            .endpoints
+            .iter()
+            .take(self.num_players)
+            .filter_map(|endpoint| endpoint.lock().smoothed_round_trip_time())
+            .max()
+            .map_or(1, |round_trip_time| {
+                time_sync::one_way_frames(round_trip_time).max(1)
+            });
+        self.callbacks
+            .lock()
+            .on_event(&ggpo::Event::PredictionBarrier(ggpo::PredictionBarrier {
+                frames_stalled,
+                estimated_frames,
+            }));
+    }
+
+    fn on_desync(&self, report: DesyncReport) -> Result<(), Peer2PeerError> {
+        error!(
+            "Desync with player {} at frame {}: local {:#x}, remote {:#x}.\n",
+            report.player, report.frame, report.local_checksum, report.remote_checksum
+        );
+        self.callbacks
+            .lock()
+            .on_event(&ggpo::Event::DesyncDetected(report));
+        match self.on_desync {
+            DesyncAction::Continue => {}
+            DesyncAction::Pause => {
+                if !self.pause.lock().paused {
+                    self.begin_pause()?;
+                }
+            }
+            DesyncAction::Disconnect => self.leave_match()?,
+        }
+        Ok(())
+    }
+
+    /*
+     * Says goodbye to every remote player and disconnects them at the current
+     * frame, as disconnecting the local player does.  The socket stays open,
+     * so the session can still be polled afterwards.
+     */
+    fn leave_match(&self) -> Result<(), Peer2PeerError> {
+        info!("Leaving the match.\n");
+        let frame = Frame::new(self.sync.lock().get_frame_count());
+        for queue in 0..self.num_players {
+            if !self.endpoints[queue].lock().is_initialized()
+                || self.local_connect_status[queue].lock().disconnected
+            {
+                continue;
+            }
+            self.endpoints[queue].lock().send_goodbye()?;
+            self.disconnect_player_queue(queue as u32, frame)?;
+        }
+        Ok(())
+    }
+
     // Is this supposed to do anything?
     fn on_sync_event(&mut self, _event: &sync::Event) {}
 
@@ -422,56 +1061,202 @@ impl<T: GGPOSessionCallbacks + Send + Sync> Peer2PeerBackend<T> {
                 });
                 self.callbacks.lock().on_event(&info);
             }
-            udp_proto::Event::NetworkResumed => {
+            udp_proto::Event::NetworkResumed | udp_proto::Event::Resumed => {
                 info = ggpo::Event::ConnectionResumed(ggpo::ConnectionResumed { player: handle });
                 self.callbacks.lock().on_event(&info);
             }
+            udp_proto::Event::InputSizeMismatch(mismatch) => {
+                info = ggpo::Event::InputSizeMismatch(ggpo::InputSizeMismatch {
+                    player: handle,
+                    local_size: mismatch.local,
+                    remote_size: mismatch.remote,
+                });
+                self.callbacks.lock().on_event(&info);
+            }
+            udp_proto::Event::IncompatibleVersion(mismatch) => {
+                info = ggpo::Event::IncompatibleVersion(ggpo::IncompatibleVersion {
+                    player: handle,
+                    local_version: mismatch.local,
+                    remote_version: mismatch.remote,
+                });
+                self.callbacks.lock().on_event(&info);
+            }
+            udp_proto::Event::QualityChanged(quality) => {
+                info = ggpo::Event::ConnectionQualityChanged(ggpo::ConnectionQualityChanged {
+                    player: handle,
+                    quality: *quality,
+                });
+                self.callbacks.lock().on_event(&info);
+            }
+            udp_proto::Event::ClockDrift(drift_ppm) => {
+                info = ggpo::Event::ClockDrift(ggpo::ClockDrift {
+                    player: handle,
+                    drift_ppm: *drift_ppm,
+                });
+                self.callbacks.lock().on_event(&info);
+            }
             _ => {}
         }
     }
 
+    /*
+     * Takes a player back after their endpoint reconnects.  Every frame we
+     * simulated in the meantime keeps the input we ran it with: none at all
+     * if we'd disconnected them, our prediction otherwise.  Those frames are
+     * committed; the player's input only counts again from the resume point,
+     * the later of the current frame and the frame after the last input we
+     * have from them, and we never roll back past it.  Each side picks its own
+     * resume point, so the two simulations may disagree about the frames
+     * around the outage.
+     */
+    fn resume_player_queue(&self, queue: u32) {
+        let frame_count = self.sync.lock().get_frame_count();
+        let mut local_connect_status = self.local_connect_status[queue as usize].lock();
+        let resume_at = std::cmp::max(
+            Frame::new(frame_count),
+            local_connect_status.last_frame.next(),
+        );
+        info!(
+            "Resuming queue {:?} at frame {} (current: {:?}).\n",
+            queue, resume_at, frame_count
+        );
+        self.sync
+            .lock()
+            .freeze_remote_input(queue, resume_at, local_connect_status.disconnected);
+        local_connect_status.disconnected = false;
+        local_connect_status.last_frame = resume_at.prev();
+    }
+
     fn on_udp_protocol_peer_event(
         &self,
         event: &udp_proto::Event,
         queue: u32,
     ) -> Result<(), Peer2PeerError> {
+        if let udp_proto::Event::Resumed = event {
+            // Take them back before anyone hears they're here.
+            self.resume_player_queue(queue);
+        }
         self.on_udp_protocol_event(event, Self::queue_to_player_handle(queue));
 
         match event {
+            udp_proto::Event::Input(input) if self.broadcast => self.on_broadcast_input(input)?,
             udp_proto::Event::Input(input) => {
                 let mut local_connect_status = self.local_connect_status[queue as usize].lock();
                 if !local_connect_status.disconnected {
                     let current_remote_frame = local_connect_status.last_frame;
                     let new_remote_frame = input.frame;
-                    // ASSERT(current_remote_frame == -1 || new_remote_frame == (current_remote_frame + 1));
-                    assert!(
-                        current_remote_frame.is_none()
-                            || new_remote_frame
-                                == Some(
-                                    current_remote_frame
-                                        .ok_or(Peer2PeerError::CurrentRemoteFrameNone)?
-                                        + 1
-                                )
-                    );
+                    if new_remote_frame <= current_remote_frame {
+                        // Input for frames committed when the player reconnected.
+                        info!(
+                            "Skipping input for frame {} from queue {:?} (have up to {}).\n",
+                            new_remote_frame, queue, current_remote_frame
+                        );
+                        return Ok(());
+                    }
+                    if new_remote_frame > current_remote_frame.next() {
+                        // They got ahead of the resume point while reconnecting.
+                        self.sync
+                            .lock()
+                            .fill_remote_input_gap(queue, new_remote_frame)?;
+                    }
 
-                    self.sync.lock().add_remote_input(queue, input);
+                    self.sync.lock().add_remote_input(queue, input)?;
                     // Notify the other endpoints which frame we received from a peer
                     info!(
-                        "Setting remote connect status for queue {:?} to {:?}.\n",
+                        "Setting remote connect status for queue {:?} to {}.\n",
                         queue, input.frame
                     );
                     local_connect_status.last_frame = input.frame;
                 }
             }
             udp_proto::Event::Disconnected => {
-                self.disconnect_player(Self::queue_to_player_handle(queue))
-                    .map_err(|e| Peer2PeerError::GGPO(e.to_string()))?;
+                // Nothing to do if we'd already dropped them, leaving the match ourselves.
+                if !self.local_connect_status[queue as usize]
+                    .lock()
+                    .disconnected
+                {
+                    self.disconnect_player(Self::queue_to_player_handle(queue))
+                        .map_err(|e| Peer2PeerError::GGPO(e.to_string()))?;
+                }
+            }
+            udp_proto::Event::Pause(pause) => self.on_peer_pause(pause)?,
+            udp_proto::Event::Checksum(report) => {
+                let desync =
+                    self.desync
+                        .lock()
+                        .record_remote(queue as usize, report.frame, report.checksum);
+                if let Some(desync) = desync {
+                    self.on_desync(desync)?;
+                }
+            }
+            udp_proto::Event::InputChecksum(report) => {
+                if let Some(checksums) = &self.input_checksums {
+                    let mismatch = checksums.lock().record_remote(
+                        queue as usize,
+                        report.frame,
+                        report.checksum,
+                    );
+                    if mismatch {
+                        self.on_input_desync(queue, report.frame);
+                    }
+                }
             }
             _ => {}
         }
         Ok(())
     }
 
+    /*
+     * Takes a frame of every player's confirmed input from one of the
+     * players' sessions.  Each of them sends the same frames, so a player's
+     * row is only taken from whichever arrives first.
+     */
+    fn on_broadcast_input(&self, input: &GameInput) -> Result<(), Peer2PeerError> {
+        for queue in 0..self.num_players {
+            let mut local_connect_status = self.local_connect_status[queue].lock();
+            if local_connect_status.disconnected
+                || input.frame != local_connect_status.last_frame.next()
+            {
+                continue;
+            }
+            let mut bits = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+            bits[0] = input.bits[queue];
+            let mut sync = self.sync.lock();
+            let row = GameInput::init(input.frame, Some(&bits), sync.input_size(queue));
+            sync.add_remote_input(queue as u32, &row)?;
+            local_connect_status.last_frame = input.frame;
+        }
+        Ok(())
+    }
+
+    /*
+     * A broadcast host never predicts: it runs each frame, handing the game
+     * the inputs through `fast_forward_frame`, as soon as every player still
+     * connected has sent theirs.  Saving each frame as it goes gives the usual
+     * checksums and snapshots for late spectators.
+     */
+    fn step_broadcast(&mut self) -> Result<(), Peer2PeerError> {
+        loop {
+            let frame = Frame::new(self.sync.lock().get_frame_count());
+            let mut connected = 0;
+            let mut ready = true;
+            for status in self.local_connect_status[..self.num_players].iter() {
+                let status = status.lock();
+                if !status.disconnected {
+                    connected += 1;
+                    ready &= status.last_frame >= frame;
+                }
+            }
+            if connected == 0 || !ready {
+                return Ok(());
+            }
+            let mut values: InputBuffer = Default::default();
+            let flags = self.sync.lock().synchronize_inputs(&mut values)?;
+            self.callbacks.lock().fast_forward_frame(&values, flags);
+            self.sync.lock().increment_frame()?;
+        }
+    }
+
     fn on_udp_protocol_spectator_event(
         &self,
         event: &udp_proto::Event,
@@ -482,6 +1267,7 @@ impl<T: GGPOSessionCallbacks + Send + Sync> Peer2PeerBackend<T> {
 
         let info: ggpo::Event;
         match event {
+            udp_proto::Event::StateRequested => self.send_state_snapshot(queue)?,
             udp_proto::Event::Disconnected => {
                 self.spectators[queue as usize].lock().disconnect()?;
                 info = ggpo::Event::DisconnectedFromPeer(ggpo::DisconnectedFromPeer {
@@ -494,18 +1280,151 @@ impl<T: GGPOSessionCallbacks + Send + Sync> Peer2PeerBackend<T> {
         Ok(())
     }
 
-    fn check_initial_sync(&self) {
-        if *self.synchronizing.lock() {
-            // Check to see if everyone is now synchronized.  If so,
-            // go ahead and tell the client that we're ok to accept input.
-            for i in 0..self.num_players {
-                // xxx: IsInitialized() must go... we're actually using it as a proxy for "represents the local player"
-                // TODO: How can we implement the above comments request....
-                let endpoint = self.endpoints[i].lock();
-                if endpoint.is_initialized()
-                    && !endpoint.is_sychronized()
-                    && !self.local_connect_status[i].lock().disconnected
-                {
+    /*
+     * Sends a spectator who joined mid-match the newest state it can pick up
+     * from: one whose inputs are all confirmed, and no earlier than the first
+     * frame of input it was sent.  Snapshots are expensive, so however many
+     * spectators ask, we send at most one per `STATE_REQUEST_INTERVAL`; the
+     * rest keep asking until they get theirs.
+     */
+    fn send_state_snapshot(&self, queue: u32) -> Result<(), Peer2PeerError> {
+        let now = self
+            .clock
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| Peer2PeerError::GGPO(e.to_string()))?
+            .as_millis();
+        let mut last_state_sent = self.last_state_sent.lock();
+        if now < *last_state_sent + udp_proto::STATE_REQUEST_INTERVAL {
+            return Ok(());
+        }
+
+        let mut spectator = self.spectators[queue as usize].lock();
+        if !spectator.is_running() {
+            return Ok(());
+        }
+        let frame_count = self.sync.lock().get_frame_count();
+        let frame = Frame::new(std::cmp::min(self.next_spectator_frame, frame_count));
+        if frame < self.spectator_first_frame[queue as usize].unwrap_or(frame) {
+            return Ok(());
+        }
+        let state = match self.sync.lock().saved_state(frame) {
+            Some(state) => state,
+            None => {
+                info!(
+                    "No saved state for frame {} to send spectator {:?}.\n",
+                    frame, queue
+                );
+                return Ok(());
+            }
+        };
+        if state.data.len() > MAX_STATE_SNAPSHOT_SIZE {
+            error!(
+                "Saved state for frame {} is {} bytes, too big to send spectator {:?} (limit {}).\n",
+                frame,
+                state.data.len(),
+                queue,
+                MAX_STATE_SNAPSHOT_SIZE
+            );
+            return Ok(());
+        }
+
+        info!(
+            "Sending spectator {:?} the state for frame {}.\n",
+            queue, frame
+        );
+        spectator.send_state(frame, state.checksum.unwrap_or_default(), state.data)?;
+        *last_state_sent = now;
+        Ok(())
+    }
+
+    // Starts a new pause epoch at the current frame and tells every peer.
+    fn begin_pause(&self) -> Result<(), Peer2PeerError> {
+        let state = {
+            let mut state = self.pause.lock();
+            state.epoch += 1;
+            state.paused = true;
+            state.frame = Frame::new(self.sync.lock().get_frame_count());
+            *state
+        };
+        info!("Pausing at frame {}.\n", state.frame);
+        self.broadcast_pause(state)?;
+        let info = ggpo::Event::SessionPaused(ggpo::SessionPaused { frame: state.frame });
+        self.callbacks.lock().on_event(&info);
+        Ok(())
+    }
+
+    fn broadcast_pause(&self, state: PauseState) -> Result<(), Peer2PeerError> {
+        for endpoint in self.endpoints[..self.num_players].iter() {
+            let mut endpoint = endpoint.lock();
+            if endpoint.is_running() {
+                endpoint.send_pause(state.epoch, state.paused, state.frame)?;
+            }
+        }
+        Ok(())
+    }
+
+    /*
+     * A peer paused or resumed.  Hearing about a new pause, we join it, at
+     * our own frame if we're already past theirs, and tell everyone where we
+     * stopped.  Anything about an older pause is stale: either we've heard
+     * it already, or a resume for it overtook it.
+     */
+    fn on_peer_pause(&self, remote: &Pause) -> Result<(), Peer2PeerError> {
+        let frame_count = Frame::new(self.sync.lock().get_frame_count());
+        let mut state = self.pause.lock();
+        let info = if remote.epoch > state.epoch {
+            state.epoch = remote.epoch;
+            state.paused = remote.paused;
+            if !remote.paused {
+                // We missed the whole pause.
+                return Ok(());
+            }
+            state.frame = std::cmp::max(remote.frame, frame_count);
+            info!(
+                "Peer paused at frame {}.  Pausing at {}.\n",
+                remote.frame, state.frame
+            );
+            let joined = *state;
+            drop(state);
+            self.broadcast_pause(joined)?;
+            ggpo::Event::SessionPaused(ggpo::SessionPaused {
+                frame: joined.frame,
+            })
+        } else if remote.epoch == state.epoch && state.paused {
+            if !remote.paused {
+                info!("Peer resumed at frame {}.\n", state.frame);
+                state.paused = false;
+                ggpo::Event::SessionResumed(ggpo::SessionResumed { frame: state.frame })
+            } else if remote.frame > state.frame {
+                info!(
+                    "Peer was further along.  Pausing at {} instead of {}.\n",
+                    remote.frame, state.frame
+                );
+                state.frame = remote.frame;
+                ggpo::Event::SessionPaused(ggpo::SessionPaused { frame: state.frame })
+            } else {
+                return Ok(());
+            }
+        } else {
+            return Ok(());
+        };
+        self.callbacks.lock().on_event(&info);
+        Ok(())
+    }
+
+    fn check_initial_sync(&self) {
+        if *self.synchronizing.lock() {
+            // Check to see if everyone is now synchronized.  If so,
+            // go ahead and tell the client that we're ok to accept input.
+            for i in 0..self.num_players {
+                // xxx: IsInitialized() must go... we're actually using it as a proxy for "represents the local player"
+                // TODO: How can we implement the above comments request....
+                let endpoint = self.endpoints[i].lock();
+                if endpoint.is_initialized()
+                    && !endpoint.is_sychronized()
+                    && !self.local_connect_status[i].lock().disconnected
+                {
                     return;
                 }
             }
@@ -515,6 +1434,24 @@ impl<T: GGPOSessionCallbacks + Send + Sync> Peer2PeerBackend<T> {
                     return;
                 }
             }
+            /*
+             * Every session contributes a random nonce and hears everyone
+             * else's during the handshake.  They're combined in order of
+             * value, so each peer arrives at the same seed whoever connected
+             * first; see `udp_proto::combine_seed_nonces`.
+             */
+            let endpoints = &self.endpoints[..self.num_players];
+            let nonces: Vec<u64> = endpoints
+                .iter()
+                .filter_map(|endpoint| endpoint.lock().remote_seed_nonce())
+                .collect();
+            let seed = endpoints
+                .iter()
+                .find_map(|endpoint| endpoint.lock().negotiated_seed(&nonces))
+                // With nobody else in the session, it's ours alone.
+                .unwrap_or_else(|| udp_proto::combine_seed_nonces(&mut [self.seed_nonce]));
+            *self.shared_seed.lock() = Some(seed);
+
             let info = crate::ggpo::Event::Running;
 
             self.callbacks.lock().on_event(&info);
@@ -522,56 +1459,241 @@ impl<T: GGPOSessionCallbacks + Send + Sync> Peer2PeerBackend<T> {
         }
     }
 
-    fn pump(&mut self, timeout: Option<std::time::Duration>) -> Result<(), Peer2PeerError> {
-        let mut events = self.events.lock();
-        self.poll.lock().poll(&mut events, timeout)?;
-        for event in events.iter() {
-            match event.token() {
-                Token(0) => {
-                    if event.is_readable() {
-                        self.udp.lock().on_loop_poll(0)?;
-                    }
-                    if event.is_writable() {
-                        for endpoint in self.endpoints.iter() {
-                            endpoint.lock().on_loop_poll(0)?;
-                        }
-                        for spectator in self.spectators.iter() {
-                            spectator.lock().on_loop_poll(0)?;
-                        }
+    /*
+     * Everything a poll does once the network's been read: handling endpoint
+     * events, rolling back, and passing on what's been confirmed.
+     */
+    fn service(&mut self) -> Result<(), GGPOError> {
+        self.poll_udp_protocol_events()?;
+        // A session with nobody to handshake with never hears a sync event.
+        self.check_initial_sync();
+        if !*self.synchronizing.lock() {
+            if self.broadcast {
+                self.step_broadcast()?;
+            }
+            self.sync.lock().check_simulation()?;
+
+            // notify all of our endpoints of their local frame number for their
+            // next connection quality report
+            let current_frame = self.sync.lock().get_frame_count();
+            for i in 0..self.num_players {
+                self.endpoints[i]
+                    .lock()
+                    .set_local_frame_number(current_frame)
+            }
+            let total_min_confirmed;
+            if self.num_players <= 2 {
+                total_min_confirmed = self.poll_2_players(current_frame)?;
+            } else {
+                total_min_confirmed = self.poll_n_players(current_frame)?;
+            }
+
+            info!(
+                "last confirmed frame in p2p backend is {}.\n",
+                total_min_confirmed
+            );
+
+            assert!(total_min_confirmed != Frame::MAX);
+            // Keeps counting with nobody watching, so a spectator who joins
+            // later starts from the current frame.
+            while Frame::new(self.next_spectator_frame) <= total_min_confirmed {
+                if self.num_spectators > 0 {
+                    info!(
+                        "pushing frame {:?} to spectators.\n",
+                        self.next_spectator_frame
+                    );
+
+                    // Spectators get every player's input in one message, one
+                    // player per row of the input buffer.
+                    let mut input = crate::game_input::GameInput::new();
+                    input.size = GAMEINPUT_MAX_BYTES * self.num_players;
+                    input.frame = Frame::new(self.next_spectator_frame);
+                    self.sync
+                        .lock()
+                        .get_confirmed_inputs(&mut input.bits, input.frame)?;
+                    for i in 0..self.num_spectators {
+                        self.send_spectator_input(i, &input)?;
                     }
                 }
-                _ => unreachable!(),
+                self.next_spectator_frame += 1;
+            }
+
+            self.exchange_checksums(total_min_confirmed)?;
+            self.update_input_checksums(total_min_confirmed)?;
+            self.report_confirmed_frames(total_min_confirmed, current_frame);
+            self.update_auto_frame_delay();
+
+            info!(
+                "setting confirmed frame in sync to {}.\n",
+                total_min_confirmed
+            );
+
+            self.sync
+                .lock()
+                .set_last_confirmed_frame(total_min_confirmed)?;
+            self.swap_callbacks()?;
+
+            // send timesync notifications if now is the proper time
+            if current_frame > self.next_recommended_sleep {
+                let mut interval = 0;
+                for i in 0..self.num_players {
+                    interval =
+                        std::cmp::max(interval, self.endpoints[i].lock().recommend_frame_delay());
+                }
+                if interval > 0 {
+                    let info = ggpo::Event::TimeSync(ggpo::TimeSyncEvent {
+                        frames_ahead: interval,
+                    });
+                    self.callbacks.lock().on_event(&info);
+                    self.next_recommended_sleep = current_frame + RECOMMENDATION_INTERVAL;
+                }
+            }
+            // wat
+            // XXX: this is obviously a farce...
+
+            // if timeout > 0 {
+            //     unblock!(std::thread::sleep(std::time::Duration::from_millis(1)));
+            // }
+        }
+        Ok(())
+    }
+
+    fn pump(&mut self, timeout: Option<Duration>) -> Result<(), Peer2PeerError> {
+        let deadline = Instant::now() + timeout.unwrap_or_default();
+        loop {
+            {
+                let mut events = self.events.lock();
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                self.poll.lock().poll(&mut events, Some(remaining))?;
+            }
+
+            // Collect everything first so the socket isn't locked while the
+            // endpoints handle (and possibly reply to) each message.
+            let msgs = self.udp.lock().recv_pending()?;
+            for (msg, len, from) in msgs {
+                self.on_msg(&from, msg, len).map_err(Peer2PeerError::GGPO)?;
+            }
+
+            if Instant::now() >= deadline {
+                break;
+            }
+        }
+        self.poll_endpoints()
+    }
+
+    /*
+     * Like `pump`, but never waits on the socket: handles whatever has
+     * already arrived until `budget` runs out, leaving the rest for next time.
+     */
+    fn pump_once(&mut self, budget: Duration) -> Result<(), Peer2PeerError> {
+        let deadline = Instant::now() + budget;
+        loop {
+            let received = self.udp.lock().get_msg();
+            match received {
+                Ok((msg, len, from)) => {
+                    self.on_msg(&from, msg, len).map_err(Peer2PeerError::GGPO)?
+                }
+                Err(UdpError::Io { source }) if source.kind() == std::io::ErrorKind::WouldBlock => {
+                    break;
+                }
+                // Forged, tampered with, or sealed under another key.
+                Err(UdpError::Unauthenticated { from }) => {
+                    info!("dropping a datagram from {} that didn't open.\n", from);
+                }
+                // Truncated, corrupt, or not ours at all; the rest still count.
+                Err(UdpError::Undecodable { from, reason }) => {
+                    info!("dropping a datagram from {}: {}\n", from, reason);
+                }
+                Err(e) => return Err(e.into()),
+            }
+            if Instant::now() >= deadline {
+                break;
+            }
+        }
+        self.poll_endpoints()
+    }
+
+    /*
+     * Runs every endpoint's timers, then sends what they queued if nothing
+     * else will.  Whatever input, acks and quality reports a poll sends a
+     * peer go in one datagram.  A peer we can't send to is disconnected on
+     * its own, so one bad address doesn't stop the poll for everyone else.
+     */
+    fn poll_endpoints(&mut self) -> Result<(), Peer2PeerError> {
+        let endpoints = self.endpoints[..self.num_players].iter();
+        let endpoints = endpoints.chain(self.spectators[..self.num_spectators].iter());
+        for endpoint in endpoints.clone() {
+            let mut endpoint = endpoint.lock();
+            if endpoint.is_initialized() {
+                endpoint.begin_batch();
+                let polled = endpoint.on_loop_poll(0);
+                if let Err(e) = polled.and(endpoint.flush_batch()) {
+                    endpoint.fail(&e.to_string());
+                }
+            }
+        }
+        self.udp.lock().flush_send_queue()?;
+
+        let failures = self.udp.lock().take_send_failures();
+        for address in failures {
+            for endpoint in endpoints.clone() {
+                let mut endpoint = endpoint.lock();
+                if endpoint.is_initialized() && endpoint.peer_addr() == Some(address) {
+                    endpoint.fail("sending to it failed");
+                }
             }
         }
 
         Ok(())
     }
+
+    // Fails with `InvalidRequest` for an address we could never send to.
+    fn check_peer_addr(&self, address: SocketAddr) -> Result<(), GGPOError> {
+        self.udp.lock().check_peer_addr(address).map_err(|problem| {
+            error!("Refusing peer: {}.\n", problem);
+            GGPOError::InvalidRequest
+        })
+    }
 }
 
 impl<GGPOCallbacks> UdpCallback for Peer2PeerBackend<GGPOCallbacks>
 where
     GGPOCallbacks: GGPOSessionCallbacks + Send + Sync,
 {
-    fn on_msg(&mut self, from: &SocketAddr, msg: &UdpMsg, _len: usize) -> Result<(), String> {
+    fn on_msg(&mut self, from: &SocketAddr, msg: UdpMsg, _len: usize) -> Result<(), String> {
         for i in 0..self.num_players {
             let mut endpoint = self.endpoints[i].lock();
-            if endpoint.handles_msg(from, msg).map_err(|e| e.to_string())? {
-                return self.endpoints[i]
-                    .lock()
-                    .on_msg(msg)
-                    .map_err(|e| e.to_string());
+            if endpoint.is_initialized()
+                && endpoint
+                    .handles_msg(from, &msg)
+                    .map_err(|e| e.to_string())?
+            {
+                return endpoint.on_msg(&msg).map_err(|e| e.to_string());
             }
         }
         for i in 0..self.num_spectators {
             let mut spectator = self.spectators[i].lock();
             if spectator
-                .handles_msg(from, msg)
+                .handles_msg(from, &msg)
                 .map_err(|e| e.to_string())?
             {
-                return self.endpoints[i]
-                    .lock()
-                    .on_msg(msg)
-                    .map_err(|e| e.to_string());
+                return spectator.on_msg(&msg).map_err(|e| e.to_string());
+            }
+        }
+
+        /*
+         * Nobody's at that address.  Only now is it worth checking whether one
+         * of our peers moved there, so a peer that didn't can't be claimed by
+         * one whose magic number happens to match.
+         */
+        let endpoints = self.endpoints.iter().take(self.num_players);
+        let spectators = self.spectators.iter().take(self.num_spectators);
+        for endpoint in endpoints.chain(spectators) {
+            let mut endpoint = endpoint.lock();
+            if endpoint.is_initialized()
+                && endpoint.rebinds_to(from, &msg).map_err(|e| e.to_string())?
+            {
+                return endpoint.on_msg(&msg).map_err(|e| e.to_string());
             }
         }
 
@@ -583,106 +1705,105 @@ impl<T> Session for Peer2PeerBackend<T>
 where
     T: GGPOSessionCallbacks + Send + Sync,
 {
-    fn do_poll(&mut self, timeout: Option<std::time::Duration>) -> Result<(), GGPOError> {
-        if self.sync.lock().in_rollback() {
+    fn do_poll(&mut self, timeout: Option<Duration>) -> Result<(), GGPOError> {
+        if !self.sync.lock().in_rollback() {
             self.pump(timeout)?;
-            self.poll_udp_protocol_events()?;
-            if !*self.synchronizing.lock() {
-                self.sync.lock().check_simulation()?;
-
-                // notify all of our endpoints of their local frame number for their
-                // next connection quality report
-                let current_frame = self.sync.lock().get_frame_count();
-                for i in 0..self.num_players {
-                    self.endpoints[i]
-                        .lock()
-                        .set_local_frame_number(current_frame)
-                }
-                let total_min_confirmed;
-                if self.num_players <= 2 {
-                    total_min_confirmed = self.poll_2_players(current_frame)?;
-                } else {
-                    total_min_confirmed = self.poll_n_players(current_frame)?;
-                }
-
-                info!(
-                    "last confirmed frame in p2p backend is {:?}.\n",
-                    total_min_confirmed
-                );
-
-                assert!(total_min_confirmed != std::u32::MAX);
-                if self.num_spectators > 0 {
-                    while self.next_spectator_frame <= total_min_confirmed {
-                        info!(
-                            "pushing frame {:?} to spectators.\n",
-                            self.next_spectator_frame
-                        );
-
-                        let mut input = crate::game_input::GameInput::new();
-                        input.size = self.input_size * self.num_players;
-                        input.frame = Some(self.next_spectator_frame);
-                        self.sync.lock().get_confirmed_inputs(
-                            &mut input.bits,
-                            Some(self.next_spectator_frame),
-                        )?;
-                        for i in 0..self.num_spectators {
-                            self.spectators[i].lock().send_input(&input)?;
-                        }
-                        self.next_spectator_frame += 1;
-                    }
-                }
-
-                info!(
-                    "setting confirmed frame in sync to {:?}.\n",
-                    total_min_confirmed
-                );
-
-                self.sync
-                    .lock()
-                    .set_last_confirmed_frame(Some(total_min_confirmed))?;
-
-                // send timesync notifications if now is the proper time
-                if current_frame > self.next_recommended_sleep {
-                    let mut interval = 0;
-                    for i in 0..self.num_players {
-                        interval = std::cmp::max(
-                            interval,
-                            self.endpoints[i].lock().recommend_frame_delay(),
-                        );
-                    }
-                    if interval > 0 {
-                        let info = ggpo::Event::TimeSync(ggpo::TimeSyncEvent {
-                            frames_ahead: interval,
-                        });
-                        self.callbacks.lock().on_event(&info);
-                        self.next_recommended_sleep = current_frame + RECOMMENDATION_INTERVAL;
-                    }
-                }
-                // wat
-                // XXX: this is obviously a farce...
+            self.service()?;
+        }
+        Ok(())
+    }
 
-                // if timeout > 0 {
-                //     unblock!(std::thread::sleep(std::time::Duration::from_millis(1)));
-                // }
-            }
+    fn poll_once(&mut self, budget: Duration) -> Result<(), GGPOError> {
+        if !self.sync.lock().in_rollback() {
+            self.pump_once(budget)?;
+            self.service()?;
         }
         Ok(())
     }
     fn add_player(&mut self, player: Player, handle: &mut PlayerHandle) -> Result<(), GGPOError> {
         if let crate::player::PlayerType::Spectator(remote_addr) = player.player_type {
-            return self.add_spectator(remote_addr);
+            self.check_peer_addr(remote_addr)?;
+            return self.add_spectator(remote_addr, handle);
         }
 
-        let queue = player.player_num as u32 - 1;
-
         if player.player_num < 1 || player.player_num > self.num_players {
             return Err(GGPOError::PlayerOutOfRange);
         }
+        if let crate::player::PlayerType::Remote(remote_addr) = player.player_type {
+            self.check_peer_addr(remote_addr)?;
+        }
+        let queue = player.player_num as u32 - 1;
+        if let crate::player::PlayerType::Local = player.player_type {
+            if self.broadcast {
+                error!("A broadcast host has no local players.\n");
+                return Err(GGPOError::InvalidRequest);
+            }
+            let local_players = self
+                .players
+                .iter()
+                .flatten()
+                .filter(|info| info.queue != queue)
+                .filter(|info| matches!(info.player_type, crate::player::PlayerType::Local))
+                .count();
+            if local_players >= MAX_LOCAL_PLAYERS_PER_HOST {
+                error!(
+                    "Refusing local player {}, only {} allowed per host.\n",
+                    player.player_num, MAX_LOCAL_PLAYERS_PER_HOST
+                );
+                return Err(GGPOError::TooManyLocalPlayers {
+                    max: MAX_LOCAL_PLAYERS_PER_HOST,
+                });
+            }
+        }
+        let input_size = player.input_size.unwrap_or(self.input_size);
+        if input_size == 0 || input_size > GAMEINPUT_MAX_BYTES {
+            error!(
+                "Refusing player {} with an input size of {}, it must be between 1 and {} bytes.\n",
+                player.player_num, input_size, GAMEINPUT_MAX_BYTES
+            );
+            return Err(GGPOError::InvalidRequest);
+        }
+        {
+            // Input already queued has the old size, so it can only change before we're running.
+            let mut sync = self.sync.lock();
+            if sync.input_size(queue as usize) != input_size {
+                if !*self.synchronizing.lock() {
+                    return Err(GGPOError::InvalidRequest);
+                }
+                sync.set_input_size(queue as usize, input_size);
+                self.desync
+                    .lock()
+                    .set_input_size(queue as usize, input_size);
+            }
+        }
         *handle = Self::queue_to_player_handle(queue);
 
-        if let crate::player::PlayerType::Remote(remote_addr) = player.player_type {
-            self.add_remote_player(remote_addr, queue)?;
+        match player.player_type {
+            crate::player::PlayerType::Remote(remote_addr) => {
+                self.add_remote_player(remote_addr, queue)?
+            }
+            crate::player::PlayerType::Local => {
+                if self.frame_delay > 0 {
+                    self.sync
+                        .lock()
+                        .set_frame_delay(queue as usize, self.frame_delay);
+                }
+                // Peers added before us were told the session's size.
+                for endpoint in self.endpoints.iter().take(self.num_players) {
+                    let mut endpoint = endpoint.lock();
+                    if endpoint.is_initialized() {
+                        endpoint.set_local_input_size(input_size);
+                    }
+                }
+            }
+            _ => {}
         }
+        self.players[queue as usize] = Some(PlayerInfo {
+            handle: *handle,
+            queue,
+            player_type: player.player_type,
+            connected: true,
+        });
 
         Ok(())
     }
@@ -692,40 +1813,101 @@ where
         values: &InputBuffer,
         size: usize,
     ) -> Result<(), GGPOError> {
+        // Checked before the handle itself, against that player's queue.
+        let expected = match player.checked_sub(1) {
+            Some(queue) if (queue as usize) < self.num_players => {
+                self.sync.lock().input_size(queue as usize)
+            }
+            _ => self.input_size,
+        };
+        if size != expected {
+            return Err(GGPOError::InputSizeMismatch {
+                expected,
+                found: size,
+            });
+        }
+        let info = self.handle_to_player(player)?;
+        // Only the peer a remote player is local to can give their input.
+        if !matches!(info.player_type, crate::player::PlayerType::Local) {
+            return Err(GGPOError::InvalidRequest);
+        }
+        let queue = info.queue;
         if self.sync.lock().in_rollback() {
             return Err(GGPOError::InRollback);
         }
         if *self.synchronizing.lock() {
-            return Err(GGPOError::NotSynchronized);
+            return Err(self.not_synchronized());
+        }
+        {
+            let pause = self.pause.lock();
+            if pause.paused && Frame::new(self.sync.lock().get_frame_count()) >= pause.frame {
+                return Err(GGPOError::Paused);
+            }
         }
 
-        let queue = self.player_handle_to_queue(player)?;
-
-        let mut input = GameInput::init(None, Some(&values), size);
+        // The caller fills in the row belonging to this player. Each input queue
+        // only tracks its own player, so move that row to the front.
+        let mut bits = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+        bits[0] = values[queue as usize];
+        let mut input = GameInput::init(NULL_FRAME, Some(&bits), size);
 
         // Feed the input for the current frame into the synchronzation layer.
         if !self.sync.lock().add_local_input(queue, &mut input)? {
+            self.on_prediction_barrier(queue);
             return Err(GGPOError::PredictionThreshold);
         }
+        if let Some(frames_stalled) = self.prediction_barrier.take() {
+            info!(
+                "Prediction barrier cleared after {} frames.\n",
+                frames_stalled
+            );
+            self.callbacks
+                .lock()
+                .on_event(&ggpo::Event::PredictionBarrierCleared(
+                    ggpo::PredictionBarrierCleared { frames_stalled },
+                ));
+        }
 
-        if input.frame.is_some() {
+        if !input.frame.is_null() {
             // This was still undone in the og code.
 
             // xxx: <- comment why this is the case
             // Update the local connect status state to indicate that we've got a
             // confirmed local frame for this player.  this must come first so it
             // gets incorporated into the next packet we send.
+            let last_sent = std::mem::replace(
+                &mut self.local_connect_status[queue as usize].lock().last_frame,
+                input.frame,
+            );
             info!(
-                "setting local connect status for local queue {:?} to {:?}",
+                "setting local connect status for local queue {:?} to {}",
                 queue, input.frame
             );
-            self.local_connect_status[queue as usize].lock().last_frame = input.frame;
+
+            // A raised frame delay pads the queue with copies of the last
+            // input.  Peers need those frames too, or their stream has a gap.
+            let mut outgoing = Vec::new();
+            // Nothing's been sent before the start frame.
+            let start_frame = Frame::new(self.sync.lock().start_frame());
+            let mut frame = std::cmp::max(last_sent.next(), start_frame);
+            while frame < input.frame {
+                outgoing.push(
+                    self.sync
+                        .lock()
+                        .local_input(queue, frame)
+                        .ok_or(GGPOError::GeneralFailure)?,
+                );
+                frame = frame.next();
+            }
+            outgoing.push(input);
 
             // Send the input to all the remote players.
             for i in 0..self.num_players {
                 let mut endpoint = self.endpoints[i].lock();
                 if endpoint.is_initialized() {
-                    endpoint.send_input(&input)?;
+                    for input in outgoing.iter() {
+                        endpoint.send_input(input)?;
+                    }
                 }
             }
         }
@@ -735,26 +1917,34 @@ where
 
     fn synchronize_input(
         &self,
-        values: &mut Vec<InputBuffer>,
-        disconnect_flags: Option<&mut i32>,
+        values: &mut InputBuffer,
+        disconnect_flags: Option<&mut ggpo::DisconnectFlags>,
     ) -> Result<(), GGPOError> {
+        // A broadcast host runs its frames itself.
+        if self.broadcast {
+            return Err(GGPOError::InvalidRequest);
+        }
         // Wait until we've started to return inputs.
         if *self.synchronizing.lock() {
-            return Err(GGPOError::NotSynchronized);
+            return Err(self.not_synchronized());
         }
         let flags = self.sync.lock().synchronize_inputs(values)?;
         if let Some(d_flags) = disconnect_flags {
-            *d_flags = flags;
+            *d_flags = flags.into();
         }
         Ok(())
     }
 
     fn increment_frame(&mut self) -> Result<(), GGPOError> {
+        if self.broadcast {
+            return Err(GGPOError::InvalidRequest);
+        }
         {
             let mut sync = self.sync.lock();
             info!("End of frame ({:?})...\n", sync.get_frame_count());
             sync.increment_frame()?;
         }
+        self.report_prediction_window();
         self.do_poll(None)?;
         self.poll_sync_events()?;
         Ok(())
@@ -768,13 +1958,7 @@ where
      * blob in every endpoint periodically.
      */
     fn disconnect_player(&self, handle: PlayerHandle) -> Result<(), GGPOError> {
-        let queue = self.player_handle_to_queue(handle)?;
-        if self.local_connect_status[queue as usize]
-            .lock()
-            .disconnected
-        {
-            return Err(GGPOError::PlayerDisconnected);
-        }
+        let queue = self.handle_to_player(handle)?.queue;
 
         if !self.endpoints[queue as usize].lock().is_initialized() {
             let current_frame = self.sync.lock().get_frame_count();
@@ -787,7 +1971,7 @@ where
             );
             for i in 0..self.num_players {
                 if self.endpoints[i].lock().is_initialized() {
-                    self.disconnect_player_queue(i as u32, current_frame)?;
+                    self.disconnect_player_queue(i as u32, Frame::new(current_frame))?;
                 }
             }
         } else {
@@ -796,35 +1980,305 @@ where
                 queue,
                 self.local_connect_status[queue as usize].lock().last_frame
             );
-            {
-                let connect_status = self.local_connect_status[queue as usize]
-                    .lock()
-                    .last_frame
-                    .ok_or(Peer2PeerError::LastFrameNone)?;
-                self.disconnect_player_queue(queue, connect_status)?;
-            }
+            let last_frame = self.local_connect_status[queue as usize].lock().last_frame;
+            self.disconnect_player_queue(queue, last_frame)?;
         }
         Ok(())
     }
     fn get_network_stats(&self, handle: PlayerHandle) -> Result<NetworkStats, GGPOError> {
-        let queue = self.player_handle_to_queue(handle)?;
-        Ok(self.endpoints[queue as usize].lock().get_network_stats())
+        let queue = self.handle_to_player(handle)?.queue;
+        let mut stats = self.endpoints[queue as usize].lock().get_network_stats();
+        stats.network.recv_queue_len = self.sync.lock().input_queue_len(queue as usize);
+        Ok(stats)
+    }
+    fn rollback_stats(&self) -> Result<RollbackStats, GGPOError> {
+        Ok(self.sync.lock().rollback_stats())
+    }
+    fn memory_usage(&self) -> Result<MemoryUsage, GGPOError> {
+        let mut usage = self.sync.lock().memory_usage();
+        let endpoints = self.endpoints[..self.num_players].iter();
+        for endpoint in endpoints.chain(self.spectators[..self.num_spectators].iter()) {
+            let endpoint = endpoint.lock();
+            usage.unacked_input_frames += endpoint.unacked_input_len();
+            if let Some(frame) = endpoint.oldest_unacked_frame() {
+                usage.holds(frame);
+            }
+        }
+        Ok(usage)
+    }
+    fn input_sizes(&self) -> Result<Vec<usize>, GGPOError> {
+        Ok(self.sync.lock().input_sizes())
+    }
+    fn debug_dump(&self) -> Result<SessionDebugInfo, GGPOError> {
+        let sync = self.sync.lock();
+        let frame = sync.get_frame_count();
+        let players = self
+            .players
+            .iter()
+            .flatten()
+            .map(|info| {
+                let queue = info.queue as usize;
+                let last_input_frame = sync.last_input_frame(queue);
+                let next = last_input_frame.next().number().unwrap_or(0);
+                let endpoint = self.endpoints[queue].lock();
+                let remote = endpoint.is_initialized();
+                PlayerDebugInfo {
+                    handle: info.handle,
+                    player_type: info.player_type,
+                    last_input_frame,
+                    predicted_frames: frame.saturating_sub(next),
+                    first_incorrect_frame: sync.first_incorrect_frame(queue),
+                    queued_inputs: sync.input_queue_len(queue),
+                    input_size: sync.input_size(queue),
+                    disconnected: self.local_connect_status[queue].lock().disconnected,
+                    round_trip_time: endpoint.smoothed_round_trip_time().filter(|_| remote),
+                    magic_numbers: Some(endpoint.magic_numbers()).filter(|_| remote),
+                }
+            })
+            .collect();
+        Ok(SessionDebugInfo {
+            frame,
+            last_confirmed_frame: sync.last_confirmed_frame(),
+            num_players: self.num_players,
+            num_spectators: self.num_spectators,
+            synchronizing: *self.synchronizing.lock(),
+            in_rollback: sync.in_rollback(),
+            prediction_frames: sync.prediction_frames(),
+            rollback_stats: sync.rollback_stats(),
+            players,
+        })
+    }
+    fn is_local(&self, player: PlayerHandle) -> Result<bool, GGPOError> {
+        let info = self.handle_to_player(player)?;
+        Ok(matches!(info.player_type, crate::player::PlayerType::Local))
+    }
+    fn players(&self) -> Result<Vec<PlayerInfo>, GGPOError> {
+        let players = self.players.iter().flatten().map(|info| PlayerInfo {
+            connected: !self.local_connect_status[info.queue as usize]
+                .lock()
+                .disconnected,
+            ..*info
+        });
+        let spectators = self.spectators[..self.num_spectators]
+            .iter()
+            .enumerate()
+            .filter_map(|(queue, spectator)| {
+                let spectator = spectator.lock();
+                Some(PlayerInfo {
+                    handle: Self::queue_to_spectator_handle(queue as u32),
+                    queue: queue as u32,
+                    player_type: crate::player::PlayerType::Spectator(spectator.peer_addr()?),
+                    connected: spectator.is_initialized() && !spectator.is_disconnected(),
+                })
+            });
+        Ok(players.chain(spectators).collect())
+    }
+    fn connect_status(&self) -> Result<Vec<ggpo::PeerConnectStatus>, GGPOError> {
+        Ok(self.local_connect_status[..self.num_players]
+            .iter()
+            .map(|status| {
+                let status = status.lock();
+                ggpo::PeerConnectStatus {
+                    connected: !status.disconnected,
+                    last_frame: status.last_frame,
+                }
+            })
+            .collect())
     }
     fn logv(_fmt: String) -> Result<(), GGPOError> {
         Ok(())
     }
+    fn set_version(&mut self, version: u32) -> Result<(), GGPOError> {
+        self.version = version;
+        for endpoint in self
+            .endpoints
+            .iter()
+            .take(self.num_players)
+            .chain(self.spectators.iter().take(self.num_spectators))
+        {
+            let mut endpoint = endpoint.lock();
+            if endpoint.is_initialized() {
+                endpoint.set_version(version);
+            }
+        }
+        Ok(())
+    }
+
+    fn negotiated_version(&self) -> Result<u32, GGPOError> {
+        for endpoint in self.endpoints.iter().take(self.num_players) {
+            let endpoint = endpoint.lock();
+            match endpoint.remote_version() {
+                Some(remote) if remote != self.version => {
+                    return Err(GGPOError::IncompatibleVersion {
+                        local: self.version,
+                        remote,
+                    })
+                }
+                _ => {}
+            }
+        }
+        if *self.synchronizing.lock() {
+            return Err(GGPOError::NotSynchronized);
+        }
+        Ok(self.version)
+    }
+
+    fn shared_seed(&self) -> Result<u64, GGPOError> {
+        self.shared_seed.lock().ok_or(GGPOError::NotSynchronized)
+    }
+    fn connection_quality(&self, handle: PlayerHandle) -> Result<ConnectionQuality, GGPOError> {
+        let queue = self.handle_to_player(handle)?.queue;
+        let endpoint = self.endpoints[queue as usize].lock();
+        if !endpoint.is_initialized() {
+            // Local players have no connection to speak of.
+            return Err(GGPOError::InvalidRequest);
+        }
+        Ok(endpoint.connection_quality())
+    }
+
+    fn frame_advantage(&self, handle: PlayerHandle) -> Result<(i32, i32), GGPOError> {
+        let queue = self.handle_to_player(handle)?.queue;
+        let endpoint = self.endpoints[queue as usize].lock();
+        if !endpoint.is_initialized() {
+            // Local players have no advantage over us.
+            return Err(GGPOError::InvalidRequest);
+        }
+        Ok(endpoint.frame_advantage())
+    }
+
+    fn last_confirmed_frame(&self, handle: PlayerHandle) -> Result<Frame, GGPOError> {
+        let queue = self.handle_to_player(handle)?.queue;
+        Ok(self.sync.lock().last_input_frame(queue as usize))
+    }
+
+    fn export_state(&self, frame: Frame) -> Result<(Bytes, u32), GGPOError> {
+        let sync = self.sync.lock();
+        // Until it's confirmed, the state may yet be rolled back over.
+        if frame.is_null() || frame > sync.last_confirmed_frame() {
+            return Err(GGPOError::StateNotSaved { frame });
+        }
+        let state = sync
+            .saved_state(frame)
+            .ok_or(GGPOError::StateNotSaved { frame })?;
+        let checksum = state
+            .checksum
+            .unwrap_or_else(|| checksum::fletcher32(&state.data));
+        Ok((state.data, checksum))
+    }
+
+    fn import_state(&mut self, frame: Frame, state: Bytes) -> Result<(), GGPOError> {
+        let start_frame = frame.number().ok_or(GGPOError::InvalidRequest)?;
+        let mut sync = self.sync.lock();
+        let started = sync.get_frame_count() != sync.start_frame()
+            || (0..self.num_players).any(|queue| !sync.last_input_frame(queue).is_null());
+        if started {
+            return Err(GGPOError::InvalidRequest);
+        }
+        sync.restart_at(start_frame, &state)?;
+        drop(sync);
+
+        self.next_spectator_frame = start_frame;
+        self.next_checksum_frame = frame;
+        self.next_confirmed_frame = frame;
+        self.next_input_checksum_frame = frame;
+        Ok(())
+    }
+
+    fn sync_error(&self, handle: PlayerHandle) -> Result<(), GGPOError> {
+        // Giving up on the handshake is one way to be disconnected.
+        let (queue, disconnected) = match self.handle_to_player(handle) {
+            Ok(player) => (player.queue, None),
+            Err(GGPOError::PlayerDisconnected) => (handle - 1, Some(GGPOError::PlayerDisconnected)),
+            Err(e) => return Err(e),
+        };
+        let endpoint = self.endpoints[queue as usize].lock();
+        if !endpoint.is_initialized() {
+            // Local players have no handshake to fail.
+            return Err(GGPOError::InvalidRequest);
+        }
+        match (endpoint.sync_failure(), disconnected) {
+            (Some(failure), _) => Err(failure.into()),
+            (None, Some(e)) => Err(e),
+            (None, None) => Ok(()),
+        }
+    }
+
+    fn pause(&mut self) -> Result<(), GGPOError> {
+        if *self.synchronizing.lock() {
+            return Err(GGPOError::NotSynchronized);
+        }
+        if self.pause.lock().paused {
+            return Err(GGPOError::InvalidRequest);
+        }
+        self.begin_pause()?;
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<(), GGPOError> {
+        let state = {
+            let mut state = self.pause.lock();
+            if !state.paused {
+                return Err(GGPOError::InvalidRequest);
+            }
+            state.paused = false;
+            *state
+        };
+        info!("Resuming from frame {}.\n", state.frame);
+        self.broadcast_pause(state)?;
+        let info = ggpo::Event::SessionResumed(ggpo::SessionResumed { frame: state.frame });
+        self.callbacks.lock().on_event(&info);
+        Ok(())
+    }
+
+    fn shutdown(&mut self) -> Result<(), GGPOError> {
+        info!("Shutting down session.\n");
+        for endpoint in self
+            .endpoints
+            .iter()
+            .take(self.num_players)
+            .chain(self.spectators.iter().take(self.num_spectators))
+        {
+            let mut endpoint = endpoint.lock();
+            if endpoint.is_initialized() {
+                endpoint.send_goodbye()?;
+            }
+        }
+        self.udp.lock().close()?;
+        self.sync.lock().free_saved_states();
+        Ok(())
+    }
+
+    fn set_prediction_strategy(
+        &mut self,
+        strategy: Arc<dyn PredictionStrategy>,
+    ) -> Result<(), GGPOError> {
+        self.sync.lock().set_prediction_strategy(strategy);
+        Ok(())
+    }
+
     fn set_frame_delay(&mut self, player: PlayerHandle, delay: i32) -> Result<(), GGPOError> {
-        let queue = self.player_handle_to_queue(player)?;
-        self.sync
-            .lock()
-            .set_frame_delay(queue as usize, delay as usize);
+        let queue = self.handle_to_player(player)?.queue;
+        let delay = usize::try_from(delay).map_err(|_| GGPOError::InvalidRequest)?;
+        // A delay picked by hand sticks.
+        self.auto_frame_delay = None;
+        self.sync.lock().set_frame_delay(queue as usize, delay);
+        Ok(())
+    }
+
+    fn set_auto_frame_delay(&mut self, enabled: bool) -> Result<(), GGPOError> {
+        self.auto_frame_delay = if enabled {
+            Some(AutoFrameDelay::new(self.frame_delay))
+        } else {
+            None
+        };
         Ok(())
     }
 
     fn set_disconnect_timeout(&mut self, timeout: u128) -> Result<(), GGPOError> {
         self.disconnect_timeout = timeout;
-        for i in 0..self.num_players {
-            let mut endpoint = self.endpoints[i].lock();
+        let endpoints = self.endpoints[..self.num_players].iter();
+        for endpoint in endpoints.chain(self.spectators[..self.num_spectators].iter()) {
+            let mut endpoint = endpoint.lock();
             if endpoint.is_initialized() {
                 endpoint.set_disconnect_timeout(self.disconnect_timeout);
             }
@@ -833,10 +2287,61 @@ where
     }
     fn set_disconnect_notify_start(&mut self, timeout: u128) -> Result<(), GGPOError> {
         self.disconnect_notify_start = timeout;
+        let endpoints = self.endpoints[..self.num_players].iter();
+        for endpoint in endpoints.chain(self.spectators[..self.num_spectators].iter()) {
+            let mut endpoint = endpoint.lock();
+            if endpoint.is_initialized() {
+                endpoint.set_disconnect_notify_start(self.disconnect_notify_start);
+            }
+        }
+        Ok(())
+    }
+
+    /*
+     * Only two player sessions can reconnect: with more, the other peers are
+     * told about the disconnect and drop the player for good.
+     */
+    fn set_reconnect_window(&mut self, window: u128) -> Result<(), GGPOError> {
+        if self.num_players > 2 {
+            return Err(GGPOError::Unsupported);
+        }
+        self.reconnect_window = window;
         for i in 0..self.num_players {
             let mut endpoint = self.endpoints[i].lock();
             if endpoint.is_initialized() {
-                endpoint.set_disconnect_notify_start(self.disconnect_notify_start);
+                endpoint.set_reconnect_window(self.reconnect_window);
+            }
+        }
+        Ok(())
+    }
+
+    fn set_retransmit_interval(&mut self, interval: u128) -> Result<(), GGPOError> {
+        self.retransmit_interval = interval;
+        for endpoint in self
+            .endpoints
+            .iter()
+            .take(self.num_players)
+            .chain(self.spectators.iter().take(self.num_spectators))
+        {
+            let mut endpoint = endpoint.lock();
+            if endpoint.is_initialized() {
+                endpoint.set_retransmit_interval(self.retransmit_interval);
+            }
+        }
+        Ok(())
+    }
+
+    fn set_send_rate_limit(&mut self, bytes_per_second: usize) -> Result<(), GGPOError> {
+        self.send_rate_limit = bytes_per_second;
+        for endpoint in self
+            .endpoints
+            .iter()
+            .take(self.num_players)
+            .chain(self.spectators.iter().take(self.num_spectators))
+        {
+            let mut endpoint = endpoint.lock();
+            if endpoint.is_initialized() {
+                endpoint.set_send_rate_limit(self.send_rate_limit);
             }
         }
         Ok(())
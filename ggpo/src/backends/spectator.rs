@@ -1,19 +1,664 @@
+/*
+ * A session that watches someone else's game.  The spectator never sends
+ * input: it connects to the host, which forwards every player's input for
+ * each frame once all the players have confirmed it, and plays those frames
+ * back in order.  Since nothing is ever predicted there's nothing to roll
+ * back, so the spectator only needs a ring of the frames it hasn't played yet.
+ *
+ * A spectator that joins late or falls behind can fast-forward.  With a
+ * catch-up threshold set, once more than that many received frames are
+ * waiting to be played `do_poll` steps through them itself, a batch per call,
+ * handing each frame's inputs to `fast_forward_frame` without stopping to
+ * render.  When only the newest frame is left it fires `Event::CaughtUp` and
+ * playback goes back to one frame per `synchronize_input`.  Frames keep
+ * arriving while it catches up and land in the same ring, so a spectator that
+ * falls a whole ring behind has lost frames it can't get back.
+ *
+ * A spectator that joins a match already under way is only sent input from
+ * the frame it synchronized on, so it has nothing to play those frames from.
+ * When the first frame it hears about isn't frame 0 it asks the host for a
+ * saved state, every `STATE_REQUEST_INTERVAL` until one arrives, loads it
+ * with `load_game_state`, and plays on from the state's frame.  Until then
+ * `synchronize_input` reports `NotSynchronized`.
+ *
+ * Frames that arrive unevenly play back unevenly.  A jitter buffer (see
+ * `set_jitter_buffer`) holds back playback until that many frames beyond
+ * the one being played have arrived, so a late frame is covered by the ones
+ * already waiting.  If the buffer runs dry anyway, playback pauses, with
+ * `synchronize_input` reporting `PredictionThreshold`, until it has filled
+ * back up, rather than playing each frame the moment it trickles in.
+ *
+ * The spectator can't predict its way past a frame it never got, so it
+ * watches for holes in the stream.  One still open after
+ * `DEFAULT_STREAM_GAP_WINDOW` ms is reported with `Event::SpectatorStreamGap`
+ * and the host is asked to resend from the first missing frame, again each
+ * window, until it does or `MAX_STREAM_GAP_REQUESTS` requests have gone
+ * unanswered and the host is dropped.
+ */
 use crate::{
-    game_input::{FrameNum, GameInput, InputBuffer},
-    ggpo::{
-        self, GGPOError, GGPOSessionCallbacks, NetworkStats, Session, GGPO_MAX_PLAYERS,
-        GGPO_MAX_SPECTATORS,
+    game_input::{
+        Frame, GameInput, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS, NULL_FRAME,
     },
+    ggpo::{self, GGPOError, GGPOSessionCallbacks, Session, GGPO_MAX_PLAYERS},
     network::{
+        clock::{Clock, Sleep, SystemClock, TimerSource},
         udp::{Udp, UdpCallback, UdpError},
-        udp_msg::{ConnectStatus, UdpMsg, UDP_MSG_MAX_PLAYERS},
+        udp_msg::{ConnectStatus, StateResponse, UdpMsg, UDP_MSG_MAX_PLAYERS},
         udp_proto::{self, UdpProtoError, UdpProtocol},
     },
     player::{Player, PlayerHandle},
-    sync::{self, GGPOSync, SyncError},
 };
 use log::{error, info};
-use mio::{Events, Poll, Token};
+use mio::{Events, Poll};
 use parking_lot::Mutex;
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 use thiserror::Error;
+
+// How many received frames the spectator holds on to before playing them.
+pub const SPECTATOR_FRAME_BUFFER_SIZE: usize = 64;
+// The most frames one `do_poll` fast-forwards through while catching up.
+pub const CATCH_UP_FRAMES_PER_POLL: usize = 16;
+
+// The host is the only peer a spectator has.
+const HOST_HANDLE: PlayerHandle = 0;
+
+#[derive(Debug, Error)]
+pub enum SpectatorError {
+    #[error("UDP protocol error.")]
+    UdpProtocol {
+        #[from]
+        source: UdpProtoError,
+    },
+    #[error("UDP network error.")]
+    Udp {
+        #[from]
+        source: UdpError,
+    },
+    #[error("GGPO Session error {0}")]
+    GGPO(String),
+    #[error("IO error")]
+    Mio {
+        #[from]
+        source: std::io::Error,
+    },
+}
+
+pub struct SpectatorBackend<T>
+where
+    T: GGPOSessionCallbacks + Send + Sync,
+{
+    callbacks: Arc<Mutex<T>>,
+    udp: Arc<Mutex<Udp<Self>>>,
+    host: Arc<Mutex<UdpProtocol<Self>>>,
+    synchronizing: bool,
+    num_players: usize,
+
+    // Received frames, indexed by frame number modulo the ring size.
+    inputs: Vec<GameInput>,
+    next_input_to_send: Frame,
+    last_received: Frame,
+    catch_up_threshold: usize,
+    catching_up: bool,
+    jitter_buffer_depth: usize,
+    // Waiting for the jitter buffer to fill, at the start or after it ran dry.
+    buffering: bool,
+    // Joined mid-match and hasn't got a state to start from yet.
+    awaiting_snapshot: bool,
+    last_state_request: u128,
+    // Paces the state requests; see `set_clock`.
+    clock: Arc<dyn Clock>,
+    // The same clock, when it can be waited on; see `set_timers`.
+    timers: Option<Arc<dyn TimerSource>>,
+
+    local_connect_status: [Arc<Mutex<ConnectStatus>>; UDP_MSG_MAX_PLAYERS],
+    poll: Arc<Mutex<Poll>>,
+    events: Arc<Mutex<Events>>,
+}
+
+impl<T: GGPOSessionCallbacks + Send + Sync> SpectatorBackend<T> {
+    pub fn new(
+        callbacks: Arc<Mutex<T>>,
+        local_port: u16,
+        num_players: usize,
+        input_size: usize,
+        host_addr: SocketAddr,
+    ) -> Result<Arc<Mutex<Self>>, SpectatorError> {
+        if num_players > GGPO_MAX_PLAYERS {
+            return Err(SpectatorError::GGPO(format!(
+                "{} players requested, but at most {} are supported.",
+                num_players, GGPO_MAX_PLAYERS
+            )));
+        }
+        if input_size == 0 || input_size > GAMEINPUT_MAX_BYTES {
+            return Err(SpectatorError::GGPO(format!(
+                "input size {} requested, but it must be between 1 and {} bytes.",
+                input_size, GAMEINPUT_MAX_BYTES
+            )));
+        }
+
+        let mut connect_status: [Arc<Mutex<ConnectStatus>>; UDP_MSG_MAX_PLAYERS] =
+            Default::default();
+        for status in connect_status.iter_mut() {
+            *status = Arc::new(Mutex::new(ConnectStatus {
+                disconnected: false,
+                last_frame: NULL_FRAME,
+            }));
+        }
+
+        let spectator = Arc::new(Mutex::new(Self {
+            callbacks,
+            udp: Arc::new(Mutex::new(Udp::new())),
+            host: Arc::new(Mutex::new(UdpProtocol::new())),
+            synchronizing: true,
+            num_players,
+            inputs: vec![GameInput::new(); SPECTATOR_FRAME_BUFFER_SIZE],
+            next_input_to_send: Frame::new(0),
+            last_received: NULL_FRAME,
+            catch_up_threshold: 0,
+            catching_up: false,
+            jitter_buffer_depth: 0,
+            buffering: false,
+            awaiting_snapshot: false,
+            last_state_request: 0,
+            clock: Arc::new(SystemClock),
+            timers: None,
+            local_connect_status: connect_status,
+            poll: Arc::new(Mutex::new(Poll::new()?)),
+            events: Arc::new(Mutex::new(Events::with_capacity(1024))),
+        }));
+        spectator
+            .clone()
+            .lock()
+            .init(spectator.clone(), local_port, host_addr)?;
+
+        Ok(spectator)
+    }
+
+    pub fn init(
+        &mut self,
+        spectator: Arc<Mutex<SpectatorBackend<T>>>,
+        local_port: u16,
+        host_addr: SocketAddr,
+    ) -> Result<(), SpectatorError> {
+        {
+            let mut udp = self.udp.lock();
+            udp.init(local_port, self.poll.clone(), spectator)?;
+            udp.start_send_task()?;
+        }
+
+        let mut host = self.host.lock();
+        host.init(self.udp.clone(), 0, host_addr, &self.local_connect_status);
+        // The host sends every player's input in one message.
+        host.set_input_size(GAMEINPUT_MAX_BYTES * self.num_players);
+        host.set_stream_gap_window(udp_proto::DEFAULT_STREAM_GAP_WINDOW);
+        Ok(host.synchronize()?)
+    }
+
+    /*
+     * Fast-forward once more than `frames` received frames are waiting to be
+     * played.  0, the default, never fast-forwards.  It has to leave room in
+     * the ring for the frames that arrive while catching up, so it must be
+     * less than `SPECTATOR_FRAME_BUFFER_SIZE`.
+     */
+    pub fn set_catch_up_threshold(&mut self, frames: usize) -> Result<(), GGPOError> {
+        if frames >= SPECTATOR_FRAME_BUFFER_SIZE
+            || (frames > 0 && frames <= self.jitter_buffer_depth)
+        {
+            return Err(GGPOError::InvalidRequest);
+        }
+        self.catch_up_threshold = frames;
+        Ok(())
+    }
+
+    /*
+     * Runs the host endpoint's timers, and the pacing of state requests, off
+     * `clock` in place of the wall clock.
+     */
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.host.lock().set_clock(clock.clone());
+        self.udp.lock().set_clock(clock.clone());
+        self.clock = clock;
+        self.timers = None;
+    }
+
+    // Keeps time by `timers`; see `Peer2PeerBackend::set_timers`.
+    pub fn set_timers(&mut self, timers: Arc<dyn TimerSource>) {
+        self.set_clock(timers.clone());
+        self.timers = Some(timers);
+    }
+
+    /*
+     * When the soonest timer is due: the host endpoint's, the transport's, or
+     * the next state request while we're waiting on a snapshot.
+     */
+    pub fn next_timer(&self) -> Option<SystemTime> {
+        let next_request = if self.awaiting_snapshot {
+            let at = self.last_state_request + udp_proto::STATE_REQUEST_INTERVAL;
+            Some(UNIX_EPOCH + Duration::from_millis(at as u64))
+        } else {
+            None
+        };
+        self.host
+            .lock()
+            .next_timer()
+            .into_iter()
+            .chain(self.udp.lock().next_timer())
+            .chain(next_request)
+            .min()
+    }
+
+    // See `Peer2PeerBackend::sleep_until_next_timer`.
+    pub fn sleep_until_next_timer(&self) -> Option<Sleep> {
+        let timers = self.timers.as_ref()?;
+        Some(timers.sleep_until(self.next_timer()?))
+    }
+
+    pub fn is_catching_up(&self) -> bool {
+        self.catching_up
+    }
+
+    /*
+     * Holds `frames` received frames in reserve behind the one being played.
+     * Each adds a frame of latency.  0, the default, plays frames as soon as
+     * they arrive.  A catch-up threshold has to be bigger, or catching up
+     * would eat into the reserve.
+     */
+    pub fn set_jitter_buffer(&mut self, frames: usize) -> Result<(), GGPOError> {
+        if frames >= SPECTATOR_FRAME_BUFFER_SIZE
+            || (self.catch_up_threshold > 0 && self.catch_up_threshold <= frames)
+        {
+            return Err(GGPOError::InvalidRequest);
+        }
+        self.jitter_buffer_depth = frames;
+        self.buffering = frames > 0;
+        Ok(())
+    }
+
+    pub fn jitter_buffer_depth(&self) -> usize {
+        self.jitter_buffer_depth
+    }
+
+    // How many received frames are waiting behind the one being played.
+    pub fn jitter_buffer_occupancy(&self) -> usize {
+        self.frames_behind().saturating_sub(1)
+    }
+
+    pub fn is_buffering(&self) -> bool {
+        self.buffering
+    }
+
+    // Starts playback once the buffer is full, and stops it when it runs dry.
+    fn update_buffering(&mut self) {
+        if self.buffering {
+            if self.frames_behind() > self.jitter_buffer_depth {
+                info!(
+                    "Jitter buffer full at frame {}.  Playing.\n",
+                    self.next_input_to_send
+                );
+                self.buffering = false;
+            }
+        } else if self.jitter_buffer_depth > 0 && self.frames_behind() == 0 {
+            info!(
+                "Jitter buffer ran dry at frame {}.  Waiting for {} frames.\n",
+                self.next_input_to_send, self.jitter_buffer_depth
+            );
+            self.buffering = true;
+        }
+    }
+
+    // How many received frames haven't been played yet.
+    fn frames_behind(&self) -> usize {
+        (self.last_received.as_i32() + 1 - self.next_input_to_send.as_i32()).max(0) as usize
+    }
+
+    fn slot(frame: Frame) -> usize {
+        frame.as_i32() as usize % SPECTATOR_FRAME_BUFFER_SIZE
+    }
+
+    // The inputs for `frame`, once they've arrived and while they're still in the ring.
+    fn inputs_for(&self, frame: Frame) -> Result<&GameInput, GGPOError> {
+        let input = &self.inputs[Self::slot(frame)];
+        if input.frame < frame {
+            // Not here yet.
+            return Err(GGPOError::PredictionThreshold);
+        }
+        if input.frame > frame {
+            error!(
+                "Spectator fell too far behind: frame {} was overwritten by {}.\n",
+                frame, input.frame
+            );
+            return Err(GGPOError::GeneralFailure);
+        }
+        Ok(input)
+    }
+
+    // Rows past the players are blanked, as the P2P backend leaves them.
+    fn copy_inputs(&self, input: &GameInput, values: &mut InputBuffer) {
+        *values = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+        values[..self.num_players].copy_from_slice(&input.bits[..self.num_players]);
+    }
+
+    /*
+     * Plays through the backlog, up to `CATCH_UP_FRAMES_PER_POLL` frames at a
+     * time, leaving the newest frame for `synchronize_input`, along with a
+     * full jitter buffer.
+     */
+    fn catch_up(&mut self) -> Result<(), GGPOError> {
+        if !self.catching_up {
+            if self.catch_up_threshold == 0 || self.frames_behind() <= self.catch_up_threshold {
+                return Ok(());
+            }
+            info!(
+                "Spectator is {} frames behind the host.  Catching up.\n",
+                self.frames_behind()
+            );
+            self.catching_up = true;
+        }
+
+        let keep = 1 + self.jitter_buffer_depth;
+        let mut fast_forwarded = 0;
+        while self.frames_behind() > keep && fast_forwarded < CATCH_UP_FRAMES_PER_POLL {
+            let mut values: InputBuffer = Default::default();
+            self.copy_inputs(self.inputs_for(self.next_input_to_send)?, &mut values);
+            self.callbacks.lock().fast_forward_frame(&values, 0);
+            self.next_input_to_send = self.next_input_to_send.next();
+            fast_forwarded += 1;
+        }
+
+        if self.frames_behind() <= keep {
+            info!(
+                "Spectator caught up at frame {}.\n",
+                self.next_input_to_send
+            );
+            self.catching_up = false;
+            let info = ggpo::Event::CaughtUp(ggpo::CaughtUp {
+                frame: self.next_input_to_send,
+            });
+            self.callbacks.lock().on_event(&info);
+        }
+        Ok(())
+    }
+
+    fn request_snapshot(&mut self) -> Result<(), SpectatorError> {
+        let now = self
+            .clock
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| SpectatorError::GGPO(e.to_string()))?
+            .as_millis();
+        if now >= self.last_state_request + udp_proto::STATE_REQUEST_INTERVAL {
+            info!("Asking the host for a state to start from.\n");
+            self.host.lock().send_state_request()?;
+            self.last_state_request = now;
+        }
+        Ok(())
+    }
+
+    fn on_snapshot(&mut self, snapshot: &StateResponse) {
+        if !self.awaiting_snapshot {
+            return;
+        }
+        if !self
+            .callbacks
+            .lock()
+            .load_game_state(&snapshot.data, snapshot.data.len())
+        {
+            error!(
+                "Failed to load the host's state for frame {}.\n",
+                snapshot.frame
+            );
+            return;
+        }
+        info!("Loaded the host's state for frame {}.\n", snapshot.frame);
+        self.next_input_to_send = snapshot.frame;
+        self.awaiting_snapshot = false;
+    }
+
+    fn poll_udp_protocol_events(&mut self) -> Result<(), SpectatorError> {
+        let mut events = Vec::new();
+        {
+            let mut event = udp_proto::Event::Unknown;
+            let mut host = self.host.lock();
+            while host.get_event(&mut event) {
+                events.push(std::mem::replace(&mut event, udp_proto::Event::Unknown));
+            }
+        }
+        for event in events.iter() {
+            self.on_udp_protocol_event(event)?;
+        }
+        Ok(())
+    }
+
+    fn on_udp_protocol_event(&mut self, event: &udp_proto::Event) -> Result<(), SpectatorError> {
+        let info: ggpo::Event;
+        match event {
+            udp_proto::Event::Connected => {
+                info = ggpo::Event::ConnectedToPeer(ggpo::ConnectedToPeer {
+                    player: HOST_HANDLE,
+                });
+            }
+            udp_proto::Event::Synchronizing(sync) => {
+                info = ggpo::Event::SynchronizingWithPeer(ggpo::SynchronizingWithPeer {
+                    count: sync.count,
+                    total: sync.total,
+                    player: HOST_HANDLE,
+                });
+            }
+            udp_proto::Event::Synchronzied => {
+                if self.synchronizing {
+                    let info = ggpo::Event::SynchronizedWithPeer(ggpo::SynchronizedWithPeer {
+                        player: HOST_HANDLE,
+                    });
+                    self.callbacks.lock().on_event(&info);
+                    self.synchronizing = false;
+                }
+                info = ggpo::Event::Running;
+            }
+            udp_proto::Event::NetworkInterrupted(net_interupt) => {
+                info = ggpo::Event::ConnectionInterrupted(ggpo::ConnectionInterrupted {
+                    player: HOST_HANDLE,
+                    disconnect_timeout: net_interupt.disconnect_timeout,
+                });
+            }
+            udp_proto::Event::NetworkResumed | udp_proto::Event::Resumed => {
+                info = ggpo::Event::ConnectionResumed(ggpo::ConnectionResumed {
+                    player: HOST_HANDLE,
+                });
+            }
+            udp_proto::Event::InputSizeMismatch(mismatch) => {
+                info = ggpo::Event::InputSizeMismatch(ggpo::InputSizeMismatch {
+                    player: HOST_HANDLE,
+                    local_size: mismatch.local,
+                    remote_size: mismatch.remote,
+                });
+            }
+            udp_proto::Event::IncompatibleVersion(mismatch) => {
+                info = ggpo::Event::IncompatibleVersion(ggpo::IncompatibleVersion {
+                    player: HOST_HANDLE,
+                    local_version: mismatch.local,
+                    remote_version: mismatch.remote,
+                });
+            }
+            udp_proto::Event::QualityChanged(quality) => {
+                info = ggpo::Event::ConnectionQualityChanged(ggpo::ConnectionQualityChanged {
+                    player: HOST_HANDLE,
+                    quality: *quality,
+                });
+            }
+            udp_proto::Event::Disconnected => {
+                info = ggpo::Event::DisconnectedFromPeer(ggpo::DisconnectedFromPeer {
+                    player: HOST_HANDLE,
+                });
+            }
+            udp_proto::Event::Input(input) => {
+                if self.last_received.is_null() && input.frame != Frame::new(0) {
+                    info!(
+                        "Joined at frame {}.  Waiting for a state snapshot.\n",
+                        input.frame
+                    );
+                    self.awaiting_snapshot = true;
+                }
+                self.inputs[Self::slot(input.frame)] = *input;
+                self.last_received = std::cmp::max(self.last_received, input.frame);
+                if let Some(frame) = input.frame.number() {
+                    self.host.lock().set_local_frame_number(frame);
+                }
+                return Ok(());
+            }
+            udp_proto::Event::State(snapshot) => {
+                self.on_snapshot(snapshot);
+                return Ok(());
+            }
+            udp_proto::Event::StreamGap(gap) => {
+                info = ggpo::Event::SpectatorStreamGap(ggpo::SpectatorStreamGap {
+                    missing_from: gap.missing_from,
+                    missing_to: gap.missing_to,
+                });
+            }
+            _ => return Ok(()),
+        }
+        self.callbacks.lock().on_event(&info);
+        Ok(())
+    }
+
+    fn pump(&mut self, timeout: Option<Duration>) -> Result<(), SpectatorError> {
+        let deadline = Instant::now() + timeout.unwrap_or_default();
+        loop {
+            {
+                let mut events = self.events.lock();
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                self.poll.lock().poll(&mut events, Some(remaining))?;
+            }
+
+            let msgs = self.udp.lock().recv_pending()?;
+            for (msg, len, from) in msgs {
+                self.on_msg(&from, msg, len).map_err(SpectatorError::GGPO)?;
+            }
+
+            if Instant::now() >= deadline {
+                break;
+            }
+        }
+
+        self.host.lock().on_loop_poll(0)?;
+        Ok(())
+    }
+}
+
+impl<T> UdpCallback for SpectatorBackend<T>
+where
+    T: GGPOSessionCallbacks + Send + Sync,
+{
+    fn on_msg(&mut self, from: &SocketAddr, msg: UdpMsg, _len: usize) -> Result<(), String> {
+        let mut host = self.host.lock();
+        if host.handles_msg(from, &msg).map_err(|e| e.to_string())?
+            || host.rebinds_to(from, &msg).map_err(|e| e.to_string())?
+        {
+            return host.on_msg(&msg).map_err(|e| e.to_string());
+        }
+        Ok(())
+    }
+}
+
+impl<T> Session for SpectatorBackend<T>
+where
+    T: GGPOSessionCallbacks + Send + Sync,
+{
+    fn do_poll(&mut self, timeout: Option<Duration>) -> Result<(), GGPOError> {
+        self.pump(timeout)?;
+        self.poll_udp_protocol_events()?;
+        if self.awaiting_snapshot {
+            self.request_snapshot()?;
+        } else if !self.synchronizing {
+            self.catch_up()?;
+            self.update_buffering();
+        }
+        Ok(())
+    }
+
+    fn add_player(&mut self, _player: Player, _handle: &mut PlayerHandle) -> Result<(), GGPOError> {
+        Err(GGPOError::InvalidRequest)
+    }
+
+    // Spectators have no input of their own, so there's nothing to add.
+    fn add_local_input(
+        &mut self,
+        _player: PlayerHandle,
+        _values: &InputBuffer,
+        _size: usize,
+    ) -> Result<(), GGPOError> {
+        Ok(())
+    }
+
+    fn synchronize_input(
+        &self,
+        values: &mut InputBuffer,
+        disconnect_flags: Option<&mut ggpo::DisconnectFlags>,
+    ) -> Result<(), GGPOError> {
+        if self.synchronizing {
+            return Err(self
+                .host
+                .lock()
+                .sync_failure()
+                .map_or(GGPOError::NotSynchronized, GGPOError::from));
+        }
+        if self.awaiting_snapshot {
+            return Err(GGPOError::NotSynchronized);
+        }
+        if self.buffering {
+            return Err(GGPOError::PredictionThreshold);
+        }
+        self.copy_inputs(self.inputs_for(self.next_input_to_send)?, values);
+        // xxx: should get them from the host!
+        if let Some(d_flags) = disconnect_flags {
+            *d_flags = ggpo::DisconnectFlags::default();
+        }
+        Ok(())
+    }
+
+    fn increment_frame(&mut self) -> Result<(), GGPOError> {
+        self.next_input_to_send = self.next_input_to_send.next();
+        self.update_buffering();
+        Ok(())
+    }
+
+    fn chat(&mut self, _text: String) -> Result<(), GGPOError> {
+        Ok(())
+    }
+
+    fn disconnect_player(&self, _handle: PlayerHandle) -> Result<(), GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    fn logv(_fmt: String) -> Result<(), GGPOError> {
+        Ok(())
+    }
+
+    fn set_version(&mut self, version: u32) -> Result<(), GGPOError> {
+        self.host.lock().set_version(version);
+        Ok(())
+    }
+
+    fn negotiated_version(&self) -> Result<u32, GGPOError> {
+        let host = self.host.lock();
+        match host.remote_version() {
+            Some(remote) if remote != host.version() => Err(GGPOError::IncompatibleVersion {
+                local: host.version(),
+                remote,
+            }),
+            _ if self.synchronizing => Err(GGPOError::NotSynchronized),
+            _ => Ok(host.version()),
+        }
+    }
+
+    fn shutdown(&mut self) -> Result<(), GGPOError> {
+        info!("Shutting down spectator.\n");
+        self.host.lock().send_goodbye()?;
+        self.udp.lock().close()?;
+        Ok(())
+    }
+}
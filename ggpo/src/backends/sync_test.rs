@@ -1,20 +1,12 @@
 use crate::{
-    game_input::{Frame, FrameNum, GameInput, InputBuffer},
-    ggpo::{
-        self, GGPOError, GGPOSessionCallbacks, NetworkStats, Session, GGPO_MAX_PLAYERS,
-        GGPO_MAX_SPECTATORS,
-    },
-    network::{
-        udp::{Udp, UdpCallback, UdpError},
-        udp_msg::{ConnectStatus, UdpMsg, UDP_MSG_MAX_PLAYERS},
-        udp_proto::{self, UdpProtoError, UdpProtocol},
-    },
+    game_input::{Frame, GameInput, InputBuffer},
+    ggpo::{self, GGPOError, GGPOSessionCallbacks, Session},
+    network::{udp::UdpError, udp_proto::UdpProtoError},
     player::{Player, PlayerHandle},
     sync::{self, GGPOSync, SyncError},
 };
-use log::{error, info};
-use parking_lot::{Mutex, RwLock};
-use std::{collections::VecDeque, net::SocketAddr, sync::Arc, time::Duration};
+use parking_lot::Mutex;
+use std::{collections::VecDeque, sync::Arc, time::Duration};
 use thiserror::Error;
 
 // const RECOMMENDATION_INTERVAL: u32 = 240;
@@ -102,7 +94,7 @@ where
         &mut self,
         player: PlayerHandle,
         values: &InputBuffer,
-        size: usize,
+        _size: usize,
     ) -> Result<(), GGPOError> {
         if !self.running {
             return Err(GGPOError::NotSynchronized);
@@ -116,8 +108,8 @@ where
 
     fn synchronize_input(
         &self,
-        values: &mut Vec<InputBuffer>,
-        disconnect_flags: Option<&mut i32>,
+        values: &mut InputBuffer,
+        disconnect_flags: Option<&mut ggpo::DisconnectFlags>,
     ) -> Result<(), GGPOError> {
         // TODO: self.begin_log(false);
         if self.rolling_back {
@@ -133,9 +125,9 @@ where
             }
             *self.last_input.lock() = self.current_input;
         }
-        values.push(self.last_input.lock().bits);
+        *values = self.last_input.lock().bits;
         if let Some(flags) = disconnect_flags {
-            *flags = 0;
+            *flags = ggpo::DisconnectFlags::default();
         }
         Ok(())
     }
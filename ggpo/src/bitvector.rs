@@ -1,4 +1,6 @@
-pub const BITVECTOR_NIBBLE_SIZE: usize = 8;
+// Nibbles index single bits of a `GameInput`, so 2^BITVECTOR_NIBBLE_SIZE must
+// cover GAMEINPUT_MAX_BYTES * GAMEINPUT_MAX_PLAYERS * 8 bits.
+pub const BITVECTOR_NIBBLE_SIZE: usize = 10;
 
 pub fn set_bit(vector: &mut [u8], offset: &mut usize) {
     vector[((*offset) / 8)] |= 1 << ((*offset) % 8);
@@ -21,13 +23,13 @@ pub fn write_nibblet(vector: &mut [u8], nibble: usize, offset: &mut usize) {
     }
 }
 
-pub fn read_bit(vector: &mut [u8], offset: &mut usize) -> i32 {
-    let retval: i32 = !!(vector[(*offset) / 8] & (1 << ((*offset) % 8))) as i32;
+pub fn read_bit(vector: &[u8], offset: &mut usize) -> i32 {
+    let retval: i32 = ((vector[(*offset) / 8] >> ((*offset) % 8)) & 1) as i32;
     *offset += 1;
     retval
 }
 
-pub fn read_nibblet(vector: &mut [u8], offset: &mut usize) -> i32 {
+pub fn read_nibblet(vector: &[u8], offset: &mut usize) -> i32 {
     let mut nibblet: i32 = 0;
     for i in 0..BITVECTOR_NIBBLE_SIZE {
         nibblet |= read_bit(vector, offset) << i;
@@ -0,0 +1,203 @@
+/*
+ * Everything a peer-to-peer session can be tuned with, in one place, so
+ * `Peer2PeerBackend::from_config` doesn't need an argument for each.  Start
+ * from `SessionConfig::default()` and change what you need.
+ */
+
+use crate::{
+    backends::p2p::SPECTATOR_LAG_WINDOW,
+    desync::DesyncAction,
+    game_input::{FrameNum, GAMEINPUT_MAX_BYTES},
+    ggpo::{GGPOError, GGPO_MAX_PLAYERS, GGPO_MAX_PREDICTION_FRAMES},
+    network::{
+        relay::RelayTransport,
+        udp_proto::{
+            TransportProfile, DEFAULT_RETRANSMIT_INTERVAL, NUM_SYNC_PACKETS,
+            SYNC_FIRST_RETRY_INTERVAL, SYNC_RETRY_INTERVAL,
+        },
+    },
+    sync::{ChecksumCadence, QueueOverflow},
+    time_sync::MAX_AUTO_FRAME_DELAY,
+};
+use log::error;
+
+#[cfg(feature = "encryption")]
+use crate::network::encryption::PresharedKey;
+
+pub const DEFAULT_DISCONNECT_TIMEOUT: u128 = 5000;
+pub const DEFAULT_DISCONNECT_NOTIFY_START: u128 = 750;
+pub const DEFAULT_CHECKSUM_CADENCE: ChecksumCadence = ChecksumCadence::Every(30);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionConfig {
+    pub local_port: u16,
+    // Listen on one IPv6 socket that takes IPv4 peers too; see `Udp::set_dual_stack`.
+    pub dual_stack: bool,
+    // Reach every peer through this relay; see `network::relay`.
+    pub relay: Option<RelayTransport>,
+    // Seal packets under this key, which the peer must have too, or ones derived from it; see `network::encryption`.
+    #[cfg(feature = "encryption")]
+    pub preshared_key: Option<PresharedKey>,
+    // Send from a thread of our own.  Without it, packets go out as the game polls.
+    pub send_task: bool,
+    // Only for networks nobody else can reach; see `TransportProfile::TrustedLan`.
+    pub transport_profile: TransportProfile,
+    pub num_players: usize,
+    pub input_size: usize,
+    // The game's protocol and save-state version; see `Session::set_version`.
+    pub version: u32,
+    // How many frames we run ahead of the last confirmed one before waiting.
+    pub prediction_frames: FrameNum,
+    // Run fewer while predictions keep failing; see `sync::AdaptivePrediction`.
+    pub adaptive_prediction: bool,
+    // Number frames from here rather than 0, to rejoin a match; see `sync::Config::start_frame`.
+    pub start_frame: FrameNum,
+    // Compare checksums of every player's confirmed input with peers; see `Event::InputDesyncDetected`.
+    pub input_checksums: bool,
+    /*
+     * Host a broadcast instead of playing: every player is remote, and each
+     * sends us their session's confirmed input by adding us as a spectator.
+     * The session steps itself through each frame once every player's input
+     * for it is in, and passes the frames on to its own spectators.  See
+     * `Peer2PeerBackend::step_broadcast`.
+     */
+    pub broadcast: bool,
+    // Applied to every local player as they're added.
+    pub frame_delay: usize,
+    // Start from `frame_delay`, then follow the round trip; see `Session::set_auto_frame_delay`.
+    pub auto_frame_delay: bool,
+    // Round trips in the handshake with each peer.
+    pub num_sync_packets: u32,
+    // In ms: how long to wait for a sync reply, after the first request and after the rest.
+    pub sync_first_retry_interval: u128,
+    pub sync_retry_interval: u128,
+    // In ms.  A peer still not synchronized after this is disconnected; 0 waits forever.
+    pub sync_timeout: u128,
+    // In ms.  A disconnect timeout of 0 never times out.
+    pub disconnect_timeout: u128,
+    pub disconnect_notify_start: u128,
+    pub retransmit_interval: u128,
+    // Bytes per second to each peer; 0 is unlimited.
+    pub send_rate_limit: usize,
+    pub reconnect_window: u128,
+    // Frames of input a spectator can leave unacked before it's dropped; see `SPECTATOR_LAG_WINDOW`.
+    pub spectator_lag_window: usize,
+    // `None` keeps enough for the prediction window, plus a margin.
+    pub saved_state_depth: Option<usize>,
+    /*
+     * Save state only every this many frames, and roll back by resetting to
+     * the last checkpoint (`GGPOSessionCallbacks::reset_to_frame`) and
+     * replaying input.  Less memory, more frames run again.  Desync checksums
+     * and spectator snapshots only cover the checkpoints.  1 saves every frame.
+     */
+    pub checkpoint_interval: FrameNum,
+    // Which saved states get a desync checksum; see `sync::ChecksumCadence`.
+    pub checksum_cadence: ChecksumCadence,
+    // What to do once a desync is detected.
+    pub on_desync: DesyncAction,
+    // `None` uses the default.
+    pub input_queue_length: Option<usize>,
+    pub queue_overflow: QueueOverflow,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            local_port: 0,
+            dual_stack: false,
+            relay: None,
+            #[cfg(feature = "encryption")]
+            preshared_key: None,
+            send_task: true,
+            transport_profile: TransportProfile::Internet,
+            num_players: 2,
+            input_size: 4,
+            version: 0,
+            prediction_frames: GGPO_MAX_PREDICTION_FRAMES,
+            adaptive_prediction: false,
+            start_frame: 0,
+            input_checksums: false,
+            broadcast: false,
+            frame_delay: 0,
+            auto_frame_delay: false,
+            num_sync_packets: NUM_SYNC_PACKETS,
+            sync_first_retry_interval: SYNC_FIRST_RETRY_INTERVAL,
+            sync_retry_interval: SYNC_RETRY_INTERVAL,
+            sync_timeout: 0,
+            disconnect_timeout: DEFAULT_DISCONNECT_TIMEOUT,
+            disconnect_notify_start: DEFAULT_DISCONNECT_NOTIFY_START,
+            retransmit_interval: DEFAULT_RETRANSMIT_INTERVAL,
+            send_rate_limit: 0,
+            reconnect_window: 0,
+            spectator_lag_window: SPECTATOR_LAG_WINDOW,
+            saved_state_depth: None,
+            checkpoint_interval: 1,
+            checksum_cadence: DEFAULT_CHECKSUM_CADENCE,
+            on_desync: DesyncAction::default(),
+            input_queue_length: None,
+            queue_overflow: QueueOverflow::default(),
+        }
+    }
+}
+
+impl SessionConfig {
+    /*
+     * Fails with `InvalidRequest` on settings that can't work, or that
+     * contradict each other.
+     */
+    pub fn validate(&self) -> Result<(), GGPOError> {
+        let frame_delay = if self.auto_frame_delay {
+            self.frame_delay.max(MAX_AUTO_FRAME_DELAY)
+        } else {
+            self.frame_delay
+        };
+        let problem = if self.num_players == 0 || self.num_players > GGPO_MAX_PLAYERS {
+            format!(
+                "{} players requested, but it must be between 1 and {}.",
+                self.num_players, GGPO_MAX_PLAYERS
+            )
+        } else if self.input_size == 0 || self.input_size > GAMEINPUT_MAX_BYTES {
+            format!(
+                "input size {} requested, but it must be between 1 and {} bytes.",
+                self.input_size, GAMEINPUT_MAX_BYTES
+            )
+        } else if self.prediction_frames == 0 {
+            "the prediction window must be at least one frame.".to_string()
+        } else if self.num_sync_packets == 0 {
+            "the sync handshake must be at least one round trip.".to_string()
+        } else if self.sync_first_retry_interval == 0 || self.sync_retry_interval == 0 {
+            "the sync retry intervals must be at least 1 ms.".to_string()
+        } else if self.disconnect_timeout > 0
+            && self.disconnect_notify_start >= self.disconnect_timeout
+        {
+            format!(
+                "the disconnect notification at {} ms would come after the disconnect at {} ms.",
+                self.disconnect_notify_start, self.disconnect_timeout
+            )
+        } else if self.spectator_lag_window == 0 {
+            "the spectator lag window must be at least one frame.".to_string()
+        } else if self.checkpoint_interval == 0 {
+            "the checkpoint interval must be at least one frame.".to_string()
+        } else if self.checksum_cadence == ChecksumCadence::Every(0) {
+            "the checksum cadence must be at least one frame.".to_string()
+        } else if self.saved_state_depth.map_or(false, |depth| {
+            depth <= (self.prediction_frames / self.checkpoint_interval) as usize
+        }) {
+            format!(
+                "{:?} saved states can't cover a {} frame prediction window.",
+                self.saved_state_depth, self.prediction_frames
+            )
+        } else if self.input_queue_length.map_or(false, |length| {
+            length <= self.prediction_frames as usize + frame_delay
+        }) {
+            format!(
+                "input queues of {:?} frames can't hold a {} frame prediction window and a {} frame delay.",
+                self.input_queue_length, self.prediction_frames, frame_delay
+            )
+        } else {
+            return Ok(());
+        };
+        error!("Invalid session config: {}\n", problem);
+        Err(GGPOError::InvalidRequest)
+    }
+}
@@ -0,0 +1,32 @@
+/*
+ * Checksums over saved game state.  When `save_game_state` doesn't supply one
+ * the session computes this over the saved buffer, so sync testing and desync
+ * detection work without the game hand-rolling its own.  The result depends
+ * only on the bytes, so every peer gets the same value for the same state.
+ */
+
+/*
+ * Fletcher-32 over the buffer read as little-endian 16-bit words, with an odd
+ * trailing byte padded with zero.  The same checksum the original vectorwar
+ * example computes over its game state.
+ */
+pub fn fletcher32(data: &[u8]) -> u32 {
+    let mut sum1: u32 = 0xffff;
+    let mut sum2: u32 = 0xffff;
+
+    // 359 words is the most we can add up before sum2 could overflow.
+    for block in data.chunks(359 * 2) {
+        for word in block.chunks(2) {
+            let high = word.get(1).copied().unwrap_or(0);
+            sum1 += u16::from_le_bytes([word[0], high]) as u32;
+            sum2 += sum1;
+        }
+        sum1 = (sum1 & 0xffff) + (sum1 >> 16);
+        sum2 = (sum2 & 0xffff) + (sum2 >> 16);
+    }
+
+    // Second reduction step to reduce sums to 16 bits.
+    sum1 = (sum1 & 0xffff) + (sum1 >> 16);
+    sum2 = (sum2 & 0xffff) + (sum2 >> 16);
+    sum2 << 16 | sum1
+}
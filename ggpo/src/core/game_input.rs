@@ -0,0 +1,232 @@
+use crate::core::GGPO_MAX_PLAYERS;
+use alloc::{format, string::String};
+use bytemuck::Pod;
+use core::{
+    fmt,
+    ops::{Add, Sub},
+};
+use log::info;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+// GAMEINPUT_MAX_BYTES * GAMEINPUT_MAX_PLAYERS * 8 must be less than
+// 2^BITVECTOR_NIBBLE_SIZE (see bitvector.rs)
+
+pub const GAMEINPUT_MAX_BYTES: usize = 16;
+pub const GAMEINPUT_MAX_PLAYERS: usize = GGPO_MAX_PLAYERS;
+pub const INPUT_BUFFER_SIZE: usize = GAMEINPUT_MAX_BYTES * GAMEINPUT_MAX_PLAYERS;
+pub type Input = [u8; GAMEINPUT_MAX_BYTES];
+pub type InputBuffer = [Input; GAMEINPUT_MAX_PLAYERS];
+// pub type InputBuffer = [u8; INPUT_BUFFER_SIZE];
+pub type FrameNum = u32;
+
+/*
+ * A player's input as a plain struct instead of bytes: anything
+ * `bytemuck::Pod`, with no padding, whose size is the player's input size.
+ * See `Session::add_local_input_typed` and `Session::synchronize_input_typed`.
+ */
+pub trait InputFrame: Pod {}
+
+impl<T: Pod> InputFrame for T {}
+
+/*
+ * A frame number, or `NULL_FRAME` where there isn't one (nothing received yet,
+ * an input dropped by the queue, ...).  `NULL_FRAME` orders before every real
+ * frame, and arithmetic saturates to it instead of wrapping, so stepping back
+ * from frame 0 can never produce something that looks like a real frame.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Frame(i32);
+
+pub const NULL_FRAME: Frame = Frame(-1);
+
+impl Frame {
+    // Later than any frame a session will reach; handy as the seed for a running minimum.
+    pub const MAX: Frame = Frame(i32::MAX);
+
+    pub const fn new(frame: FrameNum) -> Self {
+        if frame > i32::MAX as FrameNum {
+            Frame(i32::MAX)
+        } else {
+            Frame(frame as i32)
+        }
+    }
+    pub const fn is_null(self) -> bool {
+        self.0 < 0
+    }
+    pub const fn as_i32(self) -> i32 {
+        self.0
+    }
+    // The frame as a plain number, or `None` for `NULL_FRAME`.
+    pub const fn number(self) -> Option<FrameNum> {
+        if self.is_null() {
+            None
+        } else {
+            Some(self.0 as FrameNum)
+        }
+    }
+    // The frame after `NULL_FRAME` is frame 0.
+    pub fn next(self) -> Frame {
+        self + 1
+    }
+    // The frame before frame 0 is `NULL_FRAME`, as is the frame before that.
+    pub fn prev(self) -> Frame {
+        self - 1
+    }
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        NULL_FRAME
+    }
+}
+
+impl From<FrameNum> for Frame {
+    fn from(frame: FrameNum) -> Self {
+        Frame::new(frame)
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.number() {
+            Some(frame) => fmt::Display::fmt(&frame, f),
+            None => f.pad("NULL"),
+        }
+    }
+}
+
+/*
+ * On the wire a frame is a plain i32, `NULL_FRAME` as -1, so an ack of "nothing
+ * yet" can't be mistaken for frame 0.  No other negative number is a frame; a
+ * packet carrying one is rejected rather than read as `NULL_FRAME`.
+ */
+impl Serialize for Frame {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i32(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Frame {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match i32::deserialize(deserializer)? {
+            frame if frame >= NULL_FRAME.0 => Ok(Frame(frame)),
+            frame => Err(de::Error::invalid_value(
+                de::Unexpected::Signed(frame as i64),
+                &"a frame number or -1",
+            )),
+        }
+    }
+}
+
+impl Add<i32> for Frame {
+    type Output = Frame;
+    fn add(self, rhs: i32) -> Frame {
+        if self.is_null() && rhs <= 0 {
+            return NULL_FRAME;
+        }
+        let base = if self.is_null() { -1 } else { self.0 };
+        Frame(base.saturating_add(rhs).max(NULL_FRAME.0))
+    }
+}
+
+impl Sub<i32> for Frame {
+    type Output = Frame;
+    fn sub(self, rhs: i32) -> Frame {
+        if self.is_null() && rhs >= 0 {
+            return NULL_FRAME;
+        }
+        let base = if self.is_null() { -1 } else { self.0 };
+        Frame(base.saturating_sub(rhs).max(NULL_FRAME.0))
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Default)]
+pub struct GameInput {
+    pub frame: Frame,
+    pub size: usize, // Number of u8 in the InputBuffer array.
+    pub bits: InputBuffer,
+}
+
+impl GameInput {
+    pub const fn new() -> Self {
+        GameInput {
+            frame: NULL_FRAME,
+            size: 0,
+            bits: [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS],
+        }
+    }
+    pub fn init(frame: Frame, bits: Option<&InputBuffer>, size: usize) -> GameInput {
+        assert!(size <= GAMEINPUT_MAX_BYTES);
+        match bits {
+            Some(i_bits) => GameInput {
+                frame,
+                size,
+                bits: *i_bits,
+            },
+            None => GameInput {
+                frame,
+                size,
+                bits: [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS],
+            },
+        }
+    }
+    // These address the input as one flat bit array, the same way the original
+    // treated `bits` as a single `char[GAMEINPUT_MAX_BYTES * GAMEINPUT_MAX_PLAYERS]`.
+    // Bit `i` lives in byte `i / 8`, and bytes are laid out player by player.
+    pub const fn value(&self, i: usize) -> bool {
+        let byte = i / 8;
+        (self.bits[byte / GAMEINPUT_MAX_BYTES][byte % GAMEINPUT_MAX_BYTES] & (1 << (i % 8))) != 0
+    }
+    pub fn set(&mut self, i: usize) {
+        let byte = i / 8;
+        self.bits[byte / GAMEINPUT_MAX_BYTES][byte % GAMEINPUT_MAX_BYTES] |= 1 << (i % 8);
+    }
+    pub fn clear(&mut self, i: usize) {
+        let byte = i / 8;
+        self.bits[byte / GAMEINPUT_MAX_BYTES][byte % GAMEINPUT_MAX_BYTES] &= !(1 << (i % 8));
+    }
+    // Every flat bit index, across all players, where `self` and `other` differ.
+    pub fn changed_bits<'a>(&'a self, other: &'a GameInput) -> impl Iterator<Item = usize> + 'a {
+        (0..GAMEINPUT_MAX_BYTES * GAMEINPUT_MAX_PLAYERS * 8)
+            .filter(move |&i| self.value(i) != other.value(i))
+    }
+    pub fn erase(&mut self) {
+        self.bits = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    }
+    pub fn describe(&self, show_frame: bool) -> String {
+        let mut buf: String = String::from("");
+        if !self.frame.is_null() {
+            if show_frame {
+                buf = format!("(frame:{} size:{}", self.frame, self.size);
+            } else {
+                buf = format!("(size:{}", self.size);
+            }
+        }
+
+        for i in 0..self.size {
+            if self.value(i) {
+                buf.push_str(&format!("{:2}", i));
+            }
+        }
+
+        buf.push(')');
+        buf
+    }
+    // fn log(prefix: &String, show_frame: bool) {}
+    pub fn equal(&self, other: &GameInput, bitsonly: bool) -> bool {
+        if !bitsonly && self.frame != other.frame {
+            info!("frames don't match: {}, {}\n", self.frame, other.frame);
+        }
+
+        if self.size != other.size {
+            info!("sizes don't match: {}, {}\n", self.size, other.size);
+        }
+
+        let bits_equality = self.bits == other.bits;
+        if !bits_equality {
+            info!("bits don't match\n");
+        }
+
+        return (bitsonly || self.frame == other.frame) && self.size == other.size && bits_equality;
+    }
+}
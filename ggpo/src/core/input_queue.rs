@@ -0,0 +1,634 @@
+use crate::core::{
+    game_input::{
+        Frame, FrameNum, GameInput, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS, NULL_FRAME,
+    },
+    prediction::{PredictionStrategy, RepeatLast},
+    sync::SyncError,
+};
+use alloc::{collections::VecDeque, sync::Arc, vec, vec::Vec};
+use core::cmp;
+use log::{debug, info};
+
+pub const DEFAULT_INPUT_QUEUE_LENGTH: usize = 128;
+const DEFAULT_INPUT_SIZE: usize = 4;
+
+macro_rules! previous_frame {
+    ($offset:expr, $length:expr) => {
+        if $offset == 0 {
+            $length - 1
+        } else {
+            $offset - 1
+        }
+    };
+}
+
+#[derive(Clone)]
+pub struct InputQueue {
+    _id: usize,
+    head: usize,
+    tail: usize,
+    length: usize,
+    first_frame: bool,
+
+    last_user_added_frame: Frame,
+    last_added_frame: Frame,
+    first_incorrect_frame: Frame,
+    last_frame_requested: Frame,
+    // Predictions in a row that real input bore out; see `prediction_streak`.
+    correct_predictions: u32,
+
+    frame_delay: usize,
+    input_size: usize,
+    // The first frame there'll be input for; see `set_start_frame`.
+    start_frame: FrameNum,
+
+    inputs: Vec<GameInput>,
+    // Only the frame and size are used: the next predicted frame to check against real input.
+    prediction: GameInput,
+    strategy: Arc<dyn PredictionStrategy>,
+    // What we've predicted so far, and the input it was predicted from.
+    predictions: VecDeque<GameInput>,
+    prediction_history: Vec<GameInput>,
+}
+
+impl Default for InputQueue {
+    fn default() -> Self {
+        InputQueue::init(0, DEFAULT_INPUT_SIZE)
+    }
+}
+
+impl InputQueue {
+    pub fn new() -> Self {
+        InputQueue {
+            ..Default::default()
+        }
+    }
+    pub fn init(id: usize, input_size: usize) -> Self {
+        Self::with_length(id, input_size, DEFAULT_INPUT_QUEUE_LENGTH)
+    }
+
+    /*
+     * A queue holding up to `length` frames of input.  It never overwrites a
+     * frame that hasn't been discarded as confirmed: once it's full, `add_input`
+     * turns new input away until `discard_confirmed_frames` makes room.
+     */
+    pub fn with_length(id: usize, input_size: usize, length: usize) -> Self {
+        assert!(length > 0);
+        InputQueue {
+            _id: id,
+            head: 0,
+            tail: 0,
+            length: 0,
+            frame_delay: 0,
+            input_size,
+            start_frame: 0,
+            first_frame: true,
+            last_user_added_frame: NULL_FRAME,
+            last_added_frame: NULL_FRAME,
+            first_incorrect_frame: NULL_FRAME,
+            last_frame_requested: NULL_FRAME,
+            correct_predictions: 0,
+
+            prediction: GameInput::init(NULL_FRAME, None, input_size),
+            strategy: Arc::new(RepeatLast),
+            predictions: VecDeque::new(),
+            prediction_history: Vec::new(),
+            inputs: vec![
+                GameInput::init(
+                    NULL_FRAME,
+                    Some(&[[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS]),
+                    input_size,
+                );
+                length
+            ],
+        }
+    }
+    pub fn get_confirmed_input(&self, requested_frame: Frame, input: &mut GameInput) -> bool {
+        assert!(
+            self.first_incorrect_frame.is_null() || requested_frame < self.first_incorrect_frame
+        );
+
+        if let Some(requested_frame_value) = requested_frame.number() {
+            let offset = requested_frame_value as usize % self.inputs.len();
+
+            if self.inputs[offset].frame != requested_frame {
+                return false;
+            }
+            *input = self.inputs[offset].clone();
+            return true;
+        }
+
+        false
+    }
+    /*
+     * Every confirmed input from `start` up to, but not including, `end`, for
+     * scrubbing through a replay.  Fails with `InputUnavailable` naming the
+     * first frame that's already been discarded or hasn't arrived yet; a
+     * session turns that into `GGPOError::GeneralFailure`.
+     */
+    pub fn get_confirmed_range(
+        &self,
+        start: Frame,
+        end: Frame,
+    ) -> Result<Vec<GameInput>, SyncError> {
+        let (first, last) = match (start.number(), end.number()) {
+            (Some(first), Some(last)) if first < last => (first, last),
+            _ => return Ok(Vec::new()),
+        };
+        let oldest = self.oldest_frame().unwrap_or(Frame::MAX);
+        let mut inputs = Vec::with_capacity((last - first) as usize);
+        for frame in (first..last).map(Frame::new) {
+            let input = &self.inputs[frame.as_i32() as usize % self.inputs.len()];
+            if frame < oldest || frame > self.last_added_frame || input.frame != frame {
+                return Err(SyncError::InputUnavailable(frame));
+            }
+            inputs.push(*input);
+        }
+        Ok(inputs)
+    }
+
+    // The earliest frame still held, if any are.
+    pub fn oldest_frame(&self) -> Option<Frame> {
+        if self.length > 0 {
+            Some(self.inputs[self.tail].frame)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_last_confirmed_frame(&self) -> Frame {
+        info!(
+            "returning last confirmed frame: {}\n",
+            self.last_added_frame
+        );
+
+        self.last_added_frame
+    }
+
+    pub fn input_size(&self) -> usize {
+        self.input_size
+    }
+
+    /*
+     * Numbers the input from `frame` on, for a session that doesn't start at
+     * frame 0.  Only before anything's been added.  Each frame still lives in
+     * the slot its number picks, so the queue starts there.
+     */
+    pub fn set_start_frame(&mut self, frame: FrameNum) {
+        assert!(self.last_added_frame.is_null());
+        self.start_frame = frame;
+        self.head = frame as usize % self.inputs.len();
+        self.tail = self.head;
+    }
+
+    pub fn start_frame(&self) -> FrameNum {
+        self.start_frame
+    }
+
+    // Only before any input has gone in; everything already queued keeps the old size.
+    pub fn set_input_size(&mut self, input_size: usize) {
+        assert!(self.last_added_frame.is_null());
+        self.input_size = input_size;
+        self.prediction.size = input_size;
+        for input in self.inputs.iter_mut() {
+            input.size = input_size;
+        }
+    }
+
+    // How many frames of input the queue is holding.
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    // How many frames of input the queue can hold.
+    pub fn capacity(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /*
+     * Whether the queue can take `input` without overwriting anything.  With a
+     * frame delay, or after the delay grows, one input can fill several slots.
+     */
+    pub fn has_room_for(&self, input: &GameInput) -> bool {
+        let frame = match input.frame.number() {
+            Some(frame) => frame as usize + self.frame_delay,
+            None => return true,
+        };
+        let needed = match self.last_added_frame.number() {
+            Some(last) => frame.saturating_sub(last as usize),
+            None => (frame + 1).saturating_sub(self.start_frame as usize),
+        };
+        self.length + needed <= self.capacity()
+    }
+
+    // How to guess input that hasn't arrived yet.  Takes effect from the next prediction.
+    pub fn set_prediction_strategy(&mut self, strategy: Arc<dyn PredictionStrategy>) {
+        self.strategy = strategy;
+    }
+
+    pub fn frame_delay(&self) -> usize {
+        self.frame_delay
+    }
+
+    pub fn set_frame_delay(&mut self, delay: usize) {
+        self.frame_delay = delay;
+    }
+
+    // How many of the frames we've predicted in a row, up to the last real input, were right.
+    pub fn prediction_streak(&self) -> u32 {
+        self.correct_predictions
+    }
+
+    pub fn get_first_incorrect_frame(&self) -> Frame {
+        self.first_incorrect_frame
+    }
+
+    pub fn discard_confirmed_frames(&mut self, in_frame: FrameNum) {
+        let mut frame = in_frame;
+
+        if let Some(last_frame_requested) = self.last_frame_requested.number() {
+            frame = cmp::min(frame, last_frame_requested);
+        }
+
+        if let Some(last_added_frame) = self.last_added_frame.number() {
+            info!(
+                "discarding confirmed frames up to {} (last_added:{} length:{} [head:{} tail:{}]).\n",
+                frame, last_added_frame, self.length, self.head, self.tail
+            );
+
+            if frame >= last_added_frame {
+                self.tail = self.head;
+                self.length = 0;
+            } else {
+                if let Some(tail_frame) = self.inputs[self.tail].frame.number() {
+                    // Nothing to drop when the tail is already past `frame`.
+                    let offset: usize = (frame + 1).saturating_sub(tail_frame) as usize;
+
+                    info!("difference of {} frames.\n", offset);
+
+                    self.tail = (self.tail + offset) % self.inputs.len();
+                    self.length -= offset;
+
+                    info!(
+                        "after discarding, new tail is {} (frame:{}).\n",
+                        self.tail, tail_frame,
+                    );
+                }
+            }
+        }
+    }
+
+    /*
+     * Restarts the queue after its player reconnects, leaving `frame` as the
+     * only input in it.  That input is whatever we simulated the frame with: a
+     * blank one if the player had been disconnected, otherwise the last input
+     * they sent, which is what we've been predicting since.  Returns it.
+     */
+    pub fn resume_after(&mut self, frame: FrameNum, disconnected: bool) -> GameInput {
+        let mut input = if disconnected {
+            GameInput::init(NULL_FRAME, None, self.input_size)
+        } else {
+            self.inputs[previous_frame!(self.head, self.inputs.len())]
+        };
+        input.frame = Frame::new(frame);
+        info!("resuming queue after frame {}.\n", frame);
+
+        let offset = frame as usize % self.inputs.len();
+        self.inputs[offset] = input;
+        self.tail = offset;
+        self.head = (offset + 1) % self.inputs.len();
+        self.length = 1;
+        self.first_frame = false;
+
+        self.last_user_added_frame = input.frame;
+        self.last_added_frame = input.frame;
+        self.first_incorrect_frame = NULL_FRAME;
+        self.last_frame_requested = NULL_FRAME;
+        self.prediction.frame = NULL_FRAME;
+        self.predictions.clear();
+        input
+    }
+
+    /*
+     * Repeats the last input up to and including `frame`.  Returns false if
+     * the queue fills up first.
+     */
+    pub fn pad_to(&mut self, frame: Frame) -> bool {
+        while self.last_user_added_frame < frame {
+            let mut input = self.inputs[previous_frame!(self.head, self.inputs.len())];
+            input.frame = self.last_user_added_frame.next();
+            if !self.add_input(input) {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn reset_prediction(&mut self, frame: FrameNum) {
+        assert!(
+            self.first_incorrect_frame.is_null() || Frame::new(frame) <= self.first_incorrect_frame
+        );
+
+        info!("resetting all prediction errors back to frame {}.\n", frame);
+
+        self.prediction.frame = NULL_FRAME;
+        self.predictions.clear();
+        self.first_incorrect_frame = NULL_FRAME;
+        self.last_frame_requested = NULL_FRAME;
+    }
+
+    pub fn get_input(&mut self, requested_frame: FrameNum, input: &mut GameInput) -> bool {
+        info!("requesting input frame {}.\n", requested_frame);
+
+        /*
+         * No one should ever try to grab any input when we have a prediction
+         * error.  Doing so means that we're just going further down the wrong
+         * path.  ASSERT this to verify that it's true.
+         */
+        assert!(self.first_incorrect_frame.is_null());
+
+        /*
+         * Remember the last requested frame number for later.  We'll need
+         * this in AddInput() to drop out of prediction mode.
+         */
+        self.last_frame_requested = Frame::new(requested_frame);
+
+        if self.prediction.frame.is_null() {
+            // An empty queue has nothing confirmed to hand back.
+            if let Some(input_tail_frame) = self.inputs[self.tail].frame.number() {
+                assert!(requested_frame >= input_tail_frame);
+                let mut offset: usize = (requested_frame - input_tail_frame) as usize;
+
+                if offset < self.length {
+                    offset = (offset + self.tail) % self.inputs.len();
+                    assert!(self.inputs[offset].frame == Frame::new(requested_frame));
+                    *input = self.inputs[offset];
+                    info!("returning confirmed frame number {}.\n", input.frame);
+                    return true;
+                }
+            }
+
+            /*
+             * The requested frame isn't in the queue.  Bummer.  This means we need
+             * to return a prediction frame, guessed by the prediction strategy from
+             * the input we have so far.
+             */
+            self.predictions.clear();
+            if requested_frame == self.start_frame {
+                info!("basing new prediction frame from nothing, you're client wants the first frame.\n");
+                self.prediction_history.clear();
+                self.prediction.frame = Frame::new(self.start_frame);
+            } else if self.last_added_frame.is_null() {
+                info!("basing new prediction frame from nothing, since we have no frames yet.\n");
+                self.prediction_history.clear();
+                self.prediction.frame = Frame::new(self.start_frame);
+            } else {
+                info!("basing new prediction frame from previously added frame (queue entry:{}, frame:{}).\n",
+                    previous_frame!(self.head, self.inputs.len()), self.inputs[previous_frame!(self.head, self.inputs.len())].frame);
+                self.prediction_history = self.history();
+                self.prediction.frame = self.last_added_frame.next();
+            }
+        }
+
+        assert!(!self.prediction.frame.is_null());
+        /*
+         * If we've made it this far, we must be predicting.  Go ahead and
+         * forward the prediction for the frame the client asked for.
+         */
+        *input = self.predicted_input(Frame::new(requested_frame));
+        info!(
+            "returning prediction frame number {} ({}).\n",
+            input.frame, self.prediction.frame
+        );
+
+        false
+    }
+
+    // Everything the queue holds, oldest first.  Always includes the last input added.
+    fn history(&self) -> Vec<GameInput> {
+        let len = self.inputs.len();
+        let count = cmp::max(self.length, 1);
+        (0..count)
+            .map(|i| self.inputs[(self.head + len - count + i) % len])
+            .collect()
+    }
+
+    /*
+     * What we predict for `frame`.  Each frame is only guessed once per run of
+     * predictions, so the input we check against later is the one we returned.
+     */
+    fn predicted_input(&mut self, frame: Frame) -> GameInput {
+        while let Some(front) = self.predictions.front() {
+            if front.frame >= self.prediction.frame {
+                break;
+            }
+            self.predictions.pop_front();
+        }
+        if let Some(predicted) = self.predictions.iter().find(|p| p.frame == frame) {
+            return *predicted;
+        }
+
+        let mut predicted = if self.prediction_history.is_empty() {
+            GameInput::init(frame, None, self.input_size)
+        } else {
+            self.strategy.predict(&self.prediction_history, frame)
+        };
+        predicted.frame = frame;
+        predicted.size = self.input_size;
+        if self
+            .predictions
+            .back()
+            .map_or(true, |back| back.frame < frame)
+        {
+            self.predictions.push_back(predicted);
+        }
+        predicted
+    }
+
+    pub fn add_delayed_input_to_queue(&mut self, input: &GameInput, frame_number: FrameNum) {
+        info!(
+            "adding delayed input frame number {} to queue.\n",
+            frame_number
+        );
+        assert!(input.size == self.prediction.size);
+        let frame = Frame::new(frame_number);
+        assert!(self.last_added_frame.is_null() || frame == self.last_added_frame.next());
+        assert!(
+            frame_number == self.start_frame
+                || self.inputs[previous_frame!(self.head, self.inputs.len())].frame == frame.prev()
+        );
+
+        /*
+         * Add the frame to the back of the queue
+         */
+        self.inputs[self.head] = input.clone();
+        self.inputs[self.head].frame = frame;
+        self.head = (self.head + 1) % self.inputs.len();
+        self.length += 1;
+        self.first_frame = false;
+
+        self.last_added_frame = frame;
+
+        if !self.prediction.frame.is_null() {
+            assert!(frame == self.prediction.frame);
+
+            /*
+             * We've been predicting...  See if the inputs we've gotten match
+             * what we've been predicting.  If so, don't worry about it.  If not,
+             * remember the first input which was incorrect so we can report it
+             * in GetFirstIncorrectFrame()
+             */
+            let predicted = self.predicted_input(frame);
+            let correct = predicted.equal(input, true);
+            self.correct_predictions = if correct {
+                self.correct_predictions.saturating_add(1)
+            } else {
+                0
+            };
+            if self.first_incorrect_frame.is_null() && !correct {
+                info!(
+                    "frame {} does not match prediction.  marking error.\n",
+                    frame_number,
+                );
+                self.first_incorrect_frame = frame;
+            }
+
+            /*
+             * If this input is the same frame as the last one requested and we
+             * still haven't found any mis-predicted inputs, we can dump out
+             * of predition mode entirely!  Otherwise, advance the prediction frame
+             * count up.
+             */
+            if self.prediction.frame == self.last_frame_requested
+                && self.first_incorrect_frame.is_null()
+            {
+                info!("prediction is correct!  dumping out of prediction mode.\n");
+                self.prediction.frame = NULL_FRAME;
+            } else {
+                self.prediction.frame = self.prediction.frame.next();
+            }
+        }
+        assert!(self.length <= self.inputs.len());
+    }
+
+    /*
+     * Returns false, leaving the queue untouched, if there's no room for the
+     * input.  Input for a frame we've already been given, a late retransmission
+     * say, is dropped, also leaving the queue untouched: it may have been
+     * discarded long since, and its slot reused by a later frame.
+     */
+    pub fn add_input(&mut self, mut input: GameInput) -> bool {
+        assert!(input.size == self.input_size);
+        if !input.frame.is_null() && input.frame <= self.last_user_added_frame {
+            match self.oldest_frame() {
+                Some(oldest) if input.frame + self.frame_delay as i32 >= oldest => debug!(
+                    "dropping input frame {}, already queued (oldest {}).\n",
+                    input.frame, oldest
+                ),
+                _ => debug!(
+                    "dropping input frame {}, already discarded (have up to {}).\n",
+                    input.frame, self.last_user_added_frame
+                ),
+            }
+            return true;
+        }
+        if !self.has_room_for(&input) {
+            info!(
+                "no room for input frame {} ({} of {} frames in use).\n",
+                input.frame,
+                self.length,
+                self.capacity()
+            );
+            return false;
+        }
+        if !input.frame.is_null() {
+            info!("adding input frame number {} to queue.\n", input.frame);
+
+            /*
+             * These next two lines simply verify that inputs are passed in
+             * sequentially by the user, regardless of frame delay.
+             */
+            assert!(
+                self.last_user_added_frame.is_null()
+                    || input.frame == self.last_user_added_frame.next()
+            );
+
+            self.last_user_added_frame = input.frame;
+
+            /*
+             * Move the queue head to the correct point in preparation to
+             * input the frame into the queue.
+             */
+            let new_frame = self.advance_queue_head(input.frame);
+            if let Some(new_frame_number) = new_frame.number() {
+                self.add_delayed_input_to_queue(&input, new_frame_number);
+            }
+
+            /*
+             * Update the frame number for the input.  This will also set the
+             * frame to NULL_FRAME for frames that get dropped (by design).
+             */
+            input.frame = new_frame;
+        }
+        true
+    }
+
+    pub fn advance_queue_head(&mut self, input_frame: Frame) -> Frame {
+        if let Some(frame) = input_frame.number() {
+            info!("advancing queue head to frame {}.\n", frame);
+            // Nothing's been added before the first frame, but a frame delay
+            // still needs the frames ahead of it padding out.
+            let previous_head = self.inputs[previous_frame!(self.head, self.inputs.len())]
+                .frame
+                .number();
+            if self.first_frame || previous_head.is_some() {
+                let mut expected_frame = match previous_head {
+                    Some(input_previous_head) if !self.first_frame => input_previous_head + 1,
+                    _ => self.start_frame,
+                };
+                if expected_frame > frame {
+                    /*
+                     * This can occur when the frame delay has dropped since the last
+                     * time we shoved a frame into the system.  In this case, there's
+                     * no room on the queue.  Toss it.
+                     */
+                    info!(
+                        "Dropping input frame {} (expected next frame to be {}).\n",
+                        frame, expected_frame
+                    );
+                    return NULL_FRAME;
+                }
+
+                while expected_frame < frame {
+                    /*
+                     * This can occur when the frame delay has been increased since the last
+                     * time we shoved a frame into the system.  We need to replicate the
+                     * last frame in the queue several times in order to fill the space
+                     * left.
+                     */
+                    info!(
+                        "Adding padding frame {} to account for change in frame delay.\n",
+                        expected_frame
+                    );
+                    let last_frame_input: GameInput =
+                        self.inputs[previous_frame!(self.head, self.inputs.len())];
+
+                    self.add_delayed_input_to_queue(&last_frame_input, expected_frame);
+                    expected_frame += 1;
+                }
+
+                assert!(
+                    frame == self.start_frame
+                        || self.inputs[previous_frame!(self.head, self.inputs.len())].frame
+                            == Frame::new(frame).prev()
+                );
+            }
+            return Frame::new(frame);
+        }
+        input_frame
+    }
+}
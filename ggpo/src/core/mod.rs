@@ -0,0 +1,25 @@
+/*
+ * The rollback logic on its own: input queues, prediction, saving and
+ * loading states, and deciding when to roll back.  Nothing in here touches
+ * the network or the OS, so it builds with just `alloc`, for targets without
+ * `std` (turn off the default features).  Sessions, backends and the UDP
+ * transport sit on top of it behind the `net` feature.
+ */
+
+pub mod checksum;
+pub mod game_input;
+pub mod input_queue;
+pub mod prediction;
+pub mod sync;
+pub mod time_sync;
+
+use game_input::FrameNum;
+
+pub const GGPO_MAX_PLAYERS: usize = 4;
+pub const GGPO_MAX_PREDICTION_FRAMES: FrameNum = 8;
+
+// The lock the core shares state behind: parking_lot's with `std`, a spinlock without.
+#[cfg(feature = "std")]
+pub use parking_lot::Mutex;
+#[cfg(not(feature = "std"))]
+pub use spin::Mutex;
@@ -0,0 +1,64 @@
+/*
+ * Input prediction.  Until a remote player's input for a frame arrives we run
+ * the frame with a guess, and roll back if the guess was wrong.  Repeating the
+ * last input is right for held buttons and wrong for taps, so games that know
+ * better can supply their own guess.
+ */
+
+use crate::core::game_input::{Frame, GameInput};
+use core::fmt;
+
+pub trait PredictionStrategy: fmt::Debug + Send + Sync {
+    /*
+     * Guesses the input for `frame`.  `history` is the input the queue still
+     * holds for the player, oldest first, and is never empty; its last entry
+     * is the newest input we actually have, so `frame` is at least one past
+     * it.  Every frame predicted from the same history must be guessed the
+     * same way each time it's asked for.  The frame and size of the result
+     * are filled in by the caller.
+     */
+    fn predict(&self, history: &[GameInput], frame: Frame) -> GameInput;
+}
+
+// The original behaviour: whatever the player was doing, they're still doing.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct RepeatLast;
+
+impl PredictionStrategy for RepeatLast {
+    fn predict(&self, history: &[GameInput], frame: Frame) -> GameInput {
+        history
+            .last()
+            .copied()
+            .unwrap_or_else(|| GameInput::init(frame, None, 0))
+    }
+}
+
+/*
+ * Repeats the last input for `frames` frames, then predicts a neutral one
+ * (no buttons held).  Suits games where most inputs are short taps.
+ */
+#[derive(Debug, Copy, Clone)]
+pub struct NeutralAfterN {
+    pub frames: usize,
+}
+
+impl NeutralAfterN {
+    pub const fn new(frames: usize) -> Self {
+        Self { frames }
+    }
+}
+
+impl PredictionStrategy for NeutralAfterN {
+    fn predict(&self, history: &[GameInput], frame: Frame) -> GameInput {
+        let last = match history.last() {
+            Some(last) => *last,
+            None => return GameInput::init(frame, None, 0),
+        };
+        let predicted = (frame.as_i32() - last.frame.as_i32()).max(0) as usize;
+        if predicted <= self.frames {
+            last
+        } else {
+            GameInput::init(frame, None, last.size)
+        }
+    }
+}
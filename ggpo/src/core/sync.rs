@@ -0,0 +1,1350 @@
+use crate::core::{
+    checksum,
+    game_input::{
+        Frame, FrameNum, GameInput, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS,
+        NULL_FRAME,
+    },
+    input_queue::{InputQueue, DEFAULT_INPUT_QUEUE_LENGTH},
+    prediction::PredictionStrategy,
+    Mutex, GGPO_MAX_PREDICTION_FRAMES,
+};
+// use async_mutex::Mutex;
+use alloc::{
+    collections::VecDeque,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use bytes::Bytes;
+use core::fmt;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use thiserror::Error;
+
+// `std::error::Error` only exists with `std`; without it these are just `Debug`.
+#[derive(Debug)]
+#[cfg_attr(feature = "std", derive(Error))]
+pub enum SyncError {
+    #[cfg_attr(feature = "std", error("Config is uninitialized."))]
+    ConfigNone,
+    #[cfg_attr(feature = "std", error("Callbacks are uninitialized/None"))]
+    CallbacksNone,
+    #[cfg_attr(feature = "std", error("save_game_state callback failed: {0}"))]
+    SaveGameState(String),
+    #[cfg_attr(
+        feature = "std",
+        error("Frame {0} is no longer in the saved state ring.")
+    )]
+    FrameNotSaved(Frame),
+    #[cfg_attr(
+        feature = "std",
+        error("Local input for frame {0} arrived after that frame was confirmed.")
+    )]
+    InputDropped(Frame),
+    #[cfg_attr(
+        feature = "std",
+        error("Input queue {0} is full of frames that haven't been confirmed.")
+    )]
+    InputQueueFull(u32),
+    #[cfg_attr(
+        feature = "std",
+        error("Input for frame {0} isn't confirmed, or was already dropped.")
+    )]
+    InputUnavailable(Frame),
+}
+
+// A snapshot of the game returned from `GGPOSessionCallbacks::save_game_state`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SavedState {
+    pub data: Bytes,
+    /*
+     * `None` has the session checksum `data` itself, with `checksum::fletcher32`.
+     * Frames off the `ChecksumCadence` get no checksum either way.
+     */
+    pub checksum: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+pub struct ConnectStatus {
+    pub disconnected: bool,
+    pub last_frame: Frame,
+}
+
+impl ConnectStatus {
+    pub const fn new() -> Self {
+        Self {
+            disconnected: true,
+            last_frame: NULL_FRAME,
+        }
+    }
+}
+
+impl Default for ConnectStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/*
+ * What rolling back needs from the game.  Every `GGPOSessionCallbacks` is
+ * one already; implement this directly to drive `GGPOSync` without a
+ * session, or without `std`.
+ */
+pub trait RollbackCallbacks {
+    type Error: fmt::Display;
+
+    // Like `GGPOSessionCallbacks::save_game_state`.
+    fn save_state(&mut self, frame: Frame) -> Result<SavedState, Self::Error>;
+    // Like `GGPOSessionCallbacks::load_game_state`.
+    fn load_state(&mut self, buffer: &Bytes, length: usize) -> bool;
+    // Like `GGPOSessionCallbacks::reset_to_frame`.
+    fn reset_state(&mut self, _frame: Frame, buffer: &Bytes, length: usize) -> bool {
+        self.load_state(buffer, length)
+    }
+    // Runs one frame again during a rollback, like `GGPOSessionCallbacks::advance_frame`.
+    fn resimulate_frame(&mut self) -> bool;
+    /*
+     * `resimulate_frame`, told how far to trust the input the frame guesses;
+     * see `GGPOSync::prediction_confidence`.  The default ignores it.
+     */
+    fn resimulate_frame_with_confidence(&mut self, _confidence: u32) -> bool {
+        self.resimulate_frame()
+    }
+    // Like `GGPOSessionCallbacks::on_rollback`.
+    fn rolled_back(&mut self, _from_frame: Frame, _to_frame: Frame, _resimulated: u32) {}
+}
+
+/*
+ * How often, and how far, a session has had to roll back since it started.
+ * Only about the simulation; see `NetworkStats` for the link.
+ */
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct RollbackStats {
+    pub rollbacks: u32,
+    // Frames run again, across every rollback.
+    pub frames_resimulated: u32,
+    // The most frames any one rollback ran again.
+    pub max_distance: u32,
+    // Frames the game moved on by, not counting the ones it ran again.
+    pub frames_advanced: u32,
+}
+
+impl RollbackStats {
+    // Rollbacks per frame advanced: roughly how often a prediction turns out wrong.
+    pub fn mispredict_rate(&self) -> f32 {
+        if self.frames_advanced == 0 {
+            0.0
+        } else {
+            self.rollbacks as f32 / self.frames_advanced as f32
+        }
+    }
+}
+
+// Frames `AdaptivePrediction` watches before judging how its predictions went.
+pub const ADAPTIVE_PREDICTION_WINDOW: u32 = 60;
+// Mispredicting more often than this narrows the window...
+pub const ADAPTIVE_PREDICTION_SHRINK_RATE: f32 = 0.5;
+// ...and less often than this widens it back out.
+pub const ADAPTIVE_PREDICTION_RELAX_RATE: f32 = 0.1;
+// However erratic the input, the window doesn't get narrower than this.
+pub const MIN_ADAPTIVE_PREDICTION_FRAMES: FrameNum = 2;
+
+/*
+ * Narrows the prediction window while a peer's input keeps defying
+ * prediction, so we stop running far ahead only to roll all the way back.  A
+ * narrower window means waiting on the peer sooner: more latency, fewer and
+ * shorter rollbacks.  Each `ADAPTIVE_PREDICTION_WINDOW` frames the window
+ * halves if most frames were mispredicted, and doubles back towards the
+ * configured size once they mostly aren't.
+ */
+#[derive(Debug, Default, Copy, Clone)]
+pub struct AdaptivePrediction {
+    max_frames: FrameNum,
+    frames: FrameNum,
+    window_start: RollbackStats,
+    mispredict_rate: f32,
+}
+
+impl AdaptivePrediction {
+    pub fn new(max_frames: FrameNum) -> Self {
+        AdaptivePrediction {
+            max_frames,
+            frames: max_frames,
+            ..Default::default()
+        }
+    }
+
+    pub fn frames(&self) -> FrameNum {
+        self.frames
+    }
+
+    // Over the last full window.
+    pub fn mispredict_rate(&self) -> f32 {
+        self.mispredict_rate
+    }
+
+    // Returns the new window if the stats so far change it.
+    pub fn update(&mut self, stats: &RollbackStats) -> Option<FrameNum> {
+        let frames_advanced = stats.frames_advanced - self.window_start.frames_advanced;
+        if frames_advanced < ADAPTIVE_PREDICTION_WINDOW {
+            return None;
+        }
+        self.mispredict_rate =
+            (stats.rollbacks - self.window_start.rollbacks) as f32 / frames_advanced as f32;
+        self.window_start = *stats;
+
+        let frames = if self.mispredict_rate > ADAPTIVE_PREDICTION_SHRINK_RATE {
+            (self.frames / 2).max(MIN_ADAPTIVE_PREDICTION_FRAMES.min(self.max_frames))
+        } else if self.mispredict_rate < ADAPTIVE_PREDICTION_RELAX_RATE {
+            (self.frames * 2).min(self.max_frames)
+        } else {
+            self.frames
+        };
+        if frames == self.frames {
+            return None;
+        }
+        self.frames = frames;
+        Some(frames)
+    }
+}
+
+/*
+ * Roughly what a session is holding on to.  Input queues and saved states are
+ * rings, so they stop growing once full; unacked input grows while a peer or
+ * spectator falls behind.
+ */
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryUsage {
+    // Frames of input across every input queue.
+    pub input_frames: usize,
+    pub saved_states: usize,
+    pub saved_state_bytes: usize,
+    // Frames of input sent to peers and spectators that they haven't acked.
+    pub unacked_input_frames: usize,
+    // The earliest frame any of the above still holds input for.
+    pub oldest_frame: Option<Frame>,
+}
+
+impl MemoryUsage {
+    // An estimate of the bytes behind all of it.
+    pub fn bytes(&self) -> usize {
+        (self.input_frames + self.unacked_input_frames) * core::mem::size_of::<GameInput>()
+            + self.saved_state_bytes
+    }
+
+    // Counts `frame` toward `oldest_frame`.
+    pub fn holds(&mut self, frame: Frame) {
+        self.oldest_frame = Some(self.oldest_frame.map_or(frame, |oldest| oldest.min(frame)));
+    }
+}
+
+/*
+ * What to do with local input when its queue is full, which only happens if
+ * frames stop being confirmed for longer than the queue is long.  Remote input
+ * can't be held back, so a full remote queue is always an error.
+ */
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum QueueOverflow {
+    // Turn the input away, as if the prediction barrier had been hit.
+    #[default]
+    Block,
+    // Fail with `SyncError::InputQueueFull`.
+    Fail,
+}
+
+/*
+ * Which saved states get a checksum, for peers to compare.  Checksumming a
+ * big state every frame costs; checksumming fewer finds a desync later.
+ * Off-cadence frames get no checksum even if the game supplied one, so peers
+ * on the same cadence compare the same frames.
+ */
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ChecksumCadence {
+    // Every frame, as it's saved.
+    #[default]
+    EveryFrame,
+    // Frames that are a multiple of this, as they're saved.
+    Every(FrameNum),
+    // Every frame, but only once it's confirmed, rather than each time it's
+    // saved again after a rollback.
+    Confirmed,
+}
+
+impl ChecksumCadence {
+    // Whether `frame` gets a checksum when it's saved.
+    pub fn on_save(self, frame: FrameNum) -> bool {
+        match self {
+            ChecksumCadence::EveryFrame => true,
+            ChecksumCadence::Every(interval) => frame.is_multiple_of(interval.max(1)),
+            ChecksumCadence::Confirmed => false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Config<T: RollbackCallbacks> {
+    pub callbacks: Option<Arc<Mutex<T>>>,
+    pub num_prediction_frames: FrameNum,
+    pub num_players: usize,
+    pub input_size: usize,
+    // How many saved states to keep around for rollbacks. `None` keeps enough
+    // for a full prediction window, plus a margin.
+    pub saved_state_depth: Option<usize>,
+    /*
+     * Save a state only on every frame that's a multiple of this, and roll
+     * back by resetting to the last one and replaying input from there.  1
+     * saves every frame.  See `GGPOSync::adjust_simulation`.
+     */
+    pub checkpoint_interval: FrameNum,
+    pub checksum_cadence: ChecksumCadence,
+    // How many frames each input queue holds. `None` uses the default.
+    pub input_queue_length: Option<usize>,
+    pub queue_overflow: QueueOverflow,
+    // How to guess remote input.  `None` repeats the last input.
+    pub prediction: Option<Arc<dyn PredictionStrategy>>,
+    // Narrow the prediction window while predictions keep failing; see `AdaptivePrediction`.
+    pub adaptive_prediction: bool,
+    /*
+     * The frame the session starts on, and the first one there's input for.
+     * 0 unless joining a match already under way at that frame.
+     */
+    pub start_frame: FrameNum,
+}
+
+// By hand, as a derive would want `T: Clone` for the `Arc`.
+impl<T: RollbackCallbacks> Clone for Config<T> {
+    fn clone(&self) -> Self {
+        Config {
+            callbacks: self.callbacks.clone(),
+            num_prediction_frames: self.num_prediction_frames,
+            num_players: self.num_players,
+            input_size: self.input_size,
+            saved_state_depth: self.saved_state_depth,
+            checkpoint_interval: self.checkpoint_interval,
+            checksum_cadence: self.checksum_cadence,
+            input_queue_length: self.input_queue_length,
+            queue_overflow: self.queue_overflow,
+            prediction: self.prediction.clone(),
+            adaptive_prediction: self.adaptive_prediction,
+            start_frame: self.start_frame,
+        }
+    }
+}
+
+impl<T: RollbackCallbacks> Default for Config<T> {
+    fn default() -> Self {
+        Config {
+            callbacks: None,
+            num_prediction_frames: 0,
+            num_players: 0,
+            input_size: 0,
+            saved_state_depth: None,
+            checkpoint_interval: 1,
+            checksum_cadence: ChecksumCadence::default(),
+            input_queue_length: None,
+            queue_overflow: QueueOverflow::Block,
+            prediction: None,
+            adaptive_prediction: false,
+            start_frame: 0,
+        }
+    }
+}
+
+impl<T: RollbackCallbacks> Config<T> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+    pub fn init(
+        &mut self,
+        callbacks: Arc<Mutex<T>>,
+        num_prediction_frames: FrameNum,
+        num_players: usize,
+        input_size: usize,
+    ) {
+        self.callbacks = Some(callbacks.clone());
+        self.num_players = num_players;
+        self.input_size = input_size;
+        self.num_prediction_frames = num_prediction_frames;
+    }
+
+    // With checkpoints, enough of them that one is always at or before the window.
+    pub fn saved_state_depth(&self) -> usize {
+        let prediction_frames = self.num_prediction_frames as usize;
+        self.saved_state_depth
+            .unwrap_or(match self.checkpoint_interval() as usize {
+                1 => prediction_frames + 2,
+                interval => (prediction_frames + 1) / interval + 2,
+            })
+    }
+
+    pub fn checkpoint_interval(&self) -> FrameNum {
+        self.checkpoint_interval.max(1)
+    }
+
+    // The checkpoint a rollback to `frame` starts from.  They're counted from the start frame.
+    pub fn checkpoint_before(&self, frame: FrameNum) -> FrameNum {
+        frame - frame.saturating_sub(self.start_frame) % self.checkpoint_interval()
+    }
+
+    pub fn input_queue_length(&self) -> usize {
+        self.input_queue_length
+            .unwrap_or(DEFAULT_INPUT_QUEUE_LENGTH)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum Type {
+    ConfirmedInput,
+    Other,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Event {
+    pub input_type: Type,
+    pub input: GameInput,
+}
+
+impl Event {
+    pub const fn new() -> Self {
+        Self {
+            input_type: Type::Other,
+            input: GameInput::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SavedFrame {
+    size: usize,
+    frame: Frame,
+    checksum: Option<u32>,
+    buffer: Bytes,
+}
+
+impl SavedFrame {
+    const fn new() -> Self {
+        SavedFrame {
+            size: 0,
+            frame: NULL_FRAME,
+            checksum: None,
+            buffer: Bytes::new(),
+        }
+    }
+
+    pub fn frame(&self) -> Frame {
+        self.frame
+    }
+
+    pub fn checksum(&self) -> Option<u32> {
+        self.checksum
+    }
+
+    pub fn buffer(&self) -> &Bytes {
+        &self.buffer
+    }
+}
+
+/*
+ * Input committed for a reconnected remote player: every frame from `from` up
+ * to `until` gets `input`, the input we had already simulated (or predicted)
+ * it with.
+ */
+#[derive(Debug, Default, Copy, Clone)]
+struct FrozenInput {
+    from: Frame,
+    until: Frame,
+    input: GameInput,
+}
+
+impl FrozenInput {
+    fn covers(&self, frame: Frame) -> bool {
+        frame >= self.from && frame < self.until
+    }
+}
+
+// A ring of saved states. Once full, each save evicts the oldest one.
+#[derive(Debug, Clone)]
+struct SavedFrames {
+    frames: Vec<SavedFrame>,
+    head: usize,
+}
+
+impl SavedFrames {
+    fn with_depth(depth: usize) -> Self {
+        assert!(depth > 0);
+        SavedFrames {
+            frames: vec![SavedFrame::new(); depth],
+            head: 0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct GGPOSync<T: RollbackCallbacks> {
+    callbacks: Option<Arc<Mutex<T>>>,
+    // Waiting to take over from `callbacks`; see `set_callbacks`.
+    pending_callbacks: Option<Arc<Mutex<T>>>,
+    saved_state: SavedFrames,
+    config: Option<Config<T>>,
+
+    rolling_back: bool,
+    last_confirmed_frame: Frame,
+    frame_count: FrameNum,
+    max_prediction_frames: FrameNum,
+    adaptive_prediction: Option<AdaptivePrediction>,
+    stats: RollbackStats,
+
+    input_queues: Vec<InputQueue>,
+    frozen_inputs: Vec<FrozenInput>,
+
+    // event_queue: ArrayDeque<[Event; 32]>,
+    event_queue: VecDeque<Event>,
+    local_connect_status: Vec<Arc<Mutex<ConnectStatus>>>,
+}
+
+impl<T: RollbackCallbacks> Default for GGPOSync<T> {
+    fn default() -> GGPOSync<T> {
+        GGPOSync {
+            local_connect_status: Vec::new(),
+            frame_count: 0,
+            last_confirmed_frame: NULL_FRAME,
+            max_prediction_frames: 0,
+            adaptive_prediction: None,
+            stats: RollbackStats::default(),
+            saved_state: SavedFrames::with_depth(GGPO_MAX_PREDICTION_FRAMES as usize + 2),
+            callbacks: None,
+            pending_callbacks: None,
+            config: None,
+            rolling_back: false,
+            input_queues: Vec::new(),
+            frozen_inputs: Vec::new(),
+            // event_queue: ArrayDeque::new(),
+            event_queue: VecDeque::with_capacity(32),
+        }
+    }
+}
+
+/*
+ * Saved states are plain `Bytes`, so they go with the ring either way.  One a
+ * session never freed with `free_saved_states` means it was dropped without
+ * being shut down, which debug builds warn about, counting what was left.
+ */
+impl<T: RollbackCallbacks> Drop for GGPOSync<T> {
+    fn drop(&mut self) {
+        let unfreed = self.free_saved_states();
+        if cfg!(debug_assertions) && unfreed.saved_states > 0 {
+            warn!(
+                "dropped with {} saved states ({} bytes) never freed.\n",
+                unfreed.saved_states, unfreed.saved_state_bytes
+            );
+        }
+    }
+}
+
+impl<T: RollbackCallbacks> GGPOSync<T> {
+    pub fn new(connect_status: &[Arc<Mutex<ConnectStatus>>]) -> Self {
+        let mut sync = GGPOSync::default();
+        sync.local_connect_status = Vec::from(connect_status);
+        sync
+    }
+
+    pub fn init(&mut self, config: Config<T>) -> Result<(), SyncError> {
+        self.max_prediction_frames = config.num_prediction_frames;
+        self.saved_state = SavedFrames::with_depth(config.saved_state_depth());
+        self.config = Some(config.clone());
+        self.callbacks = Some(config.callbacks.ok_or(SyncError::CallbacksNone)?.clone());
+        self.frame_count = config.start_frame;
+        self.rolling_back = false;
+        self.stats = RollbackStats::default();
+        self.set_adaptive_prediction(config.adaptive_prediction);
+
+        self.create_queues()?;
+        Ok(())
+    }
+
+    pub fn create_queues(&mut self) -> Result<bool, SyncError> {
+        let config = self.config.as_ref().ok_or(SyncError::ConfigNone)?;
+        self.input_queues = (0..config.num_players)
+            .map(|i| {
+                let mut queue =
+                    InputQueue::with_length(i, config.input_size, config.input_queue_length());
+                queue.set_start_frame(config.start_frame);
+                if let Some(strategy) = &config.prediction {
+                    queue.set_prediction_strategy(strategy.clone());
+                }
+                queue
+            })
+            .collect();
+        self.frozen_inputs = vec![FrozenInput::default(); config.num_players];
+
+        Ok(true)
+    }
+
+    pub fn set_last_confirmed_frame(&mut self, frame: Frame) -> Result<(), SyncError> {
+        self.last_confirmed_frame = frame;
+        let config = self.config.as_ref().ok_or(SyncError::ConfigNone)?;
+        /*
+         * Nothing to discard until at least one frame before the confirmed one
+         * exists; `prev()` is null for both a null frame and frame 0.  With
+         * checkpoints, a rollback past the confirmed frame replays input from
+         * the checkpoint before it, so that input has to stay.
+         */
+        let keep_from = self
+            .last_confirmed_frame
+            .next()
+            .number()
+            .map_or(config.start_frame, |frame| config.checkpoint_before(frame));
+        let discard_to = self
+            .last_confirmed_frame
+            .prev()
+            .min(Frame::new(keep_from).prev());
+        if let Some(discard_to) = discard_to.number() {
+            for i in 0..config.num_players {
+                self.input_queues[i].discard_confirmed_frames(discard_to);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn add_local_input(
+        &mut self,
+        queue: u32,
+        input: &mut GameInput,
+    ) -> Result<bool, SyncError> {
+        // A null confirmed frame sits one before the start frame.
+        let start_frame = self.start_frame();
+        let last_confirmed = if self.last_confirmed_frame.is_null() {
+            start_frame as i32 - 1
+        } else {
+            self.last_confirmed_frame.as_i32()
+        };
+        let frames_behind = self.frame_count as i32 - last_confirmed;
+
+        let prediction_frames = self.prediction_frames();
+        if self.frame_count - start_frame >= prediction_frames
+            && frames_behind >= prediction_frames as i32
+        {
+            info!("Rejecting input from emulator: reached prediction barrier.\n");
+            return Ok(false);
+        }
+
+        /*
+         * With a frame delay the input lands `delay` frames after the current
+         * one, so that's the frame that has to still be open.  A larger delay
+         * leaves more room before a frame gets confirmed; lowering it is
+         * handled by the queue, which tosses inputs it no longer has room for.
+         * Once every peer has confirmed a frame its inputs are history, and
+         * changing them now would desync us.
+         */
+        let delay = self.input_queues[queue as usize].frame_delay() as FrameNum;
+        let target = Frame::new(self.frame_count + delay);
+        if target <= self.last_confirmed_frame {
+            info!(
+                "Rejecting input for frame {}: already confirmed up to {}.\n",
+                target, self.last_confirmed_frame
+            );
+            return Err(SyncError::InputDropped(target));
+        }
+
+        if self.frame_count == start_frame {
+            self.save_current_frame()?;
+        }
+
+        info!(
+            "Sending undelayed local frame {} to queue {}.\n",
+            self.frame_count, queue
+        );
+
+        input.frame = Frame::new(self.frame_count);
+
+        let last_added = self.input_queues[queue as usize].get_last_confirmed_frame();
+        if !self.input_queues[queue as usize].add_input(*input) {
+            info!(
+                "Rejecting input from emulator: input queue {} is full.\n",
+                queue
+            );
+            return match self
+                .config
+                .as_ref()
+                .ok_or(SyncError::ConfigNone)?
+                .queue_overflow
+            {
+                QueueOverflow::Block => Ok(false),
+                QueueOverflow::Fail => Err(SyncError::InputQueueFull(queue)),
+            };
+        }
+
+        /*
+         * Tell the caller which frame the input actually went in at, so peers
+         * get it there too: `delay` frames on, or nowhere (`NULL_FRAME`) if
+         * the delay just dropped and the queue tossed it.
+         */
+        let landed = self.input_queues[queue as usize].get_last_confirmed_frame();
+        input.frame = if landed != last_added {
+            landed
+        } else {
+            NULL_FRAME
+        };
+
+        Ok(true)
+    }
+
+    /*
+     * A local player's input for `frame`, as it went into their queue.  Used
+     * to send peers the padding a raised frame delay leaves behind.
+     */
+    pub fn local_input(&self, queue: u32, frame: Frame) -> Option<GameInput> {
+        let mut input = GameInput::new();
+        if self.input_queues[queue as usize].get_confirmed_input(frame, &mut input) {
+            Some(input)
+        } else {
+            None
+        }
+    }
+
+    pub fn add_remote_input(&mut self, queue: u32, input: &GameInput) -> Result<(), SyncError> {
+        if !self.input_queues[queue as usize].add_input(*input) {
+            return Err(SyncError::InputQueueFull(queue));
+        }
+        Ok(())
+    }
+
+    // How many frames of input a queue is holding, as a measure of pressure.
+    pub fn input_queue_len(&self, queue: usize) -> usize {
+        self.input_queues[queue].len()
+    }
+
+    /*
+     * Commits a remote player's input for every frame before `until` and
+     * restarts their queue from there, for when they reconnect.  The committed
+     * frames keep the input we already ran them with: nothing at all if we had
+     * disconnected the player, otherwise the input we've been predicting.
+     * They're served from here from now on and never rolled back.
+     */
+    pub fn freeze_remote_input(&mut self, queue: u32, until: Frame, disconnected: bool) {
+        let input_queue = &mut self.input_queues[queue as usize];
+        let from = input_queue.get_last_confirmed_frame().next();
+        if from >= until {
+            // Their real input already reaches the resume point.
+            return;
+        }
+        if let Some(last) = until.prev().number() {
+            let input = input_queue.resume_after(last, disconnected);
+            self.frozen_inputs[queue as usize] = FrozenInput { from, until, input };
+        }
+    }
+
+    /*
+     * Fills the frames missing before `frame` in a remote player's input, for
+     * when a reconnected peer's input picks up later than the frame we expected
+     * next.  The missing frames get the input we've been predicting for them.
+     * Frames we haven't reached yet are frozen rather than queued, since the
+     * peer may be further ahead than the queue is long.
+     */
+    pub fn fill_remote_input_gap(&mut self, queue: u32, frame: Frame) -> Result<(), SyncError> {
+        if frame > Frame::new(self.frame_count) {
+            self.freeze_remote_input(queue, frame, false);
+        } else if !self.input_queues[queue as usize].pad_to(frame.prev()) {
+            return Err(SyncError::InputQueueFull(queue));
+        }
+        Ok(())
+    }
+
+    pub fn save_current_frame(&mut self) -> Result<(), SyncError> {
+        // TODO: zstd compression for frame buffer
+        /*
+         * See StateCompress for the real save feature implemented by FinalBurn.
+         * Write everything into the head, then advance the head pointer.
+         */
+
+        let saved = self
+            .callbacks
+            .as_ref()
+            .ok_or(SyncError::CallbacksNone)?
+            .lock()
+            .save_state(Frame::new(self.frame_count))
+            .map_err(|e| SyncError::SaveGameState(e.to_string()))?;
+
+        let cadence = self
+            .config
+            .as_ref()
+            .ok_or(SyncError::ConfigNone)?
+            .checksum_cadence;
+        // Overwriting the slot drops whatever state it held before.
+        let state: &mut SavedFrame = &mut self.saved_state.frames[self.saved_state.head];
+        state.frame = Frame::new(self.frame_count);
+        state.size = saved.data.len();
+        state.checksum = match cadence {
+            ChecksumCadence::Confirmed => saved.checksum,
+            _ if cadence.on_save(self.frame_count) => Some(
+                saved
+                    .checksum
+                    .unwrap_or_else(|| checksum::fletcher32(&saved.data)),
+            ),
+            _ => None,
+        };
+        state.buffer = saved.data;
+        match state.checksum {
+            Some(checksum) => info!(
+                "=== Saved frame info {} (size: {}  checksum: {:#x}).\n",
+                state.frame, state.size, checksum
+            ),
+            None => info!(
+                "=== Saved frame info {} (size: {}  checksum: None).\n",
+                state.frame, state.size
+            ),
+        }
+
+        self.saved_state.head = (self.saved_state.head + 1) % self.saved_state.frames.len();
+        Ok(())
+    }
+
+    pub fn get_last_saved_frame(&self) -> &SavedFrame {
+        let mut i: isize = self.saved_state.head as isize - 1;
+        if i < 0 {
+            i = self.saved_state.frames.len() as isize - 1;
+        }
+        &self.saved_state.frames[i as usize]
+    }
+
+    pub fn saved_state_depth(&self) -> usize {
+        self.saved_state.frames.len()
+    }
+
+    pub fn find_saved_frame_index(&self, frame: Frame) -> Option<usize> {
+        if frame.is_null() {
+            return None;
+        }
+        self.saved_state
+            .frames
+            .iter()
+            .position(|saved| saved.frame == frame)
+    }
+
+    /*
+     * The checksum saved for `frame`, if it's still in the ring and its
+     * cadence gave it one.  With `ChecksumCadence::Confirmed` it's worked out
+     * here, so only ask once the frame is confirmed.
+     */
+    pub fn saved_checksum(&self, frame: Frame) -> Option<u32> {
+        let index = self.find_saved_frame_index(frame)?;
+        self.checksum_of(&self.saved_state.frames[index])
+    }
+
+    // The state saved for `frame`, if it's still in the ring.
+    pub fn saved_state(&self, frame: Frame) -> Option<SavedState> {
+        let index = self.find_saved_frame_index(frame)?;
+        let saved = &self.saved_state.frames[index];
+        Some(SavedState {
+            data: saved.buffer.clone(),
+            checksum: self.checksum_of(saved),
+        })
+    }
+
+    /*
+     * Starts over from `frame` with the game put back in `state`, as though
+     * the session had been configured to start there.  Only before any input
+     * has gone in.
+     */
+    pub fn restart_at(&mut self, frame: FrameNum, state: &Bytes) -> Result<(), SyncError> {
+        self.config
+            .as_mut()
+            .ok_or(SyncError::ConfigNone)?
+            .start_frame = frame;
+        self.frame_count = frame;
+        for queue in self.input_queues.iter_mut() {
+            queue.set_start_frame(frame);
+        }
+        self.callbacks
+            .as_ref()
+            .ok_or(SyncError::CallbacksNone)?
+            .lock()
+            .load_state(state, state.len());
+        Ok(())
+    }
+
+    fn checksum_of(&self, saved: &SavedFrame) -> Option<u32> {
+        let cadence = self.config.as_ref()?.checksum_cadence;
+        match saved.checksum {
+            None if cadence == ChecksumCadence::Confirmed => {
+                Some(checksum::fletcher32(&saved.buffer))
+            }
+            checksum => checksum,
+        }
+    }
+
+    pub fn set_input_size(&mut self, queue: usize, input_size: usize) {
+        self.input_queues[queue].set_input_size(input_size);
+    }
+
+    pub fn input_size(&self, queue: usize) -> usize {
+        self.input_queues[queue].input_size()
+    }
+
+    // By queue, so `input_sizes()[i]` bytes of row `i` from `synchronize_inputs` are input.
+    pub fn input_sizes(&self) -> Vec<usize> {
+        self.input_queues
+            .iter()
+            .map(InputQueue::input_size)
+            .collect()
+    }
+
+    pub fn set_frame_delay(&mut self, queue: usize, delay: usize) {
+        self.input_queues[queue].set_frame_delay(delay);
+    }
+
+    pub fn set_prediction_strategy(&mut self, strategy: Arc<dyn PredictionStrategy>) {
+        for queue in self.input_queues.iter_mut() {
+            queue.set_prediction_strategy(strategy.clone());
+        }
+        if let Some(config) = self.config.as_mut() {
+            config.prediction = Some(strategy);
+        }
+    }
+
+    // Turning it off goes straight back to the configured window.
+    pub fn set_adaptive_prediction(&mut self, enabled: bool) {
+        self.adaptive_prediction = if enabled {
+            Some(AdaptivePrediction::new(self.max_prediction_frames))
+        } else {
+            None
+        };
+    }
+
+    pub fn adaptive_prediction(&self) -> Option<&AdaptivePrediction> {
+        self.adaptive_prediction.as_ref()
+    }
+
+    // How far ahead of the last confirmed frame we'll run right now.
+    pub fn prediction_frames(&self) -> FrameNum {
+        self.adaptive_prediction
+            .as_ref()
+            .map_or(self.max_prediction_frames, AdaptivePrediction::frames)
+    }
+
+    pub fn reset_prediction(&mut self, frame_number: FrameNum) -> Result<(), SyncError> {
+        for i in 0..self
+            .config
+            .as_ref()
+            .ok_or(SyncError::ConfigNone)?
+            .num_players
+        {
+            self.input_queues[i].reset_prediction(frame_number);
+        }
+        Ok(())
+    }
+
+    pub fn get_event(&mut self, event: &mut Event) -> bool {
+        if let Some(e) = self.event_queue.pop_front() {
+            *event = e;
+            return true;
+        }
+        false
+    }
+
+    pub fn start_frame(&self) -> FrameNum {
+        self.config.as_ref().map_or(0, |config| config.start_frame)
+    }
+
+    pub fn get_frame_count(&self) -> FrameNum {
+        self.frame_count
+    }
+
+    pub fn last_confirmed_frame(&self) -> Frame {
+        self.last_confirmed_frame
+    }
+
+    // The last frame a queue has input for, real or, for a local player, delayed.
+    pub fn last_input_frame(&self, queue: usize) -> Frame {
+        self.input_queues[queue].get_last_confirmed_frame()
+    }
+
+    // How many predictions in a row of a queue's input real input has borne out.
+    pub fn prediction_streak(&self, queue: usize) -> u32 {
+        self.input_queues[queue].prediction_streak()
+    }
+
+    /*
+     * How far to trust the input guessed for `frame`: the shortest streak of
+     * right predictions among the players we're missing input for, and
+     * `u32::MAX` if we're missing nobody's.  Disconnected players don't count;
+     * there's nothing to guess.
+     */
+    pub fn prediction_confidence(&self, frame: Frame) -> u32 {
+        self.input_queues
+            .iter()
+            .enumerate()
+            .filter(|(i, queue)| {
+                let status = self.local_connect_status.get(*i);
+                queue.get_last_confirmed_frame() < frame
+                    && !matches!(status, Some(status) if status.lock().disconnected)
+            })
+            .map(|(_, queue)| queue.prediction_streak())
+            .min()
+            .unwrap_or(u32::MAX)
+    }
+
+    pub fn first_incorrect_frame(&self, queue: usize) -> Frame {
+        self.input_queues[queue].get_first_incorrect_frame()
+    }
+
+    pub fn rollback_stats(&self) -> RollbackStats {
+        self.stats
+    }
+
+    // What the input queues and saved states hold; the unacked input is left at 0.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::default();
+        for queue in self.input_queues.iter() {
+            usage.input_frames += queue.len();
+            if let Some(frame) = queue.oldest_frame() {
+                usage.holds(frame);
+            }
+        }
+        for saved in self.saved_state.frames.iter() {
+            if !saved.frame.is_null() {
+                usage.saved_states += 1;
+                usage.saved_state_bytes += saved.buffer.len();
+            }
+        }
+        usage
+    }
+
+    /*
+     * Releases every saved state, for a session shutting down, and says how
+     * many there were.  Nothing can be rolled back afterwards.
+     */
+    pub fn free_saved_states(&mut self) -> MemoryUsage {
+        let mut freed = MemoryUsage::default();
+        for saved in self.saved_state.frames.iter_mut() {
+            if !saved.frame.is_null() {
+                freed.saved_states += 1;
+                freed.saved_state_bytes += saved.buffer.len();
+            }
+            *saved = SavedFrame::new();
+        }
+        freed
+    }
+
+    pub fn in_rollback(&self) -> bool {
+        self.rolling_back
+    }
+
+    /*
+     * Hands saving, loading and resimulating over to `callbacks`, for a game
+     * changing scenes without tearing down its session.  The old callbacks
+     * keep them until `swap_callbacks` finds a frame boundary nothing can roll
+     * back past: no rollback under way, every frame before the current one
+     * confirmed, and the current one the last saved.  The new callbacks then
+     * save that frame over again, so from there on they only ever load
+     * states they saved themselves.  Setting another before then replaces
+     * the one waiting.
+     */
+    pub fn set_callbacks(&mut self, callbacks: Arc<Mutex<T>>) {
+        self.pending_callbacks = Some(callbacks);
+    }
+
+    pub fn callbacks(&self) -> Option<Arc<Mutex<T>>> {
+        self.callbacks.clone()
+    }
+
+    pub fn callbacks_pending(&self) -> bool {
+        self.pending_callbacks.is_some()
+    }
+
+    // Swaps in the pending callbacks if it's safe to, giving the frame they took over at.
+    pub fn swap_callbacks(&mut self) -> Result<Option<Frame>, SyncError> {
+        if self.pending_callbacks.is_none() || self.rolling_back {
+            return Ok(None);
+        }
+        let frame = Frame::new(self.frame_count);
+        if self.frame_count > self.start_frame()
+            && self.last_confirmed_frame < Frame::new(self.frame_count - 1)
+        {
+            return Ok(None);
+        }
+        if self.get_last_saved_frame().frame != frame {
+            return Ok(None);
+        }
+
+        let callbacks = self.pending_callbacks.take();
+        if let Some(config) = self.config.as_mut() {
+            config.callbacks = callbacks.clone();
+        }
+        self.callbacks = callbacks;
+        // Back over the old callbacks' save of this frame.
+        let depth = self.saved_state.frames.len();
+        self.saved_state.head = (self.saved_state.head + depth - 1) % depth;
+        self.save_current_frame()?;
+        info!("new callbacks took over at frame {}.\n", frame);
+        Ok(Some(frame))
+    }
+
+    pub fn increment_frame(&mut self) -> Result<(), SyncError> {
+        self.frame_count += 1;
+        if !self.rolling_back {
+            self.stats.frames_advanced += 1;
+            if let Some(adaptive) = self.adaptive_prediction.as_mut() {
+                if let Some(frames) = adaptive.update(&self.stats) {
+                    info!(
+                        "prediction window now {} frames (mispredict rate {:.2}).\n",
+                        frames,
+                        adaptive.mispredict_rate()
+                    );
+                }
+            }
+        }
+        let config = self.config.as_ref().ok_or(SyncError::ConfigNone)?;
+        if config.checkpoint_before(self.frame_count) == self.frame_count {
+            self.save_current_frame()?;
+        }
+        Ok(())
+    }
+
+    pub fn get_confirmed_inputs(
+        &mut self,
+        values: &mut InputBuffer,
+        frame: Frame,
+    ) -> Result<i32, SyncError> {
+        let mut disconnect_flags = 0;
+        let num_players = self
+            .config
+            .as_ref()
+            .ok_or(SyncError::ConfigNone)?
+            .num_players;
+
+        assert!(values.len() >= num_players);
+        // TODO: When slice.fill is stabilized, lower directly to memset.
+        // values.fill([b'0'; GAMEINPUT_MAX_BYTES]);
+        *values = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+        if let Some(frame_value) = frame.number() {
+            for i in 0..num_players {
+                let mut input: GameInput = GameInput::new();
+                if Self::is_disconnected_at(&self.local_connect_status[i].lock(), frame_value) {
+                    disconnect_flags |= 1 << i;
+                    input.erase();
+                } else if self.frozen_inputs[i].covers(frame) {
+                    input = self.frozen_inputs[i].input;
+                } else {
+                    self.input_queues[i].get_confirmed_input(frame, &mut input);
+                }
+                // Each queue only holds its own player's input, so concatenate them in
+                // handle order.
+                values[i] = input.bits[0];
+            }
+        }
+
+        Ok(disconnect_flags)
+    }
+
+    pub fn synchronize_inputs(&mut self, values: &mut InputBuffer) -> Result<i32, SyncError> {
+        let mut disconnect_flags = 0;
+        let num_players = self
+            .config
+            .as_ref()
+            .ok_or(SyncError::ConfigNone)?
+            .num_players;
+
+        assert!(values.len() >= num_players);
+        // TODO: When slice.fill is stabilized, lower directly to memset.
+        // values.fill([b'0'; GAMEINPUT_MAX_BYTES]);
+        *values = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+
+        for i in 0..num_players {
+            let mut input: GameInput = GameInput::new();
+            if Self::is_disconnected_at(&self.local_connect_status[i].lock(), self.frame_count) {
+                disconnect_flags |= 1 << i;
+                input.erase();
+            } else if self.frozen_inputs[i].covers(Frame::new(self.frame_count)) {
+                input = self.frozen_inputs[i].input;
+            } else {
+                self.input_queues[i].get_input(self.frame_count, &mut input);
+            }
+            values[i] = input.bits[0];
+        }
+
+        Ok(disconnect_flags)
+    }
+
+    // A player counts as disconnected for every frame after the last one we
+    // received from them. A null last frame orders before every frame, so a
+    // player we never heard from is disconnected throughout.
+    fn is_disconnected_at(connect_status: &ConnectStatus, frame: FrameNum) -> bool {
+        connect_status.disconnected && Frame::new(frame) > connect_status.last_frame
+    }
+
+    /*
+     * Rolls back if any prediction turned out wrong.  Sessions call this once
+     * a poll, after every input that came in has been added, so a burst of
+     * late input is one rollback to the earliest frame any queue got wrong,
+     * not one per message.
+     */
+    pub fn check_simulation(&mut self) -> Result<(), SyncError> {
+        let mut seek_to: FrameNum = 0;
+        if !self.check_simulation_consistency(&mut seek_to)? {
+            self.adjust_simulation(seek_to)?;
+        }
+        Ok(())
+    }
+
+    pub fn check_simulation_consistency(
+        &mut self,
+        seek_to: &mut FrameNum,
+    ) -> Result<bool, SyncError> {
+        let mut first_incorrect: Frame = NULL_FRAME;
+
+        for i in 0..self
+            .config
+            .as_ref()
+            .ok_or(SyncError::ConfigNone)?
+            .num_players
+        {
+            let incorrect = self.input_queues[i].get_first_incorrect_frame();
+            if !incorrect.is_null() {
+                info!(
+                    "considering incorrect frame {} reported by queue {}.\n",
+                    incorrect, i
+                );
+                if first_incorrect.is_null() || incorrect < first_incorrect {
+                    first_incorrect = incorrect;
+                }
+            }
+        }
+
+        if let Some(f_cor) = first_incorrect.number() {
+            *seek_to = f_cor;
+        } else {
+            info!("Prediction ok. Proceeding.\n");
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    /*
+     * Goes back to `seek_to` and runs every frame since again.  With a
+     * checkpoint interval, only the checkpoint at or before `seek_to` was
+     * saved, so the game is reset to that and the frames up to `seek_to`
+     * replayed as well: more frames to run, fewer states to keep.
+     */
+    pub fn adjust_simulation(&mut self, seek_to: FrameNum) -> Result<(), SyncError> {
+        let seek_to = self
+            .config
+            .as_ref()
+            .ok_or(SyncError::ConfigNone)?
+            .checkpoint_before(seek_to);
+        let framecount = self.frame_count;
+        let count = self.frame_count - seek_to;
+        let _span = ggpo_span!(
+            "rollback",
+            from_frame = framecount,
+            to_frame = seek_to,
+            frames = count
+        );
+
+        info!("Catching up\n");
+        /*
+         * Flush our input queue and load the last frame.  Nothing's been
+         * rolled back if there's no state to load, so the session isn't left
+         * looking mid-rollback.
+         */
+        self.load_frame(Frame::new(seek_to))?;
+        assert!(self.frame_count == seek_to);
+
+        self.rolling_back = true;
+        let resimulated = self.resimulate(count);
+        self.rolling_back = false;
+        let callbacks = resimulated?;
+        assert!(self.frame_count == framecount);
+
+        self.stats.rollbacks += 1;
+        self.stats.frames_resimulated += count;
+        self.stats.max_distance = self.stats.max_distance.max(count);
+        callbacks
+            .lock()
+            .rolled_back(Frame::new(framecount), Frame::new(seek_to), count);
+
+        info!("---\n");
+        Ok(())
+    }
+
+    /*
+     * Advance frame by frame (stuffing notifications back to the master),
+     * handing back the callbacks it ran them on.
+     */
+    fn resimulate(&mut self, count: FrameNum) -> Result<Arc<Mutex<T>>, SyncError> {
+        self.reset_prediction(self.frame_count)?;
+        let callbacks = self
+            .callbacks
+            .as_ref()
+            .ok_or(SyncError::CallbacksNone)?
+            .clone();
+        for _i in 0..count {
+            let confidence = self.prediction_confidence(Frame::new(self.frame_count));
+            callbacks
+                .lock()
+                .resimulate_frame_with_confidence(confidence);
+            /*
+             * The game can't reach back in to end the frame while we're
+             * rolling back, so end it here, as `increment_frame` would.
+             */
+            self.increment_frame()?;
+        }
+        Ok(callbacks)
+    }
+
+    pub fn load_frame(&mut self, frame: Frame) -> Result<(), SyncError> {
+        // find the frame in question
+        if frame == Frame::new(self.frame_count) {
+            info!("Skipping NOP.\n");
+            return Ok(());
+        }
+
+        // Move the head pointer back and load it up. Frames that have been
+        // evicted from the ring can't be rolled back to.
+        self.saved_state.head = self
+            .find_saved_frame_index(frame)
+            .ok_or(SyncError::FrameNotSaved(frame))?;
+        let state: &mut SavedFrame = &mut self.saved_state.frames[self.saved_state.head];
+
+        match state.checksum {
+            Some(checksum) => info!(
+                "=== Loading frame info {} (size: {}  checksum: {:#X}).\n",
+                state.frame, state.size, checksum
+            ),
+            None => info!(
+                "=== Loading frame info {} (size: {}  checksum: None).\n",
+                state.frame, state.size,
+            ),
+        }
+
+        // TODO: Obviously these serve the same purpose, but still testing the use of the `bytes` crate
+        assert!(state.buffer.len() > 0 && state.size > 0);
+
+        let checkpoints = self
+            .config
+            .as_ref()
+            .ok_or(SyncError::ConfigNone)?
+            .checkpoint_interval()
+            > 1;
+        let mut callbacks = self
+            .callbacks
+            .as_ref()
+            .ok_or(SyncError::CallbacksNone)?
+            .lock();
+        if checkpoints {
+            callbacks.reset_state(frame, &state.buffer, state.size);
+        } else {
+            callbacks.load_state(&state.buffer, state.size);
+        }
+        drop(callbacks);
+
+        // Reset framecount and the head of the state ring-buffer to point in
+        // advance of the current frame (as if we had just finished executing it).
+        self.frame_count = state.frame.number().unwrap_or(self.frame_count);
+        self.saved_state.head = (self.saved_state.head + 1) % self.saved_state.frames.len();
+        Ok(())
+    }
+}
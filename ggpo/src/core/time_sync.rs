@@ -1,6 +1,6 @@
-use crate::game_input::{FrameNum, GameInput};
+use crate::core::game_input::{FrameNum, GameInput};
+use core::cmp::min;
 use log::{error, info};
-use std::cmp::min;
 
 const FRAME_WINDOW_SIZE: usize = 40;
 const MIN_UNIQUE_FRAMES: usize = 10;
@@ -32,7 +32,7 @@ impl TimeSync {
     pub fn advance_frame(&mut self, input: &GameInput, advantage: i32, r_advantage: i32) {
         let _sleep_time: i32 = 0;
         // Remember the last frame and frame advantage
-        match input.frame {
+        match input.frame.number() {
             Some(frame) => {
                 self.last_inputs[frame as usize % MIN_UNIQUE_FRAMES] = input.clone();
                 self.local[frame as usize % FRAME_WINDOW_SIZE] = advantage;
@@ -100,3 +100,70 @@ impl TimeSync {
         return min(sleep_frames, MAX_FRAME_ADVANTAGE as FrameNum);
     }
 }
+
+// The most frame delay `AutoFrameDelay` will pick, however slow the link.
+pub const MAX_AUTO_FRAME_DELAY: usize = 6;
+// How far past the halfway point between two delays, in hundredths of a
+// frame, the one-way trip has to go before the delay follows it.
+pub const AUTO_FRAME_DELAY_HYSTERESIS: u128 = 25;
+const FRAMES_PER_SECOND: u128 = 60;
+
+// Frames that pass while a packet makes half of a `round_trip_time` ms round trip, rounded up.
+pub fn one_way_frames(round_trip_time: u128) -> usize {
+    (round_trip_time * FRAMES_PER_SECOND).div_ceil(2000) as usize
+}
+
+/*
+ * Picks a frame delay that covers the one-way trip to the slowest peer, so
+ * its input tends to arrive before we'd have to predict it.  The delay only
+ * moves once the trip is clearly into another frame's worth, so a link
+ * sitting near a boundary doesn't keep changing it.
+ */
+#[derive(Debug, Default, Copy, Clone)]
+pub struct AutoFrameDelay {
+    delay: usize,
+}
+
+impl AutoFrameDelay {
+    pub const fn new(delay: usize) -> Self {
+        AutoFrameDelay { delay }
+    }
+
+    pub fn delay(&self) -> usize {
+        self.delay
+    }
+
+    // Half of a `round_trip_time` ms round trip, in hundredths of a frame.
+    fn one_way(round_trip_time: u128) -> u128 {
+        round_trip_time * FRAMES_PER_SECOND * 100 / 2000
+    }
+
+    // The delay a round trip earns, to the nearest frame, with no memory of the last one.
+    pub fn frames_for(round_trip_time: u128) -> usize {
+        let frames = (Self::one_way(round_trip_time) + 50) / 100;
+        min(frames, MAX_AUTO_FRAME_DELAY as u128) as usize
+    }
+
+    // Returns the new delay if this round trip changes it.
+    pub fn update(&mut self, round_trip_time: u128) -> Option<usize> {
+        let target = Self::frames_for(round_trip_time);
+        let one_way = Self::one_way(round_trip_time);
+        let current = self.delay as u128 * 100;
+        let clear_of_boundary = if target > self.delay {
+            one_way >= current + 50 + AUTO_FRAME_DELAY_HYSTERESIS
+        } else if target < self.delay {
+            one_way + 50 + AUTO_FRAME_DELAY_HYSTERESIS <= current
+        } else {
+            false
+        };
+        if !clear_of_boundary {
+            return None;
+        }
+        info!(
+            "round trip of {} ms moves the frame delay from {} to {}.\n",
+            round_trip_time, self.delay, target
+        );
+        self.delay = target;
+        Some(target)
+    }
+}
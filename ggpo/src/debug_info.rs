@@ -0,0 +1,97 @@
+/*
+ * A snapshot of a session's insides, for bug reports.  Nothing here changes
+ * the session; it's what a maintainer wants to see next to a desync report:
+ * where the simulation is, how far each player's input has got, what's still
+ * queued, and how each link is doing.  `Display` lays it out as plain text.
+ */
+
+use crate::{
+    game_input::{Frame, FrameNum},
+    player::{PlayerHandle, PlayerType},
+    sync::RollbackStats,
+};
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub struct PlayerDebugInfo {
+    pub handle: PlayerHandle,
+    pub player_type: PlayerType,
+    // The last frame we have real input for.
+    pub last_input_frame: Frame,
+    // Frames since then that have run on a prediction.
+    pub predicted_frames: FrameNum,
+    // The earliest prediction found wrong and not rolled back yet, if any.
+    pub first_incorrect_frame: Frame,
+    pub queued_inputs: usize,
+    pub input_size: usize,
+    pub disconnected: bool,
+    // Remote players only: the smoothed round trip in ms, once measured.
+    pub round_trip_time: Option<u128>,
+    // Remote players only: the magic numbers stamped on our packets and on theirs.
+    pub magic_numbers: Option<(u16, u16)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionDebugInfo {
+    pub frame: FrameNum,
+    pub last_confirmed_frame: Frame,
+    pub num_players: usize,
+    pub num_spectators: usize,
+    pub synchronizing: bool,
+    pub in_rollback: bool,
+    pub prediction_frames: FrameNum,
+    pub rollback_stats: RollbackStats,
+    // Only the players that have been added.
+    pub players: Vec<PlayerDebugInfo>,
+}
+
+impl fmt::Display for SessionDebugInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = if self.synchronizing {
+            "synchronizing"
+        } else if self.in_rollback {
+            "rolling back"
+        } else {
+            "running"
+        };
+        writeln!(
+            f,
+            "session at frame {} (confirmed {}), {} players, {} spectators, {}",
+            self.frame, self.last_confirmed_frame, self.num_players, self.num_spectators, state
+        )?;
+        writeln!(
+            f,
+            "prediction window {}, {} rollbacks, {} frames resimulated, longest {}",
+            self.prediction_frames,
+            self.rollback_stats.rollbacks,
+            self.rollback_stats.frames_resimulated,
+            self.rollback_stats.max_distance
+        )?;
+        for player in self.players.iter() {
+            write!(
+                f,
+                "  player {} {:?}: input to frame {}, {} predicted, {} queued, size {}",
+                player.handle,
+                player.player_type,
+                player.last_input_frame,
+                player.predicted_frames,
+                player.queued_inputs,
+                player.input_size
+            )?;
+            if !player.first_incorrect_frame.is_null() {
+                write!(f, ", wrong from {}", player.first_incorrect_frame)?;
+            }
+            if let Some(round_trip_time) = player.round_trip_time {
+                write!(f, ", rtt {} ms", round_trip_time)?;
+            }
+            if let Some((local, remote)) = player.magic_numbers {
+                write!(f, ", magic {:#06x}/{:#06x}", local, remote)?;
+            }
+            if player.disconnected {
+                write!(f, ", disconnected")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,317 @@
+/*
+ * Desync detection.  Once a frame is confirmed every peer has run it with the
+ * same inputs, so every peer must have saved the same state for it.  Peers
+ * swap the checksum of each confirmed frame, and the first one that doesn't
+ * match is reported along with the frames leading up to it, so the two sides'
+ * reports can be lined up against each other.
+ */
+
+use crate::{
+    checksum::fletcher32,
+    game_input::{Frame, InputBuffer, NULL_FRAME},
+    ggpo::GGPOSessionCallbacks,
+    player::PlayerHandle,
+};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, fmt};
+
+// How many confirmed frames a report looks back over.
+pub const DESYNC_REPORT_WINDOW: usize = 32;
+
+// One confirmed frame as this peer saw it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameRecord {
+    pub frame: Frame,
+    // Every player's input for the frame, trimmed to the session's input size.
+    pub inputs: Vec<Vec<u8>>,
+    pub local_checksum: u32,
+    // What the peer in the report said, if it has told us yet.
+    pub remote_checksum: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DesyncReport {
+    pub player: PlayerHandle,
+    // The first frame the two peers disagree on.
+    pub frame: Frame,
+    pub local_checksum: u32,
+    pub remote_checksum: u32,
+    // The frames leading up to and including `frame`, oldest first.
+    pub frames: Vec<FrameRecord>,
+}
+
+impl DesyncReport {
+    /*
+     * Hands the report to the game's `log_game_state` callback, as text, under
+     * a name built from the player and the divergent frame.
+     */
+    pub fn log<T: GGPOSessionCallbacks>(&self, callbacks: &mut T) -> bool {
+        let text = Bytes::from(self.to_string());
+        let length = text.len();
+        callbacks.log_game_state(
+            format!("desync-p{}-{}.log", self.player, self.frame),
+            text,
+            length,
+        )
+    }
+}
+
+impl fmt::Display for DesyncReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "desync with player {} at frame {}: local {:#010x}, remote {:#010x}",
+            self.player, self.frame, self.local_checksum, self.remote_checksum
+        )?;
+        writeln!(
+            f,
+            "{:>8}  {:>10}  {:>10}  inputs",
+            "frame", "local", "remote"
+        )?;
+        for record in self.frames.iter() {
+            let remote = match record.remote_checksum {
+                Some(checksum) => format!("{:#010x}", checksum),
+                None => "-".to_string(),
+            };
+            let marker = if record.frame == self.frame {
+                " <<"
+            } else {
+                ""
+            };
+            writeln!(
+                f,
+                "{:>8}  {:#010x}  {:>10}  {:?}{}",
+                record.frame, record.local_checksum, remote, record.inputs, marker
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/*
+ * What a session does on its own once `Event::DesyncDetected` fires, since
+ * playing on from a diverged state is a different match on each side.
+ */
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum DesyncAction {
+    // Carry on, leaving it to the game.
+    #[default]
+    Continue,
+    // Pause every peer, as `Session::pause` does.  `Event::SessionPaused`
+    // follows the desync event, for the game to show what happened.
+    Pause,
+    // Leave the match: say goodbye to every peer and disconnect them all.
+    Disconnect,
+}
+
+/*
+ * Pairs up our checksums for confirmed frames with the ones each peer sends
+ * us.  Either side can arrive first, so remote checksums for frames we haven't
+ * confirmed yet wait until we have.  Each peer is only reported once.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct DesyncDetector {
+    // Per queue: how much of each player's row is input.
+    input_sizes: Vec<usize>,
+    local: VecDeque<FrameRecord>,
+    // Per queue: checksums for frames we haven't confirmed yet.
+    pending: Vec<VecDeque<(Frame, u32)>>,
+    reported: Vec<bool>,
+}
+
+impl DesyncDetector {
+    pub fn new(num_players: usize, input_size: usize) -> Self {
+        Self {
+            input_sizes: vec![input_size; num_players],
+            local: VecDeque::with_capacity(DESYNC_REPORT_WINDOW),
+            pending: vec![VecDeque::new(); num_players],
+            reported: vec![false; num_players],
+        }
+    }
+
+    pub fn set_input_size(&mut self, queue: usize, input_size: usize) {
+        self.input_sizes[queue] = input_size;
+    }
+
+    // The last frame we've recorded a checksum for.
+    pub fn last_recorded(&self) -> Option<Frame> {
+        self.local.back().map(|record| record.frame)
+    }
+
+    /*
+     * Records our checksum for a newly confirmed frame, returning a report for
+     * each peer that had already sent a different one.
+     */
+    pub fn record_local(
+        &mut self,
+        frame: Frame,
+        inputs: &InputBuffer,
+        checksum: u32,
+    ) -> Vec<DesyncReport> {
+        if self.local.len() == DESYNC_REPORT_WINDOW {
+            self.local.pop_front();
+        }
+        let num_players = self.pending.len();
+        self.local.push_back(FrameRecord {
+            frame,
+            inputs: inputs
+                .iter()
+                .zip(self.input_sizes.iter())
+                .map(|(row, &size)| row[..size].to_vec())
+                .collect(),
+            local_checksum: checksum,
+            remote_checksum: None,
+        });
+
+        let mut reports = Vec::new();
+        for queue in 0..num_players {
+            // Anything older than this frame is never going to be matched.
+            while let Some(&(pending_frame, remote)) = self.pending[queue].front() {
+                if pending_frame > frame {
+                    break;
+                }
+                self.pending[queue].pop_front();
+                if pending_frame == frame {
+                    reports.extend(self.record_remote(queue, frame, remote));
+                }
+            }
+        }
+        reports
+    }
+
+    /*
+     * Records a peer's checksum for a frame, returning a report the first
+     * time it disagrees with ours.
+     */
+    pub fn record_remote(
+        &mut self,
+        queue: usize,
+        frame: Frame,
+        checksum: u32,
+    ) -> Option<DesyncReport> {
+        if self.reported[queue] {
+            return None;
+        }
+        let index = match self.local.iter().position(|record| record.frame == frame) {
+            Some(index) => index,
+            None => {
+                if self.last_recorded().is_none_or(|last| frame > last) {
+                    self.pending[queue].push_back((frame, checksum));
+                }
+                return None;
+            }
+        };
+        self.local[index].remote_checksum = Some(checksum);
+        let local_checksum = self.local[index].local_checksum;
+        if local_checksum == checksum {
+            return None;
+        }
+
+        self.reported[queue] = true;
+        Some(DesyncReport {
+            player: queue as PlayerHandle + 1,
+            frame,
+            local_checksum,
+            remote_checksum: checksum,
+            frames: self.local.iter().take(index + 1).cloned().collect(),
+        })
+    }
+}
+
+/*
+ * Rolling checksums over every player's confirmed input, for catching peers
+ * whose inputs differ before their states do, as a bug in encoding or
+ * decoding input would.  Each confirmed frame's checksum folds in the one
+ * before it, so one comparison covers every frame up to it.  Peers put their
+ * latest in their quality reports; like `DesyncDetector`, each peer is only
+ * reported once.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct InputChecksums {
+    // Our checksum as of each recent confirmed frame, oldest first.
+    local: VecDeque<(Frame, u32)>,
+    // Per queue: the latest checksum a peer sent for a frame we haven't confirmed yet.
+    pending: Vec<Option<(Frame, u32)>>,
+    reported: Vec<bool>,
+}
+
+impl InputChecksums {
+    pub fn new(num_players: usize) -> Self {
+        Self {
+            local: VecDeque::with_capacity(DESYNC_REPORT_WINDOW),
+            pending: vec![None; num_players],
+            reported: vec![false; num_players],
+        }
+    }
+
+    // Our checksum as of the last frame confirmed, to send peers.
+    pub fn latest(&self) -> Option<(Frame, u32)> {
+        self.local.back().copied()
+    }
+
+    /*
+     * Folds in the inputs for a newly confirmed frame, the first `sizes[i]`
+     * bytes of each row, returning the queues of any peers who had already
+     * sent a checksum for it that doesn't match.
+     */
+    pub fn record_local(
+        &mut self,
+        frame: Frame,
+        inputs: &InputBuffer,
+        sizes: &[usize],
+    ) -> Vec<usize> {
+        let previous = self.latest().map_or(0, |(_, checksum)| checksum);
+        let mut bytes = previous.to_le_bytes().to_vec();
+        for (row, &size) in inputs.iter().zip(sizes.iter()) {
+            bytes.extend_from_slice(&row[..size]);
+        }
+        let checksum = fletcher32(&bytes);
+        if self.local.len() == DESYNC_REPORT_WINDOW {
+            self.local.pop_front();
+        }
+        self.local.push_back((frame, checksum));
+
+        let mut mismatched = Vec::new();
+        for queue in 0..self.pending.len() {
+            match self.pending[queue] {
+                Some((pending_frame, remote)) if pending_frame <= frame => {
+                    self.pending[queue] = None;
+                    if pending_frame == frame && self.mismatch(queue, remote, checksum) {
+                        mismatched.push(queue);
+                    }
+                }
+                _ => {}
+            }
+        }
+        mismatched
+    }
+
+    // Checks a peer's checksum as of `frame`, returning true the first time one doesn't match.
+    pub fn record_remote(&mut self, queue: usize, frame: Frame, checksum: u32) -> bool {
+        if self.reported[queue] || frame == NULL_FRAME {
+            return false;
+        }
+        match self
+            .local
+            .iter()
+            .find(|(local_frame, _)| *local_frame == frame)
+        {
+            Some(&(_, local)) => self.mismatch(queue, checksum, local),
+            None => {
+                if self.latest().is_none_or(|(last, _)| frame > last) {
+                    self.pending[queue] = Some((frame, checksum));
+                }
+                false
+            }
+        }
+    }
+
+    fn mismatch(&mut self, queue: usize, remote: u32, local: u32) -> bool {
+        if remote == local {
+            return false;
+        }
+        self.reported[queue] = true;
+        true
+    }
+}
@@ -1,18 +1,37 @@
 use crate::{
     backends::p2p::Peer2PeerError,
-    game_input::{Frame, FrameNum, InputBuffer},
-    network::udp_proto::UdpProtoError,
-    player::{Player, PlayerHandle},
-    sync::SyncError,
+    debug_info::SessionDebugInfo,
+    desync::DesyncReport,
+    game_input::{Frame, FrameNum, InputBuffer, InputFrame},
+    network::udp_proto::{SyncFailure, UdpProtoError},
+    player::{Player, PlayerHandle, PlayerInfo, PlayerType},
+    prediction::PredictionStrategy,
+    sync::{MemoryUsage, RollbackCallbacks, RollbackStats, SyncError},
 };
 use bytes::Bytes;
-use std::time::Duration;
-// use log::info;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
 use thiserror::Error;
 
-pub const GGPO_MAX_PLAYERS: usize = 4;
+pub use crate::core::{sync::SavedState, GGPO_MAX_PLAYERS, GGPO_MAX_PREDICTION_FRAMES};
 pub const GGPO_MAX_SPECTATORS: usize = 32;
-pub const GGPO_MAX_PREDICTION_FRAMES: FrameNum = 8;
+// Set in `advance_frame`'s flags for a frame the session may yet roll back.
+pub const ADVANCE_SPECULATIVE: i32 = 1;
+/*
+ * A speculative frame's flags also say how likely its guesses are to hold:
+ * from this bit up is the shortest run of right predictions among the
+ * players whose input the frame guesses, up to `ADVANCE_CONFIDENCE_MAX`,
+ * which is also what a frame guessing nobody's gets.  Read it with
+ * `advance_confidence`.
+ */
+pub const ADVANCE_CONFIDENCE_SHIFT: i32 = 8;
+pub const ADVANCE_CONFIDENCE_MAX: u32 = 0xffff;
+
+// The prediction confidence in `advance_frame`'s flags; see `ADVANCE_CONFIDENCE_SHIFT`.
+pub fn advance_confidence(flags: i32) -> u32 {
+    (flags >> ADVANCE_CONFIDENCE_SHIFT) as u32 & ADVANCE_CONFIDENCE_MAX
+}
 
 #[derive(Error, Debug)]
 pub enum GGPOError {
@@ -42,23 +61,45 @@ pub enum GGPOError {
     PlayerDisconnected,
     #[error("GGPO too many spectators.")]
     TooManySpectators,
+    #[error("GGPO too many local players, at most {max} per host.")]
+    TooManyLocalPlayers { max: usize },
     #[error("GGPO invalid request.")]
     InvalidRequest,
+    #[error("GGPO session paused.")]
+    Paused,
+    #[error("GGPO input size {found} doesn't match the player's input size {expected}.")]
+    InputSizeMismatch { expected: usize, found: usize },
+    #[error("GGPO peer runs version {remote}, but we're version {local}.")]
+    IncompatibleVersion { local: u32, remote: u32 },
+    #[error("GGPO peer didn't finish synchronizing within {timeout} ms.")]
+    SyncTimeout { timeout: u128 },
+    #[error("GGPO peer is in session {found:#x}, not {expected:#x}.")]
+    SyncMagicMismatch { expected: u16, found: u16 },
+    #[error("GGPO peer runs version {remote}, but we're version {local}.")]
+    SyncVersionMismatch { local: u32, remote: u32 },
+    #[error("GGPO peer's input size {remote} doesn't match ours, {local}.")]
+    SyncInputSizeMismatch { local: usize, remote: usize },
+    #[error("GGPO couldn't resolve {host}: {reason}")]
+    Unresolved { host: String, reason: String },
+    #[error("GGPO has no confirmed state saved for frame {frame}.")]
+    StateNotSaved { frame: Frame },
     #[error("P2P Backend error.")]
     P2P {
         #[from]
         source: Peer2PeerError,
     },
+    #[error("Spectator Backend error.")]
+    Spectator {
+        #[from]
+        source: crate::backends::spectator::SpectatorError,
+    },
     #[error("UDP Protocol error.")]
     UdpProto {
         #[from]
         source: UdpProtoError,
     },
     #[error("Synchronization engine error.")]
-    Sync {
-        #[from]
-        source: SyncError,
-    },
+    Sync { source: SyncError },
     #[error("IO error.")]
     IO {
         #[from]
@@ -74,38 +115,239 @@ pub enum GGPOError {
         #[from]
         source: crate::backends::sync_test::SyncTestError,
     },
+    #[error("Replay error.")]
+    Replay {
+        #[from]
+        source: crate::replay::ReplayError,
+    },
 }
+impl From<SyncFailure> for GGPOError {
+    fn from(failure: SyncFailure) -> Self {
+        match failure {
+            SyncFailure::Timeout(timeout) => GGPOError::SyncTimeout { timeout },
+            SyncFailure::MagicMismatch { expected, found } => {
+                GGPOError::SyncMagicMismatch { expected, found }
+            }
+            SyncFailure::VersionMismatch(mismatch) => GGPOError::SyncVersionMismatch {
+                local: mismatch.local,
+                remote: mismatch.remote,
+            },
+            SyncFailure::InputSizeMismatch(mismatch) => GGPOError::SyncInputSizeMismatch {
+                local: mismatch.local,
+                remote: mismatch.remote,
+            },
+        }
+    }
+}
+
+impl From<SyncError> for GGPOError {
+    fn from(source: SyncError) -> Self {
+        match source {
+            // Asking to roll back further than the saved state ring reaches.
+            SyncError::FrameNotSaved(_) => GGPOError::GeneralFailure,
+            SyncError::InputDropped(_) => GGPOError::InputDropped,
+            // A queue that filled up because frames stopped being confirmed.
+            SyncError::InputQueueFull(_) => GGPOError::GeneralFailure,
+            // Asking for input the queue doesn't have, or doesn't have yet.
+            SyncError::InputUnavailable(_) => GGPOError::GeneralFailure,
+            source => GGPOError::Sync { source },
+        }
+    }
+}
+
+/*
+ * The first packet we'd accept from `player` has arrived: they're there, but
+ * the sync handshake may have only just started.  Sent once per player, and
+ * always before `SynchronizedWithPeer`.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConnectedToPeer {
     pub player: PlayerHandle,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SynchronizingWithPeer {
     pub count: u32,
     pub total: u32,
     pub player: PlayerHandle,
 }
 
+// Every round of the sync handshake with `player` is done.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SynchronizedWithPeer {
     pub player: PlayerHandle,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DisconnectedFromPeer {
     pub player: PlayerHandle,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TimeSyncEvent {
     pub frames_ahead: FrameNum,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConnectionInterrupted {
     pub player: PlayerHandle,
     pub disconnect_timeout: u128,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConnectionResumed {
     pub player: PlayerHandle,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionQualityChanged {
+    pub player: PlayerHandle,
+    pub quality: ConnectionQuality,
+}
+
+// A spectator finished fast-forwarding and is back to playing frames as they come.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaughtUp {
+    pub frame: Frame,
+}
+
+/*
+ * A spectator's input stream skipped these frames and they haven't turned up
+ * since, so it's asked the host to send them again.  If they still don't
+ * come the host is dropped, with `DisconnectedFromPeer`.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpectatorStreamGap {
+    pub missing_from: Frame,
+    pub missing_to: Frame,
+}
+
+/*
+ * We or a peer paused the session.  It runs up to `frame` and stops there, or
+ * later if a peer that was further along asks, in which case this fires
+ * again with the new frame.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionPaused {
+    pub frame: Frame,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionResumed {
+    pub frame: Frame,
+}
+
+/*
+ * Every player's real input for `frame` has arrived and we've simulated it with
+ * them, so it can't be rolled back any more.  Fires once per frame, in order,
+ * trailing the frames `increment_frame` runs by up to the prediction window.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrameConfirmed {
+    pub frame: Frame,
+}
+
+/*
+ * In auto frame delay mode, the round trip to the slowest peer (`round_trip_time`,
+ * in ms) has moved far enough that local players' input now goes in `delay`
+ * frames late.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrameDelayChanged {
+    pub delay: usize,
+    pub round_trip_time: usize,
+}
+
+/*
+ * Local input was refused because we're `GGPO_MAX_PREDICTION_FRAMES` ahead
+ * of the last frame every peer has confirmed.  The game shouldn't advance
+ * this frame.  `frames_stalled` counts the frames refused so far, and
+ * `estimated_frames` guesses how many more it takes the slowest peer's input
+ * to arrive, from its round trip.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PredictionBarrier {
+    pub frames_stalled: usize,
+    pub estimated_frames: usize,
+}
+
+/*
+ * With adaptive prediction on, the session now runs at most `frames` ahead of
+ * the last confirmed frame: fewer while a peer's input keeps being
+ * mispredicted, more again as it settles.  `mispredict_rate` is rollbacks per
+ * frame over the stretch that prompted the change.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PredictionWindowChanged {
+    pub frames: FrameNum,
+    pub mispredict_rate: f32,
+}
+
+/*
+ * With input checksums on, `player`'s checksum of every player's confirmed
+ * input didn't match ours as of `frame`.  The inputs first differed on that
+ * frame or some frame before it.  Reported once per player.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputDesyncDetected {
+    pub player: PlayerHandle,
+    pub frame: Frame,
+}
+
+/*
+ * `player`'s clock runs fast (positive) or slow (negative) of ours by
+ * `drift_ppm` parts per million, past `udp_proto::CLOCK_DRIFT_THRESHOLD_PPM`.
+ * Time sync keeps the frames in step regardless, by making the faster side
+ * wait now and then; a game that would rather nudge its own frame rate can
+ * do so from this.  Reported again each time the drift crosses the threshold.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClockDrift {
+    pub player: PlayerHandle,
+    pub drift_ppm: i32,
+}
+
+/*
+ * Callbacks handed to `Peer2PeerBackend::set_callbacks` took over at `frame`:
+ * they saved it, and do every save, load and resimulation from it on.  Sent
+ * to the new callbacks.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CallbacksSwapped {
+    pub frame: Frame,
+}
+
+// The barrier lifted and local input is being taken again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PredictionBarrierCleared {
+    pub frames_stalled: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputSizeMismatch {
+    pub player: PlayerHandle,
+    pub local_size: usize,
+    pub remote_size: usize,
+}
+
+/*
+ * A peer's handshake carried a different version from ours, so we won't play
+ * with it.  The connection never gets past synchronizing.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncompatibleVersion {
+    pub player: PlayerHandle,
+    pub local_version: u32,
+    pub remote_version: u32,
+}
+
+/*
+ * Everything a session reports through `GGPOSessionCallbacks::on_event`.  New
+ * kinds of event get added over time, so matches outside this crate need a
+ * `_ => {}` arm; anything a game doesn't recognise is safe to ignore.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum Event {
     ConnectedToPeer(ConnectedToPeer),
     SynchronizingWithPeer(SynchronizingWithPeer),
@@ -115,6 +357,23 @@ pub enum Event {
     TimeSync(TimeSyncEvent),
     ConnectionInterrupted(ConnectionInterrupted),
     ConnectionResumed(ConnectionResumed),
+    InputSizeMismatch(InputSizeMismatch),
+    IncompatibleVersion(IncompatibleVersion),
+    // A peer saved a different state for a confirmed frame than we did.  See `SessionConfig::on_desync`.
+    DesyncDetected(DesyncReport),
+    CaughtUp(CaughtUp),
+    SpectatorStreamGap(SpectatorStreamGap),
+    ConnectionQualityChanged(ConnectionQualityChanged),
+    SessionPaused(SessionPaused),
+    SessionResumed(SessionResumed),
+    FrameConfirmed(FrameConfirmed),
+    FrameDelayChanged(FrameDelayChanged),
+    PredictionBarrier(PredictionBarrier),
+    PredictionBarrierCleared(PredictionBarrierCleared),
+    PredictionWindowChanged(PredictionWindowChanged),
+    InputDesyncDetected(InputDesyncDetected),
+    ClockDrift(ClockDrift),
+    CallbacksSwapped(CallbacksSwapped),
 }
 
 // #[async_trait()]
@@ -123,10 +382,25 @@ pub trait Session {
         unimplemented!()
     }
 
+    /*
+     * One pass over the session that never blocks: handles what's already
+     * arrived, for up to `budget`, then runs timers and the rollback as
+     * `do_poll` would.  For games that run their own loop, or an async
+     * runtime of their choosing, and call this once a tick.
+     */
+    fn poll_once(&mut self, _budget: Duration) -> Result<(), GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
     fn add_player(&mut self, _player: Player, _handle: &mut PlayerHandle) -> Result<(), GGPOError> {
         unimplemented!()
     }
 
+    /*
+     * Queues a local player's input for the current frame, or for `delay`
+     * frames later when a frame delay is set.  Fails with `InputDropped` if
+     * that frame has already been confirmed by every peer.
+     */
     fn add_local_input(
         &mut self,
         _player: PlayerHandle,
@@ -138,12 +412,94 @@ pub trait Session {
 
     fn synchronize_input(
         &self,
-        _values: &mut Vec<InputBuffer>,
-        _disconnect_flags: Option<&mut i32>,
+        _values: &mut InputBuffer,
+        _disconnect_flags: Option<&mut DisconnectFlags>,
     ) -> Result<(), GGPOError> {
         unimplemented!()
     }
 
+    /*
+     * `synchronize_input` for game loops that keep running (a menu, say)
+     * while the session synchronizes: `None` until the session is running,
+     * then the disconnect flags.  Other failures are logged and also give
+     * `None`.
+     */
+    fn try_synchronize_input(&self, values: &mut InputBuffer) -> Option<DisconnectFlags> {
+        let mut flags = DisconnectFlags::default();
+        match self.synchronize_input(values, Some(&mut flags)) {
+            Ok(()) => Some(flags),
+            Err(GGPOError::NotSynchronized) => None,
+            Err(e) => {
+                error!("Failed to synchronize input: {}\n", e);
+                None
+            }
+        }
+    }
+
+    /*
+     * `add_local_input` for a typed input, written out as its bytes into the
+     * player's own row.  Fails with `InputSizeMismatch` unless `I` is exactly
+     * the player's input size.
+     */
+    fn add_local_input_typed<I: InputFrame>(
+        &mut self,
+        player: PlayerHandle,
+        input: I,
+    ) -> Result<(), GGPOError>
+    where
+        Self: Sized,
+    {
+        let queue = self
+            .players()?
+            .into_iter()
+            .find(|info| {
+                info.handle == player && !matches!(info.player_type, PlayerType::Spectator(_))
+            })
+            .ok_or(GGPOError::InvalidPlayerHandle)?
+            .queue as usize;
+        let expected = *self
+            .input_sizes()?
+            .get(queue)
+            .ok_or(GGPOError::PlayerOutOfRange)?;
+        let size = core::mem::size_of::<I>();
+        if size != expected {
+            return Err(GGPOError::InputSizeMismatch {
+                expected,
+                found: size,
+            });
+        }
+        let mut values: InputBuffer = Default::default();
+        values[queue][..size].copy_from_slice(bytemuck::bytes_of(&input));
+        self.add_local_input(player, &values, size)
+    }
+
+    /*
+     * `synchronize_input` read back as typed inputs, one per player by queue.
+     * Every player's input size has to be `I`'s; otherwise it fails with
+     * `InputSizeMismatch` for the first that isn't.
+     */
+    fn synchronize_input_typed<I: InputFrame>(&self) -> Result<(Vec<I>, DisconnectFlags), GGPOError>
+    where
+        Self: Sized,
+    {
+        let size = core::mem::size_of::<I>();
+        let sizes = self.input_sizes()?;
+        if let Some(&expected) = sizes.iter().find(|&&expected| expected != size) {
+            return Err(GGPOError::InputSizeMismatch {
+                expected,
+                found: size,
+            });
+        }
+        let mut values: InputBuffer = Default::default();
+        let mut flags = DisconnectFlags::default();
+        self.synchronize_input(&mut values, Some(&mut flags))?;
+        let inputs = values[..sizes.len()]
+            .iter()
+            .map(|row| bytemuck::pod_read_unaligned(&row[..size]))
+            .collect();
+        Ok((inputs, flags))
+    }
+
     fn increment_frame(&mut self) -> Result<(), GGPOError> {
         unimplemented!()
     }
@@ -160,6 +516,57 @@ pub trait Session {
         unimplemented!()
     }
 
+    /*
+     * How many rollbacks the session has run, and how long they were, for
+     * tuning frame delay and the prediction window.
+     */
+    fn rollback_stats(&self) -> Result<RollbackStats, GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    // What the session is holding on to: input, saved states, and unacked input.
+    fn memory_usage(&self) -> Result<MemoryUsage, GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    /*
+     * Each player's input size, in bytes, by queue: of row `i` of the buffer
+     * `synchronize_input` fills, the first `input_sizes()[i]` bytes are input.
+     */
+    fn input_sizes(&self) -> Result<Vec<usize>, GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    // A snapshot of the session's state, to attach to bug reports.
+    fn debug_dump(&self) -> Result<SessionDebugInfo, GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    /*
+     * Whether `player` was added as one of ours.  Only local players take
+     * input through `add_local_input`; remote players' comes from their peer.
+     */
+    fn is_local(&self, _player: PlayerHandle) -> Result<bool, GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    /*
+     * Everyone added to the session, players by handle and then spectators,
+     * with whether each is still connected.
+     */
+    fn players(&self) -> Result<Vec<PlayerInfo>, GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    /*
+     * Every player's `PeerConnectStatus`, by queue: entry `i` is for player
+     * handle `i + 1`.  Local players count too, up to the last frame of
+     * input they've added.
+     */
+    fn connect_status(&self) -> Result<Vec<PeerConnectStatus>, GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
     //TODO: stub this with the log crate
     fn logv(_fmt: String) -> Result<(), GGPOError> {
         unimplemented!()
@@ -169,6 +576,21 @@ pub trait Session {
         Err(GGPOError::Unsupported)
     }
 
+    /*
+     * Lets the session pick local players' frame delay from the measured
+     * round trip, reporting each change with `Event::FrameDelayChanged`.
+     * `set_frame_delay` turns it back off.
+     */
+    fn set_auto_frame_delay(&mut self, _enabled: bool) -> Result<(), GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    /*
+     * This and `set_disconnect_notify_start` can change mid-match: they take
+     * effect on the next poll, against however long each peer has been
+     * silent already.  Raising the timeout for an interrupted peer reports
+     * the interruption again, with the time it has left.
+     */
     fn set_disconnect_timeout(&mut self, _timeout: u128) -> Result<(), GGPOError> {
         Err(GGPOError::Unsupported)
     }
@@ -176,6 +598,145 @@ pub trait Session {
     fn set_disconnect_notify_start(&mut self, _timeout: u128) -> Result<(), GGPOError> {
         Err(GGPOError::Unsupported)
     }
+
+    fn set_retransmit_interval(&mut self, _interval: u128) -> Result<(), GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    /*
+     * Caps how many bytes per second go to each peer.  Fresh input is always
+     * sent; quality reports and redundant resends wait for room.  0, the
+     * default, leaves sending unlimited.
+     */
+    fn set_send_rate_limit(&mut self, _bytes_per_second: usize) -> Result<(), GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    /*
+     * How long (in ms) a peer that timed out may still reconnect, resuming the
+     * session with a `ConnectionResumed` event.  0, the default, disables it.
+     */
+    fn set_reconnect_window(&mut self, _window: u128) -> Result<(), GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    /*
+     * The version of the game's protocol and save states, sent in the
+     * handshake.  Peers (and spectators) on different versions refuse each
+     * other with `Event::IncompatibleVersion` rather than play on and desync.
+     * Set it before adding players.  Defaults to 0.
+     */
+    fn set_version(&mut self, _version: u32) -> Result<(), GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    /*
+     * The version every peer agreed on, once the handshakes are done.  Fails
+     * with `IncompatibleVersion` if a peer turned out to be on another one.
+     */
+    fn negotiated_version(&self) -> Result<u32, GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    /*
+     * A random seed every peer in the session agrees on, for games that need
+     * one to simulate identically.  Available once `Event::Running` has fired.
+     */
+    fn shared_seed(&self) -> Result<u64, GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    /*
+     * The raw `(local, remote)` frame advantages time sync works from for a
+     * remote player, for tools tuning frame delay by hand: how many frames
+     * we're behind them, and how many they last reported being behind us.
+     * Not smoothed, and not a recommendation; see `Event::TimeSync` for that.
+     */
+    fn frame_advantage(&self, _handle: PlayerHandle) -> Result<(i32, i32), GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    /*
+     * Why the sync handshake with a remote player failed, if it has:
+     * `SyncTimeout`, `SyncMagicMismatch`, `SyncVersionMismatch` or
+     * `SyncInputSizeMismatch`, for a game to tell its player what went wrong.
+     * `Ok` while it's still going, and once it's through.
+     */
+    fn sync_error(&self, _handle: PlayerHandle) -> Result<(), GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    /*
+     * The last frame we have a player's real input for, not a prediction:
+     * `NULL_FRAME` until the first arrives.  For showing who's lagging, say.
+     */
+    fn last_confirmed_frame(&self, _handle: PlayerHandle) -> Result<Frame, GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    /*
+     * The state saved for a confirmed frame, and its checksum, for saving a
+     * match's position or handing it to tools.  Fails with `StateNotSaved` if
+     * the frame isn't confirmed yet, or its state has left the saved state
+     * ring; see `SessionConfig::saved_state_depth`.
+     */
+    fn export_state(&self, _frame: Frame) -> Result<(Bytes, u32), GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    /*
+     * Puts the game back in a state from `export_state` and carries on from
+     * its frame, for replaying from a saved position.  Only in a session no
+     * input has gone into yet; every peer has to import the same state.
+     */
+    fn import_state(&mut self, _frame: Frame, _state: Bytes) -> Result<(), GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    /*
+     * How the connection to a remote player is holding up.  Changes are also
+     * reported with `Event::ConnectionQualityChanged`.
+     */
+    fn connection_quality(&self, _handle: PlayerHandle) -> Result<ConnectionQuality, GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    /*
+     * How to guess remote input that hasn't arrived yet.  The default repeats
+     * each player's last input; see `prediction` for the alternatives.
+     */
+    fn set_prediction_strategy(
+        &mut self,
+        _strategy: Arc<dyn PredictionStrategy>,
+    ) -> Result<(), GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    /*
+     * Pauses every peer at the same frame: the furthest any of them had got
+     * to when they heard about it.  `add_local_input` fails with `Paused` once
+     * the session gets there, and the connections are kept alive in the
+     * meantime, so keep calling `do_poll`.  Peers are told with
+     * `Event::SessionPaused`.
+     */
+    fn pause(&mut self) -> Result<(), GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    // Picks up from the pause frame.  Any peer can resume, whoever paused.
+    fn resume(&mut self) -> Result<(), GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
+
+    /*
+     * Leaves the session: tells every peer we're going, so they see
+     * `Event::DisconnectedFromPeer` straight away instead of waiting out the
+     * disconnect timeout, closes the socket and frees the saved states.  The
+     * session can't be used afterwards.
+     */
+    fn shutdown(&mut self) -> Result<(), GGPOError> {
+        Err(GGPOError::Unsupported)
+    }
 }
 
 pub trait GGPOSessionCallbacks: Clone {
@@ -183,18 +744,13 @@ pub trait GGPOSessionCallbacks: Clone {
     // fn begin_game() -> bool;
 
     /*
-     * save_game_state - The client should allocate a buffer, copy the
-     * entire contents of the current game state into it, and copy the
-     * length into the *len parameter.  Optionally, the client can compute
-     * a checksum of the data and store it in the *checksum argument.
+     * save_game_state - The client should copy the entire contents of the
+     * current game state into a buffer and return it, along with a checksum
+     * of the data if it has a cheaper one than hashing the whole buffer.  The
+     * session owns the returned state and drops it once it's no longer needed
+     * for a rollback.
      */
-    fn save_game_state(
-        &mut self,
-        buffer: &Bytes,
-        length: &usize,
-        checksum: Option<u32>,
-        frame: Frame,
-    ) -> bool;
+    fn save_game_state(&mut self, frame: Frame) -> Result<SavedState, GGPOError>;
 
     /*
      * load_game_state - GGPO.net will call this function at the beginning
@@ -205,6 +761,20 @@ pub trait GGPOSessionCallbacks: Clone {
      */
     fn load_game_state(&mut self, buffer: &Bytes, length: usize) -> bool;
 
+    /*
+     * reset_to_frame - Called instead of load_game_state when the session
+     * only saves a checkpoint every few frames (see
+     * `SessionConfig::checkpoint_interval`).  The buffer holds the state saved
+     * for `frame`, the checkpoint the rollback starts from; the session then
+     * replays input from there with advance_frame, as in any rollback.  The
+     * game has nothing saved for the frames in between.
+     *
+     * The default calls load_game_state.
+     */
+    fn reset_to_frame(&mut self, _frame: Frame, buffer: &Bytes, length: usize) -> bool {
+        self.load_game_state(buffer, length)
+    }
+
     /*
      * log_game_state - Used in diagnostic testing.  The client should use
      * the ggpo_log function to write the contents of the specified save
@@ -212,12 +782,6 @@ pub trait GGPOSessionCallbacks: Clone {
      */
     fn log_game_state(&mut self, filename: String, buffer: Bytes, length: usize) -> bool;
 
-    /*
-     * free_buffer - Frees a game state allocated in save_game_state.  You
-     * should deallocate the memory contained in the buffer.
-     */
-    fn free_buffer(&mut self, buffer: &Bytes);
-
     /*
      * advance_frame - Called during a rollback.  You should advance your game
      * state by exactly one frame.  Before each frame, call ggpo_synchronize_input
@@ -225,15 +789,77 @@ pub trait GGPOSessionCallbacks: Clone {
      * you should call ggpo_advance_frame to notify GGPO.net that you're
      * finished.
      *
-     * The flags parameter is reserved.  It can safely be ignored at this time.
+     * The flags say how final the frame is.  `ADVANCE_SPECULATIVE` is set
+     * while a rollback runs frames again: a later rollback may run them once
+     * more, so hold back anything that can't be taken back, like sounds or
+     * messages to the outside world.  It's clear when the session steps the
+     * game onto a frame of confirmed input for the first time, which it does
+     * through the default `fast_forward_frame`.  A speculative frame also
+     * carries a confidence (`advance_confidence`): the lower it is, the
+     * likelier another rollback, and the more it's worth damping anything
+     * that would jump if one came.
      */
     fn advance_frame(&mut self, flags: i32) -> bool;
 
+    /*
+     * fast_forward_frame - Called when the session steps the game forward on
+     * its own, as a spectator does to catch up with the host.  The session has
+     * already taken the frame's inputs, so they're passed in rather than
+     * fetched with ggpo_synchronize_input, and the frame is over when this
+     * returns.  Skip rendering it.
+     *
+     * The default ignores the inputs and calls advance_frame with no flags.
+     */
+    fn fast_forward_frame(&mut self, _inputs: &InputBuffer, _disconnect_flags: i32) -> bool {
+        self.advance_frame(0)
+    }
+
     /*
      * on_event - Notification that something has happened.  See the GGPOEventCode
-     * structure above for more information.
+     * structure above for more information.  `Event` is non-exhaustive: match
+     * the events you care about and let a `_ => {}` arm drop the rest, so new
+     * events don't break the build.
      */
     fn on_event(&mut self, info: &Event);
+
+    /*
+     * on_rollback - Called once a rollback is over: the game was at
+     * `from_frame`, went back to `to_frame` and ran the `resimulated` frames
+     * between them again.  Anything drawn from the frames that were rolled
+     * over may have jumped, which is the place to start blending it.
+     *
+     * The default does nothing.
+     */
+    fn on_rollback(&mut self, _from_frame: Frame, _to_frame: Frame, _resimulated: u32) {}
+}
+
+impl<T: GGPOSessionCallbacks> RollbackCallbacks for T {
+    type Error = GGPOError;
+
+    fn save_state(&mut self, frame: Frame) -> Result<SavedState, GGPOError> {
+        self.save_game_state(frame)
+    }
+
+    fn load_state(&mut self, buffer: &Bytes, length: usize) -> bool {
+        self.load_game_state(buffer, length)
+    }
+
+    fn reset_state(&mut self, frame: Frame, buffer: &Bytes, length: usize) -> bool {
+        self.reset_to_frame(frame, buffer, length)
+    }
+
+    fn resimulate_frame(&mut self) -> bool {
+        self.advance_frame(ADVANCE_SPECULATIVE)
+    }
+
+    fn resimulate_frame_with_confidence(&mut self, confidence: u32) -> bool {
+        let confidence = confidence.min(ADVANCE_CONFIDENCE_MAX) as i32;
+        self.advance_frame(ADVANCE_SPECULATIVE | confidence << ADVANCE_CONFIDENCE_SHIFT)
+    }
+
+    fn rolled_back(&mut self, from_frame: Frame, to_frame: Frame, resimulated: u32) {
+        self.on_rollback(from_frame, to_frame, resimulated)
+    }
 }
 
 #[derive(Debug, Default, Copy, Clone)]
@@ -241,7 +867,14 @@ pub struct Network {
     pub send_queue_len: usize,
     pub recv_queue_len: usize,
     pub ping: usize,
+    // Everything we sent over the last second, as it went on the wire.
     pub kbps_sent: usize,
+    // The same, split into input and everything else (syncs, acks, quality
+    // reports, keep alives), in bytes per second.
+    pub input_bytes_per_second: usize,
+    pub overhead_bytes_per_second: usize,
+    // The share of input packets we had to retransmit over the last second.
+    pub loss_percent: usize,
 }
 
 impl Network {
@@ -251,6 +884,9 @@ impl Network {
             recv_queue_len: 0,
             ping: 0,
             kbps_sent: 0,
+            input_bytes_per_second: 0,
+            overhead_bytes_per_second: 0,
+            loss_percent: 0,
         }
     }
 }
@@ -259,6 +895,9 @@ impl Network {
 pub struct TimeSync {
     pub local_frames_behind: i32,
     pub remote_frames_behind: i32,
+    // How fast the peer's clock runs against ours, in parts per million;
+    // `None` until there's a long enough stretch of quality reports to tell.
+    pub clock_drift_ppm: Option<i32>,
 }
 
 impl TimeSync {
@@ -266,6 +905,7 @@ impl TimeSync {
         Self {
             local_frames_behind: 0,
             remote_frames_behind: 0,
+            clock_drift_ppm: None,
         }
     }
 }
@@ -285,6 +925,130 @@ impl NetworkStats {
     }
 }
 
+/*
+ * Which players `synchronize_input` found disconnected for the frame: bit
+ * `i` is for the player in queue `i`, which is player handle `i + 1`.  It
+ * converts to and from the bare `i32` of the C API and the callbacks.
+ */
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct DisconnectFlags(i32);
+
+impl DisconnectFlags {
+    pub const fn new(bits: i32) -> Self {
+        DisconnectFlags(bits)
+    }
+
+    pub const fn bits(self) -> i32 {
+        self.0
+    }
+
+    // Takes a queue index, not a player handle.
+    pub fn is_disconnected(self, player_index: usize) -> bool {
+        player_index < 32 && self.0 & (1 << player_index) != 0
+    }
+
+    // The queue index of every disconnected player, lowest first.
+    pub fn disconnected(self) -> impl Iterator<Item = usize> {
+        (0..32).filter(move |player_index| self.is_disconnected(*player_index))
+    }
+}
+
+impl From<i32> for DisconnectFlags {
+    fn from(bits: i32) -> Self {
+        DisconnectFlags(bits)
+    }
+}
+
+impl From<DisconnectFlags> for i32 {
+    fn from(flags: DisconnectFlags) -> Self {
+        flags.0
+    }
+}
+
+/*
+ * Where a player stands, like upstream's per-peer connect status: whether
+ * they're still in the match, and the last frame we have their input for.
+ * Once they're disconnected the frame stays where their input ran out.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PeerConnectStatus {
+    pub connected: bool,
+    pub last_frame: Frame,
+}
+
+/*
+ * How a connection to a peer is holding up, for a "connection bars" display.
+ * A connection is only as good as its worst measure, and each measure has an
+ * upper bound for Excellent, Good and Fair; past the last it's Poor:
+ *
+ * - ping, the round trip time in ms.
+ * - loss, the percentage of input packets we had to retransmit.
+ * - drift, how many frames apart the two sides are, halved the way time sync
+ *   splits the difference.
+ *
+ * Dropping a grade happens as soon as a measure crosses its bound, but
+ * climbing back takes clearing the better grade's bound by
+ * `QUALITY_HYSTERESIS_PERCENT`, so a link sitting on a bound doesn't flap.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ConnectionQuality {
+    Excellent,
+    Good,
+    Fair,
+    Poor,
+}
+
+pub const QUALITY_PING_BOUNDS: [usize; 3] = [50, 100, 200];
+pub const QUALITY_LOSS_BOUNDS: [usize; 3] = [1, 5, 15];
+pub const QUALITY_DRIFT_BOUNDS: [usize; 3] = [1, 3, 6];
+pub const QUALITY_HYSTERESIS_PERCENT: usize = 20;
+
+impl ConnectionQuality {
+    const GRADES: [ConnectionQuality; 4] = [
+        ConnectionQuality::Excellent,
+        ConnectionQuality::Good,
+        ConnectionQuality::Fair,
+        ConnectionQuality::Poor,
+    ];
+
+    // The grade the stats earn, with no memory of the last one.
+    pub fn classify(stats: &NetworkStats) -> Self {
+        Self::grade(stats, 100)
+    }
+
+    // The grade the stats earn coming from `self`, with hysteresis.
+    pub fn reclassify(self, stats: &NetworkStats) -> Self {
+        let quality = Self::grade(stats, 100);
+        if quality >= self {
+            return quality;
+        }
+        std::cmp::min(self, Self::grade(stats, 100 - QUALITY_HYSTERESIS_PERCENT))
+    }
+
+    // Grades against the bounds scaled to `percent` of their value.
+    fn grade(stats: &NetworkStats, percent: usize) -> Self {
+        let drift = (stats.timesync.remote_frames_behind - stats.timesync.local_frames_behind)
+            .unsigned_abs() as usize
+            / 2;
+        let measure = |value: usize, bounds: &[usize; 3]| {
+            let grade = bounds
+                .iter()
+                .position(|bound| value * 100 <= bound * percent)
+                .unwrap_or(bounds.len());
+            Self::GRADES[grade]
+        };
+        [
+            measure(stats.network.ping, &QUALITY_PING_BOUNDS),
+            measure(stats.network.loss_percent, &QUALITY_LOSS_BOUNDS),
+            measure(drift, &QUALITY_DRIFT_BOUNDS),
+        ]
+        .iter()
+        .copied()
+        .max()
+        .unwrap_or(ConnectionQuality::Excellent)
+    }
+}
+
 #[derive(Debug, Default, Copy, Clone)]
 struct LocalEndpoint {
     player_num: usize,
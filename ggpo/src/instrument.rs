@@ -0,0 +1,51 @@
+/*
+ * Structured diagnostics for the operations worth lining up against each
+ * other when chasing stutter: the sync handshake, rollbacks, packets on the
+ * wire and connection state changes.  They're reported through `tracing`
+ * under the "ggpo" target when the `tracing` feature is on, and compile away
+ * to nothing when it's off.
+ *
+ * Every span and event uses the same field names:
+ *
+ *   peer        the remote address
+ *   direction   "send" or "recv"
+ *   msg_type    the packet's `MsgType`
+ *   bytes       the packet's size on the wire
+ *   remaining   sync round trips still to go
+ *   from_frame  the frame a rollback starts from
+ *   to_frame    the frame a rollback returns to
+ *   frames      how many frames get simulated again
+ *   state       the connection state being entered
+ */
+
+// A span, entered until the returned guard is dropped.
+#[cfg(feature = "tracing")]
+macro_rules! ggpo_span {
+    ($name:literal $(, $($fields:tt)*)?) => {
+        tracing::info_span!(target: "ggpo", $name $(, $($fields)*)?).entered()
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! ggpo_span {
+    ($name:literal $(, $($fields:tt)*)?) => {
+        crate::instrument::NoSpan
+    };
+}
+
+// A one-off event, with the message last.  Only the network layer has any.
+#[cfg(all(feature = "net", feature = "tracing"))]
+macro_rules! ggpo_event {
+    ($($args:tt)*) => {
+        tracing::info!(target: "ggpo", $($args)*)
+    };
+}
+
+#[cfg(all(feature = "net", not(feature = "tracing")))]
+macro_rules! ggpo_event {
+    ($($args:tt)*) => {};
+}
+
+// What `ggpo_span!` hands back when tracing is compiled out.
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct NoSpan;
@@ -4,23 +4,53 @@
 // #![feature(slice_fill)]
 // #![feature(const_in_array_repeat_expressions)]
 // #![feature(move_ref_pattern)]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![warn(clippy::all)]
 #![forbid(unsafe_code)]
 
+extern crate alloc;
+
+// Declared first so its macros are visible everywhere below.
+#[macro_use]
+mod instrument;
+
+pub mod core;
+// The rollback core at the paths it has always had.
+pub use crate::core::{checksum, game_input, input_queue, prediction, sync, time_sync};
+
+#[cfg(feature = "net")]
 pub mod backends {
     pub mod p2p;
     pub mod spectator;
     pub mod sync_test;
 }
-pub mod game_input;
+#[cfg(feature = "net")]
 pub mod ggpo;
-pub mod input_queue;
+#[cfg(feature = "net")]
 pub mod network {
+    pub mod clock;
+    #[cfg(feature = "encryption")]
+    pub mod encryption;
+    pub mod fragment;
+    pub mod input_codec;
+    pub mod relay;
+    #[cfg(feature = "rendezvous")]
+    pub mod rendezvous;
     pub mod udp;
     pub mod udp_msg;
     pub mod udp_proto;
 }
+#[cfg(feature = "net")]
 pub mod bitvector;
+#[cfg(feature = "net")]
+pub mod config;
+#[cfg(feature = "net")]
+pub mod debug_info;
+#[cfg(feature = "net")]
+pub mod desync;
+#[cfg(feature = "net")]
 pub mod player;
-pub mod sync;
-pub mod time_sync;
+#[cfg(feature = "net")]
+pub mod replay;
+#[cfg(feature = "net")]
+pub mod runner;
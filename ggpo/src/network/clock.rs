@@ -0,0 +1,153 @@
+/*
+ * Where endpoints get the time from.  Every timer in `UdpProtocol` (the
+ * disconnect timeout, keep alives, quality reports, sync retries and round
+ * trip samples) reads a `Clock`, as do the backends' own (the pacing of
+ * state snapshots and state requests) and the transport's wait for the rest
+ * of a packet sent in pieces, so a test can hand the session a `TestClock`
+ * and move time along itself instead of sleeping.
+ *
+ * A `Clock` only answers what time it is.  To sleep until the session's next
+ * timer is due, as a game driving it from an async task wants to, give the
+ * session a `TimerSource` instead: one that can also be waited on.  Nothing
+ * here is tied to a runtime; `SmolTimers` waits on smol's, `TestClock` on
+ * whoever advances it, and any other runtime's timer fits the same trait.
+ */
+
+use parking_lot::Mutex;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+    time::{Duration, SystemTime},
+};
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+// What `TimerSource::sleep_until` hands back.
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+pub trait TimerSource: Clock {
+    // Resolves once `now()` has reached `deadline`, straight away if it has.
+    fn sleep_until(&self, deadline: SystemTime) -> Sleep;
+}
+
+// The wall clock, which sessions use unless they're given another.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+// The wall clock, waited on with smol's timers.
+#[cfg(feature = "smol-timers")]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SmolTimers;
+
+#[cfg(feature = "smol-timers")]
+impl Clock for SmolTimers {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+#[cfg(feature = "smol-timers")]
+impl TimerSource for SmolTimers {
+    fn sleep_until(&self, deadline: SystemTime) -> Sleep {
+        // smol's timers run on `Instant`s, which can't be built from a `SystemTime`.
+        let wait = deadline
+            .duration_since(SystemTime::now())
+            .unwrap_or_default();
+        Box::pin(async move {
+            smol::Timer::after(wait).await;
+        })
+    }
+}
+
+#[derive(Debug)]
+struct TestTime {
+    now: SystemTime,
+    // Sleepers to wake when the time moves.
+    sleepers: Vec<Waker>,
+}
+
+// A clock that only moves when it's told to.
+#[derive(Debug)]
+pub struct TestClock {
+    time: Arc<Mutex<TestTime>>,
+}
+
+impl Default for TestClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestClock {
+    // Starts at the current wall clock time, then stands still.
+    pub fn new() -> Self {
+        TestClock {
+            time: Arc::new(Mutex::new(TestTime {
+                now: SystemTime::now(),
+                sleepers: Vec::new(),
+            })),
+        }
+    }
+
+    // Moves time along, waking anything asleep on the clock to check its deadline.
+    pub fn advance(&self, by: Duration) {
+        let sleepers = {
+            let mut time = self.time.lock();
+            time.now += by;
+            std::mem::take(&mut time.sleepers)
+        };
+        for sleeper in sleepers {
+            sleeper.wake();
+        }
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> SystemTime {
+        self.time.lock().now
+    }
+}
+
+impl TimerSource for TestClock {
+    fn sleep_until(&self, deadline: SystemTime) -> Sleep {
+        Box::pin(TestSleep {
+            time: self.time.clone(),
+            deadline,
+        })
+    }
+}
+
+// Sleeps until a `TestClock` has been advanced to `deadline`.
+struct TestSleep {
+    time: Arc<Mutex<TestTime>>,
+    deadline: SystemTime,
+}
+
+impl Future for TestSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut time = self.time.lock();
+        if time.now >= self.deadline {
+            return Poll::Ready(());
+        }
+        if !time
+            .sleepers
+            .iter()
+            .any(|sleeper| sleeper.will_wake(cx.waker()))
+        {
+            time.sleepers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
@@ -0,0 +1,102 @@
+/*
+ * Sealing every packet with AES-256-GCM, for matches played over networks
+ * other people can see into.  Without the key nobody can read the inputs
+ * going by, and a packet that's been tampered with, forged, or sealed under
+ * another key fails to open and is dropped, so a peer on the wrong key never
+ * gets through the sync handshake.
+ *
+ * Both peers are given the key beforehand (`SessionConfig::preshared_key`),
+ * say by the matchmaking server that paired them.  It keys the sync
+ * handshake; once that has traded both peers' handshake nonces, everything
+ * else goes under a key derived from the two, for that session alone (see
+ * `EncryptedTransport::session`).  A sealed packet is
+ * `<nonce><ciphertext><tag>`: a random 12 byte nonce, then the compressed
+ * packet encrypted, then the 16 byte authentication tag.  Sealing is the
+ * last thing `Udp` does before sending and opening the first after
+ * receiving (bar a relay's header, which stays in the clear), so everything
+ * above it is none the wiser.
+ *
+ * This keeps packets secret and whole, not fresh: a recorded packet sent
+ * again in the same session still opens.  Sequence numbers drop most of
+ * those as old.  One recorded in another session doesn't, as its key is
+ * gone.
+ */
+
+use aes_gcm::{
+    aead::{Aead, NewAead},
+    Aes256Gcm, Key, Nonce,
+};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::{convert::TryInto, fmt};
+
+pub type PresharedKey = [u8; 32];
+
+pub const NONCE_SIZE: usize = 12;
+pub const TAG_SIZE: usize = 16;
+// What sealing adds to every packet.
+pub const SEAL_OVERHEAD: usize = NONCE_SIZE + TAG_SIZE;
+// HKDF's `info` for session keys, so they can't be mistaken for keys derived for anything else.
+const SESSION_KEY_INFO: &[u8] = b"ggpo-rs session key";
+
+#[derive(Clone)]
+pub struct EncryptedTransport {
+    key: PresharedKey,
+    cipher: Aes256Gcm,
+}
+
+// Leaves the key out.
+impl fmt::Debug for EncryptedTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptedTransport").finish()
+    }
+}
+
+impl EncryptedTransport {
+    pub fn new(key: &PresharedKey) -> Self {
+        EncryptedTransport {
+            key: *key,
+            cipher: Aes256Gcm::new(&Key::from(*key)),
+        }
+    }
+
+    /*
+     * The transport for one session between two peers, keyed with HKDF-SHA256
+     * from our key, salted with both peers' handshake nonces.  The nonces go
+     * in by value, so each end derives the same key whichever of them is
+     * `local`.
+     */
+    pub fn session(&self, local: u64, remote: u64) -> Self {
+        let (low, high) = (local.min(remote), local.max(remote));
+        let mut salt = [0; 16];
+        salt[..8].copy_from_slice(&low.to_le_bytes());
+        salt[8..].copy_from_slice(&high.to_le_bytes());
+        let mut key: PresharedKey = [0; 32];
+        Hkdf::<Sha256>::new(Some(&salt), &self.key)
+            .expand(SESSION_KEY_INFO, &mut key)
+            .expect("HKDF-SHA256 gives keys up to 8160 bytes long");
+        EncryptedTransport::new(&key)
+    }
+
+    pub fn seal(&self, packet: &[u8]) -> Vec<u8> {
+        let nonce: [u8; NONCE_SIZE] = rand::random();
+        let sealed = self
+            .cipher
+            .encrypt(&Nonce::from(nonce), packet)
+            .expect("AES-GCM seals any packet a datagram can hold");
+        let mut out = Vec::with_capacity(NONCE_SIZE + sealed.len());
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&sealed);
+        out
+    }
+
+    // The packet `seal` was given, or `None` if this isn't one it sealed under our key.
+    pub fn open(&self, sealed: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < SEAL_OVERHEAD {
+            return None;
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE);
+        let nonce: [u8; NONCE_SIZE] = nonce.try_into().ok()?;
+        self.cipher.decrypt(&Nonce::from(nonce), ciphertext).ok()
+    }
+}
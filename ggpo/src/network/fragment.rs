@@ -0,0 +1,201 @@
+/*
+ * Splitting packets too big for one datagram, and putting them back together.
+ *
+ * Only large state snapshots ever get this big: past the transport's
+ * `max_packet_size` once compressed (see `udp::MAX_UDP_PACKET_SIZE`).  `Udp`
+ * splits the encoded packet into `Fragment` messages of at most
+ * `FRAGMENT_SIZE` bytes each, and
+ * the receiving `Udp` holds on to the pieces until it has every one, then
+ * decodes the whole packet as if it had come in a single datagram.  Nothing
+ * is resent: a set missing a piece after `DEFAULT_FRAGMENT_TIMEOUT` is
+ * dropped, and it's up to whoever asked for the packet to ask again.
+ */
+
+use crate::network::udp_msg::{Fragment, MsgEnum, MsgType, UdpMsg, MAX_STATE_SNAPSHOT_SIZE};
+use bytes::Bytes;
+use log::{error, info};
+use std::{
+    mem::size_of,
+    net::SocketAddr,
+    time::{Duration, SystemTime},
+};
+
+// Bytes of the encoded packet each fragment carries.
+pub const FRAGMENT_SIZE: usize = 1024;
+// The most fragments a packet can need; anything claiming more is dropped.
+pub const MAX_FRAGMENTS: usize =
+    (MAX_STATE_SNAPSHOT_SIZE + size_of::<UdpMsg>()).div_ceil(FRAGMENT_SIZE);
+pub const DEFAULT_FRAGMENT_TIMEOUT: Duration = Duration::from_millis(1000);
+// Sets being reassembled at once.  A new one past this pushes out the oldest.
+pub const MAX_PENDING_FRAGMENT_SETS: usize = 8;
+
+// `packet`, already encoded, as the fragments that carry it.
+pub fn split(id: u16, packet: &[u8]) -> Vec<UdpMsg> {
+    let count = packet.len().div_ceil(FRAGMENT_SIZE);
+    assert!(count <= MAX_FRAGMENTS);
+    packet
+        .chunks(FRAGMENT_SIZE)
+        .enumerate()
+        .map(|(index, piece)| {
+            let mut msg = UdpMsg::new(MsgType::Fragment);
+            msg.message = MsgEnum::Fragment(Fragment {
+                id,
+                index: index as u16,
+                count: count as u16,
+                size: piece.len() as u16,
+                data: Bytes::copy_from_slice(piece),
+            });
+            msg
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+struct PartialPacket {
+    from: SocketAddr,
+    id: u16,
+    pieces: Vec<Option<Bytes>>,
+    received: usize,
+    started: SystemTime,
+}
+
+#[derive(Debug)]
+pub struct Reassembler {
+    partial: Vec<PartialPacket>,
+    timeout: Duration,
+    expired: usize,
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Self::new(DEFAULT_FRAGMENT_TIMEOUT)
+    }
+}
+
+impl Reassembler {
+    pub fn new(timeout: Duration) -> Self {
+        Reassembler {
+            partial: Vec::new(),
+            timeout,
+            expired: 0,
+        }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /*
+     * Takes a fragment `from` a peer, returning the encoded packet once this
+     * was the last piece missing.  Duplicates are ignored.
+     */
+    pub fn push(
+        &mut self,
+        from: SocketAddr,
+        fragment: &Fragment,
+        now: SystemTime,
+    ) -> Option<Vec<u8>> {
+        let count = fragment.count as usize;
+        if count == 0 || count > MAX_FRAGMENTS || fragment.index as usize >= count {
+            error!(
+                "dropping fragment {} of {} for packet {} from {}.\n",
+                fragment.index, fragment.count, fragment.id, from
+            );
+            return None;
+        }
+
+        let position = match self
+            .partial
+            .iter()
+            .position(|partial| partial.from == from && partial.id == fragment.id)
+        {
+            Some(position) if self.partial[position].pieces.len() == count => position,
+            // The id came round again with a different packet.
+            Some(position) => {
+                self.partial.remove(position);
+                self.start(from, fragment.id, count, now)
+            }
+            None => self.start(from, fragment.id, count, now),
+        };
+
+        let partial = &mut self.partial[position];
+        let piece = &mut partial.pieces[fragment.index as usize];
+        if piece.is_none() {
+            *piece = Some(fragment.data.clone());
+            partial.received += 1;
+        }
+        if partial.received < count {
+            return None;
+        }
+
+        let partial = self.partial.remove(position);
+        let mut packet = Vec::with_capacity(count * FRAGMENT_SIZE);
+        for piece in partial.pieces.iter().flatten() {
+            packet.extend_from_slice(piece);
+        }
+        Some(packet)
+    }
+
+    fn start(&mut self, from: SocketAddr, id: u16, count: usize, now: SystemTime) -> usize {
+        if self.partial.len() == MAX_PENDING_FRAGMENT_SETS {
+            let oldest = self.partial.remove(0);
+            info!(
+                "dropping packet {} from {}: too many packets in pieces.\n",
+                oldest.id, oldest.from
+            );
+            self.expired += 1;
+        }
+        self.partial.push(PartialPacket {
+            from,
+            id,
+            pieces: vec![None; count],
+            received: 0,
+            started: now,
+        });
+        self.partial.len() - 1
+    }
+
+    /*
+     * Drops every set that's been missing pieces for longer than the timeout.
+     * A clock that's gone backwards counts as no time having passed.
+     */
+    pub fn expire(&mut self, now: SystemTime) -> usize {
+        let timeout = self.timeout;
+        let before = self.partial.len();
+        self.partial.retain(|partial| {
+            let waited = now.duration_since(partial.started).unwrap_or_default();
+            let keep = waited < timeout;
+            if !keep {
+                info!(
+                    "dropping packet {} from {}: {} of {} pieces arrived.\n",
+                    partial.id,
+                    partial.from,
+                    partial.received,
+                    partial.pieces.len()
+                );
+            }
+            keep
+        });
+        let expired = before - self.partial.len();
+        self.expired += expired;
+        expired
+    }
+
+    // When the oldest packet still missing pieces is given up on.
+    pub fn next_expiry(&self) -> Option<SystemTime> {
+        self.partial
+            .iter()
+            .map(|partial| partial.started + self.timeout)
+            .min()
+    }
+
+    // Packets with some, but not all, of their pieces in.
+    pub fn pending(&self) -> usize {
+        self.partial.len()
+    }
+
+    // Packets given up on so far, for never getting all their pieces.
+    pub fn expired(&self) -> usize {
+        self.expired
+    }
+}
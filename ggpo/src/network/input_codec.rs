@@ -0,0 +1,150 @@
+/*
+ * The bits of an `Input` message.  Each frame is coded as its changes from
+ * the frame before: for every bit that changed for any of the players in the
+ * packet, a 1, then each player's new value for the bit, then the bit's
+ * index; a 0 ends the frame.  With one player that's GGPO's original
+ * encoding.  Local players on one host often change the same bits on the
+ * same frames (both pressing jump on the same cue), and then share the 1 and
+ * the index instead of sending one each.
+ */
+
+use crate::{
+    bitvector,
+    game_input::{Frame, GameInput, GAMEINPUT_MAX_BYTES},
+    ggpo::GGPO_MAX_PLAYERS,
+    network::udp_msg::MAX_COMPRESSED_BITS,
+};
+use bytes::Bytes;
+use thiserror::Error;
+
+// Bits that can't have come from `write_frame`, so none of the packet can be trusted.
+#[derive(Debug, Error, Copy, Clone, PartialEq, Eq)]
+pub enum InputCodecError {
+    #[error("the input bits end partway through a frame.")]
+    Truncated,
+    #[error("bit {button} is past the end of a {size} byte input.")]
+    ButtonOutOfRange { button: usize, size: usize },
+}
+
+// At least one player is somewhere else, or there'd be no one to send input to.
+pub const MAX_LOCAL_PLAYERS_PER_HOST: usize = GGPO_MAX_PLAYERS - 1;
+
+// Appends `current`'s changes from `previous`, one input per player in each.
+pub fn write_frame(
+    bits: &mut [u8],
+    offset: &mut usize,
+    previous: &[GameInput],
+    current: &[GameInput],
+) {
+    assert!(current.len() == previous.len() && current.len() <= MAX_LOCAL_PLAYERS_PER_HOST);
+    assert!(GAMEINPUT_MAX_BYTES * 8 <= 1 << bitvector::BITVECTOR_NIBBLE_SIZE);
+    let size = current.iter().map(|input| input.size).max().unwrap_or(0);
+    let changed = |i: usize| {
+        current
+            .iter()
+            .zip(previous.iter())
+            .any(|(current, previous)| current.value(i) != previous.value(i))
+    };
+    if current
+        .iter()
+        .zip(previous.iter())
+        .any(|(c, p)| c.bits != p.bits)
+    {
+        for i in (0..size * 8).filter(|&i| changed(i)) {
+            bitvector::set_bit(bits, offset);
+            for input in current.iter() {
+                if input.value(i) {
+                    bitvector::set_bit(bits, offset);
+                } else {
+                    bitvector::clear_bit(bits, offset);
+                }
+            }
+            bitvector::write_nibblet(bits, i, offset);
+        }
+    }
+    bitvector::clear_bit(bits, offset);
+}
+
+// The next of the first `num_bits` of `bits`.
+fn read_bit(bits: &[u8], offset: &mut usize, num_bits: usize) -> Result<bool, InputCodecError> {
+    if *offset >= num_bits.min(bits.len() * 8) {
+        return Err(InputCodecError::Truncated);
+    }
+    Ok(bitvector::read_bit(bits, offset) > 0)
+}
+
+/*
+ * Applies one frame written by `write_frame` to `inputs`, which hold the
+ * frame before, reading no further than the first `num_bits` of `bits`.
+ * On an error `inputs` may have been partly changed.
+ */
+pub fn read_frame(
+    bits: &[u8],
+    offset: &mut usize,
+    num_bits: usize,
+    inputs: &mut [GameInput],
+) -> Result<(), InputCodecError> {
+    let size = inputs.iter().map(|input| input.size).max().unwrap_or(0);
+    while read_bit(bits, offset, num_bits)? {
+        let mut on = [false; MAX_LOCAL_PLAYERS_PER_HOST];
+        for value in on.iter_mut().take(inputs.len()) {
+            *value = read_bit(bits, offset, num_bits)?;
+        }
+        let mut button = 0;
+        for i in 0..bitvector::BITVECTOR_NIBBLE_SIZE {
+            button |= (read_bit(bits, offset, num_bits)? as usize) << i;
+        }
+        if button >= size * 8 {
+            return Err(InputCodecError::ButtonOutOfRange { button, size });
+        }
+        for (input, on) in inputs.iter_mut().zip(on.iter()) {
+            // TODO: Fix the 1d -> 2d indexing going on here.
+            if *on {
+                input.set(button);
+            } else {
+                input.clear(button);
+            }
+        }
+    }
+    Ok(())
+}
+
+/*
+ * Codes `frames`, each holding one input per player, as changes from
+ * `previous`.  Returns the bits and how many of them are used.
+ */
+pub fn encode(previous: &[GameInput], frames: &[Vec<GameInput>]) -> (Bytes, usize) {
+    let mut bits = [0u8; MAX_COMPRESSED_BITS];
+    let mut offset = 0;
+    let mut last = previous.to_vec();
+    for frame in frames {
+        write_frame(&mut bits, &mut offset, &last, frame);
+        last = frame.clone();
+    }
+    (Bytes::copy_from_slice(&bits[..offset.div_ceil(8)]), offset)
+}
+
+/*
+ * The inverse of `encode`: every frame in the first `num_bits` of `bits`, one
+ * input per player, numbered on from `start_frame`.
+ */
+pub fn decode(
+    start_frame: Frame,
+    previous: &[GameInput],
+    bits: &[u8],
+    num_bits: usize,
+) -> Result<Vec<Vec<GameInput>>, InputCodecError> {
+    let mut frames = Vec::new();
+    let mut inputs = previous.to_vec();
+    let mut frame = start_frame;
+    let mut offset = 0;
+    while offset < num_bits {
+        read_frame(bits, &mut offset, num_bits, &mut inputs)?;
+        for input in inputs.iter_mut() {
+            input.frame = frame;
+        }
+        frames.push(inputs.clone());
+        frame = frame.next();
+    }
+    Ok(frames)
+}
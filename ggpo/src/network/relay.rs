@@ -0,0 +1,119 @@
+/*
+ * Connecting through a relay, for when hole punching can't open a path.
+ * Every packet goes to the relay server instead, behind a two byte peer id
+ * (big-endian) saying who it's for, and the relay passes it on behind the id
+ * of who it's from.  Peers on the other side of a relay are given to the
+ * session as `peer_address(id)`, so nothing above `Udp` knows the difference.
+ * `RelayTransport` does this as a `Transport`, which is how `Udp` uses it.
+ *
+ * The relay server isn't part of the crate.  It only has to:
+ *   on a datagram of just an id   remember that id lives at the sender
+ *   on  <to id><packet>            send <from id><packet> to `to`'s address
+ */
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+pub type PeerId = u16;
+
+pub const PEER_ID_SIZE: usize = 2;
+
+/*
+ * Something every packet passes through between the session and the socket.
+ * It decides where a packet really goes and what's put in front of it, and
+ * undoes that for what comes in, so the session only ever sees the addresses
+ * it knows its peers by.
+ */
+pub trait Transport: Send + Sync {
+    // The bytes put in front of every packet.
+    fn header_size(&self) -> usize;
+    // Why `address` can't be reached this way, if it can't.
+    fn check_peer_addr(&self, address: SocketAddr) -> Result<(), String>;
+    // What to send, and where, so datagrams for us can find us.  Safe to repeat.
+    fn announcement(&self) -> Option<(SocketAddr, Vec<u8>)>;
+    // Where `packet` for `destination` really goes, and what goes, or `None` if it can't.
+    fn outgoing(&self, destination: SocketAddr, packet: &[u8]) -> Option<(SocketAddr, Vec<u8>)>;
+    // Who a datagram from `from` is really from, or `None` if it isn't for the session.
+    fn incoming(&self, from: SocketAddr, datagram: &[u8]) -> Option<SocketAddr>;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RelayTransport {
+    pub server: SocketAddr,
+    // The id the relay assigned us, which other peers send to.
+    pub peer_id: PeerId,
+}
+
+impl RelayTransport {
+    pub const fn new(server: SocketAddr, peer_id: PeerId) -> Self {
+        RelayTransport { server, peer_id }
+    }
+
+    // What we send the relay so it knows where our id lives.
+    pub fn register(&self) -> [u8; PEER_ID_SIZE] {
+        self.peer_id.to_be_bytes()
+    }
+}
+
+impl Transport for RelayTransport {
+    fn header_size(&self) -> usize {
+        PEER_ID_SIZE
+    }
+
+    // Only relayed peers' addresses will do.
+    fn check_peer_addr(&self, address: SocketAddr) -> Result<(), String> {
+        match peer_id(&address) {
+            Some(_) => Ok(()),
+            None => Err(format!("{} isn't a relayed peer's address", address)),
+        }
+    }
+
+    fn announcement(&self) -> Option<(SocketAddr, Vec<u8>)> {
+        Some((self.server, self.register().to_vec()))
+    }
+
+    fn outgoing(&self, destination: SocketAddr, packet: &[u8]) -> Option<(SocketAddr, Vec<u8>)> {
+        let peer_id = peer_id(&destination)?;
+        Some((self.server, wrap(peer_id, packet)))
+    }
+
+    // Anything not from the relay, or without an id in front, isn't a peer's.
+    fn incoming(&self, from: SocketAddr, datagram: &[u8]) -> Option<SocketAddr> {
+        match unwrap(datagram) {
+            Some((peer_id, _)) if from == self.server => Some(peer_address(peer_id)),
+            _ => None,
+        }
+    }
+}
+
+/*
+ * The address a peer behind the relay goes by in the session.  Nothing can
+ * send from 0.0.0.0, so these never collide with a real peer.
+ */
+pub fn peer_address(peer_id: PeerId) -> SocketAddr {
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), peer_id)
+}
+
+// The id behind a `peer_address`, or `None` for a real address.
+pub fn peer_id(address: &SocketAddr) -> Option<PeerId> {
+    match address.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => Some(address.port()),
+        _ => None,
+    }
+}
+
+// `packet` with the id of the peer it's for in front.
+pub fn wrap(peer_id: PeerId, packet: &[u8]) -> Vec<u8> {
+    let mut wrapped = Vec::with_capacity(PEER_ID_SIZE + packet.len());
+    wrapped.extend_from_slice(&peer_id.to_be_bytes());
+    wrapped.extend_from_slice(packet);
+    wrapped
+}
+
+// Splits a datagram from the relay into who sent it and the packet.
+pub fn unwrap(datagram: &[u8]) -> Option<(PeerId, &[u8])> {
+    if datagram.len() <= PEER_ID_SIZE {
+        return None;
+    }
+    let peer_id = PeerId::from_be_bytes([datagram[0], datagram[1]]);
+    Some((peer_id, &datagram[PEER_ID_SIZE..]))
+}
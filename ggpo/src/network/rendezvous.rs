@@ -0,0 +1,203 @@
+/*
+ * Getting two peers behind NAT to talk.  Each registers a match id with a
+ * rendezvous server both can reach, which answers with the address it saw the
+ * other one come from.  Then both send to each other at once, so each NAT sees
+ * traffic go out before the other's arrives and lets it in.  All of this
+ * happens on the address the session will bind, so the holes it opens are the
+ * ones the session uses; `meet` closes its socket before returning.
+ *
+ * The server speaks one line of text per datagram:
+ *   we send   "register <match id>"
+ *   it sends  "peer <address>"
+ * and the peers send each other "punch <match id>", answered with
+ * "punched <match id>".
+ */
+
+use crate::network::udp;
+use log::info;
+use std::{
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+// How often we ask the server again while waiting for the other peer.
+pub const REGISTER_INTERVAL: Duration = Duration::from_millis(200);
+// How often we punch while the path to the peer isn't open yet.
+pub const PUNCH_INTERVAL: Duration = Duration::from_millis(50);
+pub const DEFAULT_SERVER_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_PUNCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+const MAX_DATAGRAM: usize = 512;
+
+#[derive(Debug, Error)]
+pub enum RendezvousError {
+    #[error("The rendezvous server at {0} didn't introduce a peer in time.")]
+    ServerTimeout(SocketAddr),
+    #[error("Couldn't open a path to the peer at {0} in time.")]
+    PunchTimeout(SocketAddr),
+    #[error("IO Error")]
+    Io {
+        #[from]
+        source: std::io::Error,
+    },
+}
+
+// Both ends of the path `meet` opened.  `peer` is what goes to `add_player`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Meeting {
+    pub local: SocketAddr,
+    pub peer: SocketAddr,
+}
+
+#[derive(Debug, Clone)]
+pub struct Rendezvous {
+    pub server: SocketAddr,
+    // Peers registering the same id are introduced to each other.
+    pub match_id: String,
+    // The port the session will bind afterwards.
+    pub local_port: u16,
+    // Whether the session will bind dual stack; see `SessionConfig::dual_stack`.
+    pub dual_stack: bool,
+    pub server_timeout: Duration,
+    pub punch_timeout: Duration,
+}
+
+// The socket `meet` works on, taking and giving addresses in the form the session keeps them.
+struct Socket {
+    socket: UdpSocket,
+    dual_stack: bool,
+}
+
+impl Socket {
+    fn send_to(&self, msg: &[u8], to: SocketAddr) -> std::io::Result<usize> {
+        let to = if self.dual_stack {
+            udp::to_dual_stack_addr(to)
+        } else {
+            to
+        };
+        self.socket.send_to(msg, to)
+    }
+
+    fn recv_until(
+        &self,
+        buf: &mut [u8],
+        until: Instant,
+    ) -> Result<Option<(usize, SocketAddr)>, RendezvousError> {
+        Ok(recv_until(&self.socket, buf, until)?
+            .map(|(len, from)| (len, udp::normalize_addr(from))))
+    }
+}
+
+impl Rendezvous {
+    pub fn new(server: SocketAddr, match_id: &str, local_port: u16) -> Self {
+        Self {
+            server,
+            match_id: match_id.to_string(),
+            local_port,
+            dual_stack: false,
+            server_timeout: DEFAULT_SERVER_TIMEOUT,
+            punch_timeout: DEFAULT_PUNCH_TIMEOUT,
+        }
+    }
+
+    /*
+     * Blocks until we've been introduced to a peer and each of us has heard
+     * the other, or until one of the timeouts runs out.
+     */
+    pub fn meet(&self) -> Result<Meeting, RendezvousError> {
+        // No other port will do, so no retries.
+        let (socket, dual_stack) = udp::create_session_socket(self.local_port, self.dual_stack, 0)?;
+        socket.set_nonblocking(false)?;
+        let local = socket.local_addr()?;
+        let socket = Socket { socket, dual_stack };
+        let peer = self.register(&socket)?;
+        info!("Rendezvous introduced us to {}.\n", peer);
+        self.punch(&socket, peer)?;
+        info!("Opened a path to {} from port {}.\n", peer, local.port());
+        Ok(Meeting { local, peer })
+    }
+
+    /*
+     * Anything but the server's introduction, like a reply we can't read or
+     * a stranger's datagram, is ignored; only the deadline ends the wait.
+     */
+    fn register(&self, socket: &Socket) -> Result<SocketAddr, RendezvousError> {
+        let request = format!("register {}", self.match_id);
+        let deadline = Instant::now() + self.server_timeout;
+        let mut buf = [0; MAX_DATAGRAM];
+        while Instant::now() < deadline {
+            socket.send_to(request.as_bytes(), self.server)?;
+            let resend_at = Instant::now() + REGISTER_INTERVAL;
+            while let Some((len, from)) = socket.recv_until(&mut buf, resend_at.min(deadline))? {
+                if from != self.server {
+                    continue;
+                }
+                let reply = String::from_utf8_lossy(&buf[..len]);
+                match reply.strip_prefix("peer ").map(str::parse) {
+                    Some(Ok(peer)) => return Ok(peer),
+                    _ => info!("Ignoring rendezvous reply {:?}.\n", reply),
+                }
+            }
+        }
+        Err(RendezvousError::ServerTimeout(self.server))
+    }
+
+    /*
+     * Done once the peer has answered one of our punches, so our packets get
+     * through, and we've answered one of theirs, so theirs do too.
+     */
+    fn punch(&self, socket: &Socket, peer: SocketAddr) -> Result<(), RendezvousError> {
+        let punch = format!("punch {}", self.match_id);
+        let punched = format!("punched {}", self.match_id);
+        let deadline = Instant::now() + self.punch_timeout;
+        let mut answered = false;
+        let mut heard_back = false;
+        let mut buf = [0; MAX_DATAGRAM];
+        while Instant::now() < deadline {
+            if !heard_back {
+                socket.send_to(punch.as_bytes(), peer)?;
+            }
+            let resend_at = Instant::now() + PUNCH_INTERVAL;
+            while let Some((len, from)) = socket.recv_until(&mut buf, resend_at.min(deadline))? {
+                if from != peer {
+                    continue;
+                }
+                let msg = &buf[..len];
+                if msg == punch.as_bytes() {
+                    socket.send_to(punched.as_bytes(), peer)?;
+                    answered = true;
+                } else if msg == punched.as_bytes() {
+                    heard_back = true;
+                }
+                if answered && heard_back {
+                    return Ok(());
+                }
+            }
+        }
+        Err(RendezvousError::PunchTimeout(peer))
+    }
+}
+
+// The next datagram, or `None` once `until` passes without one.
+fn recv_until(
+    socket: &UdpSocket,
+    buf: &mut [u8],
+    until: Instant,
+) -> Result<Option<(usize, SocketAddr)>, RendezvousError> {
+    let now = Instant::now();
+    if now >= until {
+        return Ok(None);
+    }
+    socket.set_read_timeout(Some(until - now))?;
+    match socket.recv_from(buf) {
+        Ok(received) => Ok(Some(received)),
+        Err(e)
+            if e.kind() == std::io::ErrorKind::WouldBlock
+                || e.kind() == std::io::ErrorKind::TimedOut =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
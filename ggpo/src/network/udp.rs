@@ -1,27 +1,60 @@
-use crate::network::udp_msg::UdpMsg;
+use crate::network::{
+    clock::{Clock, SystemClock},
+    fragment::{self, Reassembler, FRAGMENT_SIZE, MAX_FRAGMENTS},
+    relay::{RelayTransport, Transport},
+    udp_msg::{MsgEnum, MsgType, UdpMsg, MAX_COMPRESSED_BITS},
+};
 
 // use async_mutex::Mutex;
 // use async_net::UdpSocket;
 // use async_trait::async_trait;
 // use blocking::unblock;
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use log::{error, info};
 use mio::{net::UdpSocket, Interest, Poll, Token};
-use parking_lot::Mutex;
+use parking_lot::{Condvar, Mutex};
 use std::{
-    net::{IpAddr, Ipv4Addr, SocketAddr},
-    ops::Deref,
-    sync::Arc,
+    collections::VecDeque,
+    mem::size_of,
+    net::{self, IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Arc, Weak},
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
 };
 
 use thiserror::Error;
 
+#[cfg(feature = "encryption")]
+use crate::network::encryption::{self, EncryptedTransport};
+#[cfg(feature = "encryption")]
+use std::collections::HashMap;
+
 pub const ZSTD_LEVEL: i32 = 7;
+/*
+ * The largest datagram we send, transport header and all, unless the transport
+ * is given another limit with `Udp::set_max_packet_size`.  It stays under
+ * the 1280 byte minimum IPv6 MTU once the IP and UDP headers are on, so
+ * nothing along the way has to split it.  A compressed packet over the limit
+ * goes in fragments (see `network::fragment`), one that can't be made to fit
+ * is refused with `PacketTooLarge`, and the receive buffer is the same size,
+ * so both ends need the same limit.
+ */
+pub const MAX_UDP_PACKET_SIZE: usize = 1200;
+// The smallest limit a transport takes: room for a fragment, should compressing it make it grow.
+pub const MIN_UDP_PACKET_SIZE: usize = FRAGMENT_SIZE + 128;
+// The most a UDP datagram can carry over IPv4.
+pub const MAX_UDP_PAYLOAD: usize = 65507;
+// Large enough for any encoded `UdpMsg`, input bits included.
+const DECODE_BUFFER_SIZE: usize = size_of::<UdpMsg>() + MAX_COMPRESSED_BITS;
+// Packets `send_to` will hold for the send task before refusing more.
+pub const DEFAULT_SEND_QUEUE_CAPACITY: usize = 256;
+// How long the send task waits before retrying a socket that would block.
+const SEND_RETRY_DELAY: Duration = Duration::from_millis(1);
 
 // #[async_trait(?Send)]
 // #[async_trait()]
 pub trait UdpCallback {
-    fn on_msg(&mut self, from: &SocketAddr, msg: &UdpMsg, len: usize) -> Result<(), String>;
+    fn on_msg(&mut self, from: &SocketAddr, msg: UdpMsg, len: usize) -> Result<(), String>;
 }
 
 #[derive(Debug, Error)]
@@ -42,13 +75,24 @@ pub enum UdpError {
     },
     #[error("Callback error {0}")]
     Callback(String),
+    #[error("Send queue is full ({capacity} packets).")]
+    SendQueueFull { capacity: usize },
+    #[error("Packet of {size} bytes won't fit in datagrams of at most {limit}.")]
+    PacketTooLarge { size: usize, limit: usize },
+    #[error("Packet size limit {0} is out of range.")]
+    PacketSizeOutOfRange(usize),
+    #[error("Datagram from {from} failed authentication.")]
+    Unauthenticated { from: SocketAddr },
+    #[error("Datagram from {from} didn't decode: {reason}")]
+    Undecodable { from: SocketAddr, reason: String },
 }
 
-fn create_socket(socket_address: SocketAddr, retries: usize) -> std::io::Result<UdpSocket> {
+fn create_socket(socket_address: SocketAddr, retries: usize) -> std::io::Result<net::UdpSocket> {
     for port in (socket_address.port() as usize)..(socket_address.port() as usize) + retries + 1 {
-        match UdpSocket::bind(SocketAddr::new(socket_address.ip(), port as u16)) {
+        match net::UdpSocket::bind(SocketAddr::new(socket_address.ip(), port as u16)) {
             Ok(soc) => {
                 info!("Udp bound to port: {}.\n", port);
+                soc.set_nonblocking(true)?;
                 return Ok(soc);
             }
             Err(error) => {
@@ -65,14 +109,253 @@ fn create_socket(socket_address: SocketAddr, retries: usize) -> std::io::Result<
     ))
 }
 
+/*
+ * The socket a session listens on `port` with: dual stack if it asked to be
+ * and the platform allows, IPv4 otherwise, and whether it got dual stack.
+ * `rendezvous` binds the same one, so the holes it punches are the session's.
+ */
+pub(crate) fn create_session_socket(
+    port: u16,
+    dual_stack: bool,
+    retries: usize,
+) -> std::io::Result<(net::UdpSocket, bool)> {
+    if dual_stack {
+        match create_dual_stack_socket(port, retries) {
+            Ok(socket) => return Ok((socket, true)),
+            Err(e) => info!("No dual-stack socket ({}); using IPv4.\n", e),
+        }
+    }
+    let socket = create_socket(
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), port),
+        retries,
+    )?;
+    Ok((socket, false))
+}
+
+/*
+ * Binds `[::]` with IPV6_V6ONLY off, so the one socket takes both native
+ * IPv6 peers and IPv4 ones (as v4-mapped addresses).  Fails where the
+ * platform has no IPv6 or won't turn the option off.
+ */
+fn create_dual_stack_socket(port: u16, retries: usize) -> std::io::Result<net::UdpSocket> {
+    use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+
+    let mut last_error = None;
+    for port in (port as usize)..(port as usize) + retries + 1 {
+        let socket = Socket::new(Domain::ipv6(), Type::dgram(), Some(Protocol::udp()))?;
+        socket.set_only_v6(false)?;
+        let address = SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), port as u16);
+        match socket.bind(&SockAddr::from(address)) {
+            Ok(()) => {
+                info!("Udp bound dual-stack to port: {}.\n", port);
+                let socket = socket.into_udp_socket();
+                socket.set_nonblocking(true)?;
+                return Ok(socket);
+            }
+            Err(error) => {
+                error!("Failed to bind to socket. {:?}", error);
+                last_error = Some(error);
+            }
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::Other, "no port to bind dual-stack.")
+    }))
+}
+
+// The only messages sealed under the pre-shared key once a session key is set.
+#[cfg(feature = "encryption")]
+fn is_handshake(packet_type: MsgType) -> bool {
+    matches!(packet_type, MsgType::SyncRequest | MsgType::SyncReply)
+}
+
+/*
+ * The one form we keep a peer's address in: IPv4 peers as plain IPv4, even
+ * when a dual-stack socket reports them v4-mapped (`::ffff:a.b.c.d`), so the
+ * address a peer is configured with matches the one its packets come from.
+ */
+pub fn normalize_addr(address: SocketAddr) -> SocketAddr {
+    match address {
+        SocketAddr::V6(v6) => match v6.ip().segments() {
+            [0, 0, 0, 0, 0, 0xffff, high, low] => SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::new(
+                    (high >> 8) as u8,
+                    high as u8,
+                    (low >> 8) as u8,
+                    low as u8,
+                )),
+                v6.port(),
+            ),
+            _ => address,
+        },
+        v4 => v4,
+    }
+}
+
+// Anything a stray datagram can fail to decompress or decode with.
+fn undecodable(from: SocketAddr, reason: impl std::fmt::Display) -> UdpError {
+    UdpError::Undecodable {
+        from,
+        reason: reason.to_string(),
+    }
+}
+
+// A dual-stack socket can only send to IPv6 addresses, so IPv4 peers go v4-mapped.
+pub(crate) fn to_dual_stack_addr(address: SocketAddr) -> SocketAddr {
+    match address {
+        SocketAddr::V4(v4) => SocketAddr::new(IpAddr::V6(v4.ip().to_ipv6_mapped()), v4.port()),
+        v6 => v6,
+    }
+}
+
+// One compressed packet on its way to the send task.
+struct Outgoing {
+    packet: Vec<u8>,
+    destinations: Vec<SocketAddr>,
+    packet_type: MsgType,
+}
+
+#[derive(Default)]
+struct SendQueueState {
+    packets: VecDeque<Outgoing>,
+    // Destinations a send failed for, until `take_send_failures`.
+    failed: Vec<SocketAddr>,
+    // Set by `close`: the send task sends what's left, then exits.
+    closed: bool,
+}
+
+#[derive(Default)]
+struct SendQueue {
+    state: Mutex<SendQueueState>,
+    ready: Condvar,
+}
+
+impl SendQueue {
+    fn close(&self) {
+        self.state.lock().closed = true;
+        self.ready.notify_one();
+    }
+}
+
+/*
+ * The send task: takes packets off the queue in the order they were queued
+ * and writes them to the socket.  A socket that would block is retried here,
+ * where it only holds up other packets, not the game loop.
+ */
+fn send_loop(queue: Arc<SendQueue>, socket: net::UdpSocket) {
+    loop {
+        let outgoing = {
+            let mut state = queue.state.lock();
+            loop {
+                if let Some(outgoing) = state.packets.pop_front() {
+                    break outgoing;
+                }
+                if state.closed {
+                    return;
+                }
+                queue.ready.wait(&mut state);
+            }
+        };
+
+        for destination in outgoing.destinations.iter() {
+            let sent = loop {
+                match socket.send_to(&outgoing.packet, *destination) {
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        thread::sleep(SEND_RETRY_DELAY)
+                    }
+                    sent => break sent,
+                }
+            };
+            if !log_send(&outgoing, *destination, sent) {
+                queue.state.lock().failed(*destination);
+            }
+        }
+    }
+}
+
+impl SendQueueState {
+    fn failed(&mut self, destination: SocketAddr) {
+        if !self.failed.contains(&destination) {
+            self.failed.push(destination);
+        }
+    }
+}
+
+// Returns whether the send went through.
+fn log_send(outgoing: &Outgoing, destination: SocketAddr, sent: std::io::Result<usize>) -> bool {
+    match sent {
+        Ok(resp) => {
+            info!(
+                "sent packet length {} to {}:{} (resp:{}).\n",
+                outgoing.packet.len(),
+                destination.ip(),
+                destination.port(),
+                resp
+            );
+            ggpo_event!(
+                direction = "send",
+                peer = %destination,
+                msg_type = ?outgoing.packet_type,
+                bytes = outgoing.packet.len(),
+                "packet"
+            );
+            true
+        }
+        Err(e) => {
+            error!("failed to send packet to {}: {:?}\n", destination, e);
+            false
+        }
+    }
+}
+
+/*
+ * The transport shares its callbacks rather than borrowing them, so a `Udp`
+ * carries no lifetime and can be moved onto whatever thread or task runs the
+ * poll loop.  It only holds them weakly: the callbacks are usually the session
+ * that owns the transport, and a strong reference back would keep both alive
+ * for good.
+ */
 pub struct Udp<T: UdpCallback> {
     // Network transmission information
     socket: Option<UdpSocket>,
+    // A handle on the same socket, for the send task.
+    send_socket: Option<net::UdpSocket>,
+    send_queue: Arc<SendQueue>,
+    send_queue_capacity: usize,
+    send_task: Option<JoinHandle<()>>,
+    // Asked for with `set_dual_stack`, before `init`.
+    want_dual_stack: bool,
+    // Whether `init` got a dual-stack socket, or fell back to IPv4.
+    dual_stack: bool,
+    // Set with `set_transport` or `set_relay`: every packet goes through this.
+    transport: Option<Box<dyn Transport>>,
+    // Set with `set_encryption`: every packet is sealed with this, or a session key from it.
+    #[cfg(feature = "encryption")]
+    encryption: Option<EncryptedTransport>,
+    // Each peer's session key, and the nonces it came from; see `set_session_nonces`.
+    #[cfg(feature = "encryption")]
+    session_keys: HashMap<SocketAddr, ((u64, u64), EncryptedTransport)>,
 
     // state management
-    callbacks: Option<Arc<Mutex<T>>>,
+    callbacks: Option<Weak<Mutex<T>>>,
 
     poll: Option<Arc<Mutex<Poll>>>,
+
+    /*
+     * Packets are decompressed into the front of this buffer and split off,
+     * so each received `UdpMsg` can keep slices of its packet (the input bits)
+     * without copying them.  The space is reclaimed once those are dropped.
+     */
+    decode_buffer: BytesMut,
+    // Packets arriving in pieces; see `network::fragment`.
+    reassembler: Reassembler,
+    // What the reassembler's timeout goes by; see `set_clock`.
+    clock: Arc<dyn Clock>,
+    next_fragment_id: u16,
+    // See `MAX_UDP_PACKET_SIZE`.
+    max_packet_size: usize,
+    // Datagrams are read into this, `max_packet_size` long.
+    recv_buffer: Vec<u8>,
 }
 
 impl<T: UdpCallback> Default for Udp<T> {
@@ -85,8 +368,25 @@ impl<T: UdpCallback> Udp<T> {
     pub fn new() -> Self {
         let u = Udp {
             socket: None,
+            send_socket: None,
+            send_queue: Default::default(),
+            send_queue_capacity: DEFAULT_SEND_QUEUE_CAPACITY,
+            send_task: None,
+            want_dual_stack: false,
+            dual_stack: false,
+            transport: None,
+            #[cfg(feature = "encryption")]
+            encryption: None,
+            #[cfg(feature = "encryption")]
+            session_keys: HashMap::new(),
             callbacks: None,
             poll: None,
+            decode_buffer: BytesMut::new(),
+            reassembler: Reassembler::default(),
+            clock: Arc::new(SystemClock),
+            next_fragment_id: 0,
+            max_packet_size: MAX_UDP_PACKET_SIZE,
+            recv_buffer: vec![0; MAX_UDP_PACKET_SIZE],
         };
 
         return u;
@@ -97,12 +397,12 @@ impl<T: UdpCallback> Udp<T> {
         poll: Arc<Mutex<Poll>>,
         callbacks: Arc<Mutex<T>>,
     ) -> Result<(), UdpError> {
-        self.callbacks = Some(callbacks);
+        self.callbacks = Some(Arc::downgrade(&callbacks));
         info!("binding udp socket to port {}.\n", port);
-        let mut socket = create_socket(
-            SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), port),
-            3,
-        )?;
+        let (socket, dual_stack) = create_session_socket(port, self.want_dual_stack, 3)?;
+        self.dual_stack = dual_stack;
+        self.send_socket = Some(socket.try_clone()?);
+        let mut socket = UdpSocket::from_std(socket);
         // TODO: _poll->RegisterLoop(this);
         poll.lock().registry().register(
             &mut socket,
@@ -112,59 +412,563 @@ impl<T: UdpCallback> Udp<T> {
 
         self.socket = Some(socket);
         self.poll = Some(poll.clone());
+        self.announce()?;
+
+        Ok(())
+    }
+
+    /*
+     * Seals every packet sent, and opens every one received, with
+     * `encryption` from now on; see `network::encryption`.  Packets that
+     * don't open are dropped.
+     */
+    #[cfg(feature = "encryption")]
+    pub fn set_encryption(&mut self, encryption: Option<EncryptedTransport>) {
+        self.encryption = encryption;
+        self.session_keys.clear();
+    }
+
+    /*
+     * Once the handshake with `peer` has traded nonces, everything but the
+     * handshake itself goes to and from it under a key for that session
+     * alone; see `EncryptedTransport::session`.  A packet from `peer` that
+     * doesn't open under it is dropped, bar a sync request or reply under the
+     * pre-shared key, as a peer starting a new session sends.
+     */
+    #[cfg(feature = "encryption")]
+    pub fn set_session_nonces(&mut self, peer: SocketAddr, local: u64, remote: u64) {
+        let encryption = match &self.encryption {
+            Some(encryption) => encryption,
+            None => return,
+        };
+        let peer = normalize_addr(peer);
+        if let Some((nonces, _)) = self.session_keys.get(&peer) {
+            if *nonces == (local, remote) {
+                return;
+            }
+        }
+        let session = encryption.session(local, remote);
+        self.session_keys.insert(peer, ((local, remote), session));
+    }
 
+    /*
+     * Sends everything through `relay` from now on, to peers added with
+     * `relay::peer_address`.  Set it before `init`, which registers with the
+     * relay, or call `announce` after.
+     */
+    pub fn set_relay(&mut self, relay: Option<RelayTransport>) {
+        if let Some(relay) = &relay {
+            info!(
+                "reaching peers as peer {} through the relay at {}.\n",
+                relay.peer_id, relay.server
+            );
+        }
+        self.set_transport(relay.map(|relay| Box::new(relay) as Box<dyn Transport>));
+    }
+
+    /*
+     * Passes every packet through `transport` from now on.  Set it before
+     * `init`, which sends its announcement, or call `announce` after.
+     */
+    pub fn set_transport(&mut self, transport: Option<Box<dyn Transport>>) {
+        self.transport = transport;
+    }
+
+    // Sends the transport's announcement, if it has one, such as registering with a relay.  Safe to repeat.
+    pub fn announce(&mut self) -> Result<(), UdpError> {
+        let (address, announcement) = match self.transport.as_ref().and_then(|t| t.announcement()) {
+            Some(announcement) => announcement,
+            None => return Ok(()),
+        };
+        let address = if self.dual_stack {
+            to_dual_stack_addr(address)
+        } else {
+            address
+        };
+        let socket = self.socket.as_ref().ok_or(UdpError::SocketUninit)?;
+        info!("announcing ourselves to {}.\n", address);
+        socket.send_to(&announcement, address)?;
         Ok(())
     }
 
-    pub fn send_to(&mut self, msg: Arc<UdpMsg>, destination: &SocketAddr) -> Result<(), UdpError> {
+    /*
+     * Starts the thread that does the actual sending.  Until it runs, packets
+     * passed to `send_to` wait in the queue.
+     */
+    pub fn start_send_task(&mut self) -> Result<(), UdpError> {
+        if self.send_task.is_some() {
+            return Ok(());
+        }
+        let socket = self
+            .send_socket
+            .as_ref()
+            .ok_or(UdpError::SocketUninit)?
+            .try_clone()?;
+        let queue = self.send_queue.clone();
+        self.send_task = Some(
+            thread::Builder::new()
+                .name("ggpo-udp-send".to_string())
+                .spawn(move || send_loop(queue, socket))?,
+        );
+        Ok(())
+    }
+
+    /*
+     * Sends what's queued from the calling thread, for transports run without
+     * a send task.  Stops at the first send that would block, leaving the
+     * rest queued for next time, and returns how many packets went out.  Does
+     * nothing while a send task is running.
+     */
+    pub fn flush_send_queue(&mut self) -> Result<usize, UdpError> {
+        if self.send_task.is_some() {
+            return Ok(0);
+        }
+        let socket = self.send_socket.as_ref().ok_or(UdpError::SocketUninit)?;
+        let mut state = self.send_queue.state.lock();
+        let mut flushed = 0;
+        while let Some(mut outgoing) = state.packets.pop_front() {
+            while let Some(&destination) = outgoing.destinations.first() {
+                match socket.send_to(&outgoing.packet, destination) {
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        state.packets.push_front(outgoing);
+                        return Ok(flushed);
+                    }
+                    sent => {
+                        if !log_send(&outgoing, destination, sent) {
+                            state.failed(destination);
+                        }
+                    }
+                }
+                outgoing.destinations.remove(0);
+            }
+            flushed += 1;
+        }
+        Ok(flushed)
+    }
+
+    // Every destination a send has failed for since the last call.
+    pub fn take_send_failures(&mut self) -> Vec<SocketAddr> {
+        std::mem::take(&mut self.send_queue.state.lock().failed)
+    }
+
+    /*
+     * Why we couldn't send to `address`, if we couldn't: it has no port, or no
+     * host, or it's a multicast or broadcast address, or it's IPv6 and our
+     * socket isn't.  Through a transport, it's the transport's say.
+     */
+    pub fn check_peer_addr(&self, address: SocketAddr) -> Result<(), String> {
+        if let Some(transport) = &self.transport {
+            return transport.check_peer_addr(address);
+        }
+        let address = normalize_addr(address);
+        let problem = match address.ip() {
+            _ if address.port() == 0 => "has no port",
+            ip if ip.is_unspecified() => "has no host",
+            ip if ip.is_multicast() => "is a multicast address",
+            IpAddr::V4(ip) if ip.is_broadcast() => "is a broadcast address",
+            IpAddr::V6(_) if !self.dual_stack => "is IPv6, but the socket is IPv4 only",
+            _ => return Ok(()),
+        };
+        Err(format!("{} {}", address, problem))
+    }
+
+    /*
+     * Has `init` bind one dual-stack IPv6 socket that takes IPv4 peers too,
+     * in place of the IPv4 one.  Where that isn't supported `init` falls back
+     * to IPv4; `is_dual_stack` says which it got.
+     */
+    pub fn set_dual_stack(&mut self, enabled: bool) {
+        self.want_dual_stack = enabled;
+    }
+
+    pub fn is_dual_stack(&self) -> bool {
+        self.dual_stack
+    }
+
+    // How many packets may wait for the send task before `send_to` refuses more.
+    pub fn set_send_queue_capacity(&mut self, capacity: usize) {
+        self.send_queue_capacity = capacity;
+    }
+
+    /*
+     * The largest datagram this transport sends or takes, between
+     * `MIN_UDP_PACKET_SIZE` and `MAX_UDP_PAYLOAD`; see `MAX_UDP_PACKET_SIZE`.
+     * Peers have to agree on it: a datagram longer than the receiver's limit
+     * is cut short and dropped.
+     */
+    pub fn set_max_packet_size(&mut self, size: usize) -> Result<(), UdpError> {
+        if !(MIN_UDP_PACKET_SIZE..=MAX_UDP_PAYLOAD).contains(&size) {
+            return Err(UdpError::PacketSizeOutOfRange(size));
+        }
+        self.max_packet_size = size;
+        self.recv_buffer = vec![0; size];
+        Ok(())
+    }
+
+    pub fn max_packet_size(&self) -> usize {
+        self.max_packet_size
+    }
+
+    pub fn recv_buffer_size(&self) -> usize {
+        self.recv_buffer.len()
+    }
+
+    // Packets queued that the send task hasn't got to yet.
+    pub fn queued_sends(&self) -> usize {
+        self.send_queue.state.lock().packets.len()
+    }
+
+    /*
+     * Stops listening and releases the port, once the send task has sent
+     * everything already queued.
+     */
+    pub fn close(&mut self) -> Result<(), UdpError> {
+        self.send_queue.close();
+        if let Some(task) = self.send_task.take() {
+            if task.join().is_err() {
+                error!("udp send task panicked.\n");
+            }
+        }
+        self.send_socket = None;
+        if let (Some(mut socket), Some(poll)) = (self.socket.take(), self.poll.as_ref()) {
+            poll.lock().registry().deregister(&mut socket)?;
+            info!("closed udp socket.\n");
+        }
+        Ok(())
+    }
+
+    /*
+     * Serializes and compresses `msg` once, and queues the same bytes for
+     * every address in `destinations`.  Returns without waiting for the
+     * socket, with the size of each copy as it will go on the wire (UDP and
+     * IP headers aside); the send task logs any send that fails.  If the task has fallen
+     * so far behind that the queue is full, the packet is refused with
+     * `SendQueueFull` instead, and it's up to the caller to try again later.
+     */
+    pub fn send_to(
+        &mut self,
+        msg: Arc<UdpMsg>,
+        destinations: &[SocketAddr],
+    ) -> Result<usize, UdpError> {
         /*
         TODO: Can we store the serialized result into a BytesMut/buffer and be compressed in place to avoid another allocation?
-        TODO: Worthwhile to spawn here?
         TODO: Will doing the above actually improve performance?
          */
-        let serialized = bincode::serialize(msg.clone().deref())?;
+        if self.socket.is_none() {
+            return Err(UdpError::SocketUninit);
+        }
+        let serialized = msg.encode()?;
         let compressed = zstd::block::compress(&serialized, ZSTD_LEVEL)?;
+        // What's left of a datagram once a transport's header, and any seal, is on.
+        let limit = self.max_packet_size - self.header_size() - self.seal_overhead();
+        // Too big for one datagram, so it goes in pieces, each compressed on its own.
+        let packets = if compressed.len() > limit {
+            if serialized.len() > MAX_FRAGMENTS * FRAGMENT_SIZE {
+                return Err(UdpError::PacketTooLarge {
+                    size: serialized.len(),
+                    limit: MAX_FRAGMENTS * FRAGMENT_SIZE,
+                });
+            }
+            let id = self.next_fragment_id;
+            self.next_fragment_id = self.next_fragment_id.wrapping_add(1);
+            fragment::split(id, &serialized)
+                .iter()
+                .map(|piece| Ok(zstd::block::compress(&piece.encode()?, ZSTD_LEVEL)?))
+                .collect::<Result<Vec<_>, UdpError>>()?
+        } else {
+            vec![compressed]
+        };
+        if let Some(packet) = packets.iter().find(|packet| packet.len() > limit) {
+            error!(
+                "a {:?} packet of {} bytes is over the {} byte limit; not sending.\n",
+                msg.header.packet_type,
+                packet.len(),
+                limit
+            );
+            return Err(UdpError::PacketTooLarge {
+                size: packet.len(),
+                limit,
+            });
+        }
 
-        let resp = self
-            .socket
-            .as_ref()
-            .ok_or(UdpError::SocketUninit)?
-            .send_to(&compressed, *destination)?;
-
-        info!(
-            "sent packet length {} to {}:{} (resp:{}).\n",
-            compressed.len(),
-            destination.ip(),
-            destination.port(),
-            resp
-        );
-        Ok(())
+        let packets: Vec<(Vec<u8>, Vec<SocketAddr>)> = packets
+            .into_iter()
+            .map(|packet| (packet, destinations.to_vec()))
+            .collect();
+        // Each destination may have a session key of its own, so each gets its own packet.
+        #[cfg(feature = "encryption")]
+        let packets = match &self.encryption {
+            Some(encryption) => {
+                let mut sealed = Vec::with_capacity(packets.len() * destinations.len());
+                for (packet, destinations) in packets.iter() {
+                    for &destination in destinations.iter() {
+                        let packet =
+                            self.seal(encryption, packet, destination, msg.header.packet_type);
+                        sealed.push((packet, vec![destination]));
+                    }
+                }
+                sealed
+            }
+            None => packets,
+        };
+
+        let mut state = self.send_queue.state.lock();
+        // A packet in more pieces than the queue holds still goes, once it's empty.
+        if state.packets.len() + packets.len() > self.send_queue_capacity
+            && !state.packets.is_empty()
+        {
+            error!(
+                "send queue full ({} packets); refusing a {:?}.\n",
+                self.send_queue_capacity, msg.header.packet_type
+            );
+            return Err(UdpError::SendQueueFull {
+                capacity: self.send_queue_capacity,
+            });
+        }
+        let wire_size: usize = packets
+            .iter()
+            .map(|(packet, _)| packet.len() + self.header_size())
+            .sum();
+        let dual_stack = self.dual_stack;
+        let on_socket = |address: SocketAddr| {
+            if dual_stack {
+                to_dual_stack_addr(address)
+            } else {
+                address
+            }
+        };
+        for (packet, destinations) in packets {
+            if let Some(transport) = &self.transport {
+                // Each peer may need its own header, so each gets its own packet.
+                for destination in destinations.iter() {
+                    let (address, packet) = match transport.outgoing(*destination, &packet) {
+                        Some(outgoing) => outgoing,
+                        None => {
+                            error!("{} can't be reached; not sending.\n", destination);
+                            continue;
+                        }
+                    };
+                    state.packets.push_back(Outgoing {
+                        packet,
+                        destinations: vec![on_socket(address)],
+                        packet_type: msg.header.packet_type,
+                    });
+                }
+            } else {
+                state.packets.push_back(Outgoing {
+                    packet,
+                    destinations: destinations.iter().copied().map(on_socket).collect(),
+                    packet_type: msg.header.packet_type,
+                });
+            }
+        }
+        self.send_queue.ready.notify_one();
+        Ok(wire_size)
     }
 
     pub fn get_msg(&mut self) -> Result<(UdpMsg, usize, SocketAddr), UdpError> {
-        let mut recv_buf = BytesMut::new();
-        let (len, recv_address) = self
-            .socket
-            .as_ref()
-            .ok_or(UdpError::SocketUninit)?
-            .recv_from(recv_buf.as_mut())?;
+        self.reassembler.expire(self.clock.now());
+        loop {
+            let (msg, len, recv_address) = self.get_datagram()?;
+            let fragment = match &msg.message {
+                MsgEnum::Fragment(fragment) => fragment,
+                _ => return Ok((msg, len, recv_address)),
+            };
+            // Nothing to hand on until the last piece is in.
+            if let Some(packet) = self
+                .reassembler
+                .push(recv_address, fragment, self.clock.now())
+            {
+                let len = packet.len();
+                let msg = UdpMsg::decode(Bytes::from(packet))
+                    .map_err(|e| undecodable(recv_address, e))?;
+                return Ok((msg, len, recv_address));
+            }
+        }
+    }
+
+    // Under `destination`'s session key, unless it's the handshake or there isn't one yet.
+    #[cfg(feature = "encryption")]
+    fn seal(
+        &self,
+        encryption: &EncryptedTransport,
+        packet: &[u8],
+        destination: SocketAddr,
+        packet_type: MsgType,
+    ) -> Vec<u8> {
+        match self.session_keys.get(&normalize_addr(destination)) {
+            Some((_, session)) if !is_handshake(packet_type) => session.seal(packet),
+            _ => encryption.seal(packet),
+        }
+    }
+
+    /*
+     * A packet from `from`, opened under its session key, or the pre-shared
+     * key if it has none yet.  Failing both, under any session key, in case
+     * the peer's address has changed under it.  Also whether the packet came
+     * under the pre-shared key after the session key was set, when all that's
+     * allowed is the handshake for a new session.
+     */
+    #[cfg(feature = "encryption")]
+    fn open(
+        encryption: &EncryptedTransport,
+        session_keys: &HashMap<SocketAddr, ((u64, u64), EncryptedTransport)>,
+        from: SocketAddr,
+        sealed: &[u8],
+    ) -> Result<(Vec<u8>, bool), UdpError> {
+        let opened = match session_keys.get(&from) {
+            Some((_, session)) => session
+                .open(sealed)
+                .map(|packet| (packet, false))
+                .or_else(|| encryption.open(sealed).map(|packet| (packet, true))),
+            None => encryption
+                .open(sealed)
+                .or_else(|| {
+                    session_keys
+                        .values()
+                        .find_map(|(_, session)| session.open(sealed))
+                })
+                .map(|packet| (packet, false)),
+        };
+        opened.ok_or(UdpError::Unauthenticated { from })
+    }
+
+    fn header_size(&self) -> usize {
+        self.transport.as_ref().map_or(0, |t| t.header_size())
+    }
+
+    fn seal_overhead(&self) -> usize {
+        #[cfg(feature = "encryption")]
+        if self.encryption.is_some() {
+            return encryption::SEAL_OVERHEAD;
+        }
+        0
+    }
+
+    // Times packets arriving in pieces by `clock` in place of the wall clock.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    // When a packet still arriving in pieces will have waited too long for the rest.
+    pub fn next_timer(&self) -> Option<SystemTime> {
+        self.reassembler.next_expiry()
+    }
 
-        let decompressed =
-            zstd::block::decompress(recv_buf.as_mut(), std::mem::size_of::<UdpMsg>())?;
+    // Sets how long a packet arriving in pieces waits for the rest.
+    pub fn set_fragment_timeout(&mut self, timeout: Duration) {
+        self.reassembler.set_timeout(timeout);
+    }
+
+    // Packets that arrived in pieces and were given up on, missing some.
+    pub fn expired_fragment_packets(&self) -> usize {
+        self.reassembler.expired()
+    }
+
+    // One datagram, which may be a fragment.
+    fn get_datagram(&mut self) -> Result<(UdpMsg, usize, SocketAddr), UdpError> {
+        let recv_buf = &mut self.recv_buffer;
+        let (len, recv_address, start) = loop {
+            let (len, recv_address) = self
+                .socket
+                .as_ref()
+                .ok_or(UdpError::SocketUninit)?
+                .recv_from(recv_buf)?;
+            let recv_address = normalize_addr(recv_address);
+            let transport = match &self.transport {
+                Some(transport) => transport,
+                None => break (len, recv_address, 0),
+            };
+            // Through a transport, the packet comes from whoever it says.
+            match transport.incoming(recv_address, &recv_buf[..len]) {
+                Some(sender) => break (len, sender, transport.header_size()),
+                None => info!(
+                    "ignoring a datagram from {} outside the transport.\n",
+                    recv_address
+                ),
+            }
+        };
 
-        let msg: UdpMsg = bincode::deserialize(&decompressed)?;
+        let payload = &recv_buf[start..len];
+        #[cfg(feature = "encryption")]
+        let (opened, handshake_only) = match &self.encryption {
+            Some(encryption) => {
+                let (opened, handshake_only) =
+                    Self::open(encryption, &self.session_keys, recv_address, payload)?;
+                (Some(opened), handshake_only)
+            }
+            None => (None, false),
+        };
+        #[cfg(feature = "encryption")]
+        let payload = opened.as_deref().unwrap_or(payload);
+
+        self.decode_buffer.resize(DECODE_BUFFER_SIZE, 0);
+        let decompressed = zstd::block::decompress_to_buffer(payload, &mut self.decode_buffer)
+            .map_err(|e| undecodable(recv_address, e))?;
+        let packet = self.decode_buffer.split_to(decompressed).freeze();
+        self.decode_buffer.clear();
+
+        let msg = UdpMsg::decode(packet).map_err(|e| undecodable(recv_address, e))?;
+        #[cfg(feature = "encryption")]
+        if handshake_only && !is_handshake(msg.header.packet_type) {
+            return Err(UdpError::Unauthenticated { from: recv_address });
+        }
+        ggpo_event!(
+            direction = "recv",
+            peer = %recv_address,
+            msg_type = ?msg.header.packet_type,
+            bytes = len,
+            "packet"
+        );
         Ok((msg, len, recv_address))
     }
 
+    /*
+     * Reads every datagram currently waiting on the socket without blocking.
+     * The socket is registered edge-triggered, so a readable event must be
+     * drained until the OS reports `WouldBlock` or later packets are lost.
+     */
+    pub fn recv_pending(&mut self) -> Result<Vec<(UdpMsg, usize, SocketAddr)>, UdpError> {
+        let mut msgs = Vec::new();
+        loop {
+            match self.get_msg() {
+                Ok(msg) => msgs.push(msg),
+                Err(UdpError::Io { source }) if source.kind() == std::io::ErrorKind::WouldBlock => {
+                    break;
+                }
+                // Forged, tampered with, or sealed under another key.
+                Err(UdpError::Unauthenticated { from }) => {
+                    info!("dropping a datagram from {} that didn't open.\n", from);
+                }
+                // Truncated, corrupt, or not ours at all; the rest still count.
+                Err(UdpError::Undecodable { from, reason }) => {
+                    info!("dropping a datagram from {}: {}\n", from, reason);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(msgs)
+    }
+
     pub fn on_loop_poll(&mut self, _cookie: i32) -> Result<bool, UdpError> {
-        let (msg, len, recv_address) = self.get_msg()?;
-
-        self.callbacks
-            .as_mut()
-            .ok_or(UdpError::CallbacksUninit)?
-            .lock()
-            .on_msg(&recv_address, &msg, len)
-            .map_err(|e| UdpError::Callback(e))?;
+        for (msg, len, recv_address) in self.recv_pending()? {
+            self.callbacks
+                .as_ref()
+                .and_then(Weak::upgrade)
+                .ok_or(UdpError::CallbacksUninit)?
+                .lock()
+                .on_msg(&recv_address, msg, len)
+                .map_err(UdpError::Callback)?;
+        }
         Ok(true)
     }
 }
+
+// Lets the send task finish what's queued, then releases the port.
+impl<T: UdpCallback> Drop for Udp<T> {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            error!("failed to close udp socket: {}\n", e);
+        }
+    }
+}
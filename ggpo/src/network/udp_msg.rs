@@ -1,10 +1,23 @@
-use crate::game_input::Frame;
+pub use crate::core::sync::ConnectStatus;
+use crate::game_input::{Frame, NULL_FRAME};
+use bincode::Options;
+use bytes::{Buf, Bytes};
 use log::error;
 use serde::{Deserialize, Serialize};
-use serde_big_array::big_array;
 use std::mem::size_of;
 
-big_array! { BigArray; }
+/*
+ * How messages are laid out on the wire: fixed-width integers, little-endian,
+ * whatever the host.  Peers on different architectures have to agree byte for
+ * byte, so this is pinned rather than left to bincode's defaults, and nothing
+ * in a message may be a `usize`.
+ */
+pub fn wire_format() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_little_endian()
+        .allow_trailing_bytes()
+}
 
 #[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd)]
 pub enum MsgType {
@@ -16,27 +29,18 @@ pub enum MsgType {
     QualityReply = 5,
     KeepAlive = 6,
     InputAck = 7,
-}
-
-#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
-pub struct ConnectStatus {
-    pub disconnected: bool,
-    pub last_frame: Frame,
-}
-
-impl ConnectStatus {
-    pub const fn new() -> Self {
-        Self {
-            disconnected: true,
-            last_frame: None,
-        }
-    }
-}
-
-impl Default for ConnectStatus {
-    fn default() -> Self {
-        Self::new()
-    }
+    ChecksumReport = 8,
+    StateRequest = 9,
+    StateResponse = 10,
+    // Sent once, by a session shutting down, so its peers needn't wait to time out.
+    Goodbye = 11,
+    Pause = 12,
+    // A piece of a packet too big for one datagram; `Udp` puts them back together.
+    Fragment = 13,
+    // Messages for the same peer, sent together; see `Batch`.
+    Batch = 14,
+    // A spectator missing frames the host should still have; see `InputResend`.
+    InputResend = 15,
 }
 
 #[derive(Serialize, Deserialize, Copy, Clone, Debug)]
@@ -62,13 +66,34 @@ impl Default for Header {
     }
 }
 
+// Half the sequence number space; see `seq_after`.
+pub const SEQ_HALF_RANGE: u16 = 1 << 15;
+
+/*
+ * Sequence numbers are 16 bits and wrap, so whether one is newer than another
+ * is RFC 1982 serial number arithmetic, not `>`: `seq` is after `last` if it's
+ * less than half the number space ahead of it, counting forwards through the
+ * wrap.  So 1 is after 65535, and 65535 is before 1.  Exactly half way round
+ * is neither, since which way the count went can't be told.
+ */
+pub fn seq_after(seq: u16, last: u16) -> bool {
+    let ahead = seq.wrapping_sub(last);
+    ahead != 0 && ahead < SEQ_HALF_RANGE
+}
+
 pub const UDP_MSG_MAX_PLAYERS: usize = 4;
 pub const MAX_COMPRESSED_BITS: usize = 4096;
+// The largest saved state a `StateResponse` can carry.  Anything over a
+// datagram's worth goes in fragments; see `network::fragment`.
+pub const MAX_STATE_SNAPSHOT_SIZE: usize = 1 << 20;
 #[derive(Serialize, Deserialize, Copy, Clone, Debug)]
 pub struct SyncRequest {
     pub random_request: u32,
     pub remote_magic: u16,
     pub remote_endpoint: u8,
+    pub input_size: u16,
+    // The game's protocol and save-state version; peers must agree on it.
+    pub version: u32,
 }
 impl Default for SyncRequest {
     fn default() -> Self {
@@ -82,6 +107,8 @@ impl SyncRequest {
             random_request: 0,
             remote_endpoint: 0,
             remote_magic: 0,
+            input_size: 0,
+            version: 0,
         }
     }
 }
@@ -89,17 +116,30 @@ impl SyncRequest {
 #[derive(Serialize, Deserialize, Default, Copy, Clone, Debug)]
 pub struct SyncReply {
     pub random_reply: u32,
+    pub input_size: u16,
+    // The replying session's contribution to the shared seed.
+    pub seed_nonce: u64,
+    pub version: u32,
 }
 
 impl SyncReply {
     pub const fn new() -> Self {
-        Self { random_reply: 0 }
+        Self {
+            random_reply: 0,
+            input_size: 0,
+            seed_nonce: 0,
+            version: 0,
+        }
     }
 }
 #[derive(Serialize, Deserialize, Default, Copy, Clone, Debug)]
 pub struct QualityReport {
     pub frame_advantage: i8,
     pub ping: u128,
+    // The sender's rolling checksum of every player's confirmed input up to
+    // this frame, when input checksums are on; NULL_FRAME otherwise.
+    pub input_checksum_frame: Frame,
+    pub input_checksum: u32,
 }
 
 impl QualityReport {
@@ -107,6 +147,8 @@ impl QualityReport {
         Self {
             frame_advantage: 0,
             ping: 0,
+            input_checksum_frame: NULL_FRAME,
+            input_checksum: 0,
         }
     }
 }
@@ -122,7 +164,7 @@ impl QualityReply {
     }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Input {
     pub peer_connect_status: [ConnectStatus; UDP_MSG_MAX_PLAYERS],
 
@@ -131,21 +173,29 @@ pub struct Input {
     pub disconnect_requested: bool, // default value should be 1
     pub ack_frame: Frame,           // default value should be 31
 
+    // Bytes per input, so the receiver knows how to slice the decoded bits.
+    pub input_size: u16,
+    // How many players' inputs each frame in `bits` holds; see `input_codec`.
+    pub num_players: u8,
     pub num_bits: u16,
 
-    #[serde(with = "BigArray")]
-    pub bits: [u8; MAX_COMPRESSED_BITS],
+    // Not part of the bincode body: `UdpMsg::encode` appends the bits after it,
+    // and `UdpMsg::decode` slices them back out of the received packet.
+    #[serde(skip)]
+    pub bits: Bytes,
 }
 
 impl Input {
     pub const fn new() -> Self {
         Self {
-            bits: [b'0'; MAX_COMPRESSED_BITS],
+            bits: Bytes::new(),
             peer_connect_status: [ConnectStatus::new(); UDP_MSG_MAX_PLAYERS],
-            start_frame: Some(0),
+            start_frame: Frame::new(0),
             disconnect_requested: true,
-            ack_frame: Some(31),
+            ack_frame: Frame::new(31),
 
+            input_size: 0,
+            num_players: 1,
             num_bits: 0,
         }
     }
@@ -165,7 +215,7 @@ pub struct InputAck {
 impl InputAck {
     pub const fn new() -> Self {
         Self {
-            ack_frame: Some(31),
+            ack_frame: Frame::new(31),
         }
     }
 }
@@ -176,7 +226,152 @@ impl Default for InputAck {
     }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone)]
+// The checksum of the state a peer saved for a confirmed frame.
+#[derive(Serialize, Deserialize, Default, Copy, Clone, Debug)]
+pub struct ChecksumReport {
+    pub frame: Frame,
+    pub checksum: u32,
+}
+
+impl ChecksumReport {
+    pub const fn new() -> Self {
+        Self {
+            frame: NULL_FRAME,
+            checksum: 0,
+        }
+    }
+}
+
+/*
+ * Where a session stands on pausing.  Each pause gets a new epoch, so a
+ * message about an old pause can't reopen it.  Every message that isn't an
+ * ack is answered with one echoing its epoch and `paused`.
+ */
+#[derive(Serialize, Deserialize, Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Pause {
+    pub epoch: u32,
+    pub paused: bool,
+    // The frame the sender will stop at.
+    pub frame: Frame,
+    pub ack: bool,
+}
+
+impl Pause {
+    pub const fn new() -> Self {
+        Self {
+            epoch: 0,
+            paused: false,
+            frame: NULL_FRAME,
+            ack: false,
+        }
+    }
+}
+
+/*
+ * Sent by a spectator whose input stream skipped frames that haven't turned
+ * up since: resend the pending input from `from`, now, rather than when the
+ * retransmit timer next comes round.
+ */
+#[derive(Serialize, Deserialize, Default, Copy, Clone, Debug)]
+pub struct InputResend {
+    pub from: Frame,
+}
+
+impl InputResend {
+    pub const fn new() -> Self {
+        Self { from: NULL_FRAME }
+    }
+}
+
+/*
+ * A saved state, sent to a spectator joining a match that's already running.
+ * Like input bits, the state itself rides after the bincode body.
+ */
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct StateResponse {
+    // The frame the state was saved at: the first one it hasn't run yet.
+    pub frame: Frame,
+    pub checksum: u32,
+    pub size: u32,
+    // The version of the game that saved it.
+    pub version: u32,
+    #[serde(skip)]
+    pub data: Bytes,
+}
+
+impl StateResponse {
+    pub const fn new() -> Self {
+        Self {
+            frame: NULL_FRAME,
+            checksum: 0,
+            size: 0,
+            version: 0,
+            data: Bytes::new(),
+        }
+    }
+}
+
+/*
+ * Piece `index` of the `count` it took to send encoded packet `id`.  Like
+ * input bits, the piece itself rides after the bincode body.
+ */
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Fragment {
+    pub id: u16,
+    pub index: u16,
+    pub count: u16,
+    pub size: u16,
+    #[serde(skip)]
+    pub data: Bytes,
+}
+
+impl Fragment {
+    pub const fn new() -> Self {
+        Self {
+            id: 0,
+            index: 0,
+            count: 0,
+            size: 0,
+            data: Bytes::new(),
+        }
+    }
+}
+
+/*
+ * Input, an input ack and a quality report for the same peer, sent as one
+ * datagram instead of three.  The messages ride after the bincode body, each
+ * as its encoded length, a u16, followed by its `encode`d self, header and
+ * all.  Only the batch's own header is checked on the way in.
+ */
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Batch {
+    pub count: u8,
+    #[serde(skip)]
+    pub messages: Vec<UdpMsg>,
+}
+
+// The most messages a batch carries: one of each kind that can go in one.
+pub const MAX_BATCH_MESSAGES: usize = 3;
+
+impl Batch {
+    pub fn new(messages: Vec<UdpMsg>) -> Self {
+        assert!(messages.len() <= MAX_BATCH_MESSAGES);
+        Self {
+            count: messages.len() as u8,
+            messages,
+        }
+    }
+
+    // Whether a message of this type can go in a batch.
+    pub fn carries(packet_type: MsgType) -> bool {
+        matches!(
+            packet_type,
+            MsgType::Input | MsgType::InputAck | MsgType::QualityReport
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub enum MsgEnum {
     SyncRequest(SyncRequest),
     SyncReply(SyncReply),
@@ -184,11 +379,19 @@ pub enum MsgEnum {
     QualityReply(QualityReply),
     Input(Input),
     InputAck(InputAck),
+    ChecksumReport(ChecksumReport),
+    StateRequest,
+    StateResponse(StateResponse),
+    Goodbye,
+    Pause(Pause),
     KeepAlive,
     None,
+    Fragment(Fragment),
+    Batch(Batch),
+    InputResend(InputResend),
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct UdpMsg {
     pub header: Header,
     pub message: MsgEnum,
@@ -213,30 +416,195 @@ impl UdpMsg {
             MsgType::QualityReport => size_of::<QualityReport>(),
             MsgType::QualityReply => size_of::<QualityReply>(),
             MsgType::InputAck => size_of::<InputAck>(),
-            MsgType::KeepAlive => 0,
-            MsgType::Input => match self.message {
+            MsgType::ChecksumReport => size_of::<ChecksumReport>(),
+            MsgType::Pause => size_of::<Pause>(),
+            MsgType::InputResend => size_of::<InputResend>(),
+            MsgType::KeepAlive | MsgType::StateRequest | MsgType::Goodbye => 0,
+            MsgType::StateResponse => match &self.message {
+                MsgEnum::StateResponse(response) => {
+                    size_of::<StateResponse>() - size_of::<Bytes>() + response.data.len()
+                }
+                _ => {
+                    error!("State response header but not state response packet?");
+                    0
+                }
+            },
+            MsgType::Fragment => match &self.message {
+                MsgEnum::Fragment(fragment) => {
+                    size_of::<Fragment>() - size_of::<Bytes>() + fragment.data.len()
+                }
+                _ => {
+                    error!("Fragment header but not fragment packet?");
+                    0
+                }
+            },
+            MsgType::Batch => match &self.message {
+                MsgEnum::Batch(batch) => {
+                    size_of::<u8>()
+                        + batch
+                            .messages
+                            .iter()
+                            .map(|msg| size_of::<u16>() + msg.packet_size())
+                            .sum::<usize>()
+                }
+                _ => {
+                    error!("Batch header but not batch packet?");
+                    0
+                }
+            },
+            MsgType::Input => match &self.message {
                 MsgEnum::Input(Input { num_bits, .. }) => {
                     // The original computed this using the addresses within the union itself.
-                    size = size_of::<Input>() - size_of::<[u8; MAX_COMPRESSED_BITS]>();
-                    size += (num_bits as usize + 7) / 8;
+                    size = size_of::<Input>() - size_of::<Bytes>();
+                    size += (*num_bits as usize).div_ceil(8);
                     size
                 }
                 _ => {
                     error!("Input header but not input packet?");
-                    unreachable!();
+                    0
                 }
             },
             MsgType::Invalid => {
                 error!("Invalid packet payload size");
-                unreachable!();
+                0
             }
         };
     }
 
-    pub fn packet_size(self) -> usize {
+    pub fn packet_size(&self) -> usize {
         size_of::<Header>() + self.payload_size()
     }
 
+    /*
+     * The wire format is the `wire_format` encoding of the message followed, for
+     * input messages, by the raw compressed input bits, for state responses
+     * by the saved state, for fragments by the piece, and for batches by the
+     * messages in them.  Keeping the bits out
+     * of the bincode body lets `decode` hand them back as a slice of the
+     * received packet instead of copying them into a fixed array.
+     */
+    pub fn encode(&self) -> Result<Vec<u8>, bincode::Error> {
+        let mut buf = wire_format().serialize(self)?;
+        match &self.message {
+            MsgEnum::Input(input) => buf.extend_from_slice(&input.bits),
+            MsgEnum::StateResponse(response) => buf.extend_from_slice(&response.data),
+            MsgEnum::Fragment(fragment) => buf.extend_from_slice(&fragment.data),
+            MsgEnum::Batch(batch) => {
+                for msg in batch.messages.iter() {
+                    let encoded = msg.encode()?;
+                    buf.extend_from_slice(&(encoded.len() as u16).to_le_bytes());
+                    buf.extend_from_slice(&encoded);
+                }
+            }
+            _ => {}
+        }
+        Ok(buf)
+    }
+
+    /*
+     * Decodes a packet produced by `encode`.  The input bits share `packet`'s
+     * storage rather than being copied out of it, so decoding an input message
+     * doesn't allocate.
+     */
+    pub fn decode(mut packet: Bytes) -> Result<Self, bincode::Error> {
+        let mut body = &packet[..];
+        let mut msg: UdpMsg = wire_format().deserialize_from(&mut body)?;
+        if !msg.body_matches_header() {
+            return Err(Box::new(bincode::ErrorKind::Custom(format!(
+                "{:?} header on a different message.",
+                msg.header.packet_type
+            ))));
+        }
+        let consumed = packet.len() - body.len();
+        let (tail, len, what) = match &mut msg.message {
+            MsgEnum::Input(input) => (
+                &mut input.bits,
+                (input.num_bits as usize).div_ceil(8),
+                "input message",
+            ),
+            MsgEnum::StateResponse(response) => {
+                (&mut response.data, response.size as usize, "state response")
+            }
+            MsgEnum::Fragment(fragment) => (&mut fragment.data, fragment.size as usize, "fragment"),
+            MsgEnum::Batch(batch) => {
+                packet.advance(consumed);
+                batch.messages = Self::decode_batch(packet, batch.count)?;
+                return Ok(msg);
+            }
+            _ => return Ok(msg),
+        };
+        packet.advance(consumed);
+        if packet.len() < len {
+            return Err(Box::new(bincode::ErrorKind::Custom(format!(
+                "{} truncated: {} of {} bytes.",
+                what,
+                packet.len(),
+                len
+            ))));
+        }
+        *tail = packet.split_to(len);
+        Ok(msg)
+    }
+
+    // Whether the body is the message the header says it is.
+    fn body_matches_header(&self) -> bool {
+        matches!(
+            (self.header.packet_type, &self.message),
+            (MsgType::SyncRequest, MsgEnum::SyncRequest(_))
+                | (MsgType::SyncReply, MsgEnum::SyncReply(_))
+                | (MsgType::QualityReport, MsgEnum::QualityReport(_))
+                | (MsgType::QualityReply, MsgEnum::QualityReply(_))
+                | (MsgType::Input, MsgEnum::Input(_))
+                | (MsgType::InputAck, MsgEnum::InputAck(_))
+                | (MsgType::ChecksumReport, MsgEnum::ChecksumReport(_))
+                | (MsgType::StateRequest, MsgEnum::StateRequest)
+                | (MsgType::StateResponse, MsgEnum::StateResponse(_))
+                | (MsgType::Goodbye, MsgEnum::Goodbye)
+                | (MsgType::Pause, MsgEnum::Pause(_))
+                // Keep-alives go out with an empty body.
+                | (MsgType::KeepAlive, MsgEnum::KeepAlive)
+                | (MsgType::KeepAlive, MsgEnum::None)
+                | (MsgType::Fragment, MsgEnum::Fragment(_))
+                | (MsgType::Batch, MsgEnum::Batch(_))
+                | (MsgType::InputResend, MsgEnum::InputResend(_))
+        )
+    }
+
+    // The `count` messages after a batch's body.  Batches don't nest.
+    fn decode_batch(mut packet: Bytes, count: u8) -> Result<Vec<UdpMsg>, bincode::Error> {
+        let truncated = |what: &str| {
+            Box::new(bincode::ErrorKind::Custom(format!(
+                "batch truncated in {}.",
+                what
+            )))
+        };
+        if count as usize > MAX_BATCH_MESSAGES {
+            return Err(Box::new(bincode::ErrorKind::Custom(format!(
+                "batch of {} messages, at most {} allowed.",
+                count, MAX_BATCH_MESSAGES
+            ))));
+        }
+        let mut messages = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            if packet.len() < size_of::<u16>() {
+                return Err(truncated("a length"));
+            }
+            let len = packet.get_u16_le() as usize;
+            if packet.len() < len {
+                return Err(truncated("a message"));
+            }
+            let msg = Self::decode(packet.split_to(len))?;
+            if !Batch::carries(msg.header.packet_type) {
+                return Err(Box::new(bincode::ErrorKind::Custom(format!(
+                    "{:?} message in a batch.",
+                    msg.header.packet_type
+                ))));
+            }
+            messages.push(msg);
+        }
+        Ok(messages)
+    }
+
     // TODO: Make const on nightly/when const fn and const match are stabilized
     pub fn new(t: MsgType) -> Self {
         match t {
@@ -272,6 +640,38 @@ impl UdpMsg {
                 header: Header::new(t),
                 message: MsgEnum::InputAck(InputAck::new()),
             },
+            MsgType::ChecksumReport => Self {
+                header: Header::new(t),
+                message: MsgEnum::ChecksumReport(ChecksumReport::new()),
+            },
+            MsgType::StateRequest => Self {
+                header: Header::new(t),
+                message: MsgEnum::StateRequest,
+            },
+            MsgType::StateResponse => Self {
+                header: Header::new(t),
+                message: MsgEnum::StateResponse(StateResponse::new()),
+            },
+            MsgType::Goodbye => Self {
+                header: Header::new(t),
+                message: MsgEnum::Goodbye,
+            },
+            MsgType::Pause => Self {
+                header: Header::new(t),
+                message: MsgEnum::Pause(Pause::new()),
+            },
+            MsgType::Fragment => Self {
+                header: Header::new(t),
+                message: MsgEnum::Fragment(Fragment::new()),
+            },
+            MsgType::Batch => Self {
+                header: Header::new(t),
+                message: MsgEnum::Batch(Batch::default()),
+            },
+            MsgType::InputResend => Self {
+                header: Header::new(t),
+                message: MsgEnum::InputResend(InputResend::new()),
+            },
         }
     }
 }
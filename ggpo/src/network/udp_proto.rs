@@ -1,16 +1,20 @@
 use crate::{
-    bitvector,
-    game_input::{Frame, FrameNum, GameInput, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS},
-    ggpo,
+    game_input::{Frame, FrameNum, GameInput, NULL_FRAME},
+    ggpo::{self, ConnectionQuality},
     network::{
-        udp::{Udp, UdpCallback, UdpError},
+        clock::{Clock, SystemClock},
+        input_codec::{self, InputCodecError},
+        udp::{self, Udp, UdpCallback, UdpError},
         udp_msg::{
-            ConnectStatus, MsgEnum, MsgType, UdpMsg, MAX_COMPRESSED_BITS, UDP_MSG_MAX_PLAYERS,
+            seq_after, Batch, ChecksumReport, ConnectStatus, Header, InputAck, MsgEnum, MsgType,
+            Pause, QualityReport, StateResponse, UdpMsg, MAX_BATCH_MESSAGES, MAX_COMPRESSED_BITS,
+            UDP_MSG_MAX_PLAYERS,
         },
     },
     time_sync::TimeSync,
 };
 // use async_mutex::Mutex;
+use bytes::Bytes;
 use log::{error, info, trace};
 use parking_lot::Mutex;
 use rand::prelude::*;
@@ -20,6 +24,7 @@ use thiserror::Error;
 
 use std::{
     collections::VecDeque,
+    mem::size_of,
     net::{IpAddr, Ipv4Addr, SocketAddr},
     time::{SystemTime, SystemTimeError, UNIX_EPOCH},
 };
@@ -29,20 +34,40 @@ pub const NUM_SYNC_PACKETS: u32 = 5;
 pub const SYNC_RETRY_INTERVAL: u128 = 2000;
 pub const SYNC_FIRST_RETRY_INTERVAL: u128 = 500;
 pub const RUNNING_RETRY_INTERVAL: u128 = 200;
-pub const KEEP_ALIVE_INTERVAL: i32 = 200;
+pub const DEFAULT_RETRANSMIT_INTERVAL: u128 = 200;
+pub const MAX_RETRANSMIT_INTERVAL: u128 = 2000;
+pub const KEEP_ALIVE_INTERVAL: u128 = 200;
 pub const QUALITY_REPORT_INTERVAL: u128 = 1000;
+// Round trip samples are averaged over roughly this many quality replies.
+const RTT_SMOOTHING: u128 = 8;
 pub const NETWORK_STATS_INTERVAL: u128 = 1000;
 pub const UDP_SHUTDOWN_TIMER: u128 = 5000;
-pub const MAX_SEQ_DISTANCE: u16 = 1 << 15;
+// How often an unacked pause or resume is sent again.
+const PAUSE_RESEND_INTERVAL: u128 = 100;
+// How often a spectator may ask for (and the host will send) a state snapshot.
+pub const STATE_REQUEST_INTERVAL: u128 = 1000;
+// How long a hole in a spectator's input stream may last before it asks the host to fill it.
+pub const DEFAULT_STREAM_GAP_WINDOW: u128 = 250;
+// How many times it asks before giving up on the host.
+pub const MAX_STREAM_GAP_REQUESTS: u32 = 4;
+// The span the send rate cap is measured over.
+pub const SEND_RATE_WINDOW: u128 = 1000;
+// How far back, in ms, `BandwidthMeter` looks.
+pub const BANDWIDTH_WINDOW: u128 = 1000;
+// How far back, in ms, `ClockDriftMeter` looks, and how much of that it needs before it estimates.
+pub const CLOCK_DRIFT_WINDOW: u128 = 60_000;
+pub const CLOCK_DRIFT_MIN_SPAN: u128 = 20_000;
+// Beyond this, in parts per million either way, a peer's clock drift is reported.
+pub const CLOCK_DRIFT_THRESHOLD_PPM: i32 = 1000;
+// The standard deviation, in ms, of the simulated latency; see `set_send_jitter`.
+pub const DEFAULT_SEND_JITTER: i32 = 3;
+// `TransportProfile::TrustedLan` timings.  A LAN rarely drops anything, so resends can wait.
+pub const LAN_RETRANSMIT_INTERVAL: u128 = 500;
+pub const LAN_RUNNING_RETRY_INTERVAL: u128 = 1000;
+pub const LAN_QUALITY_REPORT_INTERVAL: u128 = 5000;
 
 #[derive(Debug, Error)]
 pub enum UdpProtoError {
-    #[error("Curent frame is None.")]
-    CurrentFrameUninit,
-    #[error("Last recieved input frame is None")]
-    InputFrameUninit,
-    #[error("Input start frame is None.")]
-    StartFrameUninit,
     #[error("UDP struct unitialized.")]
     UdpUninit,
     #[error("Pending Output Queue empty.")]
@@ -51,6 +76,8 @@ pub enum UdpProtoError {
     PendingOutputItemOOB(usize),
     #[error("Send queue is empty.")]
     SendQueueEmpty,
+    #[error("{0} frames of unacked input don't fit in one message.")]
+    PendingOutputOverflow(usize),
     #[error("UDP socket error.")]
     Udp {
         #[from]
@@ -65,6 +92,11 @@ pub enum UdpProtoError {
         #[from]
         source: SystemTimeError,
     },
+    #[error("Input bits don't decode.")]
+    InputCodec {
+        #[from]
+        source: InputCodecError,
+    },
 }
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
 pub struct Synchronizing {
@@ -77,6 +109,37 @@ pub struct NetworkInterrupted {
     pub disconnect_timeout: u128,
 }
 
+// Frames the input stream skipped, which haven't arrived since.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct StreamGap {
+    pub missing_from: Frame,
+    pub missing_to: Frame,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct InputSizeMismatch {
+    pub local: usize,
+    pub remote: usize,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct IncompatibleVersion {
+    pub local: u32,
+    pub remote: u32,
+}
+
+/*
+ * Why a sync handshake didn't go through: the peer never finished it in
+ * time, or its packets are from another session, game version or input size.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SyncFailure {
+    Timeout(u128),
+    MagicMismatch { expected: u16, found: u16 },
+    VersionMismatch(IncompatibleVersion),
+    InputSizeMismatch(InputSizeMismatch),
+}
+
 #[derive(Debug)]
 pub enum Event {
     Unknown,
@@ -87,6 +150,19 @@ pub enum Event {
     Disconnected,
     NetworkInterrupted(NetworkInterrupted),
     NetworkResumed,
+    InputSizeMismatch(InputSizeMismatch),
+    IncompatibleVersion(IncompatibleVersion),
+    Resumed,
+    Checksum(ChecksumReport),
+    // The peer's rolling checksum of confirmed input, from a quality report.
+    InputChecksum(ChecksumReport),
+    QualityChanged(ConnectionQuality),
+    // The peer's clock drifted past `CLOCK_DRIFT_THRESHOLD_PPM`, by this much.
+    ClockDrift(i32),
+    StateRequested,
+    State(StateResponse),
+    Pause(Pause),
+    StreamGap(StreamGap),
 }
 
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
@@ -126,6 +202,8 @@ pub struct QueueEntry {
     pub queue_time: std::time::SystemTime,
     pub dest_addr: SocketAddr,
     pub msg: Arc<UdpMsg>,
+    // In ms: how long the simulated latency holds it after `queue_time`.
+    pub delay: u64,
 }
 
 impl Default for QueueEntry {
@@ -134,6 +212,7 @@ impl Default for QueueEntry {
             queue_time: std::time::SystemTime::now(),
             dest_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0),
             msg: Default::default(),
+            delay: 0,
         }
     }
 }
@@ -144,7 +223,199 @@ impl QueueEntry {
             queue_time: time,
             dest_addr: *dst,
             msg: m,
+            delay: 0,
+        }
+    }
+}
+
+/*
+ * Decides when unacknowledged input gets sent again.  Each retransmission that
+ * goes unanswered doubles the wait (up to MAX_RETRANSMIT_INTERVAL) so a quiet
+ * peer isn't flooded, and any ack drops it back to the base interval.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RetransmitTimer {
+    interval: u128,
+    current: u128,
+    next_send: Option<u128>,
+}
+
+impl Default for RetransmitTimer {
+    fn default() -> Self {
+        Self::new(DEFAULT_RETRANSMIT_INTERVAL)
+    }
+}
+
+impl RetransmitTimer {
+    pub const fn new(interval: u128) -> Self {
+        Self {
+            interval,
+            current: interval,
+            next_send: None,
+        }
+    }
+
+    pub fn set_interval(&mut self, interval: u128) {
+        self.interval = interval;
+        self.current = interval;
+    }
+
+    pub fn current_interval(&self) -> u128 {
+        self.current
+    }
+
+    // Starts the timer for freshly sent input, unless it's already running.
+    pub fn arm(&mut self, now: u128) {
+        if self.next_send.is_none() {
+            self.next_send = Some(now + self.current);
+        }
+    }
+
+    pub fn is_due(&self, now: u128) -> bool {
+        matches!(self.next_send, Some(next_send) if next_send <= now)
+    }
+
+    pub fn back_off(&mut self, now: u128) {
+        self.current = std::cmp::min(self.current * 2, MAX_RETRANSMIT_INTERVAL);
+        self.next_send = Some(now + self.current);
+    }
+
+    pub fn reset(&mut self) {
+        self.current = self.interval;
+        self.next_send = None;
+    }
+}
+
+/*
+ * How much an endpoint trusts the network between it and its peer.  Leave it
+ * at `Internet` unless every machine that can reach the session's port is
+ * one you control.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TransportProfile {
+    Internet,
+    /*
+     * For an isolated network, like a LAN tournament's: packets aren't checked
+     * against the magic number the peer synchronized with, input is resent
+     * less eagerly, and quality reports go out a fifth as often.
+     *
+     * WARNING: without the magic number check, anyone who can send a packet to
+     * the session's port can feed input to, or disconnect, a player.  Never
+     * use this on a network that strangers can reach.
+     */
+    TrustedLan,
+}
+
+impl Default for TransportProfile {
+    fn default() -> Self {
+        TransportProfile::Internet
+    }
+}
+
+impl TransportProfile {
+    // Whether packets past the handshake must carry the peer's magic number.
+    pub fn checks_magic(self) -> bool {
+        self == TransportProfile::Internet
+    }
+
+    // The base retransmit interval for a configured one.
+    pub fn retransmit_interval(self, configured: u128) -> u128 {
+        match self {
+            TransportProfile::Internet => configured,
+            TransportProfile::TrustedLan => configured.max(LAN_RETRANSMIT_INTERVAL),
+        }
+    }
+
+    // How long a quiet link goes before we resend input anyway.
+    pub fn running_retry_interval(self) -> u128 {
+        match self {
+            TransportProfile::Internet => RUNNING_RETRY_INTERVAL,
+            TransportProfile::TrustedLan => LAN_RUNNING_RETRY_INTERVAL,
+        }
+    }
+
+    pub fn quality_report_interval(self) -> u128 {
+        match self {
+            TransportProfile::Internet => QUALITY_REPORT_INTERVAL,
+            TransportProfile::TrustedLan => LAN_QUALITY_REPORT_INTERVAL,
+        }
+    }
+}
+
+/*
+ * What actually went on the wire, compressed and with UDP and IP headers,
+ * over the last `BANDWIDTH_WINDOW`, so `kbps_sent` follows what the link is
+ * carrying now rather than averaging over the whole session.
+ */
+#[derive(Debug, Default, Clone)]
+pub struct BandwidthMeter {
+    // When, how many bytes, and whether it was input.
+    sends: VecDeque<(u128, usize, bool)>,
+}
+
+impl BandwidthMeter {
+    pub fn record(&mut self, now: u128, bytes: usize, input: bool) {
+        self.sends.push_back((now, bytes + UDP_HEADER_SIZE, input));
+    }
+
+    // Input and everything else sent over the window, in bytes per second.
+    pub fn rates(&mut self, now: u128) -> (usize, usize) {
+        while let Some(&(time, _, _)) = self.sends.front() {
+            if time + BANDWIDTH_WINDOW > now {
+                break;
+            }
+            self.sends.pop_front();
+        }
+        let (input, overhead) =
+            self.sends
+                .iter()
+                .fold((0, 0), |(input, overhead), &(_, bytes, is_input)| {
+                    if is_input {
+                        (input + bytes, overhead)
+                    } else {
+                        (input, overhead + bytes)
+                    }
+                });
+        let per_second = |bytes: usize| (bytes as u128 * 1000 / BANDWIDTH_WINDOW) as usize;
+        (per_second(input), per_second(overhead))
+    }
+}
+
+/*
+ * How fast the peer's clock runs against ours.  Every quality report carries
+ * the time it was sent by the peer's clock; over a long enough stretch, how
+ * far that moved against how far ours did in the same time gives the rate.
+ * The network's jitter comes into each end of it, so the longer the stretch
+ * the less it counts: nothing is estimated until the reports span
+ * `CLOCK_DRIFT_MIN_SPAN`, and they're kept for `CLOCK_DRIFT_WINDOW`.
+ */
+#[derive(Debug, Default, Clone)]
+pub struct ClockDriftMeter {
+    // When each report arrived by our clock, and when it was sent by the peer's.
+    samples: VecDeque<(u128, u128)>,
+}
+
+impl ClockDriftMeter {
+    pub fn record(&mut self, now: u128, remote: u128) {
+        while let Some(&(time, _)) = self.samples.front() {
+            if time + CLOCK_DRIFT_WINDOW > now {
+                break;
+            }
+            self.samples.pop_front();
+        }
+        self.samples.push_back((now, remote));
+    }
+
+    // In parts per million: positive when the peer's clock runs fast of ours.
+    pub fn drift_ppm(&self) -> Option<i32> {
+        let (first, first_remote) = *self.samples.front()?;
+        let (last, last_remote) = *self.samples.back()?;
+        let local = last - first;
+        if local < CLOCK_DRIFT_MIN_SPAN {
+            return None;
         }
+        let remote = last_remote as i128 - first_remote as i128;
+        Some(((remote - local as i128) * 1_000_000 / local as i128) as i32)
     }
 }
 
@@ -186,17 +457,34 @@ pub struct UdpProtocol<T: UdpCallback + Send + Sync> {
     remote_magic_number: u16,
     connected: bool,
     send_latency: i32,
+    send_jitter: i32,
     oop_percent: i32,
+    loss_percent: i32,
     oo_packet: OoPacket,
     send_queue: VecDeque<QueueEntry>,
+    // While a batch is open, what it's holding; see `begin_batch`.
+    batch: Option<Vec<UdpMsg>>,
     /*
      * Stats
      */
     round_trip_time: u128,
+    // A moving average of the samples above; `None` until the first reply.
+    smoothed_round_trip_time: Option<u128>,
     packets_sent: usize,
     bytes_sent: usize,
     kbps_sent: usize,
+    input_bytes_per_second: usize,
+    overhead_bytes_per_second: usize,
+    bandwidth: BandwidthMeter,
     stats_start_time: u128,
+    // Input packets sent, and how many of them were retransmissions, since
+    // the last stats update.
+    input_packets_sent: usize,
+    input_packets_resent: usize,
+    loss_percent_estimate: usize,
+    quality: ConnectionQuality,
+    clock_drift: ClockDriftMeter,
+    clock_drift_reported: bool,
     /*
      * The state machine
      */
@@ -218,17 +506,76 @@ pub struct UdpProtocol<T: UdpCallback + Send + Sync> {
      */
     // pending_output: ArrayDeque<[GameInput; 64]>,
     pending_output: VecDeque<GameInput>,
+    /*
+     * A hole in the input we've received, when we first saw it, and how many
+     * times we've asked for it to be resent; see `set_stream_gap_window`.
+     */
+    stream_gap_window: u128,
+    stream_gap: Option<StreamGap>,
+    stream_gap_since: u128,
+    stream_gap_requests: u32,
+    // What we send, and what we expect the peer to send.  The same unless
+    // players on each end have different input sizes.
+    input_size: usize,
+    remote_input_size: usize,
+    input_size_mismatch_sent: bool,
+    // Why the handshake last failed; cleared when one goes through.
+    sync_failure: Option<SyncFailure>,
+    version: u32,
+    remote_version: Option<u32>,
+    version_mismatch_sent: bool,
+    seed_nonce: u64,
+    remote_seed_nonce: Option<u64>,
     last_received_input: GameInput,
+    // The last frame we told the peer we have, in an input or an input ack.
+    last_ack_sent: Frame,
+    // Our latest rolling checksum of confirmed input, sent with quality reports.
+    input_checksum: Option<(Frame, u32)>,
+    // For testing: the frame whose decoded input gets a bit flipped.
+    input_corruption: Option<Frame>,
     last_sent_input: GameInput,
     last_acked_input: GameInput,
+    retransmit: RetransmitTimer,
+    // As configured; the profile may stretch it.
+    retransmit_interval: u128,
+    profile: TransportProfile,
+    // Bytes per second; 0 means no cap.
+    send_rate_limit: usize,
+    recent_sends: VecDeque<(u128, usize)>,
+    // Our latest pause or resume, until the peer acks it.
+    pending_pause: Option<Pause>,
+    last_pause_send: u128,
     last_send_time: std::time::SystemTime,
     last_recv_time: std::time::SystemTime,
+    // Every timer reads this; see `set_clock`.
+    clock: Arc<dyn Clock>,
     shutdown_timeout: u128,
+    /*
+     * The handshake: how many round trips it takes, how long to wait for a
+     * reply before asking again, and how long to try at all (0 is forever).
+     */
+    num_sync_packets: u32,
+    sync_first_retry_interval: u128,
+    sync_retry_interval: u128,
+    sync_timeout: u128,
+    sync_started: u128,
+    // Retries go by this rather than `last_send_time`, which anything we send resets.
+    last_sync_request: u128,
     disconnect_event_sent: bool,
     disconnect_timeout: u128,
     disconnect_notify_start: u128,
     disconnect_notify_sent: bool,
 
+    /*
+     * Reconnection.  After a timeout we keep listening for `reconnect_window`
+     * ms; if the peer turns up again in that time we redo the sync handshake
+     * and pick up where we left off.
+     */
+    reconnect_window: u128,
+    reconnect_deadline: u128,
+    timed_out_at: u128,
+    resuming: bool,
+
     next_send_seq: u16,
     next_recv_seq: u16,
 
@@ -242,6 +589,30 @@ pub struct UdpProtocol<T: UdpCallback + Send + Sync> {
     event_queue: VecDeque<Event>,
 }
 
+/*
+ * Peers that open to each other at once can't agree on who went first, so
+ * nothing both ends derive may depend on it.  Whatever they combine is put
+ * in order by value instead: sorted, with duplicates (one host's nonce heard
+ * through two of its players, say) counted once, then mixed, so two equal
+ * values don't cancel out the way they would XORed together.
+ */
+pub fn combine_seed_nonces(nonces: &mut [u64]) -> u64 {
+    nonces.sort_unstable();
+    nonces
+        .iter()
+        .enumerate()
+        .filter(|&(i, nonce)| i == 0 || nonces[i - 1] != *nonce)
+        .fold(0, |seed, (_, &nonce)| mix64(seed ^ nonce))
+}
+
+// SplitMix64's finalizer: every bit of `x` stirs every bit of the result.
+fn mix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
 impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
     pub fn new() -> Self {
         let mut connect_status: [Arc<Mutex<ConnectStatus>>; UDP_MSG_MAX_PLAYERS] =
@@ -260,38 +631,93 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
             bytes_sent: 0,
             stats_start_time: 0,
             last_send_time: std::time::SystemTime::now(),
+            clock: Arc::new(SystemClock),
             shutdown_timeout: 0,
+            num_sync_packets: NUM_SYNC_PACKETS,
+            sync_first_retry_interval: SYNC_FIRST_RETRY_INTERVAL,
+            sync_retry_interval: SYNC_RETRY_INTERVAL,
+            sync_timeout: 0,
+            sync_started: 0,
+            last_sync_request: 0,
             disconnect_timeout: 0,
             disconnect_notify_start: 0,
             disconnect_notify_sent: false,
             disconnect_event_sent: false,
+            reconnect_window: 0,
+            reconnect_deadline: 0,
+            timed_out_at: 0,
+            resuming: false,
             connected: false,
             next_send_seq: 0,
             next_recv_seq: 0,
             udp: None,
 
-            last_sent_input: Default::default(),
-            last_received_input: Default::default(),
-            last_acked_input: Default::default(),
+            input_size: 0,
+            remote_input_size: 0,
+            input_size_mismatch_sent: false,
+            sync_failure: None,
+            version: 0,
+            remote_version: None,
+            version_mismatch_sent: false,
+            seed_nonce: 0,
+            remote_seed_nonce: None,
+            // Both ends start delta-coding from a blank input.
+            last_sent_input: GameInput::new(),
+            last_received_input: GameInput::new(),
+            last_ack_sent: NULL_FRAME,
+            input_checksum: None,
+            input_corruption: None,
+            last_acked_input: GameInput::new(),
+            retransmit: Default::default(),
+            retransmit_interval: DEFAULT_RETRANSMIT_INTERVAL,
+            profile: TransportProfile::default(),
+            send_rate_limit: 0,
+            recent_sends: VecDeque::new(),
+            pending_pause: None,
+            last_pause_send: 0,
 
             // state: State::Start,
-            peer_connect_status: [Default::default(); UDP_MSG_MAX_PLAYERS],
+            // Everyone's connected until the peer says otherwise.
+            peer_connect_status: [ConnectStatus {
+                disconnected: false,
+                last_frame: NULL_FRAME,
+            }; UDP_MSG_MAX_PLAYERS],
             peer_addr: None,
             send_latency: std::env::var("ggpo.network.delay")
                 .unwrap_or("".to_string())
                 .parse()
                 .unwrap_or(0),
+            send_jitter: DEFAULT_SEND_JITTER,
             oop_percent: std::env::var("ggpo.oop.percent")
                 .unwrap_or("".to_string())
                 .parse()
                 .unwrap_or(0),
+            loss_percent: std::env::var("ggpo.network.loss")
+                .unwrap_or("".to_string())
+                .parse()
+                .unwrap_or(0),
             oo_packet: Default::default(),
             send_queue: VecDeque::with_capacity(64),
+            batch: None,
             round_trip_time: 0,
+            smoothed_round_trip_time: None,
             kbps_sent: 0,
+            input_bytes_per_second: 0,
+            overhead_bytes_per_second: 0,
+            bandwidth: Default::default(),
+            input_packets_sent: 0,
+            input_packets_resent: 0,
+            loss_percent_estimate: 0,
+            quality: ConnectionQuality::Excellent,
+            clock_drift: Default::default(),
+            clock_drift_reported: false,
             local_connect_status: connect_status,
             state: State::Starting,
             pending_output: VecDeque::with_capacity(64),
+            stream_gap_window: 0,
+            stream_gap: None,
+            stream_gap_since: 0,
+            stream_gap_requests: 0,
             last_recv_time: std::time::SystemTime::now(),
             timesync: Default::default(),
             event_queue: VecDeque::with_capacity(64),
@@ -309,7 +735,7 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
         self.queue = queue as i64;
         self.local_connect_status = status.clone();
 
-        self.peer_addr = Some(addr);
+        self.peer_addr = Some(udp::normalize_addr(addr));
         self.magic_number = self.rng.gen();
         // poll.lock().registry().register(source, token, interests)
     }
@@ -341,6 +767,16 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
                  * the odds of this happening...
                  */
                 self.pending_output.push_back(input.clone());
+                self.retransmit
+                    .arm(self.clock.now().duration_since(UNIX_EPOCH)?.as_millis());
+            }
+            _ if self.reconnect_deadline > 0 => {
+                /*
+                 * The peer timed out but may still come back.  Hold on to the
+                 * input so the stream they get afterwards has no holes in it.
+                 */
+                self.pending_output.push_back(input.clone());
+                return Ok(());
             }
             _ => {}
         }
@@ -349,74 +785,158 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
     }
 
     pub fn send_pending_output(&mut self) -> Result<(), UdpProtoError> {
+        self.input_packets_sent += 1;
         let mut msg = UdpMsg::new(MsgType::Input);
         let mut offset = 0;
-        let mut bits: [u8; MAX_COMPRESSED_BITS];
+        let mut bits = [0u8; MAX_COMPRESSED_BITS];
         let mut last: GameInput;
-        if let MsgEnum::Input(mut input) = &mut msg.message {
+        if let MsgEnum::Input(input) = &mut msg.message {
             if self.pending_output.len() > 0 {
                 last = self.last_acked_input;
-                bits = input.bits;
                 let front = self
                     .pending_output
                     .front()
                     .ok_or(UdpProtoError::PendingOutputQueueEmpty)?;
                 input.start_frame = front.frame;
 
-                assert!(
-                    last.frame == None
-                        || last.frame.unwrap_or(0) + 1 == input.start_frame.unwrap_or(0)
-                );
+                assert!(last.frame.is_null() || last.frame.next() == input.start_frame);
                 for current in self.pending_output.iter() {
-                    if current.bits != last.bits {
-                        assert!(
-                            (GAMEINPUT_MAX_BYTES
-                                * GAMEINPUT_MAX_PLAYERS
-                                * bitvector::BITVECTOR_NIBBLE_SIZE)
-                                < (1 << bitvector::BITVECTOR_NIBBLE_SIZE)
-                        );
-
-                        for i in 0..current.size * bitvector::BITVECTOR_NIBBLE_SIZE {
-                            assert!(i < (1 << bitvector::BITVECTOR_NIBBLE_SIZE));
-                            if current.value(i) != last.value(i) {
-                                bitvector::set_bit(&mut input.bits, &mut offset);
-                                if current.value(i) {
-                                    bitvector::set_bit(&mut bits, &mut offset);
-                                } else {
-                                    bitvector::clear_bit(&mut bits, &mut offset);
-                                }
-                                bitvector::write_nibblet(&mut bits, i, &mut offset);
-                            }
-                        }
+                    input_codec::write_frame(&mut bits, &mut offset, &[last], &[*current]);
+                    /*
+                     * A peer that stops acking leaves us more to resend than a
+                     * message holds.  `bits` has room for a frame past the end,
+                     * so it's enough to catch that once it happens.
+                     */
+                    if offset >= MAX_COMPRESSED_BITS {
+                        return Err(UdpProtoError::PendingOutputOverflow(
+                            self.pending_output.len(),
+                        ));
                     }
-                    bitvector::clear_bit(&mut input.bits, &mut offset);
                     self.last_sent_input = current.clone();
                     last = self.last_sent_input;
                 }
             } else {
-                input.start_frame = Some(0);
+                input.start_frame = Frame::new(0);
             }
             input.ack_frame = self.last_received_input.frame;
+            self.last_ack_sent = input.ack_frame;
+            input.input_size = self.input_size as u16;
+            input.num_players = 1;
             input.num_bits = offset as u16;
+            input.bits = Bytes::copy_from_slice(&bits[..offset.div_ceil(8)]);
 
             input.disconnect_requested = self.state == State::Disconnected;
             for i in 0..self.local_connect_status.len() {
                 input.peer_connect_status[i] = *self.local_connect_status[i].lock();
             }
-
-            assert!(offset < MAX_COMPRESSED_BITS);
         }
         self.send_msg(&mut msg)
     }
 
     pub fn send_input_ack(&mut self) -> Result<(), UdpProtoError> {
         let mut msg = UdpMsg::new(MsgType::InputAck);
-        if let MsgEnum::InputAck(mut input_ack) = &mut msg.message {
+        if let MsgEnum::InputAck(input_ack) = &mut msg.message {
             input_ack.ack_frame = self.last_received_input.frame;
+            self.last_ack_sent = input_ack.ack_frame;
+        }
+        self.send_msg(&mut msg)
+    }
+
+    // Tells the peer what state we saved for a confirmed frame.
+    pub fn send_checksum(&mut self, frame: Frame, checksum: u32) -> Result<(), UdpProtoError> {
+        let mut msg = UdpMsg::new(MsgType::ChecksumReport);
+        if let MsgEnum::ChecksumReport(report) = &mut msg.message {
+            report.frame = frame;
+            report.checksum = checksum;
+        }
+        self.send_msg(&mut msg)
+    }
+
+    // Asks the host for a snapshot to start spectating from.
+    pub fn send_state_request(&mut self) -> Result<(), UdpProtoError> {
+        self.send_msg(&mut UdpMsg::new(MsgType::StateRequest))
+    }
+
+    pub fn send_state(
+        &mut self,
+        frame: Frame,
+        checksum: u32,
+        data: Bytes,
+    ) -> Result<(), UdpProtoError> {
+        let mut msg = UdpMsg::new(MsgType::StateResponse);
+        if let MsgEnum::StateResponse(response) = &mut msg.message {
+            response.frame = frame;
+            response.checksum = checksum;
+            response.size = data.len() as u32;
+            response.version = self.version;
+            response.data = data;
         }
         self.send_msg(&mut msg)
     }
 
+    /*
+     * Tells the peer where we stand on pausing, and keeps telling it every
+     * `PAUSE_RESEND_INTERVAL` until it acks.  Only the latest state matters,
+     * so a resume replaces a pause that hasn't been acked yet.
+     */
+    pub fn send_pause(
+        &mut self,
+        epoch: u32,
+        paused: bool,
+        frame: Frame,
+    ) -> Result<(), UdpProtoError> {
+        let pause = Pause {
+            epoch,
+            paused,
+            frame,
+            ack: false,
+        };
+        self.pending_pause = Some(pause);
+        self.last_pause_send = self.clock.now().duration_since(UNIX_EPOCH)?.as_millis();
+        self.send_pause_msg(pause)
+    }
+
+    fn send_pause_msg(&mut self, pause: Pause) -> Result<(), UdpProtoError> {
+        let mut msg = UdpMsg::new(MsgType::Pause);
+        msg.message = MsgEnum::Pause(pause);
+        self.send_msg(&mut msg)
+    }
+
+    /*
+     * Says goodbye to the peer on the way out.  Acks everything we've
+     * received first, so the peer doesn't resend input nobody is listening
+     * for, and sends anything still held back by the simulated latency
+     * straight away.  The peer drops us as soon as the goodbye arrives.
+     */
+    pub fn send_goodbye(&mut self) -> Result<(), UdpProtoError> {
+        if self.udp.is_none() {
+            return Ok(());
+        }
+        if !self.last_received_input.frame.is_null() {
+            self.send_input_ack()?;
+        }
+        self.send_msg(&mut UdpMsg::new(MsgType::Goodbye))?;
+        while let Some(entry) = self.send_queue.pop_front() {
+            let sent = self
+                .udp
+                .as_mut()
+                .ok_or(UdpProtoError::UdpUninit)?
+                .lock()
+                .send_to(entry.msg.clone(), &[entry.dest_addr]);
+            match sent {
+                // The goodbye is a courtesy; don't hold up the disconnect for it.
+                Err(UdpError::SendQueueFull { .. }) => {
+                    info!("send queue full; leaving without saying goodbye.\n");
+                    break;
+                }
+                sent => self.record_send(&entry.msg, sent?)?,
+            }
+        }
+        ggpo_event!(peer = ?self.peer_addr, state = "goodbye", "connection state");
+        self.reconnect_deadline = 0;
+        self.disconnect()
+    }
+
     pub fn is_initialized(&self) -> bool {
         self.udp.is_some()
     }
@@ -424,12 +944,16 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
     pub fn is_sychronized(&self) -> bool {
         match self.state {
             State::Synchronized => true,
-            // TODO: Is this supposed to report true?
-            // State::Running(_) => true,
+            // The handshake goes straight to running once it's done.
+            State::Running(_) => true,
             _ => false,
         }
     }
 
+    pub fn is_disconnected(&self) -> bool {
+        self.state == State::Disconnected
+    }
+
     pub fn is_running(&self) -> bool {
         match self.state {
             State::Running(_) => true,
@@ -450,24 +974,45 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
             return Err(UdpProtoError::UdpUninit);
         }
 
-        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let now = self.clock.now().duration_since(UNIX_EPOCH)?.as_millis();
         self.pump_send_queue()?;
+        self.check_stream_gap(now)?;
 
         match self.state {
+            State::Syncing(_) if self.resuming && self.reconnect_deadline < now => {
+                info!("Couldn't reconnect to the endpoint in time.  Giving up.\n");
+                ggpo_event!(peer = ?self.peer_addr, state = "disconnected", "connection state");
+                self.state = State::Disconnected;
+                self.resuming = false;
+            }
+            State::Syncing(_)
+                if !self.resuming
+                    && self.sync_timeout > 0
+                    && self.sync_started + self.sync_timeout < now =>
+            {
+                info!(
+                    "Couldn't synchronize with the endpoint in {} ms.  Giving up.\n",
+                    self.sync_timeout
+                );
+                ggpo_event!(peer = ?self.peer_addr, state = "disconnected", "connection state");
+                self.state = State::Disconnected;
+                self.sync_failure = Some(SyncFailure::Timeout(self.sync_timeout));
+                if !self.disconnect_event_sent {
+                    self.queue_event(Event::Disconnected);
+                    self.disconnect_event_sent = true;
+                }
+            }
             State::Syncing(Syncing {
                 roundtrips_remaining,
                 random: _,
             }) => {
-                let next_interval = if roundtrips_remaining == NUM_SYNC_PACKETS {
-                    SYNC_FIRST_RETRY_INTERVAL
+                let next_interval = if roundtrips_remaining == self.num_sync_packets {
+                    self.sync_first_retry_interval
                 } else {
-                    SYNC_RETRY_INTERVAL
+                    self.sync_retry_interval
                 };
 
-                if self.last_send_time.duration_since(UNIX_EPOCH)?.as_millis() > 0
-                    && self.last_send_time.duration_since(UNIX_EPOCH)?.as_millis() + next_interval
-                        < now
-                {
+                if self.last_sync_request > 0 && self.last_sync_request + next_interval < now {
                     info!(
                         "No luck syncing after {} ms... Re-queueing sync packet.\n",
                         next_interval
@@ -482,8 +1027,25 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
                 mut last_input_packet_recv_time,
             }) => {
                 // xxx: rig all this up with a timer wrapper
-                if !(last_input_packet_recv_time > 0)
-                    || last_input_packet_recv_time + RUNNING_RETRY_INTERVAL < now
+                if !self.pending_output.is_empty() {
+                    // Unacked input is on the clock of the retransmit timer, which
+                    // backs off until the peer acks it.
+                    if self.retransmit.is_due(now)
+                        && self.within_send_budget(now, self.pending_output_size())
+                    {
+                        info!(
+                            "Input unacked for {} ms (last acked:{:?}  last sent:{:?}).  Retransmitting.\n",
+                            self.retransmit.current_interval(),
+                            self.last_acked_input.frame,
+                            self.last_sent_input.frame
+                        );
+                        self.send_pending_output()?;
+                        self.input_packets_resent += 1;
+                        self.retransmit.back_off(now);
+                    }
+                } else if (!(last_input_packet_recv_time > 0)
+                    || last_input_packet_recv_time + self.profile.running_retry_interval() < now)
+                    && self.within_send_budget(now, self.pending_output_size())
                 {
                     info!("Haven't exchanged packets in a while (last received:{:?}  last sent:{:?}).  Resending.\n", self.last_received_input.frame, self.last_sent_input.frame);
                     self.send_pending_output()?;
@@ -493,18 +1055,33 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
                         last_network_stats_interval,
                         last_input_packet_recv_time: now,
                     });
+                } else if self.last_received_input.frame > self.last_ack_sent
+                    && self.within_send_budget(now, size_of::<InputAck>())
+                {
+                    /*
+                     * With nothing of our own to send, as for a spectator, the
+                     * peer only hears what we've received from an ack.  Without
+                     * one it would hold every frame it sends us until its queue
+                     * overflowed.
+                     */
+                    self.send_input_ack()?;
                 }
 
-                if !(last_quality_report_time > 0)
-                    || last_quality_report_time + QUALITY_REPORT_INTERVAL < now
+                if (!(last_quality_report_time > 0)
+                    || last_quality_report_time + self.profile.quality_report_interval() < now)
+                    && self.within_send_budget(now, size_of::<QualityReport>())
                 {
                     let mut msg = UdpMsg::new(MsgType::QualityReport);
                     match &mut msg.message {
                         MsgEnum::QualityReport(quality_report) => {
                             quality_report.ping =
-                                SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+                                self.clock.now().duration_since(UNIX_EPOCH)?.as_millis();
                             // TODO: Profile and test whether i8 is enough here in extreme cases.
                             quality_report.frame_advantage = self.local_frame_advantage as i8;
+                            if let Some((frame, checksum)) = self.input_checksum {
+                                quality_report.input_checksum_frame = frame;
+                                quality_report.input_checksum = checksum;
+                            }
                             self.send_msg(&mut msg)?;
                         }
                         _ => {}
@@ -517,6 +1094,14 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
                     });
                 }
 
+                if let Some(pause) = self.pending_pause {
+                    if self.last_pause_send + PAUSE_RESEND_INTERVAL < now {
+                        info!("Pause unacked.  Resending.\n");
+                        self.send_pause_msg(pause)?;
+                        self.last_pause_send = now;
+                    }
+                }
+
                 if !(last_network_stats_interval > 0)
                     || last_network_stats_interval + NETWORK_STATS_INTERVAL < now
                 {
@@ -524,7 +1109,7 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
                     last_network_stats_interval = now;
                     self.state = State::Running(Running {
                         last_quality_report_time,
-                        last_network_stats_interval: last_network_stats_interval,
+                        last_network_stats_interval,
                         last_input_packet_recv_time,
                     })
                 }
@@ -549,6 +1134,7 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
                         disconnect_timeout: self.disconnect_timeout - self.disconnect_notify_start,
                     });
 
+                    ggpo_event!(peer = ?self.peer_addr, state = "interrupted", "connection state");
                     self.queue_event(event);
                     self.disconnect_notify_sent = true;
                 }
@@ -563,12 +1149,33 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
                             "Endpoint has stopped receiving packets for {:?} ms. Disconnecting.\n",
                             self.disconnect_timeout
                         );
+                        if self.reconnect_window > 0 {
+                            self.timed_out_at = now;
+                            self.reconnect_deadline = now + self.reconnect_window;
+                        }
+                        ggpo_event!(peer = ?self.peer_addr, state = "timed_out", "connection state");
                         self.queue_event(Event::Disconnected);
                         self.disconnect_event_sent = true;
                     }
                 }
             }
+            State::Disconnected if self.reconnect_deadline > now => {
+                if self.last_recv_time.duration_since(UNIX_EPOCH)?.as_millis() > self.timed_out_at {
+                    info!("Heard from the endpoint again.  Reconnecting.\n");
+                    ggpo_event!(peer = ?self.peer_addr, state = "reconnecting", "connection state");
+                    self.resuming = true;
+                    self.synchronize()?;
+                } else if self.last_send_time.duration_since(UNIX_EPOCH)?.as_millis()
+                    + KEEP_ALIVE_INTERVAL
+                    < now
+                {
+                    // Let the peer know we're still here in case it timed out too.
+                    self.send_msg(&mut UdpMsg::new(MsgType::KeepAlive))?;
+                }
+            }
             State::Disconnected => {
+                // Too late to reconnect, if we ever could.
+                self.reconnect_deadline = 0;
                 if (self.shutdown_timeout as u128) < now {
                     info!("Shutting down udp connection.\n");
                     self.udp = None;
@@ -581,10 +1188,147 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
         Ok(true)
     }
 
+    /*
+     * When `on_loop_poll` next has something to do, by the endpoint's clock:
+     * the soonest of the timers it checks.  A time already past means it's
+     * due now, and `None` that nothing is waiting on the time at all.  Most
+     * timers fire once they've been waited on for longer than their interval,
+     * so they're due a millisecond after it.  Polling before a timer's due
+     * does no harm, so where it isn't certain this answers early, never late.
+     */
+    pub fn next_timer(&self) -> Option<SystemTime> {
+        self.udp.as_ref()?;
+        let now = self
+            .clock
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_millis();
+        let last_send = self
+            .last_send_time
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_millis();
+        let last_recv = self
+            .last_recv_time
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_millis();
+        let mut due = Vec::new();
+
+        if let Some(entry) = self.send_queue.front() {
+            let sent_at = entry.queue_time + std::time::Duration::from_millis(entry.delay);
+            due.push(sent_at.duration_since(UNIX_EPOCH).ok()?.as_millis());
+        }
+        if self.oo_packet.msg.is_some() {
+            due.push(self.oo_packet.send_time + 1);
+        }
+        if self.stream_gap.is_some() && self.stream_gap_window > 0 {
+            due.push(self.stream_gap_since + self.stream_gap_window);
+        }
+
+        match self.state {
+            State::Syncing(Syncing {
+                roundtrips_remaining,
+                random: _,
+            }) => {
+                if self.resuming {
+                    due.push(self.reconnect_deadline + 1);
+                } else if self.sync_timeout > 0 {
+                    due.push(self.sync_started + self.sync_timeout + 1);
+                }
+                if self.last_sync_request > 0 {
+                    let next_interval = if roundtrips_remaining == self.num_sync_packets {
+                        self.sync_first_retry_interval
+                    } else {
+                        self.sync_retry_interval
+                    };
+                    due.push(self.last_sync_request + next_interval + 1);
+                }
+            }
+            State::Running(Running {
+                last_quality_report_time,
+                last_network_stats_interval,
+                last_input_packet_recv_time,
+            }) => {
+                if !self.pending_output.is_empty() {
+                    if let Some(next_send) = self.retransmit.next_send {
+                        due.push(std::cmp::max(
+                            next_send,
+                            self.send_budget_frees(now, self.pending_output_size()),
+                        ));
+                    }
+                } else {
+                    due.push(std::cmp::max(
+                        last_input_packet_recv_time + self.profile.running_retry_interval() + 1,
+                        self.send_budget_frees(now, self.pending_output_size()),
+                    ));
+                    if self.last_received_input.frame > self.last_ack_sent {
+                        due.push(self.send_budget_frees(now, size_of::<InputAck>()));
+                    }
+                }
+                due.push(std::cmp::max(
+                    last_quality_report_time + self.profile.quality_report_interval() + 1,
+                    self.send_budget_frees(now, size_of::<QualityReport>()),
+                ));
+                if self.pending_pause.is_some() {
+                    due.push(self.last_pause_send + PAUSE_RESEND_INTERVAL + 1);
+                }
+                due.push(last_network_stats_interval + NETWORK_STATS_INTERVAL + 1);
+                due.push(last_send + NETWORK_STATS_INTERVAL + 1);
+                if self.disconnect_timeout > 0 {
+                    if self.disconnect_notify_start > 0 && !self.disconnect_notify_sent {
+                        due.push(last_recv + self.disconnect_notify_start + 1);
+                    }
+                    if !self.disconnect_event_sent {
+                        due.push(last_recv + self.disconnect_timeout + 1);
+                    }
+                }
+            }
+            State::Disconnected if self.reconnect_deadline > now => {
+                if last_recv > self.timed_out_at {
+                    due.push(now);
+                }
+                due.push(last_send + KEEP_ALIVE_INTERVAL + 1);
+                due.push(self.reconnect_deadline);
+            }
+            State::Disconnected => due.push(self.shutdown_timeout + 1),
+            State::Synchronized | State::Starting => {}
+        }
+
+        let soonest = due.into_iter().min()?;
+        Some(UNIX_EPOCH + std::time::Duration::from_millis(soonest as u64))
+    }
+
+    /*
+     * Gives up on a peer we can't send to: it's reported disconnected, as if
+     * it had timed out, and the rest of the session carries on without it.
+     */
+    pub fn fail(&mut self, reason: &str) {
+        if self.state == State::Disconnected || self.disconnect_event_sent {
+            return;
+        }
+        error!(
+            "Can't reach {:?} ({}).  Disconnecting it.\n",
+            self.peer_addr, reason
+        );
+        ggpo_event!(peer = ?self.peer_addr, state = "unreachable", "connection state");
+        self.queue_event(Event::Disconnected);
+        self.disconnect_event_sent = true;
+    }
+
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
     pub fn disconnect(&mut self) -> Result<(), UdpProtoError> {
+        ggpo_event!(peer = ?self.peer_addr, state = "disconnected", "connection state");
         self.state = State::Disconnected;
-        self.shutdown_timeout =
-            SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() + UDP_SHUTDOWN_TIMER;
+        // Keep the socket around for as long as the peer may still reconnect.
+        self.shutdown_timeout = std::cmp::max(
+            self.clock.now().duration_since(UNIX_EPOCH)?.as_millis() + UDP_SHUTDOWN_TIMER,
+            self.reconnect_deadline,
+        );
         Ok(())
     }
 
@@ -599,9 +1343,12 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
                 match &mut msg.message {
                     MsgEnum::SyncRequest(sync_request) => {
                         sync_request.random_request = *random;
+                        sync_request.input_size = self.input_size as u16;
+                        sync_request.version = self.version;
                     }
                     _ => {}
                 }
+                self.last_sync_request = self.clock.now().duration_since(UNIX_EPOCH)?.as_millis();
                 return self.send_msg(&mut msg);
             }
             _ => {}
@@ -609,20 +1356,78 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
         Ok(())
     }
 
+    /*
+     * Holds input, input acks and quality reports back until `flush_batch`,
+     * which sends them to the peer together, in one datagram.  Anything else
+     * goes straight out as usual.  A later message of a kind already held
+     * replaces the earlier one: the input carries everything still unacked,
+     * and the ack and report only need to be current.
+     */
+    pub fn begin_batch(&mut self) {
+        if self.batch.is_none() {
+            self.batch = Some(Vec::with_capacity(MAX_BATCH_MESSAGES));
+        }
+    }
+
+    // Sends what the batch held, alone if there's only one of it.
+    pub fn flush_batch(&mut self) -> Result<(), UdpProtoError> {
+        let mut messages = match self.batch.take() {
+            Some(messages) => messages,
+            None => return Ok(()),
+        };
+        match messages.len() {
+            0 => Ok(()),
+            1 => self.send_msg(&mut messages[0]),
+            _ => {
+                for msg in messages.iter_mut() {
+                    msg.header.magic = self.magic_number;
+                }
+                let mut msg = UdpMsg::new(MsgType::Batch);
+                msg.message = MsgEnum::Batch(Batch::new(messages));
+                self.send_msg(&mut msg)
+            }
+        }
+    }
+
     pub fn send_msg(&mut self, msg: &mut UdpMsg) -> Result<(), UdpProtoError> {
+        if let Some(batch) = self.batch.as_mut() {
+            if Batch::carries(msg.header.packet_type) {
+                batch.retain(|held| held.header.packet_type != msg.header.packet_type);
+                batch.push(msg.clone());
+                return Ok(());
+            }
+        }
         self.log_msg(LogPrefix::Send, msg);
         self.packets_sent += 1;
-        self.last_send_time = std::time::SystemTime::now();
+        self.last_send_time = self.clock.now();
         self.bytes_sent += msg.packet_size();
+        if self.send_rate_limit > 0 {
+            let now = self.last_send_time.duration_since(UNIX_EPOCH)?.as_millis();
+            self.forget_old_sends(now);
+            self.recent_sends
+                .push_back((now, msg.packet_size() + UDP_HEADER_SIZE));
+        }
 
         msg.header.magic = self.magic_number;
-        self.next_send_seq += 1;
+        self.next_send_seq = self.next_send_seq.wrapping_add(1);
         msg.header.sequence_number = self.next_send_seq;
 
+        let delay = if self.send_latency > 0 {
+            // Drawn once per packet, so a wide jitter really does spread them out.
+            // A jitter there's no spread for just holds packets for the latency.
+            let latency = self.send_latency as f64;
+            Normal::new(latency, self.send_jitter as f64)
+                .map(|spread| spread.sample(&mut StdRng::seed_from_u64(self.rng.gen())))
+                .unwrap_or(latency)
+                .max(0.) as u64
+        } else {
+            0
+        };
         self.send_queue.push_back(QueueEntry {
             dest_addr: self.peer_addr.ok_or(UdpProtoError::PeerAddrUninit)?,
-            msg: Arc::new(*msg),
-            queue_time: std::time::SystemTime::now(),
+            msg: Arc::new(msg.clone()),
+            queue_time: self.clock.now(),
+            delay,
         });
 
         self.pump_send_queue()
@@ -635,6 +1440,44 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
         Ok(self.peer_addr.ok_or(UdpProtoError::PeerAddrUninit)? == *from)
     }
 
+    /*
+     * A packet from an address we don't know is still our peer's if it carries
+     * the magic number they synced with and a sequence number we'd accept.
+     * That happens when a NAT remaps them, or they change networks, mid-match,
+     * so from then on we send to wherever they are now.  The sync handshake
+     * doesn't prove who's talking yet, so it can't move a peer.
+     */
+    pub fn rebinds_to(&mut self, from: &SocketAddr, msg: &UdpMsg) -> Result<bool, UdpProtoError> {
+        if self.udp.is_none() {
+            return Err(UdpProtoError::UdpUninit);
+        }
+        if self.remote_magic_number == 0
+            || msg.header.packet_type == MsgType::SyncRequest
+            || msg.header.packet_type == MsgType::SyncReply
+            || msg.header.magic != self.remote_magic_number
+            || !self.accepts_seq(msg.header.sequence_number)
+        {
+            return Ok(false);
+        }
+
+        info!(
+            "Peer {:?} moved to {}; sending there from now on.\n",
+            self.peer_addr, from
+        );
+        ggpo_event!(peer = ?self.peer_addr, to = ?from, "peer address changed");
+        self.peer_addr = Some(*from);
+        for entry in self.send_queue.iter_mut() {
+            entry.dest_addr = *from;
+        }
+        Ok(true)
+    }
+
+    // Whether a packet numbered `seq` is new enough to take, wrapping included.
+    // A repeat of the last one is, as it always has been.
+    fn accepts_seq(&self, seq: u16) -> bool {
+        seq == self.next_recv_seq || seq_after(seq, self.next_recv_seq)
+    }
+
     pub fn on_msg(&mut self, msg: &UdpMsg) -> Result<(), UdpProtoError> {
         let mut handled = false;
 
@@ -643,21 +1486,18 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
         if msg.header.packet_type != MsgType::SyncRequest
             && msg.header.packet_type != MsgType::SyncReply
         {
-            if msg.header.magic != self.remote_magic_number {
+            if self.profile.checks_magic() && msg.header.magic != self.remote_magic_number {
                 self.log_msg(LogPrefix::RecvRejecting, msg);
                 return Ok(());
             }
 
             // filter out out-of-order packets
-            let skipped: u16 = seq - self.next_recv_seq;
-            // below was commented out in the original code, presumably for debugging purposes,
             trace!(
-                "checking sequence number -> next - seq : {:?} - {:?} = {:?}\n",
+                "checking sequence number -> seq, last seq : {:?}, {:?}\n",
                 seq,
-                self.next_recv_seq,
-                skipped
+                self.next_recv_seq
             );
-            if skipped > MAX_SEQ_DISTANCE {
+            if !self.accepts_seq(seq) {
                 info!(
                     "dropping out of order packet (seq: {:?}, last seq:{:?})\n",
                     seq, self.next_recv_seq
@@ -667,24 +1507,18 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
         }
         self.next_recv_seq = seq;
         self.log_msg(LogPrefix::Recv, msg);
-        if msg.header.packet_type > MsgType::InputAck || msg.header.packet_type == MsgType::Invalid
-        {
-            self.on_invalid(msg)?;
-        } else {
-            handled = match msg.header.packet_type {
-                MsgType::SyncRequest => self.on_sync_request(msg)?,
-                MsgType::Invalid => self.on_invalid(msg)?,
-                MsgType::SyncReply => self.on_sync_reply(msg)?,
-                MsgType::Input => self.on_input(msg)?,
-                MsgType::QualityReport => self.on_quality_report(msg)?,
-                MsgType::QualityReply => self.on_quality_reply(msg)?,
-                MsgType::KeepAlive => self.on_keep_alive(msg)?,
-                MsgType::InputAck => self.on_input_ack(msg)?,
+        match &msg.message {
+            MsgEnum::Batch(batch) => {
+                for msg in batch.messages.iter() {
+                    handled |= self.dispatch(msg)?;
+                }
             }
+            _ => handled = self.dispatch(msg)?,
         }
 
         if handled {
-            self.last_recv_time = SystemTime::now();
+            self.on_connected();
+            self.last_recv_time = self.clock.now();
             match self.state {
                 State::Running(Running {
                     last_quality_report_time: _,
@@ -692,6 +1526,7 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
                     last_input_packet_recv_time: _,
                 }) => {
                     if self.disconnect_notify_sent {
+                        ggpo_event!(peer = ?self.peer_addr, state = "running", "connection state");
                         self.queue_event(Event::NetworkResumed);
                         self.disconnect_notify_sent = false;
                     }
@@ -703,20 +1538,61 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
         Ok(())
     }
 
-    pub fn update_network_stats(&mut self) -> Result<(), UdpProtoError> {
-        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    // Hands a message to its handler; returns whether it was one we take.
+    fn dispatch(&mut self, msg: &UdpMsg) -> Result<bool, UdpProtoError> {
+        Ok(match msg.header.packet_type {
+            MsgType::SyncRequest => self.on_sync_request(msg)?,
+            MsgType::Invalid => self.on_invalid(msg)?,
+            MsgType::SyncReply => self.on_sync_reply(msg)?,
+            MsgType::Input => self.on_input(msg)?,
+            MsgType::QualityReport => self.on_quality_report(msg)?,
+            MsgType::QualityReply => self.on_quality_reply(msg)?,
+            MsgType::KeepAlive => self.on_keep_alive(msg)?,
+            MsgType::InputAck => self.on_input_ack(msg)?,
+            MsgType::ChecksumReport => self.on_checksum_report(msg)?,
+            MsgType::StateRequest => self.on_state_request(msg)?,
+            MsgType::StateResponse => self.on_state_response(msg)?,
+            MsgType::Goodbye => self.on_goodbye(msg)?,
+            MsgType::Pause => self.on_pause(msg)?,
+            MsgType::InputResend => self.on_input_resend(msg)?,
+            // `Udp` reassembles these before they get this far, and `on_msg`
+            // unpacks batches, which don't nest.
+            MsgType::Fragment | MsgType::Batch => self.on_invalid(msg)?,
+        })
+    }
 
-        if self.stats_start_time == 0 {
-            self.stats_start_time = now;
-        }
+    /*
+     * `Connected` goes out once, for the first packet from the peer we accept,
+     * however far the handshake has got.  Completing the handshake calls this
+     * too, before queueing `Synchronzied`, so `Connected` always comes first.
+     */
+    fn on_connected(&mut self) {
+        if !self.connected {
+            ggpo_event!(peer = ?self.peer_addr, state = "connected", "connection state");
+            self.queue_event(Event::Connected);
+            self.connected = true;
+        }
+    }
+
+    pub fn update_network_stats(&mut self) -> Result<(), UdpProtoError> {
+        let now = self.clock.now().duration_since(UNIX_EPOCH)?.as_millis();
+
+        if self.stats_start_time == 0 {
+            self.stats_start_time = now;
+        }
+        let (input, overhead) = self.bandwidth.rates(now);
+        self.input_bytes_per_second = input;
+        self.overhead_bytes_per_second = overhead;
+        self.kbps_sent = (input + overhead) / 1024;
+
+        // Nothing more to report until some time has passed and something was sent.
+        if now == self.stats_start_time || self.bytes_sent == 0 {
+            return Ok(());
+        }
 
         let total_bytes_sent = self.bytes_sent + (UDP_HEADER_SIZE * self.packets_sent);
-        let seconds = (now - self.stats_start_time) as f64 / 1000.;
-        let bytes_per_second = total_bytes_sent as f64 / seconds;
         let udp_overhead = 100. * ((UDP_HEADER_SIZE * self.packets_sent) / self.bytes_sent) as f64;
 
-        self.kbps_sent = (bytes_per_second / 1024.) as usize;
-
         info!(
             "Network Stats -- Bandwidth: {:.2} KBps   Packets Sent: {:5.}%5d ({:.2} pps) 
        KB Sent: {:.2}    UDP Overhead: {:.2} %%.\n",
@@ -726,6 +1602,26 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
             total_bytes_sent as f64 / 1024.0,
             udp_overhead
         );
+
+        // Input only goes out again when the first copy wasn't acked in time,
+        // so the share of retransmissions stands in for packet loss.
+        self.loss_percent_estimate = if self.input_packets_sent > 0 {
+            self.input_packets_resent * 100 / self.input_packets_sent
+        } else {
+            0
+        };
+        self.input_packets_sent = 0;
+        self.input_packets_resent = 0;
+
+        let quality = self.quality.reclassify(&self.get_network_stats());
+        if quality != self.quality {
+            info!(
+                "Connection quality changed from {:?} to {:?}.\n",
+                self.quality, quality
+            );
+            self.quality = quality;
+            self.queue_event(Event::QualityChanged(quality));
+        }
         Ok(())
     }
 
@@ -736,8 +1632,10 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
 
     pub fn synchronize(&mut self) -> Result<(), UdpProtoError> {
         self.udp.as_ref().ok_or(UdpProtoError::UdpUninit)?;
+        ggpo_event!(peer = ?self.peer_addr, state = "syncing", "connection state");
+        self.sync_started = self.clock.now().duration_since(UNIX_EPOCH)?.as_millis();
         self.state = State::Syncing(Syncing {
-            roundtrips_remaining: NUM_SYNC_PACKETS,
+            roundtrips_remaining: self.num_sync_packets,
             random: self.rng.gen(),
         });
         self.send_sync_request()
@@ -751,7 +1649,7 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
     }
 
     fn log_msg(&self, prefix: LogPrefix, msg: &UdpMsg) {
-        match msg.message {
+        match &msg.message {
             MsgEnum::SyncRequest(sync_request) => info!(
                 "{:?} sync-request ({:?}).\n",
                 prefix, sync_request.random_request
@@ -766,11 +1664,40 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
                 prefix, input.start_frame, input.num_bits
             ),
             MsgEnum::InputAck(_) => {}
-            MsgEnum::None => {
-                error!("Unknown UdpMsg type.");
-                unreachable!();
+            MsgEnum::ChecksumReport(report) => info!(
+                "{:?} checksum report {} ({:#x}).\n",
+                prefix, report.frame, report.checksum
+            ),
+            MsgEnum::StateRequest => info!("{:?} state request.\n", prefix),
+            MsgEnum::StateResponse(response) => info!(
+                "{:?} state response {} ({} bytes).\n",
+                prefix, response.frame, response.size
+            ),
+            MsgEnum::Goodbye => info!("{:?} goodbye.\n", prefix),
+            MsgEnum::Pause(pause) => info!(
+                "{:?} pause (epoch: {} paused: {} frame: {} ack: {}).\n",
+                prefix, pause.epoch, pause.paused, pause.frame, pause.ack
+            ),
+            MsgEnum::InputResend(resend) => {
+                info!("{:?} input resend from {}.\n", prefix, resend.from)
             }
+            // Keep-alives go out with an empty body.
+            MsgEnum::None if msg.header.packet_type == MsgType::KeepAlive => {
+                info!("{:?} keep alive.\n", prefix)
+            }
+            // `UdpMsg::decode` turns these away, so only we could have made one.
+            MsgEnum::None => error!("{:?} unknown UdpMsg type.\n", prefix),
             MsgEnum::KeepAlive => info!("{:?} keep alive.\n", prefix),
+            MsgEnum::Fragment(fragment) => info!(
+                "{:?} fragment {} of {} for packet {}.\n",
+                prefix, fragment.index, fragment.count, fragment.id
+            ),
+            MsgEnum::Batch(batch) => {
+                info!("{:?} batch of {}.\n", prefix, batch.count);
+                for msg in batch.messages.iter() {
+                    self.log_msg(prefix, msg);
+                }
+            }
         };
     }
 
@@ -787,12 +1714,24 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
                 "Ignoring sync request from unknown endpoint ({:?} != {:?}.\n",
                 msg.header.magic, self.remote_magic_number
             );
+            self.sync_failure = Some(SyncFailure::MagicMismatch {
+                expected: self.remote_magic_number,
+                found: msg.header.magic,
+            });
             return Ok(false);
         }
         let mut reply = UdpMsg::new(MsgType::SyncReply);
-        match (&mut reply.message, msg.message) {
+        match (&mut reply.message, &msg.message) {
             (MsgEnum::SyncReply(sync_reply), MsgEnum::SyncRequest(sync_request)) => {
+                if !self.check_input_size(sync_request.input_size)
+                    || !self.check_version(sync_request.version)
+                {
+                    return Ok(false);
+                }
                 sync_reply.random_reply = sync_request.random_request;
+                sync_reply.input_size = self.input_size as u16;
+                sync_reply.seed_nonce = self.seed_nonce;
+                sync_reply.version = self.version;
             }
             _ => {}
         }
@@ -802,8 +1741,18 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
 
     pub fn on_sync_reply(&mut self, msg: &UdpMsg) -> Result<bool, UdpProtoError> {
         match self.state {
-            State::Syncing(syncing) => match msg.message {
+            State::Syncing(mut syncing) => match &msg.message {
                 MsgEnum::SyncReply(sync_reply) => {
+                    let _span = ggpo_span!(
+                        "sync_handshake",
+                        peer = ?self.peer_addr,
+                        remaining = syncing.roundtrips_remaining
+                    );
+                    if !self.check_input_size(sync_reply.input_size)
+                        || !self.check_version(sync_reply.version)
+                    {
+                        return Ok(false);
+                    }
                     if sync_reply.random_reply != syncing.random {
                         info!(
                             "sync reply {:?} != {:?}.  Keep looking...\n",
@@ -811,25 +1760,39 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
                         );
                         return Ok(false);
                     }
-                    if !self.connected {
-                        self.queue_event(Event::Connected);
-                        self.connected = true;
+                    self.remote_seed_nonce = Some(sync_reply.seed_nonce);
+                    #[cfg(feature = "encryption")]
+                    if let (Some(udp), Some(peer_addr)) = (&self.udp, self.peer_addr) {
+                        udp.lock().set_session_nonces(
+                            peer_addr,
+                            self.seed_nonce,
+                            sync_reply.seed_nonce,
+                        );
                     }
+                    self.on_connected();
 
                     info!(
                         "Checking sync state ({:?} round trips remaining).\n",
                         syncing.roundtrips_remaining
                     );
-                    if syncing.roundtrips_remaining - 1 == 0 {
-                        info!("Synchronized!\n");
-                        self.queue_event(Event::Synchronzied);
+                    syncing.roundtrips_remaining -= 1;
+                    self.state = State::Syncing(syncing);
+                    if syncing.roundtrips_remaining == 0 {
+                        ggpo_event!(peer = ?self.peer_addr, state = "running", "connection state");
                         self.state = State::Running(Default::default());
-                        self.last_received_input.frame = None;
                         self.remote_magic_number = msg.header.magic;
+                        self.sync_failure = None;
+                        if self.resuming {
+                            self.on_resumed()?;
+                        } else {
+                            info!("Synchronized!\n");
+                            self.queue_event(Event::Synchronzied);
+                            self.last_received_input.frame = NULL_FRAME;
+                        }
                     } else {
                         let event = Event::Synchronizing(Synchronizing {
-                            total: NUM_SYNC_PACKETS,
-                            count: NUM_SYNC_PACKETS - syncing.roundtrips_remaining,
+                            total: self.num_sync_packets,
+                            count: self.num_sync_packets - syncing.roundtrips_remaining,
                         });
                         self.queue_event(event);
                         self.send_sync_request()?;
@@ -847,29 +1810,87 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
         Ok(true)
     }
 
+    /*
+     * Picks the connection back up after a reconnect handshake.  Both input
+     * streams carry on from where they were: the peer resends whatever we
+     * didn't take while we were away, so all that's left is to flush the input
+     * we held back in the meantime.
+     */
+    fn on_resumed(&mut self) -> Result<(), UdpProtoError> {
+        info!("Reconnected!\n");
+        self.resuming = false;
+        self.reconnect_deadline = 0;
+        self.timed_out_at = 0;
+        self.disconnect_event_sent = false;
+        self.disconnect_notify_sent = false;
+        self.queue_event(Event::Resumed);
+
+        self.retransmit.reset();
+        if !self.pending_output.is_empty() {
+            self.send_pending_output()?;
+            self.retransmit
+                .arm(self.clock.now().duration_since(UNIX_EPOCH)?.as_millis());
+        }
+        Ok(())
+    }
+
     pub fn on_input(&mut self, msg: &UdpMsg) -> Result<bool, UdpProtoError> {
         /*
          * If a disconnect is requested, go ahead and disconnect now.
          */
-        match msg.message {
+        match &msg.message {
             MsgEnum::Input(input) => {
+                if !self.check_input_size(input.input_size) {
+                    return Ok(false);
+                }
+                if input.num_players != 1 {
+                    // Each endpoint carries a single player's stream.
+                    error!(
+                        "Dropping input for {} players from a one player endpoint.\n",
+                        input.num_players
+                    );
+                    return Ok(false);
+                }
+                /*
+                 * Bits that don't decode didn't come from a peer's `write_frame`,
+                 * so nothing else in the packet can be trusted either.
+                 */
+                let mut previous = self.last_received_input;
+                previous.size = self.remote_input_size;
+                if let Err(e) = input_codec::decode(
+                    input.start_frame,
+                    &[previous],
+                    &input.bits,
+                    input.num_bits as usize,
+                ) {
+                    error!(
+                        "Dropping input starting at frame {}: {}\n",
+                        input.start_frame, e
+                    );
+                    return Ok(false);
+                }
+                if self.resuming
+                    || (self.state == State::Disconnected && self.reconnect_deadline > 0)
+                {
+                    // It's them, but leave the input unacked until we're back.
+                    return Ok(true);
+                }
                 let disconnect_requested = input.disconnect_requested;
                 if disconnect_requested {
                     if self.state != State::Disconnected && !self.disconnect_event_sent {
                         info!("Disconnecting endpoint on remote request.\n");
+                        ggpo_event!(peer = ?self.peer_addr, state = "disconnect_requested", "connection state");
                         self.queue_event(Event::Disconnected);
                         self.disconnect_event_sent = true;
                     }
                 } else {
                     /*
                      * Update the peer connection status if this peer is still considered to be part
-                     * of the network.
+                     * of the network.  A reordered packet can carry an older status than one we've
+                     * already seen, so only ever move forward.
                      */
                     let remote_status = input.peer_connect_status;
                     for i in 0..self.peer_connect_status.len() {
-                        assert!(
-                            remote_status[i].last_frame >= self.peer_connect_status[i].last_frame
-                        );
                         self.peer_connect_status[i].disconnected = self.peer_connect_status[i]
                             .disconnected
                             || remote_status[i].disconnected;
@@ -884,50 +1905,42 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
                  * Decompress the input.
                  */
                 let last_received_frame_number = self.last_received_input.frame;
-                if input.num_bits > 0 {
-                    let offset = 0;
-                    let mut bits = input.bits;
-                    let num_bits = input.num_bits;
+                if input.num_bits > 0 && self.input_leaves_gap(input.start_frame) {
+                    /*
+                     * The frames before this packet's haven't arrived, and its bits are
+                     * coded against them, so there's nothing we can use.  The retransmit
+                     * that fills the gap will carry these frames too.
+                     */
+                    info!(
+                        "Dropping input starting at frame {}, past the gap after {}.\n",
+                        input.start_frame, self.last_received_input.frame
+                    );
+                    self.note_stream_gap(StreamGap {
+                        missing_from: self.last_received_input.frame.next(),
+                        missing_to: input.start_frame.prev(),
+                    })?;
+                } else if input.num_bits > 0 {
+                    let mut offset = 0;
+                    let bits = &input.bits[..];
+                    let num_bits = input.num_bits as usize;
                     let mut current_frame = input.start_frame;
-                    self.last_received_input.size = input.bits.len();
-                    if self.last_received_input.frame.is_none() {
-                        self.last_received_input.frame =
-                            Some(input.start_frame.ok_or(UdpProtoError::StartFrameUninit)? - 1);
+                    self.last_received_input.size = self.remote_input_size;
+                    if self.last_received_input.frame.is_null() {
+                        // Null again when the stream starts at frame 0.
+                        self.last_received_input.frame = input.start_frame.prev();
                     }
                     while offset < num_bits {
                         /*
                          * Keep walking through the frames (parsing bits) until we reach
                          * the inputs for the frame right after the one we're on.
                          */
-                        assert!(
-                            current_frame
-                                <= Some(
-                                    self.last_received_input
-                                        .frame
-                                        .ok_or(UdpProtoError::InputFrameUninit)?
-                                        + 1
-                                )
-                        );
-                        let use_inputs = current_frame
-                            == Some(
-                                self.last_received_input
-                                    .frame
-                                    .ok_or(UdpProtoError::InputFrameUninit)?
-                                    + 1,
-                            );
-                        while bitvector::read_bit(&mut bits, &mut (offset as usize)) > 0 {
-                            let on = bitvector::read_bit(&mut bits, &mut (offset as usize));
-                            let button = bitvector::read_nibblet(&mut bits, &mut (offset as usize));
-                            if use_inputs {
-                                // TODO: Fix the 1d -> 2d indexing going on here.
-                                if on > 0 {
-                                    self.last_received_input.set(button as usize);
-                                } else {
-                                    self.last_received_input.clear(button as usize);
-                                }
-                            }
+                        assert!(current_frame <= self.last_received_input.frame.next());
+                        let use_inputs = current_frame == self.last_received_input.frame.next();
+                        let mut decoded = [self.last_received_input];
+                        input_codec::read_frame(bits, &mut offset, num_bits, &mut decoded)?;
+                        if use_inputs {
+                            self.last_received_input = decoded[0];
                         }
-                        assert!(offset <= num_bits);
 
                         /*
                          * Now if we want to use these inputs, go ahead and send them to
@@ -938,56 +1951,62 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
                              * Move forward 1 frame in the stream.
                              */
                             let desc: String;
-                            assert!(
-                                current_frame
-                                    == Some(
-                                        self.last_received_input
-                                            .frame
-                                            .ok_or(UdpProtoError::InputFrameUninit)?
-                                            + 1
-                                    )
-                            );
+                            assert!(current_frame == self.last_received_input.frame.next());
                             self.last_received_input.frame = current_frame;
 
                             /*
                              * Send the event to the emualtor
                              */
-                            let event = Event::Input(self.last_received_input);
+                            let mut input = self.last_received_input;
+                            if self.input_corruption == Some(current_frame) {
+                                // Only what the session sees; the stream decodes on as normal.
+                                input.bits[0][0] ^= 1;
+                            }
+                            let event = Event::Input(input);
                             desc = self.last_received_input.describe(true);
 
                             match &mut self.state {
                                 State::Running(running) => {
                                     running.last_input_packet_recv_time =
-                                        SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+                                        self.clock.now().duration_since(UNIX_EPOCH)?.as_millis();
                                 }
                                 _ => {
                                     error!("Trying to update state machine for running state, but not running.");
                                 }
                             }
                             info!(
-                                "Sending frame {:?} to emu queue {:?} ({:?}).\n",
+                                "Sending frame {} to emu queue {:?} ({:?}).\n",
                                 self.last_received_input.frame, self.queue, desc
                             );
                             self.queue_event(event);
                         } else {
                             info!(
-                                "Skipping past frame:({:?}) current is {:?}.\n",
+                                "Skipping past frame:({}) current is {}.\n",
                                 current_frame, self.last_received_input.frame
                             )
                         }
                         /*
                          * Move forward 1 frame in the input stream.
                          */
-                        current_frame =
-                            Some(current_frame.ok_or(UdpProtoError::CurrentFrameUninit)? + 1);
+                        current_frame = current_frame.next();
                     }
                 }
 
                 assert!(self.last_received_input.frame >= last_received_frame_number);
+                if let Some(gap) = self.stream_gap {
+                    if self.last_received_input.frame >= gap.missing_from {
+                        info!(
+                            "Frames {} to {} arrived after all.\n",
+                            gap.missing_from, gap.missing_to
+                        );
+                        self.stream_gap = None;
+                    }
+                }
 
                 /*
                  * Get rid of our buffered input
                  */
+                let acked_frame = self.last_acked_input.frame;
                 while self.pending_output.len() > 0
                     && self
                         .pending_output
@@ -997,7 +2016,7 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
                         < input.ack_frame
                 {
                     info!(
-                        "Throwing away pending output frame {:?}\n",
+                        "Throwing away pending output frame {}\n",
                         self.pending_output
                             .front()
                             .ok_or(UdpProtoError::PendingOutputQueueEmpty)?
@@ -1008,6 +2027,7 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
                         .pop_front()
                         .ok_or(UdpProtoError::PendingOutputQueueEmpty)?;
                 }
+                self.on_pending_output_acked(acked_frame)?;
             }
             _ => {}
         }
@@ -1015,12 +2035,106 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
         Ok(true)
     }
 
+    /*
+     * The receive watermark is `last_received_input.frame`: every frame up to it
+     * has been applied, in order, and nothing past it has.  A packet that starts
+     * at or before the frame after it overlaps what we have, and we apply only
+     * the frames past the watermark, so duplicates are harmless.  One that
+     * starts later leaves a hole.  Before anything has arrived the first packet
+     * sets the watermark, whatever frame it starts at.
+     */
+    fn input_leaves_gap(&self, start_frame: Frame) -> bool {
+        !self.last_received_input.frame.is_null()
+            && start_frame > self.last_received_input.frame.next()
+    }
+
+    fn note_stream_gap(&mut self, gap: StreamGap) -> Result<(), UdpProtoError> {
+        match &mut self.stream_gap {
+            Some(known) => known.missing_to = std::cmp::max(known.missing_to, gap.missing_to),
+            None => {
+                self.stream_gap = Some(gap);
+                self.stream_gap_since = self.clock.now().duration_since(UNIX_EPOCH)?.as_millis();
+                self.stream_gap_requests = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /*
+     * Asks for a hole that's outlasted the window to be filled, reporting it
+     * the first time, and gives up on the peer once it's asked enough.
+     */
+    fn check_stream_gap(&mut self, now: u128) -> Result<(), UdpProtoError> {
+        let gap = match self.stream_gap {
+            Some(gap) if self.stream_gap_window > 0 => gap,
+            _ => return Ok(()),
+        };
+        if now < self.stream_gap_since + self.stream_gap_window {
+            return Ok(());
+        }
+        if self.stream_gap_requests >= MAX_STREAM_GAP_REQUESTS {
+            error!(
+                "Frames {} to {} never arrived.  Disconnecting.\n",
+                gap.missing_from, gap.missing_to
+            );
+            ggpo_event!(peer = ?self.peer_addr, state = "disconnected", "connection state");
+            self.state = State::Disconnected;
+            self.stream_gap = None;
+            if !self.disconnect_event_sent {
+                self.queue_event(Event::Disconnected);
+                self.disconnect_event_sent = true;
+            }
+            return Ok(());
+        }
+        if self.stream_gap_requests == 0 {
+            self.queue_event(Event::StreamGap(gap));
+        }
+        info!(
+            "Frames {} to {} still missing.  Asking for them again.\n",
+            gap.missing_from, gap.missing_to
+        );
+        let mut msg = UdpMsg::new(MsgType::InputResend);
+        if let MsgEnum::InputResend(resend) = &mut msg.message {
+            resend.from = gap.missing_from;
+        }
+        self.send_msg(&mut msg)?;
+        self.stream_gap_requests += 1;
+        self.stream_gap_since = now;
+        Ok(())
+    }
+
+    /*
+     * Resends the pending input straight away, if it reaches back to the
+     * frame asked for.  If it doesn't we've nothing that would help, and the
+     * asker gives up on us in the end.
+     */
+    pub fn on_input_resend(&mut self, msg: &UdpMsg) -> Result<bool, UdpProtoError> {
+        if let MsgEnum::InputResend(resend) = msg.message {
+            match self.pending_output.front() {
+                Some(front) if front.frame <= resend.from => {
+                    info!("Resending input from {} on request.\n", front.frame);
+                    self.send_pending_output()?;
+                    self.input_packets_resent += 1;
+                    let now = self.clock.now().duration_since(UNIX_EPOCH)?.as_millis();
+                    self.retransmit.reset();
+                    self.retransmit.arm(now);
+                }
+                _ => error!(
+                    "Asked to resend input from {}, which we no longer have.\n",
+                    resend.from
+                ),
+            }
+        }
+        Ok(true)
+    }
+
     pub fn on_input_ack(&mut self, msg: &UdpMsg) -> Result<bool, UdpProtoError> {
         /*
          * Get rid of our buffered input
          */
         match msg.message {
             MsgEnum::InputAck(input_ack) => {
+                let acked_frame = self.last_acked_input.frame;
                 while self.pending_output.len() > 0
                     && self
                         .pending_output
@@ -1030,7 +2144,7 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
                         < input_ack.ack_frame
                 {
                     info!(
-                        "Throwing away pending output frame: {:?}\n",
+                        "Throwing away pending output frame: {}\n",
                         self.pending_output
                             .front()
                             .ok_or(UdpProtoError::PendingOutputQueueEmpty)?
@@ -1041,6 +2155,7 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
                         .pop_front()
                         .ok_or(UdpProtoError::PendingOutputQueueEmpty)?;
                 }
+                self.on_pending_output_acked(acked_frame)?;
             }
             _ => (),
         }
@@ -1048,47 +2163,161 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
         Ok(true)
     }
 
+    // Restarts the retransmit timer once the peer acks something new.
+    fn on_pending_output_acked(&mut self, previously_acked: Frame) -> Result<(), UdpProtoError> {
+        if self.last_acked_input.frame != previously_acked {
+            self.retransmit.reset();
+            if !self.pending_output.is_empty() {
+                self.retransmit
+                    .arm(self.clock.now().duration_since(UNIX_EPOCH)?.as_millis());
+            }
+        }
+        Ok(())
+    }
+
     pub fn on_quality_report(&mut self, msg: &UdpMsg) -> Result<bool, UdpProtoError> {
         // send a reply so the other side can compute the round trip transmit time.
         let mut reply = UdpMsg::new(MsgType::QualityReply);
-        match (&mut reply.message, msg.message) {
+        match (&mut reply.message, &msg.message) {
             (MsgEnum::QualityReply(reply), MsgEnum::QualityReport(report)) => {
                 reply.pong = report.ping;
             }
             _ => (),
         }
+        if let MsgEnum::QualityReport(report) = &msg.message {
+            if !report.input_checksum_frame.is_null() {
+                self.queue_event(Event::InputChecksum(ChecksumReport {
+                    frame: report.input_checksum_frame,
+                    checksum: report.input_checksum,
+                }));
+            }
+            self.remote_frame_advantage = report.frame_advantage as i32;
+            let now = self.clock.now().duration_since(UNIX_EPOCH)?.as_millis();
+            self.clock_drift.record(now, report.ping);
+            self.check_clock_drift();
+        }
         self.send_msg(&mut reply)?;
         return Ok(true);
     }
 
+    // Reports the drift once each time it goes past the threshold.
+    fn check_clock_drift(&mut self) {
+        let drift = match self.clock_drift.drift_ppm() {
+            Some(drift) => drift,
+            None => return,
+        };
+        let beyond = drift.abs() > CLOCK_DRIFT_THRESHOLD_PPM;
+        if beyond && !self.clock_drift_reported {
+            info!("Peer's clock is drifting by {} ppm.\n", drift);
+            self.queue_event(Event::ClockDrift(drift));
+        }
+        self.clock_drift_reported = beyond;
+    }
+
     pub fn on_quality_reply(&mut self, msg: &UdpMsg) -> Result<bool, UdpProtoError> {
         let pong = match msg.message {
             MsgEnum::QualityReply(reply) => reply.pong,
             _ => 0,
         };
-        self.round_trip_time = std::time::SystemTime::now()
+        self.round_trip_time = self
+            .clock
+            .now()
             .duration_since(std::time::UNIX_EPOCH)?
             .as_millis()
             - pong as u128;
+        // Each sample moves the average an eighth of the way, as TCP's does.
+        let sample = self.round_trip_time;
+        self.smoothed_round_trip_time = Some(match self.smoothed_round_trip_time {
+            Some(smoothed) => (smoothed * (RTT_SMOOTHING - 1) + sample) / RTT_SMOOTHING,
+            None => sample,
+        });
 
         Ok(true)
     }
 
+    // The magic numbers on our packets and on the peer's; 0 until we know it.
+    pub fn magic_numbers(&self) -> (u16, u16) {
+        (self.magic_number, self.remote_magic_number)
+    }
+
+    // In ms, or `None` before the first quality reply.
+    pub fn smoothed_round_trip_time(&self) -> Option<u128> {
+        self.smoothed_round_trip_time
+    }
+
     pub fn on_keep_alive(&self, _: &UdpMsg) -> Result<bool, UdpProtoError> {
         Ok(true)
     }
 
+    pub fn on_checksum_report(&mut self, msg: &UdpMsg) -> Result<bool, UdpProtoError> {
+        if let MsgEnum::ChecksumReport(report) = msg.message {
+            self.queue_event(Event::Checksum(report));
+        }
+        Ok(true)
+    }
+
+    pub fn on_state_request(&mut self, _msg: &UdpMsg) -> Result<bool, UdpProtoError> {
+        self.queue_event(Event::StateRequested);
+        Ok(true)
+    }
+
+    pub fn on_state_response(&mut self, msg: &UdpMsg) -> Result<bool, UdpProtoError> {
+        if let MsgEnum::StateResponse(response) = &msg.message {
+            if response.version != self.version {
+                error!(
+                    "Ignoring a state saved by version {} (we're {}).\n",
+                    response.version, self.version
+                );
+                return Ok(true);
+            }
+            self.queue_event(Event::State(response.clone()));
+        }
+        Ok(true)
+    }
+
+    pub fn on_pause(&mut self, msg: &UdpMsg) -> Result<bool, UdpProtoError> {
+        if let MsgEnum::Pause(pause) = msg.message {
+            if pause.ack {
+                if let Some(pending) = self.pending_pause {
+                    if pending.epoch == pause.epoch && pending.paused == pause.paused {
+                        self.pending_pause = None;
+                    }
+                }
+            } else {
+                self.send_pause_msg(Pause { ack: true, ..pause })?;
+                self.queue_event(Event::Pause(pause));
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn on_goodbye(&mut self, _msg: &UdpMsg) -> Result<bool, UdpProtoError> {
+        if self.state != State::Disconnected && !self.disconnect_event_sent {
+            info!("Endpoint said goodbye.  Disconnecting.\n");
+            ggpo_event!(peer = ?self.peer_addr, state = "goodbye", "connection state");
+            // They left on purpose, so there's no coming back.
+            self.reconnect_deadline = 0;
+            self.queue_event(Event::Disconnected);
+            self.disconnect_event_sent = true;
+        }
+        Ok(true)
+    }
+
     pub fn get_network_stats(&self) -> ggpo::NetworkStats {
         ggpo::NetworkStats {
             network: ggpo::Network {
                 ping: self.round_trip_time as usize,
                 send_queue_len: self.pending_output.len(),
                 kbps_sent: self.kbps_sent,
+                input_bytes_per_second: self.input_bytes_per_second,
+                overhead_bytes_per_second: self.overhead_bytes_per_second,
+                loss_percent: self.loss_percent_estimate,
                 recv_queue_len: Default::default(),
             },
             timesync: ggpo::TimeSync {
                 remote_frames_behind: self.remote_frame_advantage,
                 local_frames_behind: self.local_frame_advantage,
+                clock_drift_ppm: self.clock_drift.drift_ppm(),
             },
         }
     }
@@ -1099,7 +2328,7 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
          * last frame they gave us plus some delta for the one-way packet
          * trip time.
          */
-        let remote_frame = self.last_received_input.frame.unwrap_or(0)
+        let remote_frame = self.last_received_input.frame.number().unwrap_or(0)
             + (self.round_trip_time as FrameNum * 60 / 1000);
 
         /*
@@ -1111,45 +2340,328 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
         self.local_frame_advantage = (remote_frame as i64 - local_frame as i64) as i32;
     }
 
+    /*
+     * The raw advantages time sync averages: ours, from the last
+     * `set_local_frame_number`, and the peer's, from its last quality report.
+     * Each is how many frames behind the other side that side is.
+     */
+    pub fn frame_advantage(&self) -> (i32, i32) {
+        (self.local_frame_advantage, self.remote_frame_advantage)
+    }
+
     pub fn recommend_frame_delay(&mut self) -> u32 {
         // XXX: require idle input should be a configuration parameter
         return self.timesync.recommend_frame_wait_duration(false);
     }
 
+    // Both ways; see `set_local_input_size` and `set_remote_input_size` to set one.
+    pub fn set_input_size(&mut self, size: usize) {
+        self.input_size = size;
+        self.remote_input_size = size;
+    }
+
+    pub fn set_local_input_size(&mut self, size: usize) {
+        self.input_size = size;
+    }
+
+    pub fn set_remote_input_size(&mut self, size: usize) {
+        self.remote_input_size = size;
+    }
+
+    // Our rolling checksum of confirmed input as of `frame`, for the next quality report.
+    pub fn set_input_checksum(&mut self, frame: Frame, checksum: u32) {
+        self.input_checksum = Some((frame, checksum));
+    }
+
+    // For testing only: flips a bit of the peer's input for `frame` as it's decoded.
+    pub fn set_input_corruption(&mut self, frame: Frame) {
+        self.input_corruption = Some(frame);
+    }
+
+    // Our contribution to the shared seed, sent back in every sync reply.
+    pub fn set_seed_nonce(&mut self, nonce: u64) {
+        self.seed_nonce = nonce;
+    }
+
+    // The peer's contribution, once one of its sync replies has come back.
+    pub fn remote_seed_nonce(&self) -> Option<u64> {
+        self.remote_seed_nonce
+    }
+
+    /*
+     * The seed our nonce and the peer's make, along with any `others` the
+     * session heard from its other peers, which each of them works out too.
+     * `None` until the peer's nonce has arrived.
+     */
+    pub fn negotiated_seed(&self, others: &[u64]) -> Option<u64> {
+        let mut nonces = vec![self.seed_nonce, self.remote_seed_nonce?];
+        nonces.extend_from_slice(others);
+        Some(combine_seed_nonces(&mut nonces))
+    }
+
+    /*
+     * Inputs are only meaningful if both ends agree on their size.  A peer
+     * that disagrees is reported once and its packets are otherwise ignored,
+     * which keeps it from ever finishing the sync handshake.
+     */
+    fn check_input_size(&mut self, remote: u16) -> bool {
+        if remote as usize == self.remote_input_size {
+            return true;
+        }
+        error!(
+            "peer input size {} doesn't match ours ({}).\n",
+            remote, self.remote_input_size
+        );
+        let mismatch = InputSizeMismatch {
+            local: self.remote_input_size,
+            remote: remote as usize,
+        };
+        self.sync_failure = Some(SyncFailure::InputSizeMismatch(mismatch));
+        if !self.input_size_mismatch_sent {
+            self.input_size_mismatch_sent = true;
+            self.queue_event(Event::InputSizeMismatch(mismatch));
+        }
+        false
+    }
+
+    pub fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    // The version the peer sent in the handshake, whether or not it matched.
+    pub fn remote_version(&self) -> Option<u32> {
+        self.remote_version
+    }
+
+    /*
+     * A peer running another version would save and simulate differently, so
+     * rather than let it in to desync we never finish the handshake with it.
+     */
+    fn check_version(&mut self, remote: u32) -> bool {
+        self.remote_version = Some(remote);
+        if remote == self.version {
+            return true;
+        }
+        error!(
+            "peer version {} doesn't match ours ({}).\n",
+            remote, self.version
+        );
+        let mismatch = IncompatibleVersion {
+            local: self.version,
+            remote,
+        };
+        self.sync_failure = Some(SyncFailure::VersionMismatch(mismatch));
+        if !self.version_mismatch_sent {
+            self.version_mismatch_sent = true;
+            self.queue_event(Event::IncompatibleVersion(mismatch));
+        }
+        false
+    }
+
+    pub fn sync_failure(&self) -> Option<SyncFailure> {
+        self.sync_failure
+    }
+
+    // Round trips the handshake takes; at least one.  Only counts from the next `synchronize`.
+    pub fn set_num_sync_packets(&mut self, packets: u32) {
+        self.num_sync_packets = packets.max(1);
+    }
+
+    // How long to wait for a sync reply before asking again: after the first request, and after the rest.
+    pub fn set_sync_retry_intervals(&mut self, first: u128, retry: u128) {
+        self.sync_first_retry_interval = first;
+        self.sync_retry_interval = retry;
+    }
+
+    /*
+     * Ask the peer to resend input once a hole in what it's sent us has gone
+     * unfilled for `window` ms, and disconnect after `MAX_STREAM_GAP_REQUESTS`
+     * requests go unanswered.  0, the default, leaves holes to the peer's
+     * retransmit timer.  Spectators, which can't predict their way past a
+     * missing frame, turn it on.
+     */
+    pub fn set_stream_gap_window(&mut self, window: u128) {
+        self.stream_gap_window = window;
+    }
+
+    pub fn stream_gap(&self) -> Option<StreamGap> {
+        self.stream_gap
+    }
+
+    // How long to try to synchronize before disconnecting.  0 tries forever.
+    pub fn set_sync_timeout(&mut self, timeout: u128) {
+        self.sync_timeout = timeout;
+    }
+
+    /*
+     * Takes effect on the next poll, measured against the silence so far
+     * rather than starting over.  A peer we've already said was interrupted
+     * gets the interruption again, with however long it has left now; one
+     * that's already timed out is gone for good.
+     */
     pub fn set_disconnect_timeout(&mut self, timeout: u128) {
         self.disconnect_timeout = timeout;
+        if timeout > 0 && self.disconnect_notify_sent && !self.disconnect_event_sent {
+            let silence = self
+                .clock
+                .now()
+                .duration_since(self.last_recv_time)
+                .unwrap_or_default()
+                .as_millis();
+            self.queue_event(Event::NetworkInterrupted(NetworkInterrupted {
+                disconnect_timeout: timeout.saturating_sub(silence),
+            }));
+        }
     }
 
+    // Like `set_disconnect_timeout`, this goes by the silence so far.
     pub fn set_disconnect_notify_start(&mut self, timeout: u128) {
         self.disconnect_notify_start = timeout;
     }
 
+    // How long after timing out the peer may still reconnect.  0 disables it.
+    pub fn set_reconnect_window(&mut self, window: u128) {
+        self.reconnect_window = window;
+    }
+
+    pub fn set_retransmit_interval(&mut self, interval: u128) {
+        self.retransmit_interval = interval;
+        self.retransmit
+            .set_interval(self.profile.retransmit_interval(interval));
+    }
+
+    // See `TransportProfile::TrustedLan` before turning the checks off.
+    pub fn set_transport_profile(&mut self, profile: TransportProfile) {
+        if profile != TransportProfile::Internet {
+            info!(
+                "using the {:?} transport profile; packets won't be checked against the peer's magic number.\n",
+                profile
+            );
+        }
+        self.profile = profile;
+        self.set_retransmit_interval(self.retransmit_interval);
+    }
+
+    /*
+     * Caps what we send, in bytes per second, counting each message at its
+     * uncompressed size plus headers.  Fresh input always goes out; quality reports and resends of input the
+     * peer already has a copy of wait until there's room.  0 removes the cap.
+     */
+    pub fn set_send_rate_limit(&mut self, bytes_per_second: usize) {
+        self.send_rate_limit = bytes_per_second;
+        self.recent_sends.clear();
+    }
+
+    // Whether `bytes` more (plus headers) fits in the last second's budget.
+    fn within_send_budget(&mut self, now: u128, bytes: usize) -> bool {
+        if self.send_rate_limit == 0 {
+            return true;
+        }
+        self.forget_old_sends(now);
+        let sent: usize = self.recent_sends.iter().map(|&(_, bytes)| bytes).sum();
+        sent + size_of::<Header>() + bytes + UDP_HEADER_SIZE <= self.send_rate_limit
+    }
+
+    /*
+     * When `bytes` more will fit in the send budget: now if they already do,
+     * otherwise once enough of the last second's sends have aged out of it.
+     */
+    fn send_budget_frees(&self, now: u128, bytes: usize) -> u128 {
+        if self.send_rate_limit == 0 {
+            return now;
+        }
+        let window = || {
+            self.recent_sends
+                .iter()
+                .filter(|&&(time, _)| time + SEND_RATE_WINDOW > now)
+        };
+        let needed = size_of::<Header>() + bytes + UDP_HEADER_SIZE;
+        let mut sent: usize = window().map(|&(_, bytes)| bytes).sum();
+        let mut frees = now;
+        for &(time, bytes) in window() {
+            if sent + needed <= self.send_rate_limit {
+                break;
+            }
+            sent -= bytes;
+            frees = time + SEND_RATE_WINDOW;
+        }
+        frees
+    }
+
+    fn forget_old_sends(&mut self, now: u128) {
+        while let Some(&(time, _)) = self.recent_sends.front() {
+            if time + SEND_RATE_WINDOW > now {
+                break;
+            }
+            self.recent_sends.pop_front();
+        }
+    }
+
+    // Roughly how big a resend of the pending output would be.
+    fn pending_output_size(&self) -> usize {
+        UdpMsg::new(MsgType::Input).payload_size() + self.pending_output.len() * self.input_size
+    }
+
+    // Drops the given percentage of outgoing packets, for testing loss recovery.
+    pub fn set_packet_loss(&mut self, percent: i32) {
+        self.loss_percent = percent;
+    }
+
+    /*
+     * Has the endpoint's timers read `clock` from now on.  The last send and
+     * receive times, and a handshake already under way, are restarted from
+     * it, so time kept on the old clock doesn't count against the new one.
+     */
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        let now = clock.now();
+        self.last_send_time = now;
+        self.last_recv_time = now;
+        if let Ok(since_epoch) = now.duration_since(UNIX_EPOCH) {
+            if self.last_sync_request > 0 {
+                self.last_sync_request = since_epoch.as_millis();
+            }
+            if self.sync_started > 0 {
+                self.sync_started = since_epoch.as_millis();
+            }
+        }
+        self.clock = clock;
+    }
+
+    // Holds outgoing packets for about this many ms, for testing slow links.
+    pub fn set_send_latency(&mut self, latency: i32) {
+        self.send_latency = latency;
+    }
+
+    /*
+     * How much the simulated latency varies, as a standard deviation in ms.
+     * Packets still go out in order, so one held back holds up the rest.
+     */
+    pub fn set_send_jitter(&mut self, jitter: i32) {
+        self.send_jitter = jitter;
+    }
+
+    pub fn connection_quality(&self) -> ConnectionQuality {
+        self.quality
+    }
+
     pub fn pump_send_queue(&mut self) -> Result<(), UdpProtoError> {
         while !self.send_queue.is_empty() {
             let entry = self.send_queue.front().unwrap();
 
-            if self.send_latency > 0 {
-                // should really come up with a gaussian distributation based on the configured
-                // value, but this will do for now.
-                // 2020-7: Below is the gaussian version.
-                // TODO: Test other standard deviations.
-                let jitter = Normal::new(self.send_latency as f64, 3.)
-                    .unwrap()
-                    .sample(&mut StdRng::seed_from_u64(self.rng.gen()));
-
-                if std::time::SystemTime::now()
-                    < self
-                        .send_queue
-                        .front()
-                        .ok_or(UdpProtoError::SendQueueEmpty)?
-                        .queue_time
-                        + std::time::Duration::from_millis(jitter as u64)
-                {
-                    break;
-                }
+            if self.clock.now() < entry.queue_time + std::time::Duration::from_millis(entry.delay) {
+                break;
             }
 
-            if self.oop_percent > 0
+            if self.loss_percent > 0 && self.rng.gen_range(0, 100) < self.loss_percent {
+                info!(
+                    "dropping packet (seq: {}) to simulate packet loss.\n",
+                    entry.msg.header.sequence_number
+                );
+            } else if self.oop_percent > 0
                 && self.oo_packet.msg.is_none()
                 && ((self.rng.gen_range(0, 100)) < self.oop_percent)
             {
@@ -1158,7 +2670,9 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
                     "creating rogue oop (seq: {} delay: {})\n",
                     entry.msg.header.sequence_number, delay
                 );
-                self.oo_packet.send_time = std::time::SystemTime::now()
+                self.oo_packet.send_time = self
+                    .clock
+                    .now()
                     .duration_since(std::time::UNIX_EPOCH)
                     .expect("Time travel is afoot")
                     .as_millis();
@@ -1168,39 +2682,82 @@ impl<Callback: UdpCallback + Send + Sync> UdpProtocol<Callback> {
                 // TODO: figure out what exactly this assert wants to check for.
                 // assert!(entry.dest_addr)
 
-                self.udp
+                let msg = entry.msg.clone();
+                let sent = self
+                    .udp
                     .as_mut()
                     .ok_or(UdpProtoError::UdpUninit)?
                     .lock()
-                    .send_to(entry.msg.clone(), &entry.dest_addr)?;
+                    .send_to(msg.clone(), &[entry.dest_addr]);
+                match sent {
+                    // Leave it at the front; the next pump tries again.
+                    Err(UdpError::SendQueueFull { .. }) => break,
+                    sent => self.record_send(&msg, sent?)?,
+                }
             }
             self.send_queue.pop_front();
         }
         if self.oo_packet.msg.is_some()
             && self.oo_packet.send_time
-                < std::time::SystemTime::now()
+                < self
+                    .clock
+                    .now()
                     .duration_since(std::time::UNIX_EPOCH)?
                     .as_millis()
         {
             info!("Sending rogue oop!");
-            self.udp
+            let msg = self
+                .oo_packet
+                .msg
+                .as_ref()
+                .ok_or(UdpProtoError::OOPacketMsgUninit)?
+                .clone();
+            let sent = self
+                .udp
                 .as_mut()
                 .ok_or(UdpProtoError::UdpUninit)?
                 .lock()
-                .send_to(
-                    self.oo_packet
-                        .msg
-                        .as_ref()
-                        .ok_or(UdpProtoError::OOPacketMsgUninit)?
-                        .clone(),
-                    &self.oo_packet.dest_addr,
-                )?;
-            self.oo_packet.msg = None;
+                .send_to(msg.clone(), &[self.oo_packet.dest_addr]);
+            match sent {
+                Err(UdpError::SendQueueFull { .. }) => {}
+                sent => {
+                    self.record_send(&msg, sent?)?;
+                    self.oo_packet.msg = None;
+                }
+            }
         }
 
         Ok(())
     }
 
+    // Counts a packet the transport took, at its size on the wire.
+    fn record_send(&mut self, msg: &UdpMsg, wire_size: usize) -> Result<(), UdpProtoError> {
+        let now = self.clock.now().duration_since(UNIX_EPOCH)?.as_millis();
+        let input = match &msg.message {
+            MsgEnum::Batch(batch) => batch
+                .messages
+                .iter()
+                .any(|msg| msg.header.packet_type == MsgType::Input),
+            _ => msg.header.packet_type == MsgType::Input,
+        };
+        self.bandwidth.record(now, wire_size, input);
+        Ok(())
+    }
+
+    // Frames of input sent that the peer hasn't acked yet.
+    pub fn unacked_input_len(&self) -> usize {
+        self.pending_output.len()
+    }
+
+    pub fn oldest_unacked_frame(&self) -> Option<Frame> {
+        self.pending_output.front().map(|input| input.frame)
+    }
+
+    // Forgets unacked input, for a peer that will never ack it.
+    pub fn clear_pending_output(&mut self) {
+        self.pending_output.clear();
+    }
+
     pub fn clear_send_queue(&mut self) {
         self.send_queue.clear();
     }
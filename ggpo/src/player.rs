@@ -1,3 +1,6 @@
+#[cfg(feature = "resolve")]
+use crate::ggpo::GGPOError;
+
 pub type PlayerHandle = u32;
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
@@ -12,6 +15,35 @@ pub struct Player {
     pub size: usize,
     pub player_type: PlayerType,
     pub player_num: usize,
+    /*
+     * Bytes of input this player sends each frame.  `None` uses the session's
+     * input size.  Both ends have to agree on it, so a peer adding us as a
+     * remote player needs the size we give our local player.
+     */
+    pub input_size: Option<usize>,
+}
+
+/*
+ * What a session remembers about a player once it has been added; see
+ * `Session::players`.  A spectator's queue is its place among the
+ * spectators.  `connected` is as of when the info was asked for.
+ */
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct PlayerInfo {
+    pub handle: PlayerHandle,
+    pub queue: u32,
+    pub player_type: PlayerType,
+    pub connected: bool,
+}
+
+impl PlayerInfo {
+    // Where the player is, for remote players and spectators.
+    pub fn addr(&self) -> Option<std::net::SocketAddr> {
+        match self.player_type {
+            PlayerType::Local => None,
+            PlayerType::Remote(addr) | PlayerType::Spectator(addr) => Some(addr),
+        }
+    }
 }
 
 impl Player {
@@ -20,6 +52,45 @@ impl Player {
             player_num,
             player_type,
             size: std::mem::size_of::<Player>(),
+            input_size: None,
+        }
+    }
+
+    pub fn with_input_size(mut self, input_size: usize) -> Player {
+        self.input_size = Some(input_size);
+        self
+    }
+
+    /*
+     * A remote player at `host`, a "name:port" string, resolved without
+     * blocking.  A session on IPv4 alone can only reach IPv4 addresses, so
+     * one of those is taken; a dual-stack session (`SessionConfig::dual_stack`)
+     * takes IPv6 over IPv4.  Resolvers don't agree on the order they give
+     * addresses in, so of the family picked, the lowest address wins.
+     */
+    #[cfg(feature = "resolve")]
+    pub async fn remote_from_host(
+        host: &str,
+        player_num: usize,
+        dual_stack: bool,
+    ) -> Result<Player, GGPOError> {
+        let unresolved = |reason: String| GGPOError::Unresolved {
+            host: host.to_string(),
+            reason,
+        };
+        let addresses = async_net::resolve(host)
+            .await
+            .map_err(|e| unresolved(e.to_string()))?;
+        let ipv4 = addresses.iter().filter(|address| address.is_ipv4()).min();
+        let ipv6 = addresses.iter().filter(|address| address.is_ipv6()).min();
+        let address = if dual_stack { ipv6.or(ipv4) } else { ipv4 };
+        match address {
+            Some(address) => Ok(Player::new(PlayerType::Remote(*address), player_num)),
+            None => Err(unresolved(format!(
+                "no {} address among {:?}",
+                if dual_stack { "IPv4 or IPv6" } else { "IPv4" },
+                addresses
+            ))),
         }
     }
 }
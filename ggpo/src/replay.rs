@@ -0,0 +1,292 @@
+/*
+ * Session recording and deterministic replay.
+ *
+ * A `Recorder` logs every local input handed to a session, along with the
+ * events the session reported, so a match can be fed back through a fresh
+ * session later by a `Replayer`.  Handy for chasing desyncs and for turning a
+ * bad match into a regression test.
+ */
+use crate::{
+    game_input::{
+        Frame, FrameNum, GameInput, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS,
+    },
+    ggpo::{DisconnectFlags, Event, GGPOError, Session},
+    player::PlayerHandle,
+};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+use thiserror::Error;
+
+// Bump whenever the layout of `Record` changes.
+pub const RECORDING_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("Recording version {found} is unsupported (expected {expected}).")]
+    UnsupportedVersion { found: u32, expected: u32 },
+    #[error("IO error.")]
+    Io {
+        #[from]
+        source: std::io::Error,
+    },
+    #[error("Bincode (de)serialization Error")]
+    Bincode {
+        #[from]
+        source: bincode::Error,
+    },
+    #[error("No input was recorded for player {player} on frame {frame}.")]
+    NotRecorded {
+        frame: FrameNum,
+        player: PlayerHandle,
+    },
+    #[error(
+        "Input for player {player} on frame {frame} diverged from the recording in bits {bits:?}."
+    )]
+    Diverged {
+        frame: FrameNum,
+        player: PlayerHandle,
+        bits: Vec<usize>,
+    },
+    // Boxed, as `GGPOError` can hold a `ReplayError` in turn.
+    #[error("Session error.")]
+    Session {
+        #[source]
+        source: Box<GGPOError>,
+    },
+}
+
+impl From<GGPOError> for ReplayError {
+    fn from(source: GGPOError) -> Self {
+        ReplayError::Session {
+            source: Box::new(source),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedInput {
+    pub frame: FrameNum,
+    pub player: PlayerHandle,
+    pub input: InputBuffer,
+    pub size: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Record {
+    Input(RecordedInput),
+    Event { frame: FrameNum, event: Event },
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Recorder {
+    records: Vec<Record>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // Call alongside `Session::add_local_input` with the same arguments.
+    pub fn record_input(
+        &mut self,
+        frame: FrameNum,
+        player: PlayerHandle,
+        input: &InputBuffer,
+        size: usize,
+    ) {
+        self.records.push(Record::Input(RecordedInput {
+            frame,
+            player,
+            input: *input,
+            size,
+        }));
+    }
+
+    // Call from `GGPOSessionCallbacks::on_event`.
+    pub fn record_event(&mut self, frame: FrameNum, event: &Event) {
+        self.records.push(Record::Event {
+            frame,
+            event: event.clone(),
+        });
+    }
+
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), ReplayError> {
+        // The version goes first on its own so a reader can reject a recording
+        // before trying to decode a body it doesn't understand.
+        bincode::serialize_into(&mut writer, &RECORDING_VERSION)?;
+        bincode::serialize_into(&mut writer, &self.records)?;
+        Ok(writer.flush()?)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ReplayError> {
+        let mut bytes = Vec::new();
+        self.write_to(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ReplayError> {
+        info!(
+            "saving {} records to {:?}.\n",
+            self.records.len(),
+            path.as_ref()
+        );
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Replayer {
+    records: Vec<Record>,
+}
+
+impl Replayer {
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, ReplayError> {
+        let version: u32 = bincode::deserialize_from(&mut reader)?;
+        if version != RECORDING_VERSION {
+            return Err(ReplayError::UnsupportedVersion {
+                found: version,
+                expected: RECORDING_VERSION,
+            });
+        }
+        let records: Vec<Record> = bincode::deserialize_from(reader)?;
+        Ok(Self { records })
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReplayError> {
+        Self::read_from(bytes)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ReplayError> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    pub fn inputs(&self) -> impl Iterator<Item = &RecordedInput> {
+        self.records.iter().filter_map(|record| match record {
+            Record::Input(input) => Some(input),
+            _ => None,
+        })
+    }
+
+    pub fn events(&self) -> impl Iterator<Item = (FrameNum, &Event)> {
+        self.records.iter().filter_map(|record| match record {
+            Record::Event { frame, event } => Some((*frame, event)),
+            _ => None,
+        })
+    }
+
+    pub fn frame_inputs(&self, frame: FrameNum) -> impl Iterator<Item = &RecordedInput> {
+        self.inputs().filter(move |input| input.frame == frame)
+    }
+
+    pub fn last_frame(&self) -> Option<FrameNum> {
+        self.inputs().map(|input| input.frame).max()
+    }
+
+    /*
+     * Drives `session` through the recorded match: for each frame the recorded
+     * local inputs are added, the synchronized inputs are handed to `advance`
+     * (which should step the game exactly one frame), and the frame is ended.
+     */
+    pub fn replay<S, F>(&self, session: &mut S, mut advance: F) -> Result<(), GGPOError>
+    where
+        S: Session,
+        F: FnMut(FrameNum, &InputBuffer, i32),
+    {
+        let last_frame = match self.last_frame() {
+            Some(frame) => frame,
+            None => return Ok(()),
+        };
+
+        for frame in 0..=last_frame {
+            for recorded in self.frame_inputs(frame) {
+                session.add_local_input(recorded.player, &recorded.input, recorded.size)?;
+            }
+
+            let mut values = [[0; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+            let mut disconnect_flags = DisconnectFlags::default();
+            session.synchronize_input(&mut values, Some(&mut disconnect_flags))?;
+            advance(frame, &values, disconnect_flags.into());
+            session.increment_frame()?;
+        }
+        Ok(())
+    }
+}
+
+/*
+ * Cross-checks a live session's local input against a recording, for catching
+ * input-handling regressions in CI.  Route local input through
+ * `add_local_input` instead of calling the session directly: each input is
+ * compared to the one recorded for the same frame and player before it's
+ * handed on, and the first mismatch fails with `ReplayError::Diverged`
+ * naming the frame and every bit that differs.
+ */
+#[derive(Debug, Clone)]
+pub struct ReplayGuard {
+    replayer: Replayer,
+}
+
+impl ReplayGuard {
+    pub fn new(replayer: Replayer) -> Self {
+        Self { replayer }
+    }
+
+    pub fn replayer(&self) -> &Replayer {
+        &self.replayer
+    }
+
+    pub fn check(
+        &self,
+        frame: FrameNum,
+        player: PlayerHandle,
+        input: &InputBuffer,
+        size: usize,
+    ) -> Result<(), ReplayError> {
+        let recorded = self
+            .replayer
+            .frame_inputs(frame)
+            .find(|recorded| recorded.player == player)
+            .ok_or(ReplayError::NotRecorded { frame, player })?;
+
+        let expected = GameInput::init(Frame::new(frame), Some(&recorded.input), recorded.size);
+        let actual = GameInput::init(Frame::new(frame), Some(input), size);
+        let bits: Vec<usize> = expected.changed_bits(&actual).collect();
+        if !bits.is_empty() {
+            error!(
+                "input for player {} on frame {} diverged from the recording in bits {:?}.\n",
+                player, frame, bits
+            );
+            return Err(ReplayError::Diverged {
+                frame,
+                player,
+                bits,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn add_local_input<S: Session>(
+        &self,
+        session: &mut S,
+        frame: FrameNum,
+        player: PlayerHandle,
+        input: &InputBuffer,
+        size: usize,
+    ) -> Result<(), ReplayError> {
+        self.check(frame, player, input, size)?;
+        Ok(session.add_local_input(player, input, size)?)
+    }
+}
@@ -0,0 +1,289 @@
+/*
+ * A fixed-rate driver for a session.
+ *
+ * `SessionRunner` owns the per-frame dance every game otherwise writes by
+ * hand: poll the network for whatever is left of the frame budget, feed in
+ * local input, fetch the synchronized inputs, step the game and end the
+ * frame.  When the session reports that we are running ahead of our peers
+ * (`Event::TimeSync`) the runner skips advancing for that many frames so the
+ * others can catch up.
+ *
+ * Events reach the runner through a channel: wrap the game's callbacks in an
+ * `EventForwarder` before handing them to the session, and pass the matching
+ * receiver to `SessionRunner::new`.  Every event is passed on, unchanged, to
+ * the receiver `new` returns.
+ *
+ * A game that renders at its own rate rather than letting the runner pace it
+ * can use `FixedTimestep` instead, to work out how many frames to step each
+ * time it draws.
+ */
+use crate::{
+    game_input::{Frame, FrameNum, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS},
+    ggpo::{DisconnectFlags, Event, GGPOError, GGPOSessionCallbacks, SavedState, Session},
+    player::PlayerHandle,
+};
+use bytes::Bytes;
+use log::info;
+use parking_lot::Mutex;
+use std::{
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+// Wraps a game's callbacks, handing a copy of every event to a channel.
+#[derive(Clone)]
+pub struct EventForwarder<T: GGPOSessionCallbacks> {
+    inner: T,
+    sender: Sender<Event>,
+}
+
+impl<T: GGPOSessionCallbacks> EventForwarder<T> {
+    pub fn new(inner: T) -> (Self, Receiver<Event>) {
+        let (sender, receiver) = channel();
+        (EventForwarder { inner, sender }, receiver)
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<T: GGPOSessionCallbacks> GGPOSessionCallbacks for EventForwarder<T> {
+    fn save_game_state(&mut self, frame: Frame) -> Result<SavedState, GGPOError> {
+        self.inner.save_game_state(frame)
+    }
+
+    fn load_game_state(&mut self, buffer: &Bytes, length: usize) -> bool {
+        self.inner.load_game_state(buffer, length)
+    }
+
+    fn log_game_state(&mut self, filename: String, buffer: Bytes, length: usize) -> bool {
+        self.inner.log_game_state(filename, buffer, length)
+    }
+
+    fn advance_frame(&mut self, flags: i32) -> bool {
+        self.inner.advance_frame(flags)
+    }
+
+    fn fast_forward_frame(&mut self, inputs: &InputBuffer, disconnect_flags: i32) -> bool {
+        self.inner.fast_forward_frame(inputs, disconnect_flags)
+    }
+
+    fn on_rollback(&mut self, from_frame: Frame, to_frame: Frame, resimulated: u32) {
+        self.inner.on_rollback(from_frame, to_frame, resimulated)
+    }
+
+    fn on_event(&mut self, info: &Event) {
+        // The runner going away just means nobody is listening any more.
+        let _ = self.sender.send(info.clone());
+        self.inner.on_event(info);
+    }
+}
+
+pub struct SessionRunner<S: Session> {
+    session: Arc<Mutex<S>>,
+    local_players: Vec<PlayerHandle>,
+    input_size: usize,
+    frame_duration: Duration,
+    next_tick: Instant,
+    stall_frames: FrameNum,
+    frames_advanced: FrameNum,
+    events: Receiver<Event>,
+    forward: Sender<Event>,
+}
+
+impl<S: Session> SessionRunner<S> {
+    pub fn new(
+        session: Arc<Mutex<S>>,
+        frame_rate: u32,
+        input_size: usize,
+        events: Receiver<Event>,
+    ) -> (Self, Receiver<Event>) {
+        assert!(frame_rate > 0);
+        let (forward, receiver) = channel();
+        let runner = SessionRunner {
+            session,
+            local_players: Vec::new(),
+            input_size,
+            frame_duration: Duration::from_secs(1) / frame_rate,
+            next_tick: Instant::now(),
+            stall_frames: 0,
+            frames_advanced: 0,
+            events,
+            forward,
+        };
+        (runner, receiver)
+    }
+
+    // Registers a handle whose input `tick` should ask for every frame.
+    pub fn add_local_player(&mut self, handle: PlayerHandle) {
+        self.local_players.push(handle);
+    }
+
+    pub fn session(&self) -> &Arc<Mutex<S>> {
+        &self.session
+    }
+
+    pub fn frames_advanced(&self) -> FrameNum {
+        self.frames_advanced
+    }
+
+    /*
+     * Runs one frame.  Polls the network until the frame's deadline, then,
+     * unless we're stalling, asks `local_input` for each local player's input
+     * and hands the synchronized inputs to `step`.  Returns whether the game
+     * actually advanced; it won't while the session is still synchronizing,
+     * has hit the prediction barrier, or is letting peers catch up.
+     */
+    pub fn tick<I, F>(&mut self, mut local_input: I, mut step: F) -> Result<bool, GGPOError>
+    where
+        I: FnMut(PlayerHandle) -> InputBuffer,
+        F: FnMut(&InputBuffer, i32),
+    {
+        let timeout = self.next_tick.saturating_duration_since(Instant::now());
+        self.session.lock().do_poll(Some(timeout))?;
+        self.next_tick = std::cmp::max(self.next_tick, Instant::now()) + self.frame_duration;
+        self.drain_events();
+
+        if self.stall_frames > 0 {
+            info!("skipping a frame to let peers catch up.\n");
+            self.stall_frames -= 1;
+            return Ok(false);
+        }
+
+        let mut session = self.session.lock();
+        for handle in self.local_players.iter() {
+            match session.add_local_input(*handle, &local_input(*handle), self.input_size) {
+                Ok(()) => (),
+                Err(GGPOError::NotSynchronized)
+                | Err(GGPOError::PredictionThreshold)
+                | Err(GGPOError::Paused) => return Ok(false),
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut values = [[0; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+        let mut disconnect_flags = DisconnectFlags::default();
+        match session.synchronize_input(&mut values, Some(&mut disconnect_flags)) {
+            Ok(()) => (),
+            Err(GGPOError::NotSynchronized) => return Ok(false),
+            Err(e) => return Err(e),
+        }
+        step(&values, disconnect_flags.into());
+        session.increment_frame()?;
+        self.frames_advanced += 1;
+        Ok(true)
+    }
+
+    // Ticks until the game has advanced `frames` more frames.
+    pub fn run<I, F>(
+        &mut self,
+        frames: FrameNum,
+        mut local_input: I,
+        mut step: F,
+    ) -> Result<(), GGPOError>
+    where
+        I: FnMut(PlayerHandle) -> InputBuffer,
+        F: FnMut(&InputBuffer, i32),
+    {
+        let target = self.frames_advanced + frames;
+        while self.frames_advanced < target {
+            self.tick(&mut local_input, &mut step)?;
+        }
+        Ok(())
+    }
+
+    fn drain_events(&mut self) {
+        while let Ok(event) = self.events.try_recv() {
+            if let Event::TimeSync(time_sync) = &event {
+                self.stall_frames = std::cmp::max(self.stall_frames, time_sync.frames_ahead);
+            }
+            let _ = self.forward.send(event);
+        }
+    }
+}
+
+/*
+ * Turns however long each rendered frame took into a whole number of fixed
+ * simulation steps, carrying the remainder over to the next one.  Call
+ * `steps` once per render with the time since the last, then run that many
+ * frames, each ending in `increment_frame`.
+ *
+ * A slow render can owe a lot of steps, and running them all makes the next
+ * render slower still.  At most `max_steps` are run at once; time owed past
+ * that is dropped, so the game slows down instead of spiralling.
+ *
+ * Running ahead of our peers (`Event::TimeSync`, from the session's
+ * `recommend_frame_wait_duration`) is handled by skipping that many steps as
+ * they come due, like `SessionRunner` does.
+ */
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+    step: Duration,
+    max_steps: u32,
+    accumulated: Duration,
+    stall_frames: FrameNum,
+}
+
+impl FixedTimestep {
+    pub fn new(frame_rate: u32, max_steps: u32) -> Self {
+        assert!(frame_rate > 0 && max_steps > 0);
+        FixedTimestep {
+            step: Duration::from_secs(1) / frame_rate,
+            max_steps,
+            accumulated: Duration::from_secs(0),
+            stall_frames: 0,
+        }
+    }
+
+    pub fn step_duration(&self) -> Duration {
+        self.step
+    }
+
+    // How many steps to run for `elapsed` more time.  Never more than `max_steps`.
+    pub fn steps(&mut self, elapsed: Duration) -> u32 {
+        self.accumulated += elapsed;
+        let mut due = 0;
+        while self.accumulated >= self.step && due < self.max_steps {
+            self.accumulated -= self.step;
+            due += 1;
+        }
+        if self.accumulated >= self.step {
+            info!(
+                "{:?} behind after {} steps; dropping it.\n",
+                self.accumulated, due
+            );
+            self.accumulated = Duration::from_secs(0);
+        }
+        let skipped = std::cmp::min(due, self.stall_frames);
+        if skipped > 0 {
+            info!("skipping {} steps to let peers catch up.\n", skipped);
+            self.stall_frames -= skipped;
+        }
+        due - skipped
+    }
+
+    // Skips the next `frames` steps that come due.
+    pub fn stall(&mut self, frames: FrameNum) {
+        self.stall_frames = std::cmp::max(self.stall_frames, frames);
+    }
+
+    // Picks up `Event::TimeSync`; pass it every event the session sends.
+    pub fn on_event(&mut self, event: &Event) {
+        if let Event::TimeSync(time_sync) = event {
+            self.stall(time_sync.frames_ahead);
+        }
+    }
+
+    // How far into the next step we are, from 0 to 1, for interpolating what's drawn.
+    pub fn alpha(&self) -> f32 {
+        self.accumulated.as_secs_f32() / self.step.as_secs_f32()
+    }
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::{connect_status, sync_with, MockGame};
+use ggpo::{
+    config::SessionConfig,
+    game_input::{Frame, GameInput, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS},
+    ggpo::GGPO_MAX_PREDICTION_FRAMES,
+    sync::{GGPOSync, ADAPTIVE_PREDICTION_WINDOW, MIN_ADAPTIVE_PREDICTION_FRAMES},
+};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+const INPUT_SIZE: usize = 1;
+
+/*
+ * Runs a window's worth of frames, with the remote player's input for each
+ * arriving one frame late.  `value` picks what they pressed on a frame.
+ */
+fn play_window(sync: &mut GGPOSync<MockGame>, value: impl Fn(u32) -> u8) {
+    for _ in 0..ADAPTIVE_PREDICTION_WINDOW {
+        let frame = sync.get_frame_count();
+        let mut local = GameInput::init(Frame::default(), None, INPUT_SIZE);
+        assert!(sync.add_local_input(0, &mut local).unwrap());
+        let mut values: InputBuffer = Default::default();
+        sync.synchronize_inputs(&mut values).unwrap();
+        sync.increment_frame().unwrap();
+
+        let mut bits = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+        bits[0][0] = value(frame);
+        sync.add_remote_input(
+            1,
+            &GameInput::init(Frame::new(frame), Some(&bits), INPUT_SIZE),
+        )
+        .unwrap();
+        sync.check_simulation().unwrap();
+        sync.set_last_confirmed_frame(Frame::new(frame)).unwrap();
+    }
+}
+
+// Something different every frame, so repeating the last input is always wrong.
+fn erratic(frame: u32) -> u8 {
+    frame as u8
+}
+
+fn steady(_frame: u32) -> u8 {
+    7
+}
+
+#[test]
+fn window_shrinks_under_erratic_input_and_recovers() {
+    let status = connect_status(2);
+    let mut sync = sync_with(
+        Arc::new(Mutex::new(MockGame::default())),
+        &status,
+        INPUT_SIZE,
+    );
+    sync.set_adaptive_prediction(true);
+    assert_eq!(sync.prediction_frames(), GGPO_MAX_PREDICTION_FRAMES);
+
+    play_window(&mut sync, erratic);
+    assert_eq!(sync.prediction_frames(), GGPO_MAX_PREDICTION_FRAMES / 2);
+    assert!(sync.adaptive_prediction().unwrap().mispredict_rate() > 0.9);
+
+    play_window(&mut sync, erratic);
+    play_window(&mut sync, erratic);
+    play_window(&mut sync, erratic);
+    assert_eq!(sync.prediction_frames(), MIN_ADAPTIVE_PREDICTION_FRAMES);
+
+    play_window(&mut sync, steady);
+    assert_eq!(sync.prediction_frames(), MIN_ADAPTIVE_PREDICTION_FRAMES * 2);
+    play_window(&mut sync, steady);
+    play_window(&mut sync, steady);
+    assert_eq!(sync.prediction_frames(), GGPO_MAX_PREDICTION_FRAMES);
+    assert_eq!(sync.adaptive_prediction().unwrap().mispredict_rate(), 0.0);
+}
+
+#[test]
+fn window_stays_put_without_adaptive_prediction() {
+    let status = connect_status(2);
+    let mut sync = sync_with(
+        Arc::new(Mutex::new(MockGame::default())),
+        &status,
+        INPUT_SIZE,
+    );
+
+    play_window(&mut sync, erratic);
+    play_window(&mut sync, erratic);
+    assert_eq!(sync.prediction_frames(), GGPO_MAX_PREDICTION_FRAMES);
+    assert!(sync.adaptive_prediction().is_none());
+    assert!(!SessionConfig::default().adaptive_prediction);
+}
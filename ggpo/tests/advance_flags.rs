@@ -0,0 +1,85 @@
+mod common;
+
+use bytes::Bytes;
+use common::{connect_status, sync_with};
+use ggpo::{
+    game_input::{
+        Frame, GameInput, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS, NULL_FRAME,
+    },
+    ggpo::{Event, GGPOError, GGPOSessionCallbacks, SavedState, ADVANCE_SPECULATIVE},
+};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+const INPUT_SIZE: usize = 1;
+
+// Keeps the flags of every `advance_frame` call.
+#[derive(Debug, Default, Clone)]
+struct Flagged {
+    flags: Vec<i32>,
+}
+
+impl GGPOSessionCallbacks for Flagged {
+    fn save_game_state(&mut self, _frame: Frame) -> Result<SavedState, GGPOError> {
+        Ok(SavedState {
+            data: Bytes::from_static(&[0]),
+            checksum: None,
+        })
+    }
+
+    fn load_game_state(&mut self, _buffer: &Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn log_game_state(&mut self, _filename: String, _buffer: Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn advance_frame(&mut self, flags: i32) -> bool {
+        self.flags.push(flags);
+        true
+    }
+
+    fn on_event(&mut self, _info: &Event) {}
+}
+
+#[test]
+fn frames_run_again_are_speculative() {
+    let game = Arc::new(Mutex::new(Flagged::default()));
+    let status = connect_status(2);
+    let mut sync = sync_with(game.clone(), &status, INPUT_SIZE);
+
+    // Run four frames predicting the remote player idles...
+    for _ in 0..4 {
+        let mut local = GameInput::init(NULL_FRAME, None, INPUT_SIZE);
+        assert!(sync.add_local_input(0, &mut local).unwrap());
+        let mut values: InputBuffer = Default::default();
+        sync.synchronize_inputs(&mut values).unwrap();
+        sync.increment_frame().unwrap();
+    }
+    sync.check_simulation().unwrap();
+    assert!(game.lock().flags.is_empty());
+
+    // ...then learn they pressed something on frame 1.
+    let mut bits = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    bits[0][0] = 7;
+    sync.add_remote_input(1, &GameInput::init(Frame::new(0), None, INPUT_SIZE))
+        .unwrap();
+    sync.add_remote_input(1, &GameInput::init(Frame::new(1), Some(&bits), INPUT_SIZE))
+        .unwrap();
+    sync.check_simulation().unwrap();
+
+    // Frames 1, 2 and 3 ran again.  The rest of the flags is their confidence.
+    let flags = game.lock().flags.clone();
+    assert_eq!(flags.len(), 3);
+    assert!(flags.iter().all(|flags| flags & ADVANCE_SPECULATIVE != 0));
+}
+
+#[test]
+fn a_frame_stepped_onto_for_the_first_time_is_not() {
+    let mut game = Flagged::default();
+    let values: InputBuffer = Default::default();
+    assert!(game.fast_forward_frame(&values, 0));
+    assert_eq!(game.flags.len(), 1);
+    assert_eq!(game.flags[0] & ADVANCE_SPECULATIVE, 0);
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use common::{advance, config, peer, poll, recording_peer, synchronize, Recorder};
+use ggpo::{config::SessionConfig, ggpo::Event, time_sync::AutoFrameDelay};
+use std::time::{Duration, Instant};
+
+fn delays(events: &Recorder) -> Vec<usize> {
+    events
+        .events
+        .lock()
+        .iter()
+        .filter_map(|e| match e {
+            Event::FrameDelayChanged(changed) => Some(changed.delay),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn the_delay_only_moves_once_clear_of_a_boundary() {
+    let mut auto = AutoFrameDelay::new(0);
+    // 100 ms there and back is 50 ms, or three frames, each way.
+    assert_eq!(auto.update(100), Some(3));
+    assert_eq!(auto.update(100), None);
+    // Just into four frames' worth isn't enough to move it...
+    assert_eq!(AutoFrameDelay::frames_for(120), 4);
+    assert_eq!(auto.update(120), None);
+    // ...but well past it is.
+    assert_eq!(auto.update(150), Some(5));
+    assert_eq!(AutoFrameDelay::frames_for(145), 4);
+    assert_eq!(auto.update(145), None);
+    assert_eq!(auto.update(100), Some(3));
+    assert_eq!(auto.delay(), 3);
+}
+
+#[test]
+fn the_delay_converges_on_a_slow_link_and_play_goes_on() {
+    let auto = SessionConfig {
+        auto_frame_delay: true,
+        ..config(17920)
+    };
+    let (a, a_events) = recording_peer(auto, 1, 17930);
+    let (b, b_events) = peer(17930, 2, 17920);
+    // About 50 ms each way, from the first round trip on.
+    a.lock().set_network_conditions(50, 0);
+    b.lock().set_network_conditions(50, 0);
+
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+
+    let mut frames = [0; 2];
+    let deadline = Instant::now() + Duration::from_secs(20);
+    let step = |frames: &mut [u32; 2]| {
+        assert!(Instant::now() < deadline, "stalled at {:?}", frames);
+        if advance(&a, 1) {
+            frames[0] += 1;
+        }
+        if advance(&b, 2) {
+            frames[1] += 1;
+        }
+        poll(&a);
+        poll(&b);
+    };
+    while delays(&a_events).last() != Some(&3) {
+        step(&mut frames);
+    }
+
+    // The padding the raised delay left has to reach B, or it stalls here.
+    let reached = frames;
+    while frames[0] < reached[0] + 60 || frames[1] < reached[1] + 60 {
+        step(&mut frames);
+    }
+    assert_eq!(delays(&a_events), vec![3]);
+    assert!(delays(&b_events).is_empty());
+}
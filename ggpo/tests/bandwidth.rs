@@ -0,0 +1,100 @@
+mod common;
+
+use bytes::Bytes;
+use common::localhost;
+use ggpo::network::{
+    clock::TestClock,
+    udp::{Udp, UdpCallback},
+    udp_msg::{ConnectStatus, MsgType, UdpMsg, UDP_MSG_MAX_PLAYERS},
+    udp_proto::{UdpProtocol, UDP_HEADER_SIZE},
+};
+use mio::Poll;
+use parking_lot::Mutex;
+use std::{
+    net::{SocketAddr, UdpSocket},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+struct Ignore;
+
+impl UdpCallback for Ignore {
+    fn on_msg(&mut self, _from: &SocketAddr, _msg: UdpMsg, _len: usize) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+// An endpoint sending to `peer`, on a clock that only moves when the test says.
+fn endpoint(port: u16, peer: SocketAddr, clock: &Arc<TestClock>) -> UdpProtocol<Ignore> {
+    let mut udp = Udp::new();
+    udp.init(
+        port,
+        Arc::new(Mutex::new(Poll::new().unwrap())),
+        Arc::new(Mutex::new(Ignore)),
+    )
+    .unwrap();
+    udp.start_send_task().unwrap();
+    let status: [Arc<Mutex<ConnectStatus>>; UDP_MSG_MAX_PLAYERS] = Default::default();
+    let mut endpoint = UdpProtocol::new();
+    endpoint.init(Arc::new(Mutex::new(udp)), 0, peer, &status);
+    endpoint.set_clock(clock.clone());
+    endpoint
+}
+
+#[test]
+fn kbps_sent_counts_the_bytes_on_the_wire() {
+    let peer = UdpSocket::bind(localhost(18090)).unwrap();
+    peer.set_nonblocking(true).unwrap();
+    let clock = Arc::new(TestClock::new());
+    let mut sender = endpoint(18080, localhost(18090), &clock);
+
+    for _ in 0..20 {
+        sender.send_msg(&mut UdpMsg::new(MsgType::Input)).unwrap();
+        sender
+            .send_msg(&mut UdpMsg::new(MsgType::KeepAlive))
+            .unwrap();
+        sender
+            .send_msg(&mut UdpMsg::new(MsgType::QualityReport))
+            .unwrap();
+    }
+
+    // Add up what arrived, input apart from the rest.
+    let (mut input, mut overhead, mut packets) = (0, 0, 0);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut buf = [0u8; 4096];
+    while packets < 60 {
+        assert!(
+            Instant::now() < deadline,
+            "only {} packets arrived",
+            packets
+        );
+        let len = match peer.recv(&mut buf) {
+            Ok(len) => len,
+            Err(_) => continue,
+        };
+        let packet = zstd::block::decompress(&buf[..len], 4096).unwrap();
+        let msg = UdpMsg::decode(Bytes::from(packet)).unwrap();
+        if msg.header.packet_type == MsgType::Input {
+            input += len + UDP_HEADER_SIZE;
+        } else {
+            overhead += len + UDP_HEADER_SIZE;
+        }
+        packets += 1;
+    }
+
+    // Everything went out at one instant, so half a second on it's all in the window.
+    clock.advance(Duration::from_millis(500));
+    sender.update_network_stats().unwrap();
+    let network = sender.get_network_stats().network;
+    assert_eq!(network.input_bytes_per_second, input);
+    assert_eq!(network.overhead_bytes_per_second, overhead);
+    assert_eq!(network.kbps_sent, (input + overhead) / 1024);
+
+    // A second after sending, none of it counts any more.
+    clock.advance(Duration::from_millis(500));
+    sender.update_network_stats().unwrap();
+    let network = sender.get_network_stats().network;
+    assert_eq!(network.input_bytes_per_second, 0);
+    assert_eq!(network.overhead_bytes_per_second, 0);
+    assert_eq!(network.kbps_sent, 0);
+}
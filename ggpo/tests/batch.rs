@@ -0,0 +1,120 @@
+mod common;
+
+use bytes::Bytes;
+use common::localhost;
+use ggpo::network::{
+    clock::TestClock,
+    udp::{Udp, UdpCallback},
+    udp_msg::{ConnectStatus, MsgEnum, MsgType, UdpMsg, UDP_MSG_MAX_PLAYERS},
+    udp_proto::UdpProtocol,
+};
+use mio::Poll;
+use parking_lot::Mutex;
+use std::{
+    net::{SocketAddr, UdpSocket},
+    sync::Arc,
+    time::Duration,
+};
+
+struct Ignore;
+
+impl UdpCallback for Ignore {
+    fn on_msg(&mut self, _from: &SocketAddr, _msg: UdpMsg, _len: usize) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+fn endpoint(port: u16, peer: SocketAddr) -> UdpProtocol<Ignore> {
+    let mut udp = Udp::new();
+    udp.init(
+        port,
+        Arc::new(Mutex::new(Poll::new().unwrap())),
+        Arc::new(Mutex::new(Ignore)),
+    )
+    .unwrap();
+    udp.start_send_task().unwrap();
+    let status: [Arc<Mutex<ConnectStatus>>; UDP_MSG_MAX_PLAYERS] = Default::default();
+    let mut endpoint = UdpProtocol::new();
+    endpoint.init(Arc::new(Mutex::new(udp)), 0, peer, &status);
+    endpoint.set_clock(Arc::new(TestClock::new()));
+    endpoint
+}
+
+// Every datagram that turns up in the next little while.
+fn received(peer: &UdpSocket) -> Vec<UdpMsg> {
+    let mut msgs = Vec::new();
+    let mut buf = [0u8; 4096];
+    while let Ok(len) = peer.recv(&mut buf) {
+        let packet = zstd::block::decompress(&buf[..len], 4096).unwrap();
+        msgs.push(UdpMsg::decode(Bytes::from(packet)).unwrap());
+    }
+    msgs
+}
+
+fn receiver(port: u16) -> UdpSocket {
+    let peer = UdpSocket::bind(localhost(port)).unwrap();
+    peer.set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    peer
+}
+
+#[test]
+fn one_tick_for_one_peer_is_one_datagram() {
+    let peer = receiver(18190);
+    let mut sender = endpoint(18180, localhost(18190));
+
+    sender.begin_batch();
+    sender.send_pending_output().unwrap();
+    sender.send_input_ack().unwrap();
+    sender
+        .send_msg(&mut UdpMsg::new(MsgType::QualityReport))
+        .unwrap();
+    // Not one of the three, so it doesn't wait.
+    sender
+        .send_msg(&mut UdpMsg::new(MsgType::KeepAlive))
+        .unwrap();
+    sender.flush_batch().unwrap();
+
+    let msgs = received(&peer);
+    assert_eq!(msgs.len(), 2);
+    assert_eq!(msgs[0].header.packet_type, MsgType::KeepAlive);
+    let batch = match &msgs[1].message {
+        MsgEnum::Batch(batch) => batch,
+        _ => panic!("{:?} instead of a batch", msgs[1].header.packet_type),
+    };
+    let types: Vec<MsgType> = batch
+        .messages
+        .iter()
+        .map(|msg| msg.header.packet_type)
+        .collect();
+    assert_eq!(
+        types,
+        vec![MsgType::Input, MsgType::InputAck, MsgType::QualityReport]
+    );
+    assert!(matches!(batch.messages[0].message, MsgEnum::Input(_)));
+    assert!(matches!(batch.messages[1].message, MsgEnum::InputAck(_)));
+    assert!(matches!(
+        batch.messages[2].message,
+        MsgEnum::QualityReport(_)
+    ));
+}
+
+#[test]
+fn a_batch_of_one_goes_out_as_it_is() {
+    let peer = receiver(18191);
+    let mut sender = endpoint(18181, localhost(18191));
+
+    sender.begin_batch();
+    sender.send_input_ack().unwrap();
+    sender.send_input_ack().unwrap();
+    sender.flush_batch().unwrap();
+    // Nothing open, nothing held.
+    sender.flush_batch().unwrap();
+    sender.send_input_ack().unwrap();
+
+    let msgs = received(&peer);
+    assert_eq!(msgs.len(), 2);
+    for msg in msgs.iter() {
+        assert!(matches!(msg.message, MsgEnum::InputAck(_)));
+    }
+}
@@ -0,0 +1,207 @@
+mod common;
+
+use bytes::Bytes;
+use common::{add_spectator, localhost, peer, poll, synchronize_by, Peer, Recorder};
+use ggpo::{
+    backends::{p2p::Peer2PeerBackend, spectator::SpectatorBackend},
+    config::SessionConfig,
+    game_input::{Frame, InputBuffer},
+    ggpo::{Event, GGPOError, GGPOSessionCallbacks, SavedState, Session},
+    player::{Player, PlayerHandle, PlayerType},
+};
+use parking_lot::Mutex;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+const A_PORT: u16 = 18510;
+const B_PORT: u16 = 18520;
+const HOST_PORT: u16 = 18530;
+const SPECTATOR_PORTS: [u16; 2] = [18540, 18550];
+
+const FRAMES: usize = 60;
+
+// Remembers both players' input for every frame it runs.
+#[derive(Debug, Default, Clone)]
+struct Viewer {
+    events: Recorder,
+    played: Arc<Mutex<Vec<(u8, u8)>>>,
+}
+
+impl GGPOSessionCallbacks for Viewer {
+    fn save_game_state(&mut self, _frame: Frame) -> Result<SavedState, GGPOError> {
+        let frames = self.played.lock().len() as u32;
+        Ok(SavedState {
+            data: Bytes::copy_from_slice(&frames.to_le_bytes()),
+            checksum: Some(frames),
+        })
+    }
+
+    fn load_game_state(&mut self, _buffer: &Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn log_game_state(&mut self, _filename: String, _buffer: Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn advance_frame(&mut self, _flags: i32) -> bool {
+        true
+    }
+
+    fn fast_forward_frame(&mut self, inputs: &InputBuffer, _disconnect_flags: i32) -> bool {
+        self.played.lock().push((inputs[0][0], inputs[1][0]));
+        true
+    }
+
+    fn on_event(&mut self, info: &Event) {
+        self.events.on_event(info);
+    }
+}
+
+// A player who also feeds the broadcast host, as one of its spectators.
+fn player(port: u16, local: usize, remote_port: u16) -> (Peer, Recorder) {
+    let (session, recorder) = peer(port, local, remote_port);
+    add_spectator(&mut session.lock(), HOST_PORT, 3);
+    (session, recorder)
+}
+
+// The input `player` gives on `frame`, different for each of them.
+fn input_for(player: usize, frame: usize) -> u8 {
+    (frame + player * 100) as u8
+}
+
+// Runs one frame if the session will take another local input.
+fn advance(session: &Peer, handle: PlayerHandle, sent: &mut usize) {
+    if *sent == FRAMES {
+        return;
+    }
+    let mut session = session.lock();
+    let mut values: InputBuffer = Default::default();
+    values[handle as usize - 1][0] = input_for(handle as usize, *sent);
+    match session.add_local_input(handle, &values, 1) {
+        Ok(()) => {}
+        Err(GGPOError::PredictionThreshold) => return,
+        Err(e) => panic!("add_local_input failed: {}", e),
+    }
+    *sent += 1;
+    session.synchronize_input(&mut values, None).unwrap();
+    session.increment_frame().unwrap();
+}
+
+#[test]
+fn spectators_of_a_broadcast_host_see_the_players_match() {
+    let (a, a_events) = player(A_PORT, 1, B_PORT);
+    let (b, b_events) = player(B_PORT, 2, A_PORT);
+
+    let host_game = Viewer::default();
+    let config = SessionConfig {
+        local_port: HOST_PORT,
+        input_size: 1,
+        broadcast: true,
+        ..Default::default()
+    };
+    let host = Peer2PeerBackend::from_config(config, Arc::new(Mutex::new(host_game.clone())))
+        .expect("host");
+    {
+        let mut host = host.lock();
+        let mut handle: PlayerHandle = 0;
+        host.add_player(
+            Player::new(PlayerType::Remote(localhost(A_PORT)), 1),
+            &mut handle,
+        )
+        .unwrap();
+        host.add_player(
+            Player::new(PlayerType::Remote(localhost(B_PORT)), 2),
+            &mut handle,
+        )
+        .unwrap();
+        for (i, port) in SPECTATOR_PORTS.iter().enumerate() {
+            add_spectator(&mut host, *port, 3 + i);
+        }
+        // It has nobody to take local input for.
+        assert!(matches!(
+            host.add_player(Player::new(PlayerType::Local, 1), &mut handle),
+            Err(GGPOError::InvalidRequest)
+        ));
+    }
+
+    let viewers = [Viewer::default(), Viewer::default()];
+    let spectators: Vec<_> = SPECTATOR_PORTS
+        .iter()
+        .zip(viewers.iter())
+        .map(|(port, viewer)| {
+            SpectatorBackend::new(
+                Arc::new(Mutex::new(viewer.clone())),
+                *port,
+                2,
+                1,
+                localhost(HOST_PORT),
+            )
+            .unwrap()
+        })
+        .collect();
+
+    let poll_all = || {
+        poll(&a);
+        poll(&b);
+        poll(&host);
+        for spectator in spectators.iter() {
+            spectator
+                .lock()
+                .do_poll(Some(Duration::from_millis(1)))
+                .unwrap();
+        }
+    };
+
+    let mut events = vec![&a_events, &b_events, &host_game.events];
+    events.extend(viewers.iter().map(|viewer| &viewer.events));
+    synchronize_by(&events, &poll_all);
+
+    // The host runs its frames itself.
+    {
+        let mut host = host.lock();
+        let mut values: InputBuffer = Default::default();
+        assert!(matches!(
+            host.synchronize_input(&mut values, None),
+            Err(GGPOError::InvalidRequest)
+        ));
+        assert!(matches!(
+            host.increment_frame(),
+            Err(GGPOError::InvalidRequest)
+        ));
+    }
+
+    let expected: Vec<(u8, u8)> = (0..FRAMES)
+        .map(|frame| (input_for(1, frame), input_for(2, frame)))
+        .collect();
+    let (mut a_sent, mut b_sent) = (0, 0);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while viewers
+        .iter()
+        .any(|viewer| viewer.played.lock().len() < FRAMES)
+    {
+        assert!(Instant::now() < deadline, "spectators never saw the match");
+        advance(&a, 1, &mut a_sent);
+        advance(&b, 2, &mut b_sent);
+        poll_all();
+        for (spectator, viewer) in spectators.iter().zip(viewers.iter()) {
+            let mut spectator = spectator.lock();
+            let mut values: InputBuffer = Default::default();
+            match spectator.synchronize_input(&mut values, None) {
+                Ok(()) => {
+                    viewer.played.lock().push((values[0][0], values[1][0]));
+                    spectator.increment_frame().unwrap();
+                }
+                Err(GGPOError::PredictionThreshold) => {}
+                Err(e) => panic!("synchronize_input failed: {}", e),
+            }
+        }
+    }
+
+    assert_eq!(host_game.played.lock()[..FRAMES], expected[..]);
+    for viewer in viewers.iter() {
+        assert_eq!(viewer.played.lock()[..FRAMES], expected[..]);
+    }
+}
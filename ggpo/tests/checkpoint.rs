@@ -0,0 +1,206 @@
+mod common;
+
+use bytes::Bytes;
+use common::connect_status;
+use ggpo::{
+    config::SessionConfig,
+    core::{
+        game_input::{
+            Frame, FrameNum, GameInput, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS,
+            NULL_FRAME,
+        },
+        sync::{Config, GGPOSync, RollbackCallbacks, SavedState},
+        GGPO_MAX_PREDICTION_FRAMES,
+    },
+    ggpo::GGPOError,
+};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+const INPUT_SIZE: usize = 1;
+const FRAMES: u32 = 40;
+// How many frames late the remote player's input turns up.
+const LAG: u32 = 3;
+
+// The remote player's input on `frame`; never blank, so predicting it from
+// nothing is always wrong.
+fn remote_input(frame: u32) -> u8 {
+    (frame / 5 % 3) as u8 + 1
+}
+
+/*
+ * Folds every frame's remote input into `state`.  Rolling the game back runs
+ * frames again without the session, so it takes input from the remote input
+ * delivered so far, predicting the way the session does: the last input, or
+ * nothing before the first.
+ */
+#[derive(Debug, Default, Clone)]
+struct Fold {
+    frame: u32,
+    state: u64,
+    delivered: Vec<u8>,
+    loads: Vec<usize>,
+    resets: Vec<Frame>,
+}
+
+impl Fold {
+    fn step(&mut self) {
+        let input = self
+            .delivered
+            .get(self.frame as usize)
+            .or_else(|| self.delivered.last())
+            .copied()
+            .unwrap_or(0);
+        self.state = self
+            .state
+            .wrapping_mul(31)
+            .wrapping_add(input as u64 * 1000 + self.frame as u64);
+        self.frame += 1;
+    }
+
+    fn restore(&mut self, buffer: &Bytes) {
+        let mut frame = [0; 4];
+        frame.copy_from_slice(&buffer[..4]);
+        let mut state = [0; 8];
+        state.copy_from_slice(&buffer[4..12]);
+        self.frame = u32::from_le_bytes(frame);
+        self.state = u64::from_le_bytes(state);
+    }
+}
+
+impl RollbackCallbacks for Fold {
+    type Error = String;
+
+    fn save_state(&mut self, _frame: Frame) -> Result<SavedState, String> {
+        let mut data = self.frame.to_le_bytes().to_vec();
+        data.extend_from_slice(&self.state.to_le_bytes());
+        Ok(SavedState {
+            data: Bytes::from(data),
+            checksum: None,
+        })
+    }
+
+    fn load_state(&mut self, buffer: &Bytes, length: usize) -> bool {
+        self.loads.push(length);
+        self.restore(buffer);
+        true
+    }
+
+    fn reset_state(&mut self, frame: Frame, buffer: &Bytes, _length: usize) -> bool {
+        self.resets.push(frame);
+        self.restore(buffer);
+        true
+    }
+
+    fn resimulate_frame(&mut self) -> bool {
+        self.step();
+        true
+    }
+}
+
+// Plays `FRAMES` frames against a remote player who's always `LAG` frames late.
+fn play(checkpoint_interval: FrameNum) -> (Fold, GGPOSync<Fold>) {
+    let game = Arc::new(Mutex::new(Fold::default()));
+    let status = connect_status(2);
+    let mut sync = GGPOSync::new(&status);
+    let mut config = Config::new();
+    config.init(game.clone(), GGPO_MAX_PREDICTION_FRAMES, 2, INPUT_SIZE);
+    config.checkpoint_interval = checkpoint_interval;
+    sync.init(config).unwrap();
+
+    let deliver = |sync: &mut GGPOSync<Fold>, until: u32| {
+        let from = game.lock().delivered.len() as u32;
+        for frame in from..until {
+            let mut bits = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+            bits[0][0] = remote_input(frame);
+            sync.add_remote_input(
+                1,
+                &GameInput::init(Frame::new(frame), Some(&bits), INPUT_SIZE),
+            )
+            .unwrap();
+            game.lock().delivered.push(remote_input(frame));
+        }
+        sync.check_simulation().unwrap();
+    };
+
+    for frame in 0..FRAMES {
+        let mut local = GameInput::init(NULL_FRAME, None, INPUT_SIZE);
+        assert!(sync.add_local_input(0, &mut local).unwrap());
+        let mut values: InputBuffer = Default::default();
+        sync.synchronize_inputs(&mut values).unwrap();
+        game.lock().step();
+        sync.increment_frame().unwrap();
+
+        if let Some(until) = (frame + 1).checked_sub(LAG) {
+            deliver(&mut sync, until);
+            if let Some(confirmed) = until.checked_sub(1) {
+                sync.set_last_confirmed_frame(Frame::new(confirmed))
+                    .unwrap();
+            }
+        }
+    }
+    deliver(&mut sync, FRAMES);
+
+    let game = game.lock().clone();
+    (game, sync)
+}
+
+// The same frames, with every input known up front.
+fn expected() -> u64 {
+    let mut game = Fold {
+        delivered: (0..FRAMES).map(remote_input).collect(),
+        ..Default::default()
+    };
+    for _ in 0..FRAMES {
+        game.step();
+    }
+    game.state
+}
+
+#[test]
+fn replaying_from_checkpoints_ends_where_saving_every_frame_does() {
+    let (saved, saved_sync) = play(1);
+    let (replayed, replayed_sync) = play(4);
+
+    assert_eq!(saved.frame, FRAMES);
+    assert_eq!(replayed.frame, FRAMES);
+    assert_eq!(saved.state, expected());
+    assert_eq!(replayed.state, saved.state);
+
+    // Saving every frame loads states; checkpoints reset to them instead.
+    assert!(!saved.loads.is_empty());
+    assert!(saved.resets.is_empty());
+    assert!(replayed.loads.is_empty());
+    assert!(!replayed.resets.is_empty());
+    for frame in replayed.resets.iter() {
+        assert_eq!(frame.number().unwrap() % 4, 0, "reset to {}", frame);
+    }
+
+    // Fewer states kept, more frames run again.
+    assert!(replayed_sync.memory_usage().saved_states < saved_sync.memory_usage().saved_states);
+    assert!(
+        replayed_sync.rollback_stats().frames_resimulated
+            > saved_sync.rollback_stats().frames_resimulated
+    );
+    assert!(replayed_sync
+        .saved_checksum(Frame::new(FRAMES - 1))
+        .is_none());
+    assert!(replayed_sync.saved_checksum(Frame::new(FRAMES)).is_some());
+}
+
+#[test]
+fn the_checkpoint_interval_has_to_be_a_frame_or_more() {
+    let config = SessionConfig {
+        checkpoint_interval: 0,
+        ..Default::default()
+    };
+    assert!(matches!(config.validate(), Err(GGPOError::InvalidRequest)));
+
+    // Every other frame, half the states reach back as far.
+    let config = SessionConfig {
+        checkpoint_interval: 2,
+        saved_state_depth: Some(GGPO_MAX_PREDICTION_FRAMES as usize / 2 + 1),
+        ..Default::default()
+    };
+    config.validate().unwrap();
+}
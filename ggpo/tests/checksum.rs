@@ -0,0 +1,93 @@
+mod common;
+
+use bytes::Bytes;
+use common::connect_status;
+use ggpo::{
+    checksum::fletcher32,
+    game_input::{Frame, GameInput, NULL_FRAME},
+    ggpo::{Event, GGPOError, GGPOSessionCallbacks, SavedState, GGPO_MAX_PREDICTION_FRAMES},
+    sync::{Config, GGPOSync},
+};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+#[test]
+fn fletcher32_matches_the_reference_values() {
+    assert_eq!(fletcher32(b"abcde"), 0xf04f_c729);
+    assert_eq!(fletcher32(b"abcdef"), 0x5650_2d2a);
+    assert_eq!(fletcher32(b"abcdefgh"), 0xebe1_9591);
+}
+
+#[test]
+fn equal_buffers_agree_and_one_bit_differs() {
+    let state: Vec<u8> = (0..4096u32).map(|i| (i * 7 % 251) as u8).collect();
+    let copy = state.clone();
+    assert_eq!(fletcher32(&state), fletcher32(&copy));
+
+    for &(byte, bit) in [(0, 0), (1000, 3), (4095, 7)].iter() {
+        let mut flipped = state.clone();
+        flipped[byte] ^= 1 << bit;
+        assert_ne!(
+            fletcher32(&state),
+            fletcher32(&flipped),
+            "byte {} bit {}",
+            byte,
+            bit
+        );
+    }
+}
+
+// Saves a fixed buffer, with or without a checksum of its own.
+#[derive(Debug, Clone)]
+struct Fixed {
+    checksum: Option<u32>,
+}
+
+const STATE: &[u8] = b"the whole game";
+
+impl GGPOSessionCallbacks for Fixed {
+    fn save_game_state(&mut self, _frame: Frame) -> Result<SavedState, GGPOError> {
+        Ok(SavedState {
+            data: Bytes::from_static(STATE),
+            checksum: self.checksum,
+        })
+    }
+
+    fn load_game_state(&mut self, _buffer: &Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn log_game_state(&mut self, _filename: String, _buffer: Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn advance_frame(&mut self, _flags: i32) -> bool {
+        true
+    }
+
+    fn on_event(&mut self, _info: &Event) {}
+}
+
+fn saved_checksum(checksum: Option<u32>) -> Option<u32> {
+    let status = connect_status(1);
+    let mut sync = GGPOSync::new(&status);
+    let mut config = Config::new();
+    config.init(
+        Arc::new(Mutex::new(Fixed { checksum })),
+        GGPO_MAX_PREDICTION_FRAMES,
+        1,
+        1,
+    );
+    sync.init(config).unwrap();
+
+    // Adding the first input saves frame 0.
+    let mut input = GameInput::init(NULL_FRAME, None, 1);
+    sync.add_local_input(0, &mut input).unwrap();
+    sync.saved_checksum(Frame::new(0))
+}
+
+#[test]
+fn the_session_checksums_states_saved_without_one() {
+    assert_eq!(saved_checksum(None), Some(fletcher32(STATE)));
+    assert_eq!(saved_checksum(Some(42)), Some(42));
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use bytes::Bytes;
+use common::connect_status;
+use ggpo::{
+    config::SessionConfig,
+    desync::DesyncDetector,
+    game_input::{Frame, GameInput, InputBuffer, NULL_FRAME},
+    ggpo::{Event, GGPOError, GGPOSessionCallbacks, SavedState, GGPO_MAX_PREDICTION_FRAMES},
+    sync::{ChecksumCadence, Config, GGPOSync},
+};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+const FRAMES: u32 = 20;
+
+// A frame counter whose state goes wrong from `drift_from` on.
+#[derive(Debug, Default, Clone)]
+struct Drifting {
+    frame: u32,
+    drift_from: Option<u32>,
+}
+
+impl GGPOSessionCallbacks for Drifting {
+    fn save_game_state(&mut self, _frame: Frame) -> Result<SavedState, GGPOError> {
+        let drift = match self.drift_from {
+            Some(from) if self.frame >= from => 100,
+            _ => 0,
+        };
+        Ok(SavedState {
+            data: Bytes::copy_from_slice(&(self.frame + drift).to_le_bytes()),
+            checksum: None,
+        })
+    }
+
+    fn load_game_state(&mut self, _buffer: &Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn log_game_state(&mut self, _filename: String, _buffer: Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn advance_frame(&mut self, _flags: i32) -> bool {
+        true
+    }
+
+    fn on_event(&mut self, _info: &Event) {}
+}
+
+// Runs `FRAMES` frames of a lone local player, collecting each frame's checksum.
+fn checksums(cadence: ChecksumCadence, drift_from: Option<u32>) -> Vec<Option<u32>> {
+    let game = Arc::new(Mutex::new(Drifting {
+        frame: 0,
+        drift_from,
+    }));
+    let status = connect_status(1);
+    let mut sync = GGPOSync::new(&status);
+    let mut config = Config::new();
+    config.init(game.clone(), GGPO_MAX_PREDICTION_FRAMES, 1, 1);
+    config.checksum_cadence = cadence;
+    sync.init(config).unwrap();
+
+    let mut checksums = Vec::new();
+    for frame in 0..FRAMES {
+        let mut input = GameInput::init(NULL_FRAME, None, 1);
+        sync.add_local_input(0, &mut input).unwrap();
+        let mut values: InputBuffer = Default::default();
+        sync.synchronize_inputs(&mut values).unwrap();
+        game.lock().frame += 1;
+        sync.increment_frame().unwrap();
+        sync.set_last_confirmed_frame(Frame::new(frame)).unwrap();
+        checksums.push(sync.saved_checksum(Frame::new(frame)));
+    }
+    checksums
+}
+
+// The first frame a peer whose state drifts from frame 7 on is caught at.
+fn caught_at(cadence: ChecksumCadence) -> Option<Frame> {
+    let local = checksums(cadence, None);
+    let remote = checksums(cadence, Some(7));
+    let mut detector = DesyncDetector::new(2, 1);
+    let inputs: InputBuffer = Default::default();
+    for frame in 0..FRAMES {
+        let frame = Frame::new(frame);
+        let index = frame.number().unwrap() as usize;
+        // Peers only send checksums for frames that have one.
+        if let Some(checksum) = remote[index] {
+            if let Some(report) = detector.record_remote(1, frame, checksum) {
+                return Some(report.frame);
+            }
+        }
+        if let Some(checksum) = local[index] {
+            if let Some(report) = detector.record_local(frame, &inputs, checksum).pop() {
+                return Some(report.frame);
+            }
+        }
+    }
+    None
+}
+
+#[test]
+fn only_frames_on_the_cadence_get_a_checksum() {
+    for (frame, checksum) in checksums(ChecksumCadence::Every(5), None)
+        .into_iter()
+        .enumerate()
+    {
+        assert_eq!(checksum.is_some(), frame % 5 == 0, "frame {}", frame);
+    }
+    assert!(checksums(ChecksumCadence::EveryFrame, None)
+        .iter()
+        .all(Option::is_some));
+    // Worked out once the frame is confirmed, from the state saved for it.
+    assert_eq!(
+        checksums(ChecksumCadence::Confirmed, None),
+        checksums(ChecksumCadence::EveryFrame, None)
+    );
+}
+
+#[test]
+fn sparser_checksums_still_catch_a_desync_later() {
+    assert_eq!(caught_at(ChecksumCadence::EveryFrame), Some(Frame::new(7)));
+    assert_eq!(caught_at(ChecksumCadence::Confirmed), Some(Frame::new(7)));
+    assert_eq!(caught_at(ChecksumCadence::Every(5)), Some(Frame::new(10)));
+}
+
+#[test]
+fn sessions_checksum_every_thirtieth_frame_unless_told_otherwise() {
+    let config = SessionConfig::default();
+    assert_eq!(config.checksum_cadence, ChecksumCadence::Every(30));
+    let config = SessionConfig {
+        checksum_cadence: ChecksumCadence::Every(0),
+        ..Default::default()
+    };
+    assert!(matches!(config.validate(), Err(GGPOError::InvalidRequest)));
+}
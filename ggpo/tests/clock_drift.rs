@@ -0,0 +1,113 @@
+mod common;
+
+use common::localhost;
+use ggpo::network::{
+    clock::{Clock, TestClock},
+    udp::{Udp, UdpCallback},
+    udp_msg::{ConnectStatus, MsgEnum, MsgType, UdpMsg, UDP_MSG_MAX_PLAYERS},
+    udp_proto::{
+        ClockDriftMeter, Event, UdpProtocol, CLOCK_DRIFT_MIN_SPAN, QUALITY_REPORT_INTERVAL,
+    },
+};
+use mio::Poll;
+use parking_lot::Mutex;
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+struct Ignore;
+
+impl UdpCallback for Ignore {
+    fn on_msg(&mut self, _from: &SocketAddr, _msg: UdpMsg, _len: usize) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+fn endpoint(port: u16, peer: SocketAddr, clock: &Arc<TestClock>) -> UdpProtocol<Ignore> {
+    let mut udp = Udp::new();
+    udp.init(
+        port,
+        Arc::new(Mutex::new(Poll::new().unwrap())),
+        Arc::new(Mutex::new(Ignore)),
+    )
+    .unwrap();
+    let status: [Arc<Mutex<ConnectStatus>>; UDP_MSG_MAX_PLAYERS] = Default::default();
+    let mut endpoint = UdpProtocol::new();
+    endpoint.init(Arc::new(Mutex::new(udp)), 0, peer, &status);
+    endpoint.set_clock(clock.clone());
+    endpoint
+}
+
+fn millis(clock: &TestClock) -> u128 {
+    clock.now().duration_since(UNIX_EPOCH).unwrap().as_millis()
+}
+
+fn quality_report(sent: u128) -> UdpMsg {
+    let mut msg = UdpMsg::new(MsgType::QualityReport);
+    if let MsgEnum::QualityReport(report) = &mut msg.message {
+        report.ping = sent;
+    }
+    msg
+}
+
+fn drift_events(endpoint: &mut UdpProtocol<Ignore>) -> Vec<i32> {
+    let mut drifts = Vec::new();
+    let mut event = Event::Unknown;
+    while endpoint.get_event(&mut event) {
+        if let Event::ClockDrift(drift) = event {
+            drifts.push(drift);
+        }
+    }
+    drifts
+}
+
+#[test]
+fn a_peer_running_one_percent_fast_is_reported() {
+    let ours = Arc::new(TestClock::new());
+    let theirs = Arc::new(TestClock::new());
+    let mut endpoint = endpoint(18280, localhost(18290), &ours);
+    let interval = Duration::from_millis(QUALITY_REPORT_INTERVAL as u64);
+
+    let mut reported = Vec::new();
+    for _ in 0..40 {
+        endpoint
+            .on_quality_report(&quality_report(millis(&theirs)))
+            .unwrap();
+        reported.extend(drift_events(&mut endpoint));
+        ours.advance(interval);
+        theirs.advance(interval * 101 / 100);
+    }
+
+    let drift = endpoint
+        .get_network_stats()
+        .timesync
+        .clock_drift_ppm
+        .unwrap();
+    assert!((9_900..=10_100).contains(&drift), "{} ppm", drift);
+    // Once, when it first had enough to go on.
+    assert_eq!(reported.len(), 1);
+    assert!((9_900..=10_100).contains(&reported[0]));
+}
+
+#[test]
+fn nothing_is_estimated_from_a_short_stretch() {
+    let mut meter = ClockDriftMeter::default();
+    meter.record(1_000, 50_000);
+    meter.record(1_000 + CLOCK_DRIFT_MIN_SPAN - 1, 60_000);
+    assert_eq!(meter.drift_ppm(), None);
+}
+
+#[test]
+fn a_slow_peer_drifts_negative_despite_jitter() {
+    let mut meter = ClockDriftMeter::default();
+    // The peer's clock loses 2 ms a second; each report spends 10-40 ms in flight.
+    for second in 0..30u128 {
+        let sent = 1_000_000 + second * 998;
+        let delay = [10, 40, 25, 15][second as usize % 4];
+        meter.record(second * 1000 + delay, sent);
+    }
+    let drift = meter.drift_ppm().unwrap();
+    assert!((-3_500..=-500).contains(&drift), "{} ppm", drift);
+}
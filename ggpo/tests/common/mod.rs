@@ -0,0 +1,384 @@
+#![allow(dead_code)]
+
+use bytes::Bytes;
+use ggpo::{
+    backends::p2p::Peer2PeerBackend,
+    config::SessionConfig,
+    game_input::{Frame, InputBuffer, NULL_FRAME},
+    ggpo::{Event, GGPOError, GGPOSessionCallbacks, SavedState, Session},
+    network::{clock::TestClock, udp_msg::ConnectStatus},
+    player::{Player, PlayerHandle, PlayerType},
+    sync::{Config, GGPOSync},
+};
+use parking_lot::Mutex;
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+// A do-nothing game for exercising the rollback core.
+#[derive(Debug, Default, Clone)]
+pub struct MockGame {
+    pub frames_advanced: u32,
+}
+
+impl GGPOSessionCallbacks for MockGame {
+    // The "state" is just the frame counter, which is enough to tell saves apart.
+    fn save_game_state(&mut self, _frame: Frame) -> Result<SavedState, GGPOError> {
+        Ok(SavedState {
+            data: Bytes::copy_from_slice(&self.frames_advanced.to_le_bytes()),
+            checksum: Some(self.frames_advanced),
+        })
+    }
+
+    fn load_game_state(&mut self, _buffer: &Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn log_game_state(&mut self, _filename: String, _buffer: Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn advance_frame(&mut self, _flags: i32) -> bool {
+        self.frames_advanced += 1;
+        true
+    }
+
+    fn on_event(&mut self, _info: &Event) {}
+}
+
+pub fn connect_status(num_players: usize) -> Vec<Arc<Mutex<ConnectStatus>>> {
+    (0..num_players)
+        .map(|_| {
+            Arc::new(Mutex::new(ConnectStatus {
+                disconnected: false,
+                last_frame: NULL_FRAME,
+            }))
+        })
+        .collect()
+}
+
+pub fn sync_with<T: GGPOSessionCallbacks>(
+    game: Arc<Mutex<T>>,
+    status: &[Arc<Mutex<ConnectStatus>>],
+    input_size: usize,
+) -> GGPOSync<T> {
+    let mut sync = GGPOSync::new(status);
+    let mut config = Config::new();
+    config.init(
+        game,
+        ggpo::ggpo::GGPO_MAX_PREDICTION_FRAMES,
+        status.len(),
+        input_size,
+    );
+    sync.init(config).expect("sync init");
+    sync
+}
+
+// Records every event the session reports.
+#[derive(Debug, Default, Clone)]
+pub struct Recorder {
+    pub events: Arc<Mutex<Vec<Event>>>,
+}
+
+impl GGPOSessionCallbacks for Recorder {
+    fn save_game_state(&mut self, _frame: Frame) -> Result<SavedState, GGPOError> {
+        Ok(SavedState {
+            data: Bytes::from_static(&[0]),
+            checksum: Some(0),
+        })
+    }
+
+    fn load_game_state(&mut self, _buffer: &Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn log_game_state(&mut self, _filename: String, _buffer: Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn advance_frame(&mut self, _flags: i32) -> bool {
+        true
+    }
+
+    fn on_event(&mut self, info: &Event) {
+        self.events.lock().push(info.clone());
+    }
+}
+
+impl Recorder {
+    pub fn saw(&self, matches: impl Fn(&Event) -> bool) -> bool {
+        self.events.lock().iter().any(matches)
+    }
+}
+
+pub type Peer<T = Recorder> = Arc<Mutex<Peer2PeerBackend<T>>>;
+
+pub fn localhost(port: u16) -> SocketAddr {
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), port)
+}
+
+// A session on `port` with a byte of input, and every other setting at its default.
+pub fn config(port: u16) -> SessionConfig {
+    SessionConfig {
+        local_port: port,
+        input_size: 1,
+        ..Default::default()
+    }
+}
+
+// A session set up from `config`, with nobody added yet.
+pub fn session<T: GGPOSessionCallbacks + Send + Sync>(
+    config: SessionConfig,
+    callbacks: Arc<Mutex<T>>,
+) -> Peer<T> {
+    Peer2PeerBackend::from_config(config, callbacks).expect("session")
+}
+
+/*
+ * Players 1 to `remotes.len() + 1`: `local` plays here, and the rest, in
+ * order, at `remotes`.  Adding a remote player sends it the first sync
+ * request, so its socket should be up by then.
+ */
+pub fn add_players_at<T: GGPOSessionCallbacks + Send + Sync>(
+    session: &mut Peer2PeerBackend<T>,
+    local: usize,
+    remotes: &[SocketAddr],
+) {
+    let mut remotes = remotes.iter();
+    let mut handle: PlayerHandle = 0;
+    for player_num in 1..=remotes.len() + 1 {
+        let player_type = if player_num == local {
+            PlayerType::Local
+        } else {
+            PlayerType::Remote(*remotes.next().unwrap())
+        };
+        session
+            .add_player(Player::new(player_type, player_num), &mut handle)
+            .unwrap();
+    }
+}
+
+// Players 1 and 2: `local` plays here, the other on whoever's at `remote_port`.
+pub fn add_players<T: GGPOSessionCallbacks + Send + Sync>(
+    session: &mut Peer2PeerBackend<T>,
+    local: usize,
+    remote_port: u16,
+) {
+    add_players_at(session, local, &[localhost(remote_port)]);
+}
+
+// A session set up from `config`; see `add_players_at`.
+pub fn peer_at<T: GGPOSessionCallbacks + Send + Sync>(
+    config: SessionConfig,
+    callbacks: Arc<Mutex<T>>,
+    local: usize,
+    remotes: &[SocketAddr],
+) -> Peer<T> {
+    let session = session(config, callbacks);
+    add_players_at(&mut session.lock(), local, remotes);
+    session
+}
+
+// A two player session set up from `config`; see `add_players`.
+pub fn peer_with<T: GGPOSessionCallbacks + Send + Sync>(
+    config: SessionConfig,
+    callbacks: Arc<Mutex<T>>,
+    local: usize,
+    remote_port: u16,
+) -> Peer<T> {
+    peer_at(config, callbacks, local, &[localhost(remote_port)])
+}
+
+// A session set up from `config`, recording its events; see `add_players_at`.
+pub fn recording_peer_at(
+    config: SessionConfig,
+    local: usize,
+    remotes: &[SocketAddr],
+) -> (Peer, Recorder) {
+    let recorder = Recorder::default();
+    let session = peer_at(
+        config,
+        Arc::new(Mutex::new(recorder.clone())),
+        local,
+        remotes,
+    );
+    (session, recorder)
+}
+
+// A two player session set up from `config`, recording its events.
+pub fn recording_peer(config: SessionConfig, local: usize, remote_port: u16) -> (Peer, Recorder) {
+    recording_peer_at(config, local, &[localhost(remote_port)])
+}
+
+// A two player session on `port` with a byte of input each, recording its events.
+pub fn peer(port: u16, local: usize, remote_port: u16) -> (Peer, Recorder) {
+    recording_peer(config(port), local, remote_port)
+}
+
+// Adds the spectator at `port`, returning its handle.
+pub fn add_spectator<T: GGPOSessionCallbacks + Send + Sync>(
+    session: &mut Peer2PeerBackend<T>,
+    port: u16,
+    player_num: usize,
+) -> PlayerHandle {
+    let mut handle: PlayerHandle = 0;
+    session
+        .add_player(
+            Player::new(PlayerType::Spectator(localhost(port)), player_num),
+            &mut handle,
+        )
+        .unwrap();
+    handle
+}
+
+// A one player host set up from `config`, with spectators at `spectator_ports`.
+pub fn host_with<T: GGPOSessionCallbacks + Send + Sync>(
+    config: SessionConfig,
+    callbacks: Arc<Mutex<T>>,
+    spectator_ports: &[u16],
+) -> Peer<T> {
+    let config = SessionConfig {
+        num_players: 1,
+        ..config
+    };
+    let host = session(config, callbacks);
+    {
+        let mut host = host.lock();
+        let mut handle: PlayerHandle = 0;
+        host.add_player(Player::new(PlayerType::Local, 1), &mut handle)
+            .unwrap();
+        for port in spectator_ports {
+            add_spectator(&mut host, *port, 2);
+        }
+    }
+    host
+}
+
+// Like `recording_peer`, but telling time by `clock` from the first sync request on.
+pub fn clocked_peer(
+    config: SessionConfig,
+    clock: &Arc<TestClock>,
+    local: usize,
+    remote_port: u16,
+) -> (Peer, Recorder) {
+    let (session, recorder) = prepared(config, |session| session.set_clock(clock.clone()));
+    add_players(&mut session.lock(), local, remote_port);
+    (session, recorder)
+}
+
+/*
+ * Players 1 and 2, recording their events, with neither sending its first
+ * sync request until both sockets are up.  Otherwise the handshake starts
+ * out waiting on a retry.
+ */
+pub fn recording_pair(a: SessionConfig, b: SessionConfig) -> ((Peer, Recorder), (Peer, Recorder)) {
+    pair(a, b, |_| {})
+}
+
+/*
+ * Like `recording_pair`, but on `clock`.  Here it matters more: time may
+ * never move, so a request that found nobody listening would never be
+ * retried at all.
+ */
+pub fn clocked_pair(
+    a: SessionConfig,
+    b: SessionConfig,
+    clock: &Arc<TestClock>,
+) -> ((Peer, Recorder), (Peer, Recorder)) {
+    pair(a, b, |session| session.set_clock(clock.clone()))
+}
+
+fn pair(
+    a: SessionConfig,
+    b: SessionConfig,
+    prepare: impl Fn(&mut Peer2PeerBackend<Recorder>),
+) -> ((Peer, Recorder), (Peer, Recorder)) {
+    let (a_port, b_port) = (a.local_port, b.local_port);
+    let a = prepared(a, &prepare);
+    let b = prepared(b, &prepare);
+    add_players(&mut a.0.lock(), 1, b_port);
+    add_players(&mut b.0.lock(), 2, a_port);
+    (a, b)
+}
+
+// A recording session from `config`, with `prepare` run on it before anyone's added.
+fn prepared(
+    config: SessionConfig,
+    prepare: impl Fn(&mut Peer2PeerBackend<Recorder>),
+) -> (Peer, Recorder) {
+    let recorder = Recorder::default();
+    let session = session(config, Arc::new(Mutex::new(recorder.clone())));
+    prepare(&mut session.lock());
+    (session, recorder)
+}
+
+// Services the session, player or spectator, for up to a millisecond.
+pub fn poll<S: Session>(session: &Arc<Mutex<S>>) {
+    session
+        .lock()
+        .do_poll(Some(Duration::from_millis(1)))
+        .unwrap();
+}
+
+// Runs one frame of blank input, if the session will take another local input.
+pub fn advance<T: GGPOSessionCallbacks + Send + Sync>(
+    session: &Peer<T>,
+    handle: PlayerHandle,
+) -> bool {
+    let mut session = session.lock();
+    let mut values: InputBuffer = Default::default();
+    match session.add_local_input(handle, &values, 1) {
+        Ok(()) => {}
+        Err(GGPOError::PredictionThreshold) | Err(GGPOError::Paused) => return false,
+        Err(e) => panic!("add_local_input failed: {}", e),
+    }
+    session.synchronize_input(&mut values, None).unwrap();
+    session.increment_frame().unwrap();
+    true
+}
+
+// Calls `step` until it says it's done or `time` is up, and says which it was.
+pub fn poll_within(time: Duration, mut step: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + time;
+    while !step() {
+        if Instant::now() >= deadline {
+            return false;
+        }
+    }
+    true
+}
+
+// Calls `step` until it says it's done, failing with `failure` if that takes over five seconds.
+pub fn poll_until(failure: &str, step: impl FnMut() -> bool) {
+    assert!(poll_within(Duration::from_secs(5), step), "{}", failure);
+}
+
+// Whether every one of `events` has seen its session start running.
+pub fn running(events: &[&Recorder]) -> bool {
+    events
+        .iter()
+        .all(|events| events.saw(|e| matches!(e, Event::Running)))
+}
+
+// Calls `poll` until every one of `events` has seen its session start running.
+pub fn synchronize_by(events: &[&Recorder], mut poll: impl FnMut()) {
+    poll_until("sessions never synchronized", || {
+        if running(events) {
+            return true;
+        }
+        poll();
+        false
+    });
+}
+
+// Polls every session until each has reported it's running.
+pub fn synchronize<T: GGPOSessionCallbacks + Send + Sync>(sessions: &[(&Peer<T>, &Recorder)]) {
+    let events: Vec<&Recorder> = sessions.iter().map(|(_, events)| *events).collect();
+    synchronize_by(&events, || {
+        for (session, _) in sessions.iter() {
+            poll(session);
+        }
+    });
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use common::{peer, synchronize, Recorder};
+use ggpo::{
+    ggpo::{Event, Session},
+    player::PlayerHandle,
+};
+use std::time::Duration;
+
+// Where each connection event for `player` came, in order.
+fn connection_events(events: &Recorder, player: PlayerHandle) -> Vec<&'static str> {
+    events
+        .events
+        .lock()
+        .iter()
+        .filter_map(|e| match e {
+            Event::ConnectedToPeer(e) if e.player == player => Some("connected"),
+            Event::SynchronizingWithPeer(e) if e.player == player => Some("synchronizing"),
+            Event::SynchronizedWithPeer(e) if e.player == player => Some("synchronized"),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn connected_comes_once_and_before_synchronized() {
+    let (a, a_events) = peer(18360, 1, 18370);
+    let (b, b_events) = peer(18370, 2, 18360);
+
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+    // Plenty more packets from each side, none of which should connect us again.
+    for _ in 0..50 {
+        a.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+        b.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+    }
+
+    for (events, remote) in [(&a_events, 2), (&b_events, 1)].iter() {
+        let events = connection_events(events, *remote);
+        // Connected as soon as the first packet got through, well before the
+        // handshake was done.
+        assert_eq!(events.first(), Some(&"connected"));
+        assert_eq!(events.last(), Some(&"synchronized"));
+        assert_eq!(events.iter().filter(|&&e| e == "connected").count(), 1);
+        assert_eq!(events.iter().filter(|&&e| e == "synchronized").count(), 1);
+        assert!(events.contains(&"synchronizing"));
+    }
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use common::{advance, peer, synchronize};
+use ggpo::{game_input::Frame, ggpo::Session};
+use std::time::{Duration, Instant};
+
+#[test]
+fn a_disconnected_player_keeps_the_frame_their_input_ran_out() {
+    let (a, a_events) = peer(18600, 1, 18610);
+    let (b, b_events) = peer(18610, 2, 18600);
+
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+
+    // Play until A has twenty of B's frames.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while a.lock().connect_status().unwrap()[1].last_frame < Frame::new(20) {
+        assert!(Instant::now() < deadline, "B's input never reached A");
+        advance(&a, 1);
+        advance(&b, 2);
+        a.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+        b.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+    }
+    let status = a.lock().connect_status().unwrap();
+    assert_eq!(status.len(), 2);
+    assert!(status.iter().all(|player| player.connected));
+    assert!(status[0].last_frame >= status[1].last_frame);
+
+    a.lock().disconnect_player(2).unwrap();
+    let frozen = a.lock().connect_status().unwrap()[1];
+    assert!(!frozen.connected);
+    assert!(frozen.last_frame >= Frame::new(20));
+
+    // A plays on alone; B's frame stays put while A's own moves on.
+    for _ in 0..10 {
+        advance(&a, 1);
+        a.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+        b.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+    }
+    let status = a.lock().connect_status().unwrap();
+    assert_eq!(status[1], frozen);
+    assert!(status[0].connected);
+    assert!(status[0].last_frame > frozen.last_frame);
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use common::{peer, synchronize};
+use ggpo::ggpo::{ConnectionQuality, Event, NetworkStats, Session};
+use std::time::{Duration, Instant};
+
+fn stats(ping: usize, loss_percent: usize, drift: i32) -> NetworkStats {
+    let mut stats = NetworkStats::new();
+    stats.network.ping = ping;
+    stats.network.loss_percent = loss_percent;
+    stats.timesync.remote_frames_behind = drift * 2;
+    stats
+}
+
+#[test]
+fn any_bad_measure_drags_the_grade_down() {
+    assert_eq!(
+        ConnectionQuality::classify(&stats(20, 0, 0)),
+        ConnectionQuality::Excellent
+    );
+    assert_eq!(
+        ConnectionQuality::classify(&stats(20, 4, 0)),
+        ConnectionQuality::Good
+    );
+    assert_eq!(
+        ConnectionQuality::classify(&stats(20, 0, 5)),
+        ConnectionQuality::Fair
+    );
+    assert_eq!(
+        ConnectionQuality::classify(&stats(250, 0, 0)),
+        ConnectionQuality::Poor
+    );
+}
+
+#[test]
+fn climbing_back_takes_clearing_the_bound() {
+    let quality = ConnectionQuality::Good.reclassify(&stats(101, 0, 0));
+    assert_eq!(quality, ConnectionQuality::Fair);
+
+    // Back under the bound, but not by enough.
+    let quality = quality.reclassify(&stats(95, 0, 0));
+    assert_eq!(quality, ConnectionQuality::Fair);
+
+    let quality = quality.reclassify(&stats(80, 0, 0));
+    assert_eq!(quality, ConnectionQuality::Good);
+}
+
+#[test]
+fn quality_degrades_as_the_link_slows() {
+    let (a, a_events) = peer(17580, 1, 17590);
+    let (b, b_events) = peer(17590, 2, 17580);
+
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+    assert_eq!(
+        a.lock().connection_quality(2).unwrap(),
+        ConnectionQuality::Excellent
+    );
+
+    // Everything A sends is held this long, so its round trips take about as long.
+    let stages = [
+        (75, ConnectionQuality::Good),
+        (150, ConnectionQuality::Fair),
+        (300, ConnectionQuality::Poor),
+    ];
+    for &(latency, expected) in stages.iter() {
+        a.lock().set_network_conditions(latency, 0);
+        let deadline = Instant::now() + Duration::from_secs(6);
+        while a.lock().connection_quality(2).unwrap() != expected {
+            assert!(
+                Instant::now() < deadline,
+                "quality never dropped to {:?} at {} ms",
+                expected,
+                latency
+            );
+            a.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+            b.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+        }
+    }
+
+    let changes: Vec<ConnectionQuality> = a_events
+        .events
+        .lock()
+        .iter()
+        .filter_map(|e| match e {
+            Event::ConnectionQualityChanged(changed) if changed.player == 2 => {
+                Some(changed.quality)
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        changes,
+        vec![
+            ConnectionQuality::Good,
+            ConnectionQuality::Fair,
+            ConnectionQuality::Poor
+        ]
+    );
+}
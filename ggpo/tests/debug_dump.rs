@@ -0,0 +1,66 @@
+mod common;
+
+use common::{advance, peer, synchronize};
+use ggpo::{game_input::Frame, ggpo::Session, player::PlayerType};
+use std::time::{Duration, Instant};
+
+const FRAMES: u32 = 30;
+
+#[test]
+fn dump_shows_where_a_short_match_got_to() {
+    let (a, a_events) = peer(18490, 1, 18500);
+    let (b, b_events) = peer(18500, 2, 18490);
+
+    let dump = a.lock().debug_dump().unwrap();
+    assert!(dump.synchronizing);
+    assert_eq!(dump.frame, 0);
+
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+
+    let mut frames = [0; 2];
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while frames[0] < FRAMES || frames[1] < FRAMES {
+        assert!(Instant::now() < deadline, "stalled at {:?}", frames);
+        if frames[0] < FRAMES && advance(&a, 1) {
+            frames[0] += 1;
+        }
+        if frames[1] < FRAMES && advance(&b, 2) {
+            frames[1] += 1;
+        }
+        a.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+        b.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+    }
+    // Let B's last inputs reach A.
+    for _ in 0..50 {
+        a.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+        b.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+    }
+
+    let dump = a.lock().debug_dump().unwrap();
+    assert_eq!(dump.frame, FRAMES);
+    assert_eq!(dump.num_players, 2);
+    assert_eq!(dump.players.len(), 2);
+    assert!(!dump.synchronizing);
+
+    let local = &dump.players[0];
+    assert_eq!(local.handle, 1);
+    assert_eq!(local.player_type, PlayerType::Local);
+    assert_eq!(local.last_input_frame, Frame::new(FRAMES - 1));
+    assert!(local.magic_numbers.is_none());
+
+    let remote = &dump.players[1];
+    assert_eq!(remote.handle, 2);
+    assert_eq!(remote.last_input_frame, Frame::new(FRAMES - 1));
+    assert_eq!(remote.predicted_frames, 0);
+    let (ours, theirs) = remote.magic_numbers.expect("magic numbers");
+    assert_ne!(ours, 0);
+    assert_ne!(theirs, 0);
+
+    let text = dump.to_string();
+    assert!(
+        text.contains(&format!("session at frame {}", FRAMES)),
+        "{}",
+        text
+    );
+    assert!(text.contains("2 players"), "{}", text);
+}
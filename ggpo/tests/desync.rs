@@ -0,0 +1,137 @@
+mod common;
+
+use bytes::Bytes;
+use common::MockGame;
+use ggpo::{
+    desync::DesyncDetector,
+    game_input::{Frame, InputBuffer},
+    ggpo::{Event, GGPOError, GGPOSessionCallbacks, SavedState},
+};
+
+const INPUT_SIZE: usize = 2;
+
+fn inputs(frame: u32) -> InputBuffer {
+    let mut inputs: InputBuffer = Default::default();
+    inputs[0][0] = frame as u8;
+    inputs[1][0] = frame as u8 * 2;
+    inputs
+}
+
+// The remote peer's state drifts from frame 6 on.
+fn remote_checksum(frame: u32) -> u32 {
+    if frame < 6 {
+        frame
+    } else {
+        frame + 100
+    }
+}
+
+#[test]
+fn report_pinpoints_the_first_divergent_frame() {
+    let mut detector = DesyncDetector::new(2, INPUT_SIZE);
+
+    // The peer is a couple of frames ahead of us, so some of its checksums
+    // arrive before we've confirmed the frame ourselves.
+    for frame in 0..3 {
+        assert!(detector
+            .record_remote(1, Frame::new(frame), remote_checksum(frame))
+            .is_none());
+    }
+    let mut report = None;
+    for frame in 0..10 {
+        if let Some(found) = detector
+            .record_local(Frame::new(frame), &inputs(frame), frame)
+            .pop()
+        {
+            report = Some(found);
+            break;
+        }
+        if let Some(found) =
+            detector.record_remote(1, Frame::new(frame + 3), remote_checksum(frame + 3))
+        {
+            report = Some(found);
+            break;
+        }
+    }
+
+    let report = report.expect("a desync report");
+    assert_eq!(report.player, 2);
+    assert_eq!(report.frame, Frame::new(6));
+    assert_eq!(report.local_checksum, 6);
+    assert_eq!(report.remote_checksum, 106);
+
+    // Every frame up to the divergent one, with both sides' checksums.
+    assert_eq!(report.frames.len(), 7);
+    for (frame, record) in report.frames.iter().enumerate() {
+        assert_eq!(record.frame, Frame::new(frame as u32));
+        assert_eq!(
+            record.inputs,
+            vec![vec![frame as u8, 0], vec![frame as u8 * 2, 0]]
+        );
+        assert_eq!(record.remote_checksum, Some(remote_checksum(frame as u32)));
+    }
+
+    // Only the first divergence is reported.
+    assert!(detector
+        .record_local(Frame::new(7), &inputs(7), 7)
+        .is_empty());
+    assert!(detector.record_remote(1, Frame::new(7), 107).is_none());
+}
+
+// Keeps whatever the session asks it to log.
+#[derive(Debug, Default, Clone)]
+struct Logger {
+    game: MockGame,
+    logged: Vec<(String, Bytes)>,
+}
+
+impl GGPOSessionCallbacks for Logger {
+    fn save_game_state(&mut self, frame: Frame) -> Result<SavedState, GGPOError> {
+        self.game.save_game_state(frame)
+    }
+
+    fn load_game_state(&mut self, buffer: &Bytes, length: usize) -> bool {
+        self.game.load_game_state(buffer, length)
+    }
+
+    fn log_game_state(&mut self, filename: String, buffer: Bytes, _length: usize) -> bool {
+        self.logged.push((filename, buffer));
+        true
+    }
+
+    fn advance_frame(&mut self, flags: i32) -> bool {
+        self.game.advance_frame(flags)
+    }
+
+    fn on_event(&mut self, _info: &Event) {}
+}
+
+#[test]
+fn report_is_written_through_log_game_state() {
+    let mut detector = DesyncDetector::new(2, INPUT_SIZE);
+    detector.record_local(Frame::new(0), &inputs(0), 0xAB);
+    let report = detector
+        .record_remote(1, Frame::new(0), 0xCD)
+        .expect("a desync report");
+
+    let mut logger = Logger::default();
+    assert!(report.log(&mut logger));
+    let (filename, text) = &logger.logged[0];
+    assert_eq!(filename, "desync-p2-0.log");
+    let text = std::str::from_utf8(text).unwrap();
+    assert!(text.contains("0x000000ab"));
+    assert!(text.contains("0x000000cd"));
+}
+
+#[test]
+fn every_peer_already_disagreeing_is_reported_at_once() {
+    let mut detector = DesyncDetector::new(3, INPUT_SIZE);
+    // Both peers' checksums for frame 0 arrive before ours, and neither matches.
+    assert!(detector.record_remote(1, Frame::new(0), 0xCD).is_none());
+    assert!(detector.record_remote(2, Frame::new(0), 0xEF).is_none());
+
+    let reports = detector.record_local(Frame::new(0), &inputs(0), 0xAB);
+    let players: Vec<_> = reports.iter().map(|report| report.player).collect();
+    assert_eq!(players, vec![2, 3]);
+    assert_eq!(reports[1].remote_checksum, 0xEF);
+}
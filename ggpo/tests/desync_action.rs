@@ -0,0 +1,172 @@
+mod common;
+
+use bytes::Bytes;
+use common::{advance, config, peer_with, poll, synchronize, Peer, Recorder};
+use ggpo::{
+    config::SessionConfig,
+    desync::DesyncAction,
+    game_input::Frame,
+    ggpo::{Event, GGPOError, GGPOSessionCallbacks, SavedState, Session},
+    sync::ChecksumCadence,
+};
+use parking_lot::Mutex;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+// The frame B's state goes wrong at.
+const DRIFT_FROM: u32 = 10;
+
+// Checksums each frame by its number, off by one from `drift_from` on.
+#[derive(Debug, Default, Clone)]
+struct Drifting {
+    events: Recorder,
+    drift_from: Option<u32>,
+}
+
+impl GGPOSessionCallbacks for Drifting {
+    fn save_game_state(&mut self, frame: Frame) -> Result<SavedState, GGPOError> {
+        let frame = frame.number().unwrap_or(0) as u32;
+        let drift = match self.drift_from {
+            Some(from) if frame >= from => 1,
+            _ => 0,
+        };
+        Ok(SavedState {
+            data: Bytes::copy_from_slice(&frame.to_le_bytes()),
+            checksum: Some(frame + drift),
+        })
+    }
+
+    fn load_game_state(&mut self, _buffer: &Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn log_game_state(&mut self, _filename: String, _buffer: Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn advance_frame(&mut self, _flags: i32) -> bool {
+        true
+    }
+
+    fn on_event(&mut self, info: &Event) {
+        self.events.on_event(info);
+    }
+}
+
+impl Drifting {
+    // A session checksumming every frame, and doing `on_desync` when they differ.
+    fn peer(
+        port: u16,
+        local: usize,
+        remote_port: u16,
+        on_desync: DesyncAction,
+        drift_from: Option<u32>,
+    ) -> (Peer<Drifting>, Recorder) {
+        let game = Drifting {
+            events: Recorder::default(),
+            drift_from,
+        };
+        let events = game.events.clone();
+        let config = SessionConfig {
+            checksum_cadence: ChecksumCadence::EveryFrame,
+            on_desync,
+            ..config(port)
+        };
+        let session = peer_with(config, Arc::new(Mutex::new(game)), local, remote_port);
+        (session, events)
+    }
+}
+
+// Two peers whose states part ways at `DRIFT_FROM`.
+struct Match {
+    a: Peer<Drifting>,
+    b: Peer<Drifting>,
+    a_events: Recorder,
+    b_events: Recorder,
+    a_frames: u32,
+}
+
+impl Match {
+    fn new(a_port: u16, b_port: u16, on_desync: DesyncAction) -> Self {
+        let (a, a_events) = Drifting::peer(a_port, 1, b_port, on_desync, None);
+        let (b, b_events) = Drifting::peer(b_port, 2, a_port, on_desync, Some(DRIFT_FROM));
+        synchronize(&[(&a, &a_events), (&b, &b_events)]);
+        Self {
+            a,
+            b,
+            a_events,
+            b_events,
+            a_frames: 0,
+        }
+    }
+
+    fn play(&mut self, duration: Duration) {
+        let until = Instant::now() + duration;
+        while Instant::now() < until {
+            if advance(&self.a, 1) {
+                self.a_frames += 1;
+            }
+            advance(&self.b, 2);
+            poll(&self.a);
+            poll(&self.b);
+        }
+    }
+
+    // Plays until A has noticed the desync.
+    fn play_until_desync(&mut self) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !self.a_events.saw(|e| matches!(e, Event::DesyncDetected(_))) {
+            assert!(Instant::now() < deadline, "desync never detected");
+            self.play(Duration::from_millis(10));
+        }
+    }
+}
+
+#[test]
+fn continuing_keeps_the_match_going() {
+    let mut game = Match::new(18750, 18760, DesyncAction::Continue);
+    game.play_until_desync();
+    let frames = game.a_frames;
+    game.play(Duration::from_millis(200));
+    assert!(game.a_frames > frames + 10, "the session stopped advancing");
+    assert!(!game
+        .a_events
+        .saw(|e| matches!(e, Event::SessionPaused(_) | Event::DisconnectedFromPeer(_))));
+}
+
+#[test]
+fn pausing_halts_both_peers() {
+    let mut game = Match::new(18770, 18780, DesyncAction::Pause);
+    game.play_until_desync();
+    game.play(Duration::from_millis(200));
+    let frames = game.a_frames;
+    game.play(Duration::from_millis(200));
+    assert_eq!(game.a_frames, frames, "the session kept advancing");
+    assert!(game.a_events.saw(|e| matches!(e, Event::SessionPaused(_))));
+    assert!(game.b_events.saw(|e| matches!(e, Event::SessionPaused(_))));
+    assert!(!advance(&game.a, 1));
+
+    // The game can pick up again once it's dealt with it.
+    game.a.lock().resume().unwrap();
+    game.play(Duration::from_millis(100));
+    assert!(game.a_frames > frames);
+}
+
+#[test]
+fn disconnecting_leaves_the_match() {
+    let mut game = Match::new(18790, 18800, DesyncAction::Disconnect);
+    game.play_until_desync();
+    game.play(Duration::from_millis(100));
+    assert!(game
+        .a_events
+        .saw(|e| matches!(e, Event::DisconnectedFromPeer(peer) if peer.player == 2)));
+    assert!(game
+        .b_events
+        .saw(|e| matches!(e, Event::DisconnectedFromPeer(peer) if peer.player == 1)));
+    let players = game.a.lock().players().unwrap();
+    assert!(players
+        .iter()
+        .all(|player| player.handle == 1 || !player.connected));
+}
@@ -0,0 +1,31 @@
+use ggpo::ggpo::DisconnectFlags;
+
+#[test]
+fn each_bit_is_one_player_by_queue_index() {
+    for player_index in 0..8 {
+        let flags = DisconnectFlags::new(1 << player_index);
+        for other in 0..8 {
+            assert_eq!(flags.is_disconnected(other), other == player_index);
+        }
+    }
+    assert!(!DisconnectFlags::default().is_disconnected(0));
+    // Past the last bit nobody's there to be disconnected.
+    assert!(!DisconnectFlags::new(-1).is_disconnected(32));
+}
+
+#[test]
+fn disconnected_yields_exactly_the_set_bits() {
+    let flags = DisconnectFlags::new((1 << 0) | (1 << 2) | (1 << 5));
+    assert_eq!(flags.disconnected().collect::<Vec<_>>(), vec![0, 2, 5]);
+    assert_eq!(DisconnectFlags::default().disconnected().count(), 0);
+    assert_eq!(DisconnectFlags::new(-1).disconnected().count(), 32);
+}
+
+#[test]
+fn converts_to_and_from_the_bare_bits() {
+    let bits = (1 << 1) | (1 << 3);
+    let flags = DisconnectFlags::from(bits);
+    assert_eq!(flags.bits(), bits);
+    assert_eq!(i32::from(flags), bits);
+    assert_eq!(DisconnectFlags::new(bits), flags);
+}
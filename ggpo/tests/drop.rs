@@ -0,0 +1,127 @@
+mod common;
+
+use common::{poll, poll_until, Peer, Recorder};
+use ggpo::{
+    backends::p2p::Peer2PeerBackend,
+    game_input::InputBuffer,
+    ggpo::{Event, Session},
+    player::{Player, PlayerHandle, PlayerType},
+};
+use parking_lot::Mutex;
+use std::{
+    net::UdpSocket,
+    sync::{Arc, Once},
+    thread::{self, ThreadId},
+};
+
+// Every warning logged, and the thread it came from, so tests running side by side keep to their own.
+static WARNINGS: std::sync::Mutex<Vec<(ThreadId, String)>> = std::sync::Mutex::new(Vec::new());
+
+struct WarningLog;
+
+impl log::Log for WarningLog {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Warn
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            WARNINGS
+                .lock()
+                .unwrap()
+                .push((thread::current().id(), record.args().to_string()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn warnings_here() -> Vec<String> {
+    let here = thread::current().id();
+    WARNINGS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(thread, _)| *thread == here)
+        .map(|(_, warning)| warning.clone())
+        .collect()
+}
+
+fn capture_warnings() {
+    static CAPTURE: Once = Once::new();
+    CAPTURE.call_once(|| {
+        log::set_logger(&WarningLog).unwrap();
+        log::set_max_level(log::LevelFilter::Warn);
+    });
+}
+
+// A one player session on `port` ten frames in, so it's holding saved states.
+fn mid_match(port: u16) -> (Peer, Arc<Mutex<Recorder>>) {
+    let recorder = Recorder::default();
+    let game = Arc::new(Mutex::new(recorder.clone()));
+    let session = Peer2PeerBackend::new(game.clone(), port, 1, 1, None).unwrap();
+    {
+        let mut session = session.lock();
+        let mut handle: PlayerHandle = 0;
+        session
+            .add_player(Player::new(PlayerType::Local, 1), &mut handle)
+            .unwrap();
+    }
+
+    poll_until("session never started", || {
+        poll(&session);
+        recorder.saw(|e| matches!(e, Event::Running))
+    });
+    for _ in 0..10 {
+        let mut session = session.lock();
+        let mut values: InputBuffer = Default::default();
+        session.add_local_input(1, &values, 1).unwrap();
+        session.synchronize_input(&mut values, None).unwrap();
+        session.increment_frame().unwrap();
+    }
+    assert!(session.lock().memory_usage().unwrap().saved_states > 0);
+    (session, game)
+}
+
+fn never_freed(warnings: &[String]) -> bool {
+    warnings
+        .iter()
+        .any(|warning| warning.contains("never freed"))
+}
+
+#[test]
+fn dropping_a_session_mid_match_releases_everything() {
+    capture_warnings();
+    let (session, game) = mid_match(18420);
+    let saved_states = session.lock().memory_usage().unwrap().saved_states;
+
+    // No shutdown: the session just goes away, saved states and all.
+    drop(session);
+
+    // Nothing of the session is left holding the game...
+    assert_eq!(Arc::strong_count(&game), 1);
+    // ...or the port.
+    UdpSocket::bind("127.0.0.1:18420").expect("the port is still bound");
+    // Debug builds point out the saved states were never freed.
+    if cfg!(debug_assertions) {
+        let warning = format!("dropped with {} saved states", saved_states);
+        assert!(
+            warnings_here().iter().any(|w| w.starts_with(&warning)),
+            "no warning about the unfreed saved states in {:?}",
+            warnings_here()
+        );
+    }
+}
+
+#[test]
+fn a_session_shut_down_first_drops_quietly() {
+    capture_warnings();
+    let (session, game) = mid_match(19660);
+
+    session.lock().shutdown().unwrap();
+    assert_eq!(session.lock().memory_usage().unwrap().saved_states, 0);
+    drop(session);
+
+    assert_eq!(Arc::strong_count(&game), 1);
+    assert!(!never_freed(&warnings_here()), "{:?}", warnings_here());
+}
@@ -0,0 +1,96 @@
+use ggpo::network::{
+    udp::{normalize_addr, Udp, UdpCallback},
+    udp_msg::{MsgType, UdpMsg},
+};
+use mio::Poll;
+use parking_lot::Mutex;
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+// Notes where each message came from.
+#[derive(Default)]
+struct Senders {
+    from: Vec<SocketAddr>,
+}
+
+impl UdpCallback for Senders {
+    fn on_msg(&mut self, from: &SocketAddr, _msg: UdpMsg, _len: usize) -> Result<(), String> {
+        self.from.push(*from);
+        Ok(())
+    }
+}
+
+fn bound_udp<T: UdpCallback>(port: u16, dual_stack: bool, callbacks: Arc<Mutex<T>>) -> Udp<T> {
+    let mut udp = Udp::new();
+    udp.set_dual_stack(dual_stack);
+    udp.init(port, Arc::new(Mutex::new(Poll::new().unwrap())), callbacks)
+        .unwrap();
+    udp.start_send_task().unwrap();
+    udp
+}
+
+fn loopback(port: u16) -> SocketAddr {
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), port)
+}
+
+#[test]
+fn dual_stack_socket_takes_an_ipv4_sender() {
+    let senders = Arc::new(Mutex::new(Senders::default()));
+    let mut receiver = bound_udp(17990, true, senders.clone());
+    let mut sender = bound_udp(18000, false, Arc::new(Mutex::new(Senders::default())));
+
+    sender
+        .send_to(
+            Arc::new(UdpMsg::new(MsgType::KeepAlive)),
+            &[loopback(17990)],
+        )
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while senders.lock().from.is_empty() {
+        assert!(Instant::now() < deadline, "nothing arrived");
+        receiver.on_loop_poll(0).unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+    }
+
+    // Whether or not the platform gave us dual-stack, the sender shows up as plain IPv4.
+    assert_eq!(senders.lock().from, vec![loopback(18000)]);
+}
+
+#[test]
+fn dual_stack_socket_replies_to_an_ipv4_peer() {
+    let senders = Arc::new(Mutex::new(Senders::default()));
+    let mut receiver = bound_udp(18010, false, senders.clone());
+    let mut sender = bound_udp(18020, true, Arc::new(Mutex::new(Senders::default())));
+
+    sender
+        .send_to(
+            Arc::new(UdpMsg::new(MsgType::KeepAlive)),
+            &[loopback(18010)],
+        )
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while senders.lock().from.is_empty() {
+        assert!(Instant::now() < deadline, "nothing arrived");
+        receiver.on_loop_poll(0).unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(senders.lock().from[0].port(), 18020);
+}
+
+#[test]
+fn mapped_addresses_normalize_to_ipv4() {
+    let mapped = SocketAddr::new(
+        IpAddr::V6(Ipv4Addr::new(10, 1, 2, 3).to_ipv6_mapped()),
+        7000,
+    );
+    assert_eq!(
+        normalize_addr(mapped),
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 1, 2, 3)), 7000)
+    );
+
+    let native = SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 7000);
+    assert_eq!(normalize_addr(native), native);
+}
@@ -0,0 +1,185 @@
+#![cfg(feature = "encryption")]
+
+mod common;
+
+use common::{advance, config, localhost, poll, poll_within, recording_peer, running, synchronize};
+use ggpo::{
+    config::SessionConfig,
+    game_input::Frame,
+    ggpo::{Event, Session},
+    network::{
+        encryption::{EncryptedTransport, PresharedKey},
+        udp::{Udp, UdpCallback, UdpError},
+        udp_msg::{MsgType, UdpMsg},
+    },
+};
+use mio::Poll;
+use parking_lot::Mutex;
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, UdpSocket},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+fn keyed(port: u16, key: PresharedKey) -> SessionConfig {
+    SessionConfig {
+        preshared_key: Some(key),
+        ..config(port)
+    }
+}
+
+#[test]
+fn only_the_key_it_was_sealed_under_opens_a_packet() {
+    let sealer = EncryptedTransport::new(&[7; 32]);
+    let sealed = sealer.seal(b"inputs");
+    assert_ne!(&sealed[sealed.len() - 6..], b"inputs");
+    assert_eq!(sealer.open(&sealed).unwrap(), b"inputs");
+    assert!(EncryptedTransport::new(&[8; 32]).open(&sealed).is_none());
+
+    let mut tampered = sealed;
+    tampered[14] ^= 1;
+    assert!(sealer.open(&tampered).is_none());
+    assert!(sealer.open(&[0; 8]).is_none());
+}
+
+#[test]
+fn peers_sharing_a_key_synchronize() {
+    let (a, a_events) = recording_peer(keyed(19020, [7; 32]), 1, 19030);
+    let (b, b_events) = recording_peer(keyed(19030, [7; 32]), 2, 19020);
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+}
+
+#[test]
+fn a_peer_on_the_wrong_key_never_synchronizes() {
+    let (a, a_events) = recording_peer(keyed(19040, [7; 32]), 1, 19050);
+    let (b, b_events) = recording_peer(keyed(19050, [8; 32]), 2, 19040);
+    assert!(!poll_within(Duration::from_secs(1), || {
+        poll(&a);
+        poll(&b);
+        running(&[&a_events, &b_events])
+    }));
+    assert!(!a_events.saw(|e| matches!(e, Event::SynchronizingWithPeer(_))));
+    assert!(!b_events.saw(|e| matches!(e, Event::SynchronizingWithPeer(_))));
+}
+
+#[test]
+fn both_ends_derive_the_same_session_key_and_nobody_else_does() {
+    let psk = EncryptedTransport::new(&[7; 32]);
+    let sealed = psk.session(1, 2).seal(b"inputs");
+    assert_eq!(psk.session(2, 1).open(&sealed).unwrap(), b"inputs");
+    assert!(psk.session(1, 3).open(&sealed).is_none());
+    assert!(psk.open(&sealed).is_none());
+    assert!(EncryptedTransport::new(&[8; 32])
+        .session(1, 2)
+        .open(&sealed)
+        .is_none());
+}
+
+struct Ignore;
+
+impl UdpCallback for Ignore {
+    fn on_msg(&mut self, _from: &SocketAddr, _msg: UdpMsg, _len: usize) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+fn sealed_udp(port: u16) -> Udp<Ignore> {
+    let mut udp = Udp::new();
+    udp.set_encryption(Some(EncryptedTransport::new(&[7; 32])));
+    udp.init(
+        port,
+        Arc::new(Mutex::new(Poll::new().unwrap())),
+        Arc::new(Mutex::new(Ignore)),
+    )
+    .unwrap();
+    udp
+}
+
+// What `udp` sends to `tap` as a `packet_type`, caught on the way.
+fn capture(udp: &mut Udp<Ignore>, tap: &UdpSocket, packet_type: MsgType) -> Vec<u8> {
+    let to = tap.local_addr().unwrap();
+    udp.send_to(Arc::new(UdpMsg::new(packet_type)), &[to])
+        .unwrap();
+    udp.flush_send_queue().unwrap();
+    let mut buffer = [0; 2048];
+    let (len, _) = tap.recv_from(&mut buffer).unwrap();
+    buffer[..len].to_vec()
+}
+
+// `packet` sent on from `tap` to `udp`, on `port`, as `udp` takes it.
+fn replay(
+    tap: &UdpSocket,
+    packet: &[u8],
+    udp: &mut Udp<Ignore>,
+    port: u16,
+) -> Result<MsgType, UdpError> {
+    tap.send_to(packet, localhost(port)).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(1);
+    loop {
+        match udp.get_msg() {
+            Err(UdpError::Io { source }) if source.kind() == ErrorKind::WouldBlock => {
+                assert!(Instant::now() < deadline, "the packet never arrived");
+                thread::sleep(Duration::from_millis(1));
+            }
+            received => return received.map(|(msg, _, _)| msg.header.packet_type),
+        }
+    }
+}
+
+#[test]
+fn a_packet_from_another_session_doesnt_open() {
+    let tap = UdpSocket::bind(localhost(19460)).unwrap();
+    tap.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    let from_tap = tap.local_addr().unwrap();
+
+    // Session A, between `a` and whoever's behind the tap.
+    let mut a = sealed_udp(19470);
+    a.set_session_nonces(from_tap, 1, 2);
+    let captured = capture(&mut a, &tap, MsgType::KeepAlive);
+
+    // The same peers, and key, in session A's place: it opens...
+    let mut same = sealed_udp(19480);
+    same.set_session_nonces(from_tap, 2, 1);
+    assert!(matches!(
+        replay(&tap, &captured, &mut same, 19480),
+        Ok(MsgType::KeepAlive)
+    ));
+    // ...but not in session B, which traded other nonces.
+    let mut b = sealed_udp(19490);
+    b.set_session_nonces(from_tap, 3, 2);
+    assert!(matches!(
+        replay(&tap, &captured, &mut b, 19490),
+        Err(UdpError::Unauthenticated { .. })
+    ));
+
+    // Once the session key is set, the pre-shared key carries the handshake and nothing else.
+    let mut handshake = sealed_udp(19500);
+    let keep_alive = capture(&mut handshake, &tap, MsgType::KeepAlive);
+    let sync_request = capture(&mut handshake, &tap, MsgType::SyncRequest);
+    assert!(matches!(
+        replay(&tap, &keep_alive, &mut b, 19490),
+        Err(UdpError::Unauthenticated { .. })
+    ));
+    assert!(matches!(
+        replay(&tap, &sync_request, &mut b, 19490),
+        Ok(MsgType::SyncRequest)
+    ));
+}
+
+#[test]
+fn peers_on_session_keys_trade_input() {
+    let (a, a_events) = recording_peer(keyed(19510, [7; 32]), 1, 19520);
+    let (b, b_events) = recording_peer(keyed(19520, [7; 32]), 2, 19510);
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while a.lock().last_confirmed_frame(2).unwrap() < Frame::new(10) {
+        assert!(Instant::now() < deadline, "B's input never reached A");
+        advance(&a, 1);
+        advance(&b, 2);
+        poll(&a);
+        poll(&b);
+    }
+}
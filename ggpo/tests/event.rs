@@ -0,0 +1,47 @@
+use bytes::Bytes;
+use ggpo::{
+    game_input::Frame,
+    ggpo::{Event, GGPOError, GGPOSessionCallbacks, SavedState, TimeSyncEvent},
+};
+
+// A game that only cares about the session starting, as most will.
+#[derive(Default, Clone)]
+struct RunningOnly {
+    running: bool,
+}
+
+impl GGPOSessionCallbacks for RunningOnly {
+    fn save_game_state(&mut self, _frame: Frame) -> Result<SavedState, GGPOError> {
+        Ok(SavedState::default())
+    }
+
+    fn load_game_state(&mut self, _buffer: &Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn log_game_state(&mut self, _filename: String, _buffer: Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn advance_frame(&mut self, _flags: i32) -> bool {
+        true
+    }
+
+    // Written as a match on purpose: it's the shape downstream code takes.
+    #[allow(clippy::single_match)]
+    fn on_event(&mut self, info: &Event) {
+        match info {
+            Event::Running => self.running = true,
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn callbacks_can_match_just_the_events_they_need() {
+    let mut game = RunningOnly::default();
+    game.on_event(&Event::TimeSync(TimeSyncEvent { frames_ahead: 2 }));
+    assert!(!game.running);
+    game.on_event(&Event::Running);
+    assert!(game.running);
+}
@@ -0,0 +1,66 @@
+use ggpo::{
+    game_input::{Frame, GameInput, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS},
+    input_queue::InputQueue,
+};
+
+const INPUT_SIZE: usize = 1;
+const LENGTH: usize = 8;
+
+fn input(frame: u32, value: u8) -> GameInput {
+    let mut bits = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    bits[0][0] = value;
+    GameInput::init(Frame::new(frame), Some(&bits), INPUT_SIZE)
+}
+
+// Everything the queue would hand back, frame by frame.
+fn contents(queue: &mut InputQueue, frames: std::ops::Range<u32>) -> Vec<GameInput> {
+    frames
+        .map(|frame| {
+            let mut out = GameInput::new();
+            queue.get_input(frame, &mut out);
+            out
+        })
+        .collect()
+}
+
+#[test]
+fn late_input_for_an_evicted_frame_is_dropped() {
+    let mut queue = InputQueue::with_length(1, INPUT_SIZE, LENGTH);
+    // Go round the ring more than once, so frame 2's slot now holds a later frame.
+    for frame in 0..20 {
+        assert!(queue.add_input(input(frame, frame as u8 + 1)));
+        if let Some(confirmed) = frame.checked_sub(2) {
+            queue.discard_confirmed_frames(confirmed);
+        }
+    }
+    let oldest = queue.oldest_frame().unwrap();
+    assert!(oldest > Frame::new(2));
+    let len = queue.len();
+    let before = contents(&mut queue, oldest.number().unwrap()..20);
+
+    // A retransmission of frame 2, long gone, with input that's since changed.
+    assert!(queue.add_input(input(2, 0xff)));
+
+    assert_eq!(queue.len(), len);
+    assert_eq!(queue.oldest_frame(), Some(oldest));
+    assert_eq!(queue.get_last_confirmed_frame(), Frame::new(19));
+    assert_eq!(contents(&mut queue, oldest.number().unwrap()..20), before);
+
+    // And the queue carries on from where it was.
+    assert!(queue.add_input(input(20, 21)));
+    assert_eq!(queue.get_last_confirmed_frame(), Frame::new(20));
+}
+
+#[test]
+fn late_input_for_a_queued_frame_is_dropped() {
+    let mut queue = InputQueue::with_length(1, INPUT_SIZE, LENGTH);
+    for frame in 0..4 {
+        assert!(queue.add_input(input(frame, frame as u8 + 1)));
+    }
+    assert!(queue.add_input(input(1, 0xff)));
+
+    assert_eq!(queue.len(), 4);
+    let mut out = GameInput::new();
+    assert!(queue.get_confirmed_input(Frame::new(1), &mut out));
+    assert_eq!(out.bits[0][0], 2);
+}
@@ -0,0 +1,151 @@
+mod common;
+
+use bytes::Bytes;
+use ggpo::{
+    backends::p2p::Peer2PeerBackend,
+    config::SessionConfig,
+    game_input::{Frame, InputBuffer},
+    ggpo::{Event, GGPOError, GGPOSessionCallbacks, SavedState, Session},
+    player::{Player, PlayerHandle, PlayerType},
+};
+use parking_lot::Mutex;
+use std::{sync::Arc, time::Duration};
+
+// A game whose state is the sum of every input it's been given.
+#[derive(Debug, Default, Clone)]
+struct Tally {
+    total: u32,
+}
+
+impl GGPOSessionCallbacks for Tally {
+    // No checksum of our own: the session's is what gets exported.
+    fn save_game_state(&mut self, _frame: Frame) -> Result<SavedState, GGPOError> {
+        Ok(SavedState {
+            data: Bytes::copy_from_slice(&self.total.to_le_bytes()),
+            checksum: None,
+        })
+    }
+
+    fn load_game_state(&mut self, buffer: &Bytes, _length: usize) -> bool {
+        let mut total = [0; 4];
+        total.copy_from_slice(&buffer[..4]);
+        self.total = u32::from_le_bytes(total);
+        true
+    }
+
+    fn log_game_state(&mut self, _filename: String, _buffer: Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn advance_frame(&mut self, _flags: i32) -> bool {
+        true
+    }
+
+    fn on_event(&mut self, _info: &Event) {}
+}
+
+struct Game {
+    session: Arc<Mutex<Peer2PeerBackend<Tally>>>,
+    tally: Arc<Mutex<Tally>>,
+    handle: PlayerHandle,
+}
+
+fn game(port: u16) -> Game {
+    let tally = Arc::new(Mutex::new(Tally::default()));
+    let config = SessionConfig {
+        local_port: port,
+        num_players: 1,
+        input_size: 1,
+        ..Default::default()
+    };
+    let session = Peer2PeerBackend::from_config(config, tally.clone()).expect("session");
+    let mut handle: PlayerHandle = 0;
+    {
+        let mut session = session.lock();
+        session
+            .add_player(Player::new(PlayerType::Local, 1), &mut handle)
+            .unwrap();
+        session.do_poll(Some(Duration::from_millis(1))).unwrap();
+    }
+    Game {
+        session,
+        tally,
+        handle,
+    }
+}
+
+// Runs a frame on `input`, which it adds to the tally.
+fn run_frame(game: &Game, input: u8) {
+    let mut session = game.session.lock();
+    let mut values: InputBuffer = Default::default();
+    values[0][0] = input;
+    session.add_local_input(game.handle, &values, 1).unwrap();
+    session.synchronize_input(&mut values, None).unwrap();
+    game.tally.lock().total += values[0][0] as u32;
+    session.increment_frame().unwrap();
+    session.do_poll(Some(Duration::from_millis(1))).unwrap();
+}
+
+#[test]
+fn an_exported_state_picks_up_where_it_left_off() {
+    let original = game(19130);
+    for input in 1..=6 {
+        run_frame(&original, input);
+    }
+    // Frame 3 starts after 1 + 2 + 3.
+    let (state, checksum) = original.session.lock().export_state(Frame::new(3)).unwrap();
+    assert_eq!(&state[..], &6u32.to_le_bytes());
+
+    let replay = game(19140);
+    replay
+        .session
+        .lock()
+        .import_state(Frame::new(3), state)
+        .unwrap();
+    assert_eq!(replay.tally.lock().total, 6);
+    for input in 4..=6 {
+        run_frame(&replay, input);
+    }
+    for frame in 3..=5 {
+        assert_eq!(
+            replay
+                .session
+                .lock()
+                .export_state(Frame::new(frame))
+                .unwrap(),
+            original
+                .session
+                .lock()
+                .export_state(Frame::new(frame))
+                .unwrap()
+        );
+    }
+    assert_eq!(
+        replay.session.lock().export_state(Frame::new(3)).unwrap().1,
+        checksum
+    );
+}
+
+#[test]
+fn only_a_retained_confirmed_frame_can_be_exported() {
+    let game = game(19150);
+    for input in 0..30 {
+        run_frame(&game, input);
+    }
+    let mut session = game.session.lock();
+    assert!(session.export_state(Frame::new(29)).is_ok());
+    assert!(matches!(
+        session.export_state(Frame::new(31)),
+        Err(GGPOError::StateNotSaved { .. })
+    ));
+    // Long gone from the ring.
+    assert!(matches!(
+        session.export_state(Frame::new(0)),
+        Err(GGPOError::StateNotSaved { .. })
+    ));
+    // Too late to start somewhere else.
+    assert!(matches!(
+        session.import_state(Frame::new(0), Bytes::from_static(&[0; 4])),
+        Err(GGPOError::InvalidRequest)
+    ));
+}
@@ -0,0 +1,54 @@
+use ggpo::{
+    ggpo::{Event, TimeSyncEvent},
+    runner::FixedTimestep,
+};
+use std::time::Duration;
+
+const FRAME_RATE: u32 = 60;
+const MAX_STEPS: u32 = 4;
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+#[test]
+fn irregular_renders_step_at_the_fixed_rate() {
+    let mut timestep = FixedTimestep::new(FRAME_RATE, MAX_STEPS);
+    // Renders anywhere from 3 to 40 ms apart, none slow enough to hit the clamp.
+    let renders = [16, 3, 40, 7, 25, 16, 33, 9, 12, 21];
+    let mut elapsed = Duration::from_secs(0);
+    let mut steps = 0;
+    for _ in 0..60 {
+        for render in renders.iter() {
+            let stepped = timestep.steps(ms(*render));
+            assert!(stepped <= MAX_STEPS);
+            elapsed += ms(*render);
+            steps += stepped;
+        }
+    }
+
+    // Whole steps for the time gone by, the rest still owed.
+    let owed = elapsed.as_secs_f64() * FRAME_RATE as f64;
+    assert_eq!(steps, owed as u32);
+    assert!(timestep.alpha() >= 0.0 && timestep.alpha() < 1.0);
+}
+
+#[test]
+fn a_long_stall_is_clamped_and_forgotten() {
+    let mut timestep = FixedTimestep::new(FRAME_RATE, MAX_STEPS);
+    assert_eq!(timestep.steps(Duration::from_secs(2)), MAX_STEPS);
+    // The rest of the two seconds doesn't come back later.
+    assert_eq!(timestep.steps(ms(1)), 0);
+    assert_eq!(timestep.steps(timestep.step_duration()), 1);
+}
+
+#[test]
+fn running_ahead_skips_steps() {
+    let mut timestep = FixedTimestep::new(FRAME_RATE, MAX_STEPS);
+    timestep.on_event(&Event::TimeSync(TimeSyncEvent { frames_ahead: 3 }));
+    let step = timestep.step_duration();
+
+    assert_eq!(timestep.steps(step * 2), 0);
+    assert_eq!(timestep.steps(step * 2), 1);
+    assert_eq!(timestep.steps(step), 1);
+}
@@ -0,0 +1,244 @@
+mod common;
+
+use bytes::Bytes;
+use common::localhost;
+use ggpo::{
+    game_input::Frame,
+    network::{
+        clock::TestClock,
+        fragment::{self, Reassembler, FRAGMENT_SIZE, MAX_FRAGMENTS},
+        udp::{
+            Udp, UdpCallback, UdpError, MAX_UDP_PACKET_SIZE, MAX_UDP_PAYLOAD, MIN_UDP_PACKET_SIZE,
+        },
+        udp_msg::{MsgEnum, MsgType, UdpMsg},
+    },
+};
+use mio::Poll;
+use parking_lot::Mutex;
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+const SNAPSHOT_SIZE: usize = 100 * 1024;
+
+struct Ignore;
+
+impl UdpCallback for Ignore {
+    fn on_msg(&mut self, _from: &SocketAddr, _msg: UdpMsg, _len: usize) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+fn bound_udp(port: u16) -> Udp<Ignore> {
+    let mut udp = Udp::new();
+    udp.init(
+        port,
+        Arc::new(Mutex::new(Poll::new().unwrap())),
+        Arc::new(Mutex::new(Ignore)),
+    )
+    .unwrap();
+    udp.start_send_task().unwrap();
+    udp
+}
+
+// Noisy enough that it's still far over a datagram once compressed.
+fn snapshot() -> UdpMsg {
+    snapshot_of(SNAPSHOT_SIZE)
+}
+
+fn snapshot_of(size: usize) -> UdpMsg {
+    let mut seed: u32 = 12345;
+    let data: Vec<u8> = (0..size)
+        .map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) as u8 & 0x0F
+        })
+        .collect();
+    let mut msg = UdpMsg::new(MsgType::StateResponse);
+    if let MsgEnum::StateResponse(response) = &mut msg.message {
+        response.frame = Frame::new(600);
+        response.checksum = 0xDEAD_BEEF;
+        response.size = data.len() as u32;
+        response.data = Bytes::from(data);
+    }
+    msg
+}
+
+fn state_data(msg: &UdpMsg) -> &Bytes {
+    match &msg.message {
+        MsgEnum::StateResponse(response) => &response.data,
+        _ => panic!("expected a state response"),
+    }
+}
+
+#[test]
+fn a_large_snapshot_arrives_whole() {
+    let mut sender = bound_udp(18310);
+    let mut receiver = bound_udp(18320);
+    let sent = snapshot();
+    assert!(fragment::split(0, &sent.encode().unwrap()).len() > 1);
+
+    sender
+        .send_to(Arc::new(sent.clone()), &[localhost(18320)])
+        .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(2);
+    let received = loop {
+        if let Some((msg, _, from)) = receiver.recv_pending().unwrap().pop() {
+            assert_eq!(from, localhost(18310));
+            break msg;
+        }
+        assert!(Instant::now() < deadline, "the snapshot never arrived");
+        std::thread::sleep(Duration::from_millis(5));
+    };
+
+    match &received.message {
+        MsgEnum::StateResponse(response) => {
+            assert_eq!(response.frame, Frame::new(600));
+            assert_eq!(response.checksum, 0xDEAD_BEEF);
+        }
+        _ => panic!("expected a state response"),
+    }
+    assert_eq!(state_data(&received), state_data(&sent));
+    assert_eq!(receiver.expired_fragment_packets(), 0);
+}
+
+#[test]
+fn a_snapshot_missing_a_fragment_is_dropped() {
+    let mut sender = bound_udp(18330);
+    let mut receiver = bound_udp(18340);
+    receiver.set_fragment_timeout(Duration::from_millis(100));
+
+    // Send the pieces ourselves, leaving one out.
+    let pieces = fragment::split(7, &snapshot().encode().unwrap());
+    for (index, piece) in pieces.into_iter().enumerate() {
+        if index != 3 {
+            sender
+                .send_to(Arc::new(piece), &[localhost(18340)])
+                .unwrap();
+        }
+    }
+
+    let deadline = Instant::now() + Duration::from_millis(400);
+    while Instant::now() < deadline {
+        assert!(receiver.recv_pending().unwrap().is_empty());
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(receiver.expired_fragment_packets(), 1);
+}
+
+#[test]
+fn fragments_wait_on_the_transport_clock() {
+    let mut sender = bound_udp(19320);
+    let mut receiver = bound_udp(19330);
+    let clock = Arc::new(TestClock::new());
+    receiver.set_clock(clock.clone());
+    receiver.set_fragment_timeout(Duration::from_millis(100));
+
+    let pieces = fragment::split(8, &snapshot().encode().unwrap());
+    for (index, piece) in pieces.into_iter().enumerate() {
+        if index != 3 {
+            sender
+                .send_to(Arc::new(piece), &[localhost(19330)])
+                .unwrap();
+        }
+    }
+
+    // Well past the timeout on the wall clock, but not on the transport's.
+    let deadline = Instant::now() + Duration::from_millis(300);
+    while Instant::now() < deadline {
+        assert!(receiver.recv_pending().unwrap().is_empty());
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(receiver.expired_fragment_packets(), 0);
+
+    clock.advance(Duration::from_millis(150));
+    assert!(receiver.recv_pending().unwrap().is_empty());
+    assert_eq!(receiver.expired_fragment_packets(), 1);
+}
+
+#[test]
+fn fragments_reassemble_in_any_order() {
+    let packet: Vec<u8> = (0..FRAGMENT_SIZE * 3 + 10).map(|i| i as u8).collect();
+    let from = localhost(18350);
+    let now = SystemTime::now();
+    let fragments: Vec<_> = fragment::split(1, &packet)
+        .into_iter()
+        .map(|msg| match msg.message {
+            MsgEnum::Fragment(fragment) => fragment,
+            _ => panic!("expected a fragment"),
+        })
+        .collect();
+    assert_eq!(fragments.len(), 4);
+
+    let mut reassembler = Reassembler::default();
+    for index in [2, 0, 3, 0].iter() {
+        assert!(reassembler.push(from, &fragments[*index], now).is_none());
+    }
+    assert_eq!(reassembler.pending(), 1);
+    assert_eq!(reassembler.push(from, &fragments[1], now), Some(packet));
+    assert_eq!(reassembler.pending(), 0);
+
+    // A set that never completes is given up on once the timeout passes.
+    reassembler.push(from, &fragments[0], now);
+    assert_eq!(reassembler.expire(now + Duration::from_millis(10)), 0);
+    assert_eq!(reassembler.expire(now + Duration::from_secs(2)), 1);
+    assert_eq!(reassembler.pending(), 0);
+    assert_eq!(reassembler.expired(), 1);
+}
+
+#[test]
+fn the_receive_buffer_is_sized_from_the_packet_limit() {
+    let mut udp: Udp<Ignore> = Udp::new();
+    assert_eq!(udp.max_packet_size(), MAX_UDP_PACKET_SIZE);
+    assert_eq!(udp.recv_buffer_size(), MAX_UDP_PACKET_SIZE);
+
+    udp.set_max_packet_size(1400).unwrap();
+    assert_eq!(udp.max_packet_size(), 1400);
+    assert_eq!(udp.recv_buffer_size(), 1400);
+
+    // Too small for a fragment, or too big for UDP.
+    for size in [MIN_UDP_PACKET_SIZE - 1, MAX_UDP_PAYLOAD + 1].iter() {
+        assert!(matches!(
+            udp.set_max_packet_size(*size),
+            Err(UdpError::PacketSizeOutOfRange(_))
+        ));
+    }
+    assert_eq!(udp.recv_buffer_size(), 1400);
+}
+
+#[test]
+fn a_smaller_limit_still_gets_a_snapshot_through() {
+    let mut sender = bound_udp(18620);
+    let mut receiver = bound_udp(18630);
+    sender.set_max_packet_size(MIN_UDP_PACKET_SIZE).unwrap();
+    receiver.set_max_packet_size(MIN_UDP_PACKET_SIZE).unwrap();
+
+    let sent = snapshot();
+    sender
+        .send_to(Arc::new(sent.clone()), &[localhost(18630)])
+        .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(2);
+    let received = loop {
+        if let Some((msg, _, _)) = receiver.recv_pending().unwrap().pop() {
+            break msg;
+        }
+        assert!(Instant::now() < deadline, "the snapshot never arrived");
+        std::thread::sleep(Duration::from_millis(5));
+    };
+    assert_eq!(state_data(&received), state_data(&sent));
+}
+
+#[test]
+fn a_snapshot_too_big_to_fragment_is_refused() {
+    let mut sender = bound_udp(18640);
+    let sent = snapshot_of(MAX_FRAGMENTS * FRAGMENT_SIZE);
+    assert!(matches!(
+        sender.send_to(Arc::new(sent), &[localhost(18650)]),
+        Err(UdpError::PacketTooLarge { .. })
+    ));
+    assert_eq!(sender.queued_sends(), 0);
+}
@@ -0,0 +1,35 @@
+use ggpo::game_input::{Frame, NULL_FRAME};
+
+#[test]
+fn null_frame_orders_before_every_frame() {
+    assert!(NULL_FRAME < Frame::new(0));
+    assert!(NULL_FRAME < Frame::new(1));
+    assert!(Frame::new(0) < Frame::new(1));
+    assert_eq!(std::cmp::min(Frame::new(7), NULL_FRAME), NULL_FRAME);
+    assert_eq!(std::cmp::max(Frame::new(7), NULL_FRAME), Frame::new(7));
+    assert_eq!(Frame::default(), NULL_FRAME);
+}
+
+#[test]
+fn prev_of_frame_zero_is_null() {
+    assert_eq!(Frame::new(0).prev(), NULL_FRAME);
+    assert!(Frame::new(0).prev().is_null());
+    assert_eq!(Frame::new(0).prev().number(), None);
+    assert_eq!(NULL_FRAME.prev(), NULL_FRAME);
+    assert_eq!(Frame::new(3) - 10, NULL_FRAME);
+    assert_eq!(Frame::new(5).prev(), Frame::new(4));
+}
+
+#[test]
+fn next_steps_forward_from_null() {
+    assert_eq!(NULL_FRAME.next(), Frame::new(0));
+    assert_eq!(Frame::new(41).next(), Frame::new(42));
+    assert_eq!(Frame::MAX.next(), Frame::MAX);
+    assert_eq!(Frame::new(2) + 3, Frame::new(5));
+}
+
+#[test]
+fn null_frame_displays_as_null() {
+    assert_eq!(NULL_FRAME.to_string(), "NULL");
+    assert_eq!(format!("{:04}", Frame::new(12)), "0012");
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use bytes::Bytes;
+use common::{localhost, peer, synchronize};
+use ggpo::{
+    game_input::{Frame, InputBuffer},
+    ggpo::{GGPOError, Session},
+    network::{
+        udp::{Udp, UdpCallback},
+        udp_msg::{ConnectStatus, MsgEnum, MsgType, UdpMsg, UDP_MSG_MAX_PLAYERS},
+        udp_proto::UdpProtocol,
+    },
+};
+use mio::Poll;
+use parking_lot::Mutex;
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+struct Ignore;
+
+impl UdpCallback for Ignore {
+    fn on_msg(&mut self, _from: &SocketAddr, _msg: UdpMsg, _len: usize) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[test]
+fn the_endpoint_keeps_both_sides_advantage() {
+    let mut udp = Udp::new();
+    udp.init(
+        19000,
+        Arc::new(Mutex::new(Poll::new().unwrap())),
+        Arc::new(Mutex::new(Ignore)),
+    )
+    .unwrap();
+    let status: [Arc<Mutex<ConnectStatus>>; UDP_MSG_MAX_PLAYERS] = Default::default();
+    let mut endpoint = UdpProtocol::new();
+    endpoint.init(Arc::new(Mutex::new(udp)), 0, localhost(19010), &status);
+    endpoint.set_input_size(1);
+
+    // The peer's sent us up to frame 9, and we're on frame 4.
+    let mut input = UdpMsg::new(MsgType::Input);
+    if let MsgEnum::Input(input) = &mut input.message {
+        input.start_frame = Frame::new(0);
+        input.input_size = 1;
+        input.num_bits = 10;
+        input.bits = Bytes::from_static(&[0, 0]);
+    }
+    endpoint.on_msg(&input).unwrap();
+    endpoint.set_local_frame_number(4);
+
+    // And they say they're 3 frames ahead of us.
+    let mut report = UdpMsg::new(MsgType::QualityReport);
+    if let MsgEnum::QualityReport(report) = &mut report.message {
+        report.frame_advantage = -3;
+    }
+    endpoint.on_msg(&report).unwrap();
+
+    assert_eq!(endpoint.frame_advantage(), (5, -3));
+}
+
+#[test]
+fn each_peer_sees_the_others_report() {
+    let (a, a_events) = peer(18980, 1, 18990);
+    let (b, b_events) = peer(18990, 2, 18980);
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+
+    // A runs six frames while B sits on frame 0.
+    for _ in 0..6 {
+        let mut a = a.lock();
+        let mut values: InputBuffer = Default::default();
+        a.add_local_input(1, &values, 1).unwrap();
+        a.synchronize_input(&mut values, None).unwrap();
+        a.increment_frame().unwrap();
+    }
+
+    // B is 5 frames behind A (the last one A sent is frame 5), which is what
+    // A hears in B's next quality report.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while a.lock().frame_advantage(2).unwrap().1 != 5 {
+        assert!(Instant::now() < deadline, "A never heard from B");
+        a.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+        b.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+    }
+    assert_eq!(b.lock().frame_advantage(1).unwrap().0, 5);
+    assert!(a.lock().frame_advantage(2).unwrap().0 < 0);
+    assert!(matches!(
+        a.lock().frame_advantage(1),
+        Err(GGPOError::InvalidRequest)
+    ));
+}
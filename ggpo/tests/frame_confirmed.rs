@@ -0,0 +1,64 @@
+mod common;
+
+use common::{advance, peer, synchronize, Recorder};
+use ggpo::{
+    game_input::Frame,
+    ggpo::{Event, Session, GGPO_MAX_PREDICTION_FRAMES},
+};
+use std::time::{Duration, Instant};
+
+fn confirmed(events: &Recorder) -> Vec<Frame> {
+    events
+        .events
+        .lock()
+        .iter()
+        .filter_map(|e| match e {
+            Event::FrameConfirmed(confirmed) => Some(confirmed.frame),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn confirmation_trails_a_lagging_peer_by_the_prediction_window() {
+    let (a, a_events) = peer(17830, 1, 17840);
+    let (b, b_events) = peer(17840, 2, 17830);
+
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+
+    // B's input reaches A late, so A keeps running into the prediction barrier.
+    b.lock().set_network_conditions(60, 0);
+    let window = GGPO_MAX_PREDICTION_FRAMES as i32;
+    let mut played = 0;
+    let mut widest = 0;
+    let deadline = Instant::now() + Duration::from_secs(20);
+    while played < 120 {
+        assert!(Instant::now() < deadline, "A only played {} frames", played);
+        if advance(&a, 1) {
+            played += 1;
+        }
+        advance(&b, 2);
+        a.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+        b.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+
+        let last = confirmed(&a_events)
+            .last()
+            .map_or(-1, |frame| frame.as_i32());
+        let newest = played - 1;
+        assert!(last <= newest, "frame {} confirmed before it ran", last);
+        let lag = newest - last;
+        assert!(lag <= window, "confirmation fell {} frames behind", lag);
+        widest = widest.max(lag);
+    }
+    assert!(
+        widest >= window - 1,
+        "confirmation never trailed by the prediction window (widest gap {})",
+        widest
+    );
+
+    // Each frame once, in order.
+    let frames = confirmed(&a_events);
+    let expected: Vec<Frame> = (0..frames.len() as u32).map(Frame::new).collect();
+    assert_eq!(frames, expected);
+    assert!(frames.len() > 100, "only {} frames confirmed", frames.len());
+}
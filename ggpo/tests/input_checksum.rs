@@ -0,0 +1,77 @@
+mod common;
+
+use common::{advance, config, poll, recording_peer, synchronize_by, Peer, Recorder};
+use ggpo::{config::SessionConfig, game_input::Frame, ggpo::Event, player::PlayerHandle};
+use std::time::{Duration, Instant};
+
+// Checksums go out with every quality report.
+fn checked(port: u16) -> SessionConfig {
+    SessionConfig {
+        input_checksums: true,
+        ..config(port)
+    }
+}
+
+fn input_desyncs(events: &Recorder) -> Vec<(PlayerHandle, Frame)> {
+    events
+        .events
+        .lock()
+        .iter()
+        .filter_map(|e| match e {
+            Event::InputDesyncDetected(desync) => Some((desync.player, desync.frame)),
+            _ => None,
+        })
+        .collect()
+}
+
+/*
+ * Plays both sessions until `done` or `run_for` has passed.  Checksums only
+ * go out with quality reports, once a second, so this takes a while.
+ */
+fn play(a: &Peer, b: &Peer, a_events: &Recorder, run_for: Duration, done: impl Fn() -> bool) {
+    synchronize_by(&[a_events], || {
+        poll(a);
+        poll(b);
+    });
+
+    let deadline = Instant::now() + run_for;
+    while Instant::now() < deadline && !done() {
+        advance(a, 1);
+        advance(b, 2);
+        poll(a);
+        poll(b);
+    }
+}
+
+#[test]
+fn matching_inputs_raise_nothing() {
+    let (a, a_events) = recording_peer(checked(18380), 1, 18390);
+    let (b, b_events) = recording_peer(checked(18390), 2, 18380);
+
+    play(&a, &b, &a_events, Duration::from_millis(2500), || false);
+
+    assert!(input_desyncs(&a_events).is_empty());
+    assert!(input_desyncs(&b_events).is_empty());
+    assert!(!SessionConfig::default().input_checksums);
+}
+
+#[test]
+fn a_corrupted_input_is_caught() {
+    let (a, a_events) = recording_peer(checked(18400), 1, 18410);
+    let (b, b_events) = recording_peer(checked(18410), 2, 18400);
+    // A runs frame 30 with input B never sent.
+    a.lock().set_input_corruption(2, Frame::new(30));
+
+    play(&a, &b, &a_events, Duration::from_secs(10), || {
+        !input_desyncs(&a_events).is_empty() && !input_desyncs(&b_events).is_empty()
+    });
+
+    let a_desyncs = input_desyncs(&a_events);
+    assert_eq!(a_desyncs.len(), 1, "A saw {:?}", a_desyncs);
+    assert_eq!(a_desyncs[0].0, 2);
+    assert!(a_desyncs[0].1 >= Frame::new(30));
+    // B's checksum is the one that's right, but it can't tell that.
+    let b_desyncs = input_desyncs(&b_events);
+    assert_eq!(b_desyncs.len(), 1, "B saw {:?}", b_desyncs);
+    assert_eq!(b_desyncs[0].0, 1);
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use common::MockGame;
+use ggpo::{
+    backends::p2p::Peer2PeerBackend,
+    game_input::{Frame, GameInput, InputBuffer, NULL_FRAME},
+    ggpo::{GGPOError, Session},
+    network::input_codec::{self, InputCodecError, MAX_LOCAL_PLAYERS_PER_HOST},
+    player::{Player, PlayerHandle, PlayerType},
+};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+const INPUT_SIZE: usize = 2;
+const FRAMES: usize = 12;
+
+fn input(frame: Frame, buttons: u16) -> GameInput {
+    let mut bits: InputBuffer = Default::default();
+    bits[0][0] = buttons as u8;
+    bits[0][1] = (buttons >> 8) as u8;
+    GameInput::init(frame, Some(&bits), INPUT_SIZE)
+}
+
+// Two local players who mostly press the same buttons, with player 2 late on a few frames.
+fn two_player_frames() -> Vec<Vec<GameInput>> {
+    (0..FRAMES)
+        .map(|i| {
+            let frame = Frame::new(i as u32);
+            let shared = ((i as u16) * 0x1357) & 0x0fff;
+            let second = if i % 5 == 3 { shared ^ 0x0101 } else { shared };
+            vec![input(frame, shared), input(frame, second)]
+        })
+        .collect()
+}
+
+fn player_frames(frames: &[Vec<GameInput>], player: usize) -> Vec<Vec<GameInput>> {
+    frames.iter().map(|frame| vec![frame[player]]).collect()
+}
+
+#[test]
+fn two_local_players_encode_smaller_than_apart() {
+    let previous = vec![input(NULL_FRAME, 0); 2];
+    let frames = two_player_frames();
+
+    let (_, joint_bits) = input_codec::encode(&previous, &frames);
+    let (_, first_bits) = input_codec::encode(&previous[..1], &player_frames(&frames, 0));
+    let (_, second_bits) = input_codec::encode(&previous[1..], &player_frames(&frames, 1));
+    assert!(
+        joint_bits < first_bits + second_bits,
+        "joint {} bits, apart {} + {}",
+        joint_bits,
+        first_bits,
+        second_bits
+    );
+}
+
+#[test]
+fn two_local_players_decode_exactly() {
+    let previous = vec![input(NULL_FRAME, 0); 2];
+    let frames = two_player_frames();
+
+    let (bits, num_bits) = input_codec::encode(&previous, &frames);
+    let decoded = input_codec::decode(Frame::new(0), &previous, &bits, num_bits).unwrap();
+    assert_eq!(decoded, frames);
+}
+
+#[test]
+fn one_player_keeps_the_original_encoding() {
+    // A changed bit costs a 1, its value and a 10 bit index; each frame ends in a 0.
+    let previous = vec![input(NULL_FRAME, 0)];
+    let frames = vec![
+        vec![input(Frame::new(0), 0b101)],
+        vec![input(Frame::new(1), 0b101)],
+        vec![input(Frame::new(2), 0b100)],
+    ];
+
+    let (bits, num_bits) = input_codec::encode(&previous, &frames);
+    assert_eq!(num_bits, (2 * 12 + 1) + 1 + (12 + 1));
+    assert_eq!(
+        input_codec::decode(Frame::new(0), &previous, &bits, num_bits).unwrap(),
+        frames
+    );
+}
+
+#[test]
+fn bits_that_stop_short_are_refused() {
+    let previous = vec![input(NULL_FRAME, 0)];
+    let frames = vec![vec![input(Frame::new(0), 0b101)]];
+    let (bits, num_bits) = input_codec::encode(&previous, &frames);
+
+    // Cut off partway through a button index, or claiming more bits than were sent.
+    assert_eq!(
+        input_codec::decode(Frame::new(0), &previous, &bits, num_bits - 5),
+        Err(InputCodecError::Truncated)
+    );
+    assert_eq!(
+        input_codec::decode(Frame::new(0), &previous, &bits[..1], num_bits),
+        Err(InputCodecError::Truncated)
+    );
+}
+
+#[test]
+fn a_button_past_the_input_is_refused() {
+    // A change to bit 1023 of a two byte input, then the end of the frame.
+    let bits = [0xff, 0x0f];
+    let previous = vec![input(NULL_FRAME, 0)];
+    assert_eq!(
+        input_codec::decode(Frame::new(0), &previous, &bits, 13),
+        Err(InputCodecError::ButtonOutOfRange {
+            button: 1023,
+            size: INPUT_SIZE
+        })
+    );
+}
+
+#[test]
+fn refuses_local_players_past_the_limit() {
+    let num_players = MAX_LOCAL_PLAYERS_PER_HOST + 1;
+    let session = Peer2PeerBackend::new(
+        Arc::new(Mutex::new(MockGame::default())),
+        17940,
+        num_players,
+        INPUT_SIZE,
+        None,
+    )
+    .expect("session");
+
+    let mut session = session.lock();
+    let mut handle: PlayerHandle = 0;
+    for player_num in 1..=MAX_LOCAL_PLAYERS_PER_HOST {
+        session
+            .add_player(Player::new(PlayerType::Local, player_num), &mut handle)
+            .unwrap();
+    }
+    match session.add_player(Player::new(PlayerType::Local, num_players), &mut handle) {
+        Err(GGPOError::TooManyLocalPlayers { max }) => {
+            assert_eq!(max, MAX_LOCAL_PLAYERS_PER_HOST)
+        }
+        other => panic!("expected TooManyLocalPlayers, got {:?}", other),
+    }
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use common::{connect_status, sync_with, MockGame};
+use ggpo::{
+    game_input::{Frame, GameInput, InputBuffer, NULL_FRAME},
+    ggpo::GGPOError,
+    sync::SyncError,
+};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+const INPUT_SIZE: usize = 2;
+
+#[test]
+fn input_for_a_confirmed_frame_is_dropped() {
+    let status = connect_status(2);
+    let mut sync = sync_with(
+        Arc::new(Mutex::new(MockGame::default())),
+        &status,
+        INPUT_SIZE,
+    );
+
+    // Both players have frames 0 and 1 in.
+    for frame in 0..2 {
+        let mut local = GameInput::init(NULL_FRAME, None, INPUT_SIZE);
+        assert!(sync.add_local_input(0, &mut local).unwrap());
+        sync.add_remote_input(1, &GameInput::init(Frame::new(frame), None, INPUT_SIZE))
+            .unwrap();
+        let mut values: InputBuffer = Default::default();
+        sync.synchronize_inputs(&mut values).unwrap();
+        sync.increment_frame().unwrap();
+    }
+    sync.set_last_confirmed_frame(Frame::new(1)).unwrap();
+
+    // Back at frame 1, its input can no longer change.
+    sync.load_frame(Frame::new(1)).unwrap();
+    let mut late = GameInput::init(NULL_FRAME, None, INPUT_SIZE);
+    let err = sync.add_local_input(0, &mut late).unwrap_err();
+    assert!(matches!(err, SyncError::InputDropped(frame) if frame == Frame::new(1)));
+    assert!(matches!(GGPOError::from(err), GGPOError::InputDropped));
+}
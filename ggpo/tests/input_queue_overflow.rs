@@ -0,0 +1,116 @@
+mod common;
+
+use common::{connect_status, MockGame};
+use ggpo::{
+    game_input::{
+        Frame, GameInput, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS, NULL_FRAME,
+    },
+    ggpo::{GGPOError, GGPO_MAX_PREDICTION_FRAMES},
+    network::udp_msg::ConnectStatus,
+    sync::{Config, GGPOSync, QueueOverflow, SyncError},
+};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+const INPUT_SIZE: usize = 2;
+// Shorter than the prediction window, so the queue fills before the barrier.
+const QUEUE_LENGTH: usize = 4;
+
+fn sync_with_queue(
+    status: &[Arc<Mutex<ConnectStatus>>],
+    overflow: QueueOverflow,
+) -> GGPOSync<MockGame> {
+    let mut sync = GGPOSync::new(status);
+    let mut config = Config::new();
+    config.init(
+        Arc::new(Mutex::new(MockGame::default())),
+        GGPO_MAX_PREDICTION_FRAMES,
+        status.len(),
+        INPUT_SIZE,
+    );
+    config.input_queue_length = Some(QUEUE_LENGTH);
+    config.queue_overflow = overflow;
+    sync.init(config).expect("sync init");
+    sync
+}
+
+fn input(frame: Frame, value: u8) -> GameInput {
+    let mut bits = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    bits[0][0] = value;
+    GameInput::init(frame, Some(&bits), INPUT_SIZE)
+}
+
+#[test]
+fn queue_never_fills_while_frames_are_confirmed() {
+    let status = connect_status(2);
+    let mut sync = sync_with_queue(&status, QueueOverflow::Fail);
+
+    for frame in 0..50 {
+        assert!(sync.add_local_input(0, &mut input(NULL_FRAME, 1)).unwrap());
+        sync.add_remote_input(1, &input(Frame::new(frame), 2))
+            .unwrap();
+        let mut values: InputBuffer = Default::default();
+        sync.synchronize_inputs(&mut values).unwrap();
+        sync.increment_frame().unwrap();
+        sync.set_last_confirmed_frame(Frame::new(frame)).unwrap();
+        assert!(sync.input_queue_len(0) <= QUEUE_LENGTH);
+        assert!(sync.input_queue_len(1) <= QUEUE_LENGTH);
+    }
+}
+
+#[test]
+fn full_queue_blocks_local_input() {
+    let status = connect_status(2);
+    let mut sync = sync_with_queue(&status, QueueOverflow::Block);
+
+    for frame in 0..QUEUE_LENGTH as u8 {
+        assert!(sync
+            .add_local_input(0, &mut input(NULL_FRAME, frame))
+            .unwrap());
+        sync.increment_frame().unwrap();
+    }
+    assert_eq!(sync.input_queue_len(0), QUEUE_LENGTH);
+
+    // Nothing has been confirmed, so there's nowhere to put another frame.
+    assert!(!sync.add_local_input(0, &mut input(NULL_FRAME, 9)).unwrap());
+    assert_eq!(sync.input_queue_len(0), QUEUE_LENGTH);
+
+    // Confirming the oldest frames makes room again.
+    sync.set_last_confirmed_frame(Frame::new(1)).unwrap();
+    assert_eq!(sync.input_queue_len(0), QUEUE_LENGTH - 1);
+    assert!(sync.add_local_input(0, &mut input(NULL_FRAME, 9)).unwrap());
+}
+
+#[test]
+fn full_queue_can_fail_instead() {
+    let status = connect_status(2);
+    let mut sync = sync_with_queue(&status, QueueOverflow::Fail);
+
+    for frame in 0..QUEUE_LENGTH as u8 {
+        assert!(sync
+            .add_local_input(0, &mut input(NULL_FRAME, frame))
+            .unwrap());
+        sync.increment_frame().unwrap();
+    }
+    let err = sync
+        .add_local_input(0, &mut input(NULL_FRAME, 9))
+        .unwrap_err();
+    assert!(matches!(err, SyncError::InputQueueFull(0)));
+    assert!(matches!(GGPOError::from(err), GGPOError::GeneralFailure));
+}
+
+#[test]
+fn full_remote_queue_is_an_error() {
+    let status = connect_status(2);
+    let mut sync = sync_with_queue(&status, QueueOverflow::Block);
+
+    for frame in 0..QUEUE_LENGTH as u32 {
+        sync.add_remote_input(1, &input(Frame::new(frame), 2))
+            .unwrap();
+    }
+    let next = input(Frame::new(QUEUE_LENGTH as u32), 2);
+    assert!(matches!(
+        sync.add_remote_input(1, &next),
+        Err(SyncError::InputQueueFull(1))
+    ));
+}
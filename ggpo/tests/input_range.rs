@@ -0,0 +1,96 @@
+use ggpo::{
+    game_input::{Frame, GameInput, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS},
+    ggpo::GGPOError,
+    input_queue::InputQueue,
+    sync::SyncError,
+};
+
+const INPUT_SIZE: usize = 1;
+const QUEUE_LENGTH: usize = 8;
+
+fn input(frame: u32, value: u8) -> GameInput {
+    let mut bits = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    bits[0][0] = value;
+    GameInput::init(Frame::new(frame), Some(&bits), INPUT_SIZE)
+}
+
+// A queue holding confirmed input for frames 0 through `frames - 1`.
+fn queue_with(frames: u32) -> InputQueue {
+    let mut queue = InputQueue::with_length(0, INPUT_SIZE, QUEUE_LENGTH);
+    for frame in 0..frames {
+        assert!(queue.add_input(input(frame, b'a' + frame as u8)));
+    }
+    queue
+}
+
+#[test]
+fn returns_every_input_in_a_confirmed_range() {
+    let queue = queue_with(6);
+
+    let inputs = queue
+        .get_confirmed_range(Frame::new(1), Frame::new(5))
+        .unwrap();
+
+    let frames: Vec<_> = inputs.iter().map(|input| input.frame).collect();
+    assert_eq!(frames, (1..5).map(Frame::new).collect::<Vec<_>>());
+    let values: Vec<_> = inputs.iter().map(|input| input.bits[0][0]).collect();
+    assert_eq!(values, b"bcde");
+}
+
+#[test]
+fn an_empty_range_is_empty() {
+    let queue = queue_with(6);
+
+    assert!(queue
+        .get_confirmed_range(Frame::new(3), Frame::new(3))
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn fails_once_part_of_the_range_was_discarded() {
+    let mut queue = queue_with(6);
+    queue.discard_confirmed_frames(2);
+
+    let err = queue
+        .get_confirmed_range(Frame::new(1), Frame::new(5))
+        .unwrap_err();
+
+    assert!(matches!(err, SyncError::InputUnavailable(frame) if frame == Frame::new(1)));
+    assert!(matches!(GGPOError::from(err), GGPOError::GeneralFailure));
+    // What's left can still be read.
+    assert_eq!(
+        queue
+            .get_confirmed_range(Frame::new(3), Frame::new(6))
+            .unwrap()
+            .len(),
+        3
+    );
+}
+
+#[test]
+fn fails_for_frames_overwritten_in_the_ring() {
+    let mut queue = queue_with(QUEUE_LENGTH as u32);
+    queue.discard_confirmed_frames(QUEUE_LENGTH as u32 - 1);
+    for frame in QUEUE_LENGTH as u32..QUEUE_LENGTH as u32 + 2 {
+        assert!(queue.add_input(input(frame, b'z')));
+    }
+
+    let err = queue
+        .get_confirmed_range(Frame::new(0), Frame::new(2))
+        .unwrap_err();
+
+    assert!(matches!(err, SyncError::InputUnavailable(frame) if frame == Frame::new(0)));
+}
+
+#[test]
+fn fails_past_the_confirmed_frontier() {
+    let queue = queue_with(4);
+
+    let err = queue
+        .get_confirmed_range(Frame::new(2), Frame::new(6))
+        .unwrap_err();
+
+    assert!(matches!(err, SyncError::InputUnavailable(frame) if frame == Frame::new(4)));
+    assert!(matches!(GGPOError::from(err), GGPOError::GeneralFailure));
+}
@@ -0,0 +1,243 @@
+mod common;
+
+use bytes::Bytes;
+use common::{connect_status, sync_with, synchronize, MockGame, Peer, Recorder};
+use ggpo::{
+    backends::p2p::Peer2PeerBackend,
+    config::SessionConfig,
+    game_input::{
+        Frame, GameInput, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS, NULL_FRAME,
+    },
+    ggpo::{Event, GGPOError, Session},
+    network::{
+        udp::{Udp, UdpCallback},
+        udp_msg::{MsgEnum, MsgType, UdpMsg},
+    },
+    player::{Player, PlayerHandle, PlayerType},
+};
+use mio::Poll;
+use parking_lot::Mutex;
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+const INPUT_SIZE: usize = 16;
+
+fn stick_input(seed: u8) -> GameInput {
+    let mut bits = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    for (i, byte) in bits[0].iter_mut().enumerate().take(INPUT_SIZE) {
+        *byte = seed.wrapping_add(i as u8 * 17);
+    }
+    GameInput::init(NULL_FRAME, Some(&bits), INPUT_SIZE)
+}
+
+#[test]
+fn sixteen_byte_inputs_round_trip_through_the_queues() {
+    let status = connect_status(2);
+    let mut sync = sync_with(
+        Arc::new(Mutex::new(MockGame::default())),
+        &status,
+        INPUT_SIZE,
+    );
+
+    let local = stick_input(1);
+    let remote = stick_input(100);
+    assert!(sync.add_local_input(0, &mut local.clone()).unwrap());
+    let mut remote_frame = remote;
+    remote_frame.frame = Frame::new(0);
+    sync.add_remote_input(1, &remote_frame).unwrap();
+
+    let mut values: InputBuffer = Default::default();
+    sync.synchronize_inputs(&mut values).unwrap();
+    assert_eq!(values[0][..INPUT_SIZE], local.bits[0][..INPUT_SIZE]);
+    assert_eq!(values[1][..INPUT_SIZE], remote.bits[0][..INPUT_SIZE]);
+}
+
+struct Ignore;
+
+impl UdpCallback for Ignore {
+    fn on_msg(&mut self, _from: &SocketAddr, _msg: UdpMsg, _len: usize) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+fn bound_udp(port: u16) -> Udp<Ignore> {
+    let mut udp = Udp::new();
+    udp.init(
+        port,
+        Arc::new(Mutex::new(Poll::new().unwrap())),
+        Arc::new(Mutex::new(Ignore)),
+    )
+    .unwrap();
+    udp.start_send_task().unwrap();
+    udp
+}
+
+#[test]
+fn input_messages_carry_the_input_size_to_the_peer() {
+    let mut sender = bound_udp(17310);
+    let mut receiver = bound_udp(17320);
+    let receiver_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 17320);
+
+    let mut msg = UdpMsg::new(MsgType::Input);
+    if let MsgEnum::Input(input) = &mut msg.message {
+        input.input_size = INPUT_SIZE as u16;
+        input.num_bits = 8;
+        input.bits = Bytes::from_static(&[0xA5]);
+    }
+    sender.send_to(Arc::new(msg), &[receiver_addr]).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(1);
+    let received = loop {
+        if let Some((msg, _, _)) = receiver.recv_pending().unwrap().pop() {
+            break msg;
+        }
+        assert!(Instant::now() < deadline, "no packet arrived");
+        std::thread::sleep(Duration::from_millis(5));
+    };
+    match received.message {
+        MsgEnum::Input(input) => {
+            assert_eq!(input.input_size as usize, INPUT_SIZE);
+            assert_eq!(input.bits[0], 0xA5);
+        }
+        _ => panic!("expected an input message"),
+    }
+}
+
+#[test]
+fn session_rejects_inputs_of_the_wrong_size() {
+    let session = Peer2PeerBackend::new(
+        Arc::new(Mutex::new(MockGame::default())),
+        17330,
+        2,
+        INPUT_SIZE,
+        None,
+    )
+    .expect("session");
+    let values: InputBuffer = Default::default();
+
+    match session.lock().add_local_input(1, &values, 8) {
+        Err(GGPOError::InputSizeMismatch { expected, found }) => {
+            assert_eq!(expected, INPUT_SIZE);
+            assert_eq!(found, 8);
+        }
+        other => panic!("expected an input size error, got {:?}", other),
+    }
+
+    assert!(Peer2PeerBackend::new(
+        Arc::new(Mutex::new(MockGame::default())),
+        17340,
+        2,
+        GAMEINPUT_MAX_BYTES + 1,
+        None,
+    )
+    .is_err());
+}
+
+// A gamepad on player 1 and a keyboard and mouse on player 2.
+const PLAYER_INPUT_SIZES: [usize; 2] = [2, 8];
+
+// Each player holds the same input the whole match, filling all of their bytes.
+fn held_input(queue: usize) -> Vec<u8> {
+    (0..PLAYER_INPUT_SIZES[queue])
+        .map(|i| (queue as u8 + 1) * 16 + i as u8)
+        .collect()
+}
+
+fn mixed_peer(port: u16, local: usize, remote_port: u16) -> (Peer, Recorder) {
+    let recorder = Recorder::default();
+    let config = SessionConfig {
+        local_port: port,
+        ..Default::default()
+    };
+    let session = Peer2PeerBackend::from_config(config, Arc::new(Mutex::new(recorder.clone())))
+        .expect("session");
+    {
+        let mut session = session.lock();
+        let mut handle: PlayerHandle = 0;
+        // Local first, so the handshake already carries the local player's size.
+        let remote = 3 - local;
+        let remote_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), remote_port);
+        for (player_num, player_type) in [
+            (local, PlayerType::Local),
+            (remote, PlayerType::Remote(remote_addr)),
+        ]
+        .iter()
+        {
+            let player = Player::new(*player_type, *player_num)
+                .with_input_size(PLAYER_INPUT_SIZES[player_num - 1]);
+            session.add_player(player, &mut handle).unwrap();
+        }
+    }
+    (session, recorder)
+}
+
+// Runs one frame if the session will take another local input, returning what it synchronized.
+fn advance_mixed(session: &Peer, queue: usize) -> Option<InputBuffer> {
+    let mut session = session.lock();
+    let mut input = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    input[queue][..PLAYER_INPUT_SIZES[queue]].copy_from_slice(&held_input(queue));
+    match session.add_local_input(queue as u32 + 1, &input, PLAYER_INPUT_SIZES[queue]) {
+        Ok(()) => {}
+        Err(GGPOError::PredictionThreshold) => return None,
+        Err(e) => panic!("add_local_input failed: {}", e),
+    }
+    let mut values: InputBuffer = Default::default();
+    session.synchronize_input(&mut values, None).unwrap();
+    session.increment_frame().unwrap();
+    Some(values)
+}
+
+#[test]
+fn players_with_different_input_sizes_play_together() {
+    let (a, a_events) = mixed_peer(18290, 1, 18300);
+    let (b, b_events) = mixed_peer(18300, 2, 18290);
+
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+    assert!(!a_events.saw(|e| matches!(e, Event::InputSizeMismatch(_))));
+    assert!(!b_events.saw(|e| matches!(e, Event::InputSizeMismatch(_))));
+    for session in [&a, &b].iter() {
+        assert_eq!(
+            session.lock().input_sizes().unwrap(),
+            PLAYER_INPUT_SIZES.to_vec()
+        );
+    }
+
+    // Each player's own size is the only one their input is taken at.
+    let values: InputBuffer = Default::default();
+    match b.lock().add_local_input(2, &values, PLAYER_INPUT_SIZES[0]) {
+        Err(GGPOError::InputSizeMismatch { expected, found }) => {
+            assert_eq!(expected, PLAYER_INPUT_SIZES[1]);
+            assert_eq!(found, PLAYER_INPUT_SIZES[0]);
+        }
+        other => panic!("expected an input size error, got {:?}", other),
+    }
+
+    let mut frames = [0; 2];
+    let mut last = [None, None];
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while frames[0] < 60 || frames[1] < 60 {
+        assert!(Instant::now() < deadline, "stalled at {:?}", frames);
+        for (queue, session) in [&a, &b].iter().enumerate() {
+            if let Some(values) = advance_mixed(session, queue) {
+                frames[queue] += 1;
+                last[queue] = Some(values);
+            }
+        }
+        a.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+        b.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+    }
+
+    // By now both ends have the other's input, and every byte of it.
+    for values in last.iter() {
+        let values = values.unwrap();
+        for queue in 0..2 {
+            assert_eq!(
+                values[queue][..PLAYER_INPUT_SIZES[queue]],
+                held_input(queue)[..]
+            );
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use bytes::Bytes;
+use ggpo::{
+    bitvector,
+    game_input::{Frame, GameInput, InputBuffer},
+    input_queue::InputQueue,
+    network::{
+        udp::UdpCallback,
+        udp_msg::{MsgEnum, MsgType, UdpMsg},
+        udp_proto::{Event, UdpProtocol},
+    },
+};
+use std::net::SocketAddr;
+
+// One byte per frame, changing every frame so each one carries some bits.
+const STREAM: [u8; 5] = [0x01, 0x03, 0x02, 0x06, 0x04];
+
+struct Ignore;
+
+impl UdpCallback for Ignore {
+    fn on_msg(&mut self, _from: &SocketAddr, _msg: UdpMsg, _len: usize) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+fn input(frame: u32) -> GameInput {
+    let mut bits: InputBuffer = Default::default();
+    bits[0][0] = STREAM[frame as usize];
+    GameInput::init(Frame::new(frame), Some(&bits), 1)
+}
+
+// Codes frames `start..=end` the way a sender does: against the frame before.
+fn input_msg(start: u32, end: u32) -> UdpMsg {
+    let mut bits = [0u8; 256];
+    let mut offset = 0;
+    let mut last = if start == 0 {
+        GameInput::new()
+    } else {
+        input(start - 1)
+    };
+    for frame in start..=end {
+        let current = input(frame);
+        for i in 0..8 {
+            if current.value(i) != last.value(i) {
+                bitvector::set_bit(&mut bits, &mut offset);
+                if current.value(i) {
+                    bitvector::set_bit(&mut bits, &mut offset);
+                } else {
+                    bitvector::clear_bit(&mut bits, &mut offset);
+                }
+                bitvector::write_nibblet(&mut bits, i, &mut offset);
+            }
+        }
+        bitvector::clear_bit(&mut bits, &mut offset);
+        last = current;
+    }
+
+    let mut msg = UdpMsg::new(MsgType::Input);
+    if let MsgEnum::Input(input) = &mut msg.message {
+        input.start_frame = Frame::new(start);
+        input.input_size = 1;
+        input.num_bits = offset as u16;
+        input.bits = Bytes::copy_from_slice(&bits[..(offset + 7) / 8]);
+    }
+    msg
+}
+
+fn receiver() -> UdpProtocol<Ignore> {
+    let mut endpoint = UdpProtocol::new();
+    endpoint.set_input_size(1);
+    endpoint
+}
+
+fn received(endpoint: &mut UdpProtocol<Ignore>) -> Vec<GameInput> {
+    let mut inputs = Vec::new();
+    let mut event = Event::Unknown;
+    while endpoint.get_event(&mut event) {
+        if let Event::Input(input) = &event {
+            inputs.push(*input);
+        }
+    }
+    inputs
+}
+
+// Feeds what arrived to an input queue, which insists on contiguous frames.
+fn assert_contiguous(inputs: &[GameInput], last: u32) {
+    let frames: Vec<Frame> = inputs.iter().map(|input| input.frame).collect();
+    let expected: Vec<Frame> = (0..=last).map(Frame::new).collect();
+    assert_eq!(frames, expected);
+
+    let mut queue = InputQueue::init(0, 1);
+    for input in inputs {
+        queue.add_input(*input);
+    }
+    assert_eq!(queue.get_last_confirmed_frame(), Frame::new(last));
+    for frame in 0..=last {
+        let mut confirmed = GameInput::new();
+        assert!(queue.get_confirmed_input(Frame::new(frame), &mut confirmed));
+        assert_eq!(
+            confirmed.bits[0][0], STREAM[frame as usize],
+            "frame {}",
+            frame
+        );
+    }
+}
+
+#[test]
+fn a_duplicate_input_message_changes_nothing() {
+    let mut endpoint = receiver();
+    endpoint.on_input(&input_msg(0, 1)).unwrap();
+    endpoint.on_input(&input_msg(0, 1)).unwrap();
+    let mut inputs = received(&mut endpoint);
+    assert_contiguous(&inputs, 1);
+
+    // A retransmit overlapping what we have only adds the frame past it.
+    endpoint.on_input(&input_msg(0, 2)).unwrap();
+    inputs.extend(received(&mut endpoint));
+    assert_contiguous(&inputs, 2);
+}
+
+#[test]
+fn reordered_frames_are_applied_in_order() {
+    let mut endpoint = receiver();
+    endpoint.on_input(&input_msg(0, 1)).unwrap();
+    let mut inputs = received(&mut endpoint);
+
+    for &frame in [3, 2, 4, 3].iter() {
+        endpoint.on_input(&input_msg(frame, frame)).unwrap();
+    }
+    inputs.extend(received(&mut endpoint));
+    // 3 and then 4 came early and were dropped; the late 3 filled the gap.
+    assert_contiguous(&inputs, 3);
+
+    endpoint.on_input(&input_msg(3, 4)).unwrap();
+    inputs.extend(received(&mut endpoint));
+    assert_contiguous(&inputs, 4);
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use common::{peer, synchronize, Peer};
+use ggpo::{
+    game_input::{Frame, InputBuffer, NULL_FRAME},
+    ggpo::Session,
+    player::PlayerHandle,
+};
+use std::time::{Duration, Instant};
+
+fn run(session: &Peer, local: PlayerHandle, frames: u32) {
+    for _ in 0..frames {
+        let mut session = session.lock();
+        let mut values: InputBuffer = Default::default();
+        session.add_local_input(local, &values, 1).unwrap();
+        session.synchronize_input(&mut values, None).unwrap();
+        session.increment_frame().unwrap();
+    }
+}
+
+#[test]
+fn each_player_is_confirmed_as_far_as_their_input_has_come() {
+    let (a, a_events) = peer(19060, 1, 19070);
+    let (b, b_events) = peer(19070, 2, 19060);
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+    assert_eq!(a.lock().last_confirmed_frame(1).unwrap(), NULL_FRAME);
+    assert_eq!(a.lock().last_confirmed_frame(2).unwrap(), NULL_FRAME);
+
+    // B lags four frames behind A.
+    run(&a, 1, 6);
+    run(&b, 2, 2);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while a.lock().last_confirmed_frame(2).unwrap() != Frame::new(1)
+        || b.lock().last_confirmed_frame(1).unwrap() != Frame::new(5)
+    {
+        assert!(Instant::now() < deadline, "input never arrived");
+        a.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+        b.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+    }
+    assert_eq!(a.lock().last_confirmed_frame(1).unwrap(), Frame::new(5));
+    assert_eq!(b.lock().last_confirmed_frame(2).unwrap(), Frame::new(1));
+    // Predicting B past frame 1 doesn't confirm anything.
+    run(&a, 1, 1);
+    assert_eq!(a.lock().last_confirmed_frame(2).unwrap(), Frame::new(1));
+}
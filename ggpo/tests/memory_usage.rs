@@ -0,0 +1,144 @@
+mod common;
+
+use bytes::Bytes;
+use common::{config, host_with, localhost, poll, synchronize_by, Peer, Recorder};
+use ggpo::{
+    backends::{p2p::SPECTATOR_LAG_WINDOW, spectator::SpectatorBackend},
+    game_input::{Frame, InputBuffer},
+    ggpo::{Event, GGPOError, GGPOSessionCallbacks, SavedState, Session},
+};
+use parking_lot::Mutex;
+use std::{sync::Arc, time::Duration};
+
+const FRAMES: u32 = 3000;
+const STATE_SIZE: usize = 64;
+
+// Saves a state of `STATE_SIZE` bytes every frame, so saved states show up in the numbers.
+#[derive(Debug, Default, Clone)]
+struct Saver {
+    events: Recorder,
+}
+
+impl GGPOSessionCallbacks for Saver {
+    fn save_game_state(&mut self, _frame: Frame) -> Result<SavedState, GGPOError> {
+        Ok(SavedState {
+            data: Bytes::from(vec![0; STATE_SIZE]),
+            checksum: None,
+        })
+    }
+
+    fn load_game_state(&mut self, _buffer: &Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn log_game_state(&mut self, _filename: String, _buffer: Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn advance_frame(&mut self, _flags: i32) -> bool {
+        true
+    }
+
+    fn on_event(&mut self, info: &Event) {
+        self.events.on_event(info);
+    }
+}
+
+// A one player host, optionally with a spectator at `spectator_port`.
+fn host(port: u16, spectator_port: Option<u16>) -> (Peer<Saver>, Saver) {
+    let saver = Saver::default();
+    let spectator_ports: Vec<u16> = spectator_port.into_iter().collect();
+    let host = host_with(
+        config(port),
+        Arc::new(Mutex::new(saver.clone())),
+        &spectator_ports,
+    );
+    (host, saver)
+}
+
+fn advance(host: &Peer<Saver>, frame: u32) {
+    let mut host = host.lock();
+    let mut values: InputBuffer = Default::default();
+    values[0][0] = frame as u8;
+    host.add_local_input(1, &values, 1).unwrap();
+    host.synchronize_input(&mut values, None).unwrap();
+    host.increment_frame().unwrap();
+    host.do_poll(Some(Duration::from_millis(0))).unwrap();
+}
+
+#[test]
+fn memory_stays_bounded_over_a_long_match() {
+    let (host, saver) = host(18210, None);
+    synchronize_by(&[&saver.events], || poll(&host));
+
+    let mut peak = 0;
+    for frame in 0..FRAMES {
+        advance(&host, frame);
+        let usage = host.lock().memory_usage().unwrap();
+        peak = peak.max(usage.bytes());
+        // Confirmed input is let go of within a couple of frames.
+        assert!(usage.input_frames <= 2, "{:?} at frame {}", usage, frame);
+        if let Some(oldest) = usage.oldest_frame {
+            assert!(
+                oldest.as_i32() + 2 >= frame as i32,
+                "{:?} at frame {}",
+                usage,
+                frame
+            );
+        }
+    }
+
+    // The saved state ring filled up early and stayed that size.
+    let usage = host.lock().memory_usage().unwrap();
+    assert!(usage.saved_states > 0);
+    assert_eq!(usage.saved_state_bytes, usage.saved_states * STATE_SIZE);
+    assert!(peak <= usage.bytes() + 2 * std::mem::size_of::<ggpo::game_input::GameInput>());
+}
+
+#[test]
+fn a_lagging_spectator_is_dropped_before_it_pins_old_input() {
+    let (host, saver) = host(18190, Some(18200));
+    let viewer = Recorder::default();
+    let spectator = SpectatorBackend::new(
+        Arc::new(Mutex::new(viewer.clone())),
+        18200,
+        1,
+        1,
+        localhost(18190),
+    )
+    .unwrap();
+
+    synchronize_by(&[&saver.events, &viewer], || {
+        poll(&host);
+        poll(&spectator);
+    });
+
+    // The spectator stops listening, so none of what follows gets acked.
+    let mut dropped_at = None;
+    for frame in 0..FRAMES {
+        advance(&host, frame);
+        let usage = host.lock().memory_usage().unwrap();
+        assert!(
+            usage.unacked_input_frames <= SPECTATOR_LAG_WINDOW + 1,
+            "{:?} at frame {}",
+            usage,
+            frame
+        );
+        if dropped_at.is_none()
+            && saver
+                .events
+                .saw(|e| matches!(e, Event::DisconnectedFromPeer(_)))
+        {
+            dropped_at = Some(frame);
+        }
+    }
+
+    // It was dropped once it fell a window behind, and nothing old is held since.
+    let dropped_at = dropped_at.expect("the spectator was never dropped");
+    assert!(dropped_at as usize <= SPECTATOR_LAG_WINDOW + 2);
+    let usage = host.lock().memory_usage().unwrap();
+    assert_eq!(usage.unacked_input_frames, 0);
+    assert!(usage
+        .oldest_frame
+        .map_or(true, |oldest| oldest.as_i32() + 2 >= FRAMES as i32));
+}
@@ -0,0 +1,247 @@
+mod common;
+
+use bytes::Bytes;
+use common::{
+    config, connect_status, localhost, peer_at, sync_with, synchronize, MockGame, Peer, Recorder,
+};
+use ggpo::{
+    config::SessionConfig,
+    game_input::{
+        Frame, GameInput, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS, NULL_FRAME,
+    },
+    ggpo::{
+        Event, GGPOError, GGPOSessionCallbacks, SavedState, Session, GGPO_MAX_PREDICTION_FRAMES,
+    },
+    player::PlayerHandle,
+};
+use parking_lot::Mutex;
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+const NUM_PLAYERS: usize = 4;
+const INPUT_SIZE: usize = 2;
+
+fn player_input(value: u8) -> GameInput {
+    let mut bits = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    bits[0][0] = value;
+    GameInput::init(NULL_FRAME, Some(&bits), INPUT_SIZE)
+}
+
+#[test]
+fn four_players_synchronize_in_handle_order() {
+    let status = connect_status(NUM_PLAYERS);
+    let mut sync = sync_with(
+        Arc::new(Mutex::new(MockGame::default())),
+        &status,
+        INPUT_SIZE,
+    );
+
+    for queue in 0..NUM_PLAYERS {
+        let mut input = player_input(queue as u8 + 1);
+        assert!(sync.add_local_input(queue as u32, &mut input).unwrap());
+    }
+
+    let mut values: InputBuffer = Default::default();
+    let flags = sync.synchronize_inputs(&mut values).unwrap();
+    assert_eq!(flags, 0);
+    for (queue, row) in values.iter().enumerate().take(NUM_PLAYERS) {
+        assert_eq!(row[0], queue as u8 + 1);
+    }
+}
+
+#[test]
+fn disconnecting_one_player_sets_only_its_bit() {
+    let status = connect_status(NUM_PLAYERS);
+    let mut sync = sync_with(
+        Arc::new(Mutex::new(MockGame::default())),
+        &status,
+        INPUT_SIZE,
+    );
+
+    for queue in 0..NUM_PLAYERS {
+        let mut input = player_input(queue as u8 + 1);
+        sync.add_local_input(queue as u32, &mut input).unwrap();
+    }
+    status[3].lock().disconnected = true;
+
+    let mut values: InputBuffer = Default::default();
+    let flags = sync.synchronize_inputs(&mut values).unwrap();
+    assert_eq!(flags, 1 << 3);
+    assert_eq!(values[2][0], 3);
+}
+
+// Ports the four loopback sessions bind, by player number.
+fn port(player: usize) -> u16 {
+    19400 + 10 * player as u16
+}
+
+/*
+ * A game whose whole state is its frame counter, so it saves and loads
+ * properly across a rollback.  Keeps the inputs the test loop read for each
+ * frame, less the ones a rollback ran again: those were predictions, and
+ * the frames were rerun with inputs the loop never got to see.
+ */
+#[derive(Debug, Default, Clone)]
+struct Ledger {
+    frame: u32,
+    events: Recorder,
+    inputs: Arc<Mutex<BTreeMap<u32, Vec<u8>>>>,
+}
+
+impl GGPOSessionCallbacks for Ledger {
+    fn save_game_state(&mut self, _frame: Frame) -> Result<SavedState, GGPOError> {
+        Ok(SavedState {
+            data: Bytes::copy_from_slice(&self.frame.to_le_bytes()),
+            checksum: None,
+        })
+    }
+
+    fn load_game_state(&mut self, buffer: &Bytes, _length: usize) -> bool {
+        let mut frame = [0; 4];
+        frame.copy_from_slice(&buffer[..4]);
+        self.frame = u32::from_le_bytes(frame);
+        true
+    }
+
+    fn log_game_state(&mut self, _filename: String, _buffer: Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn advance_frame(&mut self, _flags: i32) -> bool {
+        self.frame += 1;
+        true
+    }
+
+    fn on_event(&mut self, info: &Event) {
+        self.events.on_event(info);
+    }
+
+    fn on_rollback(&mut self, from_frame: Frame, to_frame: Frame, _resimulated: u32) {
+        let (to, from) = (to_frame.as_i32() as u32, from_frame.as_i32() as u32);
+        let mut inputs = self.inputs.lock();
+        for frame in to..from {
+            inputs.remove(&frame);
+        }
+    }
+}
+
+// What `player` presses on `frame`: holds for a few frames, so most predictions are right.
+fn pressed(player: usize, frame: u32) -> u8 {
+    (frame / 6) as u8 * 16 + player as u8
+}
+
+// A four player session, with `local` playing here and the rest on their own ports.
+fn player(local: usize) -> (Peer<Ledger>, Ledger) {
+    let config = SessionConfig {
+        num_players: NUM_PLAYERS,
+        input_size: INPUT_SIZE,
+        input_checksums: true,
+        ..config(port(local))
+    };
+    let remotes: Vec<SocketAddr> = (1..=NUM_PLAYERS)
+        .filter(|player_num| *player_num != local)
+        .map(|player_num| localhost(port(player_num)))
+        .collect();
+    let game = Ledger::default();
+    let session = peer_at(config, Arc::new(Mutex::new(game.clone())), local, &remotes);
+    (session, game)
+}
+
+// Runs `local`'s next frame, unless the prediction barrier holds it back.
+fn play(session: &Peer<Ledger>, game: &Ledger, local: usize, frame: u32) -> bool {
+    let mut session = session.lock();
+    let mut values: InputBuffer = Default::default();
+    values[local - 1][0] = pressed(local, frame);
+    match session.add_local_input(local as PlayerHandle, &values, INPUT_SIZE) {
+        Ok(()) => {}
+        Err(GGPOError::PredictionThreshold) => return false,
+        Err(e) => panic!("add_local_input failed: {}", e),
+    }
+    session.synchronize_input(&mut values, None).unwrap();
+    let read = values[..NUM_PLAYERS].iter().map(|row| row[0]).collect();
+    game.inputs.lock().insert(frame, read);
+    session.increment_frame().unwrap();
+    true
+}
+
+fn last_confirmed(events: &Recorder) -> Option<Frame> {
+    events.events.lock().iter().rev().find_map(|e| match e {
+        Event::FrameConfirmed(confirmed) => Some(confirmed.frame),
+        _ => None,
+    })
+}
+
+#[test]
+fn four_peers_on_loopback_confirm_the_same_inputs() {
+    const FRAMES: u32 = 5 * GGPO_MAX_PREDICTION_FRAMES;
+    let players: Vec<(Peer<Ledger>, Ledger)> = (1..=NUM_PLAYERS).map(player).collect();
+
+    let sessions: Vec<_> = players
+        .iter()
+        .map(|(session, game)| (session, &game.events))
+        .collect();
+    synchronize(&sessions);
+
+    let mut frames = [0; NUM_PLAYERS];
+    let deadline = Instant::now() + Duration::from_secs(20);
+    while players
+        .iter()
+        .any(|(_, game)| last_confirmed(&game.events) < Some(Frame::new(FRAMES - 1)))
+    {
+        assert!(Instant::now() < deadline, "got to frames {:?}", frames);
+        for (i, (session, game)) in players.iter().enumerate() {
+            if frames[i] < FRAMES && play(session, game, i + 1, frames[i]) {
+                frames[i] += 1;
+            }
+        }
+        for (session, _) in players.iter() {
+            session
+                .lock()
+                .do_poll(Some(Duration::from_millis(1)))
+                .unwrap();
+        }
+    }
+
+    let checksums: Vec<u32> = players
+        .iter()
+        .map(|(session, _)| {
+            session
+                .lock()
+                .export_state(Frame::new(FRAMES - 1))
+                .unwrap()
+                .1
+        })
+        .collect();
+    for (i, (_, game)) in players.iter().enumerate() {
+        assert!(
+            !game
+                .events
+                .saw(|e| matches!(e, Event::InputDesyncDetected(_) | Event::DesyncDetected(_))),
+            "player {} saw a desync",
+            i + 1
+        );
+        assert_eq!(checksums[i], checksums[0], "player {}'s checksum", i + 1);
+
+        // Every frame the loop read before it was confirmed, it read right.
+        let inputs = game.inputs.lock();
+        assert!(
+            inputs.len() as u32 > FRAMES / 2,
+            "only {} frames held",
+            inputs.len()
+        );
+        for (&frame, read) in inputs.iter() {
+            let expected: Vec<u8> = (1..=NUM_PLAYERS).map(|p| pressed(p, frame)).collect();
+            assert_eq!(
+                read,
+                &expected,
+                "player {}'s inputs for frame {}",
+                i + 1,
+                frame
+            );
+        }
+    }
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use bytes::Bytes;
+use common::{connect_status, sync_with};
+use ggpo::{
+    game_input::{
+        Frame, GameInput, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS, NULL_FRAME,
+    },
+    ggpo::{Event, GGPOError, GGPOSessionCallbacks, SavedState},
+};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+const INPUT_SIZE: usize = 1;
+
+// Counts frames like `MockGame`, and keeps every rollback it's told about.
+#[derive(Debug, Default, Clone)]
+struct Blender {
+    frame: u32,
+    rollbacks: Vec<(Frame, Frame, u32)>,
+}
+
+impl GGPOSessionCallbacks for Blender {
+    fn save_game_state(&mut self, _frame: Frame) -> Result<SavedState, GGPOError> {
+        Ok(SavedState {
+            data: Bytes::copy_from_slice(&self.frame.to_le_bytes()),
+            checksum: None,
+        })
+    }
+
+    fn load_game_state(&mut self, buffer: &Bytes, _length: usize) -> bool {
+        let mut frame = [0; 4];
+        frame.copy_from_slice(&buffer[..4]);
+        self.frame = u32::from_le_bytes(frame);
+        true
+    }
+
+    fn log_game_state(&mut self, _filename: String, _buffer: Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn advance_frame(&mut self, _flags: i32) -> bool {
+        self.frame += 1;
+        true
+    }
+
+    fn on_event(&mut self, _info: &Event) {}
+
+    fn on_rollback(&mut self, from_frame: Frame, to_frame: Frame, resimulated: u32) {
+        self.rollbacks.push((from_frame, to_frame, resimulated));
+    }
+}
+
+#[test]
+fn rollback_reports_the_frames_it_ran_again() {
+    let game = Arc::new(Mutex::new(Blender::default()));
+    let status = connect_status(2);
+    let mut sync = sync_with(game.clone(), &status, INPUT_SIZE);
+
+    // Run five frames predicting the remote player idles...
+    for _ in 0..5 {
+        let mut local = GameInput::init(NULL_FRAME, None, INPUT_SIZE);
+        assert!(sync.add_local_input(0, &mut local).unwrap());
+        let mut values: InputBuffer = Default::default();
+        sync.synchronize_inputs(&mut values).unwrap();
+        game.lock().frame += 1;
+        sync.increment_frame().unwrap();
+    }
+    sync.check_simulation().unwrap();
+    assert!(game.lock().rollbacks.is_empty());
+
+    // ...then learn they pressed something on frame 2.
+    let mut bits = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    bits[0][0] = 7;
+    for frame in 0..2 {
+        sync.add_remote_input(1, &GameInput::init(Frame::new(frame), None, INPUT_SIZE))
+            .unwrap();
+    }
+    sync.add_remote_input(1, &GameInput::init(Frame::new(2), Some(&bits), INPUT_SIZE))
+        .unwrap();
+    sync.check_simulation().unwrap();
+
+    let game = game.lock();
+    assert_eq!(game.rollbacks, vec![(Frame::new(5), Frame::new(2), 3)]);
+    // The game ran frames 2, 3 and 4 again and is back where it was.
+    assert_eq!(game.frame, 5);
+    assert_eq!(sync.get_frame_count(), 5);
+}
+
+#[test]
+fn late_input_for_several_frames_is_one_rollback() {
+    let game = Arc::new(Mutex::new(Blender::default()));
+    let status = connect_status(4);
+    let mut sync = sync_with(game.clone(), &status, INPUT_SIZE);
+
+    for _ in 0..6 {
+        let mut local = GameInput::init(NULL_FRAME, None, INPUT_SIZE);
+        assert!(sync.add_local_input(0, &mut local).unwrap());
+        let mut values: InputBuffer = Default::default();
+        sync.synchronize_inputs(&mut values).unwrap();
+        game.lock().frame += 1;
+        sync.increment_frame().unwrap();
+    }
+    sync.check_simulation().unwrap();
+
+    // Everything the three remote players did comes in at once, each of
+    // them pressing something on a different frame.
+    let mut bits = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    bits[0][0] = 7;
+    for (queue, pressed) in [(1, 3), (2, 1), (3, 4)].iter() {
+        for frame in 0..5 {
+            let input = if frame == *pressed {
+                GameInput::init(Frame::new(frame), Some(&bits), INPUT_SIZE)
+            } else {
+                GameInput::init(Frame::new(frame), None, INPUT_SIZE)
+            };
+            sync.add_remote_input(*queue, &input).unwrap();
+        }
+    }
+    sync.check_simulation().unwrap();
+
+    let game = game.lock();
+    assert_eq!(game.rollbacks, vec![(Frame::new(6), Frame::new(1), 5)]);
+    assert_eq!(game.frame, 6);
+}
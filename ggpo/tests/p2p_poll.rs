@@ -0,0 +1,56 @@
+mod common;
+
+use common::{advance, localhost, peer, synchronize, MockGame, Peer};
+use ggpo::{backends::p2p::Peer2PeerBackend, game_input::Frame, ggpo::Session};
+use parking_lot::Mutex;
+use std::{
+    net::UdpSocket,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+#[test]
+fn do_poll_returns_after_timeout_on_idle_socket() {
+    let session =
+        Peer2PeerBackend::new(Arc::new(Mutex::new(MockGame::default())), 17100, 2, 2, None)
+            .expect("session");
+
+    let timeout = Duration::from_millis(50);
+    let start = Instant::now();
+    session.lock().do_poll(Some(timeout)).unwrap();
+    let elapsed = start.elapsed();
+
+    assert!(elapsed >= timeout);
+    assert!(elapsed < timeout * 10, "do_poll hung for {:?}", elapsed);
+}
+
+// The last frame A has B's real input for.
+fn last_input_from_b(a: &Peer) -> Frame {
+    a.lock().debug_dump().unwrap().players[1].last_input_frame
+}
+
+#[test]
+fn stray_datagrams_are_dropped_without_stopping_the_poll() {
+    let (a, a_events) = peer(19530, 1, 19540);
+    let (b, b_events) = peer(19540, 2, 19530);
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+
+    // Neither compressed nor a message, and a compressed frame cut short.
+    let stranger = UdpSocket::bind(localhost(0)).unwrap();
+    let garbage: &[&[u8]] = &[b"not a ggpo packet", &[0x28, 0xb5, 0x2f, 0xfd, 0x00]];
+    let heard = last_input_from_b(&a);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while last_input_from_b(&a) == heard {
+        assert!(Instant::now() < deadline, "A stopped hearing from B");
+        for datagram in garbage {
+            stranger.send_to(datagram, localhost(19530)).unwrap();
+        }
+        advance(&b, 2);
+        b.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+        for datagram in garbage {
+            stranger.send_to(datagram, localhost(19530)).unwrap();
+        }
+        a.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+        a.lock().poll_once(Duration::from_millis(1)).unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use common::{peer, synchronize, Peer};
+use ggpo::{
+    game_input::{InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS},
+    ggpo::{GGPOError, Session},
+    player::PlayerHandle,
+};
+use std::time::{Duration, Instant};
+
+// Two peers playing, each counting the frames it has run.
+struct Match {
+    a: Peer,
+    b: Peer,
+    a_frames: u32,
+    b_frames: u32,
+}
+
+impl Match {
+    fn new(a_port: u16, b_port: u16) -> Self {
+        let (a, a_events) = peer(a_port, 1, b_port);
+        let (b, b_events) = peer(b_port, 2, a_port);
+        synchronize(&[(&a, &a_events), (&b, &b_events)]);
+        Self {
+            a,
+            b,
+            a_frames: 0,
+            b_frames: 0,
+        }
+    }
+
+    fn advance(session: &Peer, handle: PlayerHandle) -> bool {
+        let mut session = session.lock();
+        let local = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+        match session.add_local_input(handle, &local, 1) {
+            Ok(()) => {}
+            Err(GGPOError::PredictionThreshold) | Err(GGPOError::Paused) => return false,
+            Err(e) => panic!("add_local_input failed: {}", e),
+        }
+        let mut values: InputBuffer = Default::default();
+        session.synchronize_input(&mut values, None).unwrap();
+        session.increment_frame().unwrap();
+        true
+    }
+
+    // Plays for `duration`, a frame every couple of milliseconds.
+    fn play(&mut self, duration: Duration) {
+        let until = Instant::now() + duration;
+        while Instant::now() < until {
+            if Self::advance(&self.a, 1) {
+                self.a_frames += 1;
+            }
+            if Self::advance(&self.b, 2) {
+                self.b_frames += 1;
+            }
+            self.a
+                .lock()
+                .do_poll(Some(Duration::from_millis(1)))
+                .unwrap();
+            self.b
+                .lock()
+                .do_poll(Some(Duration::from_millis(1)))
+                .unwrap();
+        }
+    }
+}
+
+#[test]
+fn both_peers_halt_at_the_same_frame_and_resume_together() {
+    let mut game = Match::new(17650, 17660);
+    game.play(Duration::from_millis(100));
+
+    game.a.lock().pause().unwrap();
+    game.play(Duration::from_millis(300));
+    let paused_at = (game.a_frames, game.b_frames);
+    assert_eq!(
+        paused_at.0, paused_at.1,
+        "peers stopped at different frames"
+    );
+
+    // Long enough for a disconnect notification if nothing were being sent.
+    game.play(Duration::from_millis(1000));
+    assert_eq!((game.a_frames, game.b_frames), paused_at);
+
+    // Either side can resume.
+    game.b.lock().resume().unwrap();
+    game.play(Duration::from_millis(100));
+    assert!(game.a_frames > paused_at.0);
+    assert!(game.b_frames > paused_at.1);
+}
+
+#[test]
+fn resuming_before_the_peer_acks_leaves_nobody_paused() {
+    let mut game = Match::new(17670, 17680);
+    game.play(Duration::from_millis(100));
+
+    // Resume before the pause has even been sent on its way.
+    {
+        let mut a = game.a.lock();
+        a.pause().unwrap();
+        a.resume().unwrap();
+    }
+    game.play(Duration::from_millis(300));
+
+    let before = (game.a_frames, game.b_frames);
+    game.play(Duration::from_millis(100));
+    assert!(game.a_frames > before.0, "A is still paused");
+    assert!(game.b_frames > before.1, "B is still paused");
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use common::{config, localhost, recording_peer_at, synchronize, Recorder};
+use ggpo::{
+    backends::p2p::Peer2PeerBackend,
+    config::SessionConfig,
+    ggpo::{Event, GGPOError, Session},
+    player::{Player, PlayerHandle, PlayerType},
+};
+use parking_lot::Mutex;
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+};
+
+// On a documentation-only network: sends to it fail from a loopback socket.
+fn unreachable() -> SocketAddr {
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 18260)
+}
+
+#[test]
+fn addresses_nobody_could_be_at_are_refused() {
+    let session =
+        Peer2PeerBackend::new(Arc::new(Mutex::new(Recorder::default())), 18270, 2, 1, None)
+            .unwrap();
+    let mut session = session.lock();
+    let mut handle: PlayerHandle = 0;
+
+    let bad = [
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 7000),
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0),
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::new(224, 0, 0, 1)), 7000),
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::BROADCAST), 7000),
+        SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 7000),
+    ];
+    for address in bad.iter() {
+        assert!(
+            matches!(
+                session.add_player(Player::new(PlayerType::Remote(*address), 2), &mut handle),
+                Err(GGPOError::InvalidRequest)
+            ),
+            "{} was accepted as a player",
+            address
+        );
+        assert!(
+            matches!(
+                session.add_player(Player::new(PlayerType::Spectator(*address), 3), &mut handle),
+                Err(GGPOError::InvalidRequest)
+            ),
+            "{} was accepted as a spectator",
+            address
+        );
+    }
+
+    // A real address still goes through.
+    session
+        .add_player(
+            Player::new(PlayerType::Remote(localhost(18280)), 2),
+            &mut handle,
+        )
+        .unwrap();
+}
+
+#[test]
+fn an_unreachable_peer_is_dropped_without_stopping_the_others() {
+    // Three players: the local one, the other peer, and one we can't send to.
+    let three = |port| SessionConfig {
+        num_players: 3,
+        ..config(port)
+    };
+    let (a, a_events) = recording_peer_at(three(18240), 1, &[localhost(18250), unreachable()]);
+    let (b, b_events) = recording_peer_at(three(18250), 2, &[localhost(18240), unreachable()]);
+
+    let dropped = |e: &Event| matches!(e, Event::DisconnectedFromPeer(peer) if peer.player == 3);
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+
+    // Player 3 was given up on, and nobody else was.
+    for events in [&a_events, &b_events].iter() {
+        assert!(events.saw(dropped));
+        assert!(!events.saw(|e| matches!(e, Event::DisconnectedFromPeer(peer) if peer.player != 3)));
+    }
+}
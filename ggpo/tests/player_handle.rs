@@ -0,0 +1,125 @@
+mod common;
+
+use common::{config, localhost, recording_peer, session, synchronize, MockGame, Peer};
+use ggpo::{
+    config::SessionConfig,
+    game_input::InputBuffer,
+    ggpo::{GGPOError, GGPOSessionCallbacks, Session},
+    player::{Player, PlayerHandle, PlayerType},
+};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+const INPUT_SIZE: usize = 2;
+
+fn sized(port: u16) -> SessionConfig {
+    SessionConfig {
+        input_size: INPUT_SIZE,
+        ..config(port)
+    }
+}
+
+fn empty_session(port: u16) -> Peer<MockGame> {
+    session(sized(port), Arc::new(Mutex::new(MockGame::default())))
+}
+
+fn add(session: &Peer<MockGame>, player_type: PlayerType, player_num: usize) -> PlayerHandle {
+    let mut handle: PlayerHandle = 0;
+    session
+        .lock()
+        .add_player(Player::new(player_type, player_num), &mut handle)
+        .unwrap();
+    handle
+}
+
+// Runs every method that takes a player handle and checks they agree.
+fn assert_all_fail_with<T: GGPOSessionCallbacks + Send + Sync>(
+    session: &Peer<T>,
+    handle: PlayerHandle,
+    expected: GGPOError,
+) {
+    let mut session = session.lock();
+    let values: InputBuffer = Default::default();
+    let results = vec![
+        session.add_local_input(handle, &values, INPUT_SIZE),
+        session.disconnect_player(handle),
+        session.set_frame_delay(handle, 2),
+        session.get_network_stats(handle).map(|_| ()),
+    ];
+    for result in results {
+        match result {
+            Err(ref e) if std::mem::discriminant(e) == std::mem::discriminant(&expected) => (),
+            other => panic!("expected {:?}, got {:?}", expected, other),
+        }
+    }
+}
+
+#[test]
+fn unknown_handles_are_invalid() {
+    let session = empty_session(17420);
+    add(&session, PlayerType::Local, 1);
+
+    // Nobody has been added as player 2, and no handle is ever 0.
+    assert_all_fail_with(&session, 2, GGPOError::InvalidPlayerHandle);
+    assert_all_fail_with(&session, 0, GGPOError::InvalidPlayerHandle);
+}
+
+#[test]
+fn handles_past_the_player_count_are_out_of_range() {
+    let session = empty_session(17430);
+    add(&session, PlayerType::Local, 1);
+
+    assert_all_fail_with(&session, 3, GGPOError::PlayerOutOfRange);
+}
+
+#[test]
+fn disconnected_players_are_rejected() {
+    let session = empty_session(17440);
+    add(&session, PlayerType::Local, 1);
+    let remote = add(&session, PlayerType::Remote(localhost(17450)), 2);
+
+    session.lock().disconnect_player(remote).unwrap();
+    assert_all_fail_with(&session, remote, GGPOError::PlayerDisconnected);
+}
+
+#[test]
+fn only_local_players_take_local_input() {
+    let (a, a_events) = recording_peer(sized(18430), 1, 18440);
+    let (b, b_events) = recording_peer(sized(18440), 2, 18430);
+    let (a_local, a_remote) = (1, 2);
+
+    assert!(a.lock().is_local(a_local).unwrap());
+    assert!(!a.lock().is_local(a_remote).unwrap());
+    assert!(matches!(
+        a.lock().is_local(3),
+        Err(GGPOError::PlayerOutOfRange)
+    ));
+
+    // Refused even before the session is running.
+    let values: InputBuffer = Default::default();
+    assert!(matches!(
+        a.lock().add_local_input(a_remote, &values, INPUT_SIZE),
+        Err(GGPOError::InvalidRequest)
+    ));
+
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+    a.lock()
+        .add_local_input(a_local, &values, INPUT_SIZE)
+        .unwrap();
+    assert!(matches!(
+        a.lock().add_local_input(a_remote, &values, INPUT_SIZE),
+        Err(GGPOError::InvalidRequest)
+    ));
+}
+
+#[test]
+fn a_negative_frame_delay_is_refused() {
+    let session = empty_session(19670);
+    let local = add(&session, PlayerType::Local, 1);
+
+    assert!(matches!(
+        session.lock().set_frame_delay(local, -1),
+        Err(GGPOError::InvalidRequest)
+    ));
+    session.lock().set_frame_delay(local, 2).unwrap();
+}
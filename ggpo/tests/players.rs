@@ -0,0 +1,76 @@
+mod common;
+
+use common::{localhost, MockGame};
+use ggpo::{
+    backends::p2p::Peer2PeerBackend,
+    ggpo::{GGPOError, Session},
+    player::{Player, PlayerHandle, PlayerInfo, PlayerType},
+};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+fn add(session: &mut Peer2PeerBackend<MockGame>, player: Player) -> PlayerHandle {
+    let mut handle: PlayerHandle = 0;
+    session.add_player(player, &mut handle).unwrap();
+    handle
+}
+
+#[test]
+fn players_lists_everyone_added() {
+    let session =
+        Peer2PeerBackend::new(Arc::new(Mutex::new(MockGame::default())), 18720, 2, 1, None)
+            .expect("session");
+    let mut session = session.lock();
+    assert_eq!(session.players().unwrap(), Vec::new());
+
+    // Out of order, to show the list goes by handle.
+    let remote = add(
+        &mut session,
+        Player::new(PlayerType::Remote(localhost(18730)), 2),
+    );
+    let local = add(&mut session, Player::new(PlayerType::Local, 1));
+    let spectator = add(
+        &mut session,
+        Player::new(PlayerType::Spectator(localhost(18740)), 3),
+    );
+    assert_eq!((local, remote), (1, 2));
+    assert!(spectator > remote);
+
+    let players = session.players().unwrap();
+    assert_eq!(
+        players,
+        vec![
+            PlayerInfo {
+                handle: local,
+                queue: 0,
+                player_type: PlayerType::Local,
+                connected: true,
+            },
+            PlayerInfo {
+                handle: remote,
+                queue: 1,
+                player_type: PlayerType::Remote(localhost(18730)),
+                connected: true,
+            },
+            PlayerInfo {
+                handle: spectator,
+                queue: 0,
+                player_type: PlayerType::Spectator(localhost(18740)),
+                connected: true,
+            },
+        ]
+    );
+    assert_eq!(players[0].addr(), None);
+    assert_eq!(players[1].addr(), Some(localhost(18730)));
+    assert_eq!(players[2].addr(), Some(localhost(18740)));
+
+    // A dropped player stays on the list, disconnected.
+    session.disconnect_player(remote).unwrap();
+    let players = session.players().unwrap();
+    assert_eq!(players.len(), 3);
+    assert!(!players[1].connected);
+    assert!(matches!(
+        session.disconnect_player(remote),
+        Err(GGPOError::PlayerDisconnected)
+    ));
+}
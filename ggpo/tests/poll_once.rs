@@ -0,0 +1,53 @@
+mod common;
+
+use common::{config, recording_peer, synchronize_by};
+use ggpo::{
+    config::SessionConfig,
+    game_input::{Frame, InputBuffer},
+    ggpo::{Event, Session},
+};
+use std::time::{Duration, Instant};
+
+const BUDGET: Duration = Duration::from_millis(1);
+
+// A session with no send task: nothing runs unless the test calls into it.
+fn untasked(port: u16) -> SessionConfig {
+    SessionConfig {
+        send_task: false,
+        ..config(port)
+    }
+}
+
+#[test]
+fn poll_once_drives_a_session_without_a_send_task() {
+    let (a, a_events) = recording_peer(untasked(18220), 1, 18230);
+    let (b, b_events) = recording_peer(untasked(18230), 2, 18220);
+
+    synchronize_by(&[&a_events, &b_events], || {
+        a.lock().poll_once(BUDGET).unwrap();
+        b.lock().poll_once(BUDGET).unwrap();
+    });
+
+    // Input keeps flowing the same way once the match is on: both sides
+    // confirm every frame, which takes the other's input arriving.
+    const FRAMES: u32 = 5;
+    for value in 1..=FRAMES as u8 {
+        for (session, handle) in [(&a, 1), (&b, 2)].iter() {
+            let mut session = session.lock();
+            let mut values: InputBuffer = Default::default();
+            values[0][0] = value;
+            session.add_local_input(*handle, &values, 1).unwrap();
+            session.synchronize_input(&mut values, None).unwrap();
+            session.increment_frame().unwrap();
+        }
+        a.lock().poll_once(BUDGET).unwrap();
+        b.lock().poll_once(BUDGET).unwrap();
+    }
+    let confirmed_last = |e: &Event| matches!(e, Event::FrameConfirmed(confirmed) if confirmed.frame == Frame::new(FRAMES - 1));
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !a_events.saw(confirmed_last) || !b_events.saw(confirmed_last) {
+        assert!(Instant::now() < deadline, "frames were never confirmed");
+        a.lock().poll_once(BUDGET).unwrap();
+        b.lock().poll_once(BUDGET).unwrap();
+    }
+}
@@ -0,0 +1,86 @@
+use ggpo::{
+    game_input::{Frame, GameInput, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS},
+    input_queue::InputQueue,
+    prediction::{NeutralAfterN, PredictionStrategy, RepeatLast},
+};
+use std::sync::Arc;
+
+const INPUT_SIZE: usize = 1;
+// Remote input shows up this many frames after we need it.
+const LAG: usize = 3;
+
+fn input(frame: usize, value: u8) -> GameInput {
+    let mut bits = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    bits[0][0] = value;
+    GameInput::init(Frame::new(frame as u32), Some(&bits), INPUT_SIZE)
+}
+
+// `pressed` for `held` frames, then four frames of nothing, over and over.
+fn recorded(pressed: u8, held: usize, repeats: usize) -> Vec<u8> {
+    (0..repeats)
+        .flat_map(|_| {
+            std::iter::repeat(pressed)
+                .take(held)
+                .chain(std::iter::repeat(b'0').take(4))
+        })
+        .collect()
+}
+
+/*
+ * Plays `stream` back through a queue as a remote player's input, arriving
+ * `LAG` frames late, and counts the rollbacks.  Like the sync layer, a
+ * mispredict rewinds to the first bad frame and asks for every frame since
+ * again.
+ */
+fn mispredicts(strategy: Arc<dyn PredictionStrategy>, stream: &[u8]) -> usize {
+    let mut queue = InputQueue::init(0, INPUT_SIZE);
+    queue.set_prediction_strategy(strategy);
+    let mut rollbacks = 0;
+    let mut scratch = GameInput::new();
+    for frame in 0..stream.len() + LAG {
+        if frame >= LAG {
+            let arrived = frame - LAG;
+            assert!(queue.add_input(input(arrived, stream[arrived])));
+            if let Some(first_incorrect) = queue.get_first_incorrect_frame().number() {
+                rollbacks += 1;
+                queue.reset_prediction(first_incorrect);
+                for replayed in first_incorrect..frame as u32 {
+                    queue.get_input(replayed, &mut scratch);
+                }
+            }
+            if arrived > 0 {
+                queue.discard_confirmed_frames(arrived as u32 - 1);
+            }
+        }
+        queue.get_input(frame as u32, &mut scratch);
+    }
+    rollbacks
+}
+
+#[test]
+fn neutral_after_n_mispredicts_less_on_taps() {
+    let taps = recorded(b'A', 2, 40);
+    let repeat_last = mispredicts(Arc::new(RepeatLast), &taps);
+    let neutral = mispredicts(Arc::new(NeutralAfterN::new(1)), &taps);
+    // Repeating misses both ends of each tap; reverting to neutral only the start.
+    assert_eq!(repeat_last, 80);
+    assert_eq!(neutral, 40);
+}
+
+#[test]
+fn repeat_last_mispredicts_less_on_held_buttons() {
+    let holds = recorded(b'R', 20, 10);
+    let repeat_last = mispredicts(Arc::new(RepeatLast), &holds);
+    let neutral = mispredicts(Arc::new(NeutralAfterN::new(1)), &holds);
+    assert_eq!(repeat_last, 20);
+    assert!(neutral > repeat_last, "{} vs {}", neutral, repeat_last);
+}
+
+#[test]
+fn neutral_after_n_repeats_for_n_frames() {
+    let strategy = NeutralAfterN::new(2);
+    let history = [input(9, b'0'), input(10, b'A')];
+    assert_eq!(strategy.predict(&history, Frame::new(11)).bits[0][0], b'A');
+    assert_eq!(strategy.predict(&history, Frame::new(12)).bits[0][0], b'A');
+    assert_eq!(strategy.predict(&history, Frame::new(13)).bits[0][0], b'0');
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::{advance, peer, synchronize, Recorder};
+use ggpo::ggpo::{Event, Session, GGPO_MAX_PREDICTION_FRAMES};
+use std::time::{Duration, Instant};
+
+fn barrier_events(events: &Recorder) -> Vec<usize> {
+    events
+        .events
+        .lock()
+        .iter()
+        .filter_map(|e| match e {
+            Event::PredictionBarrier(barrier) => Some(barrier.frames_stalled),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn barrier_fires_while_a_peer_stalls_and_clears_when_it_catches_up() {
+    let (a, a_events) = peer(17970, 1, 17980);
+    let (b, b_events) = peer(17980, 2, 17970);
+
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+
+    // B stops sending input, so A runs out of frames to predict.
+    let mut played = 0;
+    while advance(&a, 1) {
+        played += 1;
+        assert!(played <= GGPO_MAX_PREDICTION_FRAMES, "A never stalled");
+        b.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+    }
+    for _ in 0..4 {
+        assert!(!advance(&a, 1));
+        a.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+    }
+    assert_eq!(barrier_events(&a_events), vec![1, 2, 3, 4, 5]);
+    let estimate = a_events.events.lock().iter().find_map(|e| match e {
+        Event::PredictionBarrier(barrier) => Some(barrier.estimated_frames),
+        _ => None,
+    });
+    assert!(estimate.unwrap() >= 1);
+    assert!(!a_events.saw(|e| matches!(e, Event::PredictionBarrierCleared(_))));
+
+    // Once B's input arrives A takes input again.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        assert!(Instant::now() < deadline, "A never got past the barrier");
+        advance(&b, 2);
+        a.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+        b.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+        if advance(&a, 1) {
+            break;
+        }
+    }
+    let stalled = barrier_events(&a_events).len();
+    assert!(a_events.saw(|e| matches!(
+        e,
+        Event::PredictionBarrierCleared(cleared) if cleared.frames_stalled == stalled
+    )));
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use bytes::Bytes;
+use common::{connect_status, sync_with};
+use ggpo::{
+    game_input::{
+        Frame, GameInput, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS, NULL_FRAME,
+    },
+    ggpo::{
+        advance_confidence, Event, GGPOError, GGPOSessionCallbacks, SavedState,
+        ADVANCE_CONFIDENCE_MAX, ADVANCE_CONFIDENCE_SHIFT, ADVANCE_SPECULATIVE,
+    },
+    sync::{GGPOSync, RollbackCallbacks},
+};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+const INPUT_SIZE: usize = 1;
+const MAX: u32 = ADVANCE_CONFIDENCE_MAX;
+
+// Keeps the flags of every `advance_frame` call.
+#[derive(Debug, Default, Clone)]
+struct Flagged {
+    flags: Vec<i32>,
+}
+
+impl GGPOSessionCallbacks for Flagged {
+    fn save_game_state(&mut self, _frame: Frame) -> Result<SavedState, GGPOError> {
+        Ok(SavedState {
+            data: Bytes::from_static(&[0]),
+            checksum: None,
+        })
+    }
+
+    fn load_game_state(&mut self, _buffer: &Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn log_game_state(&mut self, _filename: String, _buffer: Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn advance_frame(&mut self, flags: i32) -> bool {
+        self.flags.push(flags);
+        true
+    }
+
+    fn on_event(&mut self, _info: &Event) {}
+}
+
+// Runs `frames` frames, guessing whatever remote input is missing.
+fn run(sync: &mut GGPOSync<Flagged>, frames: usize) {
+    for _ in 0..frames {
+        let mut local = GameInput::init(NULL_FRAME, None, INPUT_SIZE);
+        assert!(sync.add_local_input(0, &mut local).unwrap());
+        let mut values: InputBuffer = Default::default();
+        sync.synchronize_inputs(&mut values).unwrap();
+        sync.increment_frame().unwrap();
+    }
+}
+
+// Remote input for `frames`, idle before `pressed_from` and pressed from there on.
+fn send(sync: &mut GGPOSync<Flagged>, queue: u32, frames: std::ops::Range<u32>, pressed_from: u32) {
+    let mut pressed = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    pressed[0][0] = 7;
+    for frame in frames {
+        let bits = if frame >= pressed_from {
+            Some(&pressed)
+        } else {
+            None
+        };
+        sync.add_remote_input(queue, &GameInput::init(Frame::new(frame), bits, INPUT_SIZE))
+            .unwrap();
+    }
+}
+
+// The confidence of each frame run since the last call.
+fn confidences(game: &Mutex<Flagged>) -> Vec<u32> {
+    game.lock()
+        .flags
+        .drain(..)
+        .map(|flags| {
+            assert!(flags & ADVANCE_SPECULATIVE != 0);
+            advance_confidence(flags)
+        })
+        .collect()
+}
+
+#[test]
+fn confidence_follows_each_players_recent_predictions() {
+    let game = Arc::new(Mutex::new(Flagged::default()));
+    let status = connect_status(3);
+    let mut sync = sync_with(game.clone(), &status, INPUT_SIZE);
+    run(&mut sync, 8);
+
+    // Player 1 idled through frame 4, as guessed; player 2 pressed something
+    // from frame 2 on, which nobody saw coming.
+    send(&mut sync, 1, 0..5, u32::MAX);
+    assert_eq!(sync.prediction_streak(1), 5);
+    send(&mut sync, 2, 0..8, 2);
+    assert_eq!(sync.prediction_streak(2), 0);
+
+    // Frames 2 to 4 ran again on real input; 5 to 7 still guess player 1's,
+    // whose guesses have been right five times running.
+    sync.check_simulation().unwrap();
+    assert_eq!(confidences(&game), vec![MAX, MAX, MAX, 5, 5, 5]);
+
+    // Three more frames, now everyone's input up to 4 is in, then player 1
+    // turns out to have pressed on 7.
+    sync.set_last_confirmed_frame(Frame::new(4)).unwrap();
+    run(&mut sync, 3);
+    send(&mut sync, 1, 5..8, 7);
+    assert_eq!(sync.prediction_streak(1), 0);
+    sync.check_simulation().unwrap();
+    assert_eq!(confidences(&game), vec![MAX, 0, 0, 0]);
+}
+
+#[test]
+fn a_frame_guessing_nobodys_input_is_fully_confident() {
+    let game = Arc::new(Mutex::new(Flagged::default()));
+    let status = connect_status(2);
+    let mut sync = sync_with(game, &status, INPUT_SIZE);
+    run(&mut sync, 1);
+    send(&mut sync, 1, 0..1, u32::MAX);
+    assert_eq!(sync.prediction_confidence(Frame::new(0)), u32::MAX);
+    assert_eq!(sync.prediction_confidence(Frame::new(1)), 0);
+
+    // Which the flags cap.
+    let mut flagged = Flagged::default();
+    assert!(flagged.resimulate_frame_with_confidence(u32::MAX));
+    assert_eq!(
+        flagged.flags,
+        vec![ADVANCE_SPECULATIVE | (MAX << ADVANCE_CONFIDENCE_SHIFT) as i32]
+    );
+}
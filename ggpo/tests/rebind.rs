@@ -0,0 +1,146 @@
+mod common;
+
+use bytes::Bytes;
+use common::{localhost, peer, poll, synchronize_by, Peer};
+use ggpo::{
+    game_input::{InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS},
+    ggpo::{Event, GGPOError, Session},
+    network::udp_msg::UdpMsg,
+};
+use std::{
+    net::UdpSocket,
+    time::{Duration, Instant},
+};
+
+const A_PORT: u16 = 17720;
+const B_PORT: u16 = 17730;
+// Where A sees B: first one NAT mapping, then another.
+const FIRST_MAPPING: u16 = 17740;
+const SECOND_MAPPING: u16 = 17760;
+// Where B sees A.
+const A_MAPPING: u16 = 17750;
+const SPOOFER: u16 = 17770;
+
+fn bind(port: u16) -> UdpSocket {
+    let socket = UdpSocket::bind(localhost(port)).unwrap();
+    socket.set_nonblocking(true).unwrap();
+    socket
+}
+
+/*
+ * Stands in for B's NAT.  Whatever B sends comes out of `mapping`, and what A
+ * sends to `mapping` goes back to B.  Remapping swaps in a new socket, and
+ * packets to the old one are lost.
+ */
+struct Nat {
+    mapping: UdpSocket,
+    old_mapping: Option<UdpSocket>,
+    b_side: UdpSocket,
+    // What A has sent to the current mapping.
+    to_mapping: usize,
+    // One packet B sent, for the spoofer to tamper with.
+    sample: Option<Vec<u8>>,
+}
+
+impl Nat {
+    fn new() -> Self {
+        Self {
+            mapping: bind(FIRST_MAPPING),
+            old_mapping: None,
+            b_side: bind(A_MAPPING),
+            to_mapping: 0,
+            sample: None,
+        }
+    }
+
+    fn remap(&mut self) {
+        let old = std::mem::replace(&mut self.mapping, bind(SECOND_MAPPING));
+        self.old_mapping = Some(old);
+        self.to_mapping = 0;
+    }
+
+    fn pump(&mut self) {
+        let mut buf = [0; 4096];
+        while let Ok((len, _)) = self.b_side.recv_from(&mut buf) {
+            self.sample = Some(buf[..len].to_vec());
+            self.mapping
+                .send_to(&buf[..len], localhost(A_PORT))
+                .unwrap();
+        }
+        while let Ok((len, _)) = self.mapping.recv_from(&mut buf) {
+            self.to_mapping += 1;
+            self.b_side.send_to(&buf[..len], localhost(B_PORT)).unwrap();
+        }
+        if let Some(old) = &self.old_mapping {
+            while old.recv_from(&mut buf).is_ok() {}
+        }
+    }
+}
+
+// Runs a frame on each peer that will take one, and moves packets along.
+fn step(a: &Peer, b: &Peer, nat: &mut Nat, frames: &mut [u32; 2]) {
+    let blank = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    for (i, (session, handle)) in [(a, 1), (b, 2)].iter().enumerate() {
+        let mut session = session.lock();
+        match session.add_local_input(*handle, &blank, 1) {
+            Ok(()) => {
+                let mut values: InputBuffer = Default::default();
+                session.synchronize_input(&mut values, None).unwrap();
+                session.increment_frame().unwrap();
+                frames[i] += 1;
+            }
+            Err(GGPOError::PredictionThreshold) | Err(GGPOError::NotSynchronized) => {}
+            Err(e) => panic!("add_local_input failed: {}", e),
+        }
+    }
+    poll(a);
+    poll(b);
+    nat.pump();
+}
+
+#[test]
+fn replies_follow_a_peer_to_its_new_address() {
+    let mut nat = Nat::new();
+    let (a, a_events) = peer(A_PORT, 1, FIRST_MAPPING);
+    let (b, b_events) = peer(B_PORT, 2, A_MAPPING);
+    let spoofer = bind(SPOOFER);
+    let mut frames = [0; 2];
+
+    synchronize_by(&[&a_events, &b_events], || {
+        poll(&a);
+        poll(&b);
+        nat.pump();
+    });
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while frames[0] < 30 || frames[1] < 30 {
+        assert!(Instant::now() < deadline, "peers never reached frame 30");
+        step(&a, &b, &mut nat, &mut frames);
+    }
+
+    // Someone else copies one of B's packets, but can't get the magic number right.
+    let sample = zstd::block::decompress(nat.sample.as_ref().unwrap(), 4096).unwrap();
+    let mut forged = UdpMsg::decode(Bytes::from(sample)).unwrap();
+    forged.header.magic ^= 1;
+    let forged = zstd::block::compress(&forged.encode().unwrap(), 0).unwrap();
+    spoofer.send_to(&forged, localhost(A_PORT)).unwrap();
+
+    nat.remap();
+    let played = frames;
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while frames[0] < played[0] + 60 || frames[1] < played[1] + 60 {
+        assert!(
+            Instant::now() < deadline,
+            "the match stalled after the remap ({:?})",
+            frames
+        );
+        step(&a, &b, &mut nat, &mut frames);
+    }
+
+    assert!(nat.to_mapping > 0, "A never sent to B's new address");
+    let mut buf = [0; 4096];
+    assert!(
+        spoofer.recv_from(&mut buf).is_err(),
+        "A answered the forged packet"
+    );
+    assert!(!a_events.saw(|e| matches!(e, Event::DisconnectedFromPeer(_))));
+}
@@ -0,0 +1,174 @@
+mod common;
+
+use common::{
+    config, connect_status, poll, recording_pair, sync_with, synchronize, MockGame, Peer,
+};
+use ggpo::{
+    config::SessionConfig,
+    game_input::{
+        Frame, GameInput, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS, NULL_FRAME,
+    },
+    ggpo::{Event, GGPOError, Session},
+    player::PlayerHandle,
+};
+use parking_lot::Mutex;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+const INPUT_SIZE: usize = 2;
+
+fn remote_input(frame: u32, value: u8) -> GameInput {
+    let mut bits = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    bits[0][0] = value;
+    GameInput::init(Frame::new(frame), Some(&bits), INPUT_SIZE)
+}
+
+fn confirmed_remote_value(sync: &mut ggpo::sync::GGPOSync<MockGame>, frame: u32) -> u8 {
+    let mut values: InputBuffer = Default::default();
+    sync.get_confirmed_inputs(&mut values, Frame::new(frame))
+        .unwrap();
+    values[1][0]
+}
+
+#[test]
+fn missed_frames_keep_the_input_they_were_simulated_with() {
+    let status = connect_status(2);
+    let mut sync = sync_with(
+        Arc::new(Mutex::new(MockGame::default())),
+        &status,
+        INPUT_SIZE,
+    );
+
+    // The remote player sends frame 0, then goes quiet for frames 1 and 2.
+    sync.add_remote_input(1, &remote_input(0, 7)).unwrap();
+    for _ in 0..3 {
+        let mut local = GameInput::init(NULL_FRAME, None, INPUT_SIZE);
+        assert!(sync.add_local_input(0, &mut local).unwrap());
+        let mut values: InputBuffer = Default::default();
+        sync.synchronize_inputs(&mut values).unwrap();
+        assert_eq!(values[1][0], 7);
+        sync.increment_frame().unwrap();
+    }
+
+    // They come back at frame 3: frames 1 and 2 stay predicted.
+    sync.freeze_remote_input(1, Frame::new(3), false);
+    sync.add_remote_input(1, &remote_input(3, 9)).unwrap();
+    assert_eq!(confirmed_remote_value(&mut sync, 0), 7);
+    assert_eq!(confirmed_remote_value(&mut sync, 1), 7);
+    assert_eq!(confirmed_remote_value(&mut sync, 2), 7);
+    assert_eq!(confirmed_remote_value(&mut sync, 3), 9);
+    assert!(sync.check_simulation_consistency(&mut 0).unwrap());
+}
+
+#[test]
+fn missed_frames_stay_blank_for_a_disconnected_player() {
+    let status = connect_status(2);
+    let mut sync = sync_with(
+        Arc::new(Mutex::new(MockGame::default())),
+        &status,
+        INPUT_SIZE,
+    );
+
+    sync.add_remote_input(1, &remote_input(0, 7)).unwrap();
+    sync.freeze_remote_input(1, Frame::new(4), true);
+    sync.add_remote_input(1, &remote_input(4, 9)).unwrap();
+    assert_eq!(confirmed_remote_value(&mut sync, 0), 7);
+    assert_eq!(confirmed_remote_value(&mut sync, 2), b'0');
+    assert_eq!(confirmed_remote_value(&mut sync, 4), 9);
+}
+
+// Quick to drop a quiet peer, and slow to forget it.
+fn reconnecting(port: u16) -> SessionConfig {
+    SessionConfig {
+        input_size: INPUT_SIZE,
+        disconnect_timeout: 300,
+        disconnect_notify_start: 100,
+        reconnect_window: 5000,
+        ..config(port)
+    }
+}
+
+// Runs a frame if the session lets us.  Every input is blank, so predictions
+// are never wrong and nothing gets rolled back.
+fn advance(session: &Peer, handle: PlayerHandle) -> bool {
+    let mut session = session.lock();
+    session.do_poll(Some(Duration::from_millis(1))).unwrap();
+    let inputs = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    match session.add_local_input(handle, &inputs, INPUT_SIZE) {
+        Ok(()) => (),
+        Err(GGPOError::PredictionThreshold) => return false,
+        Err(e) => panic!("add_local_input failed: {:?}", e),
+    }
+    let mut values: InputBuffer = Default::default();
+    session.synchronize_input(&mut values, None).unwrap();
+    session.increment_frame().unwrap();
+    true
+}
+
+fn assert_before(deadline: Instant, what: &str) {
+    assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+}
+
+#[test]
+fn peer_reconnects_within_the_grace_window() {
+    let ((a, a_events), (b, b_events)) = recording_pair(reconnecting(17400), reconnecting(17410));
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let (mut a_frames, mut b_frames) = (0, 0);
+    while a_frames < 20 || b_frames < 20 {
+        assert_before(deadline, "the first 20 frames");
+        if a_frames < 20 && advance(&a, 1) {
+            a_frames += 1;
+        }
+        if b_frames < 20 && advance(&b, 2) {
+            b_frames += 1;
+        }
+    }
+
+    // Get B a little ahead, so A has B's input for every frame it has run and
+    // has nothing to roll back when B drops.
+    while b_frames < 23 {
+        assert_before(deadline, "B to get ahead");
+        poll(&a);
+        if advance(&b, 2) {
+            b_frames += 1;
+        }
+    }
+    let settle = Instant::now() + Duration::from_millis(50);
+    while Instant::now() < settle {
+        poll(&a);
+    }
+
+    // B goes silent for longer than the disconnect timeout.
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while !a_events.saw(|e| matches!(e, Event::DisconnectedFromPeer(d) if d.player == 2)) {
+        assert_before(deadline, "A to drop B");
+        poll(&a);
+    }
+    let disconnected_at = a_events.events.lock().len();
+
+    // B comes back well within the window and both carry on.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !a_events.events.lock()[disconnected_at..]
+        .iter()
+        .any(|e| matches!(e, Event::ConnectionResumed(r) if r.player == 2))
+    {
+        assert_before(deadline, "A to take B back");
+        advance(&a, 1);
+        advance(&b, 2);
+    }
+    let (a_resumed, b_resumed) = (a_frames, b_frames);
+    while a_frames < a_resumed + 30 || b_frames < b_resumed + 30 {
+        assert_before(deadline, "30 more frames after reconnecting");
+        if advance(&a, 1) {
+            a_frames += 1;
+        }
+        if advance(&b, 2) {
+            b_frames += 1;
+        }
+    }
+    assert!(!b_events.saw(|e| matches!(e, Event::DisconnectedFromPeer(_))));
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use common::{config, localhost, recording_peer_at, synchronize};
+use ggpo::{
+    config::SessionConfig,
+    network::relay::{self, PeerId, RelayTransport, Transport, PEER_ID_SIZE},
+};
+use std::{
+    collections::HashMap,
+    net::{SocketAddr, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+const RELAY_PORT: u16 = 18050;
+
+// Just enough of a relay server: learns ids, and forwards with the sender's id in front.
+struct StubRelay {
+    stop: Arc<AtomicBool>,
+    forwarded: Arc<AtomicUsize>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl StubRelay {
+    fn start(port: u16) -> Self {
+        let socket = UdpSocket::bind(localhost(port)).unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_millis(5)))
+            .unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let forwarded = Arc::new(AtomicUsize::new(0));
+        let (running, count) = (stop.clone(), forwarded.clone());
+        let task = thread::spawn(move || {
+            let mut peers: HashMap<PeerId, SocketAddr> = HashMap::new();
+            let mut buf = [0u8; 4096];
+            while !running.load(Ordering::SeqCst) {
+                let (len, from) = match socket.recv_from(&mut buf) {
+                    Ok(received) => received,
+                    Err(_) => continue,
+                };
+                if len == PEER_ID_SIZE {
+                    peers.insert(PeerId::from_be_bytes([buf[0], buf[1]]), from);
+                    continue;
+                }
+                let sender = peers.iter().find(|(_, address)| **address == from);
+                let (to, packet) = match relay::unwrap(&buf[..len]) {
+                    Some(unwrapped) => unwrapped,
+                    None => continue,
+                };
+                if let (Some((&sender, _)), Some(&address)) = (sender, peers.get(&to)) {
+                    socket
+                        .send_to(&relay::wrap(sender, packet), address)
+                        .unwrap();
+                    count.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        });
+        StubRelay {
+            stop,
+            forwarded,
+            task: Some(task),
+        }
+    }
+}
+
+impl Drop for StubRelay {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(task) = self.task.take() {
+            let _ = task.join();
+        }
+    }
+}
+
+// Sending everything through the relay, as `peer_id`.
+fn relayed(port: u16, peer_id: PeerId) -> SessionConfig {
+    SessionConfig {
+        relay: Some(RelayTransport::new(localhost(RELAY_PORT), peer_id)),
+        ..config(port)
+    }
+}
+
+#[test]
+fn peers_synchronize_through_a_relay() {
+    let stub = StubRelay::start(RELAY_PORT);
+    let (a, a_events) = recording_peer_at(relayed(18060, 7), 1, &[relay::peer_address(9)]);
+    let (b, b_events) = recording_peer_at(relayed(18070, 9), 2, &[relay::peer_address(7)]);
+
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+
+    // Neither peer knows the other's real address, so all of it went through the relay.
+    assert!(stub.forwarded.load(Ordering::SeqCst) > 0);
+}
+
+#[test]
+fn relayed_peers_have_addresses_no_real_peer_can() {
+    let address = relay::peer_address(42);
+    assert_eq!(relay::peer_id(&address), Some(42));
+    assert_eq!(relay::peer_id(&localhost(42)), None);
+
+    let wrapped = relay::wrap(42, b"packet");
+    assert_eq!(relay::unwrap(&wrapped), Some((42, &b"packet"[..])));
+}
+
+#[test]
+fn the_relay_transport_only_passes_relayed_peers_packets() {
+    let relay = RelayTransport::new(localhost(RELAY_PORT), 7);
+
+    let (address, datagram) = relay.outgoing(relay::peer_address(9), b"packet").unwrap();
+    assert_eq!(address, localhost(RELAY_PORT));
+    assert_eq!(datagram, relay::wrap(9, b"packet"));
+    assert!(relay.outgoing(localhost(18080), b"packet").is_none());
+
+    assert_eq!(
+        relay.incoming(localhost(RELAY_PORT), &datagram),
+        Some(relay::peer_address(9))
+    );
+    // Only the relay's datagrams come from relayed peers.
+    assert_eq!(relay.incoming(localhost(18080), &datagram), None);
+}
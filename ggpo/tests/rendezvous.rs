@@ -0,0 +1,131 @@
+#![cfg(feature = "rendezvous")]
+
+mod common;
+
+use common::localhost;
+use ggpo::network::rendezvous::{Rendezvous, RendezvousError};
+use std::{
+    collections::HashMap,
+    net::{SocketAddr, UdpSocket},
+    thread,
+    time::Duration,
+};
+
+const SERVER_PORT: u16 = 17780;
+const A_PORT: u16 = 17790;
+const B_PORT: u16 = 17800;
+const SILENT_SERVER_PORT: u16 = 17810;
+const LONELY_PORT: u16 = 17820;
+const NOISY_SERVER_PORT: u16 = 19630;
+const NOISY_PEER_PORT: u16 = 19640;
+const NOISY_PORT: u16 = 19650;
+
+/*
+ * Introduces the first two peers to register the same match id, answering
+ * each with the address it saw the other come from.  Runs until both have
+ * been answered at least once.
+ */
+fn stub_server(port: u16) -> thread::JoinHandle<()> {
+    let socket = UdpSocket::bind(localhost(port)).unwrap();
+    thread::spawn(move || {
+        let mut registered: HashMap<String, Vec<SocketAddr>> = HashMap::new();
+        let mut answered = Vec::new();
+        let mut buf = [0; 512];
+        while answered.len() < 2 {
+            let (len, from) = socket.recv_from(&mut buf).unwrap();
+            let request = String::from_utf8_lossy(&buf[..len]).into_owned();
+            let match_id = match request.strip_prefix("register ") {
+                Some(match_id) => match_id.to_string(),
+                None => continue,
+            };
+            let peers = registered.entry(match_id).or_default();
+            if !peers.contains(&from) {
+                peers.push(from);
+            }
+            if let Some(&other) = peers.iter().find(|&&peer| peer != from) {
+                socket
+                    .send_to(format!("peer {}", other).as_bytes(), from)
+                    .unwrap();
+                if !answered.contains(&from) {
+                    answered.push(from);
+                }
+            }
+        }
+    })
+}
+
+#[test]
+fn both_peers_learn_each_others_address() {
+    let server = stub_server(SERVER_PORT);
+    let meet = |port: u16| {
+        thread::spawn(move || {
+            let mut rendezvous = Rendezvous::new(localhost(SERVER_PORT), "match", port);
+            rendezvous.server_timeout = Duration::from_secs(5);
+            rendezvous.punch_timeout = Duration::from_secs(5);
+            rendezvous.meet()
+        })
+    };
+    let a = meet(A_PORT);
+    let b = meet(B_PORT);
+    let a = a.join().unwrap().expect("A never met B");
+    let b = b.join().unwrap().expect("B never met A");
+    server.join().unwrap();
+
+    assert_eq!(a.peer, localhost(B_PORT));
+    assert_eq!(b.peer, localhost(A_PORT));
+    // Punched from just where the sessions will listen.
+    assert_eq!(a.local, localhost(A_PORT));
+    assert_eq!(b.local, localhost(B_PORT));
+
+    // The port's free again for the session to bind.
+    UdpSocket::bind(a.local).unwrap();
+}
+
+#[test]
+fn a_silent_server_times_out() {
+    let _server = UdpSocket::bind(localhost(SILENT_SERVER_PORT)).unwrap();
+    let mut rendezvous = Rendezvous::new(localhost(SILENT_SERVER_PORT), "match", LONELY_PORT);
+    rendezvous.server_timeout = Duration::from_millis(500);
+    match rendezvous.meet() {
+        Err(RendezvousError::ServerTimeout(server)) => {
+            assert_eq!(server, localhost(SILENT_SERVER_PORT))
+        }
+        other => panic!("expected a server timeout, got {:?}", other),
+    }
+}
+
+#[test]
+fn stray_datagrams_dont_end_the_wait_for_an_introduction() {
+    let server = UdpSocket::bind(localhost(NOISY_SERVER_PORT)).unwrap();
+    let peer = UdpSocket::bind(localhost(NOISY_PEER_PORT)).unwrap();
+    let meeting = thread::spawn(|| {
+        let mut rendezvous = Rendezvous::new(localhost(NOISY_SERVER_PORT), "match", NOISY_PORT);
+        rendezvous.server_timeout = Duration::from_secs(5);
+        rendezvous.punch_timeout = Duration::from_secs(5);
+        rendezvous.meet()
+    });
+
+    let mut buf = [0; 512];
+    let (_, from) = server.recv_from(&mut buf).unwrap();
+    // A reply we can't read, and an introduction from someone other than the server.
+    server.send_to(b"hello", from).unwrap();
+    peer.send_to(b"peer 127.0.0.1:1", from).unwrap();
+    server
+        .send_to(
+            format!("peer {}", localhost(NOISY_PEER_PORT)).as_bytes(),
+            from,
+        )
+        .unwrap();
+
+    // The peer's half of the punch.
+    loop {
+        let (len, from) = peer.recv_from(&mut buf).unwrap();
+        if &buf[..len] == b"punch match" {
+            peer.send_to(b"punched match", from).unwrap();
+            peer.send_to(b"punch match", from).unwrap();
+            break;
+        }
+    }
+    let meeting = meeting.join().unwrap().expect("never met the peer");
+    assert_eq!(meeting.peer, localhost(NOISY_PEER_PORT));
+}
@@ -0,0 +1,165 @@
+mod common;
+
+use common::MockGame;
+use ggpo::{
+    backends::sync_test::SyncTestBackend,
+    game_input::{FrameNum, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS},
+    ggpo::{Event, Session, TimeSyncEvent},
+    replay::{Recorder, ReplayError, ReplayGuard, Replayer, RECORDING_VERSION},
+};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+const NUM_PLAYERS: usize = 2;
+const INPUT_SIZE: usize = 1;
+const FRAMES: FrameNum = 30;
+
+#[derive(Default)]
+struct Game {
+    positions: [u32; NUM_PLAYERS],
+}
+
+impl Game {
+    fn advance(&mut self, inputs: &InputBuffer) {
+        for (position, input) in self.positions.iter_mut().zip(inputs.iter()) {
+            *position = position.wrapping_mul(31).wrapping_add(input[0] as u32);
+        }
+    }
+
+    fn checksum(&self) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        for position in self.positions.iter() {
+            hasher.update(&position.to_le_bytes());
+        }
+        hasher.finalize()
+    }
+}
+
+fn input_for(frame: FrameNum, player: usize) -> InputBuffer {
+    let mut values = [[0; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    values[player][0] = (frame as u8).wrapping_mul(7) ^ player as u8;
+    values
+}
+
+fn record_match() -> (Recorder, u32) {
+    let mut recorder = Recorder::new();
+    let mut game = Game::default();
+
+    for frame in 0..FRAMES {
+        let mut inputs = [[0; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+        for player in 0..NUM_PLAYERS {
+            let values = input_for(frame, player);
+            recorder.record_input(frame, player as u32, &values, INPUT_SIZE);
+            inputs[player] = values[player];
+        }
+        game.advance(&inputs);
+    }
+    recorder.record_event(FRAMES, &Event::TimeSync(TimeSyncEvent { frames_ahead: 2 }));
+
+    (recorder, game.checksum())
+}
+
+#[test]
+fn replay_reproduces_the_recorded_match() {
+    let (recorder, checksum) = record_match();
+    let replayer = Replayer::from_bytes(&recorder.to_bytes().unwrap()).unwrap();
+
+    assert_eq!(replayer.last_frame(), Some(FRAMES - 1));
+    assert_eq!(replayer.events().count(), 1);
+
+    let mut game = Game::default();
+    for frame in 0..=replayer.last_frame().unwrap() {
+        let mut inputs = [[0; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+        for recorded in replayer.frame_inputs(frame) {
+            let player = recorded.player as usize;
+            inputs[player] = recorded.input[player];
+        }
+        game.advance(&inputs);
+    }
+
+    assert_eq!(game.checksum(), checksum);
+}
+
+#[test]
+fn unknown_recording_version_is_rejected() {
+    let (recorder, _) = record_match();
+    let mut bytes = recorder.to_bytes().unwrap();
+    bytes[..4].copy_from_slice(&(RECORDING_VERSION + 1).to_le_bytes());
+
+    match Replayer::from_bytes(&bytes) {
+        Err(ReplayError::UnsupportedVersion { found, expected }) => {
+            assert_eq!(found, RECORDING_VERSION + 1);
+            assert_eq!(expected, RECORDING_VERSION);
+        }
+        other => panic!("expected a version error, got {:?}", other.map(|_| ())),
+    }
+}
+
+// Feeds every frame of `source` through a guard over a sync test session,
+// stopping at the first error.
+fn guard_match(source: impl Fn(FrameNum, usize) -> InputBuffer) -> Result<(), ReplayError> {
+    let (recorder, _) = record_match();
+    let guard = ReplayGuard::new(Replayer::from_bytes(&recorder.to_bytes().unwrap()).unwrap());
+    let mut session =
+        SyncTestBackend::new(Arc::new(Mutex::new(MockGame::default())), 1, NUM_PLAYERS).unwrap();
+    session.do_poll(None).unwrap();
+
+    for frame in 0..FRAMES {
+        for player in 0..NUM_PLAYERS {
+            guard.add_local_input(
+                &mut session,
+                frame,
+                player as u32,
+                &source(frame, player),
+                INPUT_SIZE,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn replay_guard_accepts_matching_input() {
+    guard_match(input_for).unwrap();
+}
+
+#[test]
+fn replay_guard_reports_the_first_divergence() {
+    const DIVERGENT_FRAME: FrameNum = 17;
+
+    let result = guard_match(|frame, player| {
+        let mut values = input_for(frame, player);
+        if frame >= DIVERGENT_FRAME && player == 1 {
+            values[player][0] ^= 0b100;
+        }
+        values
+    });
+
+    match result {
+        Err(ReplayError::Diverged {
+            frame,
+            player,
+            bits,
+        }) => {
+            assert_eq!(frame, DIVERGENT_FRAME);
+            assert_eq!(player, 1);
+            // Bit 2 of player 1's first byte, in the flat layout.
+            assert_eq!(bits, vec![GAMEINPUT_MAX_BYTES * 8 + 2]);
+        }
+        other => panic!("expected a divergence, got {:?}", other),
+    }
+}
+
+#[test]
+fn replay_guard_rejects_unrecorded_frames() {
+    let (recorder, _) = record_match();
+    let guard = ReplayGuard::new(Replayer::from_bytes(&recorder.to_bytes().unwrap()).unwrap());
+
+    match guard.check(FRAMES, 0, &input_for(FRAMES, 0), INPUT_SIZE) {
+        Err(ReplayError::NotRecorded { frame, player }) => {
+            assert_eq!(frame, FRAMES);
+            assert_eq!(player, 0);
+        }
+        other => panic!("expected a missing-record error, got {:?}", other),
+    }
+}
@@ -0,0 +1,28 @@
+#![cfg(feature = "resolve")]
+
+use ggpo::{
+    ggpo::GGPOError,
+    player::{Player, PlayerType},
+};
+
+#[test]
+fn localhost_resolves_to_a_loopback_remote_player() {
+    let player = smol::block_on(Player::remote_from_host("localhost:7000", 2, false)).unwrap();
+    assert_eq!(player.player_num, 2);
+    match player.player_type {
+        PlayerType::Remote(address) => {
+            assert!(address.is_ipv4());
+            assert!(address.ip().is_loopback());
+            assert_eq!(address.port(), 7000);
+        }
+        other => panic!("expected a remote player, got {:?}", other),
+    }
+}
+
+#[test]
+fn a_host_without_a_port_is_unresolved() {
+    assert!(matches!(
+        smol::block_on(Player::remote_from_host("localhost", 2, false)),
+        Err(GGPOError::Unresolved { .. })
+    ));
+}
@@ -0,0 +1,39 @@
+use ggpo::network::udp_proto::{RetransmitTimer, MAX_RETRANSMIT_INTERVAL};
+
+#[test]
+fn unacked_input_backs_off_exponentially() {
+    let mut timer = RetransmitTimer::new(100);
+    assert!(!timer.is_due(1_000));
+
+    timer.arm(0);
+    assert!(!timer.is_due(99));
+    assert!(timer.is_due(100));
+
+    // The first transmission was lost: resend and wait twice as long.
+    timer.back_off(100);
+    assert_eq!(timer.current_interval(), 200);
+    assert!(!timer.is_due(299));
+    assert!(timer.is_due(300));
+
+    for _ in 0..10 {
+        timer.back_off(300);
+    }
+    assert_eq!(timer.current_interval(), MAX_RETRANSMIT_INTERVAL);
+}
+
+#[test]
+fn ack_resets_the_backoff() {
+    let mut timer = RetransmitTimer::new(100);
+    timer.arm(0);
+    timer.back_off(100);
+    timer.back_off(300);
+
+    timer.reset();
+    assert_eq!(timer.current_interval(), 100);
+    assert!(!timer.is_due(10_000));
+
+    // Re-arming doesn't move an already running timer.
+    timer.arm(500);
+    timer.arm(550);
+    assert!(timer.is_due(600));
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use bytes::Bytes;
+use common::connect_status;
+use ggpo::{
+    checksum::fletcher32,
+    core::{
+        game_input::{Frame, GameInput, InputBuffer, NULL_FRAME},
+        sync::{Config, GGPOSync, RollbackCallbacks, SavedState, SyncError},
+        GGPO_MAX_PREDICTION_FRAMES,
+    },
+};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+// A game driven by the core alone, with no session callbacks.
+#[derive(Debug, Default, Clone)]
+struct Counter {
+    frame: u32,
+    // Refuse to save this frame.
+    fail_at: Option<u32>,
+}
+
+impl RollbackCallbacks for Counter {
+    type Error = String;
+
+    fn save_state(&mut self, frame: Frame) -> Result<SavedState, String> {
+        if self.fail_at == frame.number() {
+            return Err(format!("no room for frame {}", frame));
+        }
+        Ok(SavedState {
+            data: Bytes::copy_from_slice(&self.frame.to_le_bytes()),
+            checksum: None,
+        })
+    }
+
+    fn load_state(&mut self, buffer: &Bytes, _length: usize) -> bool {
+        let mut frame = [0; 4];
+        frame.copy_from_slice(&buffer[..4]);
+        self.frame = u32::from_le_bytes(frame);
+        true
+    }
+
+    fn resimulate_frame(&mut self) -> bool {
+        self.frame += 1;
+        true
+    }
+}
+
+fn play(game: Counter, frames: u32) -> (GGPOSync<Counter>, Result<(), SyncError>) {
+    let game = Arc::new(Mutex::new(game));
+    let status = connect_status(1);
+    let mut sync = GGPOSync::new(&status);
+    let mut config = Config::new();
+    config.init(game.clone(), GGPO_MAX_PREDICTION_FRAMES, 1, 1);
+    sync.init(config).unwrap();
+
+    let mut run = || -> Result<(), SyncError> {
+        for _ in 0..frames {
+            let mut input = GameInput::init(NULL_FRAME, None, 1);
+            sync.add_local_input(0, &mut input)?;
+            let mut values: InputBuffer = Default::default();
+            sync.synchronize_inputs(&mut values)?;
+            sync.set_last_confirmed_frame(input.frame)?;
+            game.lock().frame += 1;
+            sync.increment_frame()?;
+        }
+        Ok(())
+    };
+    let result = run();
+    (sync, result)
+}
+
+#[test]
+fn the_core_saves_each_frame_through_the_callbacks() {
+    let (sync, result) = play(Counter::default(), 5);
+    result.unwrap();
+    for frame in 0..=5u32 {
+        assert_eq!(
+            sync.saved_checksum(Frame::new(frame)),
+            Some(fletcher32(&frame.to_le_bytes())),
+            "frame {}",
+            frame
+        );
+    }
+}
+
+#[test]
+fn a_failed_save_carries_the_games_message() {
+    let game = Counter {
+        fail_at: Some(3),
+        ..Default::default()
+    };
+    match play(game, 5).1 {
+        Err(SyncError::SaveGameState(message)) => assert_eq!(message, "no room for frame 3"),
+        other => panic!("expected the save to fail, got {:?}", other),
+    }
+}
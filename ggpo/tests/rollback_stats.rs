@@ -0,0 +1,93 @@
+mod common;
+
+use common::{connect_status, sync_with, MockGame, Recorder};
+use ggpo::{
+    backends::p2p::Peer2PeerBackend,
+    game_input::{
+        Frame, GameInput, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS, NULL_FRAME,
+    },
+    ggpo::Session,
+    sync::{GGPOSync, RollbackStats},
+};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+const INPUT_SIZE: usize = 1;
+
+fn advance(sync: &mut GGPOSync<MockGame>, frames: usize) {
+    for _ in 0..frames {
+        let mut local = GameInput::init(NULL_FRAME, None, INPUT_SIZE);
+        assert!(sync.add_local_input(0, &mut local).unwrap());
+        let mut values: InputBuffer = Default::default();
+        sync.synchronize_inputs(&mut values).unwrap();
+        sync.increment_frame().unwrap();
+    }
+}
+
+// The remote player's input for `frames`, all `value`.
+fn remote(sync: &mut GGPOSync<MockGame>, frames: std::ops::Range<u32>, value: u8) {
+    let mut bits = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    bits[0][0] = value;
+    for frame in frames {
+        sync.add_remote_input(
+            1,
+            &GameInput::init(Frame::new(frame), Some(&bits), INPUT_SIZE),
+        )
+        .unwrap();
+    }
+}
+
+#[test]
+fn rollbacks_are_counted_and_measured() {
+    let status = connect_status(2);
+    let mut sync = sync_with(
+        Arc::new(Mutex::new(MockGame::default())),
+        &status,
+        INPUT_SIZE,
+    );
+
+    advance(&mut sync, 5);
+    sync.check_simulation().unwrap();
+    assert_eq!(sync.rollback_stats().rollbacks, 0);
+    assert_eq!(sync.rollback_stats().frames_advanced, 5);
+
+    // Predicted idle through frame 4, but they pressed something on frame 2:
+    // frames 2 to 4 run again.
+    remote(&mut sync, 0..2, b'0');
+    remote(&mut sync, 2..3, 7);
+    sync.check_simulation().unwrap();
+    // Frames 3 and 4 are now predicted to repeat frame 2, which is right.
+    remote(&mut sync, 3..5, 7);
+    sync.check_simulation().unwrap();
+    // Both players' input is in through frame 4, which makes room to go on.
+    sync.set_last_confirmed_frame(Frame::new(4)).unwrap();
+
+    // Frames 5 to 8 predicted to repeat it too, but they let go on frame 7.
+    advance(&mut sync, 4);
+    remote(&mut sync, 5..7, 7);
+    remote(&mut sync, 7..8, b'0');
+    sync.check_simulation().unwrap();
+
+    let stats = sync.rollback_stats();
+    assert_eq!(
+        stats,
+        RollbackStats {
+            rollbacks: 2,
+            frames_resimulated: 5,
+            max_distance: 3,
+            frames_advanced: 9,
+        }
+    );
+    assert!((stats.mispredict_rate() - 2.0 / 9.0).abs() < f32::EPSILON);
+}
+
+#[test]
+fn a_fresh_session_has_no_rollbacks() {
+    let session =
+        Peer2PeerBackend::new(Arc::new(Mutex::new(Recorder::default())), 18180, 2, 1, None)
+            .expect("session");
+
+    let stats = session.lock().rollback_stats().unwrap();
+    assert_eq!(stats, RollbackStats::default());
+    assert_eq!(stats.mispredict_rate(), 0.0);
+}
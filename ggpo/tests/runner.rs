@@ -0,0 +1,138 @@
+use ggpo::{
+    game_input::{FrameNum, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS},
+    ggpo::{DisconnectFlags, Event, GGPOError, Session, TimeSyncEvent},
+    player::PlayerHandle,
+    runner::SessionRunner,
+};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{channel, Sender},
+        Arc,
+    },
+    time::Duration,
+};
+
+const NUM_PLAYERS: usize = 2;
+const FRAMES: FrameNum = 20;
+
+type Shared = Arc<Mutex<HashMap<FrameNum, [Option<[u8; GAMEINPUT_MAX_BYTES]>; NUM_PLAYERS]>>>;
+
+// Two of these sharing `inputs` stand in for a pair of peers joined by an
+// in-memory transport: a frame's inputs are available once both have sent theirs.
+struct LoopbackSession {
+    frame: FrameNum,
+    inputs: Shared,
+    events: Sender<Event>,
+    time_sync_at: Option<FrameNum>,
+}
+
+impl Session for LoopbackSession {
+    fn do_poll(&mut self, _timeout: Option<Duration>) -> Result<(), GGPOError> {
+        if self.time_sync_at == Some(self.frame) {
+            self.time_sync_at = None;
+            let _ = self
+                .events
+                .send(Event::TimeSync(TimeSyncEvent { frames_ahead: 2 }));
+        }
+        Ok(())
+    }
+
+    fn add_local_input(
+        &mut self,
+        player: PlayerHandle,
+        values: &InputBuffer,
+        _size: usize,
+    ) -> Result<(), GGPOError> {
+        let player = player as usize;
+        self.inputs.lock().entry(self.frame).or_default()[player] = Some(values[player]);
+        Ok(())
+    }
+
+    fn synchronize_input(
+        &self,
+        values: &mut InputBuffer,
+        disconnect_flags: Option<&mut DisconnectFlags>,
+    ) -> Result<(), GGPOError> {
+        let inputs = self.inputs.lock();
+        let frame = inputs.get(&self.frame).ok_or(GGPOError::NotSynchronized)?;
+        for player in 0..NUM_PLAYERS {
+            values[player] = frame[player].ok_or(GGPOError::NotSynchronized)?;
+        }
+        if let Some(flags) = disconnect_flags {
+            *flags = DisconnectFlags::default();
+        }
+        Ok(())
+    }
+
+    fn increment_frame(&mut self) -> Result<(), GGPOError> {
+        self.frame += 1;
+        Ok(())
+    }
+}
+
+fn runner(
+    player: usize,
+    inputs: &Shared,
+    time_sync_at: Option<FrameNum>,
+) -> (
+    SessionRunner<LoopbackSession>,
+    std::sync::mpsc::Receiver<Event>,
+) {
+    let (sender, receiver) = channel();
+    let session = LoopbackSession {
+        frame: 0,
+        inputs: inputs.clone(),
+        events: sender,
+        time_sync_at,
+    };
+    let (mut runner, events) = SessionRunner::new(Arc::new(Mutex::new(session)), 1000, 1, receiver);
+    runner.add_local_player(player as PlayerHandle);
+    (runner, events)
+}
+
+fn local_input(handle: PlayerHandle) -> InputBuffer {
+    let mut values = [[0; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    values[handle as usize][0] = handle as u8 + 1;
+    values
+}
+
+#[test]
+fn both_sessions_advance_in_lockstep() {
+    let inputs: Shared = Default::default();
+    let (mut first, first_events) = runner(0, &inputs, Some(5));
+    let (mut second, _) = runner(1, &inputs, None);
+    let mut first_state = 0u32;
+    let mut second_state = 0u32;
+
+    while first.frames_advanced() < FRAMES || second.frames_advanced() < FRAMES {
+        if first.frames_advanced() < FRAMES {
+            first
+                .tick(local_input, |values, _| {
+                    first_state =
+                        first_state.wrapping_mul(3) + values[0][0] as u32 + values[1][0] as u32
+                })
+                .unwrap();
+        }
+        if second.frames_advanced() < FRAMES {
+            second
+                .tick(local_input, |values, _| {
+                    second_state =
+                        second_state.wrapping_mul(3) + values[0][0] as u32 + values[1][0] as u32
+                })
+                .unwrap();
+        }
+    }
+
+    assert_eq!(first.frames_advanced(), FRAMES);
+    assert_eq!(second.frames_advanced(), FRAMES);
+    assert_eq!(first.session().lock().frame, FRAMES);
+    assert_eq!(second.session().lock().frame, FRAMES);
+    assert_eq!(first_state, second_state);
+
+    // The time sync request was passed through to the game.
+    assert!(first_events
+        .try_iter()
+        .any(|event| matches!(event, Event::TimeSync(_))));
+}
@@ -0,0 +1,28 @@
+mod common;
+
+use common::{connect_status, sync_with, MockGame};
+use ggpo::game_input::{Frame, GameInput, NULL_FRAME};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+#[test]
+fn session_keeps_the_returned_state_for_rollback() {
+    let game = Arc::new(Mutex::new(MockGame::default()));
+    let status = connect_status(1);
+    let mut sync = sync_with(game.clone(), &status, 1);
+
+    // Adding the first input saves frame 0.
+    let mut input = GameInput::init(NULL_FRAME, None, 1);
+    sync.add_local_input(0, &mut input).unwrap();
+    let saved = sync.get_last_saved_frame();
+    assert_eq!(saved.frame(), Frame::new(0));
+    assert_eq!(saved.checksum(), Some(0));
+    assert_eq!(&saved.buffer()[..], &0u32.to_le_bytes()[..]);
+
+    game.lock().frames_advanced = 5;
+    sync.increment_frame().unwrap();
+    let saved = sync.get_last_saved_frame();
+    assert_eq!(saved.frame(), Frame::new(1));
+    assert_eq!(saved.checksum(), Some(5));
+    assert_eq!(&saved.buffer()[..], &5u32.to_le_bytes()[..]);
+}
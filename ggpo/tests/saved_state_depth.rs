@@ -0,0 +1,61 @@
+mod common;
+
+use common::{connect_status, MockGame};
+use ggpo::{
+    game_input::Frame,
+    ggpo::{GGPOError, GGPO_MAX_PREDICTION_FRAMES},
+    sync::{Config, GGPOSync, SyncError},
+};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+fn sync_with_depth(depth: Option<usize>) -> GGPOSync<MockGame> {
+    let status = connect_status(1);
+    let mut sync = GGPOSync::new(&status);
+    let mut config = Config::new();
+    config.init(
+        Arc::new(Mutex::new(MockGame::default())),
+        GGPO_MAX_PREDICTION_FRAMES,
+        1,
+        1,
+    );
+    config.saved_state_depth = depth;
+    sync.init(config).unwrap();
+    sync
+}
+
+#[test]
+fn depth_defaults_to_the_prediction_window_plus_two() {
+    let sync = sync_with_depth(None);
+    assert_eq!(
+        sync.saved_state_depth(),
+        GGPO_MAX_PREDICTION_FRAMES as usize + 2
+    );
+}
+
+#[test]
+fn oldest_states_are_evicted_once_the_ring_is_full() {
+    let mut sync = sync_with_depth(Some(3));
+    sync.save_current_frame().unwrap();
+    for _ in 0..5 {
+        sync.increment_frame().unwrap();
+    }
+
+    // Frames 3, 4 and 5 are retained; 0 through 2 have been evicted.
+    for frame in 0..3 {
+        assert_eq!(sync.find_saved_frame_index(Frame::new(frame)), None);
+    }
+    for frame in 3..=5 {
+        assert!(sync.find_saved_frame_index(Frame::new(frame)).is_some());
+    }
+
+    match sync.load_frame(Frame::new(2)) {
+        Err(e @ SyncError::FrameNotSaved(_)) => {
+            assert!(matches!(GGPOError::from(e), GGPOError::GeneralFailure))
+        }
+        other => panic!("expected FrameNotSaved, got {:?}", other),
+    }
+
+    sync.load_frame(Frame::new(3)).unwrap();
+    assert_eq!(sync.get_frame_count(), 3);
+}
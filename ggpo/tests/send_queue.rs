@@ -0,0 +1,79 @@
+mod common;
+
+use common::localhost;
+use ggpo::network::{
+    udp::{Udp, UdpCallback, UdpError},
+    udp_msg::{MsgType, UdpMsg},
+};
+use mio::Poll;
+use parking_lot::Mutex;
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+struct Ignore;
+
+impl UdpCallback for Ignore {
+    fn on_msg(&mut self, _from: &SocketAddr, _msg: UdpMsg, _len: usize) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+fn bound_udp(port: u16) -> Udp<Ignore> {
+    let mut udp = Udp::new();
+    udp.init(
+        port,
+        Arc::new(Mutex::new(Poll::new().unwrap())),
+        Arc::new(Mutex::new(Ignore)),
+    )
+    .unwrap();
+    udp
+}
+
+fn keep_alive(sequence_number: u16) -> Arc<UdpMsg> {
+    let mut msg = UdpMsg::new(MsgType::KeepAlive);
+    msg.header.sequence_number = sequence_number;
+    Arc::new(msg)
+}
+
+#[test]
+fn a_full_queue_refuses_more_packets() {
+    // No send task, so nothing drains the queue.
+    let mut udp = bound_udp(17890);
+    udp.set_send_queue_capacity(4);
+    let destination = [localhost(17895)];
+    for seq in 0..4 {
+        udp.send_to(keep_alive(seq), &destination).unwrap();
+    }
+    match udp.send_to(keep_alive(4), &destination) {
+        Err(UdpError::SendQueueFull { capacity }) => assert_eq!(capacity, 4),
+        other => panic!("expected a full queue, got {:?}", other),
+    }
+    assert_eq!(udp.queued_sends(), 4);
+}
+
+#[test]
+fn packets_go_out_in_the_order_they_were_queued() {
+    let mut sender = bound_udp(17900);
+    let mut receiver = bound_udp(17910);
+    let destination = [localhost(17910)];
+    for seq in 0..32 {
+        sender.send_to(keep_alive(seq), &destination).unwrap();
+    }
+    sender.start_send_task().unwrap();
+
+    let mut received = Vec::new();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while received.len() < 32 {
+        assert!(Instant::now() < deadline, "only got {:?}", received);
+        for (msg, _, _) in receiver.recv_pending().unwrap() {
+            received.push(msg.header.sequence_number);
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(received, (0..32).collect::<Vec<u16>>());
+    assert_eq!(sender.queued_sends(), 0);
+    sender.close().unwrap();
+}
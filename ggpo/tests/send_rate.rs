@@ -0,0 +1,144 @@
+mod common;
+
+use bytes::Bytes;
+use common::{localhost, peer, synchronize_by, Peer};
+use ggpo::{
+    game_input::InputBuffer,
+    ggpo::Session,
+    network::{udp_msg::UdpMsg, udp_proto::UDP_HEADER_SIZE},
+    player::PlayerHandle,
+};
+use std::{
+    collections::VecDeque,
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+const SEND_RATE_LIMIT: usize = 1500;
+const RELAY_DELAY: Duration = Duration::from_millis(30);
+
+/*
+ * Sits between the two peers and holds every packet for `RELAY_DELAY`, so
+ * unacked input stays unacked long enough for the retransmit timer to fire.
+ * Each side only talks to its own end of the relay, which forwards from the
+ * other end, so both peers see packets coming from the address they expect.
+ */
+struct Relay {
+    a_side: UdpSocket,
+    b_side: UdpSocket,
+    a_addr: SocketAddr,
+    b_addr: SocketAddr,
+    in_flight: VecDeque<(Instant, bool, Vec<u8>)>,
+    // When each of A's packets arrived, and how big it is by the limiter's count.
+    from_a: Vec<(Instant, usize)>,
+}
+
+impl Relay {
+    fn new(a_side: u16, b_side: u16, a_addr: SocketAddr, b_addr: SocketAddr) -> Self {
+        let a_side = UdpSocket::bind(localhost(a_side)).unwrap();
+        let b_side = UdpSocket::bind(localhost(b_side)).unwrap();
+        a_side.set_nonblocking(true).unwrap();
+        b_side.set_nonblocking(true).unwrap();
+        Self {
+            a_side,
+            b_side,
+            a_addr,
+            b_addr,
+            in_flight: VecDeque::new(),
+            from_a: Vec::new(),
+        }
+    }
+
+    fn pump(&mut self) {
+        let now = Instant::now();
+        let mut buf = [0u8; 4096];
+        while let Ok(len) = self.a_side.recv(&mut buf) {
+            let packet = zstd::block::decompress(&buf[..len], 4096).unwrap();
+            let msg = UdpMsg::decode(Bytes::from(packet)).unwrap();
+            self.from_a.push((now, msg.packet_size() + UDP_HEADER_SIZE));
+            self.in_flight.push_back((now, true, buf[..len].to_vec()));
+        }
+        while let Ok(len) = self.b_side.recv(&mut buf) {
+            self.in_flight.push_back((now, false, buf[..len].to_vec()));
+        }
+        while let Some((received, _, _)) = self.in_flight.front() {
+            if *received + RELAY_DELAY > now {
+                break;
+            }
+            let (_, to_b, packet) = self.in_flight.pop_front().unwrap();
+            if to_b {
+                self.b_side.send_to(&packet, self.b_addr).unwrap();
+            } else {
+                self.a_side.send_to(&packet, self.a_addr).unwrap();
+            }
+        }
+    }
+
+    // The most A sent in any stretch shorter than the limiter's window.
+    fn busiest_window(&self, window: Duration) -> usize {
+        (0..self.from_a.len())
+            .map(|start| {
+                let (opened, _) = self.from_a[start];
+                self.from_a[start..]
+                    .iter()
+                    .take_while(|(time, _)| *time < opened + window)
+                    .map(|(_, bytes)| bytes)
+                    .sum()
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+fn poll(a: &Peer, b: &Peer, relay: &mut Relay) {
+    a.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+    relay.pump();
+    b.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+    relay.pump();
+}
+
+fn advance(session: &Peer, handle: PlayerHandle) {
+    let mut session = session.lock();
+    let local: InputBuffer = Default::default();
+    session.add_local_input(handle, &local, 1).unwrap();
+    let mut values: InputBuffer = Default::default();
+    session.synchronize_input(&mut values, None).unwrap();
+    session.increment_frame().unwrap();
+}
+
+#[test]
+fn capped_peer_stays_under_its_send_rate() {
+    // A talks to the relay on 17510, B on 17520.
+    let (a, a_events) = peer(17500, 1, 17510);
+    let (b, b_events) = peer(17530, 2, 17520);
+    let mut relay = Relay::new(17510, 17520, localhost(17500), localhost(17530));
+    {
+        let mut a = a.lock();
+        // Retransmitting every millisecond would swamp the cap on its own.
+        a.set_retransmit_interval(1).unwrap();
+        a.set_send_rate_limit(SEND_RATE_LIMIT).unwrap();
+    }
+
+    synchronize_by(&[&a_events, &b_events], || poll(&a, &b, &mut relay));
+
+    // Ten frames a second for three seconds.  The input fits under the cap
+    // only if the resends give way to it; if it didn't get through, B would
+    // hit the prediction threshold and `add_local_input` would fail.
+    for _ in 0..30 {
+        advance(&a, 1);
+        advance(&b, 2);
+        let next_frame = Instant::now() + Duration::from_millis(100);
+        while Instant::now() < next_frame {
+            poll(&a, &b, &mut relay);
+        }
+    }
+
+    // Fresh input and acks go out whether or not there's room, so a window can
+    // run over by what those add: about 1.2KB a second at this frame rate.
+    let busiest = relay.busiest_window(Duration::from_millis(950));
+    assert!(
+        busiest <= SEND_RATE_LIMIT + 1300,
+        "sent {} bytes in under a second",
+        busiest
+    );
+}
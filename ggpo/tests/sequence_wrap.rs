@@ -0,0 +1,76 @@
+use bytes::Bytes;
+use ggpo::{
+    game_input::Frame,
+    network::{
+        udp::UdpCallback,
+        udp_msg::{seq_after, MsgEnum, MsgType, UdpMsg},
+        udp_proto::{Event, UdpProtocol},
+    },
+};
+use std::net::SocketAddr;
+
+struct Ignore;
+
+impl UdpCallback for Ignore {
+    fn on_msg(&mut self, _from: &SocketAddr, _msg: UdpMsg, _len: usize) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+// An input message, numbered `seq`, for one frame with no buttons held.
+fn input_msg(seq: u16, frame: u32) -> UdpMsg {
+    let mut msg = UdpMsg::new(MsgType::Input);
+    msg.header.sequence_number = seq;
+    if let MsgEnum::Input(input) = &mut msg.message {
+        input.start_frame = Frame::new(frame);
+        input.input_size = 1;
+        // Nothing changed since the frame before.
+        input.num_bits = 1;
+        input.bits = Bytes::from_static(&[0]);
+    }
+    msg
+}
+
+// Whether the endpoint took the message, going by whether its frame came out.
+fn took(endpoint: &mut UdpProtocol<Ignore>, seq: u16, frame: u32) -> bool {
+    endpoint.on_msg(&input_msg(seq, frame)).unwrap();
+    let mut got = false;
+    let mut event = Event::Unknown;
+    while endpoint.get_event(&mut event) {
+        if let Event::Input(input) = &event {
+            got |= input.frame == Frame::new(frame);
+        }
+    }
+    got
+}
+
+#[test]
+fn sequence_numbers_compare_across_the_wrap() {
+    assert!(seq_after(1, 65535));
+    assert!(seq_after(0, 65535));
+    assert!(!seq_after(65535, 1));
+    assert!(seq_after(100, 65000));
+    assert!(!seq_after(65000, 100));
+    assert!(seq_after(2, 1));
+    assert!(!seq_after(1, 2));
+    assert!(!seq_after(7, 7));
+    // Half way round, either could be first.
+    assert!(!seq_after(1 << 15, 0));
+    assert!(!seq_after(0, 1 << 15));
+}
+
+#[test]
+fn packets_past_the_wrap_are_not_dropped_as_old() {
+    let mut endpoint = UdpProtocol::<Ignore>::new();
+    endpoint.set_input_size(1);
+    // A long session's worth of packets, in strides short enough to follow.
+    for (frame, &seq) in [20000, 40000, 60000, 65534, 65535].iter().enumerate() {
+        assert!(took(&mut endpoint, seq, frame as u32), "seq {}", seq);
+    }
+    assert!(took(&mut endpoint, 1, 5));
+    assert!(took(&mut endpoint, 2, 6));
+
+    // From the other side of the wrap, the packets before it are the old ones.
+    assert!(!took(&mut endpoint, 65535, 7));
+    assert!(took(&mut endpoint, 3, 7));
+}
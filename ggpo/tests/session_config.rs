@@ -0,0 +1,144 @@
+mod common;
+
+use common::{synchronize, Recorder};
+use ggpo::{
+    backends::p2p::Peer2PeerBackend,
+    config::SessionConfig,
+    game_input::GAMEINPUT_MAX_BYTES,
+    ggpo::{GGPOError, Session},
+    player::{Player, PlayerHandle, PlayerType},
+    time_sync::MAX_AUTO_FRAME_DELAY,
+};
+use parking_lot::Mutex;
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+};
+
+fn invalid(config: SessionConfig) -> bool {
+    matches!(config.validate(), Err(GGPOError::InvalidRequest))
+}
+
+#[test]
+fn the_default_config_is_valid() {
+    assert!(SessionConfig::default().validate().is_ok());
+}
+
+#[test]
+fn out_of_range_settings_are_rejected() {
+    assert!(invalid(SessionConfig {
+        num_players: 0,
+        ..Default::default()
+    }));
+    assert!(invalid(SessionConfig {
+        num_players: 5,
+        ..Default::default()
+    }));
+    assert!(invalid(SessionConfig {
+        input_size: 0,
+        ..Default::default()
+    }));
+    assert!(invalid(SessionConfig {
+        input_size: GAMEINPUT_MAX_BYTES + 1,
+        ..Default::default()
+    }));
+    assert!(invalid(SessionConfig {
+        prediction_frames: 0,
+        ..Default::default()
+    }));
+}
+
+#[test]
+fn contradictory_settings_are_rejected() {
+    assert!(invalid(SessionConfig {
+        disconnect_timeout: 1000,
+        disconnect_notify_start: 1000,
+        ..Default::default()
+    }));
+    // With no timeout there's nothing for the notification to come after.
+    assert!(SessionConfig {
+        disconnect_timeout: 0,
+        disconnect_notify_start: 1000,
+        ..Default::default()
+    }
+    .validate()
+    .is_ok());
+
+    assert!(invalid(SessionConfig {
+        prediction_frames: 8,
+        saved_state_depth: Some(8),
+        ..Default::default()
+    }));
+    assert!(invalid(SessionConfig {
+        prediction_frames: 8,
+        frame_delay: 2,
+        input_queue_length: Some(10),
+        ..Default::default()
+    }));
+    assert!(SessionConfig {
+        prediction_frames: 8,
+        frame_delay: 2,
+        saved_state_depth: Some(9),
+        input_queue_length: Some(11),
+        ..Default::default()
+    }
+    .validate()
+    .is_ok());
+    // Auto mode may raise the delay as far as `MAX_AUTO_FRAME_DELAY`.
+    assert!(invalid(SessionConfig {
+        prediction_frames: 8,
+        auto_frame_delay: true,
+        input_queue_length: Some(8 + MAX_AUTO_FRAME_DELAY),
+        ..Default::default()
+    }));
+}
+
+#[test]
+fn from_config_validates_first() {
+    let config = SessionConfig {
+        local_port: 17690,
+        num_players: 0,
+        ..Default::default()
+    };
+    let result = Peer2PeerBackend::from_config(config, Arc::new(Mutex::new(Recorder::default())));
+    assert!(matches!(result, Err(GGPOError::InvalidRequest)));
+}
+
+#[test]
+fn sessions_from_config_synchronize() {
+    let peer = |port: u16, local: usize, remote_port: u16| {
+        let recorder = Recorder::default();
+        let config = SessionConfig {
+            local_port: port,
+            input_size: 1,
+            frame_delay: 2,
+            disconnect_timeout: 3000,
+            disconnect_notify_start: 500,
+            ..Default::default()
+        };
+        let session = Peer2PeerBackend::from_config(config, Arc::new(Mutex::new(recorder.clone())))
+            .expect("session");
+        {
+            let mut session = session.lock();
+            let mut handle: PlayerHandle = 0;
+            for player_num in 1..=2 {
+                let player_type = if player_num == local {
+                    PlayerType::Local
+                } else {
+                    PlayerType::Remote(SocketAddr::new(
+                        IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                        remote_port,
+                    ))
+                };
+                session
+                    .add_player(Player::new(player_type, player_num), &mut handle)
+                    .unwrap();
+            }
+        }
+        (session, recorder)
+    };
+    let (a, a_events) = peer(17700, 1, 17710);
+    let (b, b_events) = peer(17710, 2, 17700);
+
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+}
@@ -0,0 +1,19 @@
+mod common;
+
+use common::{peer, synchronize};
+use ggpo::ggpo::{GGPOError, Session};
+
+#[test]
+fn synced_sessions_agree_on_the_seed() {
+    let (a, a_events) = peer(17480, 1, 17490);
+    let (b, b_events) = peer(17490, 2, 17480);
+    assert!(matches!(
+        a.lock().shared_seed(),
+        Err(GGPOError::NotSynchronized)
+    ));
+
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+
+    let seed = a.lock().shared_seed().unwrap();
+    assert_eq!(b.lock().shared_seed().unwrap(), seed);
+}
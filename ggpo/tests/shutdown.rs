@@ -0,0 +1,23 @@
+mod common;
+
+use common::{peer, synchronize};
+use ggpo::ggpo::{Event, Session};
+use std::time::Duration;
+
+#[test]
+fn peers_hear_about_a_shutdown_right_away() {
+    let (a, a_events) = peer(17630, 1, 17640);
+    let (b, b_events) = peer(17640, 2, 17630);
+    // Long enough that only the goodbye can explain B dropping A in time.
+    b.lock().set_disconnect_timeout(60_000).unwrap();
+
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+
+    a.lock().shutdown().unwrap();
+    b.lock().do_poll(Some(Duration::from_millis(50))).unwrap();
+
+    assert!(b_events.saw(|e| match e {
+        Event::DisconnectedFromPeer(disconnected) => disconnected.player == 1,
+        _ => false,
+    }));
+}
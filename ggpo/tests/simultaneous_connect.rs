@@ -0,0 +1,113 @@
+mod common;
+
+use common::{localhost, peer, poll_until, synchronize};
+use ggpo::{
+    ggpo::Session,
+    network::{
+        udp::{Udp, UdpCallback},
+        udp_msg::{ConnectStatus, UdpMsg, UDP_MSG_MAX_PLAYERS},
+        udp_proto::{combine_seed_nonces, UdpProtocol},
+    },
+};
+use mio::Poll;
+use parking_lot::Mutex;
+use std::{net::SocketAddr, sync::Arc, thread, time::Duration};
+
+struct Ignore;
+
+impl UdpCallback for Ignore {
+    fn on_msg(&mut self, _from: &SocketAddr, _msg: UdpMsg, _len: usize) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+struct Side {
+    endpoint: UdpProtocol<Ignore>,
+    udp: Arc<Mutex<Udp<Ignore>>>,
+}
+
+fn side(port: u16, peer_port: u16, nonce: u64) -> Side {
+    let mut udp = Udp::new();
+    udp.init(
+        port,
+        Arc::new(Mutex::new(Poll::new().unwrap())),
+        Arc::new(Mutex::new(Ignore)),
+    )
+    .unwrap();
+    udp.start_send_task().unwrap();
+    let udp = Arc::new(Mutex::new(udp));
+    let status: [Arc<Mutex<ConnectStatus>>; UDP_MSG_MAX_PLAYERS] = Default::default();
+    let mut endpoint = UdpProtocol::new();
+    endpoint.init(udp.clone(), 0, localhost(peer_port), &status);
+    endpoint.set_input_size(1);
+    endpoint.set_seed_nonce(nonce);
+    Side { endpoint, udp }
+}
+
+fn deliver(side: &mut Side) {
+    thread::sleep(Duration::from_millis(5));
+    let msgs = side.udp.lock().recv_pending().unwrap();
+    for (msg, _, _) in msgs {
+        side.endpoint.on_msg(&msg).unwrap();
+    }
+}
+
+/*
+ * Both ends open at once, then the one at `first` hears everything sent to
+ * it before the other does, each round.  The negotiated seed, which both
+ * must agree on.
+ */
+fn connect(ports: (u16, u16), nonces: (u64, u64), a_first: bool) -> u64 {
+    let mut a = side(ports.0, ports.1, nonces.0);
+    let mut b = side(ports.1, ports.0, nonces.1);
+    a.endpoint.synchronize().unwrap();
+    b.endpoint.synchronize().unwrap();
+    poll_until("never synchronized", || {
+        if a.endpoint.is_running() && b.endpoint.is_running() {
+            return true;
+        }
+        if a_first {
+            deliver(&mut a);
+            deliver(&mut b);
+        } else {
+            deliver(&mut b);
+            deliver(&mut a);
+        }
+        false
+    });
+
+    let seed = a.endpoint.negotiated_seed(&[]).unwrap();
+    assert_eq!(b.endpoint.negotiated_seed(&[]), Some(seed));
+    seed
+}
+
+#[test]
+fn both_ends_agree_whichever_hears_the_other_first() {
+    let nonces = (0x1234_5678_9abc_def0, 0x0fed_cba9_8765_4321);
+    let seed = connect((19220, 19230), nonces, true);
+    let reversed = connect((19240, 19250), nonces, false);
+    assert_eq!(seed, reversed);
+    assert_eq!(seed, combine_seed_nonces(&mut [nonces.1, nonces.0]));
+}
+
+#[test]
+fn equal_nonces_dont_cancel_out() {
+    let seed = connect((19260, 19270), (42, 42), true);
+    assert_ne!(seed, 0);
+    assert_eq!(seed, combine_seed_nonces(&mut [42]));
+}
+
+#[test]
+fn sessions_agree_on_the_seed_whichever_is_polled_first() {
+    for &(ports, a_first) in [((19360, 19370), true), ((19380, 19390), false)].iter() {
+        let (a, a_events) = peer(ports.0, 1, ports.1);
+        let (b, b_events) = peer(ports.1, 2, ports.0);
+        if a_first {
+            synchronize(&[(&a, &a_events), (&b, &b_events)]);
+        } else {
+            synchronize(&[(&b, &b_events), (&a, &a_events)]);
+        }
+        let seed = a.lock().shared_seed().unwrap();
+        assert_eq!(b.lock().shared_seed().unwrap(), seed);
+    }
+}
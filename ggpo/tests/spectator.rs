@@ -0,0 +1,138 @@
+mod common;
+
+use bytes::Bytes;
+use common::{config, host_with, localhost, poll, synchronize_by, Recorder};
+use ggpo::{
+    backends::spectator::SpectatorBackend,
+    game_input::{Frame, InputBuffer},
+    ggpo::{Event, GGPOError, GGPOSessionCallbacks, SavedState, Session},
+};
+use parking_lot::Mutex;
+use std::{sync::Arc, time::Duration};
+
+const HOST_PORT: u16 = 17540;
+const SPECTATOR_PORT: u16 = 17550;
+
+// Remembers which of the host's inputs it played, and how.
+#[derive(Debug, Default, Clone)]
+struct Viewer {
+    events: Recorder,
+    played: Arc<Mutex<Vec<u8>>>,
+    fast_forwarded: Arc<Mutex<usize>>,
+}
+
+impl GGPOSessionCallbacks for Viewer {
+    fn save_game_state(&mut self, _frame: Frame) -> Result<SavedState, GGPOError> {
+        Ok(SavedState::default())
+    }
+
+    fn load_game_state(&mut self, _buffer: &Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn log_game_state(&mut self, _filename: String, _buffer: Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn advance_frame(&mut self, _flags: i32) -> bool {
+        true
+    }
+
+    fn fast_forward_frame(&mut self, inputs: &InputBuffer, _disconnect_flags: i32) -> bool {
+        self.played.lock().push(inputs[0][0]);
+        *self.fast_forwarded.lock() += 1;
+        true
+    }
+
+    fn on_event(&mut self, info: &Event) {
+        self.events.on_event(info);
+    }
+}
+
+#[test]
+fn spectator_fast_forwards_to_the_host() {
+    let host_events = Recorder::default();
+    let host = host_with(
+        config(HOST_PORT),
+        Arc::new(Mutex::new(host_events.clone())),
+        &[SPECTATOR_PORT],
+    );
+
+    let viewer = Viewer::default();
+    let spectator = SpectatorBackend::new(
+        Arc::new(Mutex::new(viewer.clone())),
+        SPECTATOR_PORT,
+        1,
+        1,
+        localhost(HOST_PORT),
+    )
+    .unwrap();
+    spectator.lock().set_catch_up_threshold(8).unwrap();
+
+    synchronize_by(&[&host_events, &viewer.events], || {
+        poll(&host);
+        poll(&spectator);
+    });
+
+    // The host races 40 frames ahead while the spectator isn't looking.
+    const HOST_FRAMES: u8 = 40;
+    for frame in 0..HOST_FRAMES {
+        let mut host = host.lock();
+        let mut values: InputBuffer = Default::default();
+        values[0][0] = frame;
+        host.add_local_input(1, &values, 1).unwrap();
+        host.synchronize_input(&mut values, None).unwrap();
+        host.increment_frame().unwrap();
+    }
+
+    // Then keeps polling while the spectator ticks along, one rendered frame a
+    // tick on top of whatever it fast-forwards.
+    let mut ticks = 0;
+    while viewer.played.lock().len() < HOST_FRAMES as usize {
+        assert!(ticks < 100, "spectator never got the host's frames");
+        ticks += 1;
+        host.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+        let mut spectator = spectator.lock();
+        spectator.do_poll(Some(Duration::from_millis(1))).unwrap();
+        let mut values: InputBuffer = Default::default();
+        match spectator.synchronize_input(&mut values, None) {
+            Ok(()) => {
+                viewer.played.lock().push(values[0][0]);
+                spectator.increment_frame().unwrap();
+            }
+            Err(GGPOError::PredictionThreshold) => {}
+            Err(e) => panic!("synchronize_input failed: {}", e),
+        }
+    }
+
+    assert_eq!(*viewer.played.lock(), (0..HOST_FRAMES).collect::<Vec<_>>());
+    assert!(*viewer.fast_forwarded.lock() > 0);
+    assert!(!spectator.lock().is_catching_up());
+    let last_frame = Frame::new(HOST_FRAMES as u32 - 1);
+    assert!(viewer.events.saw(|e| match e {
+        Event::CaughtUp(caught_up) => caught_up.frame == last_frame,
+        _ => false,
+    }));
+
+    // 16 frames a poll, plus the one rendered each tick, gets through a 40
+    // frame backlog in three ticks once the packets are in.
+    assert!(ticks <= 10, "took {} ticks to catch up", ticks);
+}
+
+#[test]
+fn catch_up_threshold_leaves_room_in_the_ring() {
+    let spectator = SpectatorBackend::new(
+        Arc::new(Mutex::new(Viewer::default())),
+        17560,
+        2,
+        1,
+        localhost(17570),
+    )
+    .unwrap();
+    assert!(matches!(
+        spectator
+            .lock()
+            .set_catch_up_threshold(ggpo::backends::spectator::SPECTATOR_FRAME_BUFFER_SIZE),
+        Err(GGPOError::InvalidRequest)
+    ));
+}
@@ -0,0 +1,184 @@
+mod common;
+
+use bytes::Bytes;
+use common::{add_spectator, config, host_with, localhost, poll, synchronize_by, Peer, Recorder};
+use ggpo::{
+    backends::spectator::SpectatorBackend,
+    config::SessionConfig,
+    game_input::{Frame, InputBuffer},
+    ggpo::{Event, GGPOError, GGPOSessionCallbacks, SavedState, Session},
+    player::PlayerHandle,
+};
+use parking_lot::Mutex;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+type Spectator = Arc<Mutex<SpectatorBackend<Viewer>>>;
+
+// Remembers the host's inputs as it plays them.
+#[derive(Debug, Default, Clone)]
+struct Viewer {
+    events: Recorder,
+    played: Arc<Mutex<Vec<u8>>>,
+}
+
+impl GGPOSessionCallbacks for Viewer {
+    fn save_game_state(&mut self, _frame: Frame) -> Result<SavedState, GGPOError> {
+        Ok(SavedState::default())
+    }
+
+    fn load_game_state(&mut self, _buffer: &Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn log_game_state(&mut self, _filename: String, _buffer: Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn advance_frame(&mut self, _flags: i32) -> bool {
+        true
+    }
+
+    fn fast_forward_frame(&mut self, inputs: &InputBuffer, _disconnect_flags: i32) -> bool {
+        self.played.lock().push(inputs[0][0]);
+        true
+    }
+
+    fn on_event(&mut self, info: &Event) {
+        self.events.on_event(info);
+    }
+}
+
+struct Match {
+    host: Peer,
+    host_events: Recorder,
+    watching: (Spectator, Viewer),
+    stalled_handle: PlayerHandle,
+}
+
+/*
+ * A one player host with two spectators, all running.  The second stops
+ * polling from here on, so nothing it's sent gets acked.
+ */
+fn start(port: u16, spectator_lag_window: usize) -> Match {
+    let host_events = Recorder::default();
+    let config = SessionConfig {
+        spectator_lag_window,
+        ..config(port)
+    };
+    let host = host_with(config, Arc::new(Mutex::new(host_events.clone())), &[]);
+    let handles: Vec<PlayerHandle> = (1..=2)
+        .map(|i| add_spectator(&mut host.lock(), port + 10 * i, 2))
+        .collect();
+
+    let spectators: Vec<(Spectator, Viewer)> = (1..=2)
+        .map(|i| {
+            let viewer = Viewer::default();
+            let spectator = SpectatorBackend::new(
+                Arc::new(Mutex::new(viewer.clone())),
+                port + 10 * i,
+                1,
+                1,
+                localhost(port),
+            )
+            .unwrap();
+            (spectator, viewer)
+        })
+        .collect();
+    let mut events = vec![&host_events];
+    events.extend(spectators.iter().map(|(_, viewer)| &viewer.events));
+    synchronize_by(&events, || {
+        poll(&host);
+        for (spectator, _) in spectators.iter() {
+            poll(spectator);
+        }
+    });
+
+    Match {
+        host,
+        host_events,
+        watching: spectators[0].clone(),
+        stalled_handle: handles[1],
+    }
+}
+
+// Runs the host for `frames` frames of `input`, with the watching spectator playing along.
+fn play(game: &Match, frames: u32, input: impl Fn(u32) -> u8) {
+    for frame in 0..frames {
+        {
+            let mut host = game.host.lock();
+            let mut values: InputBuffer = Default::default();
+            values[0][0] = input(frame);
+            host.add_local_input(1, &values, 1).unwrap();
+            host.synchronize_input(&mut values, None).unwrap();
+            host.increment_frame().unwrap();
+            host.do_poll(Some(Duration::from_millis(1))).unwrap();
+        }
+        watch(game);
+    }
+}
+
+// Polls the watching spectator, and plays a frame if it has one.
+fn watch(game: &Match) {
+    let (spectator, viewer) = &game.watching;
+    let mut spectator = spectator.lock();
+    spectator.do_poll(Some(Duration::from_millis(1))).unwrap();
+    let mut values: InputBuffer = Default::default();
+    if spectator.synchronize_input(&mut values, None).is_ok() {
+        viewer.played.lock().push(values[0][0]);
+        spectator.increment_frame().unwrap();
+    }
+}
+
+// Whom the host reported dropping.
+fn dropped(game: &Match) -> Vec<PlayerHandle> {
+    game.host_events
+        .events
+        .lock()
+        .iter()
+        .filter_map(|e| match e {
+            Event::DisconnectedFromPeer(disconnected) => Some(disconnected.player),
+            _ => None,
+        })
+        .collect()
+}
+
+// Has the watching spectator play everything it hasn't yet.
+fn finish_watching(game: &Match, frames: usize) {
+    let viewer = &game.watching.1;
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while viewer.played.lock().len() < frames {
+        assert!(Instant::now() < deadline, "spectator fell behind");
+        game.host
+            .lock()
+            .do_poll(Some(Duration::from_millis(1)))
+            .unwrap();
+        watch(game);
+    }
+}
+
+#[test]
+fn a_stalled_spectator_is_dropped_and_the_rest_play_on() {
+    let game = start(19160, 16);
+    play(&game, 60, |frame| frame as u8);
+
+    assert_eq!(dropped(&game), vec![game.stalled_handle]);
+    finish_watching(&game, 60);
+    let viewer = &game.watching.1;
+    assert_eq!(*viewer.played.lock(), (0..60).collect::<Vec<u8>>());
+    assert!(!viewer
+        .events
+        .saw(|e| matches!(e, Event::DisconnectedFromPeer(_))));
+}
+
+#[test]
+fn a_backlog_too_big_for_a_message_drops_the_spectator() {
+    // Long enough a window that the backlog outgrows a message first.
+    let game = start(19190, 10_000);
+    play(&game, 100, |frame| if frame % 2 == 0 { 0xff } else { 0 });
+
+    assert_eq!(dropped(&game), vec![game.stalled_handle]);
+    finish_watching(&game, 100);
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use common::{config, host_with, localhost, peer, poll, synchronize_by, Recorder};
+use ggpo::{
+    backends::spectator::{SpectatorBackend, SPECTATOR_FRAME_BUFFER_SIZE},
+    game_input::InputBuffer,
+    ggpo::{GGPOError, Session},
+};
+use parking_lot::Mutex;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+const FRAMES: usize = 150;
+
+/*
+ * Has a host play a frame a tick to a spectator that tries to play one a tick,
+ * over a link that's slow but steady until the spectator gets going, and
+ * jittery from then on.  Returns the first `FRAMES` frames the spectator
+ * played and how many ticks it went without a frame to play in between.
+ */
+fn watch(host_port: u16, spectator_port: u16, jitter_buffer: usize) -> (Vec<u8>, usize) {
+    let host_events = Recorder::default();
+    let host = host_with(
+        config(host_port),
+        Arc::new(Mutex::new(host_events.clone())),
+        &[spectator_port],
+    );
+
+    let viewer = Recorder::default();
+    let spectator = SpectatorBackend::new(
+        Arc::new(Mutex::new(viewer.clone())),
+        spectator_port,
+        1,
+        1,
+        localhost(host_port),
+    )
+    .unwrap();
+    spectator.lock().set_jitter_buffer(jitter_buffer).unwrap();
+    assert_eq!(spectator.lock().jitter_buffer_depth(), jitter_buffer);
+
+    synchronize_by(&[&host_events, &viewer], || {
+        poll(&host);
+        poll(&spectator);
+    });
+    // Steady at first, so the spectator starts out level with the host.
+    host.lock().set_network_conditions(20, 0);
+
+    let mut played = Vec::new();
+    let mut sent = 0;
+    let mut stalls = 0;
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while played.len() < FRAMES {
+        assert!(Instant::now() < deadline, "spectator never saw the match");
+        {
+            let mut host = host.lock();
+            let mut values: InputBuffer = Default::default();
+            values[0][0] = sent as u8;
+            host.add_local_input(1, &values, 1).unwrap();
+            host.synchronize_input(&mut values, None).unwrap();
+            host.increment_frame().unwrap();
+            sent += 1;
+            host.do_poll(Some(Duration::from_millis(1))).unwrap();
+        }
+
+        let mut spectator = spectator.lock();
+        spectator.do_poll(Some(Duration::from_millis(1))).unwrap();
+        assert!(spectator.jitter_buffer_occupancy() < SPECTATOR_FRAME_BUFFER_SIZE);
+        let mut values: InputBuffer = Default::default();
+        match spectator.synchronize_input(&mut values, None) {
+            Ok(()) => {
+                played.push(values[0][0]);
+                spectator.increment_frame().unwrap();
+                if played.len() == 1 {
+                    host.lock().set_network_jitter(20).unwrap();
+                }
+            }
+            Err(GGPOError::PredictionThreshold) => {
+                if !played.is_empty() {
+                    stalls += 1;
+                }
+            }
+            Err(e) => panic!("synchronize_input failed: {}", e),
+        }
+    }
+    (played, stalls)
+}
+
+#[test]
+fn a_jitter_buffer_smooths_out_playback() {
+    let (unbuffered, unbuffered_stalls) = watch(18850, 18860, 0);
+    let (buffered, buffered_stalls) = watch(18870, 18880, 40);
+
+    let expected: Vec<u8> = (0..FRAMES).map(|frame| frame as u8).collect();
+    assert_eq!(unbuffered, expected);
+    assert_eq!(buffered, expected);
+    assert!(
+        buffered_stalls < unbuffered_stalls,
+        "{} stalls with the buffer, {} without",
+        buffered_stalls,
+        unbuffered_stalls
+    );
+}
+
+#[test]
+fn the_jitter_buffer_has_to_fit_in_the_ring() {
+    let spectator = SpectatorBackend::new(
+        Arc::new(Mutex::new(Recorder::default())),
+        18890,
+        1,
+        1,
+        localhost(18900),
+    )
+    .unwrap();
+    let mut spectator = spectator.lock();
+    assert!(matches!(
+        spectator.set_jitter_buffer(SPECTATOR_FRAME_BUFFER_SIZE),
+        Err(GGPOError::InvalidRequest)
+    ));
+    // Catching up mustn't eat into the buffer.
+    spectator.set_jitter_buffer(8).unwrap();
+    assert!(matches!(
+        spectator.set_catch_up_threshold(8),
+        Err(GGPOError::InvalidRequest)
+    ));
+    spectator.set_catch_up_threshold(16).unwrap();
+    assert!(spectator.is_buffering());
+    assert_eq!(spectator.jitter_buffer_occupancy(), 0);
+}
+
+#[test]
+fn network_jitter_cant_be_negative() {
+    let (session, _) = peer(19300, 1, 19310);
+    let mut session = session.lock();
+    assert!(matches!(
+        session.set_network_jitter(-5),
+        Err(GGPOError::InvalidRequest)
+    ));
+    session.set_network_jitter(0).unwrap();
+}
@@ -0,0 +1,176 @@
+mod common;
+
+use bytes::Bytes;
+use common::{config, localhost, peer_with, poll, synchronize, Peer, Recorder};
+use ggpo::{
+    backends::spectator::SpectatorBackend,
+    game_input::{Frame, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS},
+    ggpo::{Event, GGPOError, GGPOSessionCallbacks, SavedState, Session},
+    player::{Player, PlayerHandle, PlayerType},
+};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+const A_PORT: u16 = 17600;
+const B_PORT: u16 = 17610;
+const SPECTATOR_PORT: u16 = 17620;
+
+/*
+ * A game whose state is a running hash of every frame's inputs, so a
+ * spectator that started from the wrong state (or the right state at the
+ * wrong frame) ends up somewhere else.
+ */
+#[derive(Debug, Default, Clone)]
+struct Counter {
+    events: Recorder,
+    // The frame the game is on, and its state going into that frame.
+    game: Arc<Mutex<(u32, u64)>>,
+    loaded: Arc<Mutex<Option<u32>>>,
+}
+
+impl Counter {
+    fn step(&self, values: &InputBuffer) -> (u32, u64) {
+        let mut game = self.game.lock();
+        let sum: u64 = values.iter().map(|row| row[0] as u64).sum();
+        game.0 += 1;
+        game.1 = game.1.wrapping_mul(31).wrapping_add(sum + 1);
+        *game
+    }
+}
+
+impl GGPOSessionCallbacks for Counter {
+    fn save_game_state(&mut self, _frame: Frame) -> Result<SavedState, GGPOError> {
+        let (frame, state) = *self.game.lock();
+        let mut data = frame.to_le_bytes().to_vec();
+        data.extend_from_slice(&state.to_le_bytes());
+        Ok(SavedState {
+            data: Bytes::from(data),
+            checksum: Some(state as u32),
+        })
+    }
+
+    fn load_game_state(&mut self, buffer: &Bytes, _length: usize) -> bool {
+        let frame = u32::from_le_bytes(buffer[..4].try_into().unwrap());
+        let state = u64::from_le_bytes(buffer[4..12].try_into().unwrap());
+        *self.game.lock() = (frame, state);
+        *self.loaded.lock() = Some(frame);
+        true
+    }
+
+    fn log_game_state(&mut self, _filename: String, _buffer: Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn advance_frame(&mut self, _flags: i32) -> bool {
+        true
+    }
+
+    fn on_event(&mut self, info: &Event) {
+        self.events.on_event(info);
+    }
+}
+
+// Runs one frame if the session will take another local input.
+fn advance(session: &Peer<Counter>, game: &Counter, handle: PlayerHandle) -> Option<(u32, u64)> {
+    let mut session = session.lock();
+    let local = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    match session.add_local_input(handle, &local, 1) {
+        Ok(()) => {}
+        Err(GGPOError::PredictionThreshold) => return None,
+        Err(e) => panic!("add_local_input failed: {}", e),
+    }
+    let mut values: InputBuffer = Default::default();
+    session.synchronize_input(&mut values, None).unwrap();
+    let stepped = game.step(&values);
+    session.increment_frame().unwrap();
+    Some(stepped)
+}
+
+#[test]
+fn late_spectator_starts_from_the_hosts_state() {
+    let (a_game, b_game) = (Counter::default(), Counter::default());
+    let a = peer_with(
+        config(A_PORT),
+        Arc::new(Mutex::new(a_game.clone())),
+        1,
+        B_PORT,
+    );
+    let b = peer_with(
+        config(B_PORT),
+        Arc::new(Mutex::new(b_game.clone())),
+        2,
+        A_PORT,
+    );
+    synchronize(&[(&a, &a_game.events), (&b, &b_game.events)]);
+
+    // The state A had going into each frame.
+    let mut host_states = HashMap::new();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while a_game.game.lock().0 < 100 {
+        assert!(Instant::now() < deadline, "peers never reached frame 100");
+        if let Some((frame, state)) = advance(&a, &a_game, 1) {
+            host_states.insert(frame, state);
+        }
+        advance(&b, &b_game, 2);
+        poll(&a);
+        poll(&b);
+    }
+
+    let mut handle: PlayerHandle = 0;
+    a.lock()
+        .add_player(
+            Player::new(PlayerType::Spectator(localhost(SPECTATOR_PORT)), 3),
+            &mut handle,
+        )
+        .unwrap();
+    let viewer = Counter::default();
+    let spectator = SpectatorBackend::new(
+        Arc::new(Mutex::new(viewer.clone())),
+        SPECTATOR_PORT,
+        2,
+        1,
+        localhost(A_PORT),
+    )
+    .unwrap();
+
+    let mut played = 0;
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while played < 30 {
+        assert!(
+            Instant::now() < deadline,
+            "spectator only played {} frames",
+            played
+        );
+        if let Some((frame, state)) = advance(&a, &a_game, 1) {
+            host_states.insert(frame, state);
+        }
+        advance(&b, &b_game, 2);
+        poll(&a);
+        poll(&b);
+
+        let mut spectator = spectator.lock();
+        spectator.do_poll(Some(Duration::from_millis(1))).unwrap();
+        let mut values: InputBuffer = Default::default();
+        match spectator.synchronize_input(&mut values, None) {
+            Ok(()) => {
+                let (frame, state) = viewer.step(&values);
+                assert_eq!(host_states.get(&frame), Some(&state), "frame {}", frame);
+                spectator.increment_frame().unwrap();
+                played += 1;
+            }
+            Err(GGPOError::NotSynchronized) | Err(GGPOError::PredictionThreshold) => {}
+            Err(e) => panic!("synchronize_input failed: {}", e),
+        }
+    }
+
+    let loaded = viewer
+        .loaded
+        .lock()
+        .expect("spectator never loaded a state");
+    assert!(loaded >= 100, "spectator started from frame {}", loaded);
+}
@@ -0,0 +1,149 @@
+mod common;
+
+use bytes::Bytes;
+use common::{connect_status, Recorder};
+use ggpo::{
+    backends::p2p::Peer2PeerBackend,
+    config::SessionConfig,
+    game_input::{
+        Frame, GameInput, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS, NULL_FRAME,
+    },
+    ggpo::{
+        Event, GGPOError, GGPOSessionCallbacks, SavedState, Session, GGPO_MAX_PREDICTION_FRAMES,
+    },
+    player::{Player, PlayerHandle, PlayerType},
+    sync::{Config, GGPOSync},
+};
+use parking_lot::Mutex;
+use std::{sync::Arc, time::Duration};
+
+const INPUT_SIZE: usize = 1;
+const START: u32 = 500;
+
+// A game that knows which frame it's on, and how many it ran again.
+#[derive(Debug, Default, Clone)]
+struct Scene {
+    frame: u32,
+    resimulated: u32,
+}
+
+impl GGPOSessionCallbacks for Scene {
+    fn save_game_state(&mut self, _frame: Frame) -> Result<SavedState, GGPOError> {
+        Ok(SavedState {
+            data: Bytes::copy_from_slice(&self.frame.to_le_bytes()),
+            checksum: None,
+        })
+    }
+
+    fn load_game_state(&mut self, buffer: &Bytes, _length: usize) -> bool {
+        let mut frame = [0; 4];
+        frame.copy_from_slice(&buffer[..4]);
+        self.frame = u32::from_le_bytes(frame);
+        true
+    }
+
+    fn log_game_state(&mut self, _filename: String, _buffer: Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn advance_frame(&mut self, _flags: i32) -> bool {
+        self.frame += 1;
+        self.resimulated += 1;
+        true
+    }
+
+    fn on_event(&mut self, _info: &Event) {}
+}
+
+#[test]
+fn inputs_and_rollbacks_are_keyed_from_the_start_frame() {
+    let game = Arc::new(Mutex::new(Scene {
+        frame: START,
+        ..Default::default()
+    }));
+    let status = connect_status(2);
+    let mut sync = GGPOSync::new(&status);
+    let mut config = Config::new();
+    config.init(game.clone(), GGPO_MAX_PREDICTION_FRAMES, 2, INPUT_SIZE);
+    config.start_frame = START;
+    sync.init(config).unwrap();
+    assert_eq!(sync.get_frame_count(), START);
+
+    // Five frames on, predicting the remote player idles.
+    for _ in 0..5 {
+        let mut local = GameInput::init(NULL_FRAME, None, INPUT_SIZE);
+        assert!(sync.add_local_input(0, &mut local).unwrap());
+        let mut values: InputBuffer = Default::default();
+        sync.synchronize_inputs(&mut values).unwrap();
+        game.lock().frame += 1;
+        sync.increment_frame().unwrap();
+    }
+    assert_eq!(sync.last_input_frame(0), Frame::new(START + 4));
+    assert_eq!(sync.last_input_frame(1), NULL_FRAME);
+
+    // The remote player pressed something on frame 502.
+    let mut pressed = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    pressed[0][0] = 7;
+    for frame in START..START + 5 {
+        let input = if frame == START + 2 {
+            GameInput::init(Frame::new(frame), Some(&pressed), INPUT_SIZE)
+        } else {
+            GameInput::init(Frame::new(frame), None, INPUT_SIZE)
+        };
+        sync.add_remote_input(1, &input).unwrap();
+    }
+    sync.check_simulation().unwrap();
+
+    // Back to the state saved at 502, and on through 502 to 504 again.
+    assert_eq!(game.lock().resimulated, 3);
+    assert_eq!(game.lock().frame, START + 5);
+    assert_eq!(sync.get_frame_count(), START + 5);
+    let saved = sync.saved_state(Frame::new(START + 2)).unwrap();
+    assert_eq!(&saved.data[..], &(START + 2).to_le_bytes());
+    let mut values: InputBuffer = Default::default();
+    sync.get_confirmed_inputs(&mut values, Frame::new(START + 2))
+        .unwrap();
+    assert_eq!(values[1][0], 7);
+    assert_eq!(sync.last_input_frame(1), Frame::new(START + 4));
+}
+
+#[test]
+fn a_session_started_late_confirms_from_its_start_frame() {
+    let recorder = Recorder::default();
+    let config = SessionConfig {
+        local_port: 19120,
+        num_players: 1,
+        input_size: INPUT_SIZE,
+        start_frame: START,
+        ..Default::default()
+    };
+    let session = Peer2PeerBackend::from_config(config, Arc::new(Mutex::new(recorder.clone())))
+        .expect("session");
+    let mut session = session.lock();
+    let mut handle: PlayerHandle = 0;
+    session
+        .add_player(Player::new(PlayerType::Local, 1), &mut handle)
+        .unwrap();
+    session.do_poll(Some(Duration::from_millis(1))).unwrap();
+
+    for _ in 0..3 {
+        let mut values: InputBuffer = Default::default();
+        session.add_local_input(handle, &values, 1).unwrap();
+        session.synchronize_input(&mut values, None).unwrap();
+        session.increment_frame().unwrap();
+    }
+    assert_eq!(
+        session.last_confirmed_frame(handle).unwrap(),
+        Frame::new(START + 2)
+    );
+    let confirmed: Vec<Frame> = recorder
+        .events
+        .lock()
+        .iter()
+        .filter_map(|e| match e {
+            Event::FrameConfirmed(confirmed) => Some(confirmed.frame),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(confirmed.first(), Some(&Frame::new(START)));
+}
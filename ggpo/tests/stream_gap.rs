@@ -0,0 +1,223 @@
+mod common;
+
+use bytes::Bytes;
+use common::{localhost, poll_until};
+use ggpo::{
+    game_input::{Frame, GameInput, InputBuffer},
+    network::{
+        clock::TestClock,
+        input_codec,
+        udp::{Udp, UdpCallback},
+        udp_msg::{ConnectStatus, MsgEnum, UdpMsg, MAX_COMPRESSED_BITS, UDP_MSG_MAX_PLAYERS},
+        udp_proto::{
+            Event, StreamGap, UdpProtocol, DEFAULT_STREAM_GAP_WINDOW, MAX_STREAM_GAP_REQUESTS,
+        },
+    },
+};
+use mio::Poll;
+use parking_lot::Mutex;
+use std::{net::SocketAddr, sync::Arc, thread, time::Duration};
+
+struct Ignore;
+
+impl UdpCallback for Ignore {
+    fn on_msg(&mut self, _from: &SocketAddr, _msg: UdpMsg, _len: usize) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+// An endpoint, the socket it listens on, and the clock it keeps time by.
+struct Side {
+    endpoint: UdpProtocol<Ignore>,
+    udp: Arc<Mutex<Udp<Ignore>>>,
+    clock: Arc<TestClock>,
+}
+
+fn side(port: u16, peer_port: u16) -> Side {
+    let mut udp = Udp::new();
+    udp.init(
+        port,
+        Arc::new(Mutex::new(Poll::new().unwrap())),
+        Arc::new(Mutex::new(Ignore)),
+    )
+    .unwrap();
+    udp.start_send_task().unwrap();
+    let udp = Arc::new(Mutex::new(udp));
+    let status: [Arc<Mutex<ConnectStatus>>; UDP_MSG_MAX_PLAYERS] = Default::default();
+    let mut endpoint = UdpProtocol::new();
+    endpoint.init(udp.clone(), 0, localhost(peer_port), &status);
+    endpoint.set_input_size(1);
+    let clock = Arc::new(TestClock::new());
+    endpoint.set_clock(clock.clone());
+    Side {
+        endpoint,
+        udp,
+        clock,
+    }
+}
+
+// Whatever reached `side` in the next little while.
+fn arrivals(side: &Side) -> Vec<UdpMsg> {
+    thread::sleep(Duration::from_millis(20));
+    let msgs = side.udp.lock().recv_pending().unwrap();
+    msgs.into_iter().map(|(msg, _, _)| msg).collect()
+}
+
+fn deliver(side: &mut Side) {
+    for msg in arrivals(side) {
+        side.endpoint.on_msg(&msg).unwrap();
+    }
+}
+
+fn input(frame: u32) -> GameInput {
+    let mut bits: InputBuffer = Default::default();
+    bits[0][0] = frame as u8 + 1;
+    GameInput::init(Frame::new(frame), Some(&bits), 1)
+}
+
+fn received(side: &mut Side) -> Vec<u32> {
+    let mut frames = Vec::new();
+    let mut event = Event::Unknown;
+    while side.endpoint.get_event(&mut event) {
+        if let Event::Input(input) = &event {
+            frames.push(input.frame.as_i32() as u32);
+        }
+    }
+    frames
+}
+
+fn gaps(side: &mut Side) -> Vec<StreamGap> {
+    let mut gaps = Vec::new();
+    let mut event = Event::Unknown;
+    while side.endpoint.get_event(&mut event) {
+        if let Event::StreamGap(gap) = &event {
+            gaps.push(*gap);
+        }
+    }
+    gaps
+}
+
+// `msg`, cut down to its last frame: all that's left once its start is lost.
+fn only_last_frame(mut msg: UdpMsg, last: u32) -> UdpMsg {
+    let mut bits = [0u8; MAX_COMPRESSED_BITS];
+    let mut offset = 0;
+    input_codec::write_frame(&mut bits, &mut offset, &[input(last - 1)], &[input(last)]);
+    if let MsgEnum::Input(input) = &mut msg.message {
+        input.start_frame = Frame::new(last);
+        input.num_bits = offset as u16;
+        input.bits = Bytes::copy_from_slice(&bits[..offset.div_ceil(8)]);
+    }
+    msg
+}
+
+// A host and a spectator, synchronized, with frames 0 and 1 through and acked.
+fn host_and_spectator(host_port: u16, spectator_port: u16) -> (Side, Side) {
+    let mut host = side(host_port, spectator_port);
+    let mut spectator = side(spectator_port, host_port);
+    spectator
+        .endpoint
+        .set_stream_gap_window(DEFAULT_STREAM_GAP_WINDOW);
+    host.endpoint.synchronize().unwrap();
+    spectator.endpoint.synchronize().unwrap();
+    poll_until("never synchronized", || {
+        if host.endpoint.is_running() && spectator.endpoint.is_running() {
+            return true;
+        }
+        deliver(&mut host);
+        deliver(&mut spectator);
+        false
+    });
+
+    host.endpoint.send_input(&input(0)).unwrap();
+    host.endpoint.send_input(&input(1)).unwrap();
+    deliver(&mut spectator);
+    assert_eq!(received(&mut spectator), vec![0, 1]);
+    spectator.endpoint.on_loop_poll(0).unwrap();
+    deliver(&mut host);
+    (host, spectator)
+}
+
+#[test]
+fn a_skipped_frame_is_asked_for_and_resent() {
+    let (mut host, mut spectator) = host_and_spectator(19080, 19090);
+
+    // Everything carrying frames 2 and 3 is lost; frame 4 gets through alone.
+    for frame in 2..=4 {
+        host.endpoint.send_input(&input(frame)).unwrap();
+    }
+    let last = arrivals(&spectator).pop().unwrap();
+    spectator
+        .endpoint
+        .on_msg(&only_last_frame(last, 4))
+        .unwrap();
+    assert!(received(&mut spectator).is_empty());
+
+    // Nothing's said until the window's up.
+    spectator.endpoint.on_loop_poll(0).unwrap();
+    assert!(gaps(&mut spectator).is_empty());
+    spectator
+        .clock
+        .advance(Duration::from_millis(DEFAULT_STREAM_GAP_WINDOW as u64));
+    spectator.endpoint.on_loop_poll(0).unwrap();
+    let gap = StreamGap {
+        missing_from: Frame::new(2),
+        missing_to: Frame::new(3),
+    };
+    assert_eq!(gaps(&mut spectator), vec![gap]);
+
+    // The host's retransmit timer isn't due (its clock hasn't moved), so
+    // it's the request that gets the frames resent.
+    deliver(&mut host);
+    deliver(&mut spectator);
+    assert_eq!(received(&mut spectator), vec![2, 3, 4]);
+    assert_eq!(spectator.endpoint.stream_gap(), None);
+}
+
+#[test]
+fn a_host_that_never_fills_the_gap_is_dropped() {
+    let (mut host, mut spectator) = host_and_spectator(19100, 19110);
+    for frame in 2..=4 {
+        host.endpoint.send_input(&input(frame)).unwrap();
+    }
+    let last = arrivals(&spectator).pop().unwrap();
+    spectator
+        .endpoint
+        .on_msg(&only_last_frame(last, 4))
+        .unwrap();
+    // The host has nothing left to send.
+    host.endpoint.clear_pending_output();
+
+    let mut disconnected = false;
+    for _ in 0..=MAX_STREAM_GAP_REQUESTS {
+        assert!(!disconnected);
+        spectator
+            .clock
+            .advance(Duration::from_millis(DEFAULT_STREAM_GAP_WINDOW as u64));
+        spectator.endpoint.on_loop_poll(0).unwrap();
+        deliver(&mut host);
+        deliver(&mut spectator);
+        let mut event = Event::Unknown;
+        while spectator.endpoint.get_event(&mut event) {
+            disconnected |= matches!(event, Event::Disconnected);
+        }
+    }
+    assert!(disconnected);
+}
+
+#[test]
+fn input_that_doesnt_decode_is_dropped() {
+    let (mut host, mut spectator) = host_and_spectator(19340, 19350);
+    host.endpoint.send_input(&input(2)).unwrap();
+    let sent = arrivals(&spectator).pop().unwrap();
+
+    // Claiming more bits than the packet carries.
+    let mut truncated = sent.clone();
+    if let MsgEnum::Input(input) = &mut truncated.message {
+        input.num_bits = (input.bits.len() * 8 + 8) as u16;
+    }
+    spectator.endpoint.on_msg(&truncated).unwrap();
+    assert!(received(&mut spectator).is_empty());
+
+    spectator.endpoint.on_msg(&sent).unwrap();
+    assert_eq!(received(&mut spectator), vec![2]);
+}
@@ -0,0 +1,153 @@
+mod common;
+
+use bytes::Bytes;
+use common::{connect_status, sync_with, Recorder};
+use ggpo::{
+    backends::p2p::Peer2PeerBackend,
+    game_input::{
+        Frame, GameInput, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS, NULL_FRAME,
+    },
+    ggpo::{Event, GGPOError, GGPOSessionCallbacks, SavedState, Session},
+    player::{Player, PlayerHandle, PlayerType},
+    sync::GGPOSync,
+};
+use parking_lot::Mutex;
+use std::{sync::Arc, time::Duration};
+
+const INPUT_SIZE: usize = 1;
+
+// A scene that counts frames, and how many of them it ran again.
+#[derive(Debug, Default, Clone)]
+struct Scene {
+    frame: u32,
+    resimulated: u32,
+}
+
+impl GGPOSessionCallbacks for Scene {
+    fn save_game_state(&mut self, _frame: Frame) -> Result<SavedState, GGPOError> {
+        Ok(SavedState {
+            data: Bytes::copy_from_slice(&self.frame.to_le_bytes()),
+            checksum: None,
+        })
+    }
+
+    fn load_game_state(&mut self, buffer: &Bytes, _length: usize) -> bool {
+        let mut frame = [0; 4];
+        frame.copy_from_slice(&buffer[..4]);
+        self.frame = u32::from_le_bytes(frame);
+        true
+    }
+
+    fn log_game_state(&mut self, _filename: String, _buffer: Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn advance_frame(&mut self, _flags: i32) -> bool {
+        self.frame += 1;
+        self.resimulated += 1;
+        true
+    }
+
+    fn on_event(&mut self, _info: &Event) {}
+}
+
+// Runs `frames` frames on `game`, predicting the remote player idles.
+fn run(sync: &mut GGPOSync<Scene>, game: &Arc<Mutex<Scene>>, frames: u32) {
+    for _ in 0..frames {
+        let mut local = GameInput::init(NULL_FRAME, None, INPUT_SIZE);
+        assert!(sync.add_local_input(0, &mut local).unwrap());
+        let mut values: InputBuffer = Default::default();
+        sync.synchronize_inputs(&mut values).unwrap();
+        game.lock().frame += 1;
+        sync.increment_frame().unwrap();
+    }
+}
+
+// The remote player's real input for `frames`, pressing something on `pressed`.
+fn arrive(sync: &mut GGPOSync<Scene>, frames: std::ops::Range<u32>, pressed: u32) {
+    let mut bits = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    bits[0][0] = 7;
+    for frame in frames {
+        let input = if frame == pressed {
+            GameInput::init(Frame::new(frame), Some(&bits), INPUT_SIZE)
+        } else {
+            GameInput::init(Frame::new(frame), None, INPUT_SIZE)
+        };
+        sync.add_remote_input(1, &input).unwrap();
+    }
+}
+
+#[test]
+fn the_new_scene_takes_over_at_a_confirmed_frame() {
+    let menu = Arc::new(Mutex::new(Scene::default()));
+    let status = connect_status(2);
+    let mut sync = sync_with(menu.clone(), &status, INPUT_SIZE);
+    sync.save_current_frame().unwrap();
+
+    run(&mut sync, &menu, 5);
+    let fight = Arc::new(Mutex::new(Scene {
+        frame: 5,
+        ..Default::default()
+    }));
+    sync.set_callbacks(fight.clone());
+    // Frames 0 to 4 could still roll back, and they're the menu's.
+    assert_eq!(sync.swap_callbacks().unwrap(), None);
+    assert!(sync.callbacks_pending());
+
+    // So a rollback over them still goes to the menu...
+    arrive(&mut sync, 0..5, 2);
+    sync.check_simulation().unwrap();
+    assert_eq!(menu.lock().resimulated, 3);
+    assert_eq!(sync.swap_callbacks().unwrap(), None);
+
+    // ...and only once they're confirmed does the fight take over.
+    sync.set_last_confirmed_frame(Frame::new(4)).unwrap();
+    assert_eq!(sync.swap_callbacks().unwrap(), Some(Frame::new(5)));
+    assert!(!sync.callbacks_pending());
+
+    run(&mut sync, &fight, 3);
+    arrive(&mut sync, 5..8, 6);
+    sync.check_simulation().unwrap();
+    // Frames 6 and 7 ran again from the fight's own save, on the fight.
+    assert_eq!(fight.lock().resimulated, 2);
+    assert_eq!(fight.lock().frame, 8);
+    assert_eq!(menu.lock().resimulated, 3);
+}
+
+#[test]
+fn the_session_tells_the_new_callbacks_when_theyre_in() {
+    let menu = Recorder::default();
+    let session = Peer2PeerBackend::new(Arc::new(Mutex::new(menu.clone())), 18910, 1, 1, None)
+        .expect("session");
+    let mut session = session.lock();
+    let mut handle: PlayerHandle = 0;
+    session
+        .add_player(Player::new(PlayerType::Local, 1), &mut handle)
+        .unwrap();
+    session.do_poll(Some(Duration::from_millis(1))).unwrap();
+
+    let fight = Recorder::default();
+    session.set_callbacks(Arc::new(Mutex::new(fight.clone())));
+    for _ in 0..3 {
+        let mut values: InputBuffer = Default::default();
+        session.add_local_input(handle, &values, 1).unwrap();
+        session.synchronize_input(&mut values, None).unwrap();
+        session.increment_frame().unwrap();
+    }
+
+    assert!(fight.saw(|e| matches!(e, Event::CallbacksSwapped(_))));
+    assert!(!menu.saw(|e| matches!(e, Event::CallbacksSwapped(_))));
+    // Everything from then on goes to the fight.
+    let confirmed = |recorder: &Recorder| {
+        recorder
+            .events
+            .lock()
+            .iter()
+            .filter(|e| matches!(e, Event::FrameConfirmed(_)))
+            .count()
+    };
+    assert!(confirmed(&fight) > 0);
+    let before = confirmed(&menu);
+    session.do_poll(Some(Duration::from_millis(1))).unwrap();
+    assert_eq!(confirmed(&menu), before);
+}
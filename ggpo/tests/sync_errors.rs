@@ -0,0 +1,115 @@
+mod common;
+
+use common::{clocked_peer, config, poll, recording_peer, Peer};
+use ggpo::{
+    config::SessionConfig,
+    game_input::InputBuffer,
+    ggpo::{GGPOError, Session},
+    network::clock::TestClock,
+    player::PlayerHandle,
+};
+use std::{
+    net::UdpSocket,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+// Polls both peers until `session` knows why it can't synchronize with `player`.
+fn handshake_error(session: &Peer, other: &Peer, player: PlayerHandle) -> GGPOError {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        assert!(Instant::now() < deadline, "the handshake never failed");
+        poll(session);
+        poll(other);
+        if let Err(e) = session.lock().sync_error(player) {
+            return e;
+        }
+    }
+}
+
+fn synchronize(session: &Peer) -> Result<(), GGPOError> {
+    let mut values: InputBuffer = Default::default();
+    session.lock().synchronize_input(&mut values, None)
+}
+
+#[test]
+fn a_peer_on_another_version_is_a_version_mismatch() {
+    let versioned = |port, version| SessionConfig {
+        version,
+        ..config(port)
+    };
+    let (a, _) = recording_peer(versioned(18920, 1), 1, 18930);
+    let (b, _) = recording_peer(versioned(18930, 2), 2, 18920);
+
+    assert!(matches!(
+        handshake_error(&a, &b, 2),
+        GGPOError::SyncVersionMismatch {
+            local: 1,
+            remote: 2
+        }
+    ));
+    // Which is also what's in the way of running.
+    assert!(matches!(
+        synchronize(&a),
+        Err(GGPOError::SyncVersionMismatch { .. })
+    ));
+    assert!(matches!(
+        handshake_error(&b, &a, 1),
+        GGPOError::SyncVersionMismatch {
+            local: 2,
+            remote: 1
+        }
+    ));
+}
+
+#[test]
+fn a_peer_with_other_sized_input_is_an_input_size_mismatch() {
+    let sized = |port, input_size| SessionConfig {
+        input_size,
+        ..config(port)
+    };
+    let (a, _) = recording_peer(sized(18940, 1), 1, 18950);
+    let (b, _) = recording_peer(sized(18950, 2), 2, 18940);
+
+    assert!(matches!(
+        handshake_error(&a, &b, 2),
+        GGPOError::SyncInputSizeMismatch {
+            local: 1,
+            remote: 2
+        }
+    ));
+    assert!(matches!(
+        synchronize(&a),
+        Err(GGPOError::SyncInputSizeMismatch { .. })
+    ));
+    // A local player has no handshake to ask about.
+    assert!(matches!(
+        a.lock().sync_error(1),
+        Err(GGPOError::InvalidRequest)
+    ));
+}
+
+#[test]
+fn a_silent_peer_is_a_sync_timeout() {
+    // Takes our sync requests and never answers.
+    let _silent = UdpSocket::bind("127.0.0.1:18970").unwrap();
+    let clock = Arc::new(TestClock::new());
+    let config = SessionConfig {
+        sync_timeout: 1000,
+        ..config(18960)
+    };
+    let (a, _) = clocked_peer(config, &clock, 1, 18970);
+
+    let mut waited = 0;
+    while a.lock().sync_error(2).is_ok() {
+        assert!(waited < 2000, "never gave up on the peer");
+        clock.advance(Duration::from_millis(100));
+        waited += 100;
+        poll(&a);
+    }
+    assert!(waited > 1000, "gave up after {} ms", waited);
+    assert!(matches!(
+        a.lock().sync_error(2),
+        Err(GGPOError::SyncTimeout { timeout: 1000 })
+    ));
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use common::{clocked_peer, config, poll, recording_peer, synchronize, Recorder};
+use ggpo::{config::SessionConfig, ggpo::Event, network::clock::TestClock};
+use std::{net::UdpSocket, sync::Arc, time::Duration};
+
+const SYNC_TIMEOUT: u64 = 1000;
+
+fn disconnected(events: &Recorder) -> bool {
+    events.saw(|e| matches!(e, Event::DisconnectedFromPeer(e) if e.player == 2))
+}
+
+#[test]
+fn a_silent_peer_is_given_up_on_at_the_sync_timeout() {
+    // Takes our sync requests and never answers.
+    let _silent = UdpSocket::bind("127.0.0.1:18460").unwrap();
+    let clock = Arc::new(TestClock::new());
+    let config = SessionConfig {
+        sync_first_retry_interval: 100,
+        sync_retry_interval: 100,
+        sync_timeout: SYNC_TIMEOUT as u128,
+        ..config(18450)
+    };
+    let (a, a_events) = clocked_peer(config, &clock, 1, 18460);
+
+    // Retrying all the while.
+    for _ in 0..9 {
+        clock.advance(Duration::from_millis(SYNC_TIMEOUT / 10));
+        poll(&a);
+    }
+    clock.advance(Duration::from_millis(SYNC_TIMEOUT / 10));
+    poll(&a);
+    assert!(!disconnected(&a_events), "gave up before the timeout");
+
+    clock.advance(Duration::from_millis(1));
+    poll(&a);
+    assert!(disconnected(&a_events));
+    assert!(!a_events.saw(|e| matches!(e, Event::SynchronizedWithPeer(_))));
+}
+
+#[test]
+fn the_handshake_takes_as_many_round_trips_as_configured() {
+    let two_trips = |port| SessionConfig {
+        num_sync_packets: 2,
+        ..config(port)
+    };
+    let (a, a_events) = recording_peer(two_trips(18470), 1, 18480);
+    let (b, b_events) = recording_peer(two_trips(18480), 2, 18470);
+
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+
+    let progress: Vec<_> = a_events
+        .events
+        .lock()
+        .iter()
+        .filter_map(|e| match e {
+            Event::SynchronizingWithPeer(e) => Some((e.count, e.total)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(progress, vec![(1, 2)]);
+    assert!(!SessionConfig {
+        num_sync_packets: 0,
+        ..Default::default()
+    }
+    .validate()
+    .is_ok());
+}
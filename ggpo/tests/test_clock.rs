@@ -0,0 +1,173 @@
+mod common;
+
+use common::{clocked_pair, config, localhost, poll, synchronize, Peer, Recorder};
+use ggpo::{
+    backends::spectator::SpectatorBackend,
+    config::SessionConfig,
+    ggpo::{Event, Session},
+    network::{clock::TestClock, udp_proto::SYNC_FIRST_RETRY_INTERVAL},
+};
+use parking_lot::Mutex;
+use std::{net::UdpSocket, sync::Arc, time::Duration};
+
+const DISCONNECT_TIMEOUT: u64 = 1000;
+const DISCONNECT_NOTIFY_START: u64 = 400;
+
+fn timed(port: u16) -> SessionConfig {
+    SessionConfig {
+        disconnect_timeout: DISCONNECT_TIMEOUT as u128,
+        disconnect_notify_start: DISCONNECT_NOTIFY_START as u128,
+        ..config(port)
+    }
+}
+
+fn disconnected(events: &Recorder) -> bool {
+    events.saw(|e| matches!(e, Event::DisconnectedFromPeer(_)))
+}
+
+fn interrupted(events: &Recorder) -> bool {
+    events.saw(|e| matches!(e, Event::ConnectionInterrupted(_)))
+}
+
+/*
+ * Brings up two peers on `clock` and has B go quiet, leaving A with B's
+ * last packets taken, all stamped with the same instant.
+ */
+fn quiet_pair(a_port: u16, b_port: u16, clock: &Arc<TestClock>) -> (Peer, Recorder) {
+    let ((a, a_events), (b, b_events)) = clocked_pair(timed(a_port), timed(b_port), clock);
+
+    // The handshake runs on replies alone, so time never has to move.
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+
+    for _ in 0..50 {
+        poll(&a);
+    }
+    (a, a_events)
+}
+
+#[test]
+fn disconnect_fires_exactly_at_the_timeout() {
+    let clock = Arc::new(TestClock::new());
+    let (a, a_events) = quiet_pair(18030, 18040, &clock);
+
+    clock.advance(Duration::from_millis(DISCONNECT_TIMEOUT));
+    a.lock().do_poll(Some(Duration::from_millis(0))).unwrap();
+    assert!(!disconnected(&a_events), "disconnected before the timeout");
+    assert!(interrupted(&a_events));
+
+    clock.advance(Duration::from_millis(1));
+    a.lock().do_poll(Some(Duration::from_millis(0))).unwrap();
+    assert!(disconnected(&a_events), "still connected past the timeout");
+}
+
+#[test]
+fn interruption_fires_exactly_at_the_notify_start() {
+    let clock = Arc::new(TestClock::new());
+    let (a, a_events) = quiet_pair(18560, 18570, &clock);
+
+    clock.advance(Duration::from_millis(DISCONNECT_NOTIFY_START));
+    a.lock().do_poll(Some(Duration::from_millis(0))).unwrap();
+    assert!(
+        !interrupted(&a_events),
+        "interrupted before the notify start"
+    );
+
+    clock.advance(Duration::from_millis(1));
+    a.lock().do_poll(Some(Duration::from_millis(0))).unwrap();
+    assert!(
+        interrupted(&a_events),
+        "not interrupted past the notify start"
+    );
+    assert!(!disconnected(&a_events));
+}
+
+#[test]
+fn spectator_retries_the_handshake_exactly_at_the_retry_interval() {
+    // Takes the spectator's sync requests and never answers.
+    let host = UdpSocket::bind("127.0.0.1:18580").unwrap();
+    host.set_nonblocking(true).unwrap();
+    let clock = Arc::new(TestClock::new());
+    let spectator = SpectatorBackend::new(
+        Arc::new(Mutex::new(Recorder::default())),
+        18590,
+        2,
+        1,
+        localhost(18580),
+    )
+    .unwrap();
+    spectator.lock().set_clock(clock.clone());
+
+    let requests = || {
+        let mut buf = [0; 4096];
+        let mut count = 0;
+        for _ in 0..50 {
+            spectator
+                .lock()
+                .do_poll(Some(Duration::from_millis(1)))
+                .unwrap();
+            while host.recv_from(&mut buf).is_ok() {
+                count += 1;
+            }
+        }
+        count
+    };
+
+    // Only the first request while the clock stands still.
+    assert_eq!(requests(), 1);
+
+    clock.advance(Duration::from_millis(SYNC_FIRST_RETRY_INTERVAL as u64));
+    assert_eq!(requests(), 0, "retried before the interval");
+
+    clock.advance(Duration::from_millis(1));
+    assert_eq!(requests(), 1, "didn't retry past the interval");
+}
+
+#[test]
+fn raising_the_timeout_mid_interruption_defers_the_disconnect() {
+    const RAISED_TIMEOUT: u64 = 2000;
+    let clock = Arc::new(TestClock::new());
+    let (a, a_events) = quiet_pair(18660, 18670, &clock);
+
+    clock.advance(Duration::from_millis(DISCONNECT_NOTIFY_START + 1));
+    a.lock().do_poll(Some(Duration::from_millis(0))).unwrap();
+    assert!(interrupted(&a_events));
+
+    // Counted from the silence so far, not from now.
+    a.lock()
+        .set_disconnect_timeout(RAISED_TIMEOUT as u128)
+        .unwrap();
+    a.lock().do_poll(Some(Duration::from_millis(0))).unwrap();
+    let remaining: Vec<u128> = a_events
+        .events
+        .lock()
+        .iter()
+        .filter_map(|e| match e {
+            Event::ConnectionInterrupted(e) => Some(e.disconnect_timeout),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        remaining,
+        vec![
+            (DISCONNECT_TIMEOUT - DISCONNECT_NOTIFY_START) as u128,
+            (RAISED_TIMEOUT - DISCONNECT_NOTIFY_START - 1) as u128
+        ]
+    );
+
+    // Past the old timeout, but not the new one.
+    clock.advance(Duration::from_millis(
+        RAISED_TIMEOUT - DISCONNECT_NOTIFY_START - 1,
+    ));
+    a.lock().do_poll(Some(Duration::from_millis(0))).unwrap();
+    assert!(
+        !disconnected(&a_events),
+        "disconnected before the raised timeout"
+    );
+
+    clock.advance(Duration::from_millis(1));
+    a.lock().do_poll(Some(Duration::from_millis(0))).unwrap();
+    assert!(
+        disconnected(&a_events),
+        "still connected past the raised timeout"
+    );
+}
@@ -0,0 +1,186 @@
+mod common;
+
+use common::{clocked_pair, clocked_peer, config, localhost, poll, synchronize, Peer, Recorder};
+use ggpo::{
+    backends::spectator::SpectatorBackend,
+    config::SessionConfig,
+    ggpo::{Event, Session},
+    network::{
+        clock::{Clock, Sleep, TestClock},
+        udp_proto::SYNC_FIRST_RETRY_INTERVAL,
+    },
+};
+use parking_lot::Mutex;
+use smol::future::poll_once;
+use std::{
+    net::UdpSocket,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const DISCONNECT_TIMEOUT: u64 = 1000;
+const DISCONNECT_NOTIFY_START: u64 = 400;
+// Far more timers than any of these tests should have to run through.
+const MAX_TIMERS: usize = 1000;
+
+fn timed(port: u16) -> SessionConfig {
+    SessionConfig {
+        disconnect_timeout: DISCONNECT_TIMEOUT as u128,
+        disconnect_notify_start: DISCONNECT_NOTIFY_START as u128,
+        ..config(port)
+    }
+}
+
+// The timers keep time in whole milliseconds.
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+}
+
+/*
+ * Moves `clock` up to `due`, checking `sleep` is still waiting a millisecond
+ * before it and has woken by it.
+ */
+fn sleep_until(clock: &TestClock, due: SystemTime, mut sleep: Sleep) {
+    let wait = due.duration_since(clock.now()).unwrap_or_default();
+    if wait > Duration::from_millis(0) {
+        clock.advance(wait - Duration::from_millis(1));
+        assert!(
+            smol::block_on(poll_once(&mut sleep)).is_none(),
+            "woke before the timer was due"
+        );
+        clock.advance(Duration::from_millis(1));
+    }
+    assert!(
+        smol::block_on(poll_once(&mut sleep)).is_some(),
+        "still asleep once the timer was due"
+    );
+}
+
+/*
+ * Sleeps from one of `a`'s timers to the next, running each, until `fired`.
+ * Returns how many milliseconds that took by the clock.
+ */
+fn run_timers_until(a: &Peer, clock: &TestClock, fired: impl Fn() -> bool) -> u64 {
+    let start = clock.now();
+    for _ in 0..MAX_TIMERS {
+        let (due, sleep) = {
+            let a = a.lock();
+            (a.next_timer().unwrap(), a.sleep_until_next_timer().unwrap())
+        };
+        sleep_until(clock, due, sleep);
+        a.lock().do_poll(Some(Duration::from_millis(0))).unwrap();
+        if fired() {
+            return millis(clock.now()) - millis(start);
+        }
+    }
+    panic!("never fired");
+}
+
+/*
+ * Brings up two peers on `clock` and has B go quiet, leaving A keeping time
+ * by `clock` from the instant B's last packets were taken.
+ */
+fn quiet_pair(a_port: u16, b_port: u16, clock: &Arc<TestClock>) -> (Peer, Recorder) {
+    let ((a, a_events), (b, b_events)) = clocked_pair(timed(a_port), timed(b_port), clock);
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+    for _ in 0..50 {
+        poll(&a);
+    }
+    a.lock().set_timers(clock.clone());
+    (a, a_events)
+}
+
+#[test]
+fn the_interruption_comes_due_at_the_notify_start() {
+    let clock = Arc::new(TestClock::new());
+    let (a, a_events) = quiet_pair(19550, 19560, &clock);
+
+    let waited = run_timers_until(&a, &clock, || {
+        a_events.saw(|e| matches!(e, Event::ConnectionInterrupted(_)))
+    });
+    assert_eq!(
+        waited,
+        DISCONNECT_NOTIFY_START + 1,
+        "not interrupted as soon as the peer had been quiet too long"
+    );
+}
+
+#[test]
+fn the_disconnect_comes_due_at_the_timeout() {
+    let clock = Arc::new(TestClock::new());
+    let (a, a_events) = quiet_pair(19570, 19580, &clock);
+
+    let waited = run_timers_until(&a, &clock, || {
+        a_events.saw(|e| matches!(e, Event::DisconnectedFromPeer(_)))
+    });
+    assert_eq!(
+        waited,
+        DISCONNECT_TIMEOUT + 1,
+        "not disconnected as soon as the peer had been quiet too long"
+    );
+}
+
+#[test]
+fn the_spectators_sync_retry_comes_due_at_the_retry_interval() {
+    // Takes the spectator's sync requests and never answers.
+    let host = UdpSocket::bind("127.0.0.1:19590").unwrap();
+    host.set_nonblocking(true).unwrap();
+    let clock = Arc::new(TestClock::new());
+    let spectator = SpectatorBackend::new(
+        Arc::new(Mutex::new(Recorder::default())),
+        19600,
+        2,
+        1,
+        localhost(19590),
+    )
+    .unwrap();
+    spectator.lock().set_timers(clock.clone());
+
+    let mut buf = [0; 4096];
+    let mut requests = 0;
+    let start = clock.now();
+    for _ in 0..MAX_TIMERS {
+        let (due, sleep) = {
+            let spectator = spectator.lock();
+            (
+                spectator.next_timer().unwrap(),
+                spectator.sleep_until_next_timer().unwrap(),
+            )
+        };
+        sleep_until(&clock, due, sleep);
+        for _ in 0..50 {
+            poll(&spectator);
+            while host.recv_from(&mut buf).is_ok() {
+                requests += 1;
+            }
+        }
+        if requests > 1 {
+            break;
+        }
+    }
+    assert_eq!(requests, 2);
+    assert_eq!(
+        millis(clock.now()) - millis(start),
+        SYNC_FIRST_RETRY_INTERVAL as u64 + 1,
+        "didn't retry as soon as the first request had gone unanswered too long"
+    );
+}
+
+#[test]
+fn sleeping_without_a_timer_source_is_refused() {
+    let clock = Arc::new(TestClock::new());
+    let (a, _a_events) = clocked_peer(timed(19610), &clock, 1, 19620);
+    assert!(a.lock().next_timer().is_some());
+    assert!(a.lock().sleep_until_next_timer().is_none());
+}
+
+#[cfg(feature = "smol-timers")]
+#[test]
+fn smol_timers_sleep_until_the_deadline() {
+    use ggpo::network::clock::{SmolTimers, TimerSource};
+    use std::time::Instant;
+
+    let started = Instant::now();
+    smol::block_on(SmolTimers.sleep_until(SystemTime::now() + Duration::from_millis(50)));
+    assert!(started.elapsed() >= Duration::from_millis(45));
+}
@@ -0,0 +1,120 @@
+#![cfg(feature = "tracing")]
+
+mod common;
+
+use common::{connect_status, sync_with, MockGame};
+use ggpo::game_input::{
+    Frame, GameInput, InputBuffer, GAMEINPUT_MAX_BYTES, GAMEINPUT_MAX_PLAYERS, NULL_FRAME,
+};
+use parking_lot::Mutex;
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Metadata, Subscriber,
+};
+
+const INPUT_SIZE: usize = 2;
+
+// A span as it was opened: its name and every field it carried.
+#[derive(Debug)]
+struct CapturedSpan {
+    name: &'static str,
+    fields: Vec<(String, String)>,
+}
+
+impl CapturedSpan {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl Visit for CapturedSpan {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.fields
+            .push((field.name().to_string(), format!("{:?}", value)));
+    }
+}
+
+// Keeps every span opened under the "ggpo" target.
+#[derive(Default)]
+struct Capture {
+    spans: Arc<Mutex<Vec<CapturedSpan>>>,
+    next_id: AtomicU64,
+}
+
+impl Subscriber for Capture {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.target() == "ggpo"
+    }
+
+    fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+        let mut span = CapturedSpan {
+            name: attrs.metadata().name(),
+            fields: Vec::new(),
+        };
+        attrs.record(&mut span);
+        self.spans.lock().push(span);
+        span::Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+    }
+
+    fn record(&self, _span: &span::Id, _values: &span::Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, _span: &span::Id) {}
+
+    fn exit(&self, _span: &span::Id) {}
+}
+
+#[test]
+fn rollback_span_records_the_frame_range() {
+    let status = connect_status(2);
+    let mut sync = sync_with(
+        Arc::new(Mutex::new(MockGame::default())),
+        &status,
+        INPUT_SIZE,
+    );
+
+    // Run three frames predicting the remote player idles...
+    for _ in 0..3 {
+        let mut local = GameInput::init(NULL_FRAME, None, INPUT_SIZE);
+        assert!(sync.add_local_input(0, &mut local).unwrap());
+        let mut values: InputBuffer = Default::default();
+        sync.synchronize_inputs(&mut values).unwrap();
+        sync.increment_frame().unwrap();
+    }
+
+    // ...then learn they pressed something on frame 1.
+    let mut bits = [[b'0'; GAMEINPUT_MAX_BYTES]; GAMEINPUT_MAX_PLAYERS];
+    bits[0][0] = 7;
+    sync.add_remote_input(1, &GameInput::init(Frame::new(0), None, INPUT_SIZE))
+        .unwrap();
+    sync.add_remote_input(1, &GameInput::init(Frame::new(1), Some(&bits), INPUT_SIZE))
+        .unwrap();
+
+    let capture = Capture::default();
+    let spans = capture.spans.clone();
+    tracing::subscriber::with_default(capture, || {
+        sync.check_simulation().unwrap();
+    });
+
+    let spans = spans.lock();
+    let rollback = spans
+        .iter()
+        .find(|span| span.name == "rollback")
+        .expect("a rollback span");
+    assert_eq!(rollback.field("from_frame"), Some("3"));
+    assert_eq!(rollback.field("to_frame"), Some("1"));
+    assert_eq!(rollback.field("frames"), Some("2"));
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use bytes::Bytes;
+use common::{config, localhost, recording_peer, synchronize_by, Peer};
+use ggpo::{
+    config::SessionConfig,
+    game_input::InputBuffer,
+    ggpo::Session,
+    network::{
+        udp_msg::{MsgType, UdpMsg},
+        udp_proto::{TransportProfile, LAN_RETRANSMIT_INTERVAL},
+    },
+    player::PlayerHandle,
+};
+use std::{
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+// Slow enough that a quiet link gets noticed between frames.
+const FRAME_TIME: Duration = Duration::from_millis(250);
+const FRAMES: u8 = 12;
+
+/*
+ * Passes packets straight through between the peers, counting the input and
+ * quality reports A sends once `counting` is set.  Each peer only talks to its
+ * own end of the tap, so both see packets from the address they expect.
+ */
+struct Tap {
+    a_side: UdpSocket,
+    b_side: UdpSocket,
+    a_addr: SocketAddr,
+    b_addr: SocketAddr,
+    counting: bool,
+    inputs: usize,
+    quality_reports: usize,
+}
+
+impl Tap {
+    fn new(a_side: u16, b_side: u16, a_addr: SocketAddr, b_addr: SocketAddr) -> Self {
+        let a_side = UdpSocket::bind(localhost(a_side)).unwrap();
+        let b_side = UdpSocket::bind(localhost(b_side)).unwrap();
+        a_side.set_nonblocking(true).unwrap();
+        b_side.set_nonblocking(true).unwrap();
+        Self {
+            a_side,
+            b_side,
+            a_addr,
+            b_addr,
+            counting: false,
+            inputs: 0,
+            quality_reports: 0,
+        }
+    }
+
+    fn pump(&mut self) {
+        let mut buf = [0u8; 4096];
+        while let Ok(len) = self.a_side.recv(&mut buf) {
+            if self.counting {
+                let packet = zstd::block::decompress(&buf[..len], 4096).unwrap();
+                match UdpMsg::decode(Bytes::from(packet))
+                    .unwrap()
+                    .header
+                    .packet_type
+                {
+                    MsgType::Input => self.inputs += 1,
+                    MsgType::QualityReport => self.quality_reports += 1,
+                    _ => {}
+                }
+            }
+            self.b_side.send_to(&buf[..len], self.b_addr).unwrap();
+        }
+        while let Ok(len) = self.b_side.recv(&mut buf) {
+            self.a_side.send_to(&buf[..len], self.a_addr).unwrap();
+        }
+    }
+}
+
+fn poll(a: &Peer, b: &Peer, tap: &mut Tap) {
+    a.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+    tap.pump();
+    b.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+    tap.pump();
+}
+
+// Plays a frame of `value`, returning what player 1 pressed on it.
+fn advance(session: &Peer, handle: PlayerHandle, value: u8) -> u8 {
+    let mut session = session.lock();
+    let mut local: InputBuffer = Default::default();
+    local[0][0] = value;
+    session.add_local_input(handle, &local, 1).unwrap();
+    let mut values: InputBuffer = Default::default();
+    session.synchronize_input(&mut values, None).unwrap();
+    session.increment_frame().unwrap();
+    values[0][0]
+}
+
+/*
+ * Plays `FRAMES` frames between two peers on `profile`, with B waiting for
+ * A's input each frame.  Returns what B saw A press, and how many input
+ * packets and quality reports A sent to get it there.
+ */
+fn play(ports: [u16; 4], profile: TransportProfile) -> (Vec<u8>, usize, usize) {
+    let [a_port, a_side, b_side, b_port] = ports;
+    let profiled = |port| SessionConfig {
+        transport_profile: profile,
+        ..config(port)
+    };
+    let (a, a_events) = recording_peer(profiled(a_port), 1, a_side);
+    let (b, b_events) = recording_peer(profiled(b_port), 2, b_side);
+    let mut tap = Tap::new(a_side, b_side, localhost(a_port), localhost(b_port));
+
+    synchronize_by(&[&a_events, &b_events], || poll(&a, &b, &mut tap));
+
+    tap.counting = true;
+    let mut seen = Vec::new();
+    for value in 1..=FRAMES {
+        advance(&a, 1, value);
+        let next_frame = Instant::now() + FRAME_TIME;
+        while Instant::now() < next_frame {
+            poll(&a, &b, &mut tap);
+        }
+        seen.push(advance(&b, 2, 0));
+    }
+    (seen, tap.inputs, tap.quality_reports)
+}
+
+#[test]
+fn internet_is_the_default() {
+    assert_eq!(
+        SessionConfig::default().transport_profile,
+        TransportProfile::Internet
+    );
+    assert!(TransportProfile::Internet.checks_magic());
+    assert!(!TransportProfile::TrustedLan.checks_magic());
+}
+
+#[test]
+fn trusted_lan_never_retransmits_faster_than_its_floor() {
+    assert_eq!(TransportProfile::Internet.retransmit_interval(50), 50);
+    assert_eq!(
+        TransportProfile::TrustedLan.retransmit_interval(50),
+        LAN_RETRANSMIT_INTERVAL
+    );
+    assert_eq!(
+        TransportProfile::TrustedLan.retransmit_interval(LAN_RETRANSMIT_INTERVAL * 2),
+        LAN_RETRANSMIT_INTERVAL * 2
+    );
+}
+
+#[test]
+fn trusted_lan_sends_fewer_redundant_packets() {
+    let (internet_seen, internet_inputs, internet_reports) =
+        play([18100, 18110, 18120, 18130], TransportProfile::Internet);
+    let (lan_seen, lan_inputs, lan_reports) =
+        play([18140, 18150, 18160, 18170], TransportProfile::TrustedLan);
+
+    // Both deliver every input...
+    let pressed: Vec<u8> = (1..=FRAMES).collect();
+    assert_eq!(internet_seen, pressed);
+    assert_eq!(lan_seen, pressed);
+    // ...but the LAN profile sends less to do it.
+    assert!(
+        lan_inputs < internet_inputs,
+        "{} input packets on the LAN profile, {} on the internet one",
+        lan_inputs,
+        internet_inputs
+    );
+    assert!(
+        lan_reports < internet_reports,
+        "{} quality reports on the LAN profile, {} on the internet one",
+        lan_reports,
+        internet_reports
+    );
+}
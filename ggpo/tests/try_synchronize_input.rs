@@ -0,0 +1,26 @@
+mod common;
+
+use common::{peer, poll, running, synchronize_by};
+use ggpo::{
+    game_input::InputBuffer,
+    ggpo::{DisconnectFlags, Session},
+};
+
+#[test]
+fn none_until_running_then_flags() {
+    let (a, a_events) = peer(17950, 1, 17960);
+    let (b, b_events) = peer(17960, 2, 17950);
+    let mut values: InputBuffer = Default::default();
+
+    synchronize_by(&[&a_events, &b_events], || {
+        if !running(&[&a_events]) {
+            assert_eq!(a.lock().try_synchronize_input(&mut values), None);
+        }
+        poll(&a);
+        poll(&b);
+    });
+
+    let connected = Some(DisconnectFlags::default());
+    assert_eq!(a.lock().try_synchronize_input(&mut values), connected);
+    assert_eq!(b.lock().try_synchronize_input(&mut values), connected);
+}
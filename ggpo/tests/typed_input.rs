@@ -0,0 +1,153 @@
+mod common;
+
+use bytemuck::{Pod, Zeroable};
+use common::{config, localhost, recording_peer, synchronize};
+use ggpo::{
+    config::SessionConfig,
+    game_input::{Frame, InputBuffer},
+    ggpo::{GGPOError, Session},
+    player::{PlayerHandle, PlayerInfo, PlayerType},
+};
+use std::time::{Duration, Instant};
+
+#[repr(C, packed)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+struct Pad {
+    buttons: u16,
+    stick: i16,
+}
+
+// Sized for a `Pad` a frame.
+fn padded(port: u16) -> SessionConfig {
+    SessionConfig {
+        input_size: std::mem::size_of::<Pad>(),
+        ..config(port)
+    }
+}
+
+fn pad(player: usize, frame: usize) -> Pad {
+    Pad {
+        buttons: (frame as u16) << 4 | player as u16,
+        stick: -(frame as i16) * 100,
+    }
+}
+
+#[test]
+fn pads_round_trip_through_the_session() {
+    let (a, a_events) = recording_peer(padded(18810), 1, 18820);
+    let (b, b_events) = recording_peer(padded(18820), 2, 18810);
+    let peers = [(&a, 1), (&b, 2)];
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+
+    // In lockstep, so every frame's inputs are the real ones, not predictions.
+    for frame in 0..20 {
+        for (session, handle) in peers.iter() {
+            session
+                .lock()
+                .add_local_input_typed(*handle, pad(*handle as usize, frame))
+                .unwrap();
+        }
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while peers.iter().any(|(session, handle)| {
+            let other = 2 - *handle as usize;
+            session.lock().connect_status().unwrap()[other].last_frame < Frame::new(frame as u32)
+        }) {
+            assert!(
+                Instant::now() < deadline,
+                "input for frame {} never arrived",
+                frame
+            );
+            for (session, _) in peers.iter() {
+                session
+                    .lock()
+                    .do_poll(Some(Duration::from_millis(1)))
+                    .unwrap();
+            }
+        }
+        for (session, _) in peers.iter() {
+            let mut session = session.lock();
+            let (inputs, flags) = session.synchronize_input_typed::<Pad>().unwrap();
+            assert_eq!(
+                inputs,
+                vec![pad(1, frame), pad(2, frame)],
+                "frame {}",
+                frame
+            );
+            assert_eq!(flags.bits(), 0);
+            session.increment_frame().unwrap();
+        }
+    }
+}
+
+#[test]
+fn a_struct_of_the_wrong_size_is_refused() {
+    let (a, _) = recording_peer(padded(18830), 1, 18840);
+    let mut a = a.lock();
+    assert!(matches!(
+        a.add_local_input_typed(1, 7u8),
+        Err(GGPOError::InputSizeMismatch {
+            expected: 4,
+            found: 1
+        })
+    ));
+    assert!(matches!(
+        a.synchronize_input_typed::<u64>(),
+        Err(GGPOError::InputSizeMismatch {
+            expected: 4,
+            found: 8
+        })
+    ));
+}
+
+// Two players of a `Pad` each, the second local, keeping whatever input it's given.
+#[derive(Default)]
+struct Spy {
+    given: Option<InputBuffer>,
+}
+
+impl Session for Spy {
+    fn add_local_input(
+        &mut self,
+        _player: PlayerHandle,
+        values: &InputBuffer,
+        size: usize,
+    ) -> Result<(), GGPOError> {
+        assert_eq!(size, std::mem::size_of::<Pad>());
+        self.given = Some(*values);
+        Ok(())
+    }
+
+    fn input_sizes(&self) -> Result<Vec<usize>, GGPOError> {
+        Ok(vec![std::mem::size_of::<Pad>(); 2])
+    }
+
+    fn players(&self) -> Result<Vec<PlayerInfo>, GGPOError> {
+        let player = |handle, player_type| PlayerInfo {
+            handle,
+            queue: handle - 1,
+            player_type,
+            connected: true,
+        };
+        Ok(vec![
+            player(1, PlayerType::Remote(localhost(18850))),
+            player(2, PlayerType::Local),
+        ])
+    }
+}
+
+#[test]
+fn only_the_players_own_row_is_written() {
+    let mut spy = Spy::default();
+    spy.add_local_input_typed(2, pad(2, 5)).unwrap();
+    let given = spy.given.unwrap();
+    assert_eq!(&given[1][..4], bytemuck::bytes_of(&pad(2, 5)));
+    assert!(given[1][4..].iter().all(|&byte| byte == 0));
+    for (row, values) in given.iter().enumerate().filter(|&(row, _)| row != 1) {
+        assert!(values.iter().all(|&byte| byte == 0), "row {}", row);
+    }
+
+    assert!(matches!(
+        spy.add_local_input_typed(3, pad(3, 5)),
+        Err(GGPOError::InvalidPlayerHandle)
+    ));
+}
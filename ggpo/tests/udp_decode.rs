@@ -0,0 +1,173 @@
+use bytes::Bytes;
+use ggpo::{
+    game_input::Frame,
+    network::udp_msg::{Batch, MsgEnum, MsgType, UdpMsg},
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+// Counts the allocations made on each thread, so other tests running in
+// parallel don't show up in the numbers.
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = Cell::new(0);
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(|count| count.get())
+}
+
+fn input_packet(bits: &'static [u8]) -> Bytes {
+    let mut msg = UdpMsg::new(MsgType::Input);
+    if let MsgEnum::Input(input) = &mut msg.message {
+        input.start_frame = Frame::new(120);
+        input.ack_frame = Frame::new(118);
+        input.input_size = 4;
+        input.num_bits = (bits.len() * 8) as u16;
+        input.bits = Bytes::from_static(bits);
+    }
+    Bytes::from(msg.encode().unwrap())
+}
+
+#[test]
+fn input_messages_round_trip() {
+    let msg = UdpMsg::decode(input_packet(&[0x12, 0x34, 0x56])).unwrap();
+    assert_eq!(msg.header.packet_type, MsgType::Input);
+    match msg.message {
+        MsgEnum::Input(input) => {
+            assert_eq!(input.start_frame, Frame::new(120));
+            assert_eq!(input.ack_frame, Frame::new(118));
+            assert_eq!(input.input_size, 4);
+            assert_eq!(input.num_bits, 24);
+            assert_eq!(&input.bits[..], &[0x12, 0x34, 0x56]);
+        }
+        _ => panic!("expected an input message"),
+    }
+}
+
+#[test]
+fn truncated_input_messages_are_rejected() {
+    let packet = input_packet(&[0x12, 0x34, 0x56]);
+    assert!(UdpMsg::decode(packet.slice(..packet.len() - 1)).is_err());
+}
+
+#[test]
+fn state_responses_carry_the_saved_state() {
+    let mut msg = UdpMsg::new(MsgType::StateResponse);
+    if let MsgEnum::StateResponse(response) = &mut msg.message {
+        response.frame = Frame::new(300);
+        response.checksum = 0xfeed;
+        response.size = 5;
+        response.data = Bytes::from_static(b"state");
+    }
+    let packet = Bytes::from(msg.encode().unwrap());
+
+    match UdpMsg::decode(packet.clone()).unwrap().message {
+        MsgEnum::StateResponse(response) => {
+            assert_eq!(response.frame, Frame::new(300));
+            assert_eq!(response.checksum, 0xfeed);
+            assert_eq!(&response.data[..], b"state");
+        }
+        _ => panic!("expected a state response"),
+    }
+    assert!(UdpMsg::decode(packet.slice(..packet.len() - 1)).is_err());
+}
+
+#[test]
+fn decoding_input_does_not_allocate_or_copy() {
+    let packet = input_packet(&[0xAB; 256]);
+    let start = packet.as_ptr() as usize;
+    let end = start + packet.len();
+
+    let before = allocations();
+    let msg = UdpMsg::decode(packet).unwrap();
+    assert_eq!(allocations() - before, 0);
+
+    match msg.message {
+        MsgEnum::Input(input) => {
+            // The bits are a view into the packet, not a copy of it.
+            let bits = input.bits.as_ptr() as usize;
+            assert!(bits >= start && bits + input.bits.len() <= end);
+            assert!(input.bits.iter().all(|&byte| byte == 0xAB));
+        }
+        _ => panic!("expected an input message"),
+    }
+}
+
+fn batch_of(messages: Vec<UdpMsg>) -> UdpMsg {
+    let mut msg = UdpMsg::new(MsgType::Batch);
+    msg.message = MsgEnum::Batch(Batch::new(messages));
+    msg
+}
+
+#[test]
+fn batched_input_keeps_its_bits() {
+    let input = UdpMsg::decode(input_packet(&[0xab, 0xcd])).unwrap();
+    let msg = batch_of(vec![input, UdpMsg::new(MsgType::InputAck)]);
+    let decoded = UdpMsg::decode(Bytes::from(msg.encode().unwrap())).unwrap();
+    let batch = match decoded.message {
+        MsgEnum::Batch(batch) => batch,
+        _ => panic!("decoded as something other than a batch"),
+    };
+    assert_eq!(batch.messages.len(), 2);
+    match &batch.messages[0].message {
+        MsgEnum::Input(input) => {
+            assert_eq!(input.start_frame, Frame::new(120));
+            assert_eq!(&input.bits[..], &[0xab, 0xcd]);
+        }
+        _ => panic!("first message isn't the input"),
+    }
+    assert!(matches!(batch.messages[1].message, MsgEnum::InputAck(_)));
+}
+
+#[test]
+fn batches_dont_nest() {
+    let inner = batch_of(vec![UdpMsg::new(MsgType::InputAck)]);
+    let mut packet = batch_of(vec![UdpMsg::new(MsgType::InputAck)])
+        .encode()
+        .unwrap();
+    // Swap the one message for a batch of its own.
+    let inner = inner.encode().unwrap();
+    let body = packet.len() - 2 - UdpMsg::new(MsgType::InputAck).encode().unwrap().len();
+    packet.truncate(body);
+    packet.extend_from_slice(&(inner.len() as u16).to_le_bytes());
+    packet.extend_from_slice(&inner);
+    assert!(UdpMsg::decode(Bytes::from(packet.clone())).is_err());
+
+    // Nor can one be cut short.
+    packet.truncate(packet.len() - 1);
+    assert!(UdpMsg::decode(Bytes::from(packet)).is_err());
+}
+
+#[test]
+fn a_header_on_the_wrong_message_is_rejected() {
+    let mut msg = UdpMsg::new(MsgType::KeepAlive);
+    msg.header.packet_type = MsgType::Input;
+    assert_eq!(msg.payload_size(), 0);
+    assert!(UdpMsg::decode(Bytes::from(msg.encode().unwrap())).is_err());
+
+    let invalid = UdpMsg::new(MsgType::Invalid);
+    assert_eq!(invalid.payload_size(), 0);
+    assert!(UdpMsg::decode(Bytes::from(invalid.encode().unwrap())).is_err());
+
+    // Keep-alives are the one message sent without a body of their own.
+    let keep_alive = UdpMsg::new(MsgType::KeepAlive);
+    assert!(UdpMsg::decode(Bytes::from(keep_alive.encode().unwrap())).is_ok());
+}
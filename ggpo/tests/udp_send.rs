@@ -0,0 +1,57 @@
+use ggpo::network::{
+    udp::{Udp, UdpCallback},
+    udp_msg::{MsgType, UdpMsg},
+};
+use mio::Poll;
+use parking_lot::Mutex;
+use std::{
+    net::{SocketAddr, UdpSocket},
+    sync::Arc,
+    time::Duration,
+};
+
+struct Ignore;
+
+impl UdpCallback for Ignore {
+    fn on_msg(&mut self, _from: &SocketAddr, _msg: UdpMsg, _len: usize) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+fn receiver() -> UdpSocket {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    socket
+}
+
+#[test]
+fn one_send_reaches_every_destination() {
+    let mut udp = Udp::new();
+    udp.init(
+        17200,
+        Arc::new(Mutex::new(Poll::new().unwrap())),
+        Arc::new(Mutex::new(Ignore)),
+    )
+    .unwrap();
+    udp.start_send_task().unwrap();
+
+    let receivers = [receiver(), receiver()];
+    let destinations: Vec<SocketAddr> = receivers
+        .iter()
+        .map(|socket| socket.local_addr().unwrap())
+        .collect();
+
+    udp.send_to(Arc::new(UdpMsg::new(MsgType::KeepAlive)), &destinations)
+        .unwrap();
+
+    let mut received = Vec::new();
+    for socket in receivers.iter() {
+        let mut buf = [0; 4096];
+        let (len, _) = socket.recv_from(&mut buf).expect("packet");
+        received.push(buf[..len].to_vec());
+    }
+    assert!(!received[0].is_empty());
+    assert_eq!(received[0], received[1]);
+}
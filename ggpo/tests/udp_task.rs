@@ -0,0 +1,65 @@
+use ggpo::network::{
+    udp::{Udp, UdpCallback},
+    udp_msg::{MsgType, UdpMsg},
+};
+use mio::Poll;
+use parking_lot::Mutex;
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+    thread::{self, ThreadId},
+    time::{Duration, Instant},
+};
+
+// Notes which thread each message was delivered on.
+#[derive(Default)]
+struct Deliveries {
+    threads: Vec<ThreadId>,
+}
+
+impl UdpCallback for Deliveries {
+    fn on_msg(&mut self, _from: &SocketAddr, _msg: UdpMsg, _len: usize) -> Result<(), String> {
+        self.threads.push(thread::current().id());
+        Ok(())
+    }
+}
+
+fn bound_udp<T: UdpCallback>(port: u16, callbacks: Arc<Mutex<T>>) -> Udp<T> {
+    let mut udp = Udp::new();
+    udp.init(port, Arc::new(Mutex::new(Poll::new().unwrap())), callbacks)
+        .unwrap();
+    udp.start_send_task().unwrap();
+    udp
+}
+
+#[test]
+fn poll_loop_runs_on_a_spawned_task() {
+    let deliveries = Arc::new(Mutex::new(Deliveries::default()));
+    let mut receiver = bound_udp(17460, deliveries.clone());
+    let mut sender = bound_udp(17470, Arc::new(Mutex::new(Deliveries::default())));
+
+    // The receiving transport moves into the task that polls it.
+    let seen = deliveries.clone();
+    let task = smol::spawn(async move {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while seen.lock().threads.is_empty() && Instant::now() < deadline {
+            receiver.on_loop_poll(0).unwrap();
+            smol::Timer::after(Duration::from_millis(5)).await;
+        }
+    });
+
+    sender
+        .send_to(
+            Arc::new(UdpMsg::new(MsgType::KeepAlive)),
+            &[SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                17460,
+            )],
+        )
+        .unwrap();
+    smol::block_on(task);
+
+    let threads = &deliveries.lock().threads;
+    assert_eq!(threads.len(), 1, "on_msg wasn't called");
+    assert_ne!(threads[0], thread::current().id());
+}
@@ -0,0 +1,145 @@
+mod common;
+
+use bytes::Bytes;
+use common::{config, peer, peer_with, poll, synchronize_by, Peer, Recorder};
+use ggpo::{
+    game_input::{Frame, InputBuffer},
+    ggpo::{Event, GGPOError, GGPOSessionCallbacks, SavedState, Session},
+    player::PlayerHandle,
+};
+use parking_lot::Mutex;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+// Frames both peers play before B's input changes.
+const WARM_UP: u32 = 4;
+
+// The frame A never manages to save, and B's input first changes on.
+const UNSAVED: u32 = WARM_UP + 1;
+
+// Refuses to save `unsaved` once it's set, so there's nothing to roll back to there.
+#[derive(Debug, Default, Clone)]
+struct Forgetful {
+    events: Recorder,
+    unsaved: Arc<Mutex<Option<Frame>>>,
+}
+
+impl GGPOSessionCallbacks for Forgetful {
+    fn save_game_state(&mut self, frame: Frame) -> Result<SavedState, GGPOError> {
+        if *self.unsaved.lock() == Some(frame) {
+            return Err(GGPOError::GeneralFailure);
+        }
+        Ok(SavedState {
+            data: Bytes::from_static(&[0]),
+            checksum: Some(0),
+        })
+    }
+
+    fn load_game_state(&mut self, _buffer: &Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn log_game_state(&mut self, _filename: String, _buffer: Bytes, _length: usize) -> bool {
+        true
+    }
+
+    fn advance_frame(&mut self, _flags: i32) -> bool {
+        true
+    }
+
+    fn on_event(&mut self, info: &Event) {
+        self.events.on_event(info);
+    }
+}
+
+// Runs one frame of `value`, shrugging off the frame that can't be saved.
+fn advance<T: GGPOSessionCallbacks + Send + Sync>(
+    session: &Peer<T>,
+    handle: PlayerHandle,
+    value: u8,
+) {
+    let mut session = session.lock();
+    let mut values: InputBuffer = Default::default();
+    values[handle as usize - 1][0] = value;
+    session.add_local_input(handle, &values, 1).unwrap();
+    session.synchronize_input(&mut values, None).unwrap();
+    match session.increment_frame() {
+        Ok(()) | Err(GGPOError::Sync { .. }) => {}
+        Err(e) => panic!("increment_frame failed: {}", e),
+    }
+}
+
+// The last frame A has B's real input for.
+fn last_input_from_b(a: &Peer<Forgetful>) -> Frame {
+    a.lock().debug_dump().unwrap().players[1].last_input_frame
+}
+
+// Polls both until A has B's input up to `frame` and has rolled back for it.
+fn catch_up(a: &Peer<Forgetful>, b: &Peer, frame: Frame) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        assert!(Instant::now() < deadline, "A never caught up");
+        a.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+        b.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+        let info = a.lock().debug_dump().unwrap();
+        if info.players[1].last_input_frame == frame
+            && info.players[1].first_incorrect_frame.is_null()
+        {
+            return;
+        }
+    }
+}
+
+#[test]
+fn a_failed_rollback_leaves_the_session_pumping() {
+    let game = Forgetful::default();
+    let a_events = game.events.clone();
+    let unsaved = game.unsaved.clone();
+    let a = peer_with(config(19280), Arc::new(Mutex::new(game)), 1, 19290);
+    let (b, b_events) = peer(19290, 2, 19280);
+    synchronize_by(&[&a_events, &b_events], || {
+        poll(&a);
+        poll(&b);
+    });
+    for _ in 0..WARM_UP {
+        advance(&a, 1, 0);
+        advance(&b, 2, 0);
+    }
+    catch_up(&a, &b, Frame::new(WARM_UP - 1));
+
+    // A runs ahead on predictions of B, skipping the save of `UNSAVED`.
+    *unsaved.lock() = Some(Frame::new(UNSAVED));
+    for _ in 0..3 {
+        advance(&a, 1, 0);
+    }
+    // B presses a button on `UNSAVED`, which A has to roll back for.
+    for frame in WARM_UP..WARM_UP + 3 {
+        advance(&b, 2, if frame < UNSAVED { 0 } else { 1 });
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        assert!(Instant::now() < deadline, "the rollback never failed");
+        b.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+        let polled = a.lock().do_poll(Some(Duration::from_millis(1)));
+        if let Err(e) = polled {
+            assert!(matches!(e, GGPOError::GeneralFailure));
+            break;
+        }
+    }
+    assert!(!a.lock().debug_dump().unwrap().in_rollback);
+
+    // A still hears from B after the error.
+    let heard = last_input_from_b(&a);
+    for _ in 0..2 {
+        advance(&b, 2, 1);
+    }
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while last_input_from_b(&a) == heard {
+        assert!(Instant::now() < deadline, "A stopped pumping");
+        b.lock().do_poll(Some(Duration::from_millis(1))).unwrap();
+        let _ = a.lock().do_poll(Some(Duration::from_millis(1)));
+    }
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use common::{config, poll, recording_peer, synchronize, Peer};
+use ggpo::{
+    config::SessionConfig,
+    ggpo::{Event, GGPOError, Session},
+};
+use std::time::{Duration, Instant};
+
+fn versioned(port: u16, version: u32) -> SessionConfig {
+    SessionConfig {
+        version,
+        ..config(port)
+    }
+}
+
+fn poll_for(a: &Peer, b: &Peer, how_long: Duration) {
+    let deadline = Instant::now() + how_long;
+    while Instant::now() < deadline {
+        poll(a);
+        poll(b);
+    }
+}
+
+#[test]
+fn mismatched_versions_fail_the_handshake() {
+    let (a, a_events) = recording_peer(versioned(17850, 1), 1, 17860);
+    let (b, b_events) = recording_peer(versioned(17860, 2), 2, 17850);
+    poll_for(&a, &b, Duration::from_secs(1));
+
+    // Each side names the other player, and both versions from its own side.
+    for (events, player, local, remote) in [(&a_events, 2, 1, 2), (&b_events, 1, 2, 1)].iter() {
+        assert!(events.saw(|e| matches!(
+            e,
+            Event::IncompatibleVersion(mismatch)
+                if mismatch.player == *player
+                    && mismatch.local_version == *local
+                    && mismatch.remote_version == *remote
+        )));
+        assert!(!events.saw(|e| matches!(e, Event::Running)));
+    }
+    let negotiated = a.lock().negotiated_version();
+    match negotiated {
+        Err(GGPOError::IncompatibleVersion { local, remote }) => {
+            assert_eq!((local, remote), (1, 2));
+        }
+        other => panic!("expected a version mismatch, got {:?}", other),
+    }
+}
+
+#[test]
+fn matching_versions_are_negotiated() {
+    let (a, a_events) = recording_peer(versioned(17870, 3), 1, 17880);
+    let (b, b_events) = recording_peer(versioned(17880, 3), 2, 17870);
+    assert!(matches!(
+        a.lock().negotiated_version(),
+        Err(GGPOError::NotSynchronized)
+    ));
+
+    synchronize(&[(&a, &a_events), (&b, &b_events)]);
+    assert_eq!(a.lock().negotiated_version().unwrap(), 3);
+    assert_eq!(b.lock().negotiated_version().unwrap(), 3);
+}
@@ -0,0 +1,156 @@
+use bytes::Bytes;
+use ggpo::{
+    game_input::{Frame, NULL_FRAME},
+    network::udp_msg::{
+        ConnectStatus, Header, Input, InputAck, MsgEnum, MsgType, SyncRequest, UdpMsg,
+    },
+};
+
+/*
+ * Every byte of these is spelled out, so a message encodes the same on any
+ * host: integers little-endian at their declared width, enum tags as u32.
+ */
+
+#[test]
+fn sync_request_layout() {
+    let msg = UdpMsg {
+        header: Header {
+            magic: 0x1234,
+            sequence_number: 0x0102,
+            packet_type: MsgType::SyncRequest,
+        },
+        message: MsgEnum::SyncRequest(SyncRequest {
+            random_request: 0xdead_beef,
+            remote_magic: 0x5678,
+            remote_endpoint: 9,
+            input_size: 2,
+            version: 7,
+        }),
+    };
+
+    #[rustfmt::skip]
+    let golden: &[u8] = &[
+        0x34, 0x12, // magic
+        0x02, 0x01, // sequence_number
+        0x01, 0x00, 0x00, 0x00, // packet_type
+        0x00, 0x00, 0x00, 0x00, // MsgEnum::SyncRequest
+        0xef, 0xbe, 0xad, 0xde, // random_request
+        0x78, 0x56, // remote_magic
+        0x09, // remote_endpoint
+        0x02, 0x00, // input_size
+        0x07, 0x00, 0x00, 0x00, // version
+    ];
+    assert_eq!(msg.encode().unwrap(), golden);
+}
+
+#[test]
+fn input_layout() {
+    let mut input = Input::new();
+    input.peer_connect_status[0] = ConnectStatus {
+        disconnected: false,
+        last_frame: Frame::new(5),
+    };
+    input.start_frame = Frame::new(6);
+    input.disconnect_requested = false;
+    input.ack_frame = Frame::new(4);
+    input.input_size = 1;
+    input.num_bits = 16;
+    input.bits = Bytes::from_static(&[0xaa, 0xbb]);
+    let msg = UdpMsg {
+        header: Header {
+            magic: 0x1234,
+            sequence_number: 3,
+            packet_type: MsgType::Input,
+        },
+        message: MsgEnum::Input(input),
+    };
+
+    #[rustfmt::skip]
+    let golden: &[u8] = &[
+        0x34, 0x12, 0x03, 0x00, 0x03, 0x00, 0x00, 0x00, // header
+        0x04, 0x00, 0x00, 0x00, // MsgEnum::Input
+        0x00, 0x05, 0x00, 0x00, 0x00, // peer_connect_status[0]
+        0x01, 0xff, 0xff, 0xff, 0xff, // the rest: disconnected, at NULL_FRAME
+        0x01, 0xff, 0xff, 0xff, 0xff,
+        0x01, 0xff, 0xff, 0xff, 0xff,
+        0x06, 0x00, 0x00, 0x00, // start_frame
+        0x00, // disconnect_requested
+        0x04, 0x00, 0x00, 0x00, // ack_frame
+        0x01, 0x00, // input_size
+        0x01, // num_players
+        0x10, 0x00, // num_bits
+        0xaa, 0xbb, // the bits themselves, after the body
+    ];
+    let encoded = msg.encode().unwrap();
+    assert_eq!(encoded, golden);
+
+    // And it reads back the same.
+    let decoded = UdpMsg::decode(Bytes::from(encoded)).unwrap();
+    match decoded.message {
+        MsgEnum::Input(input) => {
+            assert_eq!(input.start_frame, Frame::new(6));
+            assert_eq!(input.ack_frame, Frame::new(4));
+            assert_eq!(input.peer_connect_status[0].last_frame, Frame::new(5));
+            assert_eq!(&input.bits[..], &[0xaa, 0xbb]);
+        }
+        _ => panic!("decoded as something other than input"),
+    }
+}
+
+#[test]
+fn keep_alive_is_just_a_header() {
+    let mut msg = UdpMsg::new(MsgType::KeepAlive);
+    msg.header.magic = 0xbeef;
+    msg.header.sequence_number = 0xffff;
+
+    #[rustfmt::skip]
+    let golden: &[u8] = &[
+        0xef, 0xbe, 0xff, 0xff, 0x06, 0x00, 0x00, 0x00, // header
+        0x0c, 0x00, 0x00, 0x00, // MsgEnum::None
+    ];
+    assert_eq!(msg.encode().unwrap(), golden);
+}
+
+fn input_ack(ack_frame: Frame) -> UdpMsg {
+    let mut msg = UdpMsg::new(MsgType::InputAck);
+    msg.message = MsgEnum::InputAck(InputAck { ack_frame });
+    msg
+}
+
+fn decoded_ack(packet: Vec<u8>) -> Frame {
+    match UdpMsg::decode(Bytes::from(packet)).unwrap().message {
+        MsgEnum::InputAck(input_ack) => input_ack.ack_frame,
+        _ => panic!("decoded as something other than an input ack"),
+    }
+}
+
+#[test]
+fn null_frame_and_frame_zero_are_distinct_on_the_wire() {
+    let nothing_acked = input_ack(NULL_FRAME).encode().unwrap();
+    let zero_acked = input_ack(Frame::new(0)).encode().unwrap();
+
+    #[rustfmt::skip]
+    let header: &[u8] = &[
+        0x00, 0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, // header
+        0x05, 0x00, 0x00, 0x00, // MsgEnum::InputAck
+    ];
+    assert_eq!(&nothing_acked[..header.len()], header);
+    assert_eq!(&nothing_acked[header.len()..], &[0xff, 0xff, 0xff, 0xff]);
+    assert_eq!(&zero_acked[..header.len()], header);
+    assert_eq!(&zero_acked[header.len()..], &[0x00, 0x00, 0x00, 0x00]);
+
+    assert_eq!(decoded_ack(nothing_acked), NULL_FRAME);
+    assert_eq!(decoded_ack(zero_acked), Frame::new(0));
+    assert_eq!(
+        decoded_ack(input_ack(Frame::MAX).encode().unwrap()),
+        Frame::MAX
+    );
+}
+
+#[test]
+fn negative_frames_other_than_null_are_rejected() {
+    let mut packet = input_ack(NULL_FRAME).encode().unwrap();
+    let len = packet.len();
+    packet[len - 4..].copy_from_slice(&(-2i32).to_le_bytes());
+    assert!(UdpMsg::decode(Bytes::from(packet)).is_err());
+}